@@ -0,0 +1,581 @@
+//! Opt-in announcement of an identity on the fediverse through a minimal ActivityPub actor.
+//!
+//! An [`ActivityPubPublisher`] produces the documents of a `Person` actor for the local identity: the actor itself
+//! (with the public profile fields), a WebFinger response and an outbox made of the messages posted by the identity
+//! in a designated broadcast channel. Follow requests received in the inbox are accepted, and every new post
+//! produces a `Create` activity to be delivered to the followers.
+//!
+//! Serving the documents over HTTP, as well as signing and delivering activities, is left to the embedding
+//! application. The actor exposes the key of the identity as a `Multikey` so that activities can be signed with it.
+use std::collections::VecDeque;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use indexmap::IndexSet;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use warp::{
+    crypto::DID,
+    error::Error,
+    multipass::identity::{Identity, ProfileFields},
+    raygun::{community::RayGunCommunity, Message, MessageEventKind, MessageType, RayGun},
+};
+
+use crate::digest::escape_html;
+
+/// Audience of public posts
+pub const PUBLIC: &str = "https://www.w3.org/ns/activitystreams#Public";
+
+/// Conversation or community channel whose messages are published as public posts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BroadcastChannel {
+    Conversation(Uuid),
+    CommunityChannel {
+        community_id: Uuid,
+        channel_id: Uuid,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActivityPubConfig {
+    /// Base url the documents are served from (eg `https://example.com`)
+    pub base_url: String,
+    /// Name of the actor, used in its url and WebFinger address
+    pub username: String,
+    /// Url of the profile picture, if served by the embedding application
+    pub icon_url: Option<String>,
+    pub broadcast: Option<BroadcastChannel>,
+    /// Maximum amount of posts kept in the outbox
+    pub max_posts: usize,
+}
+
+impl ActivityPubConfig {
+    pub fn new(base_url: impl Into<String>, username: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            username: username.into(),
+            icon_url: None,
+            broadcast: None,
+            max_posts: 20,
+        }
+    }
+
+    /// Host of the base url, used in the WebFinger address
+    pub fn host(&self) -> &str {
+        let url = self
+            .base_url
+            .split_once("://")
+            .map_or(self.base_url.as_str(), |(_, rest)| rest);
+        url.split('/').next().unwrap_or(url)
+    }
+
+    pub fn actor_id(&self) -> String {
+        format!("{}/users/{}", self.base_url, self.username)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Post {
+    message_id: Uuid,
+    lines: Vec<String>,
+    published: DateTime<Utc>,
+    updated: Option<DateTime<Utc>>,
+}
+
+pub struct ActivityPubPublisher {
+    config: ActivityPubConfig,
+    identity: Identity,
+    fields: ProfileFields,
+    posts: VecDeque<Post>,
+    followers: IndexSet<String>,
+}
+
+impl ActivityPubPublisher {
+    pub fn new(config: ActivityPubConfig, identity: Identity, fields: ProfileFields) -> Self {
+        Self {
+            config,
+            identity,
+            fields,
+            posts: VecDeque::new(),
+            followers: IndexSet::new(),
+        }
+    }
+
+    pub fn config(&self) -> &ActivityPubConfig {
+        &self.config
+    }
+
+    /// Update the identity and profile fields shown on the actor
+    pub fn update_identity(&mut self, identity: Identity, fields: ProfileFields) {
+        self.identity = identity;
+        self.fields = fields;
+    }
+
+    /// Actors following the identity
+    pub fn followers(&self) -> impl Iterator<Item = &str> {
+        self.followers.iter().map(String::as_str)
+    }
+
+    /// Restore followers that were persisted by the embedding application
+    pub fn set_followers(&mut self, followers: impl IntoIterator<Item = String>) {
+        self.followers = followers.into_iter().collect();
+    }
+
+    fn did(&self) -> &DID {
+        self.identity.did_key()
+    }
+
+    /// The `Person` document of the identity, including only the public profile fields
+    pub fn actor(&self) -> Value {
+        let id = self.config.actor_id();
+        let did = self.did().to_string();
+
+        let fields = self.fields.visible_to(false);
+        let mut attachment = vec![property("Warp", &did)];
+        if let Some(pronouns) = fields.pronouns() {
+            attachment.push(property("Pronouns", pronouns.value()));
+        }
+        if let Some(timezone) = fields.timezone() {
+            attachment.push(property("Timezone", timezone.value()));
+        }
+        for link in fields.links() {
+            attachment.push(property(
+                "Link",
+                &format!(
+                    "<a href=\"{0}\" rel=\"me nofollow noopener\">{0}</a>",
+                    escape_html(link.value())
+                ),
+            ));
+        }
+
+        let mut actor = json!({
+            "@context": [
+                "https://www.w3.org/ns/activitystreams",
+                "https://w3id.org/security/data-integrity/v1",
+                { "PropertyValue": "schema:PropertyValue", "value": "schema:value", "schema": "http://schema.org#" }
+            ],
+            "id": id,
+            "type": "Person",
+            "preferredUsername": self.config.username,
+            "name": self.identity.username(),
+            "url": id,
+            "inbox": format!("{id}/inbox"),
+            "outbox": format!("{id}/outbox"),
+            "followers": format!("{id}/followers"),
+            "published": format_date(self.identity.created()),
+            "attachment": attachment,
+            "alsoKnownAs": [did],
+            "assertionMethod": [{
+                "id": format!("{id}#ed25519-key"),
+                "type": "Multikey",
+                "controller": id,
+                "publicKeyMultibase": did.trim_start_matches("did:key:"),
+            }],
+        });
+
+        if let Some(status) = self.identity.status_message() {
+            actor["summary"] = json!(format!("<p>{}</p>", escape_html(status)));
+        }
+
+        if let Some(icon) = &self.config.icon_url {
+            actor["icon"] = json!({ "type": "Image", "url": icon });
+        }
+
+        actor
+    }
+
+    /// WebFinger response for `acct:{username}@{host}`, or `None` if the resource does not match the actor
+    pub fn webfinger(&self, resource: &str) -> Option<Value> {
+        let subject = format!("acct:{}@{}", self.config.username, self.config.host());
+        let id = self.config.actor_id();
+
+        if resource != subject && resource != id {
+            return None;
+        }
+
+        Some(json!({
+            "subject": subject,
+            "aliases": [id],
+            "links": [{
+                "rel": "self",
+                "type": "application/activity+json",
+                "href": id,
+            }],
+        }))
+    }
+
+    pub fn outbox(&self) -> Value {
+        let id = self.config.actor_id();
+        let items = self
+            .posts
+            .iter()
+            .rev()
+            .map(|post| self.create_activity(post))
+            .collect::<Vec<_>>();
+
+        json!({
+            "@context": "https://www.w3.org/ns/activitystreams",
+            "id": format!("{id}/outbox"),
+            "type": "OrderedCollection",
+            "totalItems": items.len(),
+            "orderedItems": items,
+        })
+    }
+
+    pub fn followers_collection(&self) -> Value {
+        let id = self.config.actor_id();
+        json!({
+            "@context": "https://www.w3.org/ns/activitystreams",
+            "id": format!("{id}/followers"),
+            "type": "OrderedCollection",
+            "totalItems": self.followers.len(),
+            "orderedItems": self.followers,
+        })
+    }
+
+    fn note(&self, post: &Post) -> Value {
+        let id = self.config.actor_id();
+        let content = post
+            .lines
+            .iter()
+            .map(|line| escape_html(line))
+            .collect::<Vec<_>>()
+            .join("<br>");
+
+        let mut note = json!({
+            "id": format!("{id}/posts/{}", post.message_id),
+            "type": "Note",
+            "attributedTo": id,
+            "content": format!("<p>{content}</p>"),
+            "published": format_date(post.published),
+            "to": [PUBLIC],
+            "cc": [format!("{id}/followers")],
+        });
+
+        if let Some(updated) = post.updated {
+            note["updated"] = json!(format_date(updated));
+        }
+
+        note
+    }
+
+    fn activity(&self, kind: &str, object: Value) -> Value {
+        let id = self.config.actor_id();
+        json!({
+            "@context": "https://www.w3.org/ns/activitystreams",
+            "id": format!("{id}/activities/{}", Uuid::new_v4()),
+            "type": kind,
+            "actor": id,
+            "to": [PUBLIC],
+            "cc": [format!("{id}/followers")],
+            "object": object,
+        })
+    }
+
+    fn create_activity(&self, post: &Post) -> Value {
+        let mut activity = self.activity("Create", self.note(post));
+        activity["id"] = json!(format!(
+            "{}/posts/{}/activity",
+            self.config.actor_id(),
+            post.message_id
+        ));
+        activity["published"] = json!(format_date(post.published));
+        activity
+    }
+
+    fn is_broadcast(&self, message: &Message) -> bool {
+        message.message_type() == MessageType::Message && message.sender() == self.did()
+    }
+
+    /// Publish a message posted by the identity in the broadcast channel, returning the activity to deliver to
+    /// the followers. Posting an edited message again updates the post
+    pub fn publish(&mut self, message: &Message) -> Option<Value> {
+        if !self.is_broadcast(message) {
+            return None;
+        }
+
+        if let Some(index) = self
+            .posts
+            .iter()
+            .position(|post| post.message_id == message.id())
+        {
+            let post = &mut self.posts[index];
+            post.lines = message.lines().to_vec();
+            post.updated = Some(message.modified().unwrap_or_else(Utc::now));
+            let note = self.note(&self.posts[index]);
+            return Some(self.activity("Update", note));
+        }
+
+        let post = Post {
+            message_id: message.id(),
+            lines: message.lines().to_vec(),
+            published: message.date(),
+            updated: None,
+        };
+
+        let activity = self.create_activity(&post);
+
+        if self.config.max_posts > 0 {
+            if self.posts.len() >= self.config.max_posts {
+                self.posts.pop_front();
+            }
+            self.posts.push_back(post);
+        }
+
+        Some(activity)
+    }
+
+    /// Retract a post whose message was deleted, returning the activity to deliver to the followers
+    pub fn retract(&mut self, message_id: Uuid) -> Option<Value> {
+        let index = self
+            .posts
+            .iter()
+            .position(|post| post.message_id == message_id)?;
+        self.posts.remove(index);
+
+        let object = format!("{}/posts/{message_id}", self.config.actor_id());
+        Some(self.activity("Delete", json!({ "id": object, "type": "Tombstone" })))
+    }
+
+    /// Publish messages of the broadcast channel as they are sent, edited or deleted
+    pub async fn handle_message_event<R: RayGun>(
+        &mut self,
+        raygun: &R,
+        event: &MessageEventKind,
+    ) -> Result<Option<Value>, Error> {
+        let Some(broadcast) = self.config.broadcast else {
+            return Ok(None);
+        };
+
+        match (broadcast, event) {
+            (
+                BroadcastChannel::Conversation(id),
+                MessageEventKind::MessageSent {
+                    conversation_id,
+                    message_id,
+                }
+                | MessageEventKind::MessageEdited {
+                    conversation_id,
+                    message_id,
+                },
+            ) if id == *conversation_id => {
+                let message = raygun.get_message(id, *message_id).await?;
+                Ok(self.publish(&message))
+            }
+            (
+                BroadcastChannel::Conversation(id),
+                MessageEventKind::MessageDeleted {
+                    conversation_id,
+                    message_id,
+                },
+            ) if id == *conversation_id => Ok(self.retract(*message_id)),
+            (
+                BroadcastChannel::CommunityChannel {
+                    community_id,
+                    channel_id,
+                },
+                MessageEventKind::CommunityMessageSent {
+                    community_id: event_community,
+                    channel_id: event_channel,
+                    message_id,
+                }
+                | MessageEventKind::CommunityMessageEdited {
+                    community_id: event_community,
+                    channel_id: event_channel,
+                    message_id,
+                },
+            ) if community_id == *event_community && channel_id == *event_channel => {
+                let message = raygun
+                    .get_community_channel_message(community_id, channel_id, *message_id)
+                    .await?;
+                Ok(self.publish(&message))
+            }
+            (
+                BroadcastChannel::CommunityChannel {
+                    community_id,
+                    channel_id,
+                },
+                MessageEventKind::CommunityMessageDeleted {
+                    community_id: event_community,
+                    channel_id: event_channel,
+                    message_id,
+                },
+            ) if community_id == *event_community && channel_id == *event_channel => {
+                Ok(self.retract(*message_id))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Process an activity received in the inbox. The signature of the request is expected to be verified by the
+    /// embedding application. A `Follow` of the actor returns the `Accept` activity to deliver to the follower
+    pub fn handle_inbox(&mut self, activity: &Value) -> Result<Option<Value>, Error> {
+        let kind = activity
+            .get("type")
+            .and_then(Value::as_str)
+            .ok_or_else(|| Error::OtherWithContext("Activity is missing a type".into()))?;
+        let actor = activity
+            .get("actor")
+            .and_then(Value::as_str)
+            .ok_or_else(|| Error::OtherWithContext("Activity is missing an actor".into()))?;
+
+        let object_id = |object: &Value| -> Option<String> {
+            match object {
+                Value::String(id) => Some(id.clone()),
+                object => object
+                    .get("id")
+                    .and_then(Value::as_str)
+                    .map(ToString::to_string),
+            }
+        };
+
+        let id = self.config.actor_id();
+
+        match kind {
+            "Follow" => {
+                if activity.get("object").and_then(object_id).as_deref() != Some(id.as_str()) {
+                    return Ok(None);
+                }
+
+                self.followers.insert(actor.to_string());
+
+                let mut accept = self.activity("Accept", activity.clone());
+                accept["to"] = json!([actor]);
+                accept
+                    .as_object_mut()
+                    .expect("activity is an object")
+                    .remove("cc");
+                Ok(Some(accept))
+            }
+            "Undo" => {
+                let Some(object) = activity.get("object") else {
+                    return Ok(None);
+                };
+
+                if object.get("type").and_then(Value::as_str) == Some("Follow")
+                    && object.get("actor").and_then(Value::as_str) == Some(actor)
+                {
+                    self.followers.shift_remove(actor);
+                }
+                Ok(None)
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+fn property(name: &str, value: &str) -> Value {
+    json!({ "type": "PropertyValue", "name": name, "value": value })
+}
+
+fn format_date(date: DateTime<Utc>) -> String {
+    date.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use warp::{
+        crypto::DID,
+        multipass::identity::{FieldVisibility, Identity, ProfileField, ProfileFields},
+        raygun::Message,
+    };
+
+    use super::{ActivityPubConfig, ActivityPubPublisher};
+
+    fn publisher() -> ActivityPubPublisher {
+        let mut identity = Identity::default();
+        identity.set_did_key(DID::default());
+        identity.set_username("JohnDoe");
+
+        let mut fields = ProfileFields::default();
+        fields.set_pronouns(Some(ProfileField::new("he/him", FieldVisibility::Public)));
+        fields.set_timezone(Some(ProfileField::new(
+            "Europe/Berlin",
+            FieldVisibility::Friends,
+        )));
+
+        let config = ActivityPubConfig::new("https://example.com/", "john");
+        ActivityPubPublisher::new(config, identity, fields)
+    }
+
+    #[test]
+    fn actor_shows_public_fields() {
+        let publisher = publisher();
+        let actor = publisher.actor();
+
+        assert_eq!(actor["id"], "https://example.com/users/john");
+        assert_eq!(actor["name"], "JohnDoe");
+
+        let names = actor["attachment"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|field| field["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["Warp", "Pronouns"]);
+
+        assert!(publisher.webfinger("acct:john@example.com").is_some());
+        assert!(publisher.webfinger("acct:jane@example.com").is_none());
+    }
+
+    #[test]
+    fn follow_accepted_and_undone() {
+        let mut publisher = publisher();
+        let follow = json!({
+            "id": "https://social.example/activities/1",
+            "type": "Follow",
+            "actor": "https://social.example/users/alice",
+            "object": "https://example.com/users/john",
+        });
+
+        let accept = publisher.handle_inbox(&follow).unwrap().unwrap();
+        assert_eq!(accept["type"], "Accept");
+        assert_eq!(accept["object"], follow);
+        assert_eq!(
+            publisher.followers().collect::<Vec<_>>(),
+            ["https://social.example/users/alice"]
+        );
+
+        let undo = json!({
+            "type": "Undo",
+            "actor": "https://social.example/users/alice",
+            "object": follow,
+        });
+        assert!(publisher.handle_inbox(&undo).unwrap().is_none());
+        assert_eq!(publisher.followers().count(), 0);
+    }
+
+    #[test]
+    fn posts_published_and_retracted() {
+        let mut publisher = publisher();
+        let did = publisher.identity.did_key().clone();
+
+        let mut message = Message::default();
+        message.set_id(Uuid::new_v4());
+        message.set_sender(did);
+        message.set_lines(vec!["<hello>".into(), "world".into()]);
+
+        let create = publisher.publish(&message).unwrap();
+        assert_eq!(create["type"], "Create");
+        assert_eq!(create["object"]["content"], "<p>&lt;hello&gt;<br>world</p>");
+
+        let outbox = publisher.outbox();
+        assert_eq!(outbox["totalItems"], 1);
+
+        message.set_lines(vec!["edited".into()]);
+        let update = publisher.publish(&message).unwrap();
+        assert_eq!(update["type"], "Update");
+
+        // messages of other identities are never published
+        let mut other = message.clone();
+        other.set_sender(DID::default());
+        assert!(publisher.publish(&other).is_none());
+
+        let delete = publisher.retract(message.id()).unwrap();
+        assert_eq!(delete["type"], "Delete");
+        assert_eq!(publisher.outbox()["totalItems"], 0);
+    }
+}
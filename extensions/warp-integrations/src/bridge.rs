@@ -0,0 +1,561 @@
+//! Framework for bridging conversations to rooms of other chat networks on top of [`RayGun`].
+//!
+//! A [`Bridge`] runs on a dedicated identity that is a member of each bridged conversation. Messages, edits and
+//! reactions from the other members are forwarded to the room through a [`BridgeAdapter`], which is expected to
+//! post them on behalf of the member (eg through a puppet account). Events received from the room are posted into
+//! the conversation by the bridge identity, with each message prefixed by the name of its remote author since
+//! messages in a conversation are signed by their sender.
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    task::Poll,
+};
+
+use futures::{
+    stream::{BoxStream, SelectAll},
+    StreamExt,
+};
+use indexmap::IndexMap;
+use uuid::Uuid;
+
+use warp::{
+    crypto::DID,
+    error::Error,
+    raygun::{
+        Message, MessageEventKind, MessageEventStream, MessageType, RayGun, RayGunEventKind,
+        ReactionState,
+    },
+};
+
+pub mod matrix;
+
+/// Maximum amount of messages for which the remote event is remembered. Edits and reactions to older
+/// messages are not bridged
+pub const MAX_BRIDGED_MESSAGES: usize = 10_000;
+
+/// User of the remote network
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RemoteUser {
+    pub id: String,
+    pub display_name: Option<String>,
+}
+
+impl RemoteUser {
+    /// Name shown alongside the messages of the user
+    pub fn name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.id)
+    }
+}
+
+/// Event received from a room of the remote network
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoteEvent {
+    Message {
+        room: String,
+        event_id: String,
+        sender: RemoteUser,
+        lines: Vec<String>,
+        /// Event being replied to
+        reply_to: Option<String>,
+    },
+    Edit {
+        room: String,
+        /// Event of the original message
+        event_id: String,
+        sender: RemoteUser,
+        lines: Vec<String>,
+    },
+    Reaction {
+        room: String,
+        /// Event being reacted to
+        event_id: String,
+        sender: RemoteUser,
+        reaction: String,
+        state: ReactionState,
+    },
+}
+
+#[async_trait::async_trait]
+pub trait BridgeAdapter: Send + Sync + 'static {
+    /// Post a message to the room on behalf of `sender`, returning the id of the remote event
+    async fn send(
+        &self,
+        room: &str,
+        sender: &DID,
+        lines: &[String],
+        reply_to: Option<&str>,
+    ) -> Result<String, Error>;
+
+    /// Edit a message previously posted with [`BridgeAdapter::send`]
+    async fn edit(
+        &self,
+        room: &str,
+        sender: &DID,
+        event_id: &str,
+        lines: &[String],
+    ) -> Result<(), Error>;
+
+    /// Add or remove a reaction to an event on behalf of `sender`
+    async fn react(
+        &self,
+        room: &str,
+        sender: &DID,
+        event_id: &str,
+        reaction: &str,
+        state: ReactionState,
+    ) -> Result<(), Error>;
+
+    /// Stream of events received from the rooms of the remote network
+    async fn events(&self) -> Result<BoxStream<'static, RemoteEvent>, Error>;
+}
+
+/// Lines posted into the conversation for a message of a remote user
+pub fn remote_lines(sender: &RemoteUser, lines: &[String]) -> Vec<String> {
+    let mut lines = lines.to_vec();
+    match lines.first_mut() {
+        Some(first) => *first = format!("{}: {first}", sender.name()),
+        None => lines.push(format!("{}:", sender.name())),
+    }
+    lines
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct RemoteRef {
+    room: String,
+    event_id: String,
+}
+
+// Messages that were bridged in either direction, bounded by `MAX_BRIDGED_MESSAGES`
+#[derive(Debug, Default)]
+struct MessageMap {
+    by_local: IndexMap<Uuid, RemoteRef>,
+    by_remote: HashMap<RemoteRef, Uuid>,
+}
+
+impl MessageMap {
+    fn insert(&mut self, message_id: Uuid, remote: RemoteRef) {
+        if self.by_local.len() >= MAX_BRIDGED_MESSAGES {
+            if let Some((_, oldest)) = self.by_local.shift_remove_index(0) {
+                self.by_remote.remove(&oldest);
+            }
+        }
+        self.by_remote.insert(remote.clone(), message_id);
+        self.by_local.insert(message_id, remote);
+    }
+
+    fn remote(&self, message_id: Uuid) -> Option<&RemoteRef> {
+        self.by_local.get(&message_id)
+    }
+
+    fn local(&self, room: &str, event_id: &str) -> Option<Uuid> {
+        self.by_remote
+            .get(&RemoteRef {
+                room: room.to_string(),
+                event_id: event_id.to_string(),
+            })
+            .copied()
+    }
+}
+
+pub struct Bridge<R: RayGun, A: BridgeAdapter> {
+    raygun: R,
+    adapter: Arc<A>,
+    did: DID,
+    rooms: HashMap<Uuid, String>,
+    conversations: HashMap<String, Uuid>,
+    messages: MessageMap,
+    // remote users reacting with a reaction to a message, as the bridge identity can only react once
+    reactions: HashMap<(Uuid, String), HashSet<String>>,
+}
+
+impl<R: RayGun, A: BridgeAdapter> Bridge<R, A> {
+    /// Create a bridge running on the identity `did`
+    pub fn new(raygun: R, adapter: A, did: DID) -> Self {
+        Self {
+            raygun,
+            adapter: Arc::new(adapter),
+            did,
+            rooms: HashMap::new(),
+            conversations: HashMap::new(),
+            messages: MessageMap::default(),
+            reactions: HashMap::new(),
+        }
+    }
+
+    /// Bridge a conversation to a room, replacing any previous room of the conversation
+    pub fn bridge_conversation(&mut self, conversation_id: Uuid, room: impl Into<String>) {
+        let room = room.into();
+        if let Some(previous) = self.rooms.insert(conversation_id, room.clone()) {
+            self.conversations.remove(&previous);
+        }
+        // A room is only bridged to a single conversation
+        if let Some(previous) = self.conversations.insert(room, conversation_id) {
+            if previous != conversation_id {
+                self.rooms.remove(&previous);
+            }
+        }
+    }
+
+    pub fn unbridge_conversation(&mut self, conversation_id: Uuid) {
+        if let Some(room) = self.rooms.remove(&conversation_id) {
+            self.conversations.remove(&room);
+        }
+    }
+
+    /// Room the conversation is bridged to
+    pub fn room(&self, conversation_id: Uuid) -> Option<&str> {
+        self.rooms.get(&conversation_id).map(String::as_str)
+    }
+
+    pub fn adapter(&self) -> &A {
+        &self.adapter
+    }
+
+    pub fn raygun(&self) -> &R {
+        &self.raygun
+    }
+
+    pub fn raygun_mut(&mut self) -> &mut R {
+        &mut self.raygun
+    }
+}
+
+impl<R: RayGun, A: BridgeAdapter> Bridge<R, A> {
+    /// Forward an event of a bridged conversation to its room
+    pub async fn handle_local_event(&mut self, event: MessageEventKind) -> Result<(), Error> {
+        match event {
+            MessageEventKind::MessageReceived {
+                conversation_id,
+                message_id,
+            } => {
+                let Some(room) = self.rooms.get(&conversation_id).cloned() else {
+                    return Ok(());
+                };
+
+                let message = self.raygun.get_message(conversation_id, message_id).await?;
+
+                if !self.is_bridgeable(&message) {
+                    return Ok(());
+                }
+
+                let reply_to = message
+                    .replied()
+                    .and_then(|id| self.messages.remote(id))
+                    .map(|remote| remote.event_id.clone());
+
+                let event_id = self
+                    .adapter
+                    .send(
+                        &room,
+                        message.sender(),
+                        message.lines(),
+                        reply_to.as_deref(),
+                    )
+                    .await?;
+
+                self.messages
+                    .insert(message_id, RemoteRef { room, event_id });
+            }
+            MessageEventKind::MessageEdited {
+                conversation_id,
+                message_id,
+            } => {
+                if !self.rooms.contains_key(&conversation_id) {
+                    return Ok(());
+                }
+
+                let Some(remote) = self.messages.remote(message_id).cloned() else {
+                    return Ok(());
+                };
+
+                let message = self.raygun.get_message(conversation_id, message_id).await?;
+
+                if !self.is_bridgeable(&message) {
+                    return Ok(());
+                }
+
+                self.adapter
+                    .edit(
+                        &remote.room,
+                        message.sender(),
+                        &remote.event_id,
+                        message.lines(),
+                    )
+                    .await?;
+            }
+            MessageEventKind::MessageReactionAdded {
+                conversation_id,
+                message_id,
+                did_key,
+                reaction,
+            } => {
+                self.forward_reaction(
+                    conversation_id,
+                    message_id,
+                    &did_key,
+                    &reaction,
+                    ReactionState::Add,
+                )
+                .await?;
+            }
+            MessageEventKind::MessageReactionRemoved {
+                conversation_id,
+                message_id,
+                did_key,
+                reaction,
+            } => {
+                self.forward_reaction(
+                    conversation_id,
+                    message_id,
+                    &did_key,
+                    &reaction,
+                    ReactionState::Remove,
+                )
+                .await?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Post an event received from a bridged room into its conversation
+    pub async fn handle_remote_event(&mut self, event: RemoteEvent) -> Result<(), Error> {
+        match event {
+            RemoteEvent::Message {
+                room,
+                event_id,
+                sender,
+                lines,
+                reply_to,
+            } => {
+                let Some(conversation_id) = self.conversations.get(&room).copied() else {
+                    return Ok(());
+                };
+
+                let lines = remote_lines(&sender, &lines);
+
+                let reply_to = reply_to.and_then(|event_id| self.messages.local(&room, &event_id));
+
+                let message_id = match reply_to {
+                    Some(message_id) => {
+                        self.raygun
+                            .reply(conversation_id, message_id, lines)
+                            .await?
+                    }
+                    None => self.raygun.send(conversation_id, lines).await?,
+                };
+
+                self.messages
+                    .insert(message_id, RemoteRef { room, event_id });
+            }
+            RemoteEvent::Edit {
+                room,
+                event_id,
+                sender,
+                lines,
+            } => {
+                let Some(conversation_id) = self.conversations.get(&room).copied() else {
+                    return Ok(());
+                };
+
+                let Some(message_id) = self.messages.local(&room, &event_id) else {
+                    return Ok(());
+                };
+
+                let message = self.raygun.get_message(conversation_id, message_id).await?;
+
+                // Only messages posted on behalf of remote users can be edited by them
+                if message.sender() != &self.did {
+                    return Ok(());
+                }
+
+                self.raygun
+                    .edit(conversation_id, message_id, remote_lines(&sender, &lines))
+                    .await?;
+            }
+            RemoteEvent::Reaction {
+                room,
+                event_id,
+                sender,
+                reaction,
+                state,
+            } => {
+                let Some(conversation_id) = self.conversations.get(&room).copied() else {
+                    return Ok(());
+                };
+
+                let Some(message_id) = self.messages.local(&room, &event_id) else {
+                    return Ok(());
+                };
+
+                let users = self
+                    .reactions
+                    .entry((message_id, reaction.clone()))
+                    .or_default();
+
+                let changed = match state {
+                    ReactionState::Add => users.insert(sender.id) && users.len() == 1,
+                    ReactionState::Remove => users.remove(&sender.id) && users.is_empty(),
+                };
+
+                if users.is_empty() {
+                    self.reactions.remove(&(message_id, reaction.clone()));
+                }
+
+                if changed {
+                    self.raygun
+                        .react(conversation_id, message_id, state, reaction)
+                        .await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Messages posted by the bridge identity originate from the remote network and are not sent back
+    fn is_bridgeable(&self, message: &Message) -> bool {
+        message.message_type() == MessageType::Message && message.sender() != &self.did
+    }
+
+    async fn forward_reaction(
+        &self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        did: &DID,
+        reaction: &str,
+        state: ReactionState,
+    ) -> Result<(), Error> {
+        if did == &self.did || !self.rooms.contains_key(&conversation_id) {
+            return Ok(());
+        }
+
+        let Some(remote) = self.messages.remote(message_id) else {
+            return Ok(());
+        };
+
+        self.adapter
+            .react(&remote.room, did, &remote.event_id, reaction, state)
+            .await
+    }
+
+    /// Bridge events of the bridged conversations and rooms until either event stream ends
+    pub async fn run(mut self) -> Result<(), Error> {
+        let mut events = self.raygun.raygun_subscribe().await?;
+        let mut remote = self.adapter.events().await?;
+        let mut streams: SelectAll<MessageEventStream> = SelectAll::new();
+
+        let conversations = self.rooms.keys().copied().collect::<Vec<_>>();
+        for conversation_id in conversations {
+            streams.push(self.raygun.get_conversation_stream(conversation_id).await?);
+        }
+
+        enum BridgeEvent {
+            RayGun(RayGunEventKind),
+            Message(MessageEventKind),
+            Remote(RemoteEvent),
+        }
+
+        loop {
+            let event = futures::future::poll_fn(|cx| {
+                if let Poll::Ready(event) = events.poll_next_unpin(cx) {
+                    return Poll::Ready(event.map(BridgeEvent::RayGun));
+                }
+
+                if let Poll::Ready(event) = remote.poll_next_unpin(cx) {
+                    return Poll::Ready(event.map(BridgeEvent::Remote));
+                }
+
+                match streams.poll_next_unpin(cx) {
+                    Poll::Ready(Some(event)) => Poll::Ready(Some(BridgeEvent::Message(event))),
+                    // an empty set of streams will return `None`, which is treated as pending
+                    _ => Poll::Pending,
+                }
+            })
+            .await;
+
+            let Some(event) = event else {
+                return Ok(());
+            };
+
+            match event {
+                // Conversations that are bridged before the bridge joins them are subscribed to once created
+                BridgeEvent::RayGun(RayGunEventKind::ConversationCreated { conversation_id })
+                    if self.rooms.contains_key(&conversation_id) =>
+                {
+                    match self.raygun.get_conversation_stream(conversation_id).await {
+                        Ok(stream) => streams.push(stream),
+                        Err(e) => {
+                            tracing::warn!(%conversation_id, error = %e, "unable to subscribe to conversation")
+                        }
+                    }
+                }
+                BridgeEvent::RayGun(_) => {}
+                BridgeEvent::Message(event) => {
+                    if let Err(e) = self.handle_local_event(event).await {
+                        tracing::warn!(error = %e, "unable to forward event to room");
+                    }
+                }
+                BridgeEvent::Remote(event) => {
+                    if let Err(e) = self.handle_remote_event(event).await {
+                        tracing::warn!(error = %e, "unable to post event into conversation");
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use uuid::Uuid;
+
+    use super::{remote_lines, MessageMap, RemoteRef, RemoteUser, MAX_BRIDGED_MESSAGES};
+
+    #[test]
+    fn remote_lines_prefixed_with_name() {
+        let user = RemoteUser {
+            id: "@alice:example.com".into(),
+            display_name: Some("Alice".into()),
+        };
+
+        let lines = remote_lines(&user, &["hello".into(), "world".into()]);
+        assert_eq!(lines, ["Alice: hello", "world"]);
+
+        let user = RemoteUser {
+            id: "@bob:example.com".into(),
+            display_name: None,
+        };
+
+        let lines = remote_lines(&user, &[]);
+        assert_eq!(lines, ["@bob:example.com:"]);
+    }
+
+    #[test]
+    fn message_map_bounded() {
+        let mut map = MessageMap::default();
+        let first = Uuid::new_v4();
+
+        map.insert(
+            first,
+            RemoteRef {
+                room: "room".into(),
+                event_id: "event-0".into(),
+            },
+        );
+        assert_eq!(map.local("room", "event-0"), Some(first));
+
+        for index in 1..=MAX_BRIDGED_MESSAGES {
+            map.insert(
+                Uuid::new_v4(),
+                RemoteRef {
+                    room: "room".into(),
+                    event_id: format!("event-{index}"),
+                },
+            );
+        }
+
+        assert!(map.remote(first).is_none());
+        assert!(map.local("room", "event-0").is_none());
+        assert_eq!(map.by_local.len(), MAX_BRIDGED_MESSAGES);
+    }
+}
@@ -0,0 +1,767 @@
+//! [`BridgeAdapter`] for Matrix, running as an application service.
+//!
+//! Every member of a bridged conversation is puppeted by a Matrix user in the namespace of the application
+//! service (eg `@warp_did=3akey=3az6mk...:example.com`), which is registered and joined to the room the first time
+//! it is needed. The HTTP layer is left to the embedder: requests to the homeserver are made through a
+//! [`MatrixTransport`], and transactions pushed by the homeserver to the application service
+//! (`PUT /_matrix/app/v1/transactions/{txnId}`) are handed to [`MatrixAppService::push_transaction`].
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use futures::{
+    channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender},
+    stream::BoxStream,
+    StreamExt,
+};
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use warp::{crypto::DID, error::Error, raygun::ReactionState};
+
+use super::{BridgeAdapter, RemoteEvent, RemoteUser};
+
+/// Amount of transaction ids remembered to ignore transactions that are retried by the homeserver
+const MAX_TRANSACTIONS: usize = 1_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatrixMethod {
+    Get,
+    Post,
+    Put,
+}
+
+/// Request to the client-server API of the homeserver
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatrixRequest {
+    pub method: MatrixMethod,
+    /// Path of the endpoint, with each parameter already percent-encoded
+    pub path: String,
+    /// User the request is made on behalf of. The transport is expected to pass it as the `user_id` query
+    /// parameter alongside the `as_token` of the application service
+    pub user_id: Option<String>,
+    pub body: Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatrixResponse {
+    pub status: u16,
+    pub body: Value,
+}
+
+impl MatrixResponse {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    pub fn errcode(&self) -> Option<&str> {
+        self.body.get("errcode").and_then(Value::as_str)
+    }
+}
+
+#[async_trait::async_trait]
+pub trait MatrixTransport: Send + Sync + 'static {
+    /// Send a request to the homeserver using the `as_token` of the application service
+    async fn request(&self, request: MatrixRequest) -> Result<MatrixResponse, Error>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatrixConfig {
+    /// Server name of the homeserver (eg `example.com`)
+    pub server_name: String,
+    /// User of the application service (`sender_localpart` of the registration)
+    pub bot_localpart: String,
+    /// Prefix of the localpart of puppets, which should match the user namespace of the registration
+    pub puppet_prefix: String,
+}
+
+impl MatrixConfig {
+    pub fn new(server_name: impl Into<String>) -> Self {
+        Self {
+            server_name: server_name.into(),
+            bot_localpart: "warp".into(),
+            puppet_prefix: "warp_".into(),
+        }
+    }
+
+    pub fn bot_user_id(&self) -> String {
+        format!("@{}:{}", self.bot_localpart, self.server_name)
+    }
+
+    /// Matrix user puppeting the identity
+    pub fn puppet_user_id(&self, did: &DID) -> String {
+        format!(
+            "@{}{}:{}",
+            self.puppet_prefix,
+            escape_localpart(&did.to_string()),
+            self.server_name
+        )
+    }
+
+    /// Whether the user is managed by the application service
+    pub fn is_managed(&self, user_id: &str) -> bool {
+        if user_id == self.bot_user_id() {
+            return true;
+        }
+
+        let Some((localpart, server_name)) = user_id
+            .strip_prefix('@')
+            .and_then(|user| user.split_once(':'))
+        else {
+            return false;
+        };
+
+        server_name == self.server_name && localpart.starts_with(&self.puppet_prefix)
+    }
+}
+
+/// Escape a string into a valid localpart, following the mapping suggested by the Matrix specification
+/// (`A` => `_a`, `_` => `__` and any other invalid character into `=xx`)
+pub fn escape_localpart(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for byte in input.bytes() {
+        match byte {
+            b'A'..=b'Z' => {
+                escaped.push('_');
+                escaped.push(byte.to_ascii_lowercase() as char);
+            }
+            b'_' => escaped.push_str("__"),
+            b'a'..=b'z' | b'0'..=b'9' | b'.' | b'-' | b'/' => escaped.push(byte as char),
+            _ => escaped.push_str(&format!("={byte:02x}")),
+        }
+    }
+    escaped
+}
+
+fn encode_path(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ReactionKey {
+    room: String,
+    event_id: String,
+    sender: String,
+    reaction: String,
+}
+
+#[derive(Default)]
+struct State {
+    registered: HashSet<String>,
+    joined: HashSet<(String, String)>,
+    display_names: HashMap<String, String>,
+    // reactions sent on behalf of puppets, to be redacted when removed
+    sent_reactions: HashMap<ReactionKey, String>,
+    // reactions received from the room by their event id, to be removed when redacted
+    received_reactions: HashMap<String, ReactionKey>,
+    transactions: VecDeque<String>,
+}
+
+pub struct MatrixAppService<T: MatrixTransport> {
+    config: MatrixConfig,
+    transport: T,
+    state: Mutex<State>,
+    event_tx: UnboundedSender<RemoteEvent>,
+    event_rx: Mutex<Option<UnboundedReceiver<RemoteEvent>>>,
+}
+
+impl<T: MatrixTransport> MatrixAppService<T> {
+    pub fn new(config: MatrixConfig, transport: T) -> Self {
+        let (event_tx, event_rx) = unbounded();
+        Self {
+            config,
+            transport,
+            state: Mutex::default(),
+            event_tx,
+            event_rx: Mutex::new(Some(event_rx)),
+        }
+    }
+
+    pub fn config(&self) -> &MatrixConfig {
+        &self.config
+    }
+
+    /// Set the display name of the puppet of the identity
+    pub async fn set_display_name(&self, did: &DID, name: &str) -> Result<(), Error> {
+        let user_id = self.ensure_registered(did).await?;
+        let path = format!(
+            "/_matrix/client/v3/profile/{}/displayname",
+            encode_path(&user_id)
+        );
+        self.request(
+            MatrixMethod::Put,
+            path,
+            Some(user_id),
+            json!({ "displayname": name }),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Process a transaction pushed by the homeserver. Transactions that were already processed are ignored
+    pub fn push_transaction(&self, txn_id: &str, body: &Value) -> Result<(), Error> {
+        {
+            let mut state = self.state.lock();
+            if state.transactions.iter().any(|id| id == txn_id) {
+                return Ok(());
+            }
+            if state.transactions.len() >= MAX_TRANSACTIONS {
+                state.transactions.pop_front();
+            }
+            state.transactions.push_back(txn_id.to_string());
+        }
+
+        let events = body
+            .get("events")
+            .and_then(Value::as_array)
+            .ok_or_else(|| Error::OtherWithContext("Transaction is missing events".into()))?;
+
+        for event in events {
+            if let Some(event) = self.translate(event) {
+                if self.event_tx.unbounded_send(event).is_err() {
+                    tracing::warn!("bridge is no longer receiving events");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn translate(&self, event: &Value) -> Option<RemoteEvent> {
+        let kind = event.get("type")?.as_str()?;
+        let room = event.get("room_id")?.as_str()?.to_string();
+        let sender = event.get("sender")?.as_str()?;
+        let event_id = event.get("event_id")?.as_str()?.to_string();
+        let content = event.get("content")?;
+
+        if kind == "m.room.member" {
+            if let (Some(user_id), Some(name)) = (
+                event.get("state_key").and_then(Value::as_str),
+                content.get("displayname").and_then(Value::as_str),
+            ) {
+                self.state
+                    .lock()
+                    .display_names
+                    .insert(user_id.to_string(), name.to_string());
+            }
+            return None;
+        }
+
+        if self.config.is_managed(sender) {
+            return None;
+        }
+
+        let user = RemoteUser {
+            id: sender.to_string(),
+            display_name: self.state.lock().display_names.get(sender).cloned(),
+        };
+
+        let relates_to = content.get("m.relates_to");
+
+        match kind {
+            "m.room.message" => {
+                let msgtype = content.get("msgtype")?.as_str()?;
+                if !matches!(msgtype, "m.text" | "m.notice" | "m.emote") {
+                    return None;
+                }
+
+                if relates_to
+                    .and_then(|r| r.get("rel_type"))
+                    .and_then(Value::as_str)
+                    == Some("m.replace")
+                {
+                    let original = relates_to?.get("event_id")?.as_str()?.to_string();
+                    let body = content
+                        .get("m.new_content")
+                        .and_then(|c| c.get("body"))
+                        .and_then(Value::as_str)
+                        .or_else(|| {
+                            content
+                                .get("body")
+                                .and_then(Value::as_str)
+                                .map(|body| body.strip_prefix("* ").unwrap_or(body))
+                        })?;
+
+                    return Some(RemoteEvent::Edit {
+                        room,
+                        event_id: original,
+                        sender: user,
+                        lines: message_lines(msgtype, body),
+                    });
+                }
+
+                let reply_to = relates_to
+                    .and_then(|r| r.get("m.in_reply_to"))
+                    .and_then(|r| r.get("event_id"))
+                    .and_then(Value::as_str)
+                    .map(ToString::to_string);
+
+                let mut body = content.get("body")?.as_str()?;
+                if reply_to.is_some() {
+                    body = strip_reply_fallback(body);
+                }
+
+                Some(RemoteEvent::Message {
+                    room,
+                    event_id,
+                    sender: user,
+                    lines: message_lines(msgtype, body),
+                    reply_to,
+                })
+            }
+            "m.reaction" => {
+                let relates_to = relates_to?;
+                if relates_to.get("rel_type")?.as_str()? != "m.annotation" {
+                    return None;
+                }
+
+                let target = relates_to.get("event_id")?.as_str()?.to_string();
+                let reaction = relates_to.get("key")?.as_str()?.to_string();
+
+                self.state.lock().received_reactions.insert(
+                    event_id,
+                    ReactionKey {
+                        room: room.clone(),
+                        event_id: target.clone(),
+                        sender: user.id.clone(),
+                        reaction: reaction.clone(),
+                    },
+                );
+
+                Some(RemoteEvent::Reaction {
+                    room,
+                    event_id: target,
+                    sender: user,
+                    reaction,
+                    state: ReactionState::Add,
+                })
+            }
+            "m.room.redaction" => {
+                let redacts = event
+                    .get("redacts")
+                    .or_else(|| content.get("redacts"))?
+                    .as_str()?;
+
+                let key = self.state.lock().received_reactions.remove(redacts)?;
+
+                Some(RemoteEvent::Reaction {
+                    room: key.room,
+                    event_id: key.event_id,
+                    sender: user,
+                    reaction: key.reaction,
+                    state: ReactionState::Remove,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    async fn request(
+        &self,
+        method: MatrixMethod,
+        path: String,
+        user_id: Option<String>,
+        body: Value,
+    ) -> Result<MatrixResponse, Error> {
+        let response = self
+            .transport
+            .request(MatrixRequest {
+                method,
+                path,
+                user_id,
+                body,
+            })
+            .await?;
+
+        if !response.is_success() {
+            return Err(Error::OtherWithContext(format!(
+                "Homeserver responded with {}: {}",
+                response.status,
+                response.errcode().unwrap_or("unknown error")
+            )));
+        }
+
+        Ok(response)
+    }
+
+    async fn ensure_registered(&self, did: &DID) -> Result<String, Error> {
+        let user_id = self.config.puppet_user_id(did);
+
+        if self.state.lock().registered.contains(&user_id) {
+            return Ok(user_id);
+        }
+
+        let localpart = format!(
+            "{}{}",
+            self.config.puppet_prefix,
+            escape_localpart(&did.to_string())
+        );
+
+        let response = self
+            .transport
+            .request(MatrixRequest {
+                method: MatrixMethod::Post,
+                path: "/_matrix/client/v3/register".into(),
+                user_id: None,
+                body: json!({
+                    "type": "m.login.application_service",
+                    "username": localpart,
+                }),
+            })
+            .await?;
+
+        if !response.is_success() && response.errcode() != Some("M_USER_IN_USE") {
+            return Err(Error::OtherWithContext(format!(
+                "Unable to register {user_id}: {}",
+                response.errcode().unwrap_or("unknown error")
+            )));
+        }
+
+        self.state.lock().registered.insert(user_id.clone());
+        Ok(user_id)
+    }
+
+    async fn ensure_joined(&self, room: &str, did: &DID) -> Result<String, Error> {
+        let user_id = self.ensure_registered(did).await?;
+        let key = (room.to_string(), user_id.clone());
+
+        if self.state.lock().joined.contains(&key) {
+            return Ok(user_id);
+        }
+
+        let path = format!("/_matrix/client/v3/join/{}", encode_path(room));
+        self.request(MatrixMethod::Post, path, Some(user_id.clone()), json!({}))
+            .await?;
+
+        self.state.lock().joined.insert(key);
+        Ok(user_id)
+    }
+
+    async fn send_event(
+        &self,
+        room: &str,
+        user_id: String,
+        kind: &str,
+        content: Value,
+    ) -> Result<String, Error> {
+        let path = format!(
+            "/_matrix/client/v3/rooms/{}/send/{}/{}",
+            encode_path(room),
+            encode_path(kind),
+            Uuid::new_v4()
+        );
+
+        let response = self
+            .request(MatrixMethod::Put, path, Some(user_id), content)
+            .await?;
+
+        response
+            .body
+            .get("event_id")
+            .and_then(Value::as_str)
+            .map(ToString::to_string)
+            .ok_or_else(|| Error::OtherWithContext("Homeserver did not return an event id".into()))
+    }
+}
+
+fn message_lines(msgtype: &str, body: &str) -> Vec<String> {
+    let mut lines = body.lines().map(ToString::to_string).collect::<Vec<_>>();
+    if msgtype == "m.emote" {
+        if let Some(first) = lines.first_mut() {
+            *first = format!("* {first}");
+        }
+    }
+    lines
+}
+
+// Replies include a quote of the original message (`> <@user:example.com> ...`) followed by a blank line
+fn strip_reply_fallback(body: &str) -> &str {
+    if !body.starts_with("> ") {
+        return body;
+    }
+
+    match body.split_once("\n\n") {
+        Some((_, body)) => body,
+        None => body,
+    }
+}
+
+fn text_content(lines: &[String]) -> Value {
+    json!({
+        "msgtype": "m.text",
+        "body": lines.join("\n"),
+    })
+}
+
+#[async_trait::async_trait]
+impl<T: MatrixTransport> BridgeAdapter for MatrixAppService<T> {
+    async fn send(
+        &self,
+        room: &str,
+        sender: &DID,
+        lines: &[String],
+        reply_to: Option<&str>,
+    ) -> Result<String, Error> {
+        let user_id = self.ensure_joined(room, sender).await?;
+
+        let mut content = text_content(lines);
+        if let Some(event_id) = reply_to {
+            content["m.relates_to"] = json!({ "m.in_reply_to": { "event_id": event_id } });
+        }
+
+        self.send_event(room, user_id, "m.room.message", content)
+            .await
+    }
+
+    async fn edit(
+        &self,
+        room: &str,
+        sender: &DID,
+        event_id: &str,
+        lines: &[String],
+    ) -> Result<(), Error> {
+        let user_id = self.ensure_joined(room, sender).await?;
+
+        let new_content = text_content(lines);
+        let content = json!({
+            "msgtype": "m.text",
+            "body": format!("* {}", lines.join("\n")),
+            "m.new_content": new_content,
+            "m.relates_to": {
+                "rel_type": "m.replace",
+                "event_id": event_id,
+            },
+        });
+
+        self.send_event(room, user_id, "m.room.message", content)
+            .await?;
+        Ok(())
+    }
+
+    async fn react(
+        &self,
+        room: &str,
+        sender: &DID,
+        event_id: &str,
+        reaction: &str,
+        state: ReactionState,
+    ) -> Result<(), Error> {
+        let user_id = self.ensure_joined(room, sender).await?;
+
+        let key = ReactionKey {
+            room: room.to_string(),
+            event_id: event_id.to_string(),
+            sender: user_id.clone(),
+            reaction: reaction.to_string(),
+        };
+
+        match state {
+            ReactionState::Add => {
+                if self.state.lock().sent_reactions.contains_key(&key) {
+                    return Ok(());
+                }
+
+                let content = json!({
+                    "m.relates_to": {
+                        "rel_type": "m.annotation",
+                        "event_id": event_id,
+                        "key": reaction,
+                    },
+                });
+
+                let reaction_id = self
+                    .send_event(room, user_id, "m.reaction", content)
+                    .await?;
+
+                self.state.lock().sent_reactions.insert(key, reaction_id);
+            }
+            ReactionState::Remove => {
+                let Some(reaction_id) = self.state.lock().sent_reactions.remove(&key) else {
+                    return Ok(());
+                };
+
+                let path = format!(
+                    "/_matrix/client/v3/rooms/{}/redact/{}/{}",
+                    encode_path(room),
+                    encode_path(&reaction_id),
+                    Uuid::new_v4()
+                );
+
+                self.request(MatrixMethod::Put, path, Some(user_id), json!({}))
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn events(&self) -> Result<BoxStream<'static, RemoteEvent>, Error> {
+        let rx = self.event_rx.lock().take().ok_or_else(|| {
+            Error::OtherWithContext("Events of the application service are already taken".into())
+        })?;
+        Ok(rx.boxed())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::StreamExt;
+    use serde_json::json;
+
+    use warp::{error::Error, raygun::ReactionState};
+
+    use crate::bridge::{BridgeAdapter, RemoteEvent, RemoteUser};
+
+    use super::{
+        escape_localpart, MatrixAppService, MatrixConfig, MatrixRequest, MatrixResponse,
+        MatrixTransport,
+    };
+
+    struct NoTransport;
+
+    #[async_trait::async_trait]
+    impl MatrixTransport for NoTransport {
+        async fn request(&self, _: MatrixRequest) -> Result<MatrixResponse, Error> {
+            Err(Error::Unimplemented)
+        }
+    }
+
+    fn service() -> MatrixAppService<NoTransport> {
+        MatrixAppService::new(MatrixConfig::new("example.com"), NoTransport)
+    }
+
+    #[test]
+    fn localpart_escaped() {
+        assert_eq!(escape_localpart("did:key:z6Mk_a"), "did=3akey=3az6_mk__a");
+    }
+
+    #[test]
+    fn managed_users() {
+        let config = MatrixConfig::new("example.com");
+        assert!(config.is_managed("@warp:example.com"));
+        assert!(config.is_managed("@warp_did=3akey=3az6_mk:example.com"));
+        assert!(!config.is_managed("@warp_did=3akey=3az6_mk:other.com"));
+        assert!(!config.is_managed("@alice:example.com"));
+    }
+
+    #[tokio::test]
+    async fn transaction_translated() -> anyhow::Result<()> {
+        let service = service();
+        let events = service.events().await?;
+
+        let alice = RemoteUser {
+            id: "@alice:example.com".into(),
+            display_name: Some("Alice".into()),
+        };
+
+        let transaction = json!({
+            "events": [
+                {
+                    "type": "m.room.member",
+                    "room_id": "!room:example.com",
+                    "sender": "@alice:example.com",
+                    "state_key": "@alice:example.com",
+                    "event_id": "$member",
+                    "content": { "membership": "join", "displayname": "Alice" }
+                },
+                {
+                    "type": "m.room.message",
+                    "room_id": "!room:example.com",
+                    "sender": "@warp_did=3akey=3az6_mk:example.com",
+                    "event_id": "$echo",
+                    "content": { "msgtype": "m.text", "body": "echo" }
+                },
+                {
+                    "type": "m.room.message",
+                    "room_id": "!room:example.com",
+                    "sender": "@alice:example.com",
+                    "event_id": "$message",
+                    "content": {
+                        "msgtype": "m.text",
+                        "body": "> <@bob:example.com> hi\n\nhello\nworld",
+                        "m.relates_to": { "m.in_reply_to": { "event_id": "$original" } }
+                    }
+                },
+                {
+                    "type": "m.room.message",
+                    "room_id": "!room:example.com",
+                    "sender": "@alice:example.com",
+                    "event_id": "$edit",
+                    "content": {
+                        "msgtype": "m.text",
+                        "body": "* hello there",
+                        "m.new_content": { "msgtype": "m.text", "body": "hello there" },
+                        "m.relates_to": { "rel_type": "m.replace", "event_id": "$message" }
+                    }
+                },
+                {
+                    "type": "m.reaction",
+                    "room_id": "!room:example.com",
+                    "sender": "@alice:example.com",
+                    "event_id": "$reaction",
+                    "content": {
+                        "m.relates_to": { "rel_type": "m.annotation", "event_id": "$original", "key": "👍" }
+                    }
+                },
+                {
+                    "type": "m.room.redaction",
+                    "room_id": "!room:example.com",
+                    "sender": "@alice:example.com",
+                    "event_id": "$redaction",
+                    "redacts": "$reaction",
+                    "content": {}
+                }
+            ]
+        });
+
+        service.push_transaction("1", &transaction)?;
+        // retried transactions are ignored
+        service.push_transaction("1", &transaction)?;
+        drop(service);
+
+        let events = events.collect::<Vec<_>>().await;
+
+        assert_eq!(
+            events,
+            [
+                RemoteEvent::Message {
+                    room: "!room:example.com".into(),
+                    event_id: "$message".into(),
+                    sender: alice.clone(),
+                    lines: vec!["hello".into(), "world".into()],
+                    reply_to: Some("$original".into()),
+                },
+                RemoteEvent::Edit {
+                    room: "!room:example.com".into(),
+                    event_id: "$message".into(),
+                    sender: alice.clone(),
+                    lines: vec!["hello there".into()],
+                },
+                RemoteEvent::Reaction {
+                    room: "!room:example.com".into(),
+                    event_id: "$original".into(),
+                    sender: alice.clone(),
+                    reaction: "👍".into(),
+                    state: ReactionState::Add,
+                },
+                RemoteEvent::Reaction {
+                    room: "!room:example.com".into(),
+                    event_id: "$original".into(),
+                    sender: alice,
+                    reaction: "👍".into(),
+                    state: ReactionState::Remove,
+                },
+            ]
+        );
+
+        Ok(())
+    }
+}
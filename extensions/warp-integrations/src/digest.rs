@@ -0,0 +1,489 @@
+//! Periodic digest of events that were not seen by the user.
+//!
+//! A [`DigestCollector`] aggregates mentions, friend requests and file shares as they are received. Once the
+//! configured period elapsed, the collected items are taken as a [`Digest`], which can be rendered as JSON or HTML
+//! for the embedding application to deliver (eg by email). Items are dropped from the pending digest as the user
+//! catches up, such as when a conversation is read or a friend request is answered.
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use warp::{
+    crypto::DID,
+    error::Error,
+    multipass::{
+        identity::ShortId,
+        notification::{NotificationMode, NotificationPreferences},
+        MultiPassEventKind,
+    },
+    raygun::{community::RayGunCommunity, Message, MessageEventKind, MessageType, RayGun},
+};
+
+/// Maximum length of the excerpt of a message, in characters
+pub const EXCERPT_LENGTH: usize = 140;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DigestConfig {
+    /// Period covered by a digest
+    pub period: Duration,
+    /// Maximum amount of items kept for each section. The oldest items are dropped first
+    pub max_items: usize,
+}
+
+impl Default for DigestConfig {
+    fn default() -> Self {
+        Self {
+            period: Duration::days(1),
+            max_items: 50,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DigestMention {
+    pub conversation_id: Uuid,
+    pub message_id: Uuid,
+    pub sender: DID,
+    pub sender_name: String,
+    pub date: DateTime<Utc>,
+    pub excerpt: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DigestFriendRequest {
+    pub from: DID,
+    pub from_name: String,
+    pub date: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DigestFile {
+    pub name: String,
+    pub size: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DigestFileShare {
+    pub conversation_id: Uuid,
+    pub message_id: Uuid,
+    pub sender: DID,
+    pub sender_name: String,
+    pub date: DateTime<Utc>,
+    pub files: Vec<DigestFile>,
+}
+
+/// Summary of the events received over a period
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Digest {
+    pub identity: DID,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    #[serde(default)]
+    pub mentions: Vec<DigestMention>,
+    #[serde(default)]
+    pub friend_requests: Vec<DigestFriendRequest>,
+    #[serde(default)]
+    pub file_shares: Vec<DigestFileShare>,
+}
+
+impl Digest {
+    pub fn is_empty(&self) -> bool {
+        self.mentions.is_empty() && self.friend_requests.is_empty() && self.file_shares.is_empty()
+    }
+
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(self).map_err(Error::from)
+    }
+
+    pub fn from_json(data: &str) -> Result<Self, Error> {
+        serde_json::from_str(data).map_err(Error::from)
+    }
+
+    /// Render the digest as a standalone HTML document
+    pub fn to_html(&self) -> String {
+        let title = format!(
+            "Digest from {} to {}",
+            self.start.format("%Y-%m-%d %H:%M UTC"),
+            self.end.format("%Y-%m-%d %H:%M UTC")
+        );
+
+        let mut html = String::new();
+        html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        html.push_str(&format!(
+            "<title>{title}</title>\n</head>\n<body>\n<h1>{title}</h1>\n"
+        ));
+
+        if self.is_empty() {
+            html.push_str("<p>Nothing new.</p>\n");
+        }
+
+        if !self.mentions.is_empty() {
+            html.push_str(&format!(
+                "<h2>Mentions ({})</h2>\n<ul>\n",
+                self.mentions.len()
+            ));
+            for mention in &self.mentions {
+                html.push_str(&format!(
+                    "<li><strong>{}</strong> <time>{}</time>: {}</li>\n",
+                    escape_html(&mention.sender_name),
+                    mention.date.format("%Y-%m-%d %H:%M"),
+                    escape_html(&mention.excerpt)
+                ));
+            }
+            html.push_str("</ul>\n");
+        }
+
+        if !self.friend_requests.is_empty() {
+            html.push_str(&format!(
+                "<h2>Friend requests ({})</h2>\n<ul>\n",
+                self.friend_requests.len()
+            ));
+            for request in &self.friend_requests {
+                html.push_str(&format!(
+                    "<li><strong>{}</strong> <time>{}</time></li>\n",
+                    escape_html(&request.from_name),
+                    request.date.format("%Y-%m-%d %H:%M")
+                ));
+            }
+            html.push_str("</ul>\n");
+        }
+
+        if !self.file_shares.is_empty() {
+            html.push_str(&format!(
+                "<h2>Shared files ({})</h2>\n<ul>\n",
+                self.file_shares.len()
+            ));
+            for share in &self.file_shares {
+                let files = share
+                    .files
+                    .iter()
+                    .map(|file| escape_html(&file.name))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                html.push_str(&format!(
+                    "<li><strong>{}</strong> <time>{}</time>: {}</li>\n",
+                    escape_html(&share.sender_name),
+                    share.date.format("%Y-%m-%d %H:%M"),
+                    files
+                ));
+            }
+            html.push_str("</ul>\n");
+        }
+
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+fn excerpt(lines: &[String]) -> String {
+    let text = lines.join(" ");
+    match text.chars().count() > EXCERPT_LENGTH {
+        true => format!("{}…", text.chars().take(EXCERPT_LENGTH).collect::<String>()),
+        false => text,
+    }
+}
+
+pub struct DigestCollector {
+    identity: DID,
+    config: DigestConfig,
+    preferences: NotificationPreferences,
+    start: DateTime<Utc>,
+    names: HashMap<DID, String>,
+    mentions: Vec<DigestMention>,
+    friend_requests: Vec<DigestFriendRequest>,
+    file_shares: Vec<DigestFileShare>,
+}
+
+impl DigestCollector {
+    /// Create a collector for the local identity, with the first period starting at `start`
+    pub fn new(identity: DID, config: DigestConfig, start: DateTime<Utc>) -> Self {
+        Self {
+            identity,
+            config,
+            preferences: NotificationPreferences::default(),
+            start,
+            names: HashMap::new(),
+            mentions: vec![],
+            friend_requests: vec![],
+            file_shares: vec![],
+        }
+    }
+
+    /// Preferences used to skip messages of muted conversations
+    pub fn set_preferences(&mut self, preferences: NotificationPreferences) {
+        self.preferences = preferences;
+    }
+
+    /// Set the name shown for an identity. Identities without a name are shown by their short id
+    pub fn set_name(&mut self, did: DID, name: impl Into<String>) {
+        self.names.insert(did, name.into());
+    }
+
+    fn name(&self, did: &DID) -> String {
+        match self.names.get(did) {
+            Some(name) => name.clone(),
+            None => ShortId::try_from(did.to_string())
+                .map(|id| id.to_string())
+                .unwrap_or_else(|_| did.to_string()),
+        }
+    }
+
+    /// Record a received message, which is kept if it mentions the local identity or shares files
+    pub fn record_message(&mut self, message: &Message) {
+        if message.message_type() != MessageType::Message || message.sender() == &self.identity {
+            return;
+        }
+
+        let conversation_id = message.conversation_id();
+
+        match self.preferences.conversation_mode(conversation_id) {
+            NotificationMode::Muted => return,
+            NotificationMode::MentionsOnly if !message.mentions().contains(&self.identity) => {
+                return
+            }
+            _ => {}
+        }
+
+        if message.mentions().contains(&self.identity) {
+            let mention = DigestMention {
+                conversation_id,
+                message_id: message.id(),
+                sender: message.sender().clone(),
+                sender_name: self.name(message.sender()),
+                date: message.date(),
+                excerpt: excerpt(message.lines()),
+            };
+            push_bounded(&mut self.mentions, mention, self.config.max_items);
+        }
+
+        if !message.attachments().is_empty() {
+            let share = DigestFileShare {
+                conversation_id,
+                message_id: message.id(),
+                sender: message.sender().clone(),
+                sender_name: self.name(message.sender()),
+                date: message.date(),
+                files: message
+                    .attachments()
+                    .iter()
+                    .map(|file| DigestFile {
+                        name: file.name(),
+                        size: file.size(),
+                    })
+                    .collect(),
+            };
+            push_bounded(&mut self.file_shares, share, self.config.max_items);
+        }
+    }
+
+    pub fn record_friend_request(&mut self, from: DID, date: DateTime<Utc>) {
+        self.friend_requests.retain(|request| request.from != from);
+        let request = DigestFriendRequest {
+            from_name: self.name(&from),
+            from,
+            date,
+        };
+        push_bounded(&mut self.friend_requests, request, self.config.max_items);
+    }
+
+    /// Drop the pending items of a conversation that was read
+    pub fn mark_conversation_read(&mut self, conversation_id: Uuid) {
+        self.mentions
+            .retain(|mention| mention.conversation_id != conversation_id);
+        self.file_shares
+            .retain(|share| share.conversation_id != conversation_id);
+    }
+
+    /// Record friend requests, dropping those that were answered
+    pub fn handle_multipass_event(&mut self, event: &MultiPassEventKind) {
+        match event {
+            MultiPassEventKind::FriendRequestReceived { from, date, .. } => {
+                self.record_friend_request(from.clone(), *date)
+            }
+            MultiPassEventKind::IncomingFriendRequestRejected { did }
+            | MultiPassEventKind::IncomingFriendRequestClosed { did }
+            | MultiPassEventKind::FriendAdded { did }
+            | MultiPassEventKind::Blocked { did } => {
+                self.friend_requests.retain(|request| &request.from != did)
+            }
+            _ => {}
+        }
+    }
+
+    /// Record received messages of a conversation or community stream
+    pub async fn handle_message_event<R: RayGun>(
+        &mut self,
+        raygun: &R,
+        event: &MessageEventKind,
+    ) -> Result<(), Error> {
+        let message = match *event {
+            MessageEventKind::MessageReceived {
+                conversation_id,
+                message_id,
+            } => raygun.get_message(conversation_id, message_id).await?,
+            MessageEventKind::CommunityMessageReceived {
+                community_id,
+                channel_id,
+                message_id,
+            } => {
+                raygun
+                    .get_community_channel_message(community_id, channel_id, message_id)
+                    .await?
+            }
+            _ => return Ok(()),
+        };
+
+        self.record_message(&message);
+        Ok(())
+    }
+
+    /// Check to determine if the current period elapsed
+    pub fn is_due(&self, now: DateTime<Utc>) -> bool {
+        now >= self.start + self.config.period
+    }
+
+    /// Take the items collected since the start of the period, starting a new period at `now`
+    pub fn take(&mut self, now: DateTime<Utc>) -> Digest {
+        let digest = Digest {
+            identity: self.identity.clone(),
+            start: self.start,
+            end: now,
+            mentions: std::mem::take(&mut self.mentions),
+            friend_requests: std::mem::take(&mut self.friend_requests),
+            file_shares: std::mem::take(&mut self.file_shares),
+        };
+        self.start = now;
+        digest
+    }
+
+    /// Take the digest once the period elapsed. `None` is returned if the period did not elapse or if nothing
+    /// was collected, in which case a new period is started all the same
+    pub fn poll_digest(&mut self, now: DateTime<Utc>) -> Option<Digest> {
+        if !self.is_due(now) {
+            return None;
+        }
+
+        let digest = self.take(now);
+        (!digest.is_empty()).then_some(digest)
+    }
+}
+
+fn push_bounded<T>(items: &mut Vec<T>, item: T, max: usize) {
+    if max == 0 {
+        return;
+    }
+    if items.len() >= max {
+        items.remove(0);
+    }
+    items.push(item);
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, Utc};
+    use uuid::Uuid;
+
+    use warp::{
+        crypto::DID,
+        multipass::{
+            notification::{NotificationMode, NotificationPreferences},
+            MultiPassEventKind,
+        },
+        raygun::Message,
+    };
+
+    use super::{DigestCollector, DigestConfig};
+
+    fn message(conversation_id: Uuid, sender: &DID, mentions: Vec<DID>, text: &str) -> Message {
+        let mut message = Message::default();
+        message.set_id(Uuid::new_v4());
+        message.set_conversation_id(conversation_id);
+        message.set_sender(sender.clone());
+        message.set_mentions(mentions);
+        message.set_lines(vec![text.into()]);
+        message
+    }
+
+    #[test]
+    fn mentions_collected() {
+        let own = DID::default();
+        let other = DID::default();
+        let now = Utc::now();
+        let conversation_id = Uuid::new_v4();
+
+        let mut collector = DigestCollector::new(own.clone(), DigestConfig::default(), now);
+        collector.set_name(other.clone(), "<JaneDoe>");
+
+        collector.record_message(&message(conversation_id, &other, vec![], "hello"));
+        collector.record_message(&message(
+            conversation_id,
+            &other,
+            vec![own.clone()],
+            "hello @you",
+        ));
+        collector.record_message(&message(conversation_id, &own, vec![own.clone()], "me"));
+
+        assert!(collector.poll_digest(now + Duration::hours(1)).is_none());
+
+        let digest = collector
+            .poll_digest(now + Duration::days(1))
+            .expect("digest is due");
+        assert_eq!(digest.mentions.len(), 1);
+        assert_eq!(digest.mentions[0].excerpt, "hello @you");
+
+        let html = digest.to_html();
+        assert!(html.contains("&lt;JaneDoe&gt;"));
+        assert!(!html.contains("<JaneDoe>"));
+
+        let json = digest.to_json().unwrap();
+        assert_eq!(super::Digest::from_json(&json).unwrap(), digest);
+
+        // a new period starts once the digest is taken
+        assert!(!collector.is_due(now + Duration::days(1)));
+    }
+
+    #[test]
+    fn answered_and_read_items_dropped() {
+        let own = DID::default();
+        let other = DID::default();
+        let now = Utc::now();
+        let conversation_id = Uuid::new_v4();
+        let muted = Uuid::new_v4();
+
+        let mut collector = DigestCollector::new(own.clone(), DigestConfig::default(), now);
+
+        let mut preferences = NotificationPreferences::default();
+        preferences.set_conversation_mode(muted, Some(NotificationMode::Muted));
+        collector.set_preferences(preferences);
+
+        collector.handle_multipass_event(&MultiPassEventKind::FriendRequestReceived {
+            from: other.clone(),
+            date: now,
+            message: None,
+        });
+        collector.record_message(&message(conversation_id, &other, vec![own.clone()], "a"));
+        collector.record_message(&message(muted, &other, vec![own.clone()], "b"));
+
+        collector.handle_multipass_event(&MultiPassEventKind::FriendAdded { did: other });
+        collector.mark_conversation_read(conversation_id);
+
+        assert!(collector.take(now + Duration::days(1)).is_empty());
+    }
+}
@@ -0,0 +1,318 @@
+//! Gateway mirroring community channels to rooms of text based chat networks (eg IRC or XMPP) on top of
+//! [`RayGun`].
+//!
+//! Unlike a [`Bridge`](crate::bridge::Bridge), which puppets every member, a [`Gateway`] goes through a single
+//! relay account on the remote network: messages of a mirrored channel are posted to the room by the relay account
+//! with the name of their author (eg `<JohnDoe> hello`), and messages of the room are posted into the channel by the
+//! identity of the gateway, prefixed by the nickname of their author. Edits are posted as corrections, while
+//! reactions are not mirrored as the networks have no equivalent.
+use std::{collections::HashMap, task::Poll};
+
+use futures::{
+    stream::{BoxStream, SelectAll},
+    StreamExt,
+};
+use indexmap::IndexSet;
+use uuid::Uuid;
+
+use warp::{
+    crypto::DID,
+    error::Error,
+    multipass::identity::ShortId,
+    raygun::{
+        community::RayGunCommunity, MessageEventKind, MessageEventStream, MessageType, RayGun,
+        RayGunEventKind,
+    },
+};
+
+use crate::bridge::{remote_lines, RemoteUser};
+
+pub mod irc;
+pub mod xmpp;
+
+/// Message received from a room of the remote network
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GatewayMessage {
+    pub room: String,
+    /// Nickname of the author
+    pub nick: String,
+    pub lines: Vec<String>,
+}
+
+#[async_trait::async_trait]
+pub trait GatewayRelay: Send + Sync + 'static {
+    /// Join a room with the relay account
+    async fn join(&self, room: &str) -> Result<(), Error>;
+
+    /// Post lines to a room with the relay account
+    async fn send(&self, room: &str, lines: &[String]) -> Result<(), Error>;
+
+    /// Stream of messages received from the joined rooms. Messages of the relay account itself are expected to be
+    /// filtered out
+    async fn events(&self) -> Result<BoxStream<'static, GatewayMessage>, Error>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct ChannelRef {
+    community_id: Uuid,
+    channel_id: Uuid,
+}
+
+pub struct Gateway<R: RayGun, G: GatewayRelay> {
+    raygun: R,
+    relay: G,
+    did: DID,
+    rooms: HashMap<ChannelRef, String>,
+    channels: HashMap<String, ChannelRef>,
+    names: HashMap<DID, String>,
+}
+
+impl<R: RayGun, G: GatewayRelay> Gateway<R, G> {
+    /// Create a gateway running on the identity `did`, which is expected to be able to post to the mirrored channels
+    pub fn new(raygun: R, relay: G, did: DID) -> Self {
+        Self {
+            raygun,
+            relay,
+            did,
+            rooms: HashMap::new(),
+            channels: HashMap::new(),
+            names: HashMap::new(),
+        }
+    }
+
+    /// Mirror a community channel to a room, replacing any previous room of the channel
+    pub fn mirror_channel(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        room: impl Into<String>,
+    ) {
+        let channel = ChannelRef {
+            community_id,
+            channel_id,
+        };
+        let room = room.into();
+        if let Some(previous) = self.rooms.insert(channel, room.clone()) {
+            self.channels.remove(&previous);
+        }
+        // A room is only mirrored to a single channel
+        if let Some(previous) = self.channels.insert(room, channel) {
+            if previous != channel {
+                self.rooms.remove(&previous);
+            }
+        }
+    }
+
+    pub fn unmirror_channel(&mut self, community_id: Uuid, channel_id: Uuid) {
+        let channel = ChannelRef {
+            community_id,
+            channel_id,
+        };
+        if let Some(room) = self.rooms.remove(&channel) {
+            self.channels.remove(&room);
+        }
+    }
+
+    /// Room the channel is mirrored to
+    pub fn room(&self, community_id: Uuid, channel_id: Uuid) -> Option<&str> {
+        self.rooms
+            .get(&ChannelRef {
+                community_id,
+                channel_id,
+            })
+            .map(String::as_str)
+    }
+
+    /// Set the name shown in rooms for the messages of an identity. Identities without a name are shown by their
+    /// short id
+    pub fn set_name(&mut self, did: DID, name: impl Into<String>) {
+        self.names.insert(did, name.into());
+    }
+
+    pub fn relay(&self) -> &G {
+        &self.relay
+    }
+
+    pub fn raygun(&self) -> &R {
+        &self.raygun
+    }
+
+    pub fn raygun_mut(&mut self) -> &mut R {
+        &mut self.raygun
+    }
+
+    fn name(&self, did: &DID) -> String {
+        match self.names.get(did) {
+            Some(name) => name.clone(),
+            None => ShortId::try_from(did.to_string())
+                .map(|id| id.to_string())
+                .unwrap_or_else(|_| did.to_string()),
+        }
+    }
+
+    /// Post a message of a mirrored channel to its room
+    pub async fn handle_local_event(&mut self, event: MessageEventKind) -> Result<(), Error> {
+        let (community_id, channel_id, message_id, edited) = match event {
+            MessageEventKind::CommunityMessageReceived {
+                community_id,
+                channel_id,
+                message_id,
+            } => (community_id, channel_id, message_id, false),
+            MessageEventKind::CommunityMessageEdited {
+                community_id,
+                channel_id,
+                message_id,
+            } => (community_id, channel_id, message_id, true),
+            _ => return Ok(()),
+        };
+
+        let Some(room) = self
+            .rooms
+            .get(&ChannelRef {
+                community_id,
+                channel_id,
+            })
+            .cloned()
+        else {
+            return Ok(());
+        };
+
+        let message = self
+            .raygun
+            .get_community_channel_message(community_id, channel_id, message_id)
+            .await?;
+
+        // Messages posted by the gateway originate from the room
+        if message.message_type() != MessageType::Message || message.sender() == &self.did {
+            return Ok(());
+        }
+
+        let lines = room_lines(&self.name(message.sender()), message.lines(), edited);
+        self.relay.send(&room, &lines).await
+    }
+
+    /// Post a message of a mirrored room into its channel
+    pub async fn handle_remote_message(&mut self, message: GatewayMessage) -> Result<(), Error> {
+        let Some(channel) = self.channels.get(&message.room).copied() else {
+            return Ok(());
+        };
+
+        let sender = RemoteUser {
+            id: message.nick,
+            display_name: None,
+        };
+
+        self.raygun
+            .send_community_channel_message(
+                channel.community_id,
+                channel.channel_id,
+                remote_lines(&sender, &message.lines),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Mirror messages of the channels and rooms until either event stream ends
+    pub async fn run(mut self) -> Result<(), Error> {
+        let mut events = self.raygun.raygun_subscribe().await?;
+        let mut remote = self.relay.events().await?;
+        let mut streams: SelectAll<MessageEventStream> = SelectAll::new();
+
+        for room in self.channels.keys() {
+            self.relay.join(room).await?;
+        }
+
+        let communities = self
+            .rooms
+            .keys()
+            .map(|channel| channel.community_id)
+            .collect::<IndexSet<_>>();
+
+        for community_id in communities {
+            streams.push(self.raygun.get_community_stream(community_id).await?);
+        }
+
+        enum GatewayEvent {
+            RayGun(RayGunEventKind),
+            Message(MessageEventKind),
+            Remote(GatewayMessage),
+        }
+
+        loop {
+            let event = futures::future::poll_fn(|cx| {
+                if let Poll::Ready(event) = events.poll_next_unpin(cx) {
+                    return Poll::Ready(event.map(GatewayEvent::RayGun));
+                }
+
+                if let Poll::Ready(event) = remote.poll_next_unpin(cx) {
+                    return Poll::Ready(event.map(GatewayEvent::Remote));
+                }
+
+                match streams.poll_next_unpin(cx) {
+                    Poll::Ready(Some(event)) => Poll::Ready(Some(GatewayEvent::Message(event))),
+                    // an empty set of streams will return `None`, which is treated as pending
+                    _ => Poll::Pending,
+                }
+            })
+            .await;
+
+            let Some(event) = event else {
+                return Ok(());
+            };
+
+            match event {
+                // Channels of a community can be mirrored before the gateway joins the community
+                GatewayEvent::RayGun(RayGunEventKind::CommunityJoined { community_id })
+                    if self
+                        .rooms
+                        .keys()
+                        .any(|channel| channel.community_id == community_id) =>
+                {
+                    match self.raygun.get_community_stream(community_id).await {
+                        Ok(stream) => streams.push(stream),
+                        Err(e) => {
+                            tracing::warn!(%community_id, error = %e, "unable to subscribe to community")
+                        }
+                    }
+                }
+                GatewayEvent::RayGun(_) => {}
+                GatewayEvent::Message(event) => {
+                    if let Err(e) = self.handle_local_event(event).await {
+                        tracing::warn!(error = %e, "unable to post message to room");
+                    }
+                }
+                GatewayEvent::Remote(message) => {
+                    if let Err(e) = self.handle_remote_message(message).await {
+                        tracing::warn!(error = %e, "unable to post message into channel");
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Lines posted to a room for a message of a channel
+pub fn room_lines(name: &str, lines: &[String], edited: bool) -> Vec<String> {
+    lines
+        .iter()
+        .enumerate()
+        .map(|(index, line)| match index == 0 && edited {
+            true => format!("<{name}> * {line}"),
+            false => format!("<{name}> {line}"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::room_lines;
+
+    #[test]
+    fn room_lines_prefixed_with_name() {
+        let lines = room_lines("JohnDoe", &["hello".into(), "world".into()], false);
+        assert_eq!(lines, ["<JohnDoe> hello", "<JohnDoe> world"]);
+
+        let lines = room_lines("JohnDoe", &["hello there".into()], true);
+        assert_eq!(lines, ["<JohnDoe> * hello there"]);
+    }
+}
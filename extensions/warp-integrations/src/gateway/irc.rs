@@ -0,0 +1,312 @@
+//! [`GatewayRelay`] for IRC, speaking the client protocol over a connection supplied by the embedder (eg a TLS
+//! stream to the server).
+use std::sync::Arc;
+
+use futures::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    lock::Mutex as AsyncMutex,
+    stream::BoxStream,
+    StreamExt,
+};
+use indexmap::IndexSet;
+use parking_lot::Mutex;
+
+use warp::error::Error;
+
+use super::{GatewayMessage, GatewayRelay};
+
+/// Maximum size of the text of a single message. Lines are limited to 512 bytes, which includes the prefix added by
+/// the server when relaying the message
+pub const MAX_MESSAGE_BYTES: usize = 400;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IrcConfig {
+    pub nick: String,
+    pub username: String,
+    pub real_name: String,
+    pub password: Option<String>,
+}
+
+impl IrcConfig {
+    pub fn new(nick: impl Into<String>) -> Self {
+        let nick = nick.into();
+        Self {
+            username: nick.clone(),
+            real_name: nick.clone(),
+            nick,
+            password: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    nick: String,
+    registered: bool,
+    rooms: IndexSet<String>,
+}
+
+type Writer = Arc<AsyncMutex<Box<dyn AsyncWrite + Unpin + Send>>>;
+
+pub struct IrcRelay {
+    writer: Writer,
+    lines: Mutex<Option<BoxStream<'static, std::io::Result<String>>>>,
+    state: Arc<Mutex<State>>,
+}
+
+impl IrcRelay {
+    /// Register the relay account over the connection. Rooms are joined once the server accepted the registration
+    pub async fn connect<R, W>(reader: R, writer: W, config: IrcConfig) -> Result<Self, Error>
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        let writer: Writer = Arc::new(AsyncMutex::new(Box::new(writer)));
+
+        if let Some(password) = &config.password {
+            write_line(&writer, &format!("PASS {password}")).await?;
+        }
+        write_line(&writer, &format!("NICK {}", config.nick)).await?;
+        write_line(
+            &writer,
+            &format!("USER {} 0 * :{}", config.username, config.real_name),
+        )
+        .await?;
+
+        Ok(Self {
+            writer,
+            lines: Mutex::new(Some(BufReader::new(reader).lines().boxed())),
+            state: Arc::new(Mutex::new(State {
+                nick: config.nick,
+                registered: false,
+                rooms: IndexSet::new(),
+            })),
+        })
+    }
+}
+
+async fn write_line(writer: &Writer, line: &str) -> Result<(), Error> {
+    let mut writer = writer.lock().await;
+    writer.write_all(format!("{line}\r\n").as_bytes()).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Message of the IRC protocol
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IrcMessage<'a> {
+    pub prefix: Option<&'a str>,
+    pub command: &'a str,
+    pub params: Vec<&'a str>,
+}
+
+impl IrcMessage<'_> {
+    /// Nickname of the source of the message
+    pub fn nick(&self) -> Option<&str> {
+        self.prefix
+            .map(|prefix| prefix.split_once('!').map_or(prefix, |(nick, _)| nick))
+    }
+}
+
+/// Parse a line received from the server, ignoring any message tags
+pub fn parse_line(line: &str) -> Option<IrcMessage<'_>> {
+    let mut line = line.trim_end_matches(['\r', '\n']);
+
+    if line.starts_with('@') {
+        line = line.split_once(' ')?.1.trim_start();
+    }
+
+    let prefix = match line.strip_prefix(':') {
+        Some(rest) => {
+            let (prefix, rest) = rest.split_once(' ')?;
+            line = rest.trim_start();
+            Some(prefix)
+        }
+        None => None,
+    };
+
+    let (middle, trailing) = match line.split_once(" :") {
+        Some((middle, trailing)) => (middle, Some(trailing)),
+        None => (line, None),
+    };
+
+    let mut params = middle.split(' ').filter(|param| !param.is_empty());
+    let command = params.next()?;
+    let mut params = params.collect::<Vec<_>>();
+    params.extend(trailing);
+
+    Some(IrcMessage {
+        prefix,
+        command,
+        params,
+    })
+}
+
+/// Split a line into messages fitting [`MAX_MESSAGE_BYTES`], replacing characters that would end the line
+pub fn split_message(line: &str) -> Vec<String> {
+    let line = line.replace(['\r', '\n', '\0'], " ");
+    let mut messages = vec![];
+    let mut current = String::new();
+
+    for ch in line.chars() {
+        if current.len() + ch.len_utf8() > MAX_MESSAGE_BYTES {
+            messages.push(std::mem::take(&mut current));
+        }
+        current.push(ch);
+    }
+
+    if !current.trim().is_empty() {
+        messages.push(current);
+    }
+
+    messages
+}
+
+fn is_channel(target: &str) -> bool {
+    target.starts_with(['#', '&'])
+}
+
+#[async_trait::async_trait]
+impl GatewayRelay for IrcRelay {
+    async fn join(&self, room: &str) -> Result<(), Error> {
+        let registered = {
+            let mut state = self.state.lock();
+            state.rooms.insert(room.to_string());
+            state.registered
+        };
+
+        if registered {
+            write_line(&self.writer, &format!("JOIN {room}")).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn send(&self, room: &str, lines: &[String]) -> Result<(), Error> {
+        for line in lines {
+            for message in split_message(line) {
+                write_line(&self.writer, &format!("PRIVMSG {room} :{message}")).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn events(&self) -> Result<BoxStream<'static, GatewayMessage>, Error> {
+        let mut lines = self.lines.lock().take().ok_or_else(|| {
+            Error::OtherWithContext("Events of the relay are already taken".into())
+        })?;
+
+        let writer = self.writer.clone();
+        let state = self.state.clone();
+
+        let stream = async_stream::stream! {
+            while let Some(line) = lines.next().await {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(e) => {
+                        tracing::warn!(error = %e, "irc connection closed");
+                        break;
+                    }
+                };
+
+                let Some(message) = parse_line(&line) else {
+                    continue;
+                };
+
+                let reply = match (message.command, message.params.as_slice()) {
+                    ("PING", [token, ..]) => Some(format!("PONG :{token}")),
+                    // RPL_WELCOME
+                    ("001", _) => {
+                        let rooms = {
+                            let mut state = state.lock();
+                            state.registered = true;
+                            state.rooms.iter().cloned().collect::<Vec<_>>()
+                        };
+                        for room in rooms {
+                            if let Err(e) = write_line(&writer, &format!("JOIN {room}")).await {
+                                tracing::warn!(%room, error = %e, "unable to join room");
+                            }
+                        }
+                        None
+                    }
+                    // ERR_NICKNAMEINUSE
+                    ("433", _) => {
+                        let mut state = state.lock();
+                        state.nick.push('_');
+                        Some(format!("NICK {}", state.nick))
+                    }
+                    ("PRIVMSG", [target, text]) if is_channel(target) => {
+                        let Some(nick) = message.nick() else {
+                            continue;
+                        };
+
+                        if nick.eq_ignore_ascii_case(&state.lock().nick) {
+                            continue;
+                        }
+
+                        // CTCP ACTION (`/me`)
+                        let text = match text.strip_prefix("\u{1}ACTION ") {
+                            Some(action) => format!("* {}", action.trim_end_matches('\u{1}')),
+                            None if text.starts_with('\u{1}') => continue,
+                            None => text.to_string(),
+                        };
+
+                        yield GatewayMessage {
+                            room: target.to_string(),
+                            nick: nick.to_string(),
+                            lines: vec![text],
+                        };
+                        None
+                    }
+                    _ => None,
+                };
+
+                if let Some(reply) = reply {
+                    if let Err(e) = write_line(&writer, &reply).await {
+                        tracing::warn!(error = %e, "unable to reply to server");
+                    }
+                }
+            }
+        };
+
+        Ok(stream.boxed())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_line, split_message, IrcMessage, MAX_MESSAGE_BYTES};
+
+    #[test]
+    fn line_parsed() {
+        let message = parse_line(":alice!alice@example.com PRIVMSG #warp :hello: world\r\n");
+        assert_eq!(
+            message,
+            Some(IrcMessage {
+                prefix: Some("alice!alice@example.com"),
+                command: "PRIVMSG",
+                params: vec!["#warp", "hello: world"],
+            })
+        );
+        assert_eq!(message.unwrap().nick(), Some("alice"));
+
+        let message = parse_line("@time=2024-01-01T00:00:00Z PING :server").unwrap();
+        assert_eq!(message.prefix, None);
+        assert_eq!(message.command, "PING");
+        assert_eq!(message.params, ["server"]);
+
+        assert_eq!(parse_line(""), None);
+    }
+
+    #[test]
+    fn message_split_and_sanitized() {
+        assert_eq!(split_message("hello\r\nworld"), ["hello  world"]);
+        assert!(split_message("").is_empty());
+
+        let long = "é".repeat(MAX_MESSAGE_BYTES);
+        let messages = split_message(&long);
+        assert_eq!(messages.len(), 2);
+        assert!(messages.iter().all(|m| m.len() <= MAX_MESSAGE_BYTES));
+        assert_eq!(messages.concat(), long);
+    }
+}
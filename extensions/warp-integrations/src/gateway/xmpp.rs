@@ -0,0 +1,230 @@
+//! [`GatewayRelay`] for XMPP multi-user chat rooms (XEP-0045).
+//!
+//! The XMPP stream (TLS, authentication and resource binding) is left to the embedder, which exchanges stanzas with
+//! the relay through a [`XmppTransport`].
+use futures::{stream::BoxStream, StreamExt};
+use uuid::Uuid;
+
+use warp::error::Error;
+
+use super::{GatewayMessage, GatewayRelay};
+
+#[async_trait::async_trait]
+pub trait XmppTransport: Send + Sync + 'static {
+    /// Send a stanza over the stream of the relay account
+    async fn send(&self, stanza: String) -> Result<(), Error>;
+
+    /// Stanzas received over the stream, one top-level element per item
+    async fn stanzas(&self) -> Result<BoxStream<'static, String>, Error>;
+}
+
+pub struct XmppRelay<T: XmppTransport> {
+    transport: T,
+    /// Full jid of the relay account
+    jid: String,
+    /// Nickname of the relay account in the rooms
+    nick: String,
+}
+
+impl<T: XmppTransport> XmppRelay<T> {
+    pub fn new(transport: T, jid: impl Into<String>, nick: impl Into<String>) -> Self {
+        Self {
+            transport,
+            jid: jid.into(),
+            nick: nick.into(),
+        }
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+}
+
+/// Escape text for use in character data or attribute values
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+/// Reverse of [`escape`], including numeric character references
+pub fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let Some(end) = rest.find(';') else {
+            break;
+        };
+
+        let ch = match &rest[1..end] {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            entity => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(Result::ok)
+                .and_then(char::from_u32),
+        };
+
+        match ch {
+            Some(ch) => {
+                unescaped.push(ch);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    unescaped.push_str(rest);
+    unescaped
+}
+
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = tag;
+    loop {
+        let index = rest.find(name)?;
+        let preceded = rest[..index].ends_with(char::is_whitespace);
+        rest = &rest[index + name.len()..];
+
+        let Some(value) = rest.trim_start().strip_prefix('=') else {
+            continue;
+        };
+
+        if !preceded {
+            continue;
+        }
+
+        let value = value.trim_start();
+        let quote = value.chars().next().filter(|ch| matches!(ch, '"' | '\''))?;
+        let value = &value[1..];
+        return value.find(quote).map(|end| &value[..end]);
+    }
+}
+
+/// Parse a groupchat message from a room, returning the room, the nickname of the author and the body
+pub fn parse_groupchat(stanza: &str) -> Option<(String, String, String)> {
+    let stanza = stanza.trim_start();
+    let tag = stanza.strip_prefix("<message")?;
+    if !tag.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let tag = &tag[..tag.find('>')?];
+
+    if attribute(tag, "type")? != "groupchat" {
+        return None;
+    }
+
+    // Messages replayed from the history of the room
+    if stanza.contains("<delay") {
+        return None;
+    }
+
+    let from = unescape(attribute(tag, "from")?);
+    let (room, nick) = from.split_once('/')?;
+
+    let body = &stanza[stanza.find("<body")?..];
+    let body = &body[body.find('>')? + 1..];
+    let body = &body[..body.find("</body>")?];
+
+    Some((room.to_string(), nick.to_string(), unescape(body)))
+}
+
+#[async_trait::async_trait]
+impl<T: XmppTransport> GatewayRelay for XmppRelay<T> {
+    async fn join(&self, room: &str) -> Result<(), Error> {
+        let stanza = format!(
+            "<presence from=\"{}\" to=\"{}/{}\"><x xmlns=\"http://jabber.org/protocol/muc\"><history maxstanzas=\"0\"/></x></presence>",
+            escape(&self.jid),
+            escape(room),
+            escape(&self.nick)
+        );
+        self.transport.send(stanza).await
+    }
+
+    async fn send(&self, room: &str, lines: &[String]) -> Result<(), Error> {
+        let stanza = format!(
+            "<message from=\"{}\" to=\"{}\" type=\"groupchat\" id=\"{}\"><body>{}</body></message>",
+            escape(&self.jid),
+            escape(room),
+            Uuid::new_v4(),
+            escape(&lines.join("\n"))
+        );
+        self.transport.send(stanza).await
+    }
+
+    async fn events(&self) -> Result<BoxStream<'static, GatewayMessage>, Error> {
+        let nick = self.nick.clone();
+        let stream = self.transport.stanzas().await?.filter_map(move |stanza| {
+            let message = parse_groupchat(&stanza)
+                .filter(|(_, author, _)| author != &nick)
+                .map(|(room, nick, body)| GatewayMessage {
+                    room,
+                    nick,
+                    lines: body.lines().map(ToString::to_string).collect(),
+                });
+            futures::future::ready(message)
+        });
+        Ok(stream.boxed())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{escape, parse_groupchat, unescape};
+
+    #[test]
+    fn text_escaped() {
+        let text = "<b>\"Tom\" & 'Jerry'</b>";
+        assert_eq!(
+            escape(text),
+            "&lt;b&gt;&quot;Tom&quot; &amp; &apos;Jerry&apos;&lt;/b&gt;"
+        );
+        assert_eq!(unescape(&escape(text)), text);
+        assert_eq!(unescape("&#x1F44D; &#65; & &bogus;"), "👍 A & &bogus;");
+    }
+
+    #[test]
+    fn groupchat_parsed() {
+        let stanza = r#"<message xmlns="jabber:client" id="1" type='groupchat' from="room@conference.example.com/alice" to="warp@example.com/gateway"><body xml:lang="en">hello &amp;
+world</body></message>"#;
+
+        assert_eq!(
+            parse_groupchat(stanza),
+            Some((
+                "room@conference.example.com".into(),
+                "alice".into(),
+                "hello &\nworld".into()
+            ))
+        );
+
+        let history = r#"<message type="groupchat" from="room@conference.example.com/alice"><body>old</body><delay xmlns="urn:xmpp:delay" stamp="2024-01-01T00:00:00Z"/></message>"#;
+        assert_eq!(parse_groupchat(history), None);
+
+        let chat =
+            r#"<message type="chat" from="alice@example.com/phone"><body>hi</body></message>"#;
+        assert_eq!(parse_groupchat(chat), None);
+
+        let subject = r#"<message type="groupchat" from="room@conference.example.com/alice"><subject>topic</subject></message>"#;
+        assert_eq!(parse_groupchat(subject), None);
+    }
+}
@@ -0,0 +1,9 @@
+//! Integrations of Warp with other networks, built on top of the [`MultiPass`](warp::multipass::MultiPass) and
+//! [`RayGun`](warp::raygun::RayGun) traits so they can run on any implementation of them.
+//!
+//! They are kept out of the core crate as each of them targets a specific network, while the transport to that
+//! network (eg an HTTP server, a TLS stream or an email service) is left to the embedding application.
+pub mod activitypub;
+pub mod bridge;
+pub mod digest;
+pub mod gateway;
@@ -0,0 +1,92 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use futures::StreamExt;
+    use serde_json::Value;
+    use warp::{
+        multipass::{identity::ProfileFields, LocalIdentity},
+        raygun::{
+            GroupPermissions, MessageEventKind, MessageEventStream, RayGun, RayGunEventKind,
+            RayGunGroupConversation, RayGunStream,
+        },
+    };
+    use warp_integrations::activitypub::{
+        ActivityPubConfig, ActivityPubPublisher, BroadcastChannel,
+    };
+    use warp_ipfs::WarpIpfsInstance;
+
+    use crate::common::create_accounts;
+
+    /// Wait for the next event of the conversation that the publisher turns into an activity
+    async fn next_activity(
+        instance: &WarpIpfsInstance,
+        publisher: &mut ActivityPubPublisher,
+        stream: &mut MessageEventStream,
+    ) -> anyhow::Result<Value> {
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                let event = stream.next().await.expect("conversation stream open");
+                if let Some(activity) = publisher.handle_message_event(instance, &event).await? {
+                    break Ok(activity);
+                }
+            }
+        })
+        .await?
+    }
+
+    #[tokio::test]
+    async fn broadcast_conversation_published() -> anyhow::Result<()> {
+        let accounts = create_accounts(&["JohnDoe"]).await?;
+        let (mut instance, _) = accounts[0].clone();
+
+        let mut chat_subscribe = instance.raygun_subscribe().await?;
+
+        instance
+            .create_group_conversation(None, vec![], GroupPermissions::new())
+            .await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        let mut stream = instance.get_conversation_stream(conversation_id).await?;
+
+        let mut config = ActivityPubConfig::new("https://example.com", "john");
+        config.broadcast = Some(BroadcastChannel::Conversation(conversation_id));
+
+        let identity = instance.identity().await?;
+        let mut publisher = ActivityPubPublisher::new(config, identity, ProfileFields::default());
+
+        let message_id = instance.send(conversation_id, vec!["hello".into()]).await?;
+
+        let create = next_activity(&instance, &mut publisher, &mut stream).await?;
+        assert_eq!(create["type"], "Create");
+        assert_eq!(create["object"]["content"], "<p>hello</p>");
+        assert_eq!(publisher.outbox()["totalItems"], 1);
+
+        instance
+            .edit(conversation_id, message_id, vec!["hello there".into()])
+            .await?;
+
+        let update = next_activity(&instance, &mut publisher, &mut stream).await?;
+        assert_eq!(update["type"], "Update");
+        assert_eq!(update["object"]["content"], "<p>hello there</p>");
+
+        instance.delete(conversation_id, Some(message_id)).await?;
+
+        let delete = next_activity(&instance, &mut publisher, &mut stream).await?;
+        assert_eq!(delete["type"], "Delete");
+        assert_eq!(publisher.outbox()["totalItems"], 0);
+        Ok(())
+    }
+}
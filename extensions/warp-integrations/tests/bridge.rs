@@ -0,0 +1,171 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use std::{sync::Arc, time::Duration};
+
+    use futures::{
+        channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender},
+        stream::BoxStream,
+        StreamExt,
+    };
+    use parking_lot::Mutex;
+    use uuid::Uuid;
+    use warp::{
+        crypto::DID,
+        error::Error,
+        raygun::{MessageEventKind, RayGun, RayGunEventKind, RayGunStream, ReactionState},
+    };
+    use warp_integrations::bridge::{Bridge, BridgeAdapter, RemoteEvent, RemoteUser};
+
+    use crate::common::create_accounts;
+
+    /// Adapter recording the messages posted to rooms, with events of the rooms pushed by the test
+    struct TestAdapter {
+        sent: UnboundedSender<(String, DID, Vec<String>, String)>,
+        events: Mutex<Option<UnboundedReceiver<RemoteEvent>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl BridgeAdapter for TestAdapter {
+        async fn send(
+            &self,
+            room: &str,
+            sender: &DID,
+            lines: &[String],
+            _: Option<&str>,
+        ) -> Result<String, Error> {
+            let event_id = format!("${}", Uuid::new_v4());
+            _ = self.sent.unbounded_send((
+                room.to_string(),
+                sender.clone(),
+                lines.to_vec(),
+                event_id.clone(),
+            ));
+            Ok(event_id)
+        }
+
+        async fn edit(&self, _: &str, _: &DID, _: &str, _: &[String]) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn react(
+            &self,
+            _: &str,
+            _: &DID,
+            _: &str,
+            _: &str,
+            _: ReactionState,
+        ) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn events(&self) -> Result<BoxStream<'static, RemoteEvent>, Error> {
+            let rx = self.events.lock().take().ok_or(Error::Unimplemented)?;
+            Ok(rx.boxed())
+        }
+    }
+
+    #[tokio::test]
+    async fn conversation_bridged_to_room() -> anyhow::Result<()> {
+        let accounts = create_accounts(&["JohnDoe", "Bridge"]).await?;
+        let (mut instance_a, did_a) = accounts[0].clone();
+        let (mut instance_b, did_b) = accounts[1].clone();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            let mut id_a = None;
+            let mut id_b = None;
+            loop {
+                tokio::select! {
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_a.next() => {
+                        id_a.replace(conversation_id);
+                    },
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_b.next() => {
+                        id_b.replace(conversation_id);
+                    },
+                }
+
+                if id_a.is_some() && id_b.is_some() {
+                    assert_eq!(id_a, id_b);
+                    break id_a.expect("valid conversation_id")
+                }
+            }
+        }).await?;
+
+        let mut conversation_a = instance_a.get_conversation_stream(conversation_id).await?;
+
+        let (sent_tx, mut sent_rx) = unbounded();
+        let (events_tx, events_rx) = unbounded();
+        let adapter = TestAdapter {
+            sent: sent_tx,
+            events: Mutex::new(Some(events_rx)),
+        };
+
+        let mut bridge = Bridge::new(instance_b.clone(), adapter, did_b.clone());
+
+        // bridging another conversation to the room replaces the conversation previously bridged to it
+        let other_conversation = Uuid::new_v4();
+        bridge.bridge_conversation(other_conversation, "!room:example.com");
+        bridge.bridge_conversation(conversation_id, "!room:example.com");
+        assert_eq!(bridge.room(other_conversation), None);
+        assert_eq!(bridge.room(conversation_id), Some("!room:example.com"));
+
+        let handle = tokio::spawn(bridge.run());
+
+        // the conversation stream is subscribed to once the bridge runs
+        futures_timer::Delay::new(Duration::from_secs(1)).await;
+
+        let message_id = instance_a
+            .send(conversation_id, vec!["hello".into()])
+            .await?;
+
+        let (room, sender, lines, event_id) =
+            crate::common::timeout(Duration::from_secs(60), sent_rx.next())
+                .await?
+                .expect("message posted to room");
+        assert_eq!(room, "!room:example.com");
+        assert_eq!(sender, did_a);
+        assert_eq!(lines, ["hello"]);
+
+        // replies to bridged messages are posted as replies in the conversation
+        let alice = RemoteUser {
+            id: "@alice:example.com".into(),
+            display_name: Some("Alice".into()),
+        };
+
+        events_tx.unbounded_send(RemoteEvent::Message {
+            room: "!room:example.com".into(),
+            event_id: "$reply".into(),
+            sender: alice,
+            lines: vec!["hi".into()],
+            reply_to: Some(event_id),
+        })?;
+
+        let received = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived { message_id, .. }) =
+                    conversation_a.next().await
+                {
+                    break instance_a.get_message(conversation_id, message_id).await;
+                }
+            }
+        })
+        .await??;
+
+        assert_eq!(received.sender(), &did_b);
+        assert_eq!(received.lines(), ["Alice: hi"]);
+        assert_eq!(received.replied(), Some(message_id));
+
+        // messages posted by the bridge are not sent back to the room
+        futures_timer::Delay::new(Duration::from_secs(1)).await;
+        assert!(sent_rx.try_next().is_err());
+
+        handle.abort();
+        Ok(())
+    }
+}
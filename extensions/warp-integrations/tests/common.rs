@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use futures::Future;
+use futures_timeout::TimeoutExt;
+use rust_ipfs::{AddPeerOpt, Ipfs, Multiaddr, Protocol};
+use warp::{
+    crypto::DID,
+    multipass::{LocalIdentity, MultiPass},
+    SingleHandle,
+};
+use warp_ipfs::{
+    config::{Bootstrap, Config, Discovery},
+    WarpIpfsBuilder, WarpIpfsInstance,
+};
+
+/// Accounts running in memory, connected to each other
+#[allow(dead_code)]
+pub async fn create_accounts(usernames: &[&str]) -> anyhow::Result<Vec<(WarpIpfsInstance, DID)>> {
+    let mut accounts = vec![];
+    for username in usernames {
+        let mut instance = WarpIpfsBuilder::default().set_config(config()).await;
+        instance.tesseract().unlock(b"internal pass")?;
+
+        let profile = instance.create_identity(Some(username), None).await?;
+        let did = profile.identity().did_key().clone();
+        accounts.push((instance, did));
+    }
+
+    let nodes = accounts
+        .iter()
+        .map(|(instance, _)| node(instance))
+        .collect::<Vec<_>>();
+
+    for node in &nodes {
+        for other in &nodes {
+            let peer = other.identity(None).await?;
+            if peer.peer_id == node.keypair().public().to_peer_id() {
+                continue;
+            }
+
+            let opt = AddPeerOpt::with_peer_id(peer.peer_id).set_addresses(peer.listen_addrs);
+            node.add_peer(opt).await?;
+            _ = node.connect(peer.peer_id).await;
+        }
+    }
+
+    Ok(accounts)
+}
+
+fn config() -> Config {
+    let mut config = Config::development();
+    *config.listen_on_mut() = vec![Multiaddr::empty().with(Protocol::Memory(0))];
+    config.ipfs_setting_mut().memory_transport = true;
+    config.store_setting_mut().discovery = Discovery::None;
+    config.ipfs_setting_mut().relay_client.relay_address = vec![];
+    config.ipfs_setting_mut().mdns.enable = false;
+    config.store_setting_mut().announce_to_mesh = true;
+    *config.bootstrap_mut() = Bootstrap::None;
+    config
+}
+
+/// Ipfs node of the instance
+#[allow(dead_code)]
+pub fn node(instance: &WarpIpfsInstance) -> Ipfs {
+    instance
+        .handle()
+        .expect("Handle accessible")
+        .downcast_ref::<Ipfs>()
+        .cloned()
+        .unwrap()
+}
+
+#[allow(dead_code)]
+pub async fn timeout<F>(duration: Duration, future: F) -> Result<F::Output, std::io::Error>
+where
+    F: Future,
+{
+    future.timeout(duration).await
+}
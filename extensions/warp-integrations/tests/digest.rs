@@ -0,0 +1,64 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use chrono::Utc;
+    use futures::StreamExt;
+    use warp::multipass::{Friends, MultiPassEvent, MultiPassEventKind};
+    use warp_integrations::digest::{DigestCollector, DigestConfig};
+
+    use crate::common::create_accounts;
+
+    #[tokio::test]
+    async fn friend_request_collected_until_answered() -> anyhow::Result<()> {
+        let accounts = create_accounts(&["JohnDoe", "JaneDoe"]).await?;
+        let (mut instance_a, did_a) = accounts[0].clone();
+        let (mut instance_b, did_b) = accounts[1].clone();
+
+        let mut collector =
+            DigestCollector::new(did_b.clone(), DigestConfig::default(), Utc::now());
+        collector.set_name(did_a.clone(), "JohnDoe");
+
+        let mut subscribe_b = instance_b.multipass_subscribe().await?;
+        instance_a.send_request(&did_b).await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                let event = subscribe_b.next().await.expect("multipass stream open");
+                collector.handle_multipass_event(&event);
+                if matches!(event, MultiPassEventKind::FriendRequestReceived { .. }) {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let digest = collector.take(Utc::now());
+        assert_eq!(digest.identity, did_b);
+        assert_eq!(digest.friend_requests.len(), 1);
+        assert_eq!(digest.friend_requests[0].from, did_a);
+        assert_eq!(digest.friend_requests[0].from_name, "JohnDoe");
+
+        // Carry the unanswered request over to the next period
+        let request = &digest.friend_requests[0];
+        collector.record_friend_request(request.from.clone(), request.date);
+        instance_b.accept_request(&did_a).await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                let event = subscribe_b.next().await.expect("multipass stream open");
+                collector.handle_multipass_event(&event);
+                if matches!(event, MultiPassEventKind::FriendAdded { .. }) {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let digest = collector.take(Utc::now());
+        assert!(digest.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,165 @@
+mod common;
+
+#[cfg(test)]
+mod test {
+    use std::{sync::Arc, time::Duration};
+
+    use futures::{
+        channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender},
+        stream::BoxStream,
+        StreamExt,
+    };
+    use parking_lot::Mutex;
+    use warp::{
+        error::Error,
+        raygun::{
+            community::{CommunityChannelType, RayGunCommunity},
+            MessageEventKind, RayGunEventKind, RayGunStream,
+        },
+    };
+    use warp_integrations::gateway::{Gateway, GatewayMessage, GatewayRelay};
+
+    use crate::common::create_accounts;
+
+    /// Relay recording the messages posted to rooms, with messages of the rooms pushed by the test
+    #[derive(Clone)]
+    struct TestRelay {
+        joined: Arc<Mutex<Vec<String>>>,
+        sent: UnboundedSender<(String, Vec<String>)>,
+        events: Arc<Mutex<Option<UnboundedReceiver<GatewayMessage>>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl GatewayRelay for TestRelay {
+        async fn join(&self, room: &str) -> Result<(), Error> {
+            self.joined.lock().push(room.to_string());
+            Ok(())
+        }
+
+        async fn send(&self, room: &str, lines: &[String]) -> Result<(), Error> {
+            _ = self.sent.unbounded_send((room.to_string(), lines.to_vec()));
+            Ok(())
+        }
+
+        async fn events(&self) -> Result<BoxStream<'static, GatewayMessage>, Error> {
+            let rx = self.events.lock().take().ok_or(Error::Unimplemented)?;
+            Ok(rx.boxed())
+        }
+    }
+
+    #[tokio::test]
+    async fn channel_mirrored_to_room() -> anyhow::Result<()> {
+        let accounts = create_accounts(&["JohnDoe", "Gateway"]).await?;
+        let (mut instance_a, did_a) = accounts[0].clone();
+        let (mut instance_b, did_b) = accounts[1].clone();
+
+        let community = instance_a.create_community("Community0").await?;
+        let channel = instance_a
+            .create_community_channel(community.id(), "Channel0", CommunityChannelType::Standard)
+            .await?;
+        let other_channel = instance_a
+            .create_community_channel(community.id(), "Channel1", CommunityChannelType::Standard)
+            .await?;
+
+        let mut rg_stream_b = instance_b.raygun_subscribe().await?;
+        instance_a
+            .create_community_invite(community.id(), Some(did_b.clone()), None)
+            .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::CommunityInvited { .. }) = rg_stream_b.next().await {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let mut stream_a = instance_a.get_community_stream(community.id()).await?;
+        instance_b.request_join_community(community.id()).await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::CommunityJoined { .. }) = stream_a.next().await {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let (sent_tx, mut sent_rx) = unbounded();
+        let (events_tx, events_rx) = unbounded();
+        let relay = TestRelay {
+            joined: Arc::default(),
+            sent: sent_tx,
+            events: Arc::new(Mutex::new(Some(events_rx))),
+        };
+
+        let mut gateway = Gateway::new(instance_b.clone(), relay.clone(), did_b.clone());
+
+        // mirroring another channel to the room replaces the channel previously mirrored to it
+        gateway.mirror_channel(community.id(), other_channel.id(), "#warp");
+        gateway.mirror_channel(community.id(), channel.id(), "#warp");
+        assert_eq!(gateway.room(community.id(), other_channel.id()), None);
+        assert_eq!(gateway.room(community.id(), channel.id()), Some("#warp"));
+
+        gateway.set_name(did_a.clone(), "JohnDoe");
+
+        let handle = tokio::spawn(gateway.run());
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            while relay.joined.lock().is_empty() {
+                futures_timer::Delay::new(Duration::from_millis(100)).await;
+            }
+        })
+        .await?;
+        assert_eq!(*relay.joined.lock(), ["#warp"]);
+
+        // the community stream is subscribed to once the room is joined
+        futures_timer::Delay::new(Duration::from_secs(1)).await;
+
+        instance_a
+            .send_community_channel_message(community.id(), channel.id(), vec!["hello".into()])
+            .await?;
+
+        let (room, lines) = crate::common::timeout(Duration::from_secs(60), sent_rx.next())
+            .await?
+            .expect("message posted to room");
+        assert_eq!(room, "#warp");
+        assert_eq!(lines, ["<JohnDoe> hello"]);
+
+        events_tx.unbounded_send(GatewayMessage {
+            room: "#warp".into(),
+            nick: "alice".into(),
+            lines: vec!["hi".into()],
+        })?;
+
+        let message_id = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::CommunityMessageReceived {
+                    channel_id,
+                    message_id,
+                    ..
+                }) = stream_a.next().await
+                {
+                    assert_eq!(channel_id, channel.id());
+                    break message_id;
+                }
+            }
+        })
+        .await?;
+
+        let message = instance_a
+            .get_community_channel_message(community.id(), channel.id(), message_id)
+            .await?;
+        assert_eq!(message.sender(), &did_b);
+        assert_eq!(message.lines(), ["alice: hi"]);
+
+        // messages posted by the gateway are not sent back to the room
+        futures_timer::Delay::new(Duration::from_secs(1)).await;
+        assert!(sent_rx.try_next().is_err());
+
+        handle.abort();
+        Ok(())
+    }
+}
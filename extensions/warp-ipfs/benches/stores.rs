@@ -0,0 +1,210 @@
+use chrono::Utc;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use either::Either;
+use rust_ipfs::{Ipfs, Keypair, UninitializedIpfsDefault};
+use tokio::runtime::Runtime;
+use warp::crypto::{Fingerprint, DID};
+use warp::multipass::identity::SHORT_ID_SIZE;
+
+use warp_ipfs::store::conversation::message::{MessageDocument, MessageDocumentBuilder};
+use warp_ipfs::store::conversation::reference::MessageReferenceList;
+use warp_ipfs::store::document::cache::IdentityCache;
+use warp_ipfs::store::document::identity::IdentityDocument;
+use warp_ipfs::store::document::root::RootDocumentMap;
+use warp_ipfs::store::document::RootDocument;
+use warp_ipfs::store::PeerIdExt;
+
+fn runtime() -> Runtime {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("valid runtime")
+}
+
+async fn ipfs() -> Ipfs {
+    UninitializedIpfsDefault::new()
+        .start()
+        .await
+        .expect("constructed ipfs instance")
+}
+
+fn identity_document(keypair: &Keypair) -> IdentityDocument {
+    let did = keypair.to_did().expect("valid keypair");
+    let fingerprint = did.fingerprint();
+    let bytes = fingerprint.as_bytes();
+    let time = Utc::now();
+
+    IdentityDocument {
+        username: warp::multipass::generator::generate_name(),
+        handle: None,
+        short_id: bytes[bytes.len() - SHORT_ID_SIZE..]
+            .try_into()
+            .expect("valid conversion"),
+        did,
+        created: time,
+        modified: time,
+        status_message: None,
+        activity: None,
+        profile: None,
+        friends_summary: None,
+        metadata: Default::default(),
+        devices: vec![],
+        revoked_devices: vec![],
+        version: Default::default(),
+        signature: None,
+        extended_signature: None,
+    }
+    .sign(keypair)
+    .expect("valid document")
+}
+
+fn random_did() -> DID {
+    Keypair::generate_ed25519().to_did().expect("valid keypair")
+}
+
+fn message(keypair: &Keypair, did: &DID) -> MessageDocument {
+    MessageDocumentBuilder::new(keypair, Either::Left(did))
+        .set_sender(did.clone())
+        .set_message(vec!["This is a benchmark message".into()])
+        .expect("valid message")
+        .build()
+        .expect("valid document")
+}
+
+/// Add and remove a friend, so the size of the list stays the same between iterations
+fn root_document(c: &mut Criterion) {
+    let rt = runtime();
+    let mut group = c.benchmark_group("root_document");
+
+    for friends in [0, 100, 1000] {
+        let root = rt.block_on(async {
+            let ipfs = ipfs().await;
+            let identity = ipfs
+                .put_dag(identity_document(ipfs.keypair()))
+                .await
+                .expect("stored identity");
+
+            let mut root = RootDocumentMap::new(&ipfs, None).await;
+            root.set(RootDocument {
+                identity,
+                ..Default::default()
+            })
+            .await
+            .expect("stored root document");
+
+            for _ in 0..friends {
+                root.add_friend(&random_did()).await.expect("added friend");
+            }
+            root
+        });
+
+        group.bench_with_input(
+            BenchmarkId::new("add_remove_friend", friends),
+            &root,
+            |b, root| {
+                b.to_async(&rt).iter_batched(
+                    random_did,
+                    |did| async move {
+                        root.add_friend(&did).await.expect("added friend");
+                        root.remove_friend(&did).await.expect("removed friend");
+                    },
+                    BatchSize::SmallInput,
+                )
+            },
+        );
+    }
+
+    group.finish();
+}
+
+/// Reference lists spanning several pages, since each page holds a limited number of references
+fn message_reference_list(c: &mut Criterion) {
+    let rt = runtime();
+    let mut group = c.benchmark_group("message_reference_list");
+    group.sample_size(10);
+
+    let keypair = Keypair::generate_ed25519();
+    let did = keypair.to_did().expect("valid keypair");
+
+    for messages in [100, 1000, 2500] {
+        let (ipfs, list) = rt.block_on(async {
+            let ipfs = ipfs().await;
+            let mut list = MessageReferenceList::default();
+            for _ in 0..messages {
+                list.insert(&ipfs, &message(&keypair, &did))
+                    .await
+                    .expect("inserted message");
+            }
+            (ipfs, list)
+        });
+
+        group.bench_with_input(BenchmarkId::new("insert", messages), &list, |b, list| {
+            b.to_async(&rt).iter_batched(
+                || (*list, message(&keypair, &did)),
+                |(mut list, message)| {
+                    let ipfs = &ipfs;
+                    async move {
+                        list.insert(ipfs, &message).await.expect("inserted message");
+                    }
+                },
+                BatchSize::SmallInput,
+            )
+        });
+
+        group.bench_with_input(BenchmarkId::new("list", messages), &list, |b, list| {
+            b.to_async(&rt).iter(|| async {
+                use futures::StreamExt;
+                let count = list.list(&ipfs).count().await;
+                assert_eq!(count, messages);
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("count", messages), &list, |b, list| {
+            b.to_async(&rt).iter(|| async { list.count(&ipfs).await })
+        });
+    }
+
+    group.finish();
+}
+
+fn identity_cache(c: &mut Criterion) {
+    let rt = runtime();
+    let mut group = c.benchmark_group("identity_cache");
+
+    for identities in [10, 100, 1000] {
+        let (cache, dids) = rt.block_on(async {
+            let ipfs = ipfs().await;
+            let cache = IdentityCache::new(&ipfs).await;
+            let mut dids = Vec::with_capacity(identities);
+            for _ in 0..identities {
+                let document = identity_document(&Keypair::generate_ed25519());
+                dids.push(document.did.clone());
+                cache.insert(&document).await.expect("inserted");
+            }
+            (cache, dids)
+        });
+
+        group.bench_with_input(
+            BenchmarkId::new("get", identities),
+            &(cache, dids),
+            |b, (cache, dids)| {
+                let mut index = 0;
+                b.to_async(&rt).iter(|| {
+                    let did = &dids[index % dids.len()];
+                    index += 1;
+                    async move { cache.get(did).await.expect("cached identity") }
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    root_document,
+    message_reference_list,
+    identity_cache
+);
+criterion_main!(benches);
@@ -256,6 +256,14 @@ async fn main() -> anyhow::Result<()> {
 
                             writeln!(stdout, "> Request for {username} has been retracted")?;
                         },
+                        warp::multipass::MultiPassEventKind::FriendRequestExpired { did } => {
+                            let username = account
+                                .get_identity(Identifier::did_key(did.clone())).await
+                                .map(|ident| ident.username().to_owned())
+                                .unwrap_or_else(|_| did.to_string());
+
+                            writeln!(stdout, "> Request for {username} has expired")?;
+                        },
                         warp::multipass::MultiPassEventKind::FriendAdded { did } => {
                             let username = account
                                 .get_identity(Identifier::did_key(did.clone())).await
@@ -330,6 +338,12 @@ async fn main() -> anyhow::Result<()> {
 
                             writeln!(stdout, "> {username} has been updated ")?;
                         }
+                        warp::multipass::MultiPassEventKind::AppDataUpdated { namespace } => {
+                            writeln!(stdout, "> app data for \"{namespace}\" has been updated")?;
+                        }
+                        warp::multipass::MultiPassEventKind::NotificationPreferencesUpdated => {
+                            writeln!(stdout, "> notification preferences have been updated")?;
+                        }
                     }
                 }
             }
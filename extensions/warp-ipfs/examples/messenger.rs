@@ -318,6 +318,14 @@ async fn main() -> anyhow::Result<()> {
 
                         writeln!(stdout, "> Request for {username} has been retracted")?;
                     },
+                    warp::multipass::MultiPassEventKind::FriendRequestExpired { did } => {
+                        let username = instance
+                            .get_identity(Identifier::did_key(did.clone())).await
+                            .map(|ident| ident.username().to_owned())
+                            .unwrap_or_else(|_| did.to_string());
+
+                        writeln!(stdout, "> Request for {username} has expired")?;
+                    },
                     warp::multipass::MultiPassEventKind::FriendAdded { did } => {
                         let username = instance
                             .get_identity(Identifier::did_key(did.clone())).await
@@ -408,6 +416,23 @@ async fn main() -> anyhow::Result<()> {
                                 writeln!(stdout, "Conversation {conversation_id} has been deleted")?;
                             }
                         },
+                        warp::raygun::RayGunEventKind::ConversationDegraded { conversation_id } => {
+                            stream_map.remove(&conversation_id);
+                            writeln!(stdout, "Conversation {conversation_id} stopped. Restarting")?;
+                        },
+                        warp::raygun::RayGunEventKind::ConversationRecovered { conversation_id } => {
+                            let stream = instance.get_conversation_stream(conversation_id).await?;
+                            stream_map.insert(conversation_id, stream);
+                            writeln!(stdout, "Conversation {conversation_id} has been restarted")?;
+                        },
+                        warp::raygun::RayGunEventKind::ConversationMerged { primary, duplicate } => {
+                            stream_map.remove(&duplicate);
+
+                            if topic == duplicate {
+                                topic = primary;
+                                writeln!(stdout, "Conversation {duplicate} has been merged into {primary}")?;
+                            }
+                        },
                         _ => {},
                     }
                 }
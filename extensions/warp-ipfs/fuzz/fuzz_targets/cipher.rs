@@ -0,0 +1,17 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use warp::crypto::cipher::Cipher;
+
+fuzz_target!(|data: &[u8]| {
+    let key = [7; 32];
+
+    let _ = Cipher::direct_decrypt(data, &key);
+    let _ = Cipher::self_decrypt(data);
+
+    let mut buffer = data.to_vec();
+    let _ = Cipher::direct_decrypt_in_place(&mut buffer, &key);
+
+    let mut plaintext = Vec::new();
+    let _ = Cipher::from(key.as_slice()).decrypt_stream(&mut &data[..], &mut plaintext);
+});
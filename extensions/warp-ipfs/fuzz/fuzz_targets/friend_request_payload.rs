@@ -0,0 +1,25 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_ipfs::Keypair;
+use warp_ipfs::store::identity::RequestResponsePayload;
+use warp_ipfs::store::payload::PayloadMessage;
+
+fuzz_target!(|data: &[u8]| {
+    let keypair = Keypair::ed25519_from_bytes([7; 32]).expect("valid keypair");
+
+    if let Ok(payload) = PayloadMessage::<RequestResponsePayload>::from_bytes(data) {
+        if let Ok(request) = payload.message(&keypair) {
+            check(&keypair, &request);
+        }
+    }
+
+    if let Ok(request) = cbor4ii::serde::from_slice::<RequestResponsePayload>(data) {
+        check(&keypair, &request);
+    }
+});
+
+fn check(keypair: &Keypair, request: &RequestResponsePayload) {
+    let _ = request.verify();
+    let _ = request.decrypt_message(keypair, &request.sender);
+}
@@ -0,0 +1,23 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use warp_ipfs::store::document::identity::IdentityDocument;
+use warp_ipfs::store::identity::IdentityEvent;
+use warp_ipfs::store::payload::PayloadMessage;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(payload) = PayloadMessage::<IdentityDocument>::from_bytes(data) {
+        if let Ok(document) = payload.message(None) {
+            let _ = document.resolve();
+        }
+    }
+
+    if let Ok(payload) = PayloadMessage::<IdentityEvent>::from_bytes(data) {
+        let _ = payload.message(None);
+    }
+
+    if let Ok(document) = cbor4ii::serde::from_slice::<IdentityDocument>(data) {
+        let _ = document.resolve();
+    }
+    let _ = cbor4ii::serde::from_slice::<IdentityEvent>(data);
+});
@@ -0,0 +1,21 @@
+#![no_main]
+
+use either::Either;
+use libfuzzer_sys::fuzz_target;
+use rust_ipfs::Keypair;
+use warp_ipfs::store::conversation::message::MessageDocument;
+use warp_ipfs::store::PeerIdExt;
+
+fuzz_target!(|data: &[u8]| {
+    let keypair = Keypair::ed25519_from_bytes([7; 32]).expect("valid keypair");
+    let did = keypair.to_did().expect("valid keypair");
+
+    let Ok(document) = cbor4ii::serde::from_slice::<MessageDocument>(data) else {
+        return;
+    };
+
+    let _ = document.verify();
+    let _ = document.nonce_from_message();
+    let _ = document.message(&keypair, Either::Left(&did));
+    let _ = document.message(&keypair, Either::Left(&document.sender()));
+});
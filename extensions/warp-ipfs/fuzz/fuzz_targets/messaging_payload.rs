@@ -0,0 +1,23 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_ipfs::Keypair;
+use warp_ipfs::store::payload::PayloadMessage;
+use warp_ipfs::store::{ConversationEvents, MessagingEvents};
+
+fuzz_target!(|data: &[u8]| {
+    let keypair = Keypair::ed25519_from_bytes([7; 32]).expect("valid keypair");
+
+    if let Ok(payload) = PayloadMessage::<MessagingEvents>::from_bytes(data) {
+        let _ = payload.message(&keypair);
+        let _ = payload.message_from_key(&[7; 32]);
+    }
+
+    if let Ok(payload) = PayloadMessage::<ConversationEvents>::from_bytes(data) {
+        let _ = payload.message(&keypair);
+    }
+
+    // The events are only reached after the signature of the payload is verified, so they are also parsed directly
+    let _ = cbor4ii::serde::from_slice::<MessagingEvents>(data);
+    let _ = cbor4ii::serde::from_slice::<ConversationEvents>(data);
+});
@@ -9,6 +9,7 @@ use clap::Parser;
 use rust_ipfs::{Keypair, Multiaddr};
 use std::path::PathBuf;
 use std::time::Duration;
+use warp::crypto::DID;
 
 use zeroize::Zeroizing;
 
@@ -84,6 +85,16 @@ struct Opt {
     /// Note: NOOP if `enable_gc` is false
     #[clap(long)]
     gc_duration: Option<u16>,
+
+    /// Reject identities with a username that is already used by another registered identity.
+    /// Usernames are compared without case, separators or similar looking characters
+    #[clap(long)]
+    unique_usernames: bool,
+
+    /// Administrators of the organization directory in did key format.
+    /// If set, the node acts as the directory of the organization, using its keypair as the organization key
+    #[clap(long)]
+    organization_admin: Vec<DID>,
 }
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -181,6 +192,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         opts.gc_duration.map(u64::from).map(Duration::from_secs),
         None,
         true,
+        opts.unique_usernames,
+        &opts.organization_admin,
     )
     .await?;
 
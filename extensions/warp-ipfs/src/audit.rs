@@ -0,0 +1,85 @@
+//! Read-only replica of an account for compliance review or support cases.
+//!
+//! An account is exported with [`WarpIpfs::export_audit_root`], which provides the cid of its root document.
+//! [`AuditReplica::new`] loads the root document from the network into an in-memory instance that does not register
+//! with shuttle nodes, fetch the mailbox or publish the identity, and rejects any change to the root document. The
+//! instance is only reachable through read-only handles, so messages and files can be browsed but nothing can be sent
+//! on behalf of the account.
+//!
+//! Note: The content of the account can only be decrypted with the keypair of the account, so the replica has to be
+//!       opened with the unlocked keystore of the account itself. No key is exported for a replica to be opened by
+//!       anyone else until the content keys can be wrapped to a key that is only able to decrypt them.
+use ipld_core::cid::Cid;
+use warp::crypto::zeroize::Zeroizing;
+use warp::error::Error;
+use warp::multipass::LocalIdentity;
+use warp::scoped::{ReadOnlyConstellation, ReadOnlyMultiPass, ReadOnlyRayGun};
+use warp::tesseract::Tesseract;
+
+use crate::config::Config;
+use crate::{WarpIpfs, WarpIpfsInstance};
+
+#[derive(Clone)]
+pub struct AuditReplica {
+    multipass: ReadOnlyMultiPass<WarpIpfsInstance>,
+    raygun: ReadOnlyRayGun<WarpIpfsInstance>,
+    constellation: ReadOnlyConstellation<WarpIpfsInstance>,
+}
+
+impl AuditReplica {
+    /// Instantiate a replica of the account exported at `root`, reading its content with the keypair held in the
+    /// unlocked `keystore` of the account
+    pub async fn new(mut config: Config, root: Cid, keystore: &Tesseract) -> Result<Self, Error> {
+        let keypair = Zeroizing::new(keystore.retrieve("keypair")?);
+
+        config.set_audit_replica(root);
+
+        // The keystore of the replica only lives as long as the instance
+        let tesseract = Tesseract::default();
+        let passphrase = Zeroizing::new(warp::crypto::generate::<32>());
+        tesseract.unlock(passphrase.as_slice())?;
+        tesseract.set("keypair", &keypair)?;
+
+        let instance = WarpIpfs::new(config, tesseract).await;
+
+        // Confirms that the root document was loaded
+        instance.identity().await?;
+
+        Ok(Self {
+            multipass: ReadOnlyMultiPass::new(instance.clone()),
+            raygun: ReadOnlyRayGun::new(instance.clone()),
+            constellation: ReadOnlyConstellation::new(instance),
+        })
+    }
+
+    pub fn multipass(&self) -> ReadOnlyMultiPass<WarpIpfsInstance> {
+        self.multipass.clone()
+    }
+
+    pub fn raygun(&self) -> ReadOnlyRayGun<WarpIpfsInstance> {
+        self.raygun.clone()
+    }
+
+    pub fn constellation(&self) -> ReadOnlyConstellation<WarpIpfsInstance> {
+        self.constellation.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use warp::tesseract::Tesseract;
+
+    use super::AuditReplica;
+    use crate::config::Config;
+
+    #[tokio::test]
+    async fn replica_requires_unlocked_keystore() -> anyhow::Result<()> {
+        let root = "QmdfTbBqBPQ7VNxZEYEj14VmRuZBkqFbiwReogJgS1zR1n".parse()?;
+
+        let keystore = Tesseract::default();
+        assert!(AuditReplica::new(Config::development(), root, &keystore)
+            .await
+            .is_err());
+        Ok(())
+    }
+}
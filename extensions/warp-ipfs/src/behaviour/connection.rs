@@ -0,0 +1,102 @@
+use std::task::{Context, Poll};
+
+use rust_ipfs::libp2p::{
+    core::{transport::PortUse, Endpoint},
+    swarm::{
+        derive_prelude::ConnectionEstablished, dummy, ConnectionClosed, ConnectionDenied,
+        ConnectionId, FromSwarm, THandler, THandlerInEvent, THandlerOutEvent, ToSwarm,
+    },
+    Multiaddr, PeerId,
+};
+use rust_ipfs::NetworkBehaviour;
+use tokio::sync::broadcast;
+use void::Void;
+
+use crate::connection::{connection_route, connection_transport, ConnectionEvent};
+use crate::store::PeerIdExt;
+
+/// Reports connection lifecycle events of peers to the application
+pub struct Behaviour {
+    event: broadcast::Sender<ConnectionEvent>,
+}
+
+impl Behaviour {
+    pub fn new(event: broadcast::Sender<ConnectionEvent>) -> Self {
+        Behaviour { event }
+    }
+}
+
+impl NetworkBehaviour for Behaviour {
+    type ConnectionHandler = dummy::ConnectionHandler;
+    type ToSwarm = Void;
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _: ConnectionId,
+        _: PeerId,
+        _: &Multiaddr,
+        _: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _: ConnectionId,
+        _: PeerId,
+        _: &Multiaddr,
+        _: Endpoint,
+        _: PortUse,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        _: PeerId,
+        _: ConnectionId,
+        _: THandlerOutEvent<Self>,
+    ) {
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm) {
+        // Sending will only fail if there are no subscribers, in which case the event is not needed
+        match event {
+            FromSwarm::ConnectionEstablished(ConnectionEstablished {
+                peer_id,
+                endpoint,
+                other_established,
+                ..
+            }) => {
+                let address = endpoint.get_remote_address();
+                let _ = self.event.send(ConnectionEvent::Connected {
+                    peer_id,
+                    did: peer_id.to_did().ok(),
+                    route: connection_route(address),
+                    transport: connection_transport(address),
+                    connections: other_established + 1,
+                });
+            }
+            FromSwarm::ConnectionClosed(ConnectionClosed {
+                peer_id,
+                endpoint,
+                remaining_established,
+                ..
+            }) => {
+                let address = endpoint.get_remote_address();
+                let _ = self.event.send(ConnectionEvent::Disconnected {
+                    peer_id,
+                    did: peer_id.to_did().ok(),
+                    route: connection_route(address),
+                    transport: connection_transport(address),
+                    connections: remaining_established,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    fn poll(&mut self, _: &mut Context) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        Poll::Pending
+    }
+}
@@ -1,3 +1,4 @@
+pub mod connection;
 pub mod phonebook;
 
 use libp2p::swarm::NetworkBehaviour;
@@ -7,4 +8,5 @@ use rust_ipfs::libp2p;
 #[behaviour(prelude = "libp2p::swarm::derive_prelude", to_swarm = "void::Void")]
 pub struct Behaviour {
     pub phonebook: phonebook::Behaviour,
+    pub connection: connection::Behaviour,
 }
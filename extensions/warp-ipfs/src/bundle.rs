@@ -0,0 +1,102 @@
+//! Bundles of pending outbound payloads.
+//!
+//! When two peers are unable to reach each other over a network, the friend request and messages that are
+//! queued for a recipient can be exported with [`OfflineBundles::export_bundle`] into a bundle that is signed by
+//! the sender and encrypted for the recipient. The bundle can then be carried to the recipient by any means
+//! (eg a USB drive) and imported with [`OfflineBundles::import_bundle`], where each payload is processed as if it
+//! was received over the network.
+//!
+//! Exporting a bundle does not remove the payloads from the queue, so they are still delivered once the peers are
+//! able to connect. Payloads that were already received are ignored by the recipient.
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use warp::{crypto::DID, error::Error};
+
+use crate::store::identity::RequestResponsePayload;
+
+/// Summary of an imported bundle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BundleImport {
+    /// Identity that exported the bundle
+    pub sender: DID,
+    /// Friend requests (or responses to requests) that were processed
+    pub requests: usize,
+    /// Conversations that were received (eg invites to new conversations)
+    pub conversations: usize,
+    /// Conversation payloads that were processed
+    pub messages: usize,
+    /// Payloads that could not be processed
+    pub rejected: usize,
+}
+
+impl BundleImport {
+    pub(crate) fn new(sender: DID) -> Self {
+        Self {
+            sender,
+            requests: 0,
+            conversations: 0,
+            messages: 0,
+            rejected: 0,
+        }
+    }
+}
+
+/// Payloads carried by a bundle. The bundle itself is signed and encrypted for the recipient
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct Bundle {
+    /// Pending friend request event
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<RequestResponsePayload>,
+    /// Payloads sent to the messaging topic of the recipient
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conversations: Vec<Bytes>,
+    /// Payloads sent to the topics of a conversation, in the order they were queued
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub messages: Vec<BundleMessage>,
+}
+
+impl Bundle {
+    pub fn is_empty(&self) -> bool {
+        self.request.is_none() && self.conversations.is_empty() && self.messages.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct BundleMessage {
+    pub conversation_id: Uuid,
+    pub topic: String,
+    pub data: Bytes,
+}
+
+#[async_trait::async_trait]
+pub trait OfflineBundles: Sync + Send {
+    /// Export the friend request and messages pending for the recipient into a bundle that only they can import
+    async fn export_bundle(&self, _: &DID) -> Result<Bytes, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Import a bundle exported for this identity
+    async fn import_bundle(&self, _: &[u8]) -> Result<BundleImport, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Export the bundle for the recipient into a file
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn export_bundle_to_file(
+        &self,
+        recipient: &DID,
+        path: &std::path::Path,
+    ) -> Result<(), Error> {
+        let bundle = self.export_bundle(recipient).await?;
+        tokio::fs::write(path, bundle).await?;
+        Ok(())
+    }
+
+    /// Import a bundle from a file
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn import_bundle_from_file(&self, path: &std::path::Path) -> Result<BundleImport, Error> {
+        let bundle = tokio::fs::read(path).await?;
+        self.import_bundle(&bundle).await
+    }
+}
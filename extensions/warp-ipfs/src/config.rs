@@ -1,9 +1,13 @@
 use std::{path::PathBuf, time::Duration};
 
-use ipfs::{Multiaddr, Protocol};
+use ipfs::{Multiaddr, PeerId, Protocol};
+use ipld_core::cid::Cid;
 use rust_ipfs as ipfs;
 
-use warp::{constellation::file::FileType, multipass::identity::Identity};
+use warp::{constellation::file::FileType, crypto::DID, multipass::identity::Identity};
+
+use crate::store::{MAX_MESSAGE_SIZE, MAX_MESSAGE_SIZE_LIMIT, MIN_MESSAGE_SIZE};
+use crate::time::Clock;
 
 #[derive(Default, Debug, Clone)]
 pub enum Bootstrap {
@@ -60,6 +64,15 @@ pub struct RelayClient {
     pub relay_address: Vec<Multiaddr>,
     pub background: bool,
     pub quorum: RelayQuorum,
+    /// Relays that are always used, regardless of their measured latency
+    pub pinned: Vec<PeerId>,
+    /// Ordered chains of relays. For each chain, the first relay that accepts a reservation is used,
+    /// falling back to the next relay in the chain when it fails. Relays that are part of a chain
+    /// are not selected based on their latency
+    pub fallback_chains: Vec<Vec<PeerId>>,
+    /// Interval in which the relays are measured again and the selection is updated.
+    /// If `None`, the relays are only selected on startup
+    pub evaluation_interval: Option<Duration>,
 }
 
 #[derive(Default, Debug, Clone, Copy)]
@@ -87,7 +100,10 @@ impl Default for RelayClient {
                 "/dns4/nyc-3-dev.relay.satellite.im/tcp/4410/wss/p2p/12D3KooWJWw4KG2KKpUxQAc8kZZDqmownRvjWGxnr5Y6XRur8WSx".parse().unwrap(),
             ],
             background: true,
-            quorum: Default::default()
+            quorum: Default::default(),
+            pinned: vec![],
+            fallback_chains: vec![],
+            evaluation_interval: Some(Duration::from_secs(5 * 60)),
         }
     }
 }
@@ -125,12 +141,33 @@ pub struct StoreSetting {
     pub fetch_over_bitswap: bool,
     /// Waits for a response from peer for a specific duration
     pub friend_request_response_duration: Option<Duration>,
+    /// Duration after which an outgoing friend request that was not answered expires and is retracted.
+    /// If `None`, outgoing requests are kept until they are answered or closed
+    pub friend_request_ttl: Option<Duration>,
     /// Disable providing images for identities
     pub disable_images: bool,
     /// Announce to mesh network
     pub announce_to_mesh: bool,
     /// Function to call to provide data for a default profile picture if one is not apart of the identity
     pub default_profile_picture: Option<DefaultPfpFn>,
+    /// Keep a local history of relationship changes (eg requests, blocks) with other identities
+    pub relationship_history: bool,
+    /// DID of the organization the identity is a member of. The organization key is held by the directory of the
+    /// organization, a shuttle node that is expected to be reachable through [`Discovery::Shuttle`].
+    /// Members listed in the roster of the organization are added as friends automatically, while revoked
+    /// identities are removed and blocked
+    pub organization: Option<DID>,
+    /// Move the content of files that were not modified for a while to cold storage.
+    /// If `None`, the content of every file is kept locally
+    pub cold_storage: Option<ColdStoragePolicy>,
+    /// Periodically fold removed messages out of the history of conversations.
+    /// If `None`, the history is never compacted
+    pub compaction: Option<CompactionPolicy>,
+    /// Encoding of the encrypted payloads of the root document (eg friends, blocks, requests)
+    pub document_codec: DocumentCodec,
+    /// Encode the existing payloads of the root document with [`StoreSetting::document_codec`] on startup.
+    /// Selecting the other codec migrates them back
+    pub migrate_document_payloads: bool,
 }
 
 impl std::fmt::Debug for StoreSetting {
@@ -149,10 +186,75 @@ impl Default for StoreSetting {
             },
             fetch_over_bitswap: false,
             friend_request_response_duration: None,
+            friend_request_ttl: None,
             disable_images: false,
             with_friends: false,
             default_profile_picture: None,
             announce_to_mesh: false,
+            relationship_history: false,
+            organization: None,
+            cold_storage: None,
+            compaction: None,
+            document_codec: DocumentCodec::default(),
+            migrate_document_payloads: false,
+        }
+    }
+}
+
+/// Encoding of the payloads of the root document that are encrypted before being stored, as the dag codec cannot
+/// encode their content. Payloads in either encoding are read regardless of this setting, which only selects the
+/// encoding used when writing them. Existing payloads keep their encoding until they are written again, unless
+/// [`StoreSetting::migrate_document_payloads`] is enabled
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentCodec {
+    /// JSON, as written by previous versions and readable by every device
+    #[default]
+    Json,
+    /// dag-cbor, prefixed by a version byte. Should only be selected once every device sharing the root document
+    /// is able to read dag-cbor payloads
+    Cbor,
+}
+
+/// Policy of the files whose content is moved to cold storage, where only the entry in the index and the reference
+/// to the content are kept locally. The content is fetched from the network again when the file is accessed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColdStoragePolicy {
+    /// Duration since the file was last modified before its content is moved to cold storage
+    pub age: Duration,
+    /// Only files of at least this size, in bytes, are moved to cold storage
+    pub min_size: usize,
+    /// Interval in which the policy is applied
+    pub interval: Duration,
+}
+
+impl Default for ColdStoragePolicy {
+    fn default() -> Self {
+        Self {
+            age: Duration::from_secs(90 * 24 * 60 * 60),
+            min_size: 1024 * 1024,
+            interval: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+/// Policy of the compaction of conversation history, where the references left behind by removed messages are dropped
+/// and the remaining references are packed together, reducing the size of the history and the time it takes to load it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactionPolicy {
+    /// References left by removed messages are kept among the messages sent within this duration
+    pub window: Duration,
+    /// Minimum amount of references that can be dropped before the history is compacted
+    pub min_removed: usize,
+    /// Interval in which the policy is applied
+    pub interval: Duration,
+}
+
+impl Default for CompactionPolicy {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(30 * 24 * 60 * 60),
+            min_removed: 64,
+            interval: Duration::from_secs(60 * 60),
         }
     }
 }
@@ -171,6 +273,13 @@ pub struct Config {
     max_file_size: Option<usize>,
     thumbnail_size: (u32, u32),
     thumbnail_exact_format: bool,
+    strip_metadata: bool,
+    data_usage_period: Option<Duration>,
+    max_message_size: usize,
+    snippets: bool,
+    lan_only: bool,
+    clock: Clock,
+    audit_replica: Option<Cid>,
 }
 
 impl Config {
@@ -221,6 +330,35 @@ impl Config {
     pub fn thumbnail_exact_format(&self) -> bool {
         self.thumbnail_exact_format
     }
+
+    pub fn strip_metadata(&self) -> bool {
+        self.strip_metadata
+    }
+
+    pub fn data_usage_period(&self) -> Option<Duration> {
+        self.data_usage_period
+    }
+
+    pub fn max_message_size(&self) -> usize {
+        self.max_message_size
+    }
+
+    pub fn snippets(&self) -> bool {
+        self.snippets
+    }
+
+    pub fn lan_only(&self) -> bool {
+        self.lan_only
+    }
+
+    pub fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    /// Root document of the account when the instance is a read-only replica
+    pub fn audit_replica(&self) -> Option<Cid> {
+        self.audit_replica
+    }
 }
 
 impl Config {
@@ -271,6 +409,27 @@ impl Config {
     pub fn thumbnail_exact_format_mut(&mut self) -> &mut bool {
         &mut self.thumbnail_exact_format
     }
+
+    pub fn strip_metadata_mut(&mut self) -> &mut bool {
+        &mut self.strip_metadata
+    }
+
+    pub fn data_usage_period_mut(&mut self) -> &mut Option<Duration> {
+        &mut self.data_usage_period
+    }
+
+    pub fn max_message_size_mut(&mut self) -> &mut usize {
+        &mut self.max_message_size
+    }
+
+    pub fn lan_only_mut(&mut self) -> &mut bool {
+        &mut self.lan_only
+    }
+
+    /// Time source of the stores. See [`Clock`]
+    pub fn clock_mut(&mut self) -> &mut Clock {
+        &mut self.clock
+    }
 }
 
 impl Config {
@@ -305,6 +464,66 @@ impl Config {
     pub fn with_thumbnail_exact_format(&mut self, exact: bool) {
         self.thumbnail_exact_format = exact
     }
+
+    /// Strip metadata (eg EXIF and GPS data) from images before they are uploaded
+    pub fn set_strip_metadata(&mut self, strip: bool) {
+        self.strip_metadata = strip
+    }
+
+    /// Period after which the data usage is rolled over. If `None`, the usage is only reset manually
+    pub fn set_data_usage_period(&mut self, period: Option<Duration>) {
+        self.data_usage_period = period
+    }
+
+    /// Maximum amount of characters allowed in an outgoing message. The size is clamped between
+    /// [`MIN_MESSAGE_SIZE`] and [`MAX_MESSAGE_SIZE_LIMIT`]. Messages that exceed the pubsub payload limit
+    /// once encoded are split into chunks and reassembled by the recipients.
+    pub fn set_max_message_size(&mut self, size: usize) {
+        self.max_message_size = size.clamp(MIN_MESSAGE_SIZE, MAX_MESSAGE_SIZE_LIMIT)
+    }
+
+    /// Send messages over the maximum message size as snippets, where the text is attached to the message as a
+    /// file and the message only holds a preview of it, rather than rejecting them. Disabled by default, since the
+    /// attached file is not encrypted like the lines of the message
+    pub fn set_snippets(&mut self, enable: bool) {
+        self.snippets = enable
+    }
+
+    /// Operate only on the local network. Peers are discovered over mdns exclusively while bootstrapping,
+    /// relays, shuttles, the DHT and port mapping are disabled, regardless of the other settings.
+    /// Note: mdns is not available on wasm32, so peers would have to be connected to manually
+    pub fn set_lan_only(&mut self, lan_only: bool) {
+        self.lan_only = lan_only
+    }
+
+    // Turns the instance into a read-only replica of the account exported at `root`, which is kept in memory and does
+    // not publish anything on behalf of the account
+    pub(crate) fn set_audit_replica(&mut self, root: Cid) {
+        self.audit_replica = Some(root);
+        self.persist = false;
+        self.path = None;
+        self.save_phrase = false;
+        self.store_setting.auto_push = None;
+        self.store_setting.announce_to_mesh = false;
+        self.store_setting.organization = None;
+    }
+
+    // Overrides the settings that would reach beyond the local network when operating in lan-only mode
+    pub(crate) fn apply_lan_only(&mut self) {
+        if !self.lan_only {
+            return;
+        }
+
+        self.bootstrap = Bootstrap::None;
+        self.enable_relay = false;
+        self.ipfs_setting.mdns.enable = true;
+        self.ipfs_setting.relay_client.relay_address.clear();
+        self.ipfs_setting.portmapping = false;
+        self.ipfs_setting.dht_client = false;
+        self.store_setting.discovery = Discovery::None;
+        // Without any discovery, identities are found through announcements to connected peers
+        self.store_setting.announce_to_mesh = true;
+    }
 }
 
 impl Default for Config {
@@ -336,6 +555,13 @@ impl Default for Config {
             max_file_size: Some(100 * 1024 * 1024),
             thumbnail_size: (128, 128),
             thumbnail_exact_format: true,
+            strip_metadata: false,
+            data_usage_period: None,
+            max_message_size: MAX_MESSAGE_SIZE,
+            snippets: false,
+            lan_only: false,
+            clock: Clock::system(),
+            audit_replica: None,
         }
     }
 }
@@ -481,3 +707,41 @@ impl Config {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Bootstrap, Config, Discovery, DiscoveryType};
+
+    #[test]
+    fn lan_only_overrides_settings() {
+        let mut config = Config::testing();
+        config.store_setting_mut().discovery = Discovery::Namespace {
+            namespace: None,
+            discovery_type: DiscoveryType::DHT,
+        };
+        config.ipfs_setting_mut().mdns.enable = false;
+        config.ipfs_setting_mut().portmapping = true;
+        config.set_lan_only(true);
+        config.apply_lan_only();
+
+        assert!(matches!(config.bootstrap(), Bootstrap::None));
+        assert!(!config.enable_relay());
+        assert!(config.ipfs_setting().mdns.enable);
+        assert!(config.ipfs_setting().relay_client.relay_address.is_empty());
+        assert!(!config.ipfs_setting().portmapping);
+        assert!(matches!(config.store_setting().discovery, Discovery::None));
+    }
+
+    #[test]
+    fn settings_kept_without_lan_only() {
+        let mut config = Config::testing();
+        config.apply_lan_only();
+
+        assert!(matches!(config.bootstrap(), Bootstrap::Ipfs));
+        assert!(config.enable_relay());
+        assert!(matches!(
+            config.store_setting().discovery,
+            Discovery::Namespace { .. }
+        ));
+    }
+}
@@ -0,0 +1,176 @@
+//! Connection lifecycle events of peers.
+//!
+//! Applications can use [`PeerConnections`] to follow when peers connect or disconnect, whether the connection
+//! goes through a relay and which transport is used, along with round trip times measured to friends. This can
+//! be used to display connectivity indicators for contacts. Changes between relays of a fallback chain are
+//! reported as well, see [`crate::relay`].
+use std::time::Duration;
+
+use futures::stream::BoxStream;
+use rust_ipfs::{Multiaddr, PeerId, Protocol};
+use warp::{crypto::DID, error::Error};
+
+use crate::relay::RelayInfo;
+
+pub type ConnectionEventStream = BoxStream<'static, ConnectionEvent>;
+
+/// Route taken by a connection to a peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, derive_more::Display)]
+pub enum ConnectionRoute {
+    #[display(fmt = "direct")]
+    Direct,
+    #[display(fmt = "relay")]
+    Relay,
+}
+
+/// Transport used by a connection to a peer.
+/// For relayed connections, this is the transport used to reach the relay
+#[derive(Debug, Clone, Copy, PartialEq, Eq, derive_more::Display)]
+pub enum ConnectionTransport {
+    #[display(fmt = "tcp")]
+    Tcp,
+    #[display(fmt = "quic")]
+    Quic,
+    #[display(fmt = "websocket")]
+    WebSocket,
+    #[display(fmt = "webrtc")]
+    WebRtc,
+    #[display(fmt = "webtransport")]
+    WebTransport,
+    #[display(fmt = "memory")]
+    Memory,
+    #[display(fmt = "unknown")]
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionEvent {
+    /// A connection to the peer has been established. `connections` is the number of connections
+    /// currently established to the peer, including this one
+    Connected {
+        peer_id: PeerId,
+        did: Option<DID>,
+        route: ConnectionRoute,
+        transport: ConnectionTransport,
+        connections: usize,
+    },
+    /// A connection to the peer has been closed. `connections` is the number of connections
+    /// that remain established to the peer
+    Disconnected {
+        peer_id: PeerId,
+        did: Option<DID>,
+        route: ConnectionRoute,
+        transport: ConnectionTransport,
+        connections: usize,
+    },
+    /// Round trip time measured to a friend
+    Latency {
+        peer_id: PeerId,
+        did: Option<DID>,
+        rtt: Duration,
+    },
+    /// Reservation of the relay `failed` in a fallback chain failed and the relay is used in its place
+    RelayFallback { peer_id: PeerId, failed: PeerId },
+    /// A relay earlier in its fallback chain accepted a reservation again and is used in place of the fallback relay
+    RelayRestored { peer_id: PeerId, fallback: PeerId },
+}
+
+impl ConnectionEvent {
+    pub fn peer_id(&self) -> PeerId {
+        match self {
+            ConnectionEvent::Connected { peer_id, .. }
+            | ConnectionEvent::Disconnected { peer_id, .. }
+            | ConnectionEvent::Latency { peer_id, .. }
+            | ConnectionEvent::RelayFallback { peer_id, .. }
+            | ConnectionEvent::RelayRestored { peer_id, .. } => *peer_id,
+        }
+    }
+
+    /// DID of the peer, if it could be derived from the peer id
+    pub fn did(&self) -> Option<&DID> {
+        match self {
+            ConnectionEvent::Connected { did, .. }
+            | ConnectionEvent::Disconnected { did, .. }
+            | ConnectionEvent::Latency { did, .. } => did.as_ref(),
+            ConnectionEvent::RelayFallback { .. } | ConnectionEvent::RelayRestored { .. } => None,
+        }
+    }
+}
+
+pub(crate) fn connection_route(address: &Multiaddr) -> ConnectionRoute {
+    match address
+        .iter()
+        .any(|protocol| matches!(protocol, Protocol::P2pCircuit))
+    {
+        true => ConnectionRoute::Relay,
+        false => ConnectionRoute::Direct,
+    }
+}
+
+pub(crate) fn connection_transport(address: &Multiaddr) -> ConnectionTransport {
+    let mut transport = ConnectionTransport::Unknown;
+
+    for protocol in address.iter() {
+        transport = match protocol {
+            // Anything after the circuit describes the relayed peer and not the transport used
+            Protocol::P2pCircuit => break,
+            Protocol::Ws(_) | Protocol::Wss(_) => ConnectionTransport::WebSocket,
+            Protocol::WebRTC | Protocol::WebRTCDirect => ConnectionTransport::WebRtc,
+            Protocol::WebTransport => ConnectionTransport::WebTransport,
+            Protocol::Quic | Protocol::QuicV1 => ConnectionTransport::Quic,
+            Protocol::Memory(_) => ConnectionTransport::Memory,
+            Protocol::Tcp(_) if transport == ConnectionTransport::Unknown => {
+                ConnectionTransport::Tcp
+            }
+            _ => continue,
+        };
+    }
+
+    transport
+}
+
+#[async_trait::async_trait]
+pub trait PeerConnections: Sync + Send {
+    /// Subscribe to connection lifecycle events of peers
+    async fn connection_subscribe(&self) -> Result<ConnectionEventStream, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Relays that currently hold a reservation for the node, in order of preference
+    async fn active_relays(&self) -> Result<Vec<RelayInfo>, Error> {
+        Err(Error::Unimplemented)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rust_ipfs::Multiaddr;
+
+    use super::{connection_route, connection_transport, ConnectionRoute, ConnectionTransport};
+
+    #[test]
+    fn direct_connections() {
+        let address: Multiaddr = "/ip4/127.0.0.1/tcp/4001".parse().unwrap();
+        assert_eq!(connection_route(&address), ConnectionRoute::Direct);
+        assert_eq!(connection_transport(&address), ConnectionTransport::Tcp);
+
+        let address: Multiaddr = "/ip4/127.0.0.1/udp/4001/quic-v1".parse().unwrap();
+        assert_eq!(connection_transport(&address), ConnectionTransport::Quic);
+
+        let address: Multiaddr = "/dns4/example.com/tcp/443/wss".parse().unwrap();
+        assert_eq!(
+            connection_transport(&address),
+            ConnectionTransport::WebSocket
+        );
+
+        let address: Multiaddr = "/memory/10".parse().unwrap();
+        assert_eq!(connection_transport(&address), ConnectionTransport::Memory);
+    }
+
+    #[test]
+    fn relayed_connections() {
+        let address: Multiaddr = "/ip4/127.0.0.1/udp/4001/quic-v1/p2p/12D3KooWDpJ7As7BWAwRMfu1VU2WCqNjvq387JEYKDBj4kx6nXTN/p2p-circuit".parse().unwrap();
+        assert_eq!(connection_route(&address), ConnectionRoute::Relay);
+        assert_eq!(connection_transport(&address), ConnectionTransport::Quic);
+    }
+}
@@ -0,0 +1,550 @@
+//! Diagnostics for accounts that fail to load or behave inconsistently.
+//!
+//! [`AccountDoctor::diagnose`] runs a series of checks over the keystore, the root document and its blocks, the
+//! registration with shuttle nodes and the connectivity of the node, producing a [`DoctorReport`] where each failed
+//! check carries a suggested [`DoctorRepair`]. [`AccountDoctor::repair`] applies the repairs that are safe to perform
+//! automatically (ie those that never remove data) and diagnoses the account again.
+use std::fmt::Display;
+
+use ipld_core::cid::Cid;
+use rust_ipfs::{p2p::MultiaddrExt, Ipfs, Keypair};
+use uuid::Uuid;
+use warp::crypto::zeroize::Zeroizing;
+use warp::{error::Error, tesseract::Tesseract};
+
+use crate::config::Discovery;
+use crate::store::document::RootDocument;
+use crate::store::identity::IdentityStore;
+use crate::store::message::MessageStore;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DoctorCheckKind {
+    /// Keystore is unlocked and holds the keypair of the identity
+    Tesseract,
+    /// Root document can be resolved from the local blockstore and its signature is valid
+    RootDocument,
+    /// Root document and the documents it references are pinned and present locally
+    Pins,
+    /// Identity is registered with the configured shuttle nodes
+    Shuttle,
+    /// Node is connected to peers (and to shuttle nodes, if any are configured)
+    Connectivity,
+    /// Conversation documents can be resolved and their signatures are valid
+    Conversations,
+}
+
+impl Display for DoctorCheckKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            DoctorCheckKind::Tesseract => "tesseract",
+            DoctorCheckKind::RootDocument => "root document",
+            DoctorCheckKind::Pins => "pins",
+            DoctorCheckKind::Shuttle => "shuttle",
+            DoctorCheckKind::Connectivity => "connectivity",
+            DoctorCheckKind::Conversations => "conversations",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DoctorStatus {
+    Ok,
+    /// Check could not be performed or found an issue that does not prevent the account from operating
+    Warning,
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DoctorRepair {
+    /// Pin the root document recursively, fetching missing blocks from the network
+    PinRootDocument,
+    /// Register the identity with the shuttle nodes
+    RegisterWithShuttle,
+    /// Connect to the configured shuttle nodes
+    ConnectShuttle,
+    /// Fetch damaged conversation documents from participants and shuttle nodes and rebuild the conversation index
+    RepairConversations,
+    /// Repair that cannot be performed automatically
+    Manual(String),
+}
+
+impl DoctorRepair {
+    /// Whether the repair can be applied by [`AccountDoctor::repair`]
+    pub fn is_automatic(&self) -> bool {
+        !matches!(self, DoctorRepair::Manual(_))
+    }
+}
+
+impl Display for DoctorRepair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DoctorRepair::PinRootDocument => f.write_str("pin the root document"),
+            DoctorRepair::RegisterWithShuttle => f.write_str("register with the shuttle nodes"),
+            DoctorRepair::ConnectShuttle => f.write_str("connect to the shuttle nodes"),
+            DoctorRepair::RepairConversations => f.write_str("repair the conversation documents"),
+            DoctorRepair::Manual(action) => f.write_str(action),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DoctorCheck {
+    pub kind: DoctorCheckKind,
+    pub status: DoctorStatus,
+    pub detail: String,
+    pub repair: Option<DoctorRepair>,
+}
+
+impl DoctorCheck {
+    pub(crate) fn ok(kind: DoctorCheckKind, detail: impl Into<String>) -> Self {
+        Self {
+            kind,
+            status: DoctorStatus::Ok,
+            detail: detail.into(),
+            repair: None,
+        }
+    }
+
+    pub(crate) fn warning(kind: DoctorCheckKind, detail: impl Into<String>) -> Self {
+        Self {
+            kind,
+            status: DoctorStatus::Warning,
+            detail: detail.into(),
+            repair: None,
+        }
+    }
+
+    pub(crate) fn failed(
+        kind: DoctorCheckKind,
+        detail: impl Into<String>,
+        repair: DoctorRepair,
+    ) -> Self {
+        Self {
+            kind,
+            status: DoctorStatus::Failed,
+            detail: detail.into(),
+            repair: Some(repair),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DoctorReport {
+    pub checks: Vec<DoctorCheck>,
+}
+
+impl DoctorReport {
+    /// Returns true if no check failed
+    pub fn is_healthy(&self) -> bool {
+        self.checks
+            .iter()
+            .all(|check| check.status != DoctorStatus::Failed)
+    }
+
+    /// Repairs suggested by the failed checks
+    pub fn repairs(&self) -> impl Iterator<Item = &DoctorRepair> {
+        self.checks.iter().filter_map(|check| check.repair.as_ref())
+    }
+
+    /// Repairs that can be applied automatically, without duplicates and in the order they are applied
+    pub(crate) fn automatic_repairs(&self) -> Vec<DoctorRepair> {
+        let mut repairs: Vec<DoctorRepair> = vec![];
+        for repair in self.repairs().filter(|repair| repair.is_automatic()) {
+            if !repairs.contains(repair) {
+                repairs.push(repair.clone());
+            }
+        }
+
+        // connecting first gives the other repairs peers to fetch blocks from and register with
+        repairs.sort_by_key(|repair| !matches!(repair, DoctorRepair::ConnectShuttle));
+        repairs
+    }
+
+    pub(crate) fn push(&mut self, check: DoctorCheck) {
+        self.checks.push(check);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConversationDamage {
+    /// Document could not be resolved from the local blockstore
+    Unresolvable,
+    /// Signature of the document is invalid
+    InvalidSignature,
+    /// Document is indexed under a key other than the id of the conversation
+    MismatchedId(Uuid),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DamagedConversation {
+    /// Key of the document in the conversation index
+    pub key: String,
+    pub cid: Cid,
+    pub damage: ConversationDamage,
+    /// Whether the damage was repaired
+    pub repaired: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConversationRepairReport {
+    /// Number of documents in the conversation index
+    pub checked: usize,
+    pub damaged: Vec<DamagedConversation>,
+}
+
+impl ConversationRepairReport {
+    /// Returns true if no damaged document was found
+    pub fn is_clean(&self) -> bool {
+        self.damaged.is_empty()
+    }
+
+    /// Damaged documents that were not repaired
+    pub fn unrepaired(&self) -> impl Iterator<Item = &DamagedConversation> {
+        self.damaged.iter().filter(|damaged| !damaged.repaired)
+    }
+
+    /// Repair suggested for the documents that were not repaired, if any. Documents with an invalid signature are
+    /// never removed automatically, so they have to be reviewed by the user
+    pub fn repair(&self) -> Option<DoctorRepair> {
+        let mut unrepaired = self.unrepaired().peekable();
+        unrepaired.peek()?;
+
+        match unrepaired.all(|damaged| damaged.damage == ConversationDamage::InvalidSignature) {
+            true => Some(DoctorRepair::Manual(
+                "review the conversations with an invalid signature and delete those that are not trusted".into(),
+            )),
+            false => Some(DoctorRepair::RepairConversations),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+pub trait AccountDoctor: Sync + Send {
+    /// Diagnose the account, without modifying it
+    async fn diagnose(&self) -> Result<DoctorReport, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Apply the automatic repairs suggested by a diagnosis, returning a new diagnosis of the account
+    async fn repair(&self) -> Result<DoctorReport, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Fetch conversation documents that cannot be resolved locally from participants and shuttle nodes, and rebuild
+    /// the conversation index. Documents whose signature is invalid are reported as unrepaired and kept in the index,
+    /// since removing them would delete the conversation
+    async fn repair_conversations(&self) -> Result<ConversationRepairReport, Error> {
+        Err(Error::Unimplemented)
+    }
+}
+
+pub(crate) fn check_tesseract(tesseract: &Tesseract, ipfs: Option<&Ipfs>) -> DoctorCheck {
+    let kind = DoctorCheckKind::Tesseract;
+
+    if !tesseract.is_unlock() {
+        return DoctorCheck::failed(
+            kind,
+            "keystore is locked",
+            DoctorRepair::Manual("unlock the keystore with its passphrase".into()),
+        );
+    }
+
+    if !tesseract.exist("keypair") {
+        return DoctorCheck::failed(
+            kind,
+            "keystore does not contain a keypair",
+            DoctorRepair::Manual("import the account from its recovery phrase".into()),
+        );
+    }
+
+    let keypair = tesseract.retrieve("keypair").and_then(|encoded| {
+        let bytes = Zeroizing::new(bs58::decode(encoded).into_vec()?);
+        let keypair = warp::crypto::ed25519_dalek::Keypair::from_bytes(&bytes)?;
+        Keypair::ed25519_from_bytes(keypair.secret.to_bytes()).map_err(|_| Error::PrivateKeyInvalid)
+    });
+
+    let keypair = match keypair {
+        Ok(keypair) => keypair,
+        Err(e) => {
+            return DoctorCheck::failed(
+                kind,
+                format!("keypair is invalid: {e}"),
+                DoctorRepair::Manual("import the account from its recovery phrase".into()),
+            )
+        }
+    };
+
+    match ipfs {
+        Some(ipfs) if ipfs.keypair().public() != keypair.public() => DoctorCheck::failed(
+            kind,
+            "keypair does not match the loaded identity",
+            DoctorRepair::Manual("restart the application to reload the keystore".into()),
+        ),
+        _ => DoctorCheck::ok(kind, "keypair is present and valid"),
+    }
+}
+
+pub(crate) async fn check_root_document(
+    identity: &IdentityStore,
+) -> (DoctorCheck, Option<RootDocument>) {
+    let kind = DoctorCheckKind::RootDocument;
+
+    if let Err(e) = identity.root_document().export_root_cid().await {
+        return (
+            DoctorCheck::failed(
+                kind,
+                format!("root document was not created: {e}"),
+                DoctorRepair::Manual("import the account from its recovery phrase".into()),
+            ),
+            None,
+        );
+    }
+
+    match identity.root_document().get().await {
+        Ok(document) => (
+            DoctorCheck::ok(kind, "root document resolved and verified"),
+            Some(document),
+        ),
+        Err(e) => (
+            DoctorCheck::failed(
+                kind,
+                format!("unable to resolve root document: {e}"),
+                DoctorRepair::PinRootDocument,
+            ),
+            None,
+        ),
+    }
+}
+
+pub(crate) async fn check_pins(
+    ipfs: &Ipfs,
+    identity: &IdentityStore,
+    document: &RootDocument,
+) -> DoctorCheck {
+    let kind = DoctorCheckKind::Pins;
+
+    let root_cid = match identity.root_document().export_root_cid().await {
+        Ok(cid) => cid,
+        Err(e) => return DoctorCheck::warning(kind, format!("root cid is unavailable: {e}")),
+    };
+
+    if !ipfs.is_pinned(root_cid).await.unwrap_or_default() {
+        return DoctorCheck::failed(
+            kind,
+            format!("root document {root_cid} is not pinned"),
+            DoctorRepair::PinRootDocument,
+        );
+    }
+
+    let critical = [
+        Some(document.identity),
+        document.friends,
+        document.blocks,
+        document.block_by,
+        document.request,
+        document.conversations,
+        document.communities,
+        document.keystore,
+        document.file_index,
+    ];
+
+    let mut missing = vec![];
+    for cid in critical.into_iter().flatten() {
+        if !ipfs.repo().contains(&cid).await.unwrap_or_default() {
+            missing.push(cid.to_string());
+        }
+    }
+
+    match missing.is_empty() {
+        true => DoctorCheck::ok(kind, "root document and its documents are pinned"),
+        false => DoctorCheck::failed(
+            kind,
+            format!("missing blocks: {}", missing.join(", ")),
+            DoctorRepair::PinRootDocument,
+        ),
+    }
+}
+
+pub(crate) async fn check_shuttle(identity: &IdentityStore) -> DoctorCheck {
+    let kind = DoctorCheckKind::Shuttle;
+
+    if !matches!(identity.discovery_type(), Discovery::Shuttle { .. }) {
+        return DoctorCheck::ok(kind, "shuttle discovery is not used");
+    }
+
+    match identity.is_registered().await {
+        Ok(()) => DoctorCheck::ok(kind, "identity is registered"),
+        Err(Error::IdentityDoesntExist) => DoctorCheck::failed(
+            kind,
+            "identity is not registered with any shuttle node",
+            DoctorRepair::RegisterWithShuttle,
+        ),
+        Err(e) => DoctorCheck::warning(kind, format!("unable to check registration: {e}")),
+    }
+}
+
+pub(crate) async fn check_connectivity(ipfs: &Ipfs, identity: &IdentityStore) -> DoctorCheck {
+    let kind = DoctorCheckKind::Connectivity;
+
+    let connected = match ipfs.connected().await {
+        Ok(peers) => peers,
+        Err(e) => return DoctorCheck::warning(kind, format!("unable to list peers: {e}")),
+    };
+
+    if let Discovery::Shuttle { addresses } = identity.discovery_type() {
+        let shuttles = addresses
+            .iter()
+            .filter_map(|addr| addr.peer_id())
+            .collect::<Vec<_>>();
+
+        if !shuttles.is_empty() && !shuttles.iter().any(|peer| connected.contains(peer)) {
+            return DoctorCheck::failed(
+                kind,
+                "not connected to any shuttle node",
+                DoctorRepair::ConnectShuttle,
+            );
+        }
+    }
+
+    match connected.len() {
+        0 => DoctorCheck::warning(kind, "not connected to any peer"),
+        count => DoctorCheck::ok(kind, format!("connected to {count} peers")),
+    }
+}
+
+pub(crate) async fn check_conversations(messaging: &MessageStore) -> DoctorCheck {
+    let kind = DoctorCheckKind::Conversations;
+
+    let report = match messaging.repair_conversations(false).await {
+        Ok(report) => report,
+        Err(e) => return DoctorCheck::warning(kind, format!("unable to scan conversations: {e}")),
+    };
+
+    match report.repair() {
+        None => DoctorCheck::ok(kind, format!("{} conversations verified", report.checked)),
+        Some(repair) => DoctorCheck::failed(
+            kind,
+            format!(
+                "{} of {} conversation documents are damaged",
+                report.damaged.len(),
+                report.checked
+            ),
+            repair,
+        ),
+    }
+}
+
+/// Apply a repair, returning false if the repair cannot be applied automatically
+pub(crate) async fn apply_repair(
+    ipfs: &Ipfs,
+    identity: &IdentityStore,
+    repair: &DoctorRepair,
+) -> Result<bool, Error> {
+    match repair {
+        DoctorRepair::PinRootDocument => {
+            let root_cid = identity.root_document().export_root_cid().await?;
+            // fetches any missing block from connected peers while pinning
+            ipfs.insert_pin(root_cid).recursive().await?;
+        }
+        DoctorRepair::RegisterWithShuttle => identity.register().await?,
+        DoctorRepair::ConnectShuttle => {
+            if let Discovery::Shuttle { addresses } = identity.discovery_type() {
+                for peer_id in addresses.iter().filter_map(|addr| addr.peer_id()) {
+                    if let Err(e) = ipfs.connect(peer_id).await {
+                        tracing::warn!(%peer_id, error = %e, "unable to connect to shuttle node");
+                    }
+                }
+            }
+        }
+        // conversations are repaired by the message store, which is not available to the identity
+        DoctorRepair::RepairConversations | DoctorRepair::Manual(_) => return Ok(false),
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn damaged(damage: ConversationDamage, repaired: bool) -> DamagedConversation {
+        DamagedConversation {
+            key: Uuid::new_v4().to_string(),
+            cid: Cid::default(),
+            damage,
+            repaired,
+        }
+    }
+
+    #[test]
+    fn invalid_signature_is_left_for_review() {
+        let report = ConversationRepairReport {
+            checked: 2,
+            damaged: vec![damaged(ConversationDamage::InvalidSignature, false)],
+        };
+
+        assert!(!report.is_clean());
+        assert_eq!(report.unrepaired().count(), 1);
+        let repair = report.repair().expect("repair suggested");
+        assert!(!repair.is_automatic());
+    }
+
+    #[test]
+    fn unresolvable_document_is_repaired_automatically() {
+        let report = ConversationRepairReport {
+            checked: 2,
+            damaged: vec![
+                damaged(ConversationDamage::InvalidSignature, false),
+                damaged(ConversationDamage::Unresolvable, false),
+            ],
+        };
+
+        assert_eq!(report.repair(), Some(DoctorRepair::RepairConversations));
+
+        let repaired = ConversationRepairReport {
+            checked: 1,
+            damaged: vec![damaged(ConversationDamage::Unresolvable, true)],
+        };
+        assert_eq!(repaired.repair(), None);
+    }
+
+    #[test]
+    fn automatic_repairs_deduplicated_and_ordered() {
+        let mut report = DoctorReport::default();
+        report.push(DoctorCheck::failed(
+            DoctorCheckKind::RootDocument,
+            "unable to resolve root document",
+            DoctorRepair::PinRootDocument,
+        ));
+        report.push(DoctorCheck::failed(
+            DoctorCheckKind::Shuttle,
+            "identity is not registered",
+            DoctorRepair::RegisterWithShuttle,
+        ));
+        report.push(DoctorCheck::failed(
+            DoctorCheckKind::Pins,
+            "root document is not pinned",
+            DoctorRepair::PinRootDocument,
+        ));
+        report.push(DoctorCheck::failed(
+            DoctorCheckKind::Connectivity,
+            "not connected to any shuttle node",
+            DoctorRepair::ConnectShuttle,
+        ));
+        report.push(DoctorCheck::failed(
+            DoctorCheckKind::Tesseract,
+            "keystore is locked",
+            DoctorRepair::Manual("unlock the keystore with its passphrase".into()),
+        ));
+
+        assert!(!report.is_healthy());
+        assert_eq!(
+            report.automatic_repairs(),
+            vec![
+                DoctorRepair::ConnectShuttle,
+                DoctorRepair::PinRootDocument,
+                DoctorRepair::RegisterWithShuttle,
+            ]
+        );
+    }
+}
@@ -0,0 +1,257 @@
+//! Export of the data of an account, used to answer data subject access requests.
+//!
+//! [`AccountDataExport::export_account_data`] collects the identity, contacts, conversations and files of the account
+//! into an [`AccountDataArchive`], which holds a human-readable summary along with the media it references. Only the
+//! data of the account is included, so conversations list the messages and reactions of the account but not those of
+//! the other participants. The archive can be written into a directory with [`AccountDataArchive::write_to`], where
+//! the summary is stored as `account.json` and the media is stored under `media/`. Summaries of accounts with long
+//! conversation histories can be compressed with [`AccountDataArchive::write_compressed_to`].
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use warp::{
+    constellation::{directory::Directory, file::File},
+    crypto::DID,
+    error::Error,
+    raygun::{ConversationType, Message, SystemMessage},
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AccountData {
+    pub exported: DateTime<Utc>,
+    pub identity: IdentityData,
+    pub friends: Vec<DID>,
+    pub blocked: Vec<DID>,
+    pub requests: Vec<RequestData>,
+    pub conversations: Vec<ConversationData>,
+    pub files: Vec<FileData>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct IdentityData {
+    pub did: DID,
+    pub username: String,
+    pub status_message: Option<String>,
+    pub created: DateTime<Utc>,
+    pub modified: DateTime<Utc>,
+    /// Path of the profile picture within the archive
+    pub profile_picture: Option<String>,
+    /// Path of the profile banner within the archive
+    pub profile_banner: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RequestData {
+    pub did: DID,
+    pub outgoing: bool,
+    pub date: DateTime<Utc>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ConversationData {
+    pub id: Uuid,
+    pub name: Option<String>,
+    pub conversation_type: ConversationType,
+    pub created: DateTime<Utc>,
+    pub participants: Vec<DID>,
+    /// Messages sent by the account
+    pub messages: Vec<MessageData>,
+    /// Reactions of the account to messages of other participants
+    pub reactions: Vec<ReactionData>,
+    /// Changes to the conversation, such as members being added
+    pub system_messages: Vec<SystemMessageData>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MessageData {
+    pub id: Uuid,
+    pub date: DateTime<Utc>,
+    pub modified: Option<DateTime<Utc>>,
+    pub replied: Option<Uuid>,
+    pub lines: Vec<String>,
+    pub reactions: Vec<String>,
+    /// Paths of the attachments within the archive
+    pub attachments: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SystemMessageData {
+    pub date: DateTime<Utc>,
+    /// Stable key of the change. See [`SystemMessage::key`]
+    pub key: String,
+    pub params: IndexMap<String, String>,
+}
+
+impl SystemMessageData {
+    pub(crate) fn new(date: DateTime<Utc>, system: &SystemMessage) -> Self {
+        Self {
+            date,
+            key: system.key().to_string(),
+            params: system
+                .params()
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ReactionData {
+    pub message_id: Uuid,
+    pub emoji: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FileData {
+    /// Path of the file in constellation
+    pub path: String,
+    pub size: usize,
+    pub file_type: String,
+    pub created: DateTime<Utc>,
+    pub modified: DateTime<Utc>,
+    /// Path of the file within the archive, if its content could be retrieved
+    pub media: Option<String>,
+}
+
+/// Summary of the account along with the media it references
+#[derive(Debug, Clone)]
+pub struct AccountDataArchive {
+    pub data: AccountData,
+    /// Content of the media, keyed by its path within the archive
+    pub media: Vec<(String, Bytes)>,
+}
+
+impl AccountDataArchive {
+    pub(crate) fn new(data: AccountData) -> Self {
+        Self {
+            data,
+            media: vec![],
+        }
+    }
+
+    /// Add media to the archive, returning its path within the archive
+    pub(crate) fn add_media(&mut self, path: String, data: Bytes) -> String {
+        let path = format!("media/{}", sanitize_path(&path));
+        self.media.push((path.clone(), data));
+        path
+    }
+
+    /// Summary of the account as pretty printed json
+    pub fn to_json(&self) -> Result<String, Error> {
+        Ok(serde_json::to_string_pretty(&self.data)?)
+    }
+
+    /// Write the archive into `path`, creating the directory if it does not exist
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn write_to(&self, path: &std::path::Path) -> Result<(), Error> {
+        self.write(path, false).await
+    }
+
+    /// Write the archive into `path` like [`AccountDataArchive::write_to`], with the summary compressed with zstd
+    /// and stored as `account.json.zst`
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn write_compressed_to(&self, path: &std::path::Path) -> Result<(), Error> {
+        self.write(path, true).await
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn write(&self, path: &std::path::Path, compressed: bool) -> Result<(), Error> {
+        tokio::fs::create_dir_all(path).await?;
+
+        let summary = self.to_json()?;
+        match compressed {
+            true => {
+                let summary = warp::data::compress(summary.as_bytes())?;
+                tokio::fs::write(path.join("account.json.zst"), summary).await?;
+            }
+            false => tokio::fs::write(path.join("account.json"), summary).await?,
+        }
+
+        for (media_path, data) in &self.media {
+            let media_path = path.join(media_path);
+            if let Some(parent) = media_path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::write(media_path, data).await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl MessageData {
+    pub(crate) fn new(message: &Message, own_did: &DID) -> Self {
+        Self {
+            id: message.id(),
+            date: message.date(),
+            modified: message.modified(),
+            replied: message.replied(),
+            lines: message.lines().to_vec(),
+            reactions: own_reactions(message, own_did),
+            attachments: vec![],
+        }
+    }
+}
+
+/// Reactions of `did` to the message
+pub(crate) fn own_reactions(message: &Message, did: &DID) -> Vec<String> {
+    message
+        .reactions()
+        .iter()
+        .filter(|(_, reactors)| reactors.contains(did))
+        .map(|(emoji, _)| emoji.clone())
+        .collect()
+}
+
+/// Files within `directory` and its subdirectories, along with their path
+pub(crate) fn collect_files(directory: &Directory, prefix: &str) -> Vec<(String, File)> {
+    let mut files = vec![];
+    for item in directory.get_items() {
+        let path = match prefix.is_empty() {
+            true => item.name(),
+            false => format!("{prefix}/{}", item.name()),
+        };
+
+        if let Ok(file) = item.get_file() {
+            files.push((path, file));
+        } else if let Ok(directory) = item.get_directory() {
+            files.extend(collect_files(&directory, &path));
+        }
+    }
+    files
+}
+
+/// Strips components that would place media outside of the archive
+fn sanitize_path(path: &str) -> String {
+    path.split(['/', '\\'])
+        .filter(|component| !component.is_empty() && *component != "." && *component != "..")
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[async_trait::async_trait]
+pub trait AccountDataExport: Sync + Send {
+    /// Collect the data of the account into an archive
+    async fn export_account_data(&self) -> Result<AccountDataArchive, Error> {
+        Err(Error::Unimplemented)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::sanitize_path;
+
+    #[test]
+    fn media_paths_stay_within_archive() {
+        assert_eq!(
+            sanitize_path("files/photos/cat.png"),
+            "files/photos/cat.png"
+        );
+        assert_eq!(sanitize_path("/files/../../etc/passwd"), "files/etc/passwd");
+        assert_eq!(sanitize_path("files\\..\\cat.png"), "files/cat.png");
+    }
+}
@@ -0,0 +1,64 @@
+//! Events waiting to be acknowledged by the application.
+//!
+//! Mentions and friend requests are written to a queue in the datastore before their event is emitted, so the
+//! application can restore its notification badges with [`EventInbox::pending_events`] after a crash or restart
+//! rather than relying on having processed every event. Events stay pending until they are acknowledged with
+//! [`EventInbox::acknowledge_events`] or [`EventInbox::acknowledge_conversation`], or until they no longer apply
+//! (eg the friend request was answered or the message was deleted).
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use warp::{crypto::DID, error::Error};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PendingEventKind {
+    /// Own identity was mentioned in a message
+    Mention {
+        conversation_id: Uuid,
+        message_id: Uuid,
+        sender: DID,
+    },
+    /// Friend request was received
+    FriendRequest { from: DID },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingEvent {
+    pub(crate) id: Uuid,
+    pub(crate) kind: PendingEventKind,
+    pub(crate) date: DateTime<Utc>,
+}
+
+impl PendingEvent {
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    pub fn kind(&self) -> &PendingEventKind {
+        &self.kind
+    }
+
+    /// Date the event was received
+    pub fn date(&self) -> DateTime<Utc> {
+        self.date
+    }
+}
+
+#[async_trait::async_trait]
+pub trait EventInbox: Sync + Send {
+    /// List the events that were not acknowledged, from the oldest to the newest
+    async fn pending_events(&self) -> Result<Vec<PendingEvent>, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Acknowledge events once they were processed by the application, returning the amount of events removed
+    async fn acknowledge_events(&self, _: &[Uuid]) -> Result<usize, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Acknowledge every pending mention of a conversation, such as after it was read
+    async fn acknowledge_conversation(&self, _: Uuid) -> Result<usize, Error> {
+        Err(Error::Unimplemented)
+    }
+}
@@ -4,18 +4,19 @@ use futures::channel::mpsc::channel;
 use futures::future::BoxFuture;
 use futures::stream::{self, BoxStream};
 use futures::{FutureExt, StreamExt, TryStreamExt};
-use futures_timeout::TimeoutExt;
-use indexmap::IndexSet;
+use indexmap::{IndexMap, IndexSet};
 use ipfs::p2p::{
     IdentifyConfiguration, KadConfig, KadInserts, MultiaddrExt, PubsubConfig, TransportConfig,
 };
-use ipfs::{DhtMode, Ipfs, Keypair, Protocol, UninitializedIpfs};
+use ipfs::{DhtMode, Ipfs, IpfsPath, Keypair, Multiaddr, PeerId, Protocol, UninitializedIpfs};
+use ipld_core::cid::Cid;
 use parking_lot::RwLock;
 use rust_ipfs as ipfs;
 use rust_ipfs::p2p::{RequestResponseConfig, UpgradeVersion};
 use std::any::Any;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::ffi::OsStr;
+use std::future::Future;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
@@ -27,59 +28,109 @@ use uuid::Uuid;
 use warp::raygun::community::{
     CommunityChannelPermission, CommunityPermission, CommunityRole, RoleId,
 };
+use warp::raygun::processor::{MessageProcessor, MessageProcessorPipeline};
 
+use crate::bundle::{Bundle, BundleImport, OfflineBundles};
 use crate::config::{Bootstrap, DiscoveryType};
+use crate::connection::{ConnectionEvent, ConnectionEventStream, PeerConnections};
+use crate::doctor::{
+    AccountDoctor, ConversationRepairReport, DoctorCheck, DoctorCheckKind, DoctorRepair,
+    DoctorReport,
+};
+use crate::export::{
+    AccountData, AccountDataArchive, AccountDataExport, ConversationData, FileData, IdentityData,
+    MessageData, ReactionData, RequestData, SystemMessageData,
+};
+use crate::inbox::{EventInbox, PendingEvent};
+use crate::moderation::{
+    MessageReporting, ModerationReport, ReportAction, ReportStatus, ReportTarget,
+};
+use crate::readiness::{Readiness, ReadinessEventStream, Subsystem};
+use crate::relay::{RelayInfo, RelaySelection};
+use crate::rpc::{PeerRpc, RpcRequestStream};
 use crate::store::discovery::Discovery;
+use crate::store::payload::{PayloadBuilder, PayloadMessage};
 use crate::store::phonebook::PhoneBook;
 use crate::store::{ecdh_decrypt, PeerIdExt};
 use crate::store::{MAX_IMAGE_SIZE, MAX_USERNAME_LENGTH, MIN_USERNAME_LENGTH};
+use crate::tasks::TaskTracker;
+use crate::usage::{DataUsage, DataUsageAccounting};
 use crate::utils::{ByteCollection, ReaderStream};
 use config::Config;
+use shuttle::identity::organization::{MembershipCertificate, OrganizationRoster};
+use store::cold_storage::ColdStorageReport;
+use store::device::{DeviceCertificate, DeviceKey, DeviceLink};
 use store::document::ResolvedRootDocument;
+use store::emoji::{EmojiPack, EmojiPacks};
+use store::encrypted::EncryptedStore;
 use store::event_subscription::EventSubscription;
+use store::exchange::ExchangeStore;
 use store::files::FileStore;
 use store::identity::IdentityStore;
 use store::message::MessageStore;
+use store::moderation::ModerationStore;
+use store::rpc::RpcStore;
+use store::sync::{SelectiveSync, SelectiveSyncSettings};
+use store::usage::UsageTracker;
 use utils::ExtensionType;
 use warp::constellation::directory::Directory;
 use warp::constellation::file::FileType;
 use warp::constellation::{
     Constellation, ConstellationEvent, ConstellationEventKind, ConstellationEventStream,
-    ConstellationProgressStream,
+    ConstellationProgressStream, IntegrityReport, UploadSession,
 };
 use warp::crypto::keypair::PhraseType;
 use warp::crypto::zeroize::Zeroizing;
 use warp::crypto::{KeyMaterial, DID};
-use warp::error::Error;
+use warp::error::{Error, ErrorContext, OperationFailure};
 use warp::module::Module;
+use warp::multipass::contact::ContactFormat;
 use warp::multipass::identity::{
-    FriendRequest, Identifier, Identity, IdentityImage, IdentityProfile, IdentityUpdate,
-    Relationship,
+    FriendRequest, FriendsPrivacy, Identifier, Identity, IdentityActivity, IdentityImage,
+    IdentityProfile, IdentityUpdate, ProfileFields, Relationship, RelationshipHistoryEntry,
 };
+use warp::multipass::notification::NotificationPreferences;
 use warp::multipass::{
-    identity, Friends, GetIdentity, IdentityImportOption, IdentityInformation, ImportLocation,
-    LocalIdentity, MultiPass, MultiPassEvent, MultiPassEventKind, MultiPassEventStream,
-    MultiPassImportExport,
+    identity, Friends, GetIdentity, IdentityChangeStream, IdentityImportOption,
+    IdentityInformation, ImportLocation, LocalIdentity, MultiPass, MultiPassEvent,
+    MultiPassEventKind, MultiPassEventStream, MultiPassImportExport,
 };
 use warp::raygun::{
     community::{
         Community, CommunityChannel, CommunityChannelType, CommunityInvite, RayGunCommunity,
     },
-    AttachmentEventStream, Conversation, ConversationImage, EmbedState, GroupPermissionOpt,
-    Location, Message, MessageEvent, MessageEventStream, MessageOptions, MessageReference,
-    MessageStatus, Messages, PinState, RayGun, RayGunAttachment, RayGunConversationInformation,
-    RayGunEventKind, RayGunEventStream, RayGunEvents, RayGunGroupConversation, RayGunStream,
-    ReactionState,
+    AttachmentEventStream, AttachmentOptions, Conversation, ConversationImage, ConversationStats,
+    EmbedState, GroupPermissionOpt, HistoryVisibility, Location, Message, MessageEvent,
+    MessageEventStream, MessageOptions, MessageReference, MessageStatus, Messages, PinState,
+    RayGun, RayGunAttachment, RayGunConversationInformation, RayGunEventKind, RayGunEventStream,
+    RayGunEvents, RayGunGroupConversation, RayGunStream, ReactionState, RetentionPolicy,
+    SearchQuery, SearchResultStream,
 };
+use warp::subscription::SubscriptionOptions;
 use warp::tesseract::{Tesseract, TesseractEvent};
 use warp::warp::Warp;
 use warp::{Extension, SingleHandle};
 
+pub mod audit;
 mod behaviour;
+pub mod bundle;
 pub mod config;
+pub mod connection;
+pub mod doctor;
+pub mod export;
+pub mod inbox;
+mod metadata;
+pub mod moderation;
+pub mod readiness;
+pub mod relay;
+pub mod rpc;
+mod rt;
 pub mod shuttle;
 pub mod store;
+mod tasks;
 mod thumbnail;
+pub mod time;
+pub mod usage;
 mod utils;
 
 const PUBSUB_MAX_BUF: usize = 8_388_608;
@@ -91,6 +142,8 @@ pub struct WarpIpfs {
     multipass_tx: EventSubscription<MultiPassEventKind>,
     raygun_tx: EventSubscription<RayGunEventKind>,
     constellation_tx: EventSubscription<ConstellationEventKind>,
+    connection_tx: tokio::sync::broadcast::Sender<ConnectionEvent>,
+    failure_tx: tokio::sync::broadcast::Sender<OperationFailure>,
 }
 
 pub type WarpIpfsInstance = Warp<WarpIpfs, WarpIpfs, WarpIpfs>;
@@ -101,6 +154,13 @@ struct Inner {
     init_guard: tokio::sync::Mutex<()>,
     span: RwLock<Span>,
     components: RwLock<Option<Components>>,
+    // generated phrase of an identity that was rejected during creation (eg username was taken)
+    pending_phrase: RwLock<Option<Zeroizing<String>>>,
+    processors: MessageProcessorPipeline,
+    usage: UsageTracker,
+    // background tasks that are not owned by a store
+    tasks: TaskTracker,
+    readiness: Readiness,
 }
 
 // Holds the initialized components
@@ -110,6 +170,13 @@ struct Components {
     identity_store: IdentityStore,
     message_store: MessageStore,
     file_store: FileStore,
+    selective_sync: SelectiveSync,
+    emoji_packs: EmojiPacks,
+    rpc_store: RpcStore,
+    // answers exchange requests and samples latency for as long as the components are alive
+    _exchange_store: ExchangeStore,
+    moderation_store: ModerationStore,
+    relay_selection: Option<RelaySelection>,
 }
 
 #[derive(Default)]
@@ -131,6 +198,12 @@ impl WarpIpfsBuilder {
         self.tesseract = Some(tesseract);
         self
     }
+
+    /// Operate only on the local network. See [`Config::set_lan_only`]
+    pub fn set_lan_only(mut self, lan_only: bool) -> Self {
+        self.config.set_lan_only(lan_only);
+        self
+    }
 }
 
 impl core::future::IntoFuture for WarpIpfsBuilder {
@@ -143,10 +216,17 @@ impl core::future::IntoFuture for WarpIpfsBuilder {
 }
 
 impl WarpIpfs {
-    pub async fn new(config: Config, tesseract: impl Into<Option<Tesseract>>) -> WarpIpfsInstance {
+    pub async fn new(
+        mut config: Config,
+        tesseract: impl Into<Option<Tesseract>>,
+    ) -> WarpIpfsInstance {
+        config.apply_lan_only();
+
         let multipass_tx = EventSubscription::new();
         let raygun_tx = EventSubscription::new();
         let constellation_tx = EventSubscription::new();
+        let (connection_tx, _) = tokio::sync::broadcast::channel(256);
+        let (failure_tx, _) = tokio::sync::broadcast::channel(256);
         let span = RwLock::new(Span::current());
 
         let tesseract = match tesseract.into() {
@@ -174,11 +254,18 @@ impl WarpIpfs {
             }
         };
 
+        let usage = UsageTracker::new(config.data_usage_period());
+
         let inner = Arc::new(Inner {
             config,
             components: Default::default(),
+            pending_phrase: Default::default(),
             identity_guard: Default::default(),
             init_guard: Default::default(),
+            processors: Default::default(),
+            usage,
+            tasks: TaskTracker::new(),
+            readiness: Readiness::default(),
             span,
         });
 
@@ -188,11 +275,13 @@ impl WarpIpfs {
             multipass_tx,
             raygun_tx,
             constellation_tx,
+            connection_tx,
+            failure_tx,
         };
 
         if !identity.tesseract.is_unlock() {
             let inner = identity.clone();
-            async_rt::task::dispatch(async move {
+            identity.inner.tasks.spawn(async move {
                 let mut stream = inner.tesseract.subscribe();
                 while let Some(event) = stream.next().await {
                     if matches!(event, TesseractEvent::Unlocked) {
@@ -246,6 +335,33 @@ impl WarpIpfs {
         Ok(())
     }
 
+    // Keypair of this device, which is kept in tesseract. Devices that stored their keypair encrypted with the
+    // account key have it moved into tesseract
+    async fn device_keypair(&self, ipfs: &Ipfs, account: &Keypair) -> Result<Keypair, Error> {
+        if self.tesseract.exist("device_keypair") {
+            let encoded = Zeroizing::new(self.tesseract.retrieve("device_keypair")?);
+            let bytes = Zeroizing::new(bs58::decode(encoded.as_str()).into_vec()?);
+            return Keypair::from_protobuf_encoding(&bytes)
+                .map_err(anyhow::Error::from)
+                .map_err(Error::from);
+        }
+
+        let keypair = match DeviceKey::legacy_keypair(ipfs, account).await? {
+            Some(keypair) => keypair,
+            None => Keypair::generate_ed25519(),
+        };
+
+        let bytes = Zeroizing::new(
+            keypair
+                .to_protobuf_encoding()
+                .map_err(anyhow::Error::from)?,
+        );
+        self.tesseract
+            .set("device_keypair", &bs58::encode(&bytes).into_string())?;
+
+        Ok(keypair)
+    }
+
     pub(crate) async fn init_ipfs(&self, keypair: Keypair) -> Result<(), Error> {
         // Since some trait functions are not async (this may change in the future), we cannot hold a lock that is not async-aware
         // through this function without suffering dead locks in the process through await points with the lock held.
@@ -262,6 +378,8 @@ impl WarpIpfs {
             return Err(Error::IdentityExist);
         }
 
+        self.inner.readiness.start();
+
         let peer_id = keypair.public().to_peer_id();
 
         let did = peer_id.to_did().expect("Valid conversion");
@@ -280,7 +398,10 @@ impl WarpIpfs {
             Bootstrap::None => true,
         };
 
-        if empty_bootstrap && !self.inner.config.ipfs_setting().dht_client {
+        if empty_bootstrap
+            && !self.inner.config.ipfs_setting().dht_client
+            && !self.inner.config.lan_only()
+        {
             tracing::warn!(
                 "Bootstrap list is empty. Will not be able to perform a bootstrap for DHT"
             );
@@ -290,6 +411,7 @@ impl WarpIpfs {
 
         let behaviour = behaviour::Behaviour {
             phonebook: behaviour::phonebook::Behaviour::new(self.multipass_tx.clone(), pb_rx),
+            connection: behaviour::connection::Behaviour::new(self.connection_tx.clone()),
         };
 
         let mut request_response_configs = vec![
@@ -311,6 +433,12 @@ impl WarpIpfs {
                 max_response_size: 512 * 1024,
                 ..Default::default()
             },
+            RequestResponseConfig {
+                protocol: protocols::PEER_RPC_PROTOCOL.as_ref().into(),
+                max_request_size: 128 * 1024,
+                max_response_size: 128 * 1024,
+                ..Default::default()
+            },
         ];
 
         if let config::Discovery::Shuttle { .. } = &self.inner.config.store_setting().discovery {
@@ -330,6 +458,15 @@ impl WarpIpfs {
             ]);
         }
 
+        // A replica is only given the key of the account to read its content. The node uses a key of its own, and
+        // without pubsub or request-response it has no way to publish or send anything on behalf of the account
+        let replica = self.inner.config.audit_replica().is_some();
+
+        let node_keypair = match replica {
+            true => Keypair::generate_ed25519(),
+            false => keypair.clone(),
+        };
+
         tracing::info!("Starting ipfs");
         let mut uninitialized = UninitializedIpfs::new()
             .with_identify({
@@ -344,15 +481,10 @@ impl WarpIpfs {
             })
             .with_bitswap()
             .with_ping(Default::default())
-            .with_pubsub(PubsubConfig {
-                max_transmit_size: PUBSUB_MAX_BUF,
-                ..Default::default()
-            })
             .with_relay(true)
-            .with_request_response(request_response_configs)
             .set_listening_addrs(self.inner.config.listen_on().to_vec())
             .with_custom_behaviour(behaviour)
-            .set_keypair(&keypair)
+            .set_keypair(&node_keypair)
             .set_span(span.clone())
             .set_transport_configuration(TransportConfig {
                 enable_memory_transport: self.inner.config.ipfs_setting().memory_transport,
@@ -363,6 +495,15 @@ impl WarpIpfs {
                 ..Default::default()
             });
 
+        if !replica {
+            uninitialized = uninitialized
+                .with_pubsub(PubsubConfig {
+                    max_transmit_size: PUBSUB_MAX_BUF,
+                    ..Default::default()
+                })
+                .with_request_response(request_response_configs);
+        }
+
         // TODO: Uncomment for persistence on wasm once config option is added
         #[cfg(target_arch = "wasm32")]
         {
@@ -430,8 +571,12 @@ impl WarpIpfs {
 
         let ipfs = uninitialized.start().await?;
 
+        self.inner.readiness.set_ready(Subsystem::Ipfs);
+
+        let mut relay_selection = None;
+
         if self.inner.config.enable_relay() {
-            let mut relay_peers = HashSet::new();
+            let mut relay_peers: IndexMap<PeerId, Vec<Multiaddr>> = IndexMap::new();
 
             for mut addr in self
                 .inner
@@ -457,66 +602,32 @@ impl WarpIpfs {
                     tracing::warn!("Failed to add relay to address book: {e}");
                 }
 
-                if let Err(e) = ipfs.add_relay(peer_id, addr).await {
+                if let Err(e) = ipfs.add_relay(peer_id, addr.clone()).await {
                     tracing::error!("Error adding relay: {e}");
                     continue;
                 }
 
-                relay_peers.insert(peer_id);
+                relay_peers.entry(peer_id).or_default().push(addr);
             }
 
             if relay_peers.is_empty() {
                 tracing::warn!("No relays available");
             }
 
-            // Use the selected relays
-            let relay_connection_task = {
-                let ipfs = ipfs.clone();
-                let quorum = self.inner.config.ipfs_setting().relay_client.quorum;
-                async move {
-                    let mut counter = 0;
-                    for relay_peer in relay_peers {
-                        match ipfs
-                            .enable_relay(Some(relay_peer))
-                            .timeout(Duration::from_secs(5))
-                            .await
-                        {
-                            Ok(Ok(_)) => {}
-                            Ok(Err(e)) => {
-                                tracing::error!("Failed to use {relay_peer} as a relay: {e}");
-                                continue;
-                            }
-                            Err(_) => {
-                                tracing::error!("Relay connection timed out");
-                                continue;
-                            }
-                        };
-
-                        match quorum {
-                            config::RelayQuorum::First => break,
-                            config::RelayQuorum::N(n) => {
-                                if counter < n {
-                                    counter += 1;
-                                    continue;
-                                }
-                                break;
-                            }
-                            config::RelayQuorum::All => continue,
-                        }
-                    }
-
-                    let list = ipfs.list_relays(true).await.unwrap_or_default();
-                    for addr in list.iter().flat_map(|(_, addrs)| addrs) {
-                        tracing::info!("Listening on {}", addr.clone().with(Protocol::P2pCircuit));
-                    }
-                }
-            };
+            // Select between the relays based on their latency and reservation success
+            let (selection, selected) = RelaySelection::new(
+                &ipfs,
+                relay_peers,
+                &self.inner.config.ipfs_setting().relay_client,
+                self.connection_tx.clone(),
+                &span,
+            );
 
-            if self.inner.config.ipfs_setting().relay_client.background {
-                async_rt::task::dispatch(relay_connection_task);
-            } else {
-                relay_connection_task.await;
+            if !self.inner.config.ipfs_setting().relay_client.background {
+                let _ = selected.await;
             }
+
+            relay_selection = Some(selection);
         }
 
         if let config::Discovery::Shuttle { addresses } =
@@ -574,7 +685,7 @@ impl WarpIpfs {
             }
 
             if !empty_bootstrap {
-                async_rt::task::dispatch({
+                self.inner.tasks.spawn({
                     let ipfs = ipfs.clone();
                     async move {
                         loop {
@@ -632,46 +743,119 @@ impl WarpIpfs {
 
         let phonebook = PhoneBook::new(discovery.clone(), pb_tx);
 
+        if self.tesseract.exist("device_link_keypair")
+            && self.tesseract.exist("device_link_certificate")
+        {
+            tracing::info!("Storing key of linked device");
+            let encoded = Zeroizing::new(self.tesseract.retrieve("device_link_keypair")?);
+            let bytes = Zeroizing::new(bs58::decode(encoded.as_str()).into_vec()?);
+            let device_kp = Keypair::from_protobuf_encoding(&bytes).map_err(anyhow::Error::from)?;
+            let certificate = serde_json::from_str::<DeviceCertificate>(
+                &self.tesseract.retrieve("device_link_certificate")?,
+            )?;
+
+            DeviceKey::store(&ipfs, &keypair, device_kp, certificate).await?;
+
+            self.tesseract.set("device_keypair", &encoded)?;
+            self.tesseract.delete("device_link_keypair")?;
+            self.tesseract.delete("device_link_certificate")?;
+        }
+
+        let device_kp = self.device_keypair(&ipfs, &keypair).await?;
+
         tracing::info!("Initializing identity profile");
         let identity_store = IdentityStore::new(
             &ipfs,
+            &keypair,
             &self.inner.config,
             self.multipass_tx.clone(),
             &phonebook,
             &discovery,
+            device_kp,
+            &self.inner.usage,
+            &self.inner.tasks,
             &span,
         )
         .await?;
 
         tracing::info!("Identity initialized");
 
+        self.inner.readiness.set_ready(Subsystem::Identity);
+
         let root = identity_store.root_document();
 
+        let selective_sync = SelectiveSync::new(&ipfs).await;
+
         let filestore = FileStore::new(
             &ipfs,
             root,
             &self.inner.config,
+            &selective_sync,
             self.constellation_tx.clone(),
+            &self.inner.readiness,
             &span,
         )
         .await;
 
+        // the index is only kept in memory if local data cannot be encrypted
+        let search_store = match EncryptedStore::new(&ipfs, &self.tesseract, "search_index") {
+            Ok(store) => Some(store),
+            Err(e) => {
+                tracing::warn!(error = %e, "unable to open search index store");
+                None
+            }
+        };
+
         let message_store = MessageStore::new(
             &ipfs,
             discovery,
             &filestore,
             self.raygun_tx.clone(),
             &identity_store,
+            self.inner.processors.clone(),
+            &self.inner.usage,
+            &selective_sync,
+            search_store,
+            &self.inner.readiness,
         )
         .await;
 
         tracing::info!("Messaging store initialized");
 
+        let emoji_packs = EmojiPacks::new(&ipfs).await;
+
+        // reports are only kept in memory if local data cannot be encrypted
+        let moderation_reports = match EncryptedStore::new(&ipfs, &self.tesseract, "moderation") {
+            Ok(store) => Some(store),
+            Err(e) => {
+                tracing::warn!(error = %e, "unable to open moderation store");
+                None
+            }
+        };
+
+        let (rpc_store, exchange_store, moderation_store) = futures::try_join!(
+            RpcStore::new(&ipfs, &identity_store, &span),
+            ExchangeStore::new(&ipfs, &identity_store, self.connection_tx.clone(), &span),
+            ModerationStore::new(
+                &ipfs,
+                &identity_store,
+                &message_store,
+                moderation_reports,
+                &span
+            ),
+        )?;
+
         *self.inner.components.write() = Some(Components {
             ipfs,
             identity_store,
             message_store,
             file_store: filestore,
+            selective_sync,
+            emoji_packs,
+            rpc_store,
+            _exchange_store: exchange_store,
+            moderation_store,
+            relay_selection,
         });
 
         // Announce identity out to mesh if identity has been created at that time
@@ -724,6 +908,24 @@ impl WarpIpfs {
             .ok_or(Error::ConstellationExtensionUnavailable)
     }
 
+    pub(crate) fn rpc_store(&self) -> Result<RpcStore, Error> {
+        self.inner
+            .components
+            .read()
+            .as_ref()
+            .map(|com| com.rpc_store.clone())
+            .ok_or(Error::MultiPassExtensionUnavailable)
+    }
+
+    pub(crate) fn moderation_store(&self) -> Result<ModerationStore, Error> {
+        self.inner
+            .components
+            .read()
+            .as_ref()
+            .map(|com| com.moderation_store.clone())
+            .ok_or(Error::RayGunExtensionUnavailable)
+    }
+
     pub(crate) fn direct_identity_store(&self) -> Result<IdentityStore, Error> {
         let store = self
             .inner
@@ -744,6 +946,318 @@ impl WarpIpfs {
             .map(|com| com.ipfs.clone())
             .ok_or(Error::MultiPassExtensionUnavailable)
     }
+
+    /// Stream of the subsystems as they become ready during startup, see [`readiness`]
+    pub fn readiness_subscribe(&self) -> ReadinessEventStream {
+        self.inner.readiness.subscribe()
+    }
+
+    pub fn is_ready(&self, subsystem: Subsystem) -> bool {
+        self.inner.readiness.is_ready(subsystem)
+    }
+
+    /// Subscribe to the failures of conversation and friend operations. Each failure carries the [`ErrorContext`] of
+    /// the operation, whose operation id is also recorded in the logs of the stores
+    pub fn operation_failure_subscribe(&self) -> BoxStream<'static, OperationFailure> {
+        let mut rx = self.failure_tx.subscribe();
+        let stream = async_stream::stream! {
+            loop {
+                match rx.recv().await {
+                    Ok(failure) => yield failure,
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    Err(_) => {}
+                };
+            }
+        };
+        stream.boxed()
+    }
+
+    /// Run the operation in a span carrying its context, so the logs of the stores can be correlated with the
+    /// operation, and publish any failure along with the context to [`WarpIpfs::operation_failure_subscribe`].
+    /// The error itself is returned as is
+    async fn with_context<T>(
+        &self,
+        context: ErrorContext,
+        operation: impl Future<Output = Result<T, Error>>,
+    ) -> Result<T, Error> {
+        let span = tracing::info_span!(
+            "operation",
+            operation = context.operation(),
+            operation_id = %context.operation_id()
+        );
+
+        let result = operation.instrument(span).await;
+
+        if let Err(error) = &result {
+            tracing::warn!(%context, %error, "operation failed");
+            let _ = self.failure_tx.send(OperationFailure::new(context, error));
+        }
+
+        result
+    }
+
+    /// Time taken by each subsystem to be ready since the start of the initialization
+    pub fn startup_report(&self) -> BTreeMap<Subsystem, Duration> {
+        self.inner.readiness.report()
+    }
+
+    /// Open the local store for `namespace`, used for data holding decrypted message content (eg drafts,
+    /// search indexes or caches) so that it is encrypted at rest with a key kept in tesseract
+    pub fn encrypted_store(&self, namespace: &str) -> Result<EncryptedStore, Error> {
+        let ipfs = self.ipfs()?;
+        EncryptedStore::new(&ipfs, &self.tesseract, namespace)
+    }
+
+    /// Export the root document, returning its cid used to open the account as an
+    /// [`AuditReplica`](audit::AuditReplica)
+    pub async fn export_audit_root(&self) -> Result<Cid, Error> {
+        let store = self.identity_store(true).await?;
+        let _ = store.export_root_document().await;
+        store.root_document().export_root_cid().await
+    }
+
+    /// Last roster of the organization set in [`StoreSetting::organization`](config::StoreSetting::organization)
+    /// that was received from its directory
+    pub async fn organization_roster(&self) -> Result<Option<OrganizationRoster>, Error> {
+        let store = self.identity_store(true).await?;
+        Ok(store.organization_roster().await)
+    }
+
+    /// Provision `did` as a member of the organization, returning the certificate issued by the directory.
+    /// Only available to administrators of the organization
+    pub async fn enroll_organization_member(
+        &self,
+        did: &DID,
+    ) -> Result<MembershipCertificate, Error> {
+        let store = self.identity_store(true).await?;
+        store.enroll_organization_member(did).await
+    }
+
+    /// Revoke the membership of `did`. Members remove and block the identity once they receive the updated roster.
+    /// Only available to administrators of the organization
+    pub async fn revoke_organization_member(&self, did: &DID) -> Result<(), Error> {
+        let store = self.identity_store(true).await?;
+        store.revoke_organization_member(did).await
+    }
+
+    /// Selective sync settings of this device
+    pub fn selective_sync(&self) -> Result<SelectiveSyncSettings, Error> {
+        self.inner
+            .components
+            .read()
+            .as_ref()
+            .map(|com| com.selective_sync.settings())
+            .ok_or(Error::ConstellationExtensionUnavailable)
+    }
+
+    /// Exclude the directory at `path` from being kept on this device, or include it again.
+    /// See [`FileStore::set_directory_excluded`]
+    pub async fn set_directory_excluded(&self, path: &str, excluded: bool) -> Result<(), Error> {
+        self.file_store()?
+            .set_directory_excluded(path, excluded)
+            .await
+    }
+
+    /// Fetch and pin the content at `path`, which may be a placeholder of an excluded directory
+    pub async fn hydrate(&self, path: &str) -> Result<(), Error> {
+        self.file_store()?.hydrate(path).await
+    }
+
+    /// Returns true if the content of the file at `path` is kept on this device
+    pub async fn is_hydrated(&self, path: &str) -> Result<bool, Error> {
+        self.file_store()?.is_hydrated(path).await
+    }
+
+    /// Report of the files whose content is in cold storage, and of the space that the
+    /// [`ColdStoragePolicy`](config::ColdStoragePolicy) would reclaim if applied now
+    pub async fn cold_storage_report(&self) -> Result<ColdStorageReport, Error> {
+        self.file_store()?.cold_storage_report().await
+    }
+
+    /// Apply the [`ColdStoragePolicy`](config::ColdStoragePolicy) without waiting for its next interval
+    pub async fn apply_cold_storage(&self) -> Result<ColdStorageReport, Error> {
+        self.file_store()?.apply_cold_storage().await
+    }
+
+    /// Keep the content of the file at `path` on this device regardless of the cold storage policy
+    pub async fn set_pinned_locally(&self, path: &str, pinned: bool) -> Result<(), Error> {
+        self.file_store()?.set_pinned_locally(path, pinned).await
+    }
+
+    /// Emoji packs that can be used for custom emoji reactions
+    pub fn emoji_packs(&self) -> Result<Vec<EmojiPack>, Error> {
+        Ok(self.emoji_pack_store()?.list())
+    }
+
+    /// Install an emoji pack, replacing any pack with the same id
+    pub async fn install_emoji_pack(&self, pack: EmojiPack) -> Result<(), Error> {
+        self.emoji_pack_store()?.install(pack).await
+    }
+
+    pub async fn remove_emoji_pack(&self, pack_id: Uuid) -> Result<(), Error> {
+        self.emoji_pack_store()?.remove(pack_id).await
+    }
+
+    fn emoji_pack_store(&self) -> Result<EmojiPacks, Error> {
+        self.inner
+            .components
+            .read()
+            .as_ref()
+            .map(|com| com.emoji_packs.clone())
+            .ok_or(Error::RayGunExtensionUnavailable)
+    }
+
+    /// Certificates of the devices of the identity
+    pub async fn devices(&self) -> Result<Vec<DeviceCertificate>, Error> {
+        self.identity_store(true).await?.own_devices().await
+    }
+
+    /// Merge the changes made on the other devices of the identity, returning true if any change was merged. This is
+    /// otherwise done periodically when shuttle is used for discovery
+    pub async fn synchronize_devices(&self) -> Result<bool, Error> {
+        self.identity_store(true)
+            .await?
+            .synchronize_root_document()
+            .await
+    }
+
+    /// Certificate of this device
+    pub async fn current_device(&self) -> Result<DeviceCertificate, Error> {
+        let store = self.identity_store(true).await?;
+        Ok(store.device_key().certificate().clone())
+    }
+
+    /// Revoke a device of the identity and rotate our key in group conversations, so the device is unable to read
+    /// the messages we send afterwards
+    pub async fn revoke_device(&self, device: &DID) -> Result<(), Error> {
+        self.identity_store(true)
+            .await?
+            .revoke_device(device)
+            .await?;
+        self.messaging_store()?.rotate_group_keys().await;
+        Ok(())
+    }
+
+    /// Key of this device to be linked to an existing identity. The returned DID is passed to
+    /// [`WarpIpfs::link_device`] on a device of the identity, and the code it returns to
+    /// [`WarpIpfs::complete_device_link`] on this device
+    pub fn device_link_request(&self) -> Result<DID, Error> {
+        if !self.tesseract.is_unlock() {
+            return Err(Error::TesseractLocked);
+        }
+
+        if self.tesseract.exist("keypair") {
+            return Err(Error::IdentityExist);
+        }
+
+        let keypair = match self.tesseract.exist("device_link_keypair") {
+            true => {
+                let encoded = Zeroizing::new(self.tesseract.retrieve("device_link_keypair")?);
+                let bytes = Zeroizing::new(bs58::decode(encoded.as_str()).into_vec()?);
+                Keypair::from_protobuf_encoding(&bytes).map_err(anyhow::Error::from)?
+            }
+            false => {
+                let keypair = Keypair::generate_ed25519();
+                let bytes = Zeroizing::new(
+                    keypair
+                        .to_protobuf_encoding()
+                        .map_err(anyhow::Error::from)?,
+                );
+                self.tesseract
+                    .set("device_link_keypair", &bs58::encode(&bytes).into_string())?;
+                keypair
+            }
+        };
+
+        keypair.to_did()
+    }
+
+    /// Authorize the device to use the identity, returning the code to complete the link on the device.
+    ///
+    /// Note: The code only carries the certificate issued for the key of the device. The device is not given the key
+    ///       of the identity, which it derives from the passphrase passed to [`WarpIpfs::complete_device_link`]
+    pub async fn link_device(&self, device: &DID) -> Result<String, Error> {
+        let link = self.identity_store(true).await?.link_device(device).await?;
+        link.encode()
+    }
+
+    /// Complete the link of this device using the code returned by [`WarpIpfs::link_device`] and the passphrase of
+    /// the identity, loading the identity from shuttle
+    pub async fn complete_device_link(
+        &self,
+        code: &str,
+        passphrase: &str,
+    ) -> Result<Identity, Error> {
+        let _g = self.inner.identity_guard.lock().await;
+
+        if self.inner.components.read().is_some() {
+            return Err(Error::IdentityExist);
+        }
+
+        if !self.tesseract.is_unlock() {
+            return Err(Error::TesseractLocked);
+        }
+
+        let link = DeviceLink::decode(code)?;
+
+        let encoded = Zeroizing::new(self.tesseract.retrieve("device_link_keypair")?);
+        let bytes = Zeroizing::new(bs58::decode(encoded.as_str()).into_vec()?);
+        let device_kp = Keypair::from_protobuf_encoding(&bytes).map_err(anyhow::Error::from)?;
+
+        let keypair = warp::crypto::keypair::did_from_mnemonic(passphrase, None)?;
+        let bytes = Zeroizing::new(keypair.private_key_bytes());
+        let internal_keypair =
+            Keypair::ed25519_from_bytes(bytes).map_err(|_| Error::PrivateKeyInvalid)?;
+
+        link.verify(&internal_keypair.to_did()?, &device_kp)?;
+
+        // the device key is stored once ipfs is initialized with the identity
+        self.tesseract.set(
+            "device_link_certificate",
+            &serde_json::to_string(&link.certificate)?,
+        )?;
+
+        warp::crypto::keypair::mnemonic_into_tesseract(
+            &self.tesseract,
+            passphrase,
+            None,
+            self.inner.config.save_phrase(),
+            false,
+        )?;
+
+        self.init_ipfs(internal_keypair).await?;
+
+        let mut store = self.identity_store(false).await?;
+
+        store.import_identity_remote_resolve().await
+    }
+
+    /// Exclude the conversation from being loaded on this device until it is accessed, or include it again
+    pub async fn set_conversation_excluded(
+        &self,
+        conversation_id: Uuid,
+        excluded: bool,
+    ) -> Result<(), Error> {
+        self.messaging_store()?
+            .set_conversation_excluded(conversation_id, excluded)
+            .await
+    }
+
+    /// Abort the background tasks of the instance, drop the stores and stop the ipfs node.
+    /// The instance is unusable afterwards.
+    pub async fn shutdown(&self) {
+        self.inner.tasks.shutdown().await;
+
+        let components = self.inner.components.write().take();
+
+        if let Some(components) = components {
+            let ipfs = components.ipfs.clone();
+            drop(components);
+            ipfs.exit_daemon().await;
+        }
+
+        tracing::info!("instance has been shutdown");
+    }
 }
 
 impl Extension for WarpIpfs {
@@ -779,7 +1293,10 @@ impl MultiPass for WarpIpfs {
             passphrase.is_some()
         );
 
-        if self.inner.components.read().is_some() {
+        // The stores would already be initialized if a previous attempt to create the identity was rejected
+        let initialized = self.inner.components.read().is_some();
+
+        if initialized && self.identity_store(true).await.is_ok() {
             tracing::info!("Store is initialized with existing identity");
             return Err(Error::IdentityExist);
         }
@@ -797,12 +1314,26 @@ impl MultiPass for WarpIpfs {
             }
         }
 
-        let (phrase, can_include) = match passphrase {
-            Some(phrase) => {
+        let pending_phrase = self.inner.pending_phrase.read().clone();
+
+        let (phrase, can_include) = match (passphrase, initialized) {
+            (Some(phrase), true) => {
+                // The keypair of the previous attempt is reused so the phrase has to match it
+                let did = warp::crypto::keypair::did_from_mnemonic(phrase, None)?;
+                if did != self.identity_store(false).await?.did_key() {
+                    return Err(Error::IdentityExist);
+                }
+                (phrase.to_string(), false)
+            }
+            (Some(phrase), false) => {
                 tracing::info!("Passphrase was supplied");
                 (phrase.to_string(), false)
             }
-            None => (
+            (None, true) => match pending_phrase {
+                Some(phrase) => (phrase.to_string(), true),
+                None => return Err(Error::IdentityExist),
+            },
+            (None, false) => (
                 warp::crypto::keypair::generate_mnemonic_phrase(PhraseType::Standard).into_phrase(),
                 true,
             ),
@@ -820,14 +1351,29 @@ impl MultiPass for WarpIpfs {
             )?;
         }
 
-        tracing::info!("Initializing stores");
-        self.initialize_store(true).await?;
-        tracing::info!("Stores initialized. Creating identity");
-        let identity = self
+        if !initialized {
+            tracing::info!("Initializing stores");
+            self.initialize_store(true).await?;
+            tracing::info!("Stores initialized");
+        }
+
+        tracing::info!("Creating identity");
+        let identity = match self
             .identity_store(false)
             .await?
             .create_identity(username)
-            .await?;
+            .await
+        {
+            Ok(identity) => identity,
+            Err(Error::UsernameTaken) => {
+                if can_include {
+                    *self.inner.pending_phrase.write() = Some(Zeroizing::new(phrase));
+                }
+                return Err(Error::UsernameTaken);
+            }
+            Err(e) => return Err(e),
+        };
+        self.inner.pending_phrase.write().take();
         tracing::info!("Identity with {} has been created", identity.did_key());
         let profile = IdentityProfile::new(identity, can_include.then_some(phrase));
         Ok(profile)
@@ -841,6 +1387,24 @@ impl MultiPass for WarpIpfs {
 
         store.lookup(id)
     }
+
+    async fn add_from_share_code(&mut self, code: &str) -> Result<DID, Error> {
+        let mut store = self.identity_store(true).await?;
+        store.add_from_share_code(code).await
+    }
+
+    async fn relationship_history(
+        &self,
+        did: &DID,
+    ) -> Result<Vec<RelationshipHistoryEntry>, Error> {
+        let store = self.identity_store(true).await?;
+        store.relationship_history(did).await
+    }
+
+    async fn watch_identity(&self, did: &DID) -> Result<IdentityChangeStream, Error> {
+        let store = self.identity_store(true).await?;
+        store.watch_identity(did).await
+    }
 }
 
 #[async_trait::async_trait]
@@ -882,6 +1446,10 @@ impl LocalIdentity for WarpIpfs {
                     });
                 }
 
+                if !identity.username.eq_ignore_ascii_case(&username) {
+                    store.is_username_available(&username).await?;
+                }
+
                 identity.username = username;
                 return store.identity_update(identity).await;
             }
@@ -1158,6 +1726,8 @@ impl LocalIdentity for WarpIpfs {
             }
         }
 
+        store::document::image_dag::store_image_variants(&ipfs, cid, Some(MAX_IMAGE_SIZE)).await;
+
         if let Some(cid) = old_cid {
             if let Err(e) = store.delete_photo(cid).await {
                 tracing::error!("Error deleting picture: {e}");
@@ -1167,14 +1737,51 @@ impl LocalIdentity for WarpIpfs {
         store.identity_update(identity).await
     }
 
-    fn tesseract(&self) -> Tesseract {
-        self.tesseract.clone()
+    async fn set_app_data(&mut self, namespace: &str, data: Bytes) -> Result<(), Error> {
+        let mut store = self.identity_store(true).await?;
+        store.set_app_data(namespace, data).await
     }
-}
 
-#[async_trait::async_trait]
-impl MultiPassImportExport for WarpIpfs {
-    async fn import_identity<'a>(
+    async fn get_app_data(&self, namespace: &str) -> Result<Bytes, Error> {
+        let store = self.identity_store(true).await?;
+        store.get_app_data(namespace).await
+    }
+
+    async fn share_code(&self) -> Result<String, Error> {
+        let store = self.identity_store(true).await?;
+        store.share_code().await
+    }
+
+    async fn sign_detached(
+        &self,
+        data: &[u8],
+        trusted_comment: Option<&str>,
+    ) -> Result<String, Error> {
+        let store = self.identity_store(true).await?;
+        store.sign_detached(data, trusted_comment)
+    }
+
+    async fn notification_preferences(&self) -> Result<NotificationPreferences, Error> {
+        let store = self.identity_store(true).await?;
+        store.notification_preferences().await
+    }
+
+    async fn set_notification_preferences(
+        &mut self,
+        preferences: NotificationPreferences,
+    ) -> Result<(), Error> {
+        let mut store = self.identity_store(true).await?;
+        store.set_notification_preferences(preferences).await
+    }
+
+    fn tesseract(&self) -> Tesseract {
+        self.tesseract.clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl MultiPassImportExport for WarpIpfs {
+    async fn import_identity<'a>(
         &mut self,
         option: IdentityImportOption<'a>,
     ) -> Result<Identity, Error> {
@@ -1302,22 +1909,60 @@ impl MultiPassImportExport for WarpIpfs {
 impl Friends for WarpIpfs {
     async fn send_request(&mut self, pubkey: &DID) -> Result<(), Error> {
         let mut store = self.identity_store(true).await?;
-        store.send_request(pubkey).await
+        self.with_context(
+            ErrorContext::new("send_request").with_did(pubkey),
+            store.send_request(pubkey),
+        )
+        .await
+    }
+
+    async fn send_request_with_message(
+        &mut self,
+        pubkey: &DID,
+        message: &str,
+    ) -> Result<(), Error> {
+        let mut store = self.identity_store(true).await?;
+        self.with_context(
+            ErrorContext::new("send_request_with_message").with_did(pubkey),
+            store.send_request_with_message(pubkey, message),
+        )
+        .await
     }
 
     async fn accept_request(&mut self, pubkey: &DID) -> Result<(), Error> {
         let mut store = self.identity_store(true).await?;
-        store.accept_request(pubkey).await
+        self.with_context(
+            ErrorContext::new("accept_request").with_did(pubkey),
+            store.accept_request(pubkey),
+        )
+        .await
     }
 
     async fn deny_request(&mut self, pubkey: &DID) -> Result<(), Error> {
         let mut store = self.identity_store(true).await?;
-        store.reject_request(pubkey).await
+        self.with_context(
+            ErrorContext::new("deny_request").with_did(pubkey),
+            store.reject_request(pubkey),
+        )
+        .await
     }
 
     async fn close_request(&mut self, pubkey: &DID) -> Result<(), Error> {
         let mut store = self.identity_store(true).await?;
-        store.close_request(pubkey).await
+        self.with_context(
+            ErrorContext::new("close_request").with_did(pubkey),
+            store.close_request(pubkey),
+        )
+        .await
+    }
+
+    async fn resend_request(&mut self, pubkey: &DID) -> Result<(), Error> {
+        let mut store = self.identity_store(true).await?;
+        self.with_context(
+            ErrorContext::new("resend_request").with_did(pubkey),
+            store.resend_request(pubkey),
+        )
+        .await
     }
 
     async fn list_incoming_request(&self) -> Result<Vec<FriendRequest>, Error> {
@@ -1374,6 +2019,40 @@ impl Friends for WarpIpfs {
         let store = self.identity_store(true).await?;
         store.is_friend(pubkey).await
     }
+
+    async fn friends_privacy(&self) -> Result<FriendsPrivacy, Error> {
+        let store = self.identity_store(true).await?;
+        store.friends_privacy().await
+    }
+
+    async fn set_friends_privacy(&mut self, privacy: FriendsPrivacy) -> Result<(), Error> {
+        let mut store = self.identity_store(true).await?;
+        store.set_friends_privacy(privacy).await
+    }
+
+    async fn friend_count(&self, pubkey: &DID) -> Result<Option<usize>, Error> {
+        let store = self.identity_store(true).await?;
+        store.friend_count(pubkey).await
+    }
+
+    async fn mutual_friends(&self, pubkey: &DID) -> Result<Vec<DID>, Error> {
+        let store = self.identity_store(true).await?;
+        store.mutual_friends(pubkey).await
+    }
+
+    async fn export_contacts(&self, format: ContactFormat) -> Result<Vec<u8>, Error> {
+        let store = self.identity_store(true).await?;
+        store.export_contacts(format).await
+    }
+
+    async fn import_contacts(
+        &mut self,
+        format: ContactFormat,
+        data: &[u8],
+    ) -> Result<Vec<DID>, Error> {
+        let mut store = self.identity_store(true).await?;
+        store.import_contacts(format, data).await
+    }
 }
 
 #[async_trait::async_trait]
@@ -1382,6 +2061,14 @@ impl MultiPassEvent for WarpIpfs {
         let store = self.identity_store(true).await?;
         store.subscribe().await
     }
+
+    async fn multipass_subscribe_with(
+        &mut self,
+        options: SubscriptionOptions,
+    ) -> Result<MultiPassEventStream, Error> {
+        let store = self.identity_store(true).await?;
+        store.subscribe_with(options).await
+    }
 }
 
 #[async_trait::async_trait]
@@ -1396,6 +2083,24 @@ impl IdentityInformation for WarpIpfs {
         store.identity_banner(did).await
     }
 
+    async fn identity_picture_sized(
+        &self,
+        did: &DID,
+        dimension: u32,
+    ) -> Result<IdentityImage, Error> {
+        let store = self.identity_store(true).await?;
+        store.identity_picture_sized(did, dimension).await
+    }
+
+    async fn identity_banner_sized(
+        &self,
+        did: &DID,
+        dimension: u32,
+    ) -> Result<IdentityImage, Error> {
+        let store = self.identity_store(true).await?;
+        store.identity_banner_sized(did, dimension).await
+    }
+
     async fn identity_status(&self, did: &DID) -> Result<identity::IdentityStatus, Error> {
         let store = self.identity_store(true).await?;
         store.identity_status(did).await
@@ -1406,6 +2111,29 @@ impl IdentityInformation for WarpIpfs {
         store.set_identity_status(status).await
     }
 
+    async fn identity_activity(&self, did: &DID) -> Result<Option<IdentityActivity>, Error> {
+        let store = self.identity_store(true).await?;
+        store.identity_activity(did).await
+    }
+
+    async fn set_identity_activity(
+        &mut self,
+        activity: Option<IdentityActivity>,
+    ) -> Result<(), Error> {
+        let mut store = self.identity_store(true).await?;
+        store.set_identity_activity(activity).await
+    }
+
+    async fn identity_profile_fields(&self, did: &DID) -> Result<ProfileFields, Error> {
+        let store = self.identity_store(true).await?;
+        store.identity_profile_fields(did).await
+    }
+
+    async fn set_identity_profile_fields(&mut self, fields: ProfileFields) -> Result<(), Error> {
+        let mut store = self.identity_store(true).await?;
+        store.set_identity_profile_fields(fields).await
+    }
+
     async fn identity_platform(&self, did: &DID) -> Result<identity::Platform, Error> {
         let store = self.identity_store(true).await?;
         store.identity_platform(did).await
@@ -1434,7 +2162,11 @@ impl IdentityInformation for WarpIpfs {
 #[async_trait::async_trait]
 impl RayGun for WarpIpfs {
     async fn create_conversation(&mut self, did_key: &DID) -> Result<Conversation, Error> {
-        self.messaging_store()?.create_conversation(did_key).await
+        self.with_context(
+            ErrorContext::new("create_conversation").with_did(did_key),
+            self.messaging_store()?.create_conversation(did_key),
+        )
+        .await
     }
 
     async fn create_group_conversation<P: Into<GroupPermissionOpt> + Send + Sync>(
@@ -1474,6 +2206,12 @@ impl RayGun for WarpIpfs {
             .await
     }
 
+    async fn conversation_stats(&self, conversation_id: Uuid) -> Result<ConversationStats, Error> {
+        self.messaging_store()?
+            .conversation_stats(conversation_id)
+            .await
+    }
+
     async fn get_message(&self, conversation_id: Uuid, message_id: Uuid) -> Result<Message, Error> {
         self.messaging_store()?
             .get_message(conversation_id, message_id)
@@ -1500,6 +2238,16 @@ impl RayGun for WarpIpfs {
             .await
     }
 
+    async fn search_messages(
+        &self,
+        conversation_id: Option<Uuid>,
+        query: SearchQuery,
+    ) -> Result<SearchResultStream, Error> {
+        self.messaging_store()?
+            .search_messages(conversation_id, query)
+            .await
+    }
+
     async fn message_status(
         &self,
         conversation_id: Uuid,
@@ -1510,6 +2258,15 @@ impl RayGun for WarpIpfs {
             .await
     }
 
+    async fn mark_read(&mut self, conversation_id: Uuid, message_id: Uuid) -> Result<(), Error> {
+        self.with_context(
+            ErrorContext::new("mark_read").with_conversation(conversation_id),
+            self.messaging_store()?
+                .mark_read(conversation_id, message_id),
+        )
+        .await
+    }
+
     async fn get_messages(
         &self,
         conversation_id: Uuid,
@@ -1521,9 +2278,11 @@ impl RayGun for WarpIpfs {
     }
 
     async fn send(&mut self, conversation_id: Uuid, value: Vec<String>) -> Result<Uuid, Error> {
-        self.messaging_store()?
-            .send_message(conversation_id, value)
-            .await
+        self.with_context(
+            ErrorContext::new("send").with_conversation(conversation_id),
+            self.messaging_store()?.send_message(conversation_id, value),
+        )
+        .await
     }
 
     async fn edit(
@@ -1532,9 +2291,12 @@ impl RayGun for WarpIpfs {
         message_id: Uuid,
         value: Vec<String>,
     ) -> Result<(), Error> {
-        self.messaging_store()?
-            .edit_message(conversation_id, message_id, value)
-            .await
+        self.with_context(
+            ErrorContext::new("edit").with_conversation(conversation_id),
+            self.messaging_store()?
+                .edit_message(conversation_id, message_id, value),
+        )
+        .await
     }
 
     async fn delete(
@@ -1543,10 +2305,14 @@ impl RayGun for WarpIpfs {
         message_id: Option<Uuid>,
     ) -> Result<(), Error> {
         let store = self.messaging_store()?;
-        match message_id {
-            Some(id) => store.delete_message(conversation_id, id).await,
-            None => store.delete_conversation(conversation_id).await.map(|_| ()),
-        }
+        let context = ErrorContext::new("delete").with_conversation(conversation_id);
+        self.with_context(context, async {
+            match message_id {
+                Some(id) => store.delete_message(conversation_id, id).await,
+                None => store.delete_conversation(conversation_id).await.map(|_| ()),
+            }
+        })
+        .await
     }
 
     async fn react(
@@ -1556,9 +2322,15 @@ impl RayGun for WarpIpfs {
         state: ReactionState,
         emoji: String,
     ) -> Result<(), Error> {
-        self.messaging_store()?
-            .react(conversation_id, message_id, state, emoji)
-            .await
+        if state == ReactionState::Add {
+            self.emoji_pack_store()?.validate(&emoji)?;
+        }
+        self.with_context(
+            ErrorContext::new("react").with_conversation(conversation_id),
+            self.messaging_store()?
+                .react(conversation_id, message_id, state, emoji),
+        )
+        .await
     }
 
     async fn pin(
@@ -1567,9 +2339,12 @@ impl RayGun for WarpIpfs {
         message_id: Uuid,
         state: PinState,
     ) -> Result<(), Error> {
-        self.messaging_store()?
-            .pin_message(conversation_id, message_id, state)
-            .await
+        self.with_context(
+            ErrorContext::new("pin").with_conversation(conversation_id),
+            self.messaging_store()?
+                .pin_message(conversation_id, message_id, state),
+        )
+        .await
     }
 
     async fn reply(
@@ -1578,9 +2353,12 @@ impl RayGun for WarpIpfs {
         message_id: Uuid,
         value: Vec<String>,
     ) -> Result<Uuid, Error> {
-        self.messaging_store()?
-            .reply(conversation_id, message_id, value)
-            .await
+        self.with_context(
+            ErrorContext::new("reply").with_conversation(conversation_id),
+            self.messaging_store()?
+                .reply(conversation_id, message_id, value),
+        )
+        .await
     }
 
     async fn embeds(&mut self, _: Uuid, _: Uuid, _: EmbedState) -> Result<(), Error> {
@@ -1652,6 +2430,33 @@ impl RayGun for WarpIpfs {
             .unarchived_conversation(conversation_id)
             .await
     }
+
+    async fn merge_conversations(&mut self, primary: Uuid, duplicate: Uuid) -> Result<(), Error> {
+        self.messaging_store()?
+            .merge_conversations(primary, duplicate)
+            .await
+    }
+
+    async fn replay_events(
+        &self,
+        conversation_id: Uuid,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<MessageEventStream, Error> {
+        self.messaging_store()?
+            .replay_events(conversation_id, since)
+            .await
+    }
+
+    async fn register_message_processor(
+        &mut self,
+        processor: Arc<dyn MessageProcessor>,
+    ) -> Result<(), Error> {
+        self.inner.processors.register(processor)
+    }
+
+    async fn unregister_message_processor(&mut self, name: &str) -> Result<(), Error> {
+        self.inner.processors.unregister(name)
+    }
 }
 
 #[async_trait::async_trait]
@@ -1663,9 +2468,38 @@ impl RayGunAttachment for WarpIpfs {
         locations: Vec<Location>,
         message: Vec<String>,
     ) -> Result<(Uuid, AttachmentEventStream), Error> {
-        self.messaging_store()?
-            .attach(conversation_id, message_id, locations, message)
-            .await
+        self.with_context(
+            ErrorContext::new("attach").with_conversation(conversation_id),
+            self.messaging_store()?.attach(
+                conversation_id,
+                message_id,
+                locations,
+                message,
+                AttachmentOptions::default(),
+            ),
+        )
+        .await
+    }
+
+    async fn attach_with_options(
+        &mut self,
+        conversation_id: Uuid,
+        message_id: Option<Uuid>,
+        locations: Vec<Location>,
+        message: Vec<String>,
+        options: AttachmentOptions,
+    ) -> Result<(Uuid, AttachmentEventStream), Error> {
+        self.with_context(
+            ErrorContext::new("attach").with_conversation(conversation_id),
+            self.messaging_store()?.attach(
+                conversation_id,
+                message_id,
+                locations,
+                message,
+                options,
+            ),
+        )
+        .await
     }
 
     async fn download(
@@ -1690,6 +2524,18 @@ impl RayGunAttachment for WarpIpfs {
             .download_stream(conversation_id, message_id, file)
             .await
     }
+
+    async fn save_attachment(
+        &self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        file: &str,
+        path: PathBuf,
+    ) -> Result<ConstellationProgressStream, Error> {
+        self.messaging_store()?
+            .save_attachment(conversation_id, message_id, file, path)
+            .await
+    }
 }
 
 #[async_trait::async_trait]
@@ -1965,6 +2811,16 @@ impl RayGunCommunity for WarpIpfs {
             .edit_community_channel_description(community_id, channel_id, description)
             .await
     }
+    async fn edit_community_channel_slow_mode(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        slow_mode: u64,
+    ) -> Result<(), Error> {
+        self.messaging_store()?
+            .edit_community_channel_slow_mode(community_id, channel_id, slow_mode)
+            .await
+    }
     async fn grant_community_channel_permission(
         &mut self,
         community_id: Uuid,
@@ -2128,6 +2984,9 @@ impl RayGunCommunity for WarpIpfs {
         state: ReactionState,
         emoji: String,
     ) -> Result<(), Error> {
+        if state == ReactionState::Add {
+            self.emoji_pack_store()?.validate(&emoji)?;
+        }
         self.messaging_store()?
             .react_to_community_channel_message(community_id, channel_id, message_id, state, emoji)
             .await
@@ -2242,6 +3101,13 @@ impl RayGunStream for WarpIpfs {
         Ok(rx)
     }
 
+    async fn raygun_subscribe_with(
+        &mut self,
+        options: SubscriptionOptions,
+    ) -> Result<RayGunEventStream, Error> {
+        self.raygun_tx.subscribe_with(options).await
+    }
+
     async fn get_conversation_stream(
         &mut self,
         conversation_id: Uuid,
@@ -2250,6 +3116,18 @@ impl RayGunStream for WarpIpfs {
         let stream = store.get_conversation_stream(conversation_id).await?;
         Ok(stream.boxed())
     }
+
+    async fn get_conversation_stream_with(
+        &mut self,
+        conversation_id: Uuid,
+        options: SubscriptionOptions,
+    ) -> Result<MessageEventStream, Error> {
+        let store = self.messaging_store()?;
+        let stream = store
+            .get_conversation_stream_with(conversation_id, options)
+            .await?;
+        Ok(stream.boxed())
+    }
 }
 
 #[async_trait::async_trait]
@@ -2286,6 +3164,32 @@ impl RayGunConversationInformation for WarpIpfs {
             .set_description(conversation_id, description)
             .await
     }
+
+    async fn set_history_visibility(
+        &mut self,
+        conversation_id: Uuid,
+        visibility: HistoryVisibility,
+    ) -> Result<(), Error> {
+        self.messaging_store()?
+            .set_history_visibility(conversation_id, visibility)
+            .await
+    }
+
+    async fn set_slow_mode(&mut self, conversation_id: Uuid, slow_mode: u64) -> Result<(), Error> {
+        self.messaging_store()?
+            .set_slow_mode(conversation_id, slow_mode)
+            .await
+    }
+
+    async fn set_retention_policy(
+        &mut self,
+        conversation_id: Uuid,
+        policy: Option<RetentionPolicy>,
+    ) -> Result<(), Error> {
+        self.messaging_store()?
+            .set_retention_policy(conversation_id, policy)
+            .await
+    }
 }
 
 #[async_trait::async_trait]
@@ -2338,6 +3242,32 @@ impl Constellation for WarpIpfs {
             .await
     }
 
+    /// Used to start an upload that can be resumed if interrupted
+    async fn put_resumable(
+        &mut self,
+        name: &str,
+        total_size: usize,
+    ) -> Result<Box<dyn UploadSession>, Error> {
+        let session = self.file_store()?.put_resumable(name, total_size).await?;
+        Ok(Box::new(session))
+    }
+
+    /// Used to reopen an upload that was not completed
+    async fn upload_session(&self, id: Uuid) -> Result<Box<dyn UploadSession>, Error> {
+        let session = self.file_store()?.upload_session(id).await?;
+        Ok(Box::new(session))
+    }
+
+    /// Used to list the uploads that were not completed
+    async fn upload_sessions(&self) -> Result<Vec<Uuid>, Error> {
+        Ok(self.file_store()?.upload_sessions().await)
+    }
+
+    /// Used to discard an upload that was not completed
+    async fn cancel_upload(&mut self, id: Uuid) -> Result<(), Error> {
+        self.file_store()?.cancel_upload(id).await
+    }
+
     /// Used to download data from the filesystem using a stream
     async fn get_stream(
         &self,
@@ -2363,6 +3293,10 @@ impl Constellation for WarpIpfs {
         self.file_store()?.sync_ref(path).await
     }
 
+    async fn verify(&self, refetch: bool) -> Result<IntegrityReport, Error> {
+        self.file_store()?.verify(refetch).await
+    }
+
     fn set_path(&mut self, path: PathBuf) {
         if let Ok(mut store) = self.file_store() {
             store.set_path(path)
@@ -2382,6 +3316,509 @@ impl ConstellationEvent for WarpIpfs {
         let rx = self.constellation_tx.subscribe().await?;
         Ok(ConstellationEventStream(rx))
     }
+
+    async fn constellation_subscribe_with(
+        &mut self,
+        options: SubscriptionOptions,
+    ) -> Result<ConstellationEventStream, Error> {
+        let rx = self.constellation_tx.subscribe_with(options).await?;
+        Ok(ConstellationEventStream(rx))
+    }
+}
+
+#[async_trait::async_trait]
+impl PeerRpc for WarpIpfs {
+    async fn rpc_request(
+        &self,
+        did: &DID,
+        namespace: &str,
+        payload: Bytes,
+    ) -> Result<Bytes, Error> {
+        // ensure that the identity is created and the store is unlocked
+        self.identity_store(true).await?;
+        self.rpc_store()?.request(did, namespace, payload).await
+    }
+
+    async fn rpc_subscribe(&self, namespace: &str) -> Result<RpcRequestStream, Error> {
+        self.identity_store(true).await?;
+        self.rpc_store()?.subscribe(namespace)
+    }
+}
+
+#[async_trait::async_trait]
+impl PeerConnections for WarpIpfs {
+    async fn connection_subscribe(&self) -> Result<ConnectionEventStream, Error> {
+        let mut rx = self.connection_tx.subscribe();
+        let stream = async_stream::stream! {
+            loop {
+                match rx.recv().await {
+                    Ok(event) => yield event,
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    Err(_) => {}
+                };
+            }
+        };
+        Ok(stream.boxed())
+    }
+
+    async fn active_relays(&self) -> Result<Vec<RelayInfo>, Error> {
+        let relays = self
+            .inner
+            .components
+            .read()
+            .as_ref()
+            .ok_or(Error::MultiPassExtensionUnavailable)?
+            .relay_selection
+            .as_ref()
+            .map(|selection| selection.active_relays())
+            .unwrap_or_default();
+        Ok(relays)
+    }
+}
+
+#[async_trait::async_trait]
+impl AccountDoctor for WarpIpfs {
+    async fn diagnose(&self) -> Result<DoctorReport, Error> {
+        let ipfs = self.ipfs().ok();
+
+        let mut report = DoctorReport::default();
+        report.push(doctor::check_tesseract(&self.tesseract, ipfs.as_ref()));
+
+        let (ipfs, identity) = match (ipfs, self.identity_store(true).await) {
+            (Some(ipfs), Ok(identity)) => (ipfs, identity),
+            (_, result) => {
+                let detail = match result {
+                    Err(e) => format!("account is not loaded: {e}"),
+                    Ok(_) => "ipfs node is not running".into(),
+                };
+                report.push(DoctorCheck::failed(
+                    DoctorCheckKind::RootDocument,
+                    detail,
+                    DoctorRepair::Manual(
+                        "unlock the keystore and create or import an identity".into(),
+                    ),
+                ));
+                return Ok(report);
+            }
+        };
+
+        let (check, document) = doctor::check_root_document(&identity).await;
+        report.push(check);
+
+        if let Some(document) = document {
+            report.push(doctor::check_pins(&ipfs, &identity, &document).await);
+        }
+
+        report.push(doctor::check_shuttle(&identity).await);
+        report.push(doctor::check_connectivity(&ipfs, &identity).await);
+
+        if let Ok(messaging) = self.messaging_store() {
+            report.push(doctor::check_conversations(&messaging).await);
+        }
+
+        Ok(report)
+    }
+
+    async fn repair(&self) -> Result<DoctorReport, Error> {
+        let report = self.diagnose().await?;
+        if report.is_healthy() {
+            return Ok(report);
+        }
+
+        let ipfs = self.ipfs()?;
+        let identity = self.identity_store(true).await?;
+
+        for repair in report.automatic_repairs() {
+            let result = match repair {
+                DoctorRepair::RepairConversations => {
+                    self.repair_conversations().await.map(|_| true)
+                }
+                ref repair => doctor::apply_repair(&ipfs, &identity, repair).await,
+            };
+
+            if let Err(e) = result {
+                tracing::warn!(%repair, error = %e, "unable to repair account");
+            }
+        }
+
+        self.diagnose().await
+    }
+
+    async fn repair_conversations(&self) -> Result<ConversationRepairReport, Error> {
+        self.messaging_store()?.repair_conversations(true).await
+    }
+}
+
+#[async_trait::async_trait]
+impl AccountDataExport for WarpIpfs {
+    async fn export_account_data(&self) -> Result<AccountDataArchive, Error> {
+        let ipfs = self.ipfs()?;
+        let identity = self.identity().await?;
+        let own_did = identity.did_key().clone();
+
+        let mut archive = AccountDataArchive::new(AccountData {
+            exported: Utc::now(),
+            identity: IdentityData {
+                did: own_did.clone(),
+                username: identity.username().to_string(),
+                status_message: identity.status_message().map(ToString::to_string),
+                created: identity.created(),
+                modified: identity.modified(),
+                profile_picture: None,
+                profile_banner: None,
+            },
+            friends: self.list_friends().await?,
+            blocked: self.block_list().await?,
+            requests: vec![],
+            conversations: vec![],
+            files: vec![],
+        });
+
+        for (name, image) in [
+            ("picture", self.profile_picture().await),
+            ("banner", self.profile_banner().await),
+        ] {
+            let Ok(image) = image else {
+                continue;
+            };
+            if image.data().is_empty() {
+                continue;
+            }
+            let path = archive.add_media(
+                format!("profile/{name}"),
+                Bytes::copy_from_slice(image.data()),
+            );
+            match name {
+                "picture" => archive.data.identity.profile_picture = Some(path),
+                _ => archive.data.identity.profile_banner = Some(path),
+            }
+        }
+
+        for (outgoing, requests) in [
+            (false, self.list_incoming_request().await?),
+            (true, self.list_outgoing_request().await?),
+        ] {
+            archive
+                .data
+                .requests
+                .extend(requests.into_iter().map(|request| RequestData {
+                    did: request.identity().clone(),
+                    outgoing,
+                    date: request.date(),
+                    message: request.message().map(ToString::to_string),
+                }));
+        }
+
+        for conversation in self.list_conversations().await? {
+            let conversation_id = conversation.id();
+
+            let messages = match self
+                .get_messages(conversation_id, MessageOptions::default())
+                .await?
+            {
+                Messages::List(list) => list,
+                _ => vec![],
+            };
+
+            let mut data = ConversationData {
+                id: conversation_id,
+                name: conversation.name().map(ToString::to_string),
+                conversation_type: conversation.conversation_type(),
+                created: conversation.created(),
+                participants: conversation.recipients().to_vec(),
+                messages: vec![],
+                reactions: vec![],
+                system_messages: vec![],
+            };
+
+            for message in messages {
+                if let Some(system) = message.system_message() {
+                    data.system_messages
+                        .push(SystemMessageData::new(message.date(), system));
+                    continue;
+                }
+
+                if message.sender() != &own_did {
+                    data.reactions.extend(
+                        export::own_reactions(&message, &own_did)
+                            .into_iter()
+                            .map(|emoji| ReactionData {
+                                message_id: message.id(),
+                                emoji,
+                            }),
+                    );
+                    continue;
+                }
+
+                let mut message_data = MessageData::new(&message, &own_did);
+
+                for attachment in message.attachments() {
+                    let name = attachment.name();
+                    let content = match self
+                        .download_stream(conversation_id, message.id(), &name)
+                        .await
+                    {
+                        Ok(stream) => ByteCollection::new(stream).await,
+                        Err(e) => Err(std::io::Error::other(e)),
+                    };
+
+                    match content {
+                        Ok(content) => message_data.attachments.push(archive.add_media(
+                            format!("attachments/{conversation_id}/{}/{name}", message.id()),
+                            content,
+                        )),
+                        Err(e) => {
+                            tracing::warn!(
+                                %conversation_id,
+                                message_id = %message.id(),
+                                %name,
+                                error = %e,
+                                "unable to export attachment"
+                            );
+                        }
+                    }
+                }
+
+                data.messages.push(message_data);
+            }
+
+            archive.data.conversations.push(data);
+        }
+
+        for (path, file) in export::collect_files(&self.root_directory(), "") {
+            let content = match file
+                .reference()
+                .map(|reference| reference.parse::<IpfsPath>())
+            {
+                Some(Ok(reference)) => ByteCollection::new(
+                    ipfs.cat_unixfs(reference)
+                        .map_err(std::io::Error::other)
+                        .boxed(),
+                )
+                .await
+                .ok(),
+                _ => None,
+            };
+
+            if content.is_none() {
+                tracing::warn!(%path, "unable to export file content");
+            }
+
+            let media = content.map(|content| archive.add_media(format!("files/{path}"), content));
+
+            archive.data.files.push(FileData {
+                path,
+                size: file.size(),
+                file_type: file.file_type().to_string(),
+                created: file.creation(),
+                modified: file.modified(),
+                media,
+            });
+        }
+
+        Ok(archive)
+    }
+}
+
+#[async_trait::async_trait]
+impl OfflineBundles for WarpIpfs {
+    async fn export_bundle(&self, recipient: &DID) -> Result<Bytes, Error> {
+        let identity = self.identity_store(true).await?;
+        let messaging = self.messaging_store()?;
+
+        let (conversations, messages) = messaging.export_queue(recipient).await;
+
+        let bundle = Bundle {
+            request: identity.queued_request(recipient).await,
+            conversations,
+            messages,
+        };
+
+        if bundle.is_empty() {
+            return Err(Error::OtherWithContext(
+                "Nothing is pending for the recipient".into(),
+            ));
+        }
+
+        let keypair = identity.root_document().keypair();
+
+        PayloadBuilder::new(keypair, bundle)
+            .add_recipient(recipient)?
+            .build()?
+            .to_bytes()
+    }
+
+    async fn import_bundle(&self, bundle: &[u8]) -> Result<BundleImport, Error> {
+        let identity = self.identity_store(true).await?;
+        let messaging = self.messaging_store()?;
+
+        let payload = PayloadMessage::<Bundle>::from_bytes(bundle)?;
+        let sender = payload.sender().to_did()?;
+
+        if sender == identity.did_key() {
+            return Err(Error::PublicKeyInvalid);
+        }
+
+        let Bundle {
+            request,
+            conversations,
+            messages,
+        } = payload.message(identity.root_document().keypair())?;
+
+        let mut summary = BundleImport::new(sender.clone());
+
+        if let Some(request) = request {
+            match identity.import_request(&sender, request).await {
+                Ok(_) => summary.requests += 1,
+                Err(e) => {
+                    tracing::warn!(%sender, error = %e, "unable to import friend request");
+                    summary.rejected += 1;
+                }
+            }
+        }
+
+        messaging
+            .import_queue(&sender, conversations, messages, &mut summary)
+            .await;
+
+        Ok(summary)
+    }
+}
+
+#[async_trait::async_trait]
+impl MessageReporting for WarpIpfs {
+    async fn report_message(
+        &self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        reason: &str,
+    ) -> Result<Uuid, Error> {
+        let store = self.messaging_store()?;
+        let message = store.get_message(conversation_id, message_id).await?;
+
+        self.moderation_store()?
+            .report(
+                ReportTarget::Conversation { conversation_id },
+                message_id,
+                message.sender().clone(),
+                reason,
+            )
+            .await
+    }
+
+    async fn report_community_message(
+        &self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        message_id: Uuid,
+        reason: &str,
+    ) -> Result<Uuid, Error> {
+        let store = self.messaging_store()?;
+        let message = store
+            .get_community_channel_message(community_id, channel_id, message_id)
+            .await?;
+
+        self.moderation_store()?
+            .report(
+                ReportTarget::CommunityChannel {
+                    community_id,
+                    channel_id,
+                },
+                message_id,
+                message.sender().clone(),
+                reason,
+            )
+            .await
+    }
+
+    async fn moderation_reports(&self) -> Result<Vec<ModerationReport>, Error> {
+        Ok(self.moderation_store()?.list())
+    }
+
+    async fn moderation_report(&self, id: Uuid) -> Result<ModerationReport, Error> {
+        self.moderation_store()?.get(id)
+    }
+
+    async fn resolve_report(&self, id: Uuid, action: ReportAction) -> Result<(), Error> {
+        let moderation_store = self.moderation_store()?;
+        let report = moderation_store.get(id)?;
+
+        if report.status() != ReportStatus::Pending {
+            return Err(Error::OtherWithContext(
+                "report has already been resolved".into(),
+            ));
+        }
+
+        let mut store = self.messaging_store()?;
+
+        match (report.target(), action) {
+            (_, ReportAction::Dismiss) => {}
+            (ReportTarget::Conversation { conversation_id }, ReportAction::DeleteMessage) => {
+                store
+                    .delete_message(conversation_id, report.message_id())
+                    .await?
+            }
+            (ReportTarget::Conversation { conversation_id }, ReportAction::RemoveMember) => {
+                store
+                    .remove_participant(conversation_id, report.message_sender())
+                    .await?
+            }
+            (
+                ReportTarget::CommunityChannel {
+                    community_id,
+                    channel_id,
+                },
+                ReportAction::DeleteMessage,
+            ) => {
+                store
+                    .delete_community_channel_message(community_id, channel_id, report.message_id())
+                    .await?
+            }
+            (ReportTarget::CommunityChannel { community_id, .. }, ReportAction::RemoveMember) => {
+                store
+                    .remove_community_member(community_id, report.message_sender().clone())
+                    .await?
+            }
+        }
+
+        moderation_store.set_resolved(id, action).await
+    }
+}
+
+#[async_trait::async_trait]
+impl EventInbox for WarpIpfs {
+    async fn pending_events(&self) -> Result<Vec<PendingEvent>, Error> {
+        let store = self.identity_store(true).await?;
+        Ok(store.pending_events().list().await)
+    }
+
+    async fn acknowledge_events(&self, ids: &[Uuid]) -> Result<usize, Error> {
+        let store = self.identity_store(true).await?;
+        store.pending_events().acknowledge(ids).await
+    }
+
+    async fn acknowledge_conversation(&self, conversation_id: Uuid) -> Result<usize, Error> {
+        let store = self.identity_store(true).await?;
+        store
+            .pending_events()
+            .acknowledge_mentions(conversation_id, None)
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl DataUsageAccounting for WarpIpfs {
+    async fn data_usage(&self) -> Result<DataUsage, Error> {
+        Ok(self.inner.usage.current())
+    }
+
+    async fn previous_data_usage(&self) -> Result<Option<DataUsage>, Error> {
+        Ok(self.inner.usage.previous())
+    }
+
+    async fn reset_data_usage(&self) -> Result<DataUsage, Error> {
+        Ok(self.inner.usage.reset())
+    }
 }
 
 pub(crate) fn to_file_type(name: &str) -> FileType {
@@ -0,0 +1,430 @@
+//! Removal of embedded metadata (eg EXIF, GPS, XMP) from images before they are uploaded.
+//!
+//! Only the container is rewritten and the image data itself is left untouched, so no quality is lost.
+//! Note that this removes the EXIF orientation along with the rest of the EXIF data.
+use bytes::{Buf, Bytes, BytesMut};
+use futures::{stream::BoxStream, StreamExt};
+use std::{ffi::OsStr, io, path::Path};
+
+const JPEG_SIGNATURE: &[u8] = &[0xFF, 0xD8];
+const PNG_SIGNATURE: &[u8] = &[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+/// PNG chunks that carry metadata rather than image data
+const PNG_METADATA_CHUNKS: [&[u8; 4]; 5] = [b"eXIf", b"tEXt", b"zTXt", b"iTXt", b"tIME"];
+
+const WEBP_VP8X_EXIF_FLAG: u8 = 0x08;
+const WEBP_VP8X_XMP_FLAG: u8 = 0x04;
+
+/// Returns true if metadata can be stripped from a file with the given name
+pub fn is_supported<P: AsRef<Path>>(name: P) -> bool {
+    name.as_ref()
+        .extension()
+        .and_then(OsStr::to_str)
+        .map(|ext| matches!(ext.to_lowercase().as_str(), "jpg" | "jpeg" | "png" | "webp"))
+        .unwrap_or_default()
+}
+
+/// Strip metadata from the image, returning `None` if there was no metadata to remove, which includes formats that
+/// are not supported. Malformed images are left as is from the point where they could not be parsed.
+pub fn strip(data: &[u8]) -> Option<Vec<u8>> {
+    let mut stripper = Stripper::default();
+    let mut output = BytesMut::with_capacity(data.len());
+    stripper.push(data, &mut output);
+    stripper.finish(&mut output);
+
+    (output.len() != data.len()).then(|| output.to_vec())
+}
+
+/// Strip the metadata from the image as it is passed on. JPEG and PNG images are rewritten as the data arrives,
+/// while WebP images are buffered, up to `max_size`, as the size in their header has to be updated.
+pub fn strip_stream(
+    mut stream: BoxStream<'static, io::Result<Bytes>>,
+    max_size: Option<usize>,
+) -> BoxStream<'static, io::Result<Bytes>> {
+    let stream = async_stream::stream! {
+        let mut stripper = Stripper::default();
+
+        while let Some(result) = stream.next().await {
+            let bytes = match result {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    yield Err(e);
+                    return;
+                }
+            };
+
+            let mut output = BytesMut::new();
+            stripper.push(&bytes, &mut output);
+
+            if matches!(max_size, Some(max_size) if stripper.buffer.len() > max_size) {
+                yield Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "image exceeds the maximum file size",
+                ));
+                return;
+            }
+
+            if !output.is_empty() {
+                yield Ok(output.freeze());
+            }
+        }
+
+        let mut output = BytesMut::new();
+        stripper.finish(&mut output);
+
+        if !output.is_empty() {
+            yield Ok(output.freeze());
+        }
+    };
+
+    stream.boxed()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Jpeg,
+    Png,
+    Webp,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// Parsing the header of the next segment or chunk
+    #[default]
+    Parse,
+    /// Passing on a segment or chunk that is kept. The rest of the data is dropped after the last one
+    Copy { remaining: usize, last: bool },
+    /// Dropping a segment or chunk that carries metadata
+    Skip { remaining: usize },
+    /// Passing on the rest of the data as is
+    Rest,
+    /// Dropping the rest of the data
+    Discard,
+}
+
+/// Removes metadata from an image received in parts. Only the header of the current segment or chunk is held
+/// in memory, except for WebP images which are buffered until [`Stripper::finish`]
+#[derive(Debug, Default)]
+struct Stripper {
+    format: Option<Format>,
+    mode: Mode,
+    buffer: BytesMut,
+}
+
+impl Stripper {
+    fn push(&mut self, data: &[u8], output: &mut BytesMut) {
+        self.buffer.extend_from_slice(data);
+
+        loop {
+            match self.mode {
+                Mode::Copy { remaining, .. } | Mode::Skip { remaining } => {
+                    let bytes = self.buffer.split_to(remaining.min(self.buffer.len()));
+                    let remaining = remaining - bytes.len();
+
+                    self.mode = match self.mode {
+                        Mode::Copy { last, .. } => {
+                            output.extend_from_slice(&bytes);
+                            match (remaining, last) {
+                                (0, true) => Mode::Discard,
+                                (0, false) => Mode::Parse,
+                                _ => Mode::Copy { remaining, last },
+                            }
+                        }
+                        _ => match remaining {
+                            0 => Mode::Parse,
+                            _ => Mode::Skip { remaining },
+                        },
+                    };
+
+                    if remaining > 0 {
+                        return;
+                    }
+                }
+                Mode::Rest => {
+                    output.extend_from_slice(&self.buffer.split());
+                    return;
+                }
+                Mode::Discard => {
+                    self.buffer.clear();
+                    return;
+                }
+                Mode::Parse => {
+                    let next = match self.format {
+                        None => self.detect(),
+                        Some(Format::Jpeg) => self.parse_jpeg(),
+                        Some(Format::Png) => self.parse_png(),
+                        Some(Format::Webp) => None,
+                    };
+
+                    match next {
+                        Some(mode) => self.mode = mode,
+                        // more data is needed
+                        None => return,
+                    }
+                }
+            }
+        }
+    }
+
+    fn finish(self, output: &mut BytesMut) {
+        match (self.format, self.mode) {
+            (Some(Format::Webp), _) => match strip_webp(&self.buffer) {
+                Some(stripped) => output.extend_from_slice(&stripped),
+                None => output.extend_from_slice(&self.buffer),
+            },
+            (_, Mode::Discard) => {}
+            // data that is too short to be parsed is passed on as is
+            _ => output.extend_from_slice(&self.buffer),
+        }
+    }
+
+    fn detect(&mut self) -> Option<Mode> {
+        if self.buffer.len() < 12 {
+            return None;
+        }
+
+        let (format, signature) = if self.buffer.starts_with(PNG_SIGNATURE) {
+            (Format::Png, PNG_SIGNATURE.len())
+        } else if self.buffer.starts_with(JPEG_SIGNATURE) {
+            (Format::Jpeg, JPEG_SIGNATURE.len())
+        } else if &self.buffer[..4] == b"RIFF" && &self.buffer[8..12] == b"WEBP" {
+            (Format::Webp, 0)
+        } else {
+            return Some(Mode::Rest);
+        };
+
+        self.format = Some(format);
+
+        Some(Mode::Copy {
+            remaining: signature,
+            last: false,
+        })
+    }
+
+    fn parse_jpeg(&mut self) -> Option<Mode> {
+        let data = &self.buffer;
+
+        if data.len() < 2 {
+            return None;
+        }
+
+        if data[0] != 0xFF {
+            return Some(Mode::Rest);
+        }
+
+        // markers can be padded with any number of fill bytes
+        if data[1] == 0xFF {
+            self.buffer.advance(1);
+            return Some(Mode::Parse);
+        }
+
+        let marker = data[1];
+
+        match marker {
+            // start of scan: the rest of the file is entropy coded image data
+            0xDA => return Some(Mode::Rest),
+            // standalone markers without a length
+            0x01 | 0xD0..=0xD7 => {
+                return Some(Mode::Copy {
+                    remaining: 2,
+                    last: false,
+                })
+            }
+            0xD9 => {
+                return Some(Mode::Copy {
+                    remaining: 2,
+                    last: true,
+                })
+            }
+            _ => {}
+        }
+
+        if data.len() < 4 {
+            return None;
+        }
+
+        let length = u16::from_be_bytes([data[2], data[3]]) as usize;
+
+        if length < 2 {
+            return Some(Mode::Rest);
+        }
+
+        // Keep JFIF (APP0), ICC profiles (APP2) and the Adobe color transform (APP14) as they affect how the image
+        // is rendered. Every other application segment and comments are dropped.
+        let keep = !matches!(marker, 0xE1 | 0xE3..=0xED | 0xEF | 0xFE);
+
+        let remaining = 2 + length;
+
+        Some(match keep {
+            true => Mode::Copy {
+                remaining,
+                last: false,
+            },
+            false => Mode::Skip { remaining },
+        })
+    }
+
+    fn parse_png(&mut self) -> Option<Mode> {
+        let data = &self.buffer;
+
+        if data.len() < 8 {
+            return None;
+        }
+
+        let length = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+        let chunk_type = &data[4..8];
+
+        // length, type, data and crc
+        let Some(remaining) = length.checked_add(12) else {
+            return Some(Mode::Rest);
+        };
+
+        if PNG_METADATA_CHUNKS.iter().any(|ty| ty[..] == *chunk_type) {
+            return Some(Mode::Skip { remaining });
+        }
+
+        Some(Mode::Copy {
+            remaining,
+            last: chunk_type == b"IEND",
+        })
+    }
+}
+
+fn strip_webp(data: &[u8]) -> Option<Vec<u8>> {
+    let mut output = Vec::with_capacity(data.len());
+    output.extend_from_slice(&data[..12]);
+
+    let mut pos = 12;
+
+    while pos + 8 <= data.len() {
+        let fourcc = &data[pos..pos + 4];
+        let length = u32::from_le_bytes(data[pos + 4..pos + 8].try_into().ok()?) as usize;
+        // chunks are padded to an even size
+        let end = pos.checked_add(8)?.checked_add(length + (length & 1))?;
+
+        if end > data.len() {
+            return None;
+        }
+
+        match fourcc {
+            b"EXIF" | b"XMP " => {}
+            b"VP8X" if length > 0 => {
+                let start = output.len();
+                output.extend_from_slice(&data[pos..end]);
+                output[start + 8] &= !(WEBP_VP8X_EXIF_FLAG | WEBP_VP8X_XMP_FLAG);
+            }
+            _ => output.extend_from_slice(&data[pos..end]),
+        }
+
+        pos = end;
+    }
+
+    let riff_size = u32::try_from(output.len() - 8).ok()?;
+    output[4..8].copy_from_slice(&riff_size.to_le_bytes());
+
+    Some(output)
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::BytesMut;
+
+    use super::{is_supported, strip, Stripper};
+
+    fn png_chunk(ty: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = Vec::new();
+        chunk.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        chunk.extend_from_slice(ty);
+        chunk.extend_from_slice(data);
+        // crc is not validated when stripping
+        chunk.extend_from_slice(&[0; 4]);
+        chunk
+    }
+
+    #[test]
+    fn strip_jpeg_metadata() {
+        let mut jpeg = vec![0xFF, 0xD8];
+        // APP0 (JFIF)
+        jpeg.extend_from_slice(&[0xFF, 0xE0, 0x00, 0x04, 0x4A, 0x46]);
+        // APP1 (EXIF)
+        jpeg.extend_from_slice(&[0xFF, 0xE1, 0x00, 0x06, b'E', b'x', b'i', b'f']);
+        // COM
+        jpeg.extend_from_slice(&[0xFF, 0xFE, 0x00, 0x03, b'!']);
+        // SOS with the image data
+        jpeg.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x02, 0x01, 0x02, 0xFF, 0xD9]);
+
+        let stripped = strip(&jpeg).expect("metadata removed");
+
+        let mut expected = vec![0xFF, 0xD8];
+        expected.extend_from_slice(&[0xFF, 0xE0, 0x00, 0x04, 0x4A, 0x46]);
+        expected.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x02, 0x01, 0x02, 0xFF, 0xD9]);
+
+        assert_eq!(stripped, expected);
+        // nothing left to remove
+        assert!(strip(&stripped).is_none());
+    }
+
+    #[test]
+    fn strip_png_metadata() {
+        let mut png = super::PNG_SIGNATURE.to_vec();
+        png.extend(png_chunk(b"IHDR", &[0; 13]));
+        png.extend(png_chunk(b"tEXt", b"Author\0warp"));
+        png.extend(png_chunk(b"eXIf", b"MM\0*"));
+        png.extend(png_chunk(b"IDAT", &[1, 2, 3]));
+        png.extend(png_chunk(b"IEND", &[]));
+
+        let stripped = strip(&png).expect("metadata removed");
+
+        let mut expected = super::PNG_SIGNATURE.to_vec();
+        expected.extend(png_chunk(b"IHDR", &[0; 13]));
+        expected.extend(png_chunk(b"IDAT", &[1, 2, 3]));
+        expected.extend(png_chunk(b"IEND", &[]));
+
+        assert_eq!(stripped, expected);
+    }
+
+    #[test]
+    fn unsupported_or_malformed() {
+        assert!(strip(b"plain text").is_none());
+        // truncated segment
+        assert!(strip(&[0xFF, 0xD8, 0xFF, 0xE1, 0x00, 0x10, 0x00]).is_none());
+
+        assert!(is_supported("photo.JPG"));
+        assert!(is_supported("/chat/image.webp"));
+        assert!(!is_supported("document.pdf"));
+    }
+
+    #[test]
+    fn strip_in_parts() {
+        let mut png = super::PNG_SIGNATURE.to_vec();
+        png.extend(png_chunk(b"IHDR", &[0; 13]));
+        png.extend(png_chunk(
+            b"iTXt",
+            b"XML:com.adobe.xmp\0\0\0\0\0<x:xmpmeta/>",
+        ));
+        png.extend(png_chunk(b"IDAT", &[1, 2, 3, 4, 5, 6, 7, 8]));
+        png.extend(png_chunk(b"IEND", &[]));
+
+        let mut jpeg = vec![0xFF, 0xD8];
+        jpeg.extend_from_slice(&[0xFF, 0xE1, 0x00, 0x06, b'E', b'x', b'i', b'f']);
+        jpeg.extend_from_slice(&[0xFF, 0xFF, 0xDB, 0x00, 0x03, 0x00]);
+        jpeg.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x02, 0x01, 0x02, 0xFF, 0xD9]);
+
+        for image in [png, jpeg] {
+            let expected = strip(&image).expect("metadata removed");
+
+            for size in [1, 3, 7] {
+                let mut stripper = Stripper::default();
+                let mut output = BytesMut::new();
+                for part in image.chunks(size) {
+                    stripper.push(part, &mut output);
+                    // only the header of a segment or chunk is held back
+                    assert!(stripper.buffer.len() < 12);
+                }
+                stripper.finish(&mut output);
+
+                assert_eq!(output.to_vec(), expected);
+            }
+        }
+    }
+}
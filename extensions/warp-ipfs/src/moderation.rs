@@ -0,0 +1,135 @@
+//! Reporting of messages to moderators.
+//!
+//! A report created with [`MessageReporting::report_message`] or [`MessageReporting::report_community_message`]
+//! is signed by the reporter and delivered to the creator of a group conversation, or to the members of a
+//! community that are able to delete messages or remove members. Reports to moderators that are offline are queued
+//! until they come online. Moderators only accept reports from members of the conversation or community they moderate,
+//! and keep them encrypted in a queue that they can review with [`MessageReporting::moderation_reports`] and act on
+//! with [`MessageReporting::resolve_report`].
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use warp::{crypto::DID, error::Error};
+
+/// Location of the reported message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportTarget {
+    Conversation {
+        conversation_id: Uuid,
+    },
+    CommunityChannel {
+        community_id: Uuid,
+        channel_id: Uuid,
+    },
+}
+
+/// Action taken by a moderator on a report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportAction {
+    /// Close the report without acting on the message
+    Dismiss,
+    /// Delete the reported message
+    DeleteMessage,
+    /// Remove the sender of the reported message from the conversation or community
+    RemoveMember,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportStatus {
+    #[default]
+    Pending,
+    Resolved {
+        action: ReportAction,
+        resolved: DateTime<Utc>,
+    },
+}
+
+/// Report of a message, signed by the reporter
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModerationReport {
+    pub(crate) id: Uuid,
+    pub(crate) target: ReportTarget,
+    pub(crate) message_id: Uuid,
+    pub(crate) message_sender: DID,
+    pub(crate) reporter: DID,
+    pub(crate) reason: String,
+    pub(crate) created: DateTime<Utc>,
+    #[serde(default)]
+    pub(crate) status: ReportStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) signature: Option<String>,
+}
+
+impl ModerationReport {
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    /// Conversation or community channel the message was sent in
+    pub fn target(&self) -> ReportTarget {
+        self.target
+    }
+
+    pub fn message_id(&self) -> Uuid {
+        self.message_id
+    }
+
+    /// Sender of the reported message
+    pub fn message_sender(&self) -> &DID {
+        &self.message_sender
+    }
+
+    pub fn reporter(&self) -> &DID {
+        &self.reporter
+    }
+
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    pub fn created(&self) -> DateTime<Utc> {
+        self.created
+    }
+
+    /// Status of the report in the local queue
+    pub fn status(&self) -> ReportStatus {
+        self.status
+    }
+}
+
+#[async_trait::async_trait]
+pub trait MessageReporting: Sync + Send {
+    /// Report a message in a group conversation to the creator of the conversation
+    async fn report_message(&self, _: Uuid, _: Uuid, _: &str) -> Result<Uuid, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Report a message in a community channel to the moderators of the community
+    async fn report_community_message(
+        &self,
+        _: Uuid,
+        _: Uuid,
+        _: Uuid,
+        _: &str,
+    ) -> Result<Uuid, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// List the reports received, with pending reports first
+    async fn moderation_reports(&self) -> Result<Vec<ModerationReport>, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Get a report received
+    async fn moderation_report(&self, _: Uuid) -> Result<ModerationReport, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Act on a pending report and mark it as resolved
+    async fn resolve_report(&self, _: Uuid, _: ReportAction) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+}
@@ -0,0 +1,141 @@
+//! Readiness of the subsystems during startup.
+//!
+//! Once the identity is loaded the instance is usable, while conversations are hydrated and the file index is
+//! resolved in the background. Until a subsystem is ready, calls to it wait for it to finish loading (eg listing
+//! conversations) or observe partial state (eg the file index being empty). Applications can follow the progress
+//! with [`WarpIpfs::readiness_subscribe`](crate::WarpIpfs::readiness_subscribe) to display a loading state, and
+//! the time taken by each subsystem is reported to profile the startup.
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::stream::BoxStream;
+use parking_lot::RwLock;
+use tokio::sync::watch;
+use web_time::Instant;
+
+pub type ReadinessEventStream = BoxStream<'static, ReadinessEvent>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, derive_more::Display)]
+pub enum Subsystem {
+    /// Ipfs node was started
+    #[display(fmt = "ipfs")]
+    Ipfs,
+    /// Identity was loaded
+    #[display(fmt = "identity")]
+    Identity,
+    /// File index was resolved from the root document
+    #[display(fmt = "files")]
+    Files,
+    /// Conversations were loaded and their tasks started
+    #[display(fmt = "messaging")]
+    Messaging,
+}
+
+impl Subsystem {
+    pub const ALL: [Subsystem; 4] = [
+        Subsystem::Ipfs,
+        Subsystem::Identity,
+        Subsystem::Files,
+        Subsystem::Messaging,
+    ];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadinessEvent {
+    pub subsystem: Subsystem,
+    /// Time taken since the start of the initialization until the subsystem was ready
+    pub elapsed: Duration,
+}
+
+/// Subsystems that are ready, along with the time taken to be ready
+#[derive(Clone)]
+pub(crate) struct Readiness {
+    started: Arc<RwLock<Instant>>,
+    state: watch::Sender<BTreeMap<Subsystem, Duration>>,
+}
+
+impl Default for Readiness {
+    fn default() -> Self {
+        let (state, _) = watch::channel(BTreeMap::new());
+        Self {
+            started: Arc::new(RwLock::new(Instant::now())),
+            state,
+        }
+    }
+}
+
+impl Readiness {
+    /// Mark the start of the initialization, from which the time taken by the subsystems is measured
+    pub fn start(&self) {
+        *self.started.write() = Instant::now();
+    }
+
+    pub fn set_ready(&self, subsystem: Subsystem) {
+        let elapsed = self.started.read().elapsed();
+        tracing::info!(%subsystem, elapsed_ms = elapsed.as_millis() as u64, "Subsystem ready");
+        self.state.send_modify(|state| {
+            state.entry(subsystem).or_insert(elapsed);
+        });
+    }
+
+    pub fn is_ready(&self, subsystem: Subsystem) -> bool {
+        self.state.borrow().contains_key(&subsystem)
+    }
+
+    /// Time taken by each subsystem that is ready
+    pub fn report(&self) -> BTreeMap<Subsystem, Duration> {
+        self.state.borrow().clone()
+    }
+
+    /// Stream of the subsystems as they become ready. Subsystems that are already ready are emitted first, and the
+    /// stream ends once every subsystem is ready
+    pub fn subscribe(&self) -> ReadinessEventStream {
+        let mut rx = self.state.subscribe();
+        let stream = async_stream::stream! {
+            let mut emitted = Vec::new();
+            loop {
+                let state = rx.borrow_and_update().clone();
+                for (subsystem, elapsed) in state {
+                    if emitted.contains(&subsystem) {
+                        continue;
+                    }
+                    emitted.push(subsystem);
+                    yield ReadinessEvent { subsystem, elapsed };
+                }
+
+                if emitted.len() == Subsystem::ALL.len() || rx.changed().await.is_err() {
+                    break;
+                }
+            }
+        };
+        Box::pin(stream)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::StreamExt;
+
+    use super::{Readiness, Subsystem};
+
+    #[tokio::test]
+    async fn stream_ends_once_every_subsystem_is_ready() {
+        let readiness = Readiness::default();
+        readiness.set_ready(Subsystem::Ipfs);
+
+        let stream = readiness.subscribe();
+
+        readiness.set_ready(Subsystem::Identity);
+        readiness.set_ready(Subsystem::Messaging);
+        readiness.set_ready(Subsystem::Files);
+
+        let subsystems = stream
+            .map(|event| event.subsystem)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(subsystems.len(), Subsystem::ALL.len());
+        assert_eq!(subsystems[0], Subsystem::Ipfs);
+        assert!(readiness.is_ready(Subsystem::Files));
+    }
+}
@@ -0,0 +1,591 @@
+//! Selection of the relays used by the node.
+//!
+//! When more than one relay is configured, each relay is measured by the round trip time of its reservation (or of
+//! a dial when it is not in use) along with whether reservations succeed. The relays with the fewest failures and
+//! lowest round trip time are preferred, which favours relays that are close to the node without requiring any
+//! knowledge of where they are hosted. The selection is re-evaluated periodically based on
+//! [`RelayClient::evaluation_interval`](crate::config::RelayClient), while pinned relays are always used.
+//!
+//! Relays can also be arranged in ordered fallback chains, in which case only one relay of each chain is used at a
+//! time. When a reservation fails, the next relay in the chain is used and [`ConnectionEvent::RelayFallback`] is
+//! emitted. Once a relay earlier in the chain accepts a reservation again, it is used in place of the fallback relay
+//! and [`ConnectionEvent::RelayRestored`] is emitted.
+use std::collections::HashSet;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_rt::AbortableJoinHandle;
+use futures::channel::oneshot;
+use futures_timeout::TimeoutExt;
+use futures_timer::Delay;
+use indexmap::IndexMap;
+use parking_lot::RwLock;
+use rust_ipfs::{Ipfs, Multiaddr, PeerId, Protocol};
+use tokio::sync::broadcast;
+use tracing::{Instrument, Span};
+use web_time::Instant;
+
+use crate::config::{RelayClient, RelayQuorum};
+use crate::connection::ConnectionEvent;
+use crate::rt;
+
+/// Duration to wait for a reservation or a dial to complete before considering it a failure
+const RELAY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Information about a relay known to the node
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelayInfo {
+    pub peer_id: PeerId,
+    pub addresses: Vec<Multiaddr>,
+    /// Round trip time of the last successful reservation or dial
+    pub rtt: Option<Duration>,
+    /// Number of consecutive failures when reserving or dialing the relay
+    pub failures: usize,
+    /// Relay is always used, regardless of its measurements
+    pub pinned: bool,
+    /// Relay currently holds a reservation for the node
+    pub active: bool,
+}
+
+impl RelayInfo {
+    // Round trip time used for ranking. Active relays are favoured by a quarter of their round trip time
+    // so the selection does not flip between relays with similar measurements
+    fn score(&self) -> Option<Duration> {
+        match self.active {
+            true => self.rtt.map(|rtt| rtt * 3 / 4),
+            false => self.rtt,
+        }
+    }
+}
+
+/// Orders the relays by preference: pinned relays first, followed by the relays with the fewest failures
+/// and the lowest round trip time. Relays that were never measured are placed after measured ones
+pub(crate) fn rank_relays(relays: &[RelayInfo]) -> Vec<PeerId> {
+    let mut relays = relays.iter().collect::<Vec<_>>();
+    relays.sort_by_key(|relay| {
+        (
+            !relay.pinned,
+            relay.failures,
+            relay.score().is_none(),
+            relay.score(),
+        )
+    });
+    relays.into_iter().map(|relay| relay.peer_id).collect()
+}
+
+fn quorum_target(quorum: RelayQuorum, available: usize) -> usize {
+    match quorum {
+        RelayQuorum::First => 1,
+        RelayQuorum::N(n) => (n as usize).max(1),
+        RelayQuorum::All => available,
+    }
+}
+
+/// Change to the relay used for a fallback chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChainChange {
+    /// A relay later in the chain is used as the relay before it failed
+    Fallback { peer_id: PeerId, failed: PeerId },
+    /// A relay earlier in the chain is used in place of the fallback relay
+    Restored { peer_id: PeerId, fallback: PeerId },
+}
+
+impl From<ChainChange> for ConnectionEvent {
+    fn from(change: ChainChange) -> Self {
+        match change {
+            ChainChange::Fallback { peer_id, failed } => {
+                ConnectionEvent::RelayFallback { peer_id, failed }
+            }
+            ChainChange::Restored { peer_id, fallback } => {
+                ConnectionEvent::RelayRestored { peer_id, fallback }
+            }
+        }
+    }
+}
+
+/// Tries the relays of the chain in order, up to the relay in use at `current`, until one of them is reserved
+async fn advance_chain<F, Fut>(
+    chain: &[PeerId],
+    current: Option<usize>,
+    mut reserve: F,
+) -> Option<ChainChange>
+where
+    F: FnMut(PeerId) -> Fut,
+    Fut: Future<Output = bool>,
+{
+    let mut failed = None;
+
+    for (index, peer_id) in chain.iter().copied().enumerate() {
+        // Relays after the active relay are only tried once the active relay fails
+        if current.is_some_and(|current| index >= current) {
+            break;
+        }
+
+        if !reserve(peer_id).await {
+            failed = Some(peer_id);
+            continue;
+        }
+
+        return match current {
+            Some(current) => Some(ChainChange::Restored {
+                peer_id,
+                fallback: chain[current],
+            }),
+            None => failed.map(|failed| ChainChange::Fallback { peer_id, failed }),
+        };
+    }
+
+    None
+}
+
+#[derive(Clone)]
+pub struct RelaySelection {
+    relays: Arc<RwLock<IndexMap<PeerId, RelayInfo>>>,
+    _handle: AbortableJoinHandle<()>,
+}
+
+impl RelaySelection {
+    /// Starts selecting between the relays, which must have been added to the node beforehand.
+    /// The returned receiver resolves once the initial selection has been made
+    pub fn new(
+        ipfs: &Ipfs,
+        relays: IndexMap<PeerId, Vec<Multiaddr>>,
+        config: &RelayClient,
+        event: broadcast::Sender<ConnectionEvent>,
+        span: &Span,
+    ) -> (Self, oneshot::Receiver<()>) {
+        let relays = relays
+            .into_iter()
+            .map(|(peer_id, addresses)| {
+                let info = RelayInfo {
+                    peer_id,
+                    addresses,
+                    rtt: None,
+                    failures: 0,
+                    pinned: config.pinned.contains(&peer_id),
+                    active: false,
+                };
+                (peer_id, info)
+            })
+            .collect::<IndexMap<_, _>>();
+
+        let chains = config
+            .fallback_chains
+            .iter()
+            .map(|chain| {
+                chain
+                    .iter()
+                    .filter(|peer_id| {
+                        let known = relays.contains_key(*peer_id);
+                        if !known {
+                            tracing::warn!(%peer_id, "relay in fallback chain is not a known relay. Skipping");
+                        }
+                        known
+                    })
+                    .copied()
+                    .collect::<Vec<_>>()
+            })
+            .filter(|chain| !chain.is_empty())
+            .collect::<Vec<_>>();
+
+        let relays = Arc::new(RwLock::new(relays));
+
+        let task = RelaySelectionTask {
+            ipfs: ipfs.clone(),
+            relays: relays.clone(),
+            quorum: config.quorum,
+            chains,
+            event,
+        };
+
+        let interval = config.evaluation_interval;
+        let (tx, rx) = oneshot::channel();
+
+        let span = span.clone();
+
+        let _handle = rt::spawn_abortable(
+            async move {
+                task.select_chains().await;
+                task.select().await;
+                let _ = tx.send(());
+
+                let Some(interval) = interval else {
+                    return;
+                };
+
+                loop {
+                    Delay::new(interval).await;
+                    task.reevaluate().await;
+                    task.select_chains().await;
+                    task.select().await;
+                }
+            }
+            .instrument(span),
+        );
+
+        (Self { relays, _handle }, rx)
+    }
+
+    /// Relays that currently hold a reservation for the node, in order of preference
+    pub fn active_relays(&self) -> Vec<RelayInfo> {
+        let relays = self.relays.read();
+        let list = relays.values().cloned().collect::<Vec<_>>();
+        rank_relays(&list)
+            .into_iter()
+            .filter_map(|peer_id| relays.get(&peer_id))
+            .filter(|relay| relay.active)
+            .cloned()
+            .collect()
+    }
+}
+
+struct RelaySelectionTask {
+    ipfs: Ipfs,
+    relays: Arc<RwLock<IndexMap<PeerId, RelayInfo>>>,
+    quorum: RelayQuorum,
+    chains: Vec<Vec<PeerId>>,
+    event: broadcast::Sender<ConnectionEvent>,
+}
+
+impl RelaySelectionTask {
+    /// Measures the relays that are not in use and marks relays that lost their reservation
+    async fn reevaluate(&self) {
+        let reserved = self
+            .ipfs
+            .list_relays(true)
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(peer_id, _)| peer_id)
+            .collect::<HashSet<_>>();
+
+        let idle = {
+            let mut relays = self.relays.write();
+            for relay in relays.values_mut() {
+                if relay.active && !reserved.contains(&relay.peer_id) {
+                    tracing::warn!(peer_id = %relay.peer_id, "relay reservation has been lost");
+                    relay.active = false;
+                    relay.failures += 1;
+                }
+            }
+
+            relays
+                .values()
+                .filter(|relay| !relay.active)
+                .map(|relay| relay.peer_id)
+                .collect::<Vec<_>>()
+        };
+
+        for peer_id in idle {
+            // A sample cannot be taken over an existing connection, so the previous one is kept
+            if self.ipfs.is_connected(peer_id).await.unwrap_or_default() {
+                continue;
+            }
+
+            let timer = Instant::now();
+            let result = self.ipfs.connect(peer_id).timeout(RELAY_TIMEOUT).await;
+            let rtt = timer.elapsed();
+
+            let mut relays = self.relays.write();
+            let Some(relay) = relays.get_mut(&peer_id) else {
+                continue;
+            };
+
+            match result {
+                Ok(Ok(_)) => {
+                    relay.rtt = Some(rtt);
+                    relay.failures = 0;
+                }
+                Ok(Err(e)) => {
+                    tracing::debug!(%peer_id, error = %e, "unable to dial relay");
+                    relay.failures += 1;
+                }
+                Err(_) => {
+                    tracing::debug!(%peer_id, "dialing relay timed out");
+                    relay.failures += 1;
+                }
+            }
+        }
+    }
+
+    /// Reserves the first relay of each chain that accepts a reservation, releasing the fallback relay
+    /// once a relay earlier in the chain is available again
+    async fn select_chains(&self) {
+        for chain in &self.chains {
+            let current = {
+                let relays = self.relays.read();
+                chain
+                    .iter()
+                    .position(|peer_id| relays.get(peer_id).is_some_and(|relay| relay.active))
+            };
+
+            let Some(change) = advance_chain(chain, current, |peer_id| self.reserve(peer_id)).await
+            else {
+                continue;
+            };
+
+            if let ChainChange::Restored { fallback, .. } = change {
+                if let Err(e) = self.ipfs.disable_relay(fallback).await {
+                    tracing::warn!(peer_id = %fallback, error = %e, "unable to release fallback relay");
+                }
+
+                if let Some(relay) = self.relays.write().get_mut(&fallback) {
+                    relay.active = false;
+                }
+            }
+
+            // The stream is optional, so the event is dropped when nothing is subscribed to it
+            let _ = self.event.send(change.into());
+        }
+    }
+
+    /// Reserves the relay, recording the round trip time of the reservation or the failure
+    async fn reserve(&self, peer_id: PeerId) -> bool {
+        let timer = Instant::now();
+        let result = self
+            .ipfs
+            .enable_relay(Some(peer_id))
+            .timeout(RELAY_TIMEOUT)
+            .await;
+        let rtt = timer.elapsed();
+
+        let mut relays = self.relays.write();
+        let Some(relay) = relays.get_mut(&peer_id) else {
+            return false;
+        };
+
+        match result {
+            Ok(Ok(_)) => {
+                relay.rtt = Some(rtt);
+                relay.failures = 0;
+                relay.active = true;
+                true
+            }
+            Ok(Err(e)) => {
+                tracing::error!("Failed to use {peer_id} as a relay: {e}");
+                relay.failures += 1;
+                false
+            }
+            Err(_) => {
+                tracing::error!("Relay connection to {peer_id} timed out");
+                relay.failures += 1;
+                false
+            }
+        }
+    }
+
+    /// Reserves the preferred relays until the quorum is reached and releases the relays that are no longer preferred.
+    /// Relays that are part of a fallback chain are left to [`Self::select_chains`]
+    async fn select(&self) {
+        let (ranked, target) = {
+            let relays = self.relays.read();
+            let list = relays
+                .values()
+                .filter(|relay| {
+                    !self
+                        .chains
+                        .iter()
+                        .flatten()
+                        .any(|peer_id| *peer_id == relay.peer_id)
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+            let pinned = list.iter().filter(|relay| relay.pinned).count();
+            let target = quorum_target(self.quorum, list.len()).max(pinned);
+            (rank_relays(&list), target)
+        };
+
+        let mut selected = Vec::with_capacity(target);
+
+        for peer_id in ranked.iter().copied() {
+            if selected.len() >= target {
+                break;
+            }
+
+            let active = self
+                .relays
+                .read()
+                .get(&peer_id)
+                .map(|relay| relay.active)
+                .unwrap_or_default();
+
+            if active {
+                selected.push(peer_id);
+                continue;
+            }
+
+            if self.reserve(peer_id).await {
+                selected.push(peer_id);
+            }
+        }
+
+        // Only release relays once the quorum has been reached so the node is not left without a relay
+        if selected.len() >= target {
+            for peer_id in ranked.iter().filter(|peer_id| !selected.contains(peer_id)) {
+                let active = self
+                    .relays
+                    .read()
+                    .get(peer_id)
+                    .map(|relay| relay.active && !relay.pinned)
+                    .unwrap_or_default();
+
+                if !active {
+                    continue;
+                }
+
+                if let Err(e) = self.ipfs.disable_relay(*peer_id).await {
+                    tracing::warn!(%peer_id, error = %e, "unable to release relay");
+                    continue;
+                }
+
+                if let Some(relay) = self.relays.write().get_mut(peer_id) {
+                    relay.active = false;
+                }
+            }
+        }
+
+        let list = self.ipfs.list_relays(true).await.unwrap_or_default();
+        for addr in list.iter().flat_map(|(_, addrs)| addrs) {
+            tracing::info!("Listening on {}", addr.clone().with(Protocol::P2pCircuit));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+    use std::time::Duration;
+
+    use rust_ipfs::PeerId;
+
+    use super::{advance_chain, quorum_target, rank_relays, ChainChange, RelayInfo};
+    use crate::config::RelayQuorum;
+    use crate::connection::ConnectionEvent;
+
+    fn relay(rtt: Option<u64>, failures: usize, pinned: bool, active: bool) -> RelayInfo {
+        RelayInfo {
+            peer_id: PeerId::random(),
+            addresses: vec![],
+            rtt: rtt.map(Duration::from_millis),
+            failures,
+            pinned,
+            active,
+        }
+    }
+
+    #[test]
+    fn relays_ranked_by_latency() {
+        let slow = relay(Some(200), 0, false, false);
+        let fast = relay(Some(20), 0, false, false);
+        let unmeasured = relay(None, 0, false, false);
+
+        let ranked = rank_relays(&[unmeasured.clone(), slow.clone(), fast.clone()]);
+        assert_eq!(ranked, vec![fast.peer_id, slow.peer_id, unmeasured.peer_id]);
+    }
+
+    #[test]
+    fn pinned_relays_ranked_first() {
+        let fast = relay(Some(20), 0, false, false);
+        let pinned = relay(Some(300), 2, true, false);
+        let failing = relay(Some(10), 1, false, false);
+
+        let ranked = rank_relays(&[fast.clone(), failing.clone(), pinned.clone()]);
+        assert_eq!(ranked, vec![pinned.peer_id, fast.peer_id, failing.peer_id]);
+    }
+
+    #[test]
+    fn active_relays_favoured_within_margin() {
+        let active = relay(Some(100), 0, false, true);
+        let close = relay(Some(90), 0, false, false);
+        let faster = relay(Some(50), 0, false, false);
+
+        let ranked = rank_relays(&[close.clone(), active.clone()]);
+        assert_eq!(ranked, vec![active.peer_id, close.peer_id]);
+
+        let ranked = rank_relays(&[active.clone(), faster.clone()]);
+        assert_eq!(ranked, vec![faster.peer_id, active.peer_id]);
+    }
+
+    #[test]
+    fn quorum_targets() {
+        assert_eq!(quorum_target(RelayQuorum::First, 3), 1);
+        assert_eq!(quorum_target(RelayQuorum::N(2), 3), 2);
+        assert_eq!(quorum_target(RelayQuorum::N(0), 3), 1);
+        assert_eq!(quorum_target(RelayQuorum::All, 3), 3);
+    }
+
+    async fn advance(
+        chain: &[PeerId],
+        current: Option<usize>,
+        available: &[PeerId],
+    ) -> (Option<ChainChange>, Vec<PeerId>) {
+        let available = available.iter().copied().collect::<HashSet<_>>();
+        let mut attempts = vec![];
+        let change = advance_chain(chain, current, |peer_id| {
+            attempts.push(peer_id);
+            let reserved = available.contains(&peer_id);
+            async move { reserved }
+        })
+        .await;
+        (change, attempts)
+    }
+
+    #[tokio::test]
+    async fn first_relay_of_chain_used_without_event() {
+        let chain = [PeerId::random(), PeerId::random()];
+
+        let (change, attempts) = advance(&chain, None, &chain).await;
+        assert_eq!(change, None);
+        assert_eq!(attempts, vec![chain[0]]);
+    }
+
+    #[tokio::test]
+    async fn chain_falls_back_to_next_relay() {
+        let chain = [PeerId::random(), PeerId::random(), PeerId::random()];
+
+        let (change, attempts) = advance(&chain, None, &chain[2..]).await;
+        assert_eq!(
+            change,
+            Some(ChainChange::Fallback {
+                peer_id: chain[2],
+                failed: chain[1],
+            })
+        );
+        assert_eq!(attempts, chain.to_vec());
+        assert!(matches!(
+            change.map(ConnectionEvent::from),
+            Some(ConnectionEvent::RelayFallback { peer_id, failed }) if peer_id == chain[2] && failed == chain[1]
+        ));
+
+        let (change, _) = advance(&chain, None, &[]).await;
+        assert_eq!(change, None);
+    }
+
+    #[tokio::test]
+    async fn chain_restores_earlier_relay() {
+        let chain = [PeerId::random(), PeerId::random(), PeerId::random()];
+
+        // Only the relays before the fallback relay are tried again
+        let (change, attempts) = advance(&chain, Some(1), &[]).await;
+        assert_eq!(change, None);
+        assert_eq!(attempts, vec![chain[0]]);
+
+        let (change, _) = advance(&chain, Some(2), &chain).await;
+        assert_eq!(
+            change,
+            Some(ChainChange::Restored {
+                peer_id: chain[0],
+                fallback: chain[2],
+            })
+        );
+        assert!(matches!(
+            change.map(ConnectionEvent::from),
+            Some(ConnectionEvent::RelayRestored { peer_id, fallback }) if peer_id == chain[0] && fallback == chain[2]
+        ));
+
+        let (change, attempts) = advance(&chain, Some(0), &chain).await;
+        assert_eq!(change, None);
+        assert!(attempts.is_empty());
+    }
+}
@@ -0,0 +1,82 @@
+//! Authenticated request-response channel between friends.
+//!
+//! Applications can use [`PeerRpc`] to exchange small custom payloads (eg game invites, payment requests)
+//! with friends under their own namespace without piggybacking on chat messages. Every request and
+//! response is signed and encrypted for the remote identity and is limited in size.
+use bytes::Bytes;
+use futures::{channel::oneshot, stream::BoxStream};
+use warp::{crypto::DID, error::Error};
+
+pub type RpcRequestStream = BoxStream<'static, RpcRequest>;
+
+/// Incoming request from a friend.
+/// The request should be answered with either [`RpcRequest::respond`] or [`RpcRequest::reject`]. If dropped
+/// without an answer, the requester will receive a rejection.
+#[derive(Debug)]
+pub struct RpcRequest {
+    sender: DID,
+    namespace: String,
+    payload: Bytes,
+    response: oneshot::Sender<Result<Bytes, String>>,
+}
+
+impl RpcRequest {
+    pub(crate) fn new(
+        sender: DID,
+        namespace: String,
+        payload: Bytes,
+        response: oneshot::Sender<Result<Bytes, String>>,
+    ) -> Self {
+        Self {
+            sender,
+            namespace,
+            payload,
+            response,
+        }
+    }
+}
+
+impl RpcRequest {
+    /// Identity that sent the request
+    pub fn sender(&self) -> &DID {
+        &self.sender
+    }
+
+    /// Namespace the request was sent under
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// Payload of the request
+    pub fn payload(&self) -> &Bytes {
+        &self.payload
+    }
+
+    /// Answer the request with a payload
+    pub fn respond(self, payload: impl Into<Bytes>) -> Result<(), Error> {
+        self.response
+            .send(Ok(payload.into()))
+            .map_err(|_| Error::SenderChannelUnavailable)
+    }
+
+    /// Reject the request with a reason that will be sent back to the requester
+    pub fn reject(self, reason: impl Into<String>) -> Result<(), Error> {
+        self.response
+            .send(Err(reason.into()))
+            .map_err(|_| Error::SenderChannelUnavailable)
+    }
+}
+
+#[async_trait::async_trait]
+pub trait PeerRpc: Sync + Send {
+    /// Send a request to a friend under a namespace and wait for their response
+    async fn rpc_request(&self, _: &DID, _: &str, _: Bytes) -> Result<Bytes, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Subscribe to incoming requests for a namespace.
+    /// Only a single subscriber can exist for a namespace at a time
+    async fn rpc_subscribe(&self, _: &str) -> Result<RpcRequestStream, Error> {
+        Err(Error::Unimplemented)
+    }
+}
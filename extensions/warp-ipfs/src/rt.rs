@@ -0,0 +1,49 @@
+//! Executor used by the stores to run their background tasks.
+//!
+//! Tasks are spawned onto the tokio runtime on native targets and onto the event loop of the browser with
+//! `spawn_local` on wasm32. Since wasm32 has no threads, futures only have to be `Send` on native targets.
+use std::future::Future;
+
+use async_rt::AbortableJoinHandle;
+use warp::error::Error;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub trait MaybeSend: Send {}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<T: Send> MaybeSend for T {}
+
+#[cfg(target_arch = "wasm32")]
+pub trait MaybeSend {}
+
+#[cfg(target_arch = "wasm32")]
+impl<T> MaybeSend for T {}
+
+/// Spawn a task that is aborted once every clone of its handle is dropped
+pub fn spawn_abortable<F>(fut: F) -> AbortableJoinHandle<F::Output>
+where
+    F: Future + MaybeSend + 'static,
+    F::Output: MaybeSend + 'static,
+{
+    async_rt::task::spawn_abortable(fut)
+}
+
+/// Run blocking work, such as image processing, without stalling the other tasks.
+/// On wasm32 there is no thread pool, so the work runs as part of the calling task.
+pub async fn spawn_blocking<F, R>(f: F) -> Result<R, Error>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        tokio::task::spawn_blocking(f)
+            .await
+            .map_err(anyhow::Error::from)
+            .map_err(Error::from)
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        Ok(f())
+    }
+}
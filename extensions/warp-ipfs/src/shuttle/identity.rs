@@ -3,6 +3,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::store::document::identity::IdentityDocument;
 
+pub mod organization;
 pub mod protocol;
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
@@ -0,0 +1,234 @@
+//! Documents of an organization, signed by the organization key.
+//!
+//! The organization key is the keypair of its directory, a dedicated shuttle node, so the organization is identified
+//! by the DID of the directory. Members are provisioned by the directory, which issues a [`MembershipCertificate`]
+//! for each of them, and the current set of members along with the revoked identities and the administrators is
+//! distributed to every member as an [`OrganizationRoster`].
+use chrono::{DateTime, Utc};
+use rust_ipfs::Keypair;
+use serde::{Deserialize, Serialize};
+use warp::{crypto::DID, error::Error};
+
+use crate::store::{DidExt, PeerIdExt};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MembershipCertificate {
+    pub organization: DID,
+    pub member: DID,
+    pub issued: DateTime<Utc>,
+    pub signature: String,
+}
+
+impl MembershipCertificate {
+    pub fn new(keypair: &Keypair, member: DID, issued: DateTime<Utc>) -> Result<Self, Error> {
+        let organization = keypair.to_did()?;
+
+        let mut certificate = Self {
+            organization,
+            member,
+            issued,
+            signature: String::new(),
+        };
+
+        let signature = keypair.sign(&certificate.construct()).expect("not RSA");
+        certificate.signature = bs58::encode(signature).into_string();
+
+        Ok(certificate)
+    }
+
+    /// Verify that the certificate was issued by `organization`
+    pub fn verify(&self, organization: &DID) -> Result<(), Error> {
+        if self.organization.ne(organization) {
+            return Err(Error::InvalidSignature);
+        }
+
+        let organization_pk = self.organization.to_public_key()?;
+        let signature = bs58::decode(&self.signature).into_vec()?;
+
+        if !organization_pk.verify(&self.construct(), &signature) {
+            return Err(Error::InvalidSignature);
+        }
+
+        Ok(())
+    }
+
+    fn construct(&self) -> Vec<u8> {
+        warp::crypto::hash::sha256_iter(
+            [
+                Some(self.organization.to_string().as_bytes().to_vec()),
+                Some(self.member.to_string().as_bytes().to_vec()),
+                Some(self.issued.timestamp_millis().to_be_bytes().to_vec()),
+            ]
+            .into_iter(),
+            None,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OrganizationRoster {
+    pub organization: DID,
+    pub members: Vec<MembershipCertificate>,
+    pub revoked: Vec<DID>,
+    /// Identities that administer the organization, eg by issuing the retention policy of conversations
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub admins: Vec<DID>,
+    pub updated: DateTime<Utc>,
+    pub signature: String,
+}
+
+impl OrganizationRoster {
+    pub fn new(
+        keypair: &Keypair,
+        members: Vec<MembershipCertificate>,
+        revoked: Vec<DID>,
+        admins: Vec<DID>,
+        updated: DateTime<Utc>,
+    ) -> Result<Self, Error> {
+        let organization = keypair.to_did()?;
+
+        let mut roster = Self {
+            organization,
+            members,
+            revoked,
+            admins,
+            updated,
+            signature: String::new(),
+        };
+
+        let signature = keypair.sign(&roster.construct()).expect("not RSA");
+        roster.signature = bs58::encode(signature).into_string();
+
+        Ok(roster)
+    }
+
+    /// Verify that the roster, and every certificate within it, was issued by `organization`
+    pub fn verify(&self, organization: &DID) -> Result<(), Error> {
+        if self.organization.ne(organization) {
+            return Err(Error::InvalidSignature);
+        }
+
+        let organization_pk = self.organization.to_public_key()?;
+        let signature = bs58::decode(&self.signature).into_vec()?;
+
+        if !organization_pk.verify(&self.construct(), &signature) {
+            return Err(Error::InvalidSignature);
+        }
+
+        for certificate in &self.members {
+            certificate.verify(organization)?;
+            if self.revoked.contains(&certificate.member) {
+                return Err(Error::InvalidSignature);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn is_member(&self, did: &DID) -> bool {
+        self.members
+            .iter()
+            .any(|certificate| certificate.member.eq(did))
+    }
+
+    pub fn is_revoked(&self, did: &DID) -> bool {
+        self.revoked.contains(did)
+    }
+
+    pub fn is_admin(&self, did: &DID) -> bool {
+        self.admins.contains(did)
+    }
+
+    pub fn certificate(&self, did: &DID) -> Option<&MembershipCertificate> {
+        self.members
+            .iter()
+            .find(|certificate| certificate.member.eq(did))
+    }
+
+    fn construct(&self) -> Vec<u8> {
+        let members = self
+            .members
+            .iter()
+            .map(|certificate| certificate.signature.as_bytes().to_vec());
+        let revoked = self
+            .revoked
+            .iter()
+            .map(|did| did.to_string().as_bytes().to_vec());
+        // prefixed so an administrator can not be mistaken for a revoked identity
+        let admins = self
+            .admins
+            .iter()
+            .map(|did| format!("admin:{did}").into_bytes());
+
+        warp::crypto::hash::sha256_iter(
+            [
+                Some(self.organization.to_string().as_bytes().to_vec()),
+                Some(self.updated.timestamp_millis().to_be_bytes().to_vec()),
+            ]
+            .into_iter()
+            .chain(members.map(Some))
+            .chain(revoked.map(Some))
+            .chain(admins.map(Some)),
+            None,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+    use rust_ipfs::Keypair;
+
+    use super::{MembershipCertificate, OrganizationRoster};
+    use crate::store::PeerIdExt;
+
+    #[test]
+    fn roster_from_another_key_is_rejected() -> anyhow::Result<()> {
+        let organization = Keypair::generate_ed25519();
+        let impostor = Keypair::generate_ed25519();
+        let member = Keypair::generate_ed25519().to_did()?;
+
+        let certificate = MembershipCertificate::new(&impostor, member, Utc::now())?;
+        let roster =
+            OrganizationRoster::new(&impostor, vec![certificate], vec![], vec![], Utc::now())?;
+
+        assert!(roster.verify(&organization.to_did()?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn revoked_member_can_not_be_added_back() -> anyhow::Result<()> {
+        let organization = Keypair::generate_ed25519();
+        let member = Keypair::generate_ed25519().to_did()?;
+
+        let certificate = MembershipCertificate::new(&organization, member.clone(), Utc::now())?;
+        let mut roster =
+            OrganizationRoster::new(&organization, vec![], vec![member], vec![], Utc::now())?;
+        roster.verify(&organization.to_did()?)?;
+
+        roster.members.push(certificate);
+        assert!(roster.verify(&organization.to_did()?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn administrators_are_signed() -> anyhow::Result<()> {
+        let organization = Keypair::generate_ed25519();
+        let admin = Keypair::generate_ed25519().to_did()?;
+        let member = Keypair::generate_ed25519().to_did()?;
+
+        let mut roster = OrganizationRoster::new(
+            &organization,
+            vec![],
+            vec![],
+            vec![admin.clone()],
+            Utc::now(),
+        )?;
+        roster.verify(&organization.to_did()?)?;
+        assert!(roster.is_admin(&admin));
+
+        roster.admins.push(member);
+        assert!(roster.verify(&organization.to_did()?).is_err());
+        Ok(())
+    }
+}
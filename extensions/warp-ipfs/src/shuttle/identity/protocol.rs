@@ -3,6 +3,7 @@ use rust_ipfs::Keypair;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use warp::{crypto::DID, multipass::identity::ShortId};
 
+use super::organization::{MembershipCertificate, OrganizationRoster};
 use crate::store::identity::RequestResponsePayload;
 use crate::store::{
     document::identity::IdentityDocument,
@@ -30,6 +31,7 @@ pub enum Request {
     Mailbox(Mailbox),
     Synchronized(Synchronized),
     Lookup(Lookup),
+    Organization(Organization),
 }
 
 impl From<Register> for Request {
@@ -56,6 +58,12 @@ impl From<Lookup> for Request {
     }
 }
 
+impl From<Organization> for Request {
+    fn from(organization: Organization) -> Self {
+        Request::Organization(organization)
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Response {
@@ -63,6 +71,7 @@ pub enum Response {
     SynchronizedResponse(SynchronizedResponse),
     MailboxResponse(MailboxResponse),
     LookupResponse(LookupResponse),
+    OrganizationResponse(OrganizationResponse),
     Ack,
     InvalidPayload,
     Error(String),
@@ -92,6 +101,12 @@ impl From<LookupResponse> for Response {
     }
 }
 
+impl From<OrganizationResponse> for Response {
+    fn from(res: OrganizationResponse) -> Self {
+        Response::OrganizationResponse(res)
+    }
+}
+
 #[allow(clippy::large_enum_variant)]
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -167,6 +182,7 @@ pub enum LookupResponse {
 pub enum Register {
     IsRegistered,
     RegisterIdentity { root_cid: Cid },
+    IsUsernameAvailable { username: String },
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -183,6 +199,7 @@ pub enum RegisterError {
     IdentityExist,
     IdentityVerificationFailed,
     NotRegistered,
+    UsernameTaken,
     None,
 }
 
@@ -212,3 +229,33 @@ pub enum SynchronizedError {
     InvalidPayload { msg: String },
     InvalodRecord { msg: String },
 }
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Organization {
+    /// Provision an identity as a member of the organization. Only accepted from an administrator
+    Enroll { did: DID },
+    /// Revoke the membership of an identity. Only accepted from an administrator
+    Revoke { did: DID },
+    /// Fetch the current roster of the organization
+    Roster,
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrganizationResponse {
+    Certificate(MembershipCertificate),
+    Roster(OrganizationRoster),
+    Error(OrganizationError),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrganizationError {
+    NotEnabled,
+    Unauthorized,
+    AlreadyMember,
+    NotMember,
+    InternalError,
+}
@@ -10,15 +10,18 @@ use rust_ipfs::{
     FDLimit, Ipfs, IpfsPath, Keypair, Multiaddr, NetworkBehaviour, PeerId, UninitializedIpfs,
 };
 use std::{path::Path, time::Duration};
+use warp::crypto::DID;
 use warp::error::{Error as WarpError, Error};
 
 // use crate::shuttle::identity::protocol::RegisterError;
 use super::{
     identity::{
         self,
+        organization::OrganizationRoster,
         protocol::{
-            payload_message_construct, Lookup, LookupResponse, Register, RegisterResponse,
-            Response, Synchronized, SynchronizedError, SynchronizedResponse,
+            payload_message_construct, Lookup, LookupResponse, Organization, OrganizationError,
+            OrganizationResponse, Register, RegisterResponse, Response, Synchronized,
+            SynchronizedError, SynchronizedResponse,
         },
     },
     message::{
@@ -71,11 +74,13 @@ struct ShuttleTask {
     root_storage: super::store::root::RootStorage,
     identity_storage: super::store::identity::IdentityStorage,
     message_storage: super::store::messages::MessageStorage,
+    organization_storage: super::store::organization::OrganizationStorage,
     subscriptions: super::subscription_stream::Subscriptions,
     requests: FuturesUnordered<BoxFuture<'static, ()>>,
     identity_request_response: IdReqSt,
     message_request_response: MsgReqSt,
     identity_announcement: SubscriptionStream,
+    unique_usernames: bool,
 }
 
 impl ShuttleServer {
@@ -93,6 +98,8 @@ impl ShuttleServer {
         gc_duration: Option<Duration>,
         gc_trigger: Option<GCTrigger>,
         ext: bool,
+        unique_usernames: bool,
+        organization_admins: &[DID],
     ) -> anyhow::Result<Self> {
         let path = path.map(|p| p.as_ref().to_path_buf());
 
@@ -197,6 +204,9 @@ impl ShuttleServer {
         let root = super::store::root::RootStorage::new(&ipfs).await;
         let identity = super::store::identity::IdentityStorage::new(&ipfs, &root).await;
         let message = super::store::messages::MessageStorage::new(&ipfs, &root, &identity).await;
+        let organization =
+            super::store::organization::OrganizationStorage::new(&ipfs, &root, organization_admins)
+                .await;
 
         println!(
             "Identities Registered: {}",
@@ -235,10 +245,12 @@ impl ShuttleServer {
             root_storage: root,
             identity_storage: identity,
             message_storage: message,
+            organization_storage: organization,
             requests,
             identity_request_response,
             message_request_response,
             identity_announcement,
+            unique_usernames,
         };
 
         let _handle = async_rt::task::spawn_abortable(async move {
@@ -315,6 +327,8 @@ impl ShuttleTask {
         let ipfs = self.ipfs.clone();
         let identity_storage = self.identity_storage.clone();
         let mut subscriptions = self.subscriptions.clone();
+        let unique_usernames = self.unique_usernames;
+        let organization_storage = self.organization_storage.clone();
 
         let fut = async move {
             let keypair = ipfs.keypair();
@@ -411,6 +425,32 @@ impl ShuttleTask {
                         .send_response(sender_peer_id, id, (protocols::SHUTTLE_IDENTITY, bytes))
                         .await;
                 }
+                identity::protocol::Request::Register(Register::IsUsernameAvailable {
+                    username,
+                }) => {
+                    let did = payload.sender().to_did().ok();
+
+                    let response = match unique_usernames
+                        && identity_storage.handle_taken(&username, did.as_ref()).await
+                    {
+                        true => RegisterResponse::Error(
+                            identity::protocol::RegisterError::UsernameTaken,
+                        ),
+                        false => RegisterResponse::Ok,
+                    };
+
+                    let payload = payload_message_construct(
+                        keypair,
+                        None,
+                        Response::RegisterResponse(response),
+                    )
+                    .expect("Valid payload construction");
+
+                    let bytes = payload.to_bytes().expect("valid deserialization");
+                    _ = ipfs
+                        .send_response(sender_peer_id, id, (protocols::SHUTTLE_IDENTITY, bytes))
+                        .await;
+                }
                 identity::protocol::Request::Register(Register::RegisterIdentity { root_cid }) => {
                     tracing::debug!(%sender, %root_cid, "preloading root document");
                     if let Err(e) = ipfs.fetch(&root_cid).recursive().await {
@@ -476,6 +516,29 @@ impl ShuttleTask {
                         return;
                     }
 
+                    if unique_usernames
+                        && identity_storage
+                            .handle_taken(document.handle(), Some(&document.did))
+                            .await
+                    {
+                        tracing::warn!(%document.did, handle = %document.handle(), "Handle is already taken");
+                        let payload = payload_message_construct(
+                            keypair,
+                            None,
+                            Response::RegisterResponse(RegisterResponse::Error(
+                                identity::protocol::RegisterError::UsernameTaken,
+                            )),
+                        )
+                        .expect("Valid payload construction");
+
+                        let bytes = payload.to_bytes().expect("valid deserialization");
+                        _ = ipfs
+                            .send_response(sender_peer_id, id, (protocols::SHUTTLE_IDENTITY, bytes))
+                            .await;
+
+                        return;
+                    }
+
                     if let Err(e) = identity_storage.register(&document, root_cid).await {
                         tracing::warn!(%document.did, error = %e, "Unable to register identity");
                         let res_error = match e {
@@ -805,6 +868,12 @@ impl ShuttleTask {
                     };
 
                     tracing::debug!(%did, %package, "root document preloaded");
+
+                    if document.handle() != current_document.handle() {
+                        tracing::warn!(%did, handle = %document.handle(), "Handle cannot be changed. Rejecting document");
+                        return;
+                    }
+
                     if let Err(e) = identity_storage.update_user_document(&did, package).await {
                         tracing::warn!(%did, %package, error = %e, "unable to store document");
                         return;
@@ -920,6 +989,27 @@ impl ShuttleTask {
                         .send_response(sender_peer_id, id, (protocols::SHUTTLE_IDENTITY, bytes))
                         .await;
                 }
+                identity::protocol::Request::Organization(request) => {
+                    let event = match process_organization_request(
+                        &ipfs,
+                        &organization_storage,
+                        *payload.sender(),
+                        request,
+                    )
+                    .await
+                    {
+                        Ok(response) => response,
+                        Err(e) => OrganizationResponse::Error(e),
+                    };
+
+                    let payload = payload_message_construct(keypair, None, Response::from(event))
+                        .expect("Valid payload construction");
+
+                    let bytes = payload.to_bytes().expect("valid deserialization");
+                    _ = ipfs
+                        .send_response(sender_peer_id, id, (protocols::SHUTTLE_IDENTITY, bytes))
+                        .await;
+                }
             }
         };
 
@@ -1079,6 +1169,100 @@ impl ShuttleTask {
     }
 }
 
+async fn process_organization_request(
+    ipfs: &Ipfs,
+    organization_storage: &super::store::organization::OrganizationStorage,
+    sender: PeerId,
+    request: Organization,
+) -> Result<OrganizationResponse, OrganizationError> {
+    if !organization_storage.enabled().await {
+        return Err(OrganizationError::NotEnabled);
+    }
+
+    let did = sender
+        .to_did()
+        .map_err(|_| OrganizationError::Unauthorized)?;
+
+    let roster = match request {
+        Organization::Roster => {
+            let roster = organization_storage
+                .roster()
+                .await
+                .ok_or(OrganizationError::NotMember)?;
+
+            // The roster is only shared with the members of the organization and its administrators
+            if !roster.is_member(&did) && !organization_storage.is_admin(&did).await {
+                return Err(OrganizationError::Unauthorized);
+            }
+
+            return Ok(OrganizationResponse::Roster(roster));
+        }
+        Organization::Enroll { did: member } => {
+            if !organization_storage.is_admin(&did).await {
+                return Err(OrganizationError::Unauthorized);
+            }
+
+            tracing::info!(admin = %did, %member, "enrolling member");
+
+            let (certificate, roster) =
+                organization_storage
+                    .enroll(&member)
+                    .await
+                    .map_err(|e| match e {
+                        Error::IdentityExist => OrganizationError::AlreadyMember,
+                        _ => OrganizationError::InternalError,
+                    })?;
+
+            publish_roster(ipfs, &roster).await;
+            return Ok(OrganizationResponse::Certificate(certificate));
+        }
+        Organization::Revoke { did: member } => {
+            if !organization_storage.is_admin(&did).await {
+                return Err(OrganizationError::Unauthorized);
+            }
+
+            tracing::info!(admin = %did, %member, "revoking member");
+
+            organization_storage
+                .revoke(&member)
+                .await
+                .map_err(|e| match e {
+                    Error::IdentityDoesntExist => OrganizationError::NotMember,
+                    _ => OrganizationError::InternalError,
+                })?
+        }
+    };
+
+    publish_roster(ipfs, &roster).await;
+    Ok(OrganizationResponse::Roster(roster))
+}
+
+/// Push the roster to the members of the organization. The topic can be subscribed to by anyone, so the roster is
+/// encrypted to the members and is only readable by them
+async fn publish_roster(ipfs: &Ipfs, roster: &OrganizationRoster) {
+    let members = roster.members.iter().map(|certificate| &certificate.member);
+
+    let payload = match PayloadBuilder::new(ipfs.keypair(), roster.clone())
+        .add_recipients(members)
+        .and_then(|builder| builder.build())
+    {
+        Ok(payload) => payload,
+        Err(e) => {
+            tracing::warn!(error = %e, "unable to construct organization roster payload");
+            return;
+        }
+    };
+
+    let bytes = payload.to_bytes().expect("valid deserialization");
+
+    if let Err(e) = ipfs
+        .pubsub_publish(roster.organization.organization(), bytes)
+        .await
+    {
+        tracing::warn!(error = %e, "unable to publish organization roster");
+    }
+}
+
 mod ext_behaviour {
     use std::task::{Context, Poll};
 
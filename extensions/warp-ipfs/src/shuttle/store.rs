@@ -1,3 +1,4 @@
 pub mod identity;
 pub mod messages;
+pub mod organization;
 pub mod root;
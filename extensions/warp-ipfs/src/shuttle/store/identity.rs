@@ -56,6 +56,12 @@ impl IdentityStorage {
         inner.contains(did).await
     }
 
+    /// Check if a registered identity, other than `exclude`, has a handle that collides with the handle provided
+    pub async fn handle_taken(&self, handle: &str, exclude: Option<&DID>) -> bool {
+        let inner = &*self.inner.read().await;
+        inner.handle_taken(handle, exclude).await
+    }
+
     pub async fn fetch_mailbox(
         &self,
         did: DID,
@@ -127,6 +133,19 @@ impl IdentityStorageInner {
         self.ipfs.get_dag(path).local().await.is_ok()
     }
 
+    async fn handle_taken(&self, handle: &str, exclude: Option<&DID>) -> bool {
+        let skeleton = username_skeleton(handle);
+
+        self.list()
+            .await
+            .any(|document| {
+                let taken = exclude.is_none_or(|did| document.did.ne(did))
+                    && username_skeleton(document.handle()) == skeleton;
+                futures::future::ready(taken)
+            })
+            .await
+    }
+
     async fn register(&mut self, document: &IdentityDocument, root_cid: Cid) -> Result<(), Error> {
         document.verify()?;
         let mut list: BTreeMap<String, Cid> = match self.users {
@@ -315,14 +334,7 @@ impl IdentityStorageInner {
 
                 let list = if split_data.len() != 2 {
                     list_stream
-                        .filter(|document| {
-                            futures::future::ready(
-                                document
-                                    .username
-                                    .to_lowercase()
-                                    .eq(&username.to_lowercase()),
-                            )
-                        })
+                        .filter(|document| futures::future::ready(document.name_eq(&username)))
                         .collect::<Vec<_>>()
                         .await
                 } else {
@@ -334,7 +346,7 @@ impl IdentityStorageInner {
                             list_stream
                                 .filter(|ident| {
                                     futures::future::ready(
-                                        ident.username.to_lowercase().eq(&name)
+                                        ident.name_eq(&name)
                                             && String::from_utf8_lossy(&ident.short_id)
                                                 .to_lowercase()
                                                 .eq(&code),
@@ -354,14 +366,7 @@ impl IdentityStorageInner {
             Lookup::Username { username, .. } => {
                 //TODO: Score against invalid username scheme
                 let list = list_stream
-                    .filter(|document| {
-                        futures::future::ready(
-                            document
-                                .username
-                                .to_lowercase()
-                                .contains(&username.to_lowercase()),
-                        )
-                    })
+                    .filter(|document| futures::future::ready(document.name_matches(&username)))
                     .collect::<Vec<_>>()
                     .await;
 
@@ -547,3 +552,69 @@ impl IdentityStorageInner {
     //     Ok(())
     // }
 }
+
+/// Reduce a username to a form where names that differ only by case, separators or
+/// similar looking characters (eg "JohnDoe", "john_doe", "j0hnd0e", "јоhndое") are equal
+fn username_skeleton(username: &str) -> String {
+    let skeleton = username
+        .trim()
+        .chars()
+        .flat_map(char::to_lowercase)
+        .filter_map(|c| match c {
+            c if c.is_whitespace() => None,
+            '_' | '-' | '.' | '\u{200B}'..='\u{200D}' | '\u{FEFF}' | '\u{0300}'..='\u{036F}' => {
+                None
+            }
+            '0' | 'о' | 'ο' => Some('o'),
+            '1' | 'i' | '|' | '!' | 'і' | 'ι' => Some('l'),
+            '3' | 'е' => Some('e'),
+            '4' | '@' | 'а' | 'α' => Some('a'),
+            '5' | '$' | 'ѕ' => Some('s'),
+            '7' | 'т' | 'τ' => Some('t'),
+            'в' | 'ь' => Some('b'),
+            'к' | 'κ' => Some('k'),
+            'м' => Some('m'),
+            'н' => Some('h'),
+            'р' | 'ρ' => Some('p'),
+            'с' => Some('c'),
+            'у' => Some('y'),
+            'х' | 'χ' => Some('x'),
+            'ј' => Some('j'),
+            'ԁ' => Some('d'),
+            'ν' => Some('v'),
+            'υ' => Some('u'),
+            c => Some(c),
+        })
+        .collect::<String>();
+
+    skeleton
+        .replace("rn", "m")
+        .replace("vv", "w")
+        .replace("cl", "d")
+}
+
+#[cfg(test)]
+mod test {
+    use super::username_skeleton;
+
+    #[test]
+    fn username_skeleton_collisions() {
+        let skeleton = username_skeleton("JohnDoe");
+
+        for username in [
+            "johndoe",
+            "JOHNDOE",
+            "John_Doe",
+            "john.doe",
+            "j0hnd0e",
+            "\u{0458}\u{043e}hnd\u{043e}\u{0435}",
+            "john\u{200B}doe",
+        ] {
+            assert_eq!(username_skeleton(username), skeleton, "{username}");
+        }
+
+        assert_eq!(username_skeleton("modern"), username_skeleton("rnodern"));
+        assert_eq!(username_skeleton("william"), username_skeleton("wi11iam"));
+        assert_ne!(username_skeleton("JohnDoe"), username_skeleton("JaneDoe"));
+    }
+}
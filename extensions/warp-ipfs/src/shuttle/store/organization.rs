@@ -0,0 +1,173 @@
+use std::sync::Arc;
+
+use chrono::Utc;
+use ipld_core::cid::Cid;
+use rust_ipfs::Ipfs;
+use tokio::sync::RwLock;
+use warp::{crypto::DID, error::Error};
+
+use super::root::RootStorage;
+use crate::shuttle::identity::organization::{MembershipCertificate, OrganizationRoster};
+
+/// Roster of the organization hosted by the directory. The directory is only enabled when there is at least one
+/// administrator, who are the only identities allowed to enroll or revoke members
+#[derive(Debug, Clone)]
+pub struct OrganizationStorage {
+    inner: Arc<RwLock<OrganizationStorageInner>>,
+}
+
+impl OrganizationStorage {
+    pub async fn new(ipfs: &Ipfs, root: &RootStorage, admins: &[DID]) -> Self {
+        let root_dag = root.get_root().await;
+
+        let cid = root_dag.organization;
+
+        let roster = match cid {
+            Some(cid) => ipfs
+                .get_dag(cid)
+                .local()
+                .deserialized::<OrganizationRoster>()
+                .await
+                .ok(),
+            None => None,
+        };
+
+        let mut inner = OrganizationStorageInner {
+            ipfs: ipfs.clone(),
+            root: root.clone(),
+            admins: admins.to_vec(),
+            roster,
+            cid,
+        };
+
+        // The roster is issued again if the administrators changed since it was last issued
+        if let Some(roster) = inner
+            .roster
+            .clone()
+            .filter(|roster| roster.admins != admins)
+        {
+            if let Err(e) = inner.update(roster.members, roster.revoked).await {
+                tracing::warn!(error = %e, "unable to update administrators of the roster");
+            }
+        }
+
+        let inner = Arc::new(RwLock::new(inner));
+
+        Self { inner }
+    }
+
+    pub async fn enabled(&self) -> bool {
+        let inner = &*self.inner.read().await;
+        !inner.admins.is_empty()
+    }
+
+    pub async fn is_admin(&self, did: &DID) -> bool {
+        let inner = &*self.inner.read().await;
+        inner.admins.contains(did)
+    }
+
+    pub async fn roster(&self) -> Option<OrganizationRoster> {
+        let inner = &*self.inner.read().await;
+        inner.roster.clone()
+    }
+
+    /// Issue a certificate for `did`, returning the certificate along with the updated roster
+    pub async fn enroll(
+        &self,
+        did: &DID,
+    ) -> Result<(MembershipCertificate, OrganizationRoster), Error> {
+        let inner = &mut *self.inner.write().await;
+        inner.enroll(did).await
+    }
+
+    /// Revoke the membership of `did`, returning the updated roster
+    pub async fn revoke(&self, did: &DID) -> Result<OrganizationRoster, Error> {
+        let inner = &mut *self.inner.write().await;
+        inner.revoke(did).await
+    }
+}
+
+#[derive(Debug)]
+struct OrganizationStorageInner {
+    ipfs: Ipfs,
+    root: RootStorage,
+    admins: Vec<DID>,
+    roster: Option<OrganizationRoster>,
+    cid: Option<Cid>,
+}
+
+impl OrganizationStorageInner {
+    async fn enroll(
+        &mut self,
+        did: &DID,
+    ) -> Result<(MembershipCertificate, OrganizationRoster), Error> {
+        let (mut members, mut revoked) = self
+            .roster
+            .clone()
+            .map(|roster| (roster.members, roster.revoked))
+            .unwrap_or_default();
+
+        if members.iter().any(|certificate| certificate.member.eq(did)) {
+            return Err(Error::IdentityExist);
+        }
+
+        let keypair = self.ipfs.keypair();
+
+        let certificate = MembershipCertificate::new(keypair, did.clone(), Utc::now())?;
+
+        members.push(certificate.clone());
+        revoked.retain(|revoked| revoked.ne(did));
+
+        let roster = self.update(members, revoked).await?;
+        Ok((certificate, roster))
+    }
+
+    async fn revoke(&mut self, did: &DID) -> Result<OrganizationRoster, Error> {
+        let (mut members, mut revoked) = self
+            .roster
+            .clone()
+            .map(|roster| (roster.members, roster.revoked))
+            .unwrap_or_default();
+
+        if !members.iter().any(|certificate| certificate.member.eq(did)) {
+            return Err(Error::IdentityDoesntExist);
+        }
+
+        members.retain(|certificate| certificate.member.ne(did));
+        revoked.push(did.clone());
+
+        self.update(members, revoked).await
+    }
+
+    async fn update(
+        &mut self,
+        members: Vec<MembershipCertificate>,
+        revoked: Vec<DID>,
+    ) -> Result<OrganizationRoster, Error> {
+        let roster = OrganizationRoster::new(
+            self.ipfs.keypair(),
+            members,
+            revoked,
+            self.admins.clone(),
+            Utc::now(),
+        )?;
+
+        let cid = self.ipfs.put_dag(roster.clone()).await?;
+
+        self.ipfs.insert_pin(cid).await?;
+
+        let old_cid = self.cid.replace(cid);
+
+        if let Some(old_cid) = old_cid {
+            if old_cid != cid && self.ipfs.is_pinned(&old_cid).await.unwrap_or_default() {
+                _ = self.ipfs.remove_pin(old_cid).await;
+            }
+        }
+
+        self.root.set_organization(cid).await?;
+
+        self.roster.replace(roster.clone());
+
+        Ok(roster)
+    }
+}
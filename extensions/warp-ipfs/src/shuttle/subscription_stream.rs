@@ -1,3 +1,4 @@
+use async_rt::AbortableJoinHandle;
 use futures::SinkExt;
 use futures::StreamExt;
 use pollable_map::stream::StreamMap;
@@ -8,6 +9,7 @@ use crate::store::topics::{ConversationTopic, PeerTopic};
 #[derive(Clone)]
 pub struct Subscriptions {
     tx: futures::channel::mpsc::Sender<SubscriptionCommand>,
+    _handle: AbortableJoinHandle<()>,
 }
 
 impl Subscriptions {
@@ -22,7 +24,7 @@ impl Subscriptions {
 
         let identity = identity.clone();
         let message = message.clone();
-        tokio::spawn(async move {
+        let _handle = async_rt::task::spawn_abortable(async move {
             {
                 let mut list = identity.list().await;
                 let mut conversations = message.list_conversations().await.boxed();
@@ -41,7 +43,7 @@ impl Subscriptions {
             task.run().await
         });
 
-        Self { tx }
+        Self { tx, _handle }
     }
 
     pub async fn subscribe(&mut self, topic: String) -> anyhow::Result<()> {
@@ -0,0 +1,285 @@
+//! Cold storage tiering of the files in constellation.
+//!
+//! With a [`ColdStoragePolicy`] set, the content of files that were not modified within [`ColdStoragePolicy::age`]
+//! is unpinned and its blocks are removed from the repo, while the file stays in the index along with its thumbnail
+//! and the reference to its content. Reading the file fetches the content from the network again without pinning it.
+//! Files can be marked to be kept locally, which exempts them from the policy and restores the content of a file that
+//! is already in cold storage.
+//!
+//! Note: The content of a file in cold storage is only recoverable for as long as a peer (eg another device of the
+//! account or a member of the conversation it was attached to) still provides it.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+use chrono::{DateTime, Utc};
+use parking_lot::RwLock;
+use rust_ipfs::Ipfs;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use warp::{constellation::file::File, error::Error};
+
+use super::{ds_key::DataStoreKey, files::file_cid};
+use crate::config::ColdStoragePolicy;
+
+/// Files in cold storage, and files that could be moved to cold storage under the current policy
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ColdStorageReport {
+    pub evicted: usize,
+    pub evicted_bytes: usize,
+    pub reclaimable: usize,
+    pub reclaimable_bytes: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EvictedFile {
+    size: usize,
+    evicted: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ColdStorageState {
+    evicted: BTreeMap<Uuid, EvictedFile>,
+    pinned_locally: BTreeSet<Uuid>,
+}
+
+#[derive(Clone)]
+pub struct ColdStorage {
+    ipfs: Ipfs,
+    policy: Option<ColdStoragePolicy>,
+    state: Arc<RwLock<ColdStorageState>>,
+}
+
+impl ColdStorage {
+    pub async fn new(ipfs: &Ipfs, policy: Option<ColdStoragePolicy>) -> Self {
+        let key = ipfs.cold_storage();
+
+        let state = ipfs
+            .repo()
+            .data_store()
+            .get(key.as_bytes())
+            .await
+            .unwrap_or_default()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+
+        Self {
+            ipfs: ipfs.clone(),
+            policy,
+            state: Arc::new(RwLock::new(state)),
+        }
+    }
+
+    pub fn policy(&self) -> Option<ColdStoragePolicy> {
+        self.policy
+    }
+
+    pub fn is_evicted(&self, id: Uuid) -> bool {
+        self.state.read().evicted.contains_key(&id)
+    }
+
+    fn is_eligible(&self, file: &File, now: DateTime<Utc>) -> bool {
+        let Some(policy) = self.policy else {
+            return false;
+        };
+
+        let state = &*self.state.read();
+
+        if state.pinned_locally.contains(&file.id()) || state.evicted.contains_key(&file.id()) {
+            return false;
+        }
+
+        let age = now.signed_duration_since(file.modified());
+
+        file.size() >= policy.min_size && age.to_std().is_ok_and(|age| age >= policy.age)
+    }
+
+    /// Report of the files in cold storage and of the files that are eligible to be moved to cold storage
+    pub fn report(&self, files: &[File]) -> ColdStorageReport {
+        let now = Utc::now();
+        let mut report = ColdStorageReport::default();
+
+        for file in files {
+            if self.is_evicted(file.id()) {
+                report.evicted += 1;
+                report.evicted_bytes += file.size();
+            } else if self.is_eligible(file, now) {
+                report.reclaimable += 1;
+                report.reclaimable_bytes += file.size();
+            }
+        }
+
+        report
+    }
+
+    /// Move the content of every eligible file to cold storage
+    pub async fn apply(&self, files: &[File]) -> ColdStorageReport {
+        let now = Utc::now();
+
+        // Files that were removed from the index, or whose content was pinned again, are no longer tracked
+        let evicted = self
+            .state
+            .read()
+            .evicted
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+
+        let mut stale = vec![];
+        for id in evicted {
+            let Some(file) = files.iter().find(|file| file.id() == id) else {
+                stale.push(id);
+                continue;
+            };
+            if let Ok(cid) = file_cid(file) {
+                if self.ipfs.is_pinned(cid).await.unwrap_or_default() {
+                    stale.push(id);
+                }
+            }
+        }
+
+        let mut changed = !stale.is_empty();
+
+        {
+            let state = &mut *self.state.write();
+            for id in stale {
+                state.evicted.remove(&id);
+            }
+            let ids = files.iter().map(File::id).collect::<BTreeSet<_>>();
+            state.pinned_locally.retain(|id| ids.contains(id));
+        }
+
+        for file in files.iter().filter(|file| self.is_eligible(file, now)) {
+            if let Err(e) = self.evict(file).await {
+                tracing::warn!(file = %file.name(), error = %e, "unable to move file to cold storage");
+                continue;
+            }
+
+            self.state.write().evicted.insert(
+                file.id(),
+                EvictedFile {
+                    size: file.size(),
+                    evicted: now,
+                },
+            );
+            changed = true;
+        }
+
+        if changed {
+            self.save().await;
+        }
+
+        self.report(files)
+    }
+
+    /// Keep the content of the file locally regardless of the policy, fetching it again if it is in cold storage
+    pub async fn set_pinned_locally(&self, file: &File, pinned: bool) -> Result<(), Error> {
+        if pinned {
+            let cid = file_cid(file)?;
+            if !self.ipfs.is_pinned(cid).await? {
+                self.ipfs.insert_pin(cid).recursive().await?;
+            }
+        }
+
+        let changed = {
+            let state = &mut *self.state.write();
+            match pinned {
+                true => {
+                    state.evicted.remove(&file.id());
+                    state.pinned_locally.insert(file.id())
+                }
+                false => state.pinned_locally.remove(&file.id()),
+            }
+        };
+
+        if changed {
+            self.save().await;
+        }
+
+        Ok(())
+    }
+
+    async fn evict(&self, file: &File) -> Result<(), Error> {
+        let cid = file_cid(file)?;
+
+        if self.ipfs.is_pinned(cid).await? {
+            self.ipfs.remove_pin(cid).recursive().await?;
+        }
+
+        self.ipfs.remove_block(cid, true).await?;
+        Ok(())
+    }
+
+    async fn save(&self) {
+        let key = self.ipfs.cold_storage();
+
+        let Ok(bytes) = serde_json::to_vec(&*self.state.read()) else {
+            return;
+        };
+
+        if let Err(e) = self
+            .ipfs
+            .repo()
+            .data_store()
+            .put(key.as_bytes(), &bytes)
+            .await
+        {
+            tracing::warn!(error = %e, "unable to store cold storage state");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use chrono::Utc;
+    use rust_ipfs::UninitializedIpfsDefault;
+    use warp::constellation::file::File;
+
+    use super::ColdStorage;
+    use crate::config::ColdStoragePolicy;
+
+    #[tokio::test]
+    async fn old_files_are_moved_to_cold_storage() -> anyhow::Result<()> {
+        let ipfs = UninitializedIpfsDefault::new()
+            .start()
+            .await
+            .expect("constructed ipfs instance");
+
+        let mut files = vec![];
+        for (name, age) in [("old.txt", 30), ("new.txt", 0)] {
+            let data = Bytes::from(format!("content of {name}"));
+            let path = ipfs.add_unixfs(data.clone()).await?;
+
+            let file = File::new(name);
+            file.set_reference(&path.to_string());
+            file.set_size(data.len());
+            file.set_modified(Some(Utc::now() - chrono::Duration::days(age)));
+            files.push(file);
+        }
+
+        let policy = ColdStoragePolicy {
+            age: Duration::from_secs(7 * 24 * 60 * 60),
+            min_size: 0,
+            ..Default::default()
+        };
+
+        let cold_storage = ColdStorage::new(&ipfs, Some(policy)).await;
+
+        let report = cold_storage.report(&files);
+        assert_eq!(report.reclaimable, 1);
+        assert_eq!(report.evicted, 0);
+
+        let report = cold_storage.apply(&files).await;
+        assert_eq!(report.reclaimable, 0);
+        assert_eq!(report.evicted, 1);
+        assert_eq!(report.evicted_bytes, files[0].size());
+
+        assert!(cold_storage.is_evicted(files[0].id()));
+        assert!(!cold_storage.is_evicted(files[1].id()));
+        Ok(())
+    }
+}
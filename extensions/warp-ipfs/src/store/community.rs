@@ -283,10 +283,10 @@ impl CommunityDocument {
         participants.insert(self.owner.clone());
         participants
     }
-    pub fn has_valid_invite(&self, user: &DID) -> bool {
+    pub fn has_valid_invite(&self, user: &DID, now: DateTime<Utc>) -> bool {
         for (_, invite) in &self.invites {
             let is_expired = match &invite.expiry {
-                Some(expiry) => expiry < &Utc::now(),
+                Some(expiry) => expiry < &now,
                 None => false,
             };
             let is_valid_target = match &invite.target_user {
@@ -319,6 +319,14 @@ impl CommunityDocument {
         false
     }
 
+    /// Returns true if the member is not subject to the slow mode of the channel
+    pub fn slow_mode_exempt(&self, member: &DID, channel_id: Uuid) -> bool {
+        self.channels
+            .get(&channel_id.to_string())
+            .is_none_or(|channel| channel.slow_mode == 0)
+            || self.has_permission(member, &CommunityPermission::EditChannels)
+    }
+
     pub fn has_channel_permission(
         &self,
         user: &DID,
@@ -357,6 +365,9 @@ pub struct CommunityChannelDocument {
     pub modified: DateTime<Utc>,
     pub channel_type: CommunityChannelType,
     pub permissions: CommunityChannelPermissions,
+    /// Minimum amount of seconds between messages of a member
+    #[serde(default)]
+    pub slow_mode: u64,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub messages: Option<Cid>,
 }
@@ -374,6 +385,7 @@ impl CommunityChannelDocument {
             modified: Utc::now(),
             channel_type,
             permissions: CommunityChannelPermissions::new(),
+            slow_mode: 0,
             messages: None,
         }
     }
@@ -707,6 +719,7 @@ impl From<CommunityChannelDocument> for CommunityChannel {
         community_channel.set_modified(value.modified);
         community_channel.set_channel_type(value.channel_type);
         community_channel.set_permissions(value.permissions);
+        community_channel.set_slow_mode(value.slow_mode);
         community_channel
     }
 }
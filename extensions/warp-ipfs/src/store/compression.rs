@@ -0,0 +1,87 @@
+//! Compression of payloads before they are encrypted.
+//!
+//! Payloads over [`COMPRESSION_THRESHOLD`] are compressed with zstd, and the document holding the payload records the
+//! [`Compression`] that was used so readers know to decompress it. Identities advertise the compression they are able
+//! to read in their metadata, and payloads are only compressed when every recipient advertised it. zstd is
+//! unavailable when targeting wasm, in which case nothing is advertised and compressed payloads cannot be read.
+use serde::{Deserialize, Serialize};
+use warp::data::COMPRESSION_THRESHOLD;
+use warp::error::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Zstd,
+}
+
+impl Compression {
+    /// Identifier of the compression, used when hashing the document holding the payload
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    /// Compression that this node is able to read, if any
+    pub fn supported() -> Option<Compression> {
+        (!cfg!(target_arch = "wasm32")).then_some(Compression::Zstd)
+    }
+}
+
+/// Compress `data` if it is larger than [`COMPRESSION_THRESHOLD`] and compressing it reduces its size, returning the
+/// compression that was used
+pub fn compress(data: Vec<u8>) -> (Vec<u8>, Option<Compression>) {
+    if data.len() <= COMPRESSION_THRESHOLD {
+        return (data, None);
+    }
+
+    match warp::data::compress(&data) {
+        Ok(compressed) if compressed.len() < data.len() => (compressed, Some(Compression::Zstd)),
+        Ok(_) => (data, None),
+        Err(e) => {
+            tracing::warn!(error = %e, "unable to compress payload");
+            (data, None)
+        }
+    }
+}
+
+/// Decompress `data`, rejecting payloads that would be larger than `limit` once decompressed
+pub fn decompress(
+    data: &[u8],
+    compression: Option<Compression>,
+    limit: usize,
+) -> Result<Vec<u8>, Error> {
+    match compression {
+        None => Ok(data.to_vec()),
+        Some(Compression::Zstd) => warp::data::decompress_bounded(data, limit),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{compress, decompress, Compression, COMPRESSION_THRESHOLD};
+
+    #[test]
+    fn only_large_payloads_are_compressed() {
+        let small = b"hello".to_vec();
+        assert_eq!(compress(small.clone()), (small, None));
+
+        let log = "2024-01-01T00:00:00Z INFO request handled in 12ms\n".repeat(100);
+        let (compressed, compression) = compress(log.as_bytes().to_vec());
+        assert_eq!(compression, Some(Compression::Zstd));
+        assert!(compressed.len() < log.len() / 4);
+
+        assert_eq!(
+            decompress(&compressed, compression, log.len()).unwrap(),
+            log.as_bytes()
+        );
+
+        // payloads expanding past the limit are rejected
+        assert!(decompress(&compressed, compression, COMPRESSION_THRESHOLD).is_err());
+    }
+
+    #[test]
+    fn zstd_is_advertised_natively() {
+        assert_eq!(Compression::supported(), Some(Compression::Zstd));
+    }
+}
@@ -1,11 +1,14 @@
+pub mod clock;
 pub mod message;
 pub mod reference;
+pub mod retention;
 
 use super::{keystore::Keystore, topics::ConversationTopic, verify_serde_sig, PeerIdExt};
 use crate::store::DidExt;
 
 use crate::store::conversation::message::MessageDocument;
 use crate::store::conversation::reference::MessageReferenceList;
+use crate::store::conversation::retention::RetentionPolicyDocument;
 use chrono::{DateTime, Utc};
 use core::hash::Hash;
 use either::Either;
@@ -25,11 +28,43 @@ use warp::{
     crypto::DID,
     error::Error,
     raygun::{
-        Conversation, ConversationType, GroupPermissions, Message, MessageOptions, MessagePage,
-        MessageReference, Messages, MessagesType,
+        Conversation, ConversationType, GroupPermissions, HistoryVisibility, Message,
+        MessageOptions, MessagePage, MessageReference, Messages, MessagesType, RetentionPolicy,
     },
 };
 
+/// Seed used when deriving the id of a direct conversation
+const DIRECT_CONVERSATION_SEED: &str = "direct-conversation";
+
+/// Highest generation that will be used or accepted when deriving the id of a direct conversation
+pub const MAX_DIRECT_CONVERSATION_GENERATION: u32 = 64;
+
+/// Derive the id of a direct conversation between the owner of `keypair` and `recipient`.
+///
+/// The id is derived from the shared secret of both identities so that each side derives the same id
+/// without any coordination. The derivation is versioned by `generation`:
+/// - generation 0 uses the seed `direct-conversation`, which is the derivation used prior to generations
+/// - generation `n > 0` uses the seed `direct-conversation/n`
+///
+/// A higher generation is used when the id of a lower generation is already in use (eg by a conversation that
+/// was deleted after a block), so that a new conversation never collides with a previous one.
+pub fn derive_direct_conversation_id(
+    keypair: &Keypair,
+    recipient: &DID,
+    generation: u32,
+) -> Result<Uuid, Error> {
+    if generation > MAX_DIRECT_CONVERSATION_GENERATION {
+        return Err(Error::CannotCreateConversation);
+    }
+
+    let seed = match generation {
+        0 => DIRECT_CONVERSATION_SEED.to_string(),
+        generation => format!("{DIRECT_CONVERSATION_SEED}/{generation}"),
+    };
+
+    super::generate_shared_topic(keypair, recipient, Some(&seed)).map_err(Error::from)
+}
+
 #[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum ConversationVersion {
@@ -60,6 +95,10 @@ pub struct ConversationDocument {
     pub restrict: Vec<DID>,
     #[serde(default)]
     pub deleted: bool,
+    /// Generation used to derive the id of a direct conversation.
+    /// See [`derive_direct_conversation_id`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generation: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub messages: Option<Cid>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -68,10 +107,23 @@ pub struct ConversationDocument {
     pub banner: Option<Cid>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
+    /// Part of the history that new members can read. Signed by the creator
+    #[serde(default, skip_serializing_if = "is_default_visibility")]
+    pub history_visibility: HistoryVisibility,
+    /// Minimum amount of seconds between messages of a member. Signed by the creator
+    #[serde(default)]
+    pub slow_mode: u64,
+    /// Retention policy of the group. Signed by its issuer, the creator or an administrator of the organization
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retention: Option<RetentionPolicyDocument>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub signature: Option<String>,
 }
 
+fn is_default_visibility(visibility: &HistoryVisibility) -> bool {
+    *visibility == HistoryVisibility::default()
+}
+
 impl Hash for ConversationDocument {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
         self.id.hash(state)
@@ -179,9 +231,13 @@ impl ConversationDocument {
             signature,
             restrict,
             deleted: false,
+            generation: None,
             icon: None,
             banner: None,
             description: None,
+            history_visibility: HistoryVisibility::default(),
+            slow_mode: 0,
+            retention: None,
         };
 
         if document.signature.is_some() {
@@ -197,9 +253,13 @@ impl ConversationDocument {
         Ok(document)
     }
 
-    pub fn new_direct(keypair: &Keypair, recipients: [DID; 2]) -> Result<Self, Error> {
+    pub fn new_direct(
+        keypair: &Keypair,
+        recipients: [DID; 2],
+        generation: u32,
+    ) -> Result<Self, Error> {
         let did = keypair.to_did()?;
-        let conversation_id = Some(super::generate_shared_topic(
+        let conversation_id = Some(derive_direct_conversation_id(
             keypair,
             recipients
                 .iter()
@@ -207,10 +267,10 @@ impl ConversationDocument {
                 .collect::<Vec<_>>()
                 .first()
                 .ok_or(Error::Other)?,
-            Some("direct-conversation"),
+            generation,
         )?);
 
-        Self::new(
+        let mut document = Self::new(
             keypair,
             None,
             recipients.to_vec(),
@@ -222,7 +282,11 @@ impl ConversationDocument {
             None,
             None,
             None,
-        )
+        )?;
+
+        document.generation = Some(generation);
+
+        Ok(document)
     }
 
     pub fn new_group(
@@ -273,6 +337,8 @@ impl ConversationDocument {
                         .iter()
                         .flat_map(|rec| rec.to_string().as_bytes().to_vec()),
                 )),
+                self.history_visibility_bytes(),
+                self.slow_mode_bytes(),
             ]
             .into_iter(),
             None,
@@ -284,6 +350,45 @@ impl ConversationDocument {
         Ok(())
     }
 
+    /// Returns true if the member is not subject to slow mode
+    pub fn slow_mode_exempt(&self, member: &DID) -> bool {
+        self.slow_mode == 0
+            || self
+                .creator
+                .as_ref()
+                .is_some_and(|creator| creator == member)
+    }
+
+    /// Retention policy of the group, if it is signed by its issuer. Whether the issuer is allowed to set the policy
+    /// is checked by the conversation task when the policy is received, since administrators of an organization are
+    /// only known by its members
+    pub fn retention_policy(&self) -> Option<RetentionPolicy> {
+        let document = self.retention.as_ref()?;
+
+        if self.conversation_type != ConversationType::Group || document.conversation_id != self.id
+        {
+            return None;
+        }
+
+        if let Err(e) = document.verify() {
+            tracing::warn!(conversation_id = %self.id, error = %e, "invalid retention policy");
+            return None;
+        }
+
+        Some(document.policy)
+    }
+
+    /// The default visibility is left out of the signature so documents signed prior to the setting remain valid
+    fn history_visibility_bytes(&self) -> Option<Vec<u8>> {
+        (!is_default_visibility(&self.history_visibility))
+            .then(|| self.history_visibility.to_string().into_bytes())
+    }
+
+    /// Slow mode is only part of the signature once enabled, for the same reason as the history visibility
+    fn slow_mode_bytes(&self) -> Option<Vec<u8>> {
+        (self.slow_mode != 0).then(|| format!("slow_mode:{}", self.slow_mode).into_bytes())
+    }
+
     pub fn verify(&self) -> Result<(), Error> {
         if self.conversation_type() == ConversationType::Direct {
             return Ok(());
@@ -311,6 +416,8 @@ impl ConversationDocument {
                             .iter()
                             .flat_map(|rec| rec.to_string().as_bytes().to_vec()),
                     )),
+                    self.history_visibility_bytes(),
+                    self.slow_mode_bytes(),
                 ]
                 .into_iter(),
                 None,
@@ -635,11 +742,12 @@ impl ConversationDocument {
             .await
     }
 
-    pub async fn delete_message(&mut self, ipfs: &Ipfs, message_id: Uuid) -> Result<(), Error> {
+    /// Remove the message from the conversation, returning the cid of its document
+    pub async fn delete_message(&mut self, ipfs: &Ipfs, message_id: Uuid) -> Result<Cid, Error> {
         let mut list = self.message_reference_list(ipfs).await?;
-        list.remove(ipfs, message_id).await?;
+        let cid = list.remove(ipfs, message_id).await?;
         self.set_message_reference_list(ipfs, list).await?;
-        Ok(())
+        Ok(cid)
     }
 }
 
@@ -663,6 +771,9 @@ impl From<&ConversationDocument> for Conversation {
         conversation.set_favorite(document.favorite);
         conversation.set_description(document.description.clone());
         conversation.set_archived(document.archived);
+        conversation.set_history_visibility(document.history_visibility);
+        conversation.set_slow_mode(document.slow_mode);
+        conversation.set_retention_policy(document.retention_policy());
         conversation
     }
 }
@@ -0,0 +1,145 @@
+//! Hybrid logical clock used to order messages independently of the wall clock of the sender.
+//!
+//! Each timestamp pairs the physical time (in milliseconds) with a logical counter. A clock never moves backwards
+//! and advances past any timestamp it observes, so a message is always ordered after the messages its sender had
+//! seen when it was created, even if the clocks of the peers are skewed.
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Maximum amount of time, in milliseconds, a remote timestamp can be ahead of the local clock before it is no
+/// longer used to advance the clock
+const MAX_CLOCK_DRIFT: i64 = 60 * 60 * 1000;
+
+#[derive(
+    Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct HybridTimestamp {
+    pub wall: i64,
+    pub counter: u32,
+}
+
+impl HybridTimestamp {
+    pub fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(12);
+        bytes.extend_from_slice(&self.wall.to_be_bytes());
+        bytes.extend_from_slice(&self.counter.to_be_bytes());
+        bytes
+    }
+}
+
+impl From<DateTime<Utc>> for HybridTimestamp {
+    fn from(date: DateTime<Utc>) -> Self {
+        Self {
+            wall: date.timestamp_millis(),
+            counter: 0,
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+pub struct HybridClock {
+    last: HybridTimestamp,
+}
+
+impl HybridClock {
+    pub fn new(last: HybridTimestamp) -> Self {
+        Self { last }
+    }
+
+    pub fn last(&self) -> HybridTimestamp {
+        self.last
+    }
+
+    /// Produce a timestamp for a local event
+    pub fn tick(&mut self, now: DateTime<Utc>) -> HybridTimestamp {
+        let now = now.timestamp_millis();
+
+        self.last = match now > self.last.wall {
+            true => HybridTimestamp {
+                wall: now,
+                counter: 0,
+            },
+            false => HybridTimestamp {
+                wall: self.last.wall,
+                counter: self.last.counter.saturating_add(1),
+            },
+        };
+
+        self.last
+    }
+
+    /// Advance the clock past a timestamp received from a remote peer. Timestamps that are too far ahead of the
+    /// local time are ignored so a single peer cannot push the clock of the conversation into the future.
+    pub fn update(&mut self, remote: HybridTimestamp, now: DateTime<Utc>) -> HybridTimestamp {
+        let now = now.timestamp_millis();
+
+        if remote.wall - now > MAX_CLOCK_DRIFT {
+            tracing::warn!(
+                drift = remote.wall - now,
+                "remote timestamp exceeds the maximum clock drift"
+            );
+            return self.last;
+        }
+
+        let local = self.last;
+        let wall = now.max(local.wall).max(remote.wall);
+
+        let counter = if wall == local.wall && wall == remote.wall {
+            local.counter.max(remote.counter).saturating_add(1)
+        } else if wall == local.wall {
+            local.counter.saturating_add(1)
+        } else if wall == remote.wall {
+            remote.counter.saturating_add(1)
+        } else {
+            0
+        };
+
+        self.last = HybridTimestamp { wall, counter };
+        self.last
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, Utc};
+
+    use super::{HybridClock, HybridTimestamp};
+
+    #[test]
+    fn clock_is_monotonic() {
+        let now = Utc::now();
+        let mut clock = HybridClock::default();
+
+        let first = clock.tick(now);
+        let second = clock.tick(now);
+        // wall clock moving backwards should not affect the order
+        let third = clock.tick(now - Duration::seconds(30));
+
+        assert!(first < second);
+        assert!(second < third);
+        assert_eq!(third.wall, now.timestamp_millis());
+    }
+
+    #[test]
+    fn clock_advances_past_remote() {
+        let now = Utc::now();
+        let mut clock = HybridClock::default();
+        clock.tick(now);
+
+        // a peer with a clock that is ahead of ours
+        let remote = HybridTimestamp::from(now + Duration::minutes(5));
+        let received = clock.update(remote, now);
+        assert!(received > remote);
+
+        // replies created afterward are ordered after the remote message
+        let reply = clock.tick(now);
+        assert!(reply > remote);
+
+        // timestamps beyond the maximum drift do not advance the clock
+        let last = clock.last();
+        assert_eq!(
+            clock.update(HybridTimestamp::from(now + Duration::days(1)), now),
+            last
+        );
+    }
+}
@@ -1,9 +1,12 @@
+use crate::store::compression::{self, Compression};
+use crate::store::conversation::clock::HybridTimestamp;
 use crate::store::document::files::FileDocument;
 use crate::store::document::FileAttachmentDocument;
 use crate::store::keystore::Keystore;
 use crate::store::{
-    ecdh_decrypt, ecdh_encrypt, ecdh_encrypt_with_nonce, extract_data_slice, DidExt, PeerIdExt,
-    MAX_ATTACHMENT, MAX_MESSAGE_SIZE, MAX_REACTIONS, MIN_MESSAGE_SIZE,
+    ecdh_decrypt, ecdh_encrypt_in_place, ecdh_encrypt_with_nonce, extract_data_slice, DidExt,
+    PeerIdExt, MAX_ATTACHMENT, MAX_CUSTOM_REACTIONS, MAX_MESSAGE_SIZE_LIMIT, MAX_REACTIONS,
+    MIN_MESSAGE_SIZE,
 };
 use bytes::Bytes;
 use chrono::{DateTime, Utc};
@@ -12,6 +15,7 @@ use futures::stream::{FuturesUnordered, StreamExt};
 use indexmap::{IndexMap, IndexSet};
 use rust_ipfs::{Ipfs, Keypair};
 use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::{btree_map::Entry, BTreeMap};
 use std::future::IntoFuture;
 use std::hash::{Hash, Hasher};
 use uuid::Uuid;
@@ -19,13 +23,25 @@ use warp::crypto::cipher::Cipher;
 use warp::crypto::hash::sha256_iter;
 use warp::crypto::{DIDKey, Ed25519KeyPair, KeyMaterial, DID};
 use warp::error::Error;
-use warp::raygun::{Message, MessageReference, MessageType};
+use warp::raygun::{
+    Message, MessageReference, MessageStatus, MessageType, Reaction, SystemMessage,
+};
 
 #[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, Eq, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum MessageVersion {
     #[default]
     V0,
+    /// Adds the hybrid logical clock of the message to its signature
+    V1,
+}
+
+/// Receipt sent by a member for a message they received, in the order they progress
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Eq, PartialEq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Receipt {
+    Delivered,
+    Read,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -50,6 +66,27 @@ pub struct MessageDocument {
     pub message: Option<Bytes>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub signature: Option<MessageSignature>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clock: Option<HybridTimestamp>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified_clock: Option<HybridTimestamp>,
+    /// Epoch of the sender key the message was encrypted with. Only used as a hint when decrypting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_epoch: Option<usize>,
+    /// Change to the conversation recorded by the message. Only set on [`MessageType::Event`] messages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system: Option<SystemMessage>,
+    /// Compression of the message before it was encrypted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
+    /// Receipts of the members that received the message. These are not part of the signature as they are set by
+    /// the recipients
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub receipts: BTreeMap<DID, Receipt>,
+    /// Conversation the message was sent in, if it was moved to another conversation when merging a duplicate
+    /// conversation. The signature covers the conversation the message was sent in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<Uuid>,
 }
 
 impl MessageDocument {
@@ -65,6 +102,16 @@ impl MessageDocument {
         self.conversation_id
     }
 
+    /// Move the message to `conversation_id`, keeping track of the conversation it was sent in so that its
+    /// signature remains valid
+    pub fn move_to(&mut self, conversation_id: Uuid) {
+        if self.conversation_id == conversation_id {
+            return;
+        }
+        self.origin.get_or_insert(self.conversation_id);
+        self.conversation_id = conversation_id;
+    }
+
     pub fn version(&self) -> MessageVersion {
         self.version
     }
@@ -92,6 +139,17 @@ impl MessageDocument {
     pub fn replied(&self) -> Option<Uuid> {
         self.replied
     }
+
+    /// Timestamp used to order the message. Messages created prior to the hybrid clock fall back to their date
+    pub fn timestamp(&self) -> HybridTimestamp {
+        self.clock.unwrap_or_else(|| self.date.into())
+    }
+
+    /// Timestamp of the latest edit, used to discard edits that are older than the current state of the message
+    pub fn modified_timestamp(&self) -> Option<HybridTimestamp> {
+        self.modified_clock
+            .or_else(|| self.modified.map(HybridTimestamp::from))
+    }
 }
 
 impl PartialEq for MessageDocument {
@@ -125,6 +183,13 @@ impl MessageDocument {
             replied: None,
             message: None,
             signature: None,
+            clock: None,
+            modified_clock: None,
+            key_epoch: None,
+            system: None,
+            compression: None,
+            receipts: BTreeMap::new(),
+            origin: None,
         }
     }
 }
@@ -147,7 +212,7 @@ impl From<&MessageDocument> for MessageReference {
         reference.set_pinned(document.pinned);
         reference.set_replied(document.replied);
         reference.set_sender(document.sender.to_did());
-        reference.set_delete(document.message.is_none());
+        reference.set_delete(document.message.is_none() && document.system.is_none());
         reference
     }
 }
@@ -160,7 +225,9 @@ impl PartialOrd for MessageDocument {
 
 impl Ord for MessageDocument {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.date.cmp(&other.date)
+        self.timestamp()
+            .cmp(&other.timestamp())
+            .then_with(|| self.id.cmp(&other.id))
     }
 }
 
@@ -168,6 +235,7 @@ pub struct MessageDocumentBuilder<'a> {
     keypair: &'a Keypair,
     keystore: Either<&'a DID, &'a Keystore>,
     message_document: MessageDocument,
+    compress: bool,
 }
 
 impl<'a> MessageDocumentBuilder<'a> {
@@ -176,9 +244,17 @@ impl<'a> MessageDocumentBuilder<'a> {
             keypair,
             keystore,
             message_document: MessageDocument::empty(),
+            compress: false,
         }
     }
 
+    /// Allow the message to be compressed. This should only be set when every recipient is able to read compressed
+    /// messages, and must be set before the message
+    pub fn set_compression(mut self, compress: bool) -> Self {
+        self.compress = compress;
+        self
+    }
+
     pub fn set_message_id(mut self, id: Uuid) -> Self {
         self.message_document.id = id;
         self
@@ -219,6 +295,18 @@ impl<'a> MessageDocumentBuilder<'a> {
         self
     }
 
+    pub fn set_clock(mut self, clock: HybridTimestamp) -> Self {
+        self.message_document.version = MessageVersion::V1;
+        self.message_document.clock = Some(clock);
+        self
+    }
+
+    pub fn set_system_message(mut self, system: SystemMessage) -> Self {
+        self.message_document.message_type = MessageType::Event;
+        self.message_document.system = Some(system);
+        self
+    }
+
     pub fn add_attachment(mut self, attachment: impl Into<FileDocument>) -> Result<Self, Error> {
         let amount = self.message_document.attachments.len();
         if amount > MAX_ATTACHMENT {
@@ -245,27 +333,33 @@ impl<'a> MessageDocumentBuilder<'a> {
                 .map(|s| s.chars().count())
                 .sum();
 
-            if lines_value_length > MAX_MESSAGE_SIZE {
+            if lines_value_length > MAX_MESSAGE_SIZE_LIMIT {
                 return Err(Error::InvalidLength {
                     context: "message".into(),
                     current: lines_value_length,
                     minimum: None,
-                    maximum: Some(MAX_MESSAGE_SIZE),
+                    maximum: Some(MAX_MESSAGE_SIZE_LIMIT),
                 });
             }
         }
 
-        let bytes = serde_json::to_vec(&message)?;
+        let data = serde_json::to_vec(&message)?;
+        let (mut data, compression) = match self.compress {
+            true => compression::compress(data),
+            false => (data, None),
+        };
+        self.message_document.compression = compression;
 
-        let data = match self.keystore {
+        match self.keystore {
             Either::Right(keystore) => {
-                let key = keystore.get_latest(self.keypair, &sender)?;
-                Cipher::direct_encrypt(&bytes, &key)?.into()
+                let (epoch, key) = keystore.get_latest_with_epoch(self.keypair, &sender)?;
+                self.message_document.key_epoch = Some(epoch);
+                Cipher::direct_encrypt_in_place(&mut data, &key)?;
             }
-            Either::Left(key) => ecdh_encrypt(self.keypair, Some(key), &bytes)?.into(),
+            Either::Left(key) => ecdh_encrypt_in_place(self.keypair, Some(key), &mut data)?,
         };
 
-        self.message_document.message = Some(data);
+        self.message_document.message = Some(Bytes::from(data));
         Ok(self)
     }
 
@@ -287,6 +381,18 @@ impl MessageDocument {
         self.modified = Some(modified);
     }
 
+    pub fn set_modified_clock(&mut self, clock: HybridTimestamp) {
+        self.version = MessageVersion::V1;
+        self.modified_clock = Some(clock);
+    }
+
+    /// Set the clock of a message created locally, signing the document again
+    pub fn set_clock(&mut self, keypair: &Keypair, clock: HybridTimestamp) -> Result<(), Error> {
+        self.version = MessageVersion::V1;
+        self.clock = Some(clock);
+        self.sign_in_place(keypair)
+    }
+
     pub fn add_attachment(&mut self, attachment: impl Into<FileDocument>) -> Result<(), Error> {
         let amount = self.attachments.len();
         if amount > MAX_ATTACHMENT {
@@ -313,6 +419,7 @@ impl MessageDocument {
     pub fn add_reaction(&mut self, emoji: impl Into<String>, reactor: DID) -> Result<(), Error> {
         let emoji = emoji.into();
         let size = self.reactions.len();
+        let custom_reactions = self.custom_reactions();
         match self.reactions.entry(emoji) {
             indexmap::map::Entry::Occupied(mut entry) => {
                 let list = entry.get_mut();
@@ -330,6 +437,15 @@ impl MessageDocument {
                         maximum: Some(MAX_REACTIONS),
                     });
                 }
+                let reaction = entry.key().parse::<Reaction>()?;
+                if reaction.custom_emoji().is_some() && custom_reactions >= MAX_CUSTOM_REACTIONS {
+                    return Err(Error::InvalidLength {
+                        context: "custom reactions".into(),
+                        current: custom_reactions,
+                        minimum: None,
+                        maximum: Some(MAX_CUSTOM_REACTIONS),
+                    });
+                }
                 let list = IndexSet::from_iter([reactor]);
                 entry.insert(list);
             }
@@ -338,6 +454,15 @@ impl MessageDocument {
         Ok(())
     }
 
+    /// Amount of distinct custom emoji the message was reacted with
+    fn custom_reactions(&self) -> usize {
+        self.reactions
+            .keys()
+            .filter_map(|reaction| reaction.parse::<Reaction>().ok())
+            .filter(|reaction| reaction.custom_emoji().is_some())
+            .count()
+    }
+
     pub fn remove_reaction(&mut self, emoji: impl Into<String>, reactor: DID) -> Result<(), Error> {
         let emoji = emoji.into();
         if !self.reactions.contains_key(&emoji) {
@@ -357,6 +482,42 @@ impl MessageDocument {
         Ok(())
     }
 
+    /// Record the receipt of a member, returning false if the member already sent the same or a later receipt
+    pub fn set_receipt(&mut self, member: DID, receipt: Receipt) -> bool {
+        match self.receipts.entry(member) {
+            Entry::Occupied(mut entry) if *entry.get() < receipt => {
+                entry.insert(receipt);
+                true
+            }
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(receipt);
+                true
+            }
+        }
+    }
+
+    pub fn receipt(&self, member: &DID) -> Option<Receipt> {
+        self.receipts.get(member).copied()
+    }
+
+    /// Status of the message based on the least advanced receipt among the recipients other than the sender
+    pub fn delivery_status(&self, recipients: &[DID]) -> MessageStatus {
+        let sender = self.sender();
+        let receipt = recipients
+            .iter()
+            .filter(|did| **did != sender)
+            .map(|did| self.receipt(did))
+            .min()
+            .flatten();
+
+        match receipt {
+            Some(Receipt::Read) => MessageStatus::Read,
+            Some(Receipt::Delivered) => MessageStatus::Delivered,
+            None => MessageStatus::Sent,
+        }
+    }
+
     pub fn set_message(
         &mut self,
         keypair: &Keypair,
@@ -373,35 +534,41 @@ impl MessageDocument {
                 .map(|s| s.chars().count())
                 .sum();
 
-            if lines_value_length > MAX_MESSAGE_SIZE {
+            if lines_value_length > MAX_MESSAGE_SIZE_LIMIT {
                 return Err(Error::InvalidLength {
                     context: "message".into(),
                     current: lines_value_length,
                     minimum: None,
-                    maximum: Some(MAX_MESSAGE_SIZE),
+                    maximum: Some(MAX_MESSAGE_SIZE_LIMIT),
                 });
             }
         }
 
-        let bytes = serde_json::to_vec(message)?;
+        // edits are not compressed since peers rebuild the encrypted message from the lines of the edit to verify
+        // its signature
+        let mut data = serde_json::to_vec(message)?;
+        self.compression = None;
 
-        let data = match keystore {
+        match keystore {
             Either::Right(keystore) => {
-                let key = keystore.get_latest(keypair, &sender)?;
-                Cipher::direct_encrypt(&bytes, &key)?.into()
+                let (epoch, key) = keystore.get_latest_with_epoch(keypair, &sender)?;
+                self.key_epoch = Some(epoch);
+                Cipher::direct_encrypt_in_place(&mut data, &key)?;
             }
-            Either::Left(key) => ecdh_encrypt(keypair, Some(key), &bytes)?.into(),
+            Either::Left(key) => ecdh_encrypt_in_place(keypair, Some(key), &mut data)?,
         };
 
-        self.message = Some(data);
+        self.message = Some(Bytes::from(data));
         self.sign_in_place(keypair)
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn set_message_with_nonce(
         &mut self,
         keypair: &Keypair,
         keystore: Either<&DID, &Keystore>,
         modified: DateTime<Utc>,
+        modified_clock: Option<HybridTimestamp>,
         message: Vec<String>,
         signature: Option<Vec<u8>>,
         nonce: Option<&[u8]>,
@@ -410,6 +577,9 @@ impl MessageDocument {
         let sender = self.sender.to_did();
 
         self.modified = Some(modified);
+        if let Some(clock) = modified_clock {
+            self.set_modified_clock(clock);
+        }
 
         if !message.is_empty() {
             let lines_value_length: usize = message
@@ -419,12 +589,12 @@ impl MessageDocument {
                 .map(|s| s.chars().count())
                 .sum();
 
-            if lines_value_length > MAX_MESSAGE_SIZE {
+            if lines_value_length > MAX_MESSAGE_SIZE_LIMIT {
                 return Err(Error::InvalidLength {
                     context: "message".into(),
                     current: lines_value_length,
                     minimum: None,
-                    maximum: Some(MAX_MESSAGE_SIZE),
+                    maximum: Some(MAX_MESSAGE_SIZE_LIMIT),
                 });
             }
         }
@@ -439,24 +609,27 @@ impl MessageDocument {
             return Err(Error::InvalidMessage);
         }
 
-        let bytes = serde_json::to_vec(&message)?;
+        let mut data = serde_json::to_vec(&message)?;
+        self.compression = None;
 
-        let data = match (keystore, nonce) {
+        match (keystore, nonce) {
             (Either::Right(keystore), Some(nonce)) => {
-                let key = keystore.get_latest(keypair, &sender)?;
-                Cipher::direct_encrypt_with_nonce(&bytes, &key, nonce)?
+                let (epoch, key) = keystore.get_latest_with_epoch(keypair, &sender)?;
+                self.key_epoch = Some(epoch);
+                Cipher::direct_encrypt_in_place_with_nonce(&mut data, &key, nonce)?;
             }
             (Either::Left(key), Some(nonce)) => {
-                ecdh_encrypt_with_nonce(keypair, Some(key), &bytes, nonce)?
+                data = ecdh_encrypt_with_nonce(keypair, Some(key), &data, nonce)?;
             }
             (Either::Right(keystore), None) => {
-                let key = keystore.get_latest(keypair, &sender)?;
-                Cipher::direct_encrypt(&bytes, &key)?
+                let (epoch, key) = keystore.get_latest_with_epoch(keypair, &sender)?;
+                self.key_epoch = Some(epoch);
+                Cipher::direct_encrypt_in_place(&mut data, &key)?;
             }
-            (Either::Left(key), None) => ecdh_encrypt(keypair, Some(key), &bytes)?,
+            (Either::Left(key), None) => ecdh_encrypt_in_place(keypair, Some(key), &mut data)?,
         };
 
-        self.message = (!data.is_empty()).then_some(data.into());
+        self.message = (!data.is_empty()).then_some(Bytes::from(data));
 
         match (sender.eq(&own_did), signature) {
             (true, None) => {
@@ -487,31 +660,7 @@ impl MessageDocument {
             return Err(Error::PublicKeyInvalid);
         };
 
-        let attachments_hash = sha256_iter(
-            self.attachments
-                .iter()
-                .map(|attachment| attachment.data.as_bytes())
-                .map(Option::Some),
-            None,
-        );
-        let attachments_hash = (!attachments_hash.is_empty()).then_some(attachments_hash);
-
-        let hash = match self.version {
-            MessageVersion::V0 => sha256_iter(
-                [
-                    Some(self.conversation_id.as_bytes().to_vec()),
-                    Some(self.id.as_bytes().to_vec()),
-                    Some(sender.public_key_bytes()),
-                    Some(self.date.to_string().into_bytes()),
-                    self.modified.map(|time| time.to_string().into_bytes()),
-                    self.replied.map(|id| id.as_bytes().to_vec()),
-                    attachments_hash,
-                    self.message.as_ref().map(|m| m.to_vec()),
-                ]
-                .into_iter(),
-                None,
-            ),
-        };
+        let hash = self.signature_hash(&sender);
 
         if !sender_pk.verify(&hash, signature.as_ref()) {
             return Err(Error::InvalidMessage);
@@ -526,6 +675,16 @@ impl MessageDocument {
             });
         }
 
+        let custom_reactions = self.custom_reactions();
+        if custom_reactions > MAX_CUSTOM_REACTIONS {
+            return Err(Error::InvalidLength {
+                context: "custom reactions".into(),
+                current: custom_reactions,
+                minimum: None,
+                maximum: Some(MAX_CUSTOM_REACTIONS),
+            });
+        }
+
         if self.attachments.len() > MAX_ATTACHMENT {
             return Err(Error::InvalidLength {
                 context: "attachments".into(),
@@ -544,7 +703,7 @@ impl MessageDocument {
 
     pub fn nonce_from_message(&self) -> Result<&[u8], Error> {
         let raw_encrypted_message = self.raw_encrypted_message()?;
-        let (nonce, _) = extract_data_slice::<12>(raw_encrypted_message);
+        let (nonce, _) = extract_data_slice::<12>(raw_encrypted_message)?;
         debug_assert_eq!(nonce.len(), 12);
         Ok(nonce)
     }
@@ -562,11 +721,17 @@ impl MessageDocument {
 
         let data = match keystore {
             Either::Left(exchange) => ecdh_decrypt(keypair, Some(exchange), message_cipher)?,
-            Either::Right(keystore) => {
-                keystore.try_decrypt(keypair, &self.sender(), message_cipher)?
-            }
+            Either::Right(keystore) => keystore.decrypt_with_epoch(
+                keypair,
+                &self.sender(),
+                self.key_epoch,
+                message_cipher,
+            )?,
         };
 
+        // bounds the decompressed message to the longest encoding of the maximum amount of characters
+        let data = compression::decompress(&data, self.compression, MAX_MESSAGE_SIZE_LIMIT * 8)?;
+
         let lines: Vec<String> = serde_json::from_slice(&data)?;
 
         let lines_value_length: usize = lines
@@ -576,12 +741,12 @@ impl MessageDocument {
             .map(|s| s.chars().count())
             .sum();
 
-        if lines_value_length == 0 && lines_value_length > MAX_MESSAGE_SIZE {
+        if lines_value_length == 0 && lines_value_length > MAX_MESSAGE_SIZE_LIMIT {
             return Err(Error::InvalidLength {
                 context: "message".into(),
                 current: lines_value_length,
                 minimum: Some(MIN_MESSAGE_SIZE),
-                maximum: Some(MAX_MESSAGE_SIZE),
+                maximum: Some(MAX_MESSAGE_SIZE_LIMIT),
             });
         }
 
@@ -626,6 +791,11 @@ impl MessageDocument {
 
         message.set_reactions(self.reactions.clone());
 
+        if self.message_type == MessageType::Event {
+            message.set_system_message(self.system.clone());
+            return Ok(message);
+        }
+
         match self.message(keypair, key) {
             Ok(lines) => {
                 message.set_lines(lines);
@@ -652,6 +822,15 @@ impl MessageDocument {
 
         self.modified = Some(Utc::now());
 
+        let hash = self.signature_hash(&sender);
+
+        let signature = keypair.sign(&hash).expect("not RSA");
+
+        self.signature = Some(MessageSignature::try_from(signature)?);
+        Ok(())
+    }
+
+    fn signature_hash(&self, sender: &DID) -> Vec<u8> {
         let attachments_hash = sha256_iter(
             self.attachments
                 .iter()
@@ -661,25 +840,42 @@ impl MessageDocument {
         );
         let attachments_hash = (!attachments_hash.is_empty()).then_some(attachments_hash);
 
-        let hash = sha256_iter(
-            [
-                Some(self.conversation_id.as_bytes().to_vec()),
-                Some(self.id.as_bytes().to_vec()),
-                Some(sender.public_key_bytes()),
-                Some(self.date.to_string().into_bytes()),
-                self.modified.map(|time| time.to_string().into_bytes()),
-                self.replied.map(|id| id.as_bytes().to_vec()),
-                attachments_hash,
-                self.message.as_ref().map(|m| m.to_vec()),
-            ]
-            .into_iter(),
-            None,
-        );
-
-        let signature = keypair.sign(&hash).expect("not RSA");
-
-        self.signature = Some(MessageSignature::try_from(signature)?);
-        Ok(())
+        // Absent for regular messages, leaving their hash unchanged
+        let system_hash = self
+            .system
+            .as_ref()
+            .and_then(|system| serde_json::to_vec(system).ok())
+            .map(Bytes::from);
+
+        let fields = [
+            Some(Bytes::copy_from_slice(
+                self.origin.unwrap_or(self.conversation_id).as_bytes(),
+            )),
+            Some(Bytes::copy_from_slice(self.id.as_bytes())),
+            Some(Bytes::from(sender.public_key_bytes())),
+            Some(Bytes::from(self.date.to_string())),
+            self.modified.map(|time| Bytes::from(time.to_string())),
+            self.replied.map(|id| Bytes::copy_from_slice(id.as_bytes())),
+            attachments_hash.map(Bytes::from),
+            // shared rather than copied since the encrypted message makes up most of the hashed data
+            self.message.clone(),
+            system_hash,
+            // absent for uncompressed messages
+            self.compression
+                .map(|compression| Bytes::from_static(compression.as_str().as_bytes())),
+        ];
+
+        match self.version {
+            MessageVersion::V0 => sha256_iter(fields.into_iter(), None),
+            MessageVersion::V1 => sha256_iter(
+                fields.into_iter().chain([
+                    self.clock.map(|clock| Bytes::from(clock.to_bytes())),
+                    self.modified_clock
+                        .map(|clock| Bytes::from(clock.to_bytes())),
+                ]),
+                None,
+            ),
+        }
     }
 }
 
@@ -706,9 +902,22 @@ impl From<DIDEd25519Reference> for DID {
 
 impl DIDEd25519Reference {
     pub fn from_did(did: &DID) -> Self {
-        let mut pubkey_bytes: [u8; 32] = [0u8; 32];
-        pubkey_bytes.copy_from_slice(&did.public_key_bytes());
-        Self(pubkey_bytes)
+        Self::try_from_did(did).expect("ed25519 did")
+    }
+
+    /// Same as [`DIDEd25519Reference::from_did`], but returns an error instead of panicking if the did is not ed25519.
+    /// Used for any did that is received from a peer
+    pub fn try_from_did(did: &DID) -> Result<Self, Error> {
+        if !matches!(did.as_ref(), DIDKey::Ed25519(_)) {
+            return Err(Error::PublicKeyInvalid);
+        }
+
+        let pubkey_bytes: [u8; 32] = did
+            .public_key_bytes()
+            .try_into()
+            .map_err(|_| Error::PublicKeyInvalid)?;
+
+        Ok(Self(pubkey_bytes))
     }
 
     pub fn to_did(self) -> DID {
@@ -733,7 +942,7 @@ impl<'d> Deserialize<'d> for DIDEd25519Reference {
     {
         let did_str = <String>::deserialize(deserializer)?;
         let did = DID::try_from(did_str).map_err(serde::de::Error::custom)?;
-        Ok(did.into())
+        Self::try_from_did(&did).map_err(serde::de::Error::custom)
     }
 }
 
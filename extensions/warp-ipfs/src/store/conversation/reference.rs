@@ -1,4 +1,5 @@
 use crate::store::conversation::MessageDocument;
+use chrono::{DateTime, Utc};
 use futures::stream::BoxStream;
 use futures::{stream, StreamExt};
 use indexmap::IndexMap;
@@ -256,7 +257,8 @@ impl MessageReferenceList {
     }
 
     #[async_recursion::async_recursion]
-    pub async fn remove(&mut self, ipfs: &Ipfs, message_id: Uuid) -> Result<(), Error> {
+    /// Remove the message from the list, returning the cid of its document
+    pub async fn remove(&mut self, ipfs: &Ipfs, message_id: Uuid) -> Result<Cid, Error> {
         let cid = self.messages.ok_or(Error::MessageNotFound)?;
 
         let id = &message_id.to_string();
@@ -268,16 +270,14 @@ impl MessageReferenceList {
             .await?;
 
         if let Some(item) = list.get_mut(id) {
-            if item.is_none() {
+            let Some(message_cid) = item.take() else {
                 return Err(Error::MessageNotFound);
-            }
-
-            item.take();
+            };
 
             let cid = ipfs.put_dag(list).await?;
             self.messages.replace(cid);
 
-            return Ok(());
+            return Ok(message_cid);
         }
 
         let cid = self.next.ok_or(Error::MessageNotFound)?;
@@ -288,13 +288,13 @@ impl MessageReferenceList {
             .deserialized::<MessageReferenceList>()
             .await?;
 
-        refs.remove(ipfs, message_id).await?;
+        let message_cid = refs.remove(ipfs, message_id).await?;
 
         let cid = ipfs.put_dag(refs).await?;
 
         self.next.replace(cid);
 
-        Ok(())
+        Ok(message_cid)
     }
 
     // Since we have `IndexMap<String, Option<Cid>>` where the value is an `Option`, it is possible that
@@ -313,4 +313,138 @@ impl MessageReferenceList {
         }
         Ok(new_list)
     }
+
+    /// Rebuild the list without the references left by removed messages that precede the messages sent since
+    /// `cutoff`, packing the remaining references together. References within the window are kept so a removed
+    /// message that is sent again by a peer is still recognized. Returns `None` if fewer than `min_removed`
+    /// references would be dropped, otherwise the new list along with the amount of references dropped.
+    /// Note: Like [`MessageReferenceList::shrink`], this should only be used at the root of the list
+    pub async fn compact(
+        self,
+        ipfs: &Ipfs,
+        cutoff: DateTime<Utc>,
+        min_removed: usize,
+    ) -> Result<Option<(MessageReferenceList, usize)>, Error> {
+        let mut entries = vec![];
+        let mut current = Some(self);
+
+        while let Some(list) = current.take() {
+            if let Some(cid) = list.messages {
+                let refs = ipfs
+                    .get_dag(cid)
+                    .timeout(Duration::from_secs(10))
+                    .deserialized::<IndexMap<String, Option<Cid>>>()
+                    .await?;
+                entries.extend(refs);
+            }
+
+            if let Some(cid) = list.next {
+                let next = ipfs
+                    .get_dag(cid)
+                    .timeout(Duration::from_secs(10))
+                    .deserialized::<MessageReferenceList>()
+                    .await?;
+                current.replace(next);
+            }
+        }
+
+        let mut removed = 0;
+        let mut within_window = false;
+        let mut kept = Vec::with_capacity(entries.len());
+
+        for (id, message_cid) in entries {
+            match message_cid {
+                Some(cid) => {
+                    // references are in the order the messages were inserted, so everything after the first
+                    // message sent since the cutoff is within the window
+                    if !within_window {
+                        let message = ipfs
+                            .get_dag(cid)
+                            .timeout(Duration::from_secs(10))
+                            .deserialized::<MessageDocument>()
+                            .await?;
+                        within_window = message.date >= cutoff;
+                    }
+                    kept.push((id, message_cid));
+                }
+                None if within_window => kept.push((id, message_cid)),
+                None => removed += 1,
+            }
+        }
+
+        if removed == 0 || removed < min_removed {
+            return Ok(None);
+        }
+
+        let mut new_list = MessageReferenceList::default();
+        for chunk in kept.chunks(REFERENCE_LENGTH).rev() {
+            let refs = chunk.iter().cloned().collect::<IndexMap<_, _>>();
+            let messages = ipfs.put_dag(refs).await?;
+            let next = match new_list.messages {
+                Some(_) => Some(ipfs.put_dag(new_list).await?),
+                None => None,
+            };
+            new_list = MessageReferenceList {
+                messages: Some(messages),
+                next,
+            };
+        }
+
+        Ok(Some((new_list, removed)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, Utc};
+    use either::Either;
+    use rust_ipfs::{Keypair, UninitializedIpfsDefault};
+    use uuid::Uuid;
+
+    use super::MessageReferenceList;
+    use crate::store::conversation::message::MessageDocumentBuilder;
+    use crate::store::PeerIdExt;
+
+    #[tokio::test]
+    async fn compaction_drops_removed_messages_outside_of_window() -> anyhow::Result<()> {
+        let ipfs = UninitializedIpfsDefault::new()
+            .start()
+            .await
+            .expect("constructed ipfs instance");
+
+        let keypair = Keypair::generate_ed25519();
+        let did = keypair.to_did()?;
+        let conversation_id = Uuid::new_v4();
+        let now = Utc::now();
+
+        let mut list = MessageReferenceList::default();
+        let mut ids = vec![];
+        for days in [40, 35, 32, 5, 2] {
+            let message = MessageDocumentBuilder::new(&keypair, Either::Left(&did))
+                .set_conversation_id(conversation_id)
+                .set_sender(did.clone())
+                .set_date(now - Duration::days(days))
+                .build()?;
+            list.insert(&ipfs, &message).await?;
+            ids.push(message.id);
+        }
+
+        // two removed before the window and one within it
+        for id in [ids[0], ids[1], ids[3]] {
+            list.remove(&ipfs, id).await?;
+        }
+
+        let cutoff = now - Duration::days(30);
+        assert!(list.compact(&ipfs, cutoff, 3).await?.is_none());
+
+        let (compacted, removed) = list.compact(&ipfs, cutoff, 1).await?.expect("compacted");
+        assert_eq!(removed, 2);
+        assert_eq!(compacted.count(&ipfs).await, 2);
+        assert!(compacted.contains(&ipfs, ids[2]).await);
+        assert!(compacted.contains(&ipfs, ids[4]).await);
+
+        // nothing is left to drop outside of the window
+        assert!(compacted.compact(&ipfs, cutoff, 1).await?.is_none());
+        Ok(())
+    }
 }
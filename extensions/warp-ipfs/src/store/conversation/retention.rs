@@ -0,0 +1,119 @@
+//! Retention policy of a group, signed by the member that issued it so that it can be distributed to every member
+//! along with the conversation document.
+use chrono::{DateTime, Utc};
+use rust_ipfs::Keypair;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use warp::{crypto::DID, error::Error, raygun::RetentionPolicy};
+
+use crate::store::{DidExt, PeerIdExt};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RetentionPolicyDocument {
+    pub conversation_id: Uuid,
+    pub policy: RetentionPolicy,
+    pub issuer: DID,
+    pub issued: DateTime<Utc>,
+    pub signature: String,
+}
+
+impl RetentionPolicyDocument {
+    pub fn new(
+        keypair: &Keypair,
+        conversation_id: Uuid,
+        policy: RetentionPolicy,
+        issued: DateTime<Utc>,
+    ) -> Result<Self, Error> {
+        if !policy.is_valid() {
+            return Err(Error::InvalidConversation);
+        }
+
+        let issuer = keypair.to_did()?;
+
+        let mut document = Self {
+            conversation_id,
+            policy,
+            issuer,
+            issued,
+            signature: String::new(),
+        };
+
+        let signature = keypair.sign(&document.construct()).expect("not RSA");
+        document.signature = bs58::encode(signature).into_string();
+
+        Ok(document)
+    }
+
+    pub fn verify(&self) -> Result<(), Error> {
+        if !self.policy.is_valid() {
+            return Err(Error::InvalidConversation);
+        }
+
+        let issuer_pk = self.issuer.to_public_key()?;
+        let signature = bs58::decode(&self.signature).into_vec()?;
+
+        if !issuer_pk.verify(&self.construct(), &signature) {
+            return Err(Error::InvalidSignature);
+        }
+
+        Ok(())
+    }
+
+    fn construct(&self) -> Vec<u8> {
+        let policy = &self.policy;
+        warp::crypto::hash::sha256_iter(
+            [
+                Some(self.conversation_id.into_bytes().to_vec()),
+                policy.minimum.map(|minimum| minimum.to_be_bytes().to_vec()),
+                policy.maximum.map(|maximum| maximum.to_be_bytes().to_vec()),
+                Some(vec![policy.legal_hold as u8]),
+                Some(self.issuer.to_string().as_bytes().to_vec()),
+                Some(self.issued.timestamp_millis().to_be_bytes().to_vec()),
+            ]
+            .into_iter(),
+            None,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+    use rust_ipfs::Keypair;
+    use uuid::Uuid;
+    use warp::raygun::RetentionPolicy;
+
+    use super::RetentionPolicyDocument;
+
+    #[test]
+    fn tampered_policy_is_rejected() -> anyhow::Result<()> {
+        let keypair = Keypair::generate_ed25519();
+        let policy = RetentionPolicy {
+            minimum: Some(60),
+            maximum: Some(3600),
+            legal_hold: false,
+        };
+
+        let mut document =
+            RetentionPolicyDocument::new(&keypair, Uuid::new_v4(), policy, Utc::now())?;
+        document.verify()?;
+
+        document.policy.legal_hold = true;
+        assert!(document.verify().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn minimum_above_maximum_is_invalid() {
+        let keypair = Keypair::generate_ed25519();
+        let policy = RetentionPolicy {
+            minimum: Some(3600),
+            maximum: Some(60),
+            legal_hold: false,
+        };
+
+        assert!(
+            RetentionPolicyDocument::new(&keypair, Uuid::new_v4(), policy, Utc::now()).is_err()
+        );
+    }
+}
@@ -0,0 +1,363 @@
+//! Keys of the devices of an account.
+//!
+//! Each device generates its own keypair, which is kept in tesseract and never leaves the device. The account key
+//! signs a [`DeviceCertificate`] for it, and the certificates of the active devices are published in the identity
+//! document along with the devices that were revoked, which are never registered again. Keys shared with an account, such as the keys of group conversations, are wrapped for each of
+//! its devices rather than the account key once it publishes devices, so a device can be revoked by removing its
+//! certificate and rotating the shared keys instead of replacing the identity.
+//!
+//! A new device is linked by a device of the account, which issues a certificate for the keypair of the new device,
+//! records the certificate in the root document and returns a [`DeviceLink`]. The link only carries the certificate,
+//! never the account key, so the new device still has to be given the passphrase of the account before it fetches the
+//! root document from shuttle. The certificate issued by the link is then used for the key of the device.
+//!
+//! Note: Direct conversations still derive their key from the account keys of both participants.
+use chrono::{DateTime, Utc};
+use rust_ipfs::{Ipfs, Keypair};
+use serde::{Deserialize, Serialize};
+use warp::{
+    crypto::{zeroize::Zeroizing, DID},
+    error::Error,
+};
+
+use super::{ds_key::DataStoreKey, ecdh_decrypt, ecdh_encrypt, DidExt, PeerIdExt};
+
+/// Maximum amount of devices that can be published in an identity document
+pub const MAX_DEVICES: usize = 16;
+
+/// Maximum amount of revoked devices that can be published in an identity document
+pub const MAX_REVOKED_DEVICES: usize = 256;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DeviceCertificate {
+    pub account: DID,
+    pub device: DID,
+    pub issued: DateTime<Utc>,
+    pub signature: String,
+}
+
+impl DeviceCertificate {
+    pub fn new(keypair: &Keypair, device: DID, issued: DateTime<Utc>) -> Result<Self, Error> {
+        let account = keypair.to_did()?;
+
+        let mut certificate = Self {
+            account,
+            device,
+            issued,
+            signature: String::new(),
+        };
+
+        let signature = keypair.sign(&certificate.construct()).expect("not RSA");
+        certificate.signature = bs58::encode(signature).into_string();
+
+        Ok(certificate)
+    }
+
+    /// Verify that the certificate was issued by `account`
+    pub fn verify(&self, account: &DID) -> Result<(), Error> {
+        if self.account.ne(account) {
+            return Err(Error::InvalidSignature);
+        }
+
+        let account_pk = self.account.to_public_key()?;
+        let signature = bs58::decode(&self.signature).into_vec()?;
+
+        if !account_pk.verify(&self.construct(), &signature) {
+            return Err(Error::InvalidSignature);
+        }
+
+        Ok(())
+    }
+
+    fn construct(&self) -> Vec<u8> {
+        warp::crypto::hash::sha256_iter(
+            [
+                Some(self.account.to_string().as_bytes().to_vec()),
+                Some(self.device.to_string().as_bytes().to_vec()),
+                Some(self.issued.timestamp_millis().to_be_bytes().to_vec()),
+            ]
+            .into_iter(),
+            None,
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredDeviceKey {
+    /// Keypair of the device, encrypted with the account key. Only set by devices that stored their key before it
+    /// was kept in tesseract
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key: Option<Vec<u8>>,
+    certificate: DeviceCertificate,
+}
+
+/// Keypair of this device, along with the certificate issued for it by the account
+#[derive(Clone)]
+pub struct DeviceKey {
+    keypair: Keypair,
+    certificate: DeviceCertificate,
+}
+
+impl DeviceKey {
+    /// Keypair of the device that was stored encrypted with the account key, so it can be moved into tesseract. The
+    /// key is removed from the datastore once the device is loaded with it
+    pub async fn legacy_keypair(ipfs: &Ipfs, account: &Keypair) -> Result<Option<Keypair>, Error> {
+        let key = ipfs.device_key();
+
+        let Some(encrypted) = ipfs
+            .repo()
+            .data_store()
+            .get(key.as_bytes())
+            .await
+            .unwrap_or_default()
+            .and_then(|bytes| serde_json::from_slice::<StoredDeviceKey>(&bytes).ok())
+            .and_then(|stored| stored.key)
+        else {
+            return Ok(None);
+        };
+
+        let bytes = Zeroizing::new(ecdh_decrypt(account, None, &encrypted)?);
+        let keypair = Keypair::from_protobuf_encoding(&bytes).map_err(anyhow::Error::from)?;
+        Ok(Some(keypair))
+    }
+
+    /// Load the certificate issued for the keypair of the device, issuing one on first use
+    pub async fn load(ipfs: &Ipfs, account: &Keypair, keypair: Keypair) -> Result<Self, Error> {
+        let key = ipfs.device_key();
+
+        let stored = ipfs
+            .repo()
+            .data_store()
+            .get(key.as_bytes())
+            .await
+            .unwrap_or_default()
+            .and_then(|bytes| serde_json::from_slice::<StoredDeviceKey>(&bytes).ok());
+
+        let device = keypair.to_did()?;
+
+        let certificate = match stored.filter(|stored| stored.certificate.device == device) {
+            Some(stored) if stored.key.is_none() => {
+                stored.certificate.verify(&account.to_did()?)?;
+                return Ok(Self {
+                    keypair,
+                    certificate: stored.certificate,
+                });
+            }
+            Some(stored) => stored.certificate,
+            None => DeviceCertificate::new(account, device, Utc::now())?,
+        };
+
+        Self::store(ipfs, account, keypair, certificate).await
+    }
+
+    /// Store the certificate issued for the key of the device, such as when the device was linked. The keypair
+    /// itself is kept in tesseract so it does not depend on the account key
+    pub async fn store(
+        ipfs: &Ipfs,
+        account: &Keypair,
+        keypair: Keypair,
+        certificate: DeviceCertificate,
+    ) -> Result<Self, Error> {
+        certificate.verify(&account.to_did()?)?;
+
+        if certificate.device.ne(&keypair.to_did()?) {
+            return Err(Error::PublicKeyInvalid);
+        }
+
+        let stored = StoredDeviceKey {
+            key: None,
+            certificate: certificate.clone(),
+        };
+
+        let key = ipfs.device_key();
+
+        ipfs.repo()
+            .data_store()
+            .put(key.as_bytes(), &serde_json::to_vec(&stored)?)
+            .await
+            .map_err(anyhow::Error::from)?;
+
+        Ok(Self {
+            keypair,
+            certificate,
+        })
+    }
+
+    pub fn keypair(&self) -> &Keypair {
+        &self.keypair
+    }
+
+    pub fn did(&self) -> &DID {
+        &self.certificate.device
+    }
+
+    pub fn certificate(&self) -> &DeviceCertificate {
+        &self.certificate
+    }
+}
+
+/// Authorization of a new device, issued by a device of the account
+#[derive(Serialize, Deserialize)]
+pub struct DeviceLink {
+    pub certificate: DeviceCertificate,
+}
+
+impl DeviceLink {
+    pub fn new(certificate: DeviceCertificate) -> Self {
+        Self { certificate }
+    }
+
+    pub fn encode(&self) -> Result<String, Error> {
+        let bytes = serde_json::to_vec(self)?;
+        Ok(bs58::encode(bytes).into_string())
+    }
+
+    pub fn decode(code: &str) -> Result<Self, Error> {
+        let bytes = bs58::decode(code.trim()).into_vec()?;
+        serde_json::from_slice(&bytes).map_err(Error::from)
+    }
+
+    /// Verify that the link was issued by `account` for the keypair of the device
+    pub fn verify(&self, account: &DID, device: &Keypair) -> Result<(), Error> {
+        if self.certificate.device.ne(&device.to_did()?) {
+            return Err(Error::PublicKeyInvalid);
+        }
+
+        self.certificate.verify(account)
+    }
+}
+
+/// Wrap `key` for each of the devices, returning the wrapped key keyed by the device
+pub fn wrap_key(
+    keypair: &Keypair,
+    devices: &[DID],
+    key: &[u8],
+) -> Result<Vec<(DID, Vec<u8>)>, Error> {
+    devices
+        .iter()
+        .map(|device| Ok((device.clone(), ecdh_encrypt(keypair, Some(device), key)?)))
+        .collect()
+}
+
+/// Unwrap the key that `sender` wrapped for this device
+pub fn unwrap_key(
+    device: &DeviceKey,
+    sender: &DID,
+    wrapped: &[(DID, Vec<u8>)],
+) -> Result<Vec<u8>, Error> {
+    let (_, key) = wrapped
+        .iter()
+        .find(|(did, _)| did == device.did())
+        .ok_or(Error::PublicKeyDoesntExist)?;
+
+    ecdh_decrypt(device.keypair(), Some(sender), key)
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+    use rust_ipfs::{Keypair, UninitializedIpfsDefault};
+    use warp::crypto::generate;
+
+    use super::{unwrap_key, wrap_key, DeviceCertificate, DeviceKey, DeviceLink, StoredDeviceKey};
+    use crate::store::{ds_key::DataStoreKey, ecdh_encrypt, PeerIdExt};
+
+    #[test]
+    fn certificate_from_another_account_is_rejected() -> anyhow::Result<()> {
+        let account = Keypair::generate_ed25519();
+        let impostor = Keypair::generate_ed25519();
+        let device = Keypair::generate_ed25519().to_did()?;
+
+        let certificate = DeviceCertificate::new(&impostor, device.clone(), Utc::now())?;
+        assert!(certificate.verify(&account.to_did()?).is_err());
+
+        let certificate = DeviceCertificate::new(&account, device, Utc::now())?;
+        certificate.verify(&account.to_did()?)?;
+        Ok(())
+    }
+
+    #[test]
+    fn link_is_only_accepted_by_its_device() -> anyhow::Result<()> {
+        let account = Keypair::generate_ed25519();
+        let device = Keypair::generate_ed25519();
+        let other = Keypair::generate_ed25519();
+
+        let certificate = DeviceCertificate::new(&account, device.to_did()?, Utc::now())?;
+        let link = DeviceLink::new(certificate);
+
+        let code = link.encode()?;
+        let link = DeviceLink::decode(&code)?;
+        assert!(link.verify(&account.to_did()?, &other).is_err());
+        assert!(link.verify(&other.to_did()?, &device).is_err());
+        link.verify(&account.to_did()?, &device)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn keys_are_wrapped_for_devices() -> anyhow::Result<()> {
+        let ipfs = UninitializedIpfsDefault::new()
+            .start()
+            .await
+            .expect("constructed ipfs instance");
+
+        let sender = Keypair::generate_ed25519();
+        let account = Keypair::generate_ed25519();
+
+        let keypair = Keypair::generate_ed25519();
+        let device = DeviceKey::load(&ipfs, &account, keypair.clone()).await?;
+
+        // the certificate of the device is kept
+        let loaded = DeviceKey::load(&ipfs, &account, keypair).await?;
+        assert_eq!(loaded.certificate(), device.certificate());
+
+        let key = generate::<64>();
+        let other_device = Keypair::generate_ed25519().to_did()?;
+        let wrapped = wrap_key(&sender, &[other_device, device.did().clone()], &key)?;
+
+        let unwrapped = unwrap_key(&device, &sender.to_did()?, &wrapped)?;
+        assert_eq!(unwrapped, key);
+
+        // a revoked device is no longer among the devices the key is wrapped for
+        let wrapped = wrap_key(&sender, &[], &key)?;
+        assert!(unwrap_key(&device, &sender.to_did()?, &wrapped).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn legacy_device_key_is_removed_from_datastore() -> anyhow::Result<()> {
+        let ipfs = UninitializedIpfsDefault::new()
+            .start()
+            .await
+            .expect("constructed ipfs instance");
+
+        let account = Keypair::generate_ed25519();
+        let keypair = Keypair::generate_ed25519();
+        let certificate = DeviceCertificate::new(&account, keypair.to_did()?, Utc::now())?;
+
+        let stored = StoredDeviceKey {
+            key: Some(ecdh_encrypt(
+                &account,
+                None,
+                keypair.to_protobuf_encoding()?,
+            )?),
+            certificate: certificate.clone(),
+        };
+
+        ipfs.repo()
+            .data_store()
+            .put(ipfs.device_key().as_bytes(), &serde_json::to_vec(&stored)?)
+            .await?;
+
+        let legacy = DeviceKey::legacy_keypair(&ipfs, &account)
+            .await?
+            .expect("legacy keypair");
+        assert_eq!(legacy.to_did()?, keypair.to_did()?);
+
+        let device = DeviceKey::load(&ipfs, &account, legacy).await?;
+        assert_eq!(device.certificate(), &certificate);
+
+        // the key is removed from the datastore once loaded, while the certificate issued for it is kept
+        assert!(DeviceKey::legacy_keypair(&ipfs, &account).await?.is_none());
+        Ok(())
+    }
+}
@@ -13,6 +13,7 @@ use warp::{crypto::DID, error::Error};
 
 use crate::{
     config::{Discovery as DiscoveryConfig, DiscoveryType},
+    rt,
     store::topics::PeerTopic,
 };
 
@@ -55,7 +56,7 @@ impl Discovery {
                 let namespace = namespace.clone().unwrap_or_else(|| "warp-mp-ipfs".into());
                 let cid = self.ipfs.put_dag(format!("discovery:{namespace}")).await?;
 
-                let task = async_rt::task::spawn_abortable({
+                let task = rt::spawn_abortable({
                     let discovery = self.clone();
                     async move {
                         let mut cached = HashSet::new();
@@ -141,7 +142,7 @@ impl Discovery {
                     ));
                 }
 
-                let task = async_rt::task::spawn_abortable({
+                let task = rt::spawn_abortable({
                     let discovery = self.clone();
                     let register_id = register_id;
                     async move {
@@ -440,7 +441,7 @@ impl DiscoveryEntry {
             }
         };
 
-        let guard = async_rt::task::spawn_abortable(fut);
+        let guard = rt::spawn_abortable(fut);
 
         *holder = Some(guard);
     }
@@ -14,7 +14,7 @@ use std::hash::{Hash, Hasher};
 use std::{collections::BTreeMap, path::Path, str::FromStr, time::Duration};
 use uuid::Uuid;
 
-use super::{keystore::Keystore, DidExt, MAX_IMAGE_SIZE};
+use super::{device::DeviceCertificate, keystore::Keystore, DidExt, MAX_IMAGE_SIZE};
 use warp::{
     constellation::{
         directory::Directory,
@@ -32,6 +32,7 @@ use self::{
 };
 
 pub mod cache;
+pub mod codec;
 pub mod files;
 pub mod identity;
 pub mod image_dag;
@@ -49,6 +50,16 @@ pub struct ResolvedRootDocument {
     pub file_index: Option<Directory>,
     pub request: Vec<u8>,
     pub conversation_keystore: BTreeMap<Uuid, Keystore>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub app_data: Vec<u8>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notification_preferences: Vec<u8>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub profile_fields: Vec<u8>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub friends_privacy: Vec<u8>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub devices: Vec<DeviceCertificate>,
     pub signature: Option<Vec<u8>>,
 }
 
@@ -101,6 +112,28 @@ pub struct RootDocument {
     /// index to constellation
     #[serde(skip_serializing_if = "Option::is_none")]
     pub file_index: Option<Cid>,
+    /// map of application defined data
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app_data: Option<Cid>,
+    /// map of local relationship history
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relationship_history: Option<Cid>,
+    /// notification preferences
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notification_preferences: Option<Cid>,
+    /// profile fields, including those only visible to friends
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile_fields: Option<Cid>,
+    /// settings controlling what is shared about the friends list
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub friends_privacy: Option<Cid>,
+    /// latest addition or removal of each item of the friends, blocks, block_by and request lists, used to merge
+    /// the lists with the root document of another device
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub list_changes: Option<Cid>,
+    /// array of certificates of the devices linked to the identity (DeviceCertificate)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub devices: Option<Cid>,
     /// Online/Away/Busy/Offline status
     #[serde(skip_serializing_if = "Option::is_none")]
     pub status: Option<IdentityStatus>,
@@ -224,6 +257,62 @@ impl RootDocument {
             .await
             .unwrap_or_default();
 
+        let app_data = futures::future::ready(self.app_data.ok_or(Error::Other))
+            .and_then(|document| async move {
+                ipfs.get_dag(document)
+                    .local()
+                    .deserialized()
+                    .await
+                    .map_err(Error::from)
+            })
+            .await
+            .unwrap_or_default();
+
+        let notification_preferences =
+            futures::future::ready(self.notification_preferences.ok_or(Error::Other))
+                .and_then(|document| async move {
+                    ipfs.get_dag(document)
+                        .local()
+                        .deserialized()
+                        .await
+                        .map_err(Error::from)
+                })
+                .await
+                .unwrap_or_default();
+
+        let profile_fields = futures::future::ready(self.profile_fields.ok_or(Error::Other))
+            .and_then(|document| async move {
+                ipfs.get_dag(document)
+                    .local()
+                    .deserialized()
+                    .await
+                    .map_err(Error::from)
+            })
+            .await
+            .unwrap_or_default();
+
+        let friends_privacy = futures::future::ready(self.friends_privacy.ok_or(Error::Other))
+            .and_then(|document| async move {
+                ipfs.get_dag(document)
+                    .local()
+                    .deserialized()
+                    .await
+                    .map_err(Error::from)
+            })
+            .await
+            .unwrap_or_default();
+
+        let devices = futures::future::ready(self.devices.ok_or(Error::Other))
+            .and_then(|document| async move {
+                ipfs.get_dag(document)
+                    .local()
+                    .deserialized()
+                    .await
+                    .map_err(Error::from)
+            })
+            .await
+            .unwrap_or_default();
+
         // TODO: Uncomment when tying the files portion to shuttle
         // let file_index = futures::future::ready(self.file_index.ok_or(Error::Other))
         //     .and_then(|document| async move {
@@ -249,6 +338,11 @@ impl RootDocument {
             request,
             file_index,
             conversation_keystore,
+            app_data,
+            notification_preferences,
+            profile_fields,
+            friends_privacy,
+            devices,
             signature: None,
         };
 
@@ -317,6 +411,74 @@ impl RootDocument {
                 }
             });
 
+        let fut_app_data =
+            futures::future::ready(self.app_data.ok_or(Error::Other)).and_then(|document| {
+                let ipfs = ipfs.clone();
+                async move {
+                    ipfs.get_dag(document)
+                        .await
+                        .map_err(anyhow::Error::from)
+                        .map_err(Error::from)
+                }
+            });
+
+        let fut_notification_preferences = futures::future::ready(
+            self.notification_preferences.ok_or(Error::Other),
+        )
+        .and_then(|document| {
+            let ipfs = ipfs.clone();
+            async move {
+                ipfs.get_dag(document)
+                    .await
+                    .map_err(anyhow::Error::from)
+                    .map_err(Error::from)
+            }
+        });
+
+        let fut_profile_fields = futures::future::ready(self.profile_fields.ok_or(Error::Other))
+            .and_then(|document| {
+                let ipfs = ipfs.clone();
+                async move {
+                    ipfs.get_dag(document)
+                        .await
+                        .map_err(anyhow::Error::from)
+                        .map_err(Error::from)
+                }
+            });
+
+        let fut_friends_privacy = futures::future::ready(self.friends_privacy.ok_or(Error::Other))
+            .and_then(|document| {
+                let ipfs = ipfs.clone();
+                async move {
+                    ipfs.get_dag(document)
+                        .await
+                        .map_err(anyhow::Error::from)
+                        .map_err(Error::from)
+                }
+            });
+
+        let fut_list_changes = futures::future::ready(self.list_changes.ok_or(Error::Other))
+            .and_then(|document| {
+                let ipfs = ipfs.clone();
+                async move {
+                    ipfs.get_dag(document)
+                        .await
+                        .map_err(anyhow::Error::from)
+                        .map_err(Error::from)
+                }
+            });
+
+        let fut_devices =
+            futures::future::ready(self.devices.ok_or(Error::Other)).and_then(|document| {
+                let ipfs = ipfs.clone();
+                async move {
+                    ipfs.get_dag(document)
+                        .await
+                        .map_err(anyhow::Error::from)
+                        .map_err(Error::from)
+                }
+            });
+
         let fut_keystore = futures::future::ready(self.keystore.ok_or(Error::Other)).and_then(
             |document| {
                 let ipfs = ipfs.clone();
@@ -352,6 +514,12 @@ impl RootDocument {
             fut_block_list,
             fut_blocked_by_list,
             fut_requests_list,
+            fut_app_data,
+            fut_notification_preferences,
+            fut_profile_fields,
+            fut_friends_privacy,
+            fut_list_changes,
+            fut_devices,
             fut_keystore
         );
 
@@ -389,6 +557,13 @@ impl RootDocument {
             keystore: None,
             communities: None,
             file_index: None,
+            app_data: None,
+            relationship_history: None,
+            notification_preferences: None,
+            profile_fields: None,
+            friends_privacy: None,
+            list_changes: None,
+            devices: None,
             status: None,
             signature: None,
         };
@@ -398,6 +573,11 @@ impl RootDocument {
         let has_block_by_list = !data.block_by_list.is_empty();
         let has_requests = !data.request.is_empty();
         let has_keystore = !data.conversation_keystore.is_empty();
+        let has_app_data = !data.app_data.is_empty();
+        let has_notification_preferences = !data.notification_preferences.is_empty();
+        let has_profile_fields = !data.profile_fields.is_empty();
+        let has_friends_privacy = !data.friends_privacy.is_empty();
+        let has_devices = !data.devices.is_empty();
 
         if has_friends {
             root_document.friends = ipfs.put_dag(data.friends).await.ok();
@@ -415,6 +595,27 @@ impl RootDocument {
             root_document.request = ipfs.put_dag(data.request).await.ok();
         }
 
+        if has_app_data {
+            root_document.app_data = ipfs.put_dag(data.app_data).await.ok();
+        }
+
+        if has_notification_preferences {
+            root_document.notification_preferences =
+                ipfs.put_dag(data.notification_preferences).await.ok();
+        }
+
+        if has_profile_fields {
+            root_document.profile_fields = ipfs.put_dag(data.profile_fields).await.ok();
+        }
+
+        if has_friends_privacy {
+            root_document.friends_privacy = ipfs.put_dag(data.friends_privacy).await.ok();
+        }
+
+        if has_devices {
+            root_document.devices = ipfs.put_dag(data.devices).await.ok();
+        }
+
         if has_keystore {
             let mut pointer_map: BTreeMap<String, Cid> = BTreeMap::new();
             for (k, v) in data.conversation_keystore {
@@ -273,6 +273,7 @@ mod test {
 
         let document = IdentityDocument {
             username: warp::multipass::generator::generate_name(),
+            handle: None,
             short_id: bytes[bytes.len() - SHORT_ID_SIZE..]
                 .try_into()
                 .expect("Valid conversion"),
@@ -280,9 +281,15 @@ mod test {
             created: time,
             modified: time,
             status_message: None,
+            activity: None,
+            profile: None,
+            friends_summary: None,
             metadata: Default::default(),
+            devices: vec![],
+            revoked_devices: vec![],
             version: Default::default(),
             signature: None,
+            extended_signature: None,
         };
 
         let document = document.sign(&keypair).expect("valid");
@@ -0,0 +1,96 @@
+//! Encoding of the payloads of the root document that are encrypted before being stored in a dag.
+//!
+//! The documents themselves are stored as dag-cbor, but encrypted payloads are opaque to the dag and were written
+//! as JSON, which remains the default. Payloads can be written as cbor following a version byte, which JSON never
+//! starts with, so payloads in either encoding are read. Existing payloads are only migrated to the configured
+//! encoding when [`StoreSetting::migrate_document_payloads`] is enabled.
+//!
+//! [`StoreSetting::migrate_document_payloads`]: crate::config::StoreSetting::migrate_document_payloads
+use serde::{de::DeserializeOwned, Serialize};
+use warp::error::Error;
+
+use crate::config::DocumentCodec;
+
+/// Version byte of payloads encoded as cbor
+const CBOR_V1: u8 = 0x01;
+
+impl DocumentCodec {
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Error> {
+        match self {
+            DocumentCodec::Json => serde_json::to_vec(value).map_err(Error::from),
+            DocumentCodec::Cbor => {
+                let bytes =
+                    cbor4ii::serde::to_vec(vec![CBOR_V1], value).map_err(std::io::Error::other)?;
+                Ok(bytes)
+            }
+        }
+    }
+
+    /// Encoding of the payload, or `None` if the version is unknown
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        match bytes.first() {
+            Some(&CBOR_V1) => Some(DocumentCodec::Cbor),
+            Some(byte) if byte.is_ascii() => Some(DocumentCodec::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Decode a payload in either encoding
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, Error> {
+    match DocumentCodec::detect(bytes) {
+        Some(DocumentCodec::Json) => serde_json::from_slice(bytes).map_err(Error::from),
+        Some(DocumentCodec::Cbor) => {
+            let value = cbor4ii::serde::from_slice(&bytes[1..]).map_err(std::io::Error::other)?;
+            Ok(value)
+        }
+        None => Err(Error::OtherWithContext(
+            "Unknown encoding of document payload".into(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use bytes::Bytes;
+    use rust_ipfs::Keypair;
+    use warp::crypto::DID;
+
+    use super::decode;
+    use crate::config::DocumentCodec;
+    use crate::store::PeerIdExt;
+
+    #[test]
+    fn payloads_are_read_in_either_encoding() -> anyhow::Result<()> {
+        let friends: Vec<DID> = (0..3)
+            .map(|_| Keypair::generate_ed25519().to_did())
+            .collect::<Result<_, _>>()?;
+
+        let json = DocumentCodec::Json.encode(&friends)?;
+        let cbor = DocumentCodec::Cbor.encode(&friends)?;
+
+        assert_eq!(DocumentCodec::detect(&json), Some(DocumentCodec::Json));
+        assert_eq!(DocumentCodec::detect(&cbor), Some(DocumentCodec::Cbor));
+        assert!(cbor.len() < json.len());
+
+        assert_eq!(decode::<Vec<DID>>(&json)?, friends);
+        assert_eq!(decode::<Vec<DID>>(&cbor)?, friends);
+
+        // binary data is no longer encoded as an array of numbers
+        let map = BTreeMap::from([("app".to_string(), Bytes::from_static(&[0xff; 64]))]);
+        let json = DocumentCodec::Json.encode(&map)?;
+        let cbor = DocumentCodec::Cbor.encode(&map)?;
+        assert!(cbor.len() < json.len() / 2);
+        assert_eq!(decode::<BTreeMap<String, Bytes>>(&json)?, map);
+        assert_eq!(decode::<BTreeMap<String, Bytes>>(&cbor)?, map);
+        Ok(())
+    }
+
+    #[test]
+    fn defaults_to_json() {
+        // payloads stay readable by previous versions unless cbor is selected
+        assert_eq!(DocumentCodec::default(), DocumentCodec::Json);
+    }
+}
@@ -299,19 +299,24 @@ mod test {
 
     use super::DirectoryDocument;
     use crate::config::Config;
+    use crate::readiness::Readiness;
     use crate::store::document::root::RootDocumentMap;
-    use crate::store::{event_subscription::EventSubscription, files::FileStore};
+    use crate::store::{
+        event_subscription::EventSubscription, files::FileStore, sync::SelectiveSync,
+    };
 
     async fn file_store(
         ipfs: &Ipfs,
         event: &EventSubscription<ConstellationEventKind>,
     ) -> FileStore {
-        let root_document = RootDocumentMap::new(ipfs, None).await;
+        let root_document = RootDocumentMap::new(ipfs, None, Default::default()).await;
         FileStore::new(
             ipfs,
             &root_document,
             &Config::development(),
+            &SelectiveSync::new(ipfs).await,
             event.clone(),
+            &Readiness::default(),
             &Span::current(),
         )
         .await
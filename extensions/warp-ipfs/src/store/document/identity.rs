@@ -1,27 +1,53 @@
 use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
 use ipld_core::cid::Cid;
 use rust_ipfs::Keypair;
 use serde::{Deserialize, Serialize};
+use sha2::Sha256;
 use std::hash::Hash;
 use warp::{
-    crypto::{Fingerprint, DID},
+    crypto::{zeroize::Zeroizing, Fingerprint, DID},
     error::Error,
-    multipass::identity::{Identity, IdentityStatus, Platform, SHORT_ID_SIZE},
+    multipass::{
+        identity::{
+            FriendsPrivacy, Identity, IdentityActivity, IdentityStatus, Platform, ProfileField,
+            ProfileFields, SHORT_ID_SIZE,
+        },
+        IdentityChange,
+    },
 };
 
-use crate::store::{DidExt, MAX_STATUS_LENGTH, MAX_USERNAME_LENGTH, MIN_USERNAME_LENGTH};
+use crate::store::{
+    compression::Compression,
+    device::{DeviceCertificate, MAX_DEVICES, MAX_REVOKED_DEVICES},
+    ecdh_shared_key, DidExt, MAX_ACTIVITY_DETAILS_LENGTH, MAX_ACTIVITY_TITLE_LENGTH, MAX_FRIENDS,
+    MAX_PROFILE_LINKS, MAX_PROFILE_LINK_LENGTH, MAX_PRONOUNS_LENGTH, MAX_STATUS_LENGTH,
+    MAX_TIMEZONE_LENGTH, MAX_USERNAME_LENGTH, MIN_USERNAME_LENGTH,
+};
 
 #[derive(Debug, Default, Clone, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum IdentityDocumentVersion {
     #[default]
     V0,
+    /// Fields added after [`IdentityDocumentVersion::V0`] (handle, activity, profile, devices and
+    /// revoked devices).
+    /// These are covered by [`IdentityDocument::extended_signature`] so peers that only know of
+    /// V0 can still verify the document. Documents are still published as V0 since older peers
+    /// are unable to deserialize a newer version
+    V1,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, Eq)]
 pub struct IdentityDocument {
+    // display name of the identity
     pub username: String,
 
+    // handle chosen when the identity was created. Identities created before handles were
+    // introduced will be migrated to use their username as their handle
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub handle: Option<String>,
+
     pub short_id: [u8; SHORT_ID_SIZE],
 
     pub did: DID,
@@ -33,13 +59,40 @@ pub struct IdentityDocument {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub status_message: Option<String>,
 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub activity: Option<IdentityActivity>,
+
+    // profile fields visible to the recipient of the document. Fields only visible to friends
+    // are never stored here and are only included when pushing the document to a friend
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<ProfileFields>,
+
     pub metadata: IdentityMetadata,
 
+    // certificates of the active devices of the identity, issued by the identity
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub devices: Vec<DeviceCertificate>,
+
+    // devices that were revoked by the identity. These are never registered again
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub revoked_devices: Vec<DID>,
+
+    // information about the friends list shared with the recipient of the document. This is only
+    // set when pushing the document directly to an identity and, like the metadata, is not signed
+    // since it is different for every recipient
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub friends_summary: Option<FriendsSummary>,
+
     #[serde(default)]
     pub version: IdentityDocumentVersion,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub signature: Option<String>,
+
+    // signature over the fields added in V1 along with `signature`. Only set when any of those
+    // fields are present
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extended_signature: Option<String>,
 }
 
 #[derive(Default, Debug, Clone, Copy, Deserialize, Serialize, Eq, PartialEq)]
@@ -58,11 +111,120 @@ pub struct IdentityMetadata {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub arb_data: Option<Cid>,
+
+    // compression of messages that the identity is able to read
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<Compression>,
+
+    // optional events of a conversation that the identity is able to process
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub features: Vec<Feature>,
+}
+
+/// Optional events of a conversation. Events of a feature are only sent once every member advertised it, since
+/// older peers are unable to deserialize them
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, Eq, PartialEq, Hash)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum Feature {
+    RetentionPolicy,
+    /// Requesting the events missed while offline with `CatchUp`
+    CatchUp,
+    /// Delivery and read receipts of messages
+    Receipts,
+    /// Feature advertised by a newer version
+    #[serde(other)]
+    Unknown,
+}
+
+impl Feature {
+    /// Features that this node is able to process
+    pub fn supported() -> Vec<Feature> {
+        vec![
+            Feature::RetentionPolicy,
+            Feature::CatchUp,
+            Feature::Receipts,
+        ]
+    }
+}
+
+/// Friend count and digests of the friends of an identity that were shared with a recipient. The
+/// friends list itself is never shared. Each digest is keyed with the secret shared between the
+/// identity and the recipient so it can only be matched by the recipient to find mutual friends
+#[derive(Default, Debug, Clone, Deserialize, Serialize, Eq, PartialEq)]
+pub struct FriendsSummary {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count: Option<usize>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mutual: Vec<String>,
+}
+
+impl FriendsSummary {
+    pub fn new(
+        keypair: &Keypair,
+        friends: &[DID],
+        recipient: &DID,
+        is_friend: bool,
+        privacy: &FriendsPrivacy,
+    ) -> Result<Self, Error> {
+        let count = privacy
+            .share_friend_count(is_friend)
+            .then_some(friends.len());
+
+        let mutual = match privacy.share_mutual_friends(is_friend) {
+            true => {
+                let secret = Zeroizing::new(ecdh_shared_key(keypair, Some(recipient))?);
+                friends
+                    .iter()
+                    .filter(|friend| *friend != recipient)
+                    .map(|friend| friend_digest(&secret, friend))
+                    .collect()
+            }
+            false => vec![],
+        };
+
+        Ok(FriendsSummary { count, mutual })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count.is_none() && self.mutual.is_empty()
+    }
+
+    /// Friends of the recipient that are listed in the summary shared by `sender`
+    pub fn mutual_friends(
+        &self,
+        keypair: &Keypair,
+        sender: &DID,
+        friends: &[DID],
+    ) -> Result<Vec<DID>, Error> {
+        if self.mutual.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let secret = Zeroizing::new(ecdh_shared_key(keypair, Some(sender))?);
+
+        Ok(friends
+            .iter()
+            .filter(|friend| self.mutual.contains(&friend_digest(&secret, friend)))
+            .cloned()
+            .collect())
+    }
+}
+
+// HMAC of the friend keyed with the shared secret of the pair, since a plain hash of public keys
+// could be reversed by hashing every known identity
+fn friend_digest(secret: &[u8], friend: &DID) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("hmac accepts keys of any size");
+    mac.update(friend.to_string().as_bytes());
+    bs58::encode(mac.finalize().into_bytes()).into_string()
 }
 
 impl From<Identity> for IdentityDocument {
     fn from(identity: Identity) -> Self {
         let username = identity.username().to_owned();
+        let handle = Some(identity.handle())
+            .filter(|handle| !handle.is_empty())
+            .map(ToOwned::to_owned);
         let did = identity.did_key().to_owned();
         let short_id = *identity.short_id();
         let status_message = identity.status_message().map(ToOwned::to_owned);
@@ -71,14 +233,21 @@ impl From<Identity> for IdentityDocument {
 
         IdentityDocument {
             username,
+            handle,
             short_id,
             did,
             status_message,
+            activity: None,
+            profile: None,
+            friends_summary: None,
             created,
             modified,
             metadata: Default::default(),
+            devices: vec![],
+            revoked_devices: vec![],
             version: IdentityDocumentVersion::V0,
             signature: None,
+            extended_signature: None,
         }
     }
 }
@@ -96,6 +265,7 @@ impl From<&IdentityDocument> for Identity {
         identity.set_short_id(document.short_id);
         identity.set_status_message(document.status_message.clone());
         identity.set_username(&document.username);
+        identity.set_handle(document.handle());
         identity.set_created(document.created);
         identity.set_modified(document.modified);
         identity
@@ -131,34 +301,141 @@ impl IdentityDocument {
         }
 
         self.username != other.username
+            || self.handle != other.handle
             || self.status_message != other.status_message
+            || self.activity != other.activity
+            || self.profile != other.profile
             || self.metadata != other.metadata
     }
+
+    // Changes made to the identity in the newer document. Changes to the arbitrary metadata are
+    // not included since the data is retrieved separately
+    pub fn changes(&self, other: &Self) -> Vec<IdentityChange> {
+        let mut changes = vec![];
+
+        if self.username != other.username {
+            changes.push(IdentityChange::Username {
+                username: other.username.clone(),
+            });
+        }
+
+        if self.status_message != other.status_message {
+            changes.push(IdentityChange::StatusMessage {
+                status_message: other.status_message.clone(),
+            });
+        }
+
+        if self.activity != other.activity {
+            changes.push(IdentityChange::Activity {
+                activity: other.activity.clone(),
+            });
+        }
+
+        if self.profile != other.profile {
+            changes.push(IdentityChange::ProfileFields {
+                fields: other.profile.clone().unwrap_or_default(),
+            });
+        }
+
+        if self.metadata.profile_picture != other.metadata.profile_picture {
+            changes.push(IdentityChange::Picture {
+                cid: other.metadata.profile_picture.map(|cid| cid.to_string()),
+            });
+        }
+
+        if self.metadata.profile_banner != other.metadata.profile_banner {
+            changes.push(IdentityChange::Banner {
+                cid: other.metadata.profile_banner.map(|cid| cid.to_string()),
+            });
+        }
+
+        if let Some(status) = other.metadata.status {
+            if self.metadata.status != Some(status) {
+                changes.push(IdentityChange::Status { status });
+            }
+        }
+
+        if let Some(platform) = other.metadata.platform {
+            if self.metadata.platform != Some(platform) {
+                changes.push(IdentityChange::Platform { platform });
+            }
+        }
+
+        changes
+    }
 }
 
 impl IdentityDocument {
+    pub fn handle(&self) -> &str {
+        self.handle.as_deref().unwrap_or(&self.username)
+    }
+
+    // Used for lookups, where either the display name or the handle can be used
+    pub fn name_matches(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+        self.username.to_lowercase().contains(&name) || self.handle().to_lowercase().contains(&name)
+    }
+
+    pub fn name_eq(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+        self.username.to_lowercase().eq(&name) || self.handle().to_lowercase().eq(&name)
+    }
+
     pub fn resolve(&self) -> Result<Identity, Error> {
         self.verify()?;
         Ok(self.into())
     }
 
     pub fn sign(mut self, keypair: &Keypair) -> Result<Self, Error> {
-        let metadata = self.metadata;
-
-        //We blank out the metadata since it will not be used as apart of the
-        //identification process, but will include it after it is signed
-        self.metadata = Default::default();
         self.signature = None;
-
+        self.extended_signature = None;
         self.modified = Utc::now();
 
-        let bytes = serde_json::to_vec(&self)?;
+        let bytes = self.signing_bytes(IdentityDocumentVersion::V0)?;
         let signature = bs58::encode(keypair.sign(&bytes).expect("not RSA")).into_string();
-        self.metadata = metadata;
         self.signature = Some(signature);
+
+        if self.has_extended_fields() {
+            let bytes = self.signing_bytes(IdentityDocumentVersion::V1)?;
+            let signature = bs58::encode(keypair.sign(&bytes).expect("not RSA")).into_string();
+            self.extended_signature = Some(signature);
+        }
+
         Ok(self)
     }
 
+    fn has_extended_fields(&self) -> bool {
+        self.handle.is_some()
+            || self.activity.is_some()
+            || self.profile.is_some()
+            || !self.devices.is_empty()
+            || !self.revoked_devices.is_empty()
+    }
+
+    // Bytes covered by the signature of the given version. The V0 bytes match the serialization of
+    // the document by peers that only know of the V0 fields, while the V1 bytes cover the whole
+    // document, including the V0 signature
+    fn signing_bytes(&self, version: IdentityDocumentVersion) -> Result<Vec<u8>, Error> {
+        let mut payload = self.clone();
+
+        //We blank out the metadata since it will not be used as apart of the
+        //identification process
+        payload.metadata = Default::default();
+        payload.friends_summary = None;
+        payload.extended_signature = None;
+
+        if version == IdentityDocumentVersion::V0 {
+            payload.handle = None;
+            payload.activity = None;
+            payload.profile = None;
+            payload.devices.clear();
+            payload.revoked_devices.clear();
+            payload.signature = None;
+        }
+
+        serde_json::to_vec(&payload).map_err(Error::from)
+    }
+
     pub fn verify(&self) -> Result<(), Error> {
         let mut payload = self.clone();
 
@@ -175,6 +452,17 @@ impl IdentityDocument {
             });
         }
 
+        if let Some(handle) = &payload.handle {
+            if !(MIN_USERNAME_LENGTH..=MAX_USERNAME_LENGTH).contains(&handle.len()) {
+                return Err(Error::InvalidLength {
+                    context: "handle".into(),
+                    current: handle.len(),
+                    minimum: Some(MIN_USERNAME_LENGTH),
+                    maximum: Some(MAX_USERNAME_LENGTH),
+                });
+            }
+        }
+
         if payload.short_id.is_empty() {
             return Err(Error::IdentityInvalid); //TODO: Invalid short id
         }
@@ -202,16 +490,379 @@ impl IdentityDocument {
             }
         }
 
-        let _ = std::mem::take(&mut payload.metadata);
+        if let Some(activity) = &payload.activity {
+            validate_activity(activity)?;
+        }
+
+        if let Some(profile) = &payload.profile {
+            validate_profile_fields(profile)?;
+        }
+
+        if payload.devices.len() > MAX_DEVICES {
+            return Err(Error::InvalidLength {
+                context: "devices".into(),
+                current: payload.devices.len(),
+                minimum: None,
+                maximum: Some(MAX_DEVICES),
+            });
+        }
+
+        for certificate in &payload.devices {
+            certificate.verify(&payload.did)?;
+        }
+
+        if payload.revoked_devices.len() > MAX_REVOKED_DEVICES {
+            return Err(Error::InvalidLength {
+                context: "revoked devices".into(),
+                current: payload.revoked_devices.len(),
+                minimum: None,
+                maximum: Some(MAX_REVOKED_DEVICES),
+            });
+        }
+
+        if payload
+            .devices
+            .iter()
+            .any(|certificate| payload.revoked_devices.contains(&certificate.device))
+        {
+            return Err(Error::IdentityInvalid);
+        }
+
+        if let Some(summary) = payload.friends_summary.take() {
+            if summary.mutual.len() > MAX_FRIENDS {
+                return Err(Error::InvalidLength {
+                    context: "mutual friends".into(),
+                    current: summary.mutual.len(),
+                    minimum: None,
+                    maximum: Some(MAX_FRIENDS),
+                });
+            }
+        }
 
-        let signature = std::mem::take(&mut payload.signature).ok_or(Error::InvalidSignature)?;
-        let signature_bytes = bs58::decode(signature).into_vec()?;
-        let bytes = serde_json::to_vec(&payload)?;
         let pk = self.did.to_public_key()?;
+
+        let signature = self.signature.as_ref().ok_or(Error::InvalidSignature)?;
+        let signature_bytes = bs58::decode(signature).into_vec()?;
+        let bytes = self.signing_bytes(IdentityDocumentVersion::V0)?;
         if !pk.verify(&bytes, &signature_bytes) {
             return Err(Error::InvalidSignature);
         }
 
+        if !self.has_extended_fields() {
+            return Ok(());
+        }
+
+        let signature = self
+            .extended_signature
+            .as_ref()
+            .ok_or(Error::InvalidSignature)?;
+        let signature_bytes = bs58::decode(signature).into_vec()?;
+        let bytes = self.signing_bytes(IdentityDocumentVersion::V1)?;
+        if !pk.verify(&bytes, &signature_bytes) {
+            return Err(Error::InvalidSignature);
+        }
+
+        Ok(())
+    }
+}
+
+pub fn validate_activity(activity: &IdentityActivity) -> Result<(), Error> {
+    let title = activity.title();
+    if title.trim().is_empty() || title.len() > MAX_ACTIVITY_TITLE_LENGTH {
+        return Err(Error::InvalidLength {
+            context: "activity title".into(),
+            current: title.len(),
+            minimum: Some(1),
+            maximum: Some(MAX_ACTIVITY_TITLE_LENGTH),
+        });
+    }
+
+    if let Some(details) = activity.details() {
+        if details.len() > MAX_ACTIVITY_DETAILS_LENGTH {
+            return Err(Error::InvalidLength {
+                context: "activity details".into(),
+                current: details.len(),
+                minimum: None,
+                maximum: Some(MAX_ACTIVITY_DETAILS_LENGTH),
+            });
+        }
+    }
+
+    if let (Some(started), Some(ends)) = (activity.started(), activity.ends()) {
+        if ends < started {
+            return Err(Error::OtherWithContext(
+                "activity cannot end before it started".into(),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn validate_profile_fields(fields: &ProfileFields) -> Result<(), Error> {
+    if let Some(pronouns) = fields.pronouns() {
+        let value = pronouns.value();
+        if value.trim().is_empty() || value.len() > MAX_PRONOUNS_LENGTH {
+            return Err(Error::InvalidLength {
+                context: "pronouns".into(),
+                current: value.len(),
+                minimum: Some(1),
+                maximum: Some(MAX_PRONOUNS_LENGTH),
+            });
+        }
+    }
+
+    if let Some(timezone) = fields.timezone() {
+        validate_timezone(timezone)?;
+    }
+
+    if fields.links().len() > MAX_PROFILE_LINKS {
+        return Err(Error::InvalidLength {
+            context: "links".into(),
+            current: fields.links().len(),
+            minimum: None,
+            maximum: Some(MAX_PROFILE_LINKS),
+        });
+    }
+
+    for link in fields.links() {
+        validate_link(link)?;
+    }
+
+    Ok(())
+}
+
+// Only the format is checked since the timezone database is not available
+fn validate_timezone(timezone: &ProfileField) -> Result<(), Error> {
+    let value = timezone.value();
+    if value.is_empty() || value.len() > MAX_TIMEZONE_LENGTH {
+        return Err(Error::InvalidLength {
+            context: "timezone".into(),
+            current: value.len(),
+            minimum: Some(1),
+            maximum: Some(MAX_TIMEZONE_LENGTH),
+        });
+    }
+
+    if !value
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '_' | '-' | '+' | ':'))
+    {
+        return Err(Error::OtherWithContext(format!(
+            "\"{value}\" is not a valid timezone"
+        )));
+    }
+
+    Ok(())
+}
+
+fn validate_link(link: &ProfileField) -> Result<(), Error> {
+    let value = link.value();
+    if value.len() > MAX_PROFILE_LINK_LENGTH {
+        return Err(Error::InvalidLength {
+            context: "link".into(),
+            current: value.len(),
+            minimum: None,
+            maximum: Some(MAX_PROFILE_LINK_LENGTH),
+        });
+    }
+
+    let host = value
+        .strip_prefix("https://")
+        .or_else(|| value.strip_prefix("http://"))
+        .and_then(|rest| rest.split(['/', '?', '#']).next())
+        .unwrap_or_default();
+
+    if host.is_empty()
+        || host.starts_with('.')
+        || value.chars().any(|c| c.is_whitespace() || c.is_control())
+    {
+        return Err(Error::OtherWithContext(format!(
+            "\"{value}\" is not a valid link"
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+    use rust_ipfs::Keypair;
+    use warp::{
+        crypto::{Fingerprint, DID},
+        multipass::identity::{FieldVisibility, FriendsPrivacy, Identity, SHORT_ID_SIZE},
+    };
+
+    use super::{FriendsSummary, IdentityDocument};
+    use crate::store::PeerIdExt;
+
+    fn random_did() -> DID {
+        Keypair::generate_ed25519().to_did().expect("valid keypair")
+    }
+
+    fn unsigned_document(keypair: &Keypair) -> anyhow::Result<IdentityDocument> {
+        let did = keypair.to_did()?;
+        let fingerprint = did.fingerprint();
+        let bytes = fingerprint.as_bytes();
+        let time = Utc::now();
+
+        Ok(IdentityDocument {
+            username: "JohnDoe".into(),
+            handle: None,
+            short_id: bytes[bytes.len() - SHORT_ID_SIZE..].try_into()?,
+            did,
+            created: time,
+            modified: time,
+            status_message: None,
+            activity: None,
+            profile: None,
+            metadata: Default::default(),
+            devices: vec![],
+            revoked_devices: vec![],
+            friends_summary: None,
+            version: Default::default(),
+            signature: None,
+            extended_signature: None,
+        })
+    }
+
+    fn document_with_summary(summary: FriendsSummary) -> anyhow::Result<IdentityDocument> {
+        let keypair = Keypair::generate_ed25519();
+        let mut document = unsigned_document(&keypair)?;
+        document.friends_summary = Some(summary);
+
+        let document = document.sign(&keypair)?;
+        document.verify()?;
+        Ok(document)
+    }
+
+    #[test]
+    fn friends_summary_is_empty_by_default() -> anyhow::Result<()> {
+        let keypair = Keypair::generate_ed25519();
+        let friends = vec![random_did(), random_did()];
+        let recipient = random_did();
+
+        for is_friend in [true, false] {
+            let summary = FriendsSummary::new(
+                &keypair,
+                &friends,
+                &recipient,
+                is_friend,
+                &FriendsPrivacy::default(),
+            )?;
+            assert!(summary.is_empty());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn friends_summary_respects_visibility() -> anyhow::Result<()> {
+        let keypair = Keypair::generate_ed25519();
+        let friends = vec![random_did(), random_did()];
+        let recipient = random_did();
+
+        let mut privacy = FriendsPrivacy::default();
+        privacy.set_friend_count(Some(FieldVisibility::Public));
+        privacy.set_mutual_friends(Some(FieldVisibility::Friends));
+
+        let summary = FriendsSummary::new(&keypair, &friends, &recipient, false, &privacy)?;
+        assert_eq!(summary.count, Some(2));
+        assert!(summary.mutual.is_empty());
+
+        let summary = FriendsSummary::new(&keypair, &friends, &recipient, true, &privacy)?;
+        assert_eq!(summary.count, Some(2));
+        assert_eq!(summary.mutual.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn mutual_friends_only_match_recipient() -> anyhow::Result<()> {
+        let sender = Keypair::generate_ed25519();
+        let sender_did = sender.to_did()?;
+        let recipient = Keypair::generate_ed25519();
+        let recipient_did = recipient.to_did()?;
+        let other = Keypair::generate_ed25519();
+
+        let mutual = random_did();
+        let friends = vec![mutual.clone(), random_did()];
+
+        let mut privacy = FriendsPrivacy::default();
+        privacy.set_mutual_friends(Some(FieldVisibility::Public));
+
+        let summary = FriendsSummary::new(&sender, &friends, &recipient_did, false, &privacy)?;
+
+        let recipient_friends = vec![mutual.clone(), random_did()];
+        assert_eq!(
+            summary.mutual_friends(&recipient, &sender_did, &recipient_friends)?,
+            vec![mutual.clone()]
+        );
+
+        // The digests are keyed with the secret shared by the sender and the recipient, so they
+        // cannot be matched by anyone else, even when they know every identity involved
+        assert!(summary
+            .mutual_friends(&other, &sender_did, &friends)?
+            .is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn outbound_document_does_not_contain_friends_list() -> anyhow::Result<()> {
+        let keypair = Keypair::generate_ed25519();
+        let friends = vec![random_did(), random_did(), random_did()];
+        let recipient = random_did();
+
+        let mut privacy = FriendsPrivacy::default();
+        privacy.set_friend_count(Some(FieldVisibility::Public));
+        privacy.set_mutual_friends(Some(FieldVisibility::Public));
+
+        let summary = FriendsSummary::new(&keypair, &friends, &recipient, true, &privacy)?;
+        let document = document_with_summary(summary)?;
+
+        let json = serde_json::to_string(&document)?;
+        let cbor = cbor4ii::serde::to_vec(Vec::new(), &document).map_err(std::io::Error::other)?;
+
+        assert!(document.friends_summary.is_some());
+
+        for friend in &friends {
+            let did = friend.to_string();
+            assert!(!json.contains(&did));
+            assert!(!cbor.windows(did.len()).any(|w| w == did.as_bytes()));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn extended_fields_do_not_break_legacy_signature() -> anyhow::Result<()> {
+        let keypair = Keypair::generate_ed25519();
+        let mut document = unsigned_document(&keypair)?;
+        document.handle = Some("JohnDoe".into());
+
+        let document = document.sign(&keypair)?;
+        document.verify()?;
+        assert!(document.extended_signature.is_some());
+
+        // A peer that only knows of the V0 fields drops the newer fields when deserializing
+        let mut legacy = document.clone();
+        legacy.handle = None;
+        legacy.extended_signature = None;
+        legacy.verify()?;
+
+        let mut tampered = document.clone();
+        tampered.handle = Some("JaneDoe".into());
+        assert!(tampered.verify().is_err());
+
+        let mut stripped = document;
+        stripped.extended_signature = None;
+        assert!(stripped.verify().is_err());
+
         Ok(())
     }
+
+    #[test]
+    fn document_from_identity_without_handle() {
+        let document = IdentityDocument::from(Identity::default());
+        assert!(document.handle.is_none());
+    }
 }
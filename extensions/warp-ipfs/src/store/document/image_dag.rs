@@ -4,6 +4,13 @@ use rust_ipfs::{Ipfs, PeerId};
 use serde::{Deserialize, Serialize};
 use warp::{constellation::file::FileType, error::Error, multipass::identity::IdentityImage};
 
+use crate::store::ds_key::DataStoreKey;
+use crate::thumbnail::resize_image;
+use crate::utils::ExtensionType;
+
+/// Dimensions, in pixels, of the renditions generated for profile pictures and banners
+pub const IMAGE_VARIANT_DIMENSIONS: [u32; 3] = [64, 128, 256];
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct ImageDag {
     pub link: Cid,
@@ -103,3 +110,110 @@ pub async fn get_image(
 
     Ok(id_img)
 }
+
+/// Get a rendition of the image that fits within `dimension` pixels.
+///
+/// Renditions are generated from the original image the first time they are requested and stored locally
+/// under the cid of the original, so a new image will always result in new renditions. If the requested
+/// dimension exceeds [`IMAGE_VARIANT_DIMENSIONS`], or the image is already small enough, the original is returned.
+#[tracing::instrument(skip(ipfs))]
+pub async fn get_image_variant(
+    ipfs: &Ipfs,
+    cid: Cid,
+    dimension: u32,
+    limit: Option<usize>,
+) -> Result<IdentityImage, Error> {
+    let Some(dimension) = IMAGE_VARIANT_DIMENSIONS
+        .into_iter()
+        .find(|variant| *variant >= dimension)
+    else {
+        return get_image(ipfs, cid, &[], true, limit).await;
+    };
+
+    let key = ipfs.image_variant(&cid, dimension);
+
+    if let Some(variant_cid) = ipfs
+        .repo()
+        .data_store()
+        .get(key.as_bytes())
+        .await
+        .unwrap_or_default()
+        .and_then(|bytes| String::from_utf8_lossy(&bytes).parse::<Cid>().ok())
+    {
+        match get_image(ipfs, variant_cid, &[], true, limit).await {
+            Ok(image) => return Ok(image),
+            Err(e) => {
+                tracing::warn!(%cid, dimension, error = %e, "unable to load image variant. Generating a new one")
+            }
+        }
+    }
+
+    let image = get_image(ipfs, cid, &[], true, limit).await?;
+
+    // Note: Images that cannot be resized (eg unsupported format) are served as is
+    let (file_type, data) = match resize_image(image.data(), dimension) {
+        Ok(Some((format, data))) => match ExtensionType::try_from(format) {
+            Ok(extension) => (FileType::from(extension), data),
+            Err(_) => return Ok(image),
+        },
+        Ok(None) => return Ok(image),
+        Err(e) => {
+            tracing::warn!(%cid, error = %e, "unable to resize image");
+            return Ok(image);
+        }
+    };
+
+    let variant_cid = store_photo(
+        ipfs,
+        futures::stream::iter(Ok::<_, std::io::Error>(Ok(data.clone()))).boxed(),
+        file_type.clone(),
+        limit,
+    )
+    .await?;
+
+    ipfs.repo()
+        .data_store()
+        .put(key.as_bytes(), variant_cid.to_string().as_bytes())
+        .await
+        .map_err(anyhow::Error::from)?;
+
+    let mut variant = IdentityImage::default();
+    variant.set_data(data);
+    variant.set_image_type(file_type);
+
+    Ok(variant)
+}
+
+/// Generate every rendition of the image ahead of time
+pub async fn store_image_variants(ipfs: &Ipfs, cid: Cid, limit: Option<usize>) {
+    for dimension in IMAGE_VARIANT_DIMENSIONS {
+        if let Err(e) = get_image_variant(ipfs, cid, dimension, limit).await {
+            tracing::warn!(%cid, dimension, error = %e, "unable to generate image variant");
+            return;
+        }
+    }
+}
+
+/// Remove the renditions generated for the image
+pub async fn remove_image_variants(ipfs: &Ipfs, cid: Cid) {
+    for dimension in IMAGE_VARIANT_DIMENSIONS {
+        let key = ipfs.image_variant(&cid, dimension);
+
+        let Some(variant_cid) = ipfs
+            .repo()
+            .data_store()
+            .get(key.as_bytes())
+            .await
+            .unwrap_or_default()
+            .and_then(|bytes| String::from_utf8_lossy(&bytes).parse::<Cid>().ok())
+        else {
+            continue;
+        };
+
+        if ipfs.is_pinned(variant_cid).await.unwrap_or_default() {
+            let _ = ipfs.remove_pin(variant_cid).recursive().await;
+        }
+
+        let _ = ipfs.repo().data_store().remove(key.as_bytes()).await;
+    }
+}
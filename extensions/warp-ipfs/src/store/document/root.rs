@@ -1,4 +1,5 @@
-use chrono::Utc;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
 use futures::{
     stream::{BoxStream, FuturesUnordered},
     StreamExt,
@@ -6,24 +7,36 @@ use futures::{
 use indexmap::IndexMap;
 use ipld_core::cid::Cid;
 use rust_ipfs::{Ipfs, IpfsPath, Keypair};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::borrow::Borrow;
 use std::{collections::BTreeMap, future::IntoFuture, sync::Arc};
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
 use uuid::Uuid;
 
 use warp::{
-    constellation::directory::Directory, crypto::DID, error::Error,
-    multipass::identity::IdentityStatus,
+    constellation::directory::Directory,
+    crypto::DID,
+    error::Error,
+    multipass::{
+        identity::{
+            FriendsPrivacy, IdentityActivity, IdentityStatus, ProfileFields,
+            RelationshipHistoryEntry,
+        },
+        notification::NotificationPreferences,
+    },
 };
 
+use crate::config::DocumentCodec;
 use crate::store::{
-    community::CommunityDocument, conversation::ConversationDocument, ds_key::DataStoreKey,
-    ecdh_decrypt, ecdh_encrypt, identity::Request, keystore::Keystore, VecExt,
+    community::CommunityDocument, conversation::ConversationDocument, device::DeviceCertificate,
+    ds_key::DataStoreKey, ecdh_decrypt, ecdh_encrypt, identity::Request, keystore::Keystore,
+    VecExt, MAX_APP_DATA_NAMESPACES, MAX_APP_DATA_NAMESPACE_LENGTH, MAX_APP_DATA_SIZE,
     MAX_METADATA_ENTRIES, MAX_METADATA_KEY_LENGTH, MAX_METADATA_VALUE_LENGTH,
+    MAX_RELATIONSHIP_HISTORY_ENTRIES,
 };
 
 use super::{
-    files::DirectoryDocument, identity::IdentityDocument, ResolvedRootDocument, RootDocument,
+    codec, files::DirectoryDocument, identity::IdentityDocument, ResolvedRootDocument, RootDocument,
 };
 
 #[derive(Debug, Clone)]
@@ -31,10 +44,98 @@ pub struct RootDocumentMap {
     ipfs: Ipfs,
     keypair: Option<Keypair>,
     inner: Arc<RwLock<RootDocumentInner>>,
+    changes: broadcast::Sender<RootDocumentChanges>,
+}
+
+/// Changes made to the root document when merging the root document of another device of the identity
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RootDocumentChanges {
+    /// Conversations that were added or replaced with a newer document
+    pub conversations: Vec<Uuid>,
+    /// Whether the file index was replaced
+    pub file_index: bool,
+}
+
+impl RootDocumentChanges {
+    pub fn is_empty(&self) -> bool {
+        self.conversations.is_empty() && !self.file_index
+    }
+}
+
+/// Latest addition or removal of each item of the lists in the root document, so an item removed on one device is
+/// not added back by merging the document of another device that still has it
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct ListChanges {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    friends: Vec<ListChange<DID>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    blocks: Vec<ListChange<DID>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    block_by: Vec<ListChange<DID>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    request: Vec<ListChange<Request>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct ListChange<T> {
+    item: T,
+    date: DateTime<Utc>,
+    removed: bool,
+}
+
+impl ListChanges {
+    /// Keep the latest change of each item from both sets of changes. A removal wins over an addition made at the
+    /// same time
+    fn merge(&mut self, other: ListChanges) {
+        merge_changes(&mut self.friends, other.friends);
+        merge_changes(&mut self.blocks, other.blocks);
+        merge_changes(&mut self.block_by, other.block_by);
+        merge_changes(&mut self.request, other.request);
+    }
+}
+
+fn record_change<T: Eq>(changes: &mut Vec<ListChange<T>>, item: T, removed: bool) {
+    let change = ListChange {
+        item,
+        date: Utc::now(),
+        removed,
+    };
+
+    match changes
+        .iter_mut()
+        .find(|existing| existing.item == change.item)
+    {
+        Some(existing) => *existing = change,
+        None => changes.push(change),
+    }
+}
+
+fn merge_changes<T: Eq>(changes: &mut Vec<ListChange<T>>, other: Vec<ListChange<T>>) {
+    for change in other {
+        match changes
+            .iter_mut()
+            .find(|existing| existing.item == change.item)
+        {
+            Some(existing) => {
+                if change.date > existing.date
+                    || (change.date == existing.date && change.removed && !existing.removed)
+                {
+                    *existing = change;
+                }
+            }
+            None => changes.push(change),
+        }
+    }
+}
+
+fn is_removed<T: Eq>(changes: &[ListChange<T>], item: &T) -> bool {
+    changes
+        .iter()
+        .any(|change| change.removed && change.item == *item)
 }
 
 impl RootDocumentMap {
-    pub async fn new(ipfs: &Ipfs, keypair: Option<Keypair>) -> Self {
+    pub async fn new(ipfs: &Ipfs, keypair: Option<Keypair>, codec: DocumentCodec) -> Self {
         let key = ipfs.root();
 
         let cid = ipfs
@@ -50,6 +151,8 @@ impl RootDocumentMap {
             ipfs: ipfs.clone(),
             keypair: keypair.clone(),
             cid,
+            read_only: false,
+            codec,
         };
 
         inner.migrate().await;
@@ -58,14 +161,46 @@ impl RootDocumentMap {
             ipfs: ipfs.clone(),
             keypair,
             inner: Arc::new(RwLock::new(inner)),
+            changes: broadcast::channel(16).0,
         }
     }
 
+    /// Load the root document exported at `cid` as a read-only replica. The document is fetched from the network
+    /// along with the documents it references, and any change to the document is rejected since it would have to be
+    /// signed again
+    pub async fn replica(ipfs: &Ipfs, keypair: Option<Keypair>, cid: Cid) -> Result<Self, Error> {
+        let document = ipfs.get_dag(cid).deserialized::<RootDocument>().await?;
+
+        document.resolve2(ipfs).await?;
+        document.verify(ipfs).await?;
+
+        let inner = RootDocumentInner {
+            ipfs: ipfs.clone(),
+            keypair: keypair.clone(),
+            cid: Some(cid),
+            read_only: true,
+            codec: DocumentCodec::default(),
+        };
+
+        Ok(Self {
+            ipfs: ipfs.clone(),
+            keypair,
+            inner: Arc::new(RwLock::new(inner)),
+            changes: broadcast::channel(16).0,
+        })
+    }
+
     pub async fn get(&self) -> Result<RootDocument, Error> {
         let inner = &*self.inner.read().await;
         inner.get_root_document().await
     }
 
+    /// Encode the existing payloads of the root document with the configured codec
+    pub async fn migrate_payloads(&self) {
+        let inner = &mut *self.inner.write().await;
+        inner.migrate_payloads().await
+    }
+
     pub async fn set(&mut self, document: RootDocument) -> Result<(), Error> {
         let inner = &mut *self.inner.write().await;
         inner.set_root_document(document).await
@@ -81,6 +216,31 @@ impl RootDocumentMap {
         inner.set_identity_status(status).await
     }
 
+    pub async fn set_activity(&self, activity: Option<IdentityActivity>) -> Result<(), Error> {
+        let inner = &mut *self.inner.write().await;
+        inner.set_identity_activity(activity).await
+    }
+
+    pub async fn get_profile_fields(&self) -> Result<ProfileFields, Error> {
+        let inner = &*self.inner.read().await;
+        inner.get_profile_fields().await
+    }
+
+    pub async fn set_profile_fields(&self, fields: ProfileFields) -> Result<(), Error> {
+        let inner = &mut *self.inner.write().await;
+        inner.set_profile_fields(fields).await
+    }
+
+    pub async fn get_friends_privacy(&self) -> Result<FriendsPrivacy, Error> {
+        let inner = &*self.inner.read().await;
+        inner.get_friends_privacy().await
+    }
+
+    pub async fn set_friends_privacy(&self, privacy: FriendsPrivacy) -> Result<(), Error> {
+        let inner = &mut *self.inner.write().await;
+        inner.set_friends_privacy(privacy).await
+    }
+
     pub async fn add_friend(&self, did: &DID) -> Result<(), Error> {
         let inner = &mut *self.inner.write().await;
         inner.add_friend(did.clone()).await
@@ -161,6 +321,59 @@ impl RootDocumentMap {
         inner.set_root_cid(cid).await
     }
 
+    /// Merge the root document exported by another device of the identity into our own, notifying the subscribers
+    /// of [`RootDocumentMap::subscribe_changes`] of the changes
+    pub async fn merge(&self, cid: Cid) -> Result<RootDocumentChanges, Error> {
+        let remote = self
+            .ipfs
+            .get_dag(cid)
+            .deserialized::<RootDocument>()
+            .await?;
+
+        // Step down through each field to fetch them before the document is locked
+        remote.resolve2(&self.ipfs).await?;
+
+        // the conversations and the file index are not resolved along with the other fields
+        if let Some(cid) = remote.conversations {
+            let map: BTreeMap<String, Cid> = self
+                .ipfs
+                .get_dag(cid)
+                .deserialized()
+                .await
+                .unwrap_or_default();
+
+            let mut documents = map
+                .into_values()
+                .map(|cid| self.ipfs.get_dag(cid).into_future())
+                .collect::<FuturesUnordered<_>>();
+
+            while documents.next().await.is_some() {}
+        }
+
+        if let Some(cid) = remote.file_index {
+            if let Ok(document) = self
+                .ipfs
+                .get_dag(cid)
+                .deserialized::<DirectoryDocument>()
+                .await
+            {
+                let _ = document.resolve(&self.ipfs, false).await;
+            }
+        }
+
+        let inner = &mut *self.inner.write().await;
+        let changes = inner.merge(remote).await?;
+        if !changes.is_empty() {
+            let _ = self.changes.send(changes.clone());
+        }
+        Ok(changes)
+    }
+
+    /// Subscribe to the changes made by merging the root document of another device
+    pub fn subscribe_changes(&self) -> broadcast::Receiver<RootDocumentChanges> {
+        self.changes.subscribe()
+    }
+
     pub async fn export(&self) -> Result<ResolvedRootDocument, Error> {
         let inner = &*self.inner.read().await;
         inner.export().await
@@ -176,6 +389,18 @@ impl RootDocumentMap {
         inner.get_keystore_map().await
     }
 
+    /// Index of conversation documents, keyed by the id of the conversation
+    pub async fn get_conversation_map(&self) -> Result<BTreeMap<String, Cid>, Error> {
+        let inner = &*self.inner.read().await;
+        inner.get_conversation_map().await
+    }
+
+    /// Replace the index of conversation documents
+    pub async fn set_conversation_map(&self, map: BTreeMap<String, Cid>) -> Result<(), Error> {
+        let inner = &mut *self.inner.write().await;
+        inner.set_conversation_map(map).await
+    }
+
     pub async fn list_conversation_document(&self) -> BoxStream<'static, ConversationDocument> {
         let inner = &*self.inner.read().await;
         inner.list_conversation_stream().await
@@ -198,6 +423,12 @@ impl RootDocumentMap {
         inner.set_conversation_document(document).await
     }
 
+    /// Check to determine if a conversation document exist, including documents of deleted conversations
+    pub async fn has_conversation_document(&self, id: Uuid) -> bool {
+        let inner = &*self.inner.read().await;
+        inner.has_conversation_document(id).await
+    }
+
     pub async fn get_community_document(&self, id: Uuid) -> Result<CommunityDocument, Error> {
         let inner = &*self.inner.read().await;
         inner.get_community_document(id).await
@@ -221,6 +452,13 @@ impl RootDocumentMap {
         inner.set_keystore(document).await
     }
 
+    /// Remove the keystores of conversations and communities that are no longer in the root document.
+    /// Returns the amount of keystores removed
+    pub async fn compact_keystore_map(&self) -> Result<usize, Error> {
+        let inner = &mut *self.inner.write().await;
+        inner.compact_keystore_map().await
+    }
+
     pub async fn get_directory_index(&self) -> Result<Directory, Error> {
         let inner = &*self.inner.read().await;
         inner.get_root_index().await
@@ -245,6 +483,67 @@ impl RootDocumentMap {
         inner.remove_metadata_key(key).await
     }
 
+    pub async fn get_app_data(&self, namespace: &str) -> Result<Bytes, Error> {
+        let inner = &*self.inner.read().await;
+        inner.get_app_data(namespace).await
+    }
+
+    pub async fn set_app_data(
+        &self,
+        namespace: impl Into<String>,
+        data: impl Into<Bytes>,
+    ) -> Result<(), Error> {
+        let inner = &mut *self.inner.write().await;
+        inner.set_app_data(namespace, data).await
+    }
+
+    pub async fn get_notification_preferences(&self) -> Result<NotificationPreferences, Error> {
+        let inner = &*self.inner.read().await;
+        inner.get_notification_preferences().await
+    }
+
+    pub async fn set_notification_preferences(
+        &self,
+        preferences: NotificationPreferences,
+    ) -> Result<(), Error> {
+        let inner = &mut *self.inner.write().await;
+        inner.set_notification_preferences(preferences).await
+    }
+
+    /// Certificates of the devices linked to the identity
+    pub async fn get_devices(&self) -> Result<Vec<DeviceCertificate>, Error> {
+        let inner = &*self.inner.read().await;
+        inner.get_devices().await
+    }
+
+    pub async fn set_devices(&self, devices: Vec<DeviceCertificate>) -> Result<(), Error> {
+        let inner = &mut *self.inner.write().await;
+        inner.set_devices(devices).await
+    }
+
+    /// Date the root document was last modified
+    pub async fn modified(&self) -> Result<DateTime<Utc>, Error> {
+        let inner = &*self.inner.read().await;
+        Ok(inner.get_root_document().await?.modified)
+    }
+
+    pub async fn get_relationship_history(
+        &self,
+        did: &DID,
+    ) -> Result<Vec<RelationshipHistoryEntry>, Error> {
+        let inner = &*self.inner.read().await;
+        inner.get_relationship_history(did).await
+    }
+
+    pub async fn add_relationship_history(
+        &self,
+        did: &DID,
+        entry: RelationshipHistoryEntry,
+    ) -> Result<(), Error> {
+        let inner = &mut *self.inner.write().await;
+        inner.add_relationship_history(did, entry).await
+    }
+
     pub fn keypair(&self) -> &Keypair {
         self.keypair.as_ref().unwrap_or(self.ipfs.keypair())
     }
@@ -255,6 +554,8 @@ struct RootDocumentInner {
     keypair: Option<Keypair>,
     ipfs: Ipfs,
     cid: Option<Cid>,
+    read_only: bool,
+    codec: DocumentCodec,
 }
 
 impl RootDocumentInner {
@@ -295,10 +596,12 @@ impl RootDocumentInner {
                 OldRequest::In(did) => Request::In {
                     did: did.clone(),
                     date: Utc::now(),
+                    message: None,
                 },
                 OldRequest::Out(did) => Request::Out {
                     did: did.clone(),
                     date: Utc::now(),
+                    message: None,
                 },
             })
             .collect::<Vec<_>>();
@@ -313,6 +616,85 @@ impl RootDocumentInner {
         let _ = self.set_root_document(root).await;
     }
 
+    /// Encode the encrypted payloads of the root document with the configured codec
+    async fn migrate_payloads(&mut self) {
+        if self.read_only {
+            return;
+        }
+
+        let mut root = match self.get_root_document().await {
+            Ok(r) => r,
+            Err(_) => return,
+        };
+
+        let mut changed = false;
+
+        changed |= self.transcode::<Vec<DID>>(&mut root.friends).await;
+        changed |= self.transcode::<Vec<DID>>(&mut root.blocks).await;
+        changed |= self.transcode::<Vec<DID>>(&mut root.block_by).await;
+        changed |= self.transcode::<Vec<Request>>(&mut root.request).await;
+        changed |= self
+            .transcode::<BTreeMap<String, Bytes>>(&mut root.app_data)
+            .await;
+        changed |= self
+            .transcode::<BTreeMap<String, Vec<RelationshipHistoryEntry>>>(
+                &mut root.relationship_history,
+            )
+            .await;
+        changed |= self
+            .transcode::<NotificationPreferences>(&mut root.notification_preferences)
+            .await;
+        changed |= self
+            .transcode::<ProfileFields>(&mut root.profile_fields)
+            .await;
+        changed |= self
+            .transcode::<FriendsPrivacy>(&mut root.friends_privacy)
+            .await;
+        changed |= self.transcode::<ListChanges>(&mut root.list_changes).await;
+
+        if !changed {
+            return;
+        }
+
+        tracing::info!(codec = ?self.codec, "Migrated root document payloads");
+        if let Err(e) = self.set_root_document(root).await {
+            tracing::warn!(error = %e, "Unable to store migrated root document");
+        }
+    }
+
+    /// Encode the payload at `cid` again if it is not encoded with the configured codec, returning true if `cid`
+    /// was replaced. Payloads that cannot be decoded are left as is
+    async fn transcode<T: Serialize + DeserializeOwned>(&self, cid: &mut Option<Cid>) -> bool {
+        let Some(current) = *cid else {
+            return false;
+        };
+
+        let result = async {
+            let bytes: Vec<u8> = self.ipfs.get_dag(current).local().deserialized().await?;
+            let bytes = ecdh_decrypt(self.keypair(), None, bytes)?;
+
+            if DocumentCodec::detect(&bytes) == Some(self.codec) {
+                return Ok::<_, Error>(None);
+            }
+
+            let value: T = codec::decode(&bytes)?;
+            let bytes = ecdh_encrypt(self.keypair(), None, self.codec.encode(&value)?)?;
+            Ok(Some(self.ipfs.put_dag(bytes).await?))
+        };
+
+        match result.await {
+            Ok(Some(new_cid)) => {
+                *cid = Some(new_cid);
+                true
+            }
+            Ok(None) => false,
+            Err(e) => {
+                tracing::warn!(%current, error = %e, "Unable to migrate root document payload");
+                false
+            }
+        }
+    }
+
     async fn get_root_document(&self) -> Result<RootDocument, Error> {
         let document: RootDocument = match self.cid {
             Some(cid) => self.ipfs.get_dag(cid).local().deserialized().await?,
@@ -346,6 +728,10 @@ impl RootDocumentInner {
         document: RootDocument,
         local: bool,
     ) -> Result<(), Error> {
+        if self.read_only {
+            return Err(Error::ReadOnlyReplica);
+        }
+
         let document = document.sign(self.keypair())?;
 
         //Precautionary check
@@ -477,6 +863,196 @@ impl RootDocumentInner {
         self.set_root_document(root).await
     }
 
+    async fn app_data_map(&self, document: &RootDocument) -> BTreeMap<String, Bytes> {
+        let Some(cid) = document.app_data else {
+            return BTreeMap::new();
+        };
+
+        self.ipfs
+            .get_dag(cid)
+            .local()
+            .deserialized::<Vec<u8>>()
+            .await
+            .and_then(|bytes| {
+                let bytes = ecdh_decrypt(self.keypair(), None, bytes)?;
+                codec::decode(&bytes).map_err(anyhow::Error::from)
+            })
+            .unwrap_or_default()
+    }
+
+    async fn get_app_data(&self, namespace: &str) -> Result<Bytes, Error> {
+        let document = self.get_root_document().await?;
+        let map = self.app_data_map(&document).await;
+        map.get(namespace).cloned().ok_or(Error::ObjectNotFound)
+    }
+
+    async fn set_app_data(
+        &mut self,
+        namespace: impl Into<String>,
+        data: impl Into<Bytes>,
+    ) -> Result<(), Error> {
+        let namespace = namespace.into();
+        let data = data.into();
+
+        if namespace.is_empty() || namespace.len() > MAX_APP_DATA_NAMESPACE_LENGTH {
+            return Err(Error::InvalidLength {
+                current: namespace.len(),
+                context: "namespace".into(),
+                minimum: Some(1),
+                maximum: Some(MAX_APP_DATA_NAMESPACE_LENGTH),
+            });
+        }
+
+        if data.len() > MAX_APP_DATA_SIZE {
+            return Err(Error::InvalidLength {
+                current: data.len(),
+                context: namespace,
+                minimum: None,
+                maximum: Some(MAX_APP_DATA_SIZE),
+            });
+        }
+
+        let mut document = self.get_root_document().await?;
+        let mut map = self.app_data_map(&document).await;
+
+        match data.is_empty() {
+            true => {
+                if map.remove(&namespace).is_none() {
+                    return Err(Error::ObjectNotFound);
+                }
+            }
+            false => {
+                if !map.contains_key(&namespace) && map.len() >= MAX_APP_DATA_NAMESPACES {
+                    return Err(Error::InvalidLength {
+                        current: map.len() + 1,
+                        context: "app_data".into(),
+                        minimum: None,
+                        maximum: Some(MAX_APP_DATA_NAMESPACES),
+                    });
+                }
+                map.insert(namespace, data);
+            }
+        }
+
+        document.app_data = match !map.is_empty() {
+            true => {
+                let bytes = ecdh_encrypt(self.keypair(), None, self.codec.encode(&map)?)?;
+                Some(self.ipfs.put_dag(bytes).await?)
+            }
+            false => None,
+        };
+
+        self.set_root_document(document).await
+    }
+
+    async fn get_notification_preferences(&self) -> Result<NotificationPreferences, Error> {
+        let document = self.get_root_document().await?;
+
+        let Some(cid) = document.notification_preferences else {
+            return Ok(NotificationPreferences::default());
+        };
+
+        let bytes: Vec<u8> = self.ipfs.get_dag(cid).local().deserialized().await?;
+        let bytes = ecdh_decrypt(self.keypair(), None, bytes)?;
+        codec::decode(&bytes)
+    }
+
+    async fn set_notification_preferences(
+        &mut self,
+        preferences: NotificationPreferences,
+    ) -> Result<(), Error> {
+        let mut document = self.get_root_document().await?;
+
+        document.notification_preferences = match preferences == NotificationPreferences::default()
+        {
+            true => None,
+            false => {
+                let bytes = ecdh_encrypt(self.keypair(), None, self.codec.encode(&preferences)?)?;
+                Some(self.ipfs.put_dag(bytes).await?)
+            }
+        };
+
+        self.set_root_document(document).await
+    }
+
+    async fn get_devices(&self) -> Result<Vec<DeviceCertificate>, Error> {
+        let document = self.get_root_document().await?;
+
+        let Some(cid) = document.devices else {
+            return Ok(vec![]);
+        };
+
+        self.ipfs
+            .get_dag(cid)
+            .local()
+            .deserialized()
+            .await
+            .map_err(Error::from)
+    }
+
+    async fn set_devices(&mut self, devices: Vec<DeviceCertificate>) -> Result<(), Error> {
+        let mut document = self.get_root_document().await?;
+
+        document.devices = match devices.is_empty() {
+            true => None,
+            false => Some(self.ipfs.put_dag(devices).await?),
+        };
+
+        self.set_root_document(document).await
+    }
+
+    async fn relationship_history_map(
+        &self,
+        document: &RootDocument,
+    ) -> BTreeMap<String, Vec<RelationshipHistoryEntry>> {
+        let Some(cid) = document.relationship_history else {
+            return BTreeMap::new();
+        };
+
+        self.ipfs
+            .get_dag(cid)
+            .local()
+            .deserialized::<Vec<u8>>()
+            .await
+            .and_then(|bytes| {
+                let bytes = ecdh_decrypt(self.keypair(), None, bytes)?;
+                codec::decode(&bytes).map_err(anyhow::Error::from)
+            })
+            .unwrap_or_default()
+    }
+
+    async fn get_relationship_history(
+        &self,
+        did: &DID,
+    ) -> Result<Vec<RelationshipHistoryEntry>, Error> {
+        let document = self.get_root_document().await?;
+        let mut map = self.relationship_history_map(&document).await;
+        Ok(map.remove(&did.to_string()).unwrap_or_default())
+    }
+
+    async fn add_relationship_history(
+        &mut self,
+        did: &DID,
+        entry: RelationshipHistoryEntry,
+    ) -> Result<(), Error> {
+        let mut document = self.get_root_document().await?;
+        let mut map = self.relationship_history_map(&document).await;
+
+        let history = map.entry(did.to_string()).or_default();
+        history.push(entry);
+
+        // Only keep the most recent entries for each identity
+        if history.len() > MAX_RELATIONSHIP_HISTORY_ENTRIES {
+            let excess = history.len() - MAX_RELATIONSHIP_HISTORY_ENTRIES;
+            history.drain(..excess);
+        }
+
+        let bytes = ecdh_encrypt(self.keypair(), None, self.codec.encode(&map)?)?;
+        document.relationship_history = Some(self.ipfs.put_dag(bytes).await?);
+
+        self.set_root_document(document).await
+    }
+
     async fn set_identity_status(&mut self, status: IdentityStatus) -> Result<(), Error> {
         let mut root = self.get_root_document().await?;
         let mut identity = self.identity().await?;
@@ -487,6 +1063,79 @@ impl RootDocumentInner {
         self.set_root_document(root).await
     }
 
+    async fn set_identity_activity(
+        &mut self,
+        activity: Option<IdentityActivity>,
+    ) -> Result<(), Error> {
+        let mut root = self.get_root_document().await?;
+        let mut identity = self.identity().await?;
+        identity.activity = activity;
+        let identity = identity.sign(self.keypair())?;
+        root.identity = self.ipfs.put_dag(identity).await?;
+
+        self.set_root_document(root).await
+    }
+
+    async fn get_profile_fields(&self) -> Result<ProfileFields, Error> {
+        let document = self.get_root_document().await?;
+
+        let Some(cid) = document.profile_fields else {
+            return Ok(ProfileFields::default());
+        };
+
+        let bytes: Vec<u8> = self.ipfs.get_dag(cid).local().deserialized().await?;
+        let bytes = ecdh_decrypt(self.keypair(), None, bytes)?;
+        codec::decode(&bytes)
+    }
+
+    async fn set_profile_fields(&mut self, fields: ProfileFields) -> Result<(), Error> {
+        let mut root = self.get_root_document().await?;
+
+        // The identity document only holds the public fields since it is exported and announced
+        // as is. The complete set of fields is kept encrypted in the root document
+        let public = fields.visible_to(false);
+        let mut identity = self.identity().await?;
+        identity.profile = (!public.is_empty()).then_some(public);
+        let identity = identity.sign(self.keypair())?;
+        root.identity = self.ipfs.put_dag(identity).await?;
+
+        root.profile_fields = match fields.is_empty() {
+            true => None,
+            false => {
+                let bytes = ecdh_encrypt(self.keypair(), None, self.codec.encode(&fields)?)?;
+                Some(self.ipfs.put_dag(bytes).await?)
+            }
+        };
+
+        self.set_root_document(root).await
+    }
+
+    async fn get_friends_privacy(&self) -> Result<FriendsPrivacy, Error> {
+        let document = self.get_root_document().await?;
+
+        let Some(cid) = document.friends_privacy else {
+            return Ok(FriendsPrivacy::default());
+        };
+
+        let bytes: Vec<u8> = self.ipfs.get_dag(cid).local().deserialized().await?;
+        let bytes = ecdh_decrypt(self.keypair(), None, bytes)?;
+        codec::decode(&bytes)
+    }
+
+    async fn set_friends_privacy(&mut self, privacy: FriendsPrivacy) -> Result<(), Error> {
+        let mut document = self.get_root_document().await?;
+
+        document.friends_privacy = match privacy == FriendsPrivacy::default() {
+            true => None,
+            false => {
+                let bytes = ecdh_encrypt(self.keypair(), None, self.codec.encode(&privacy)?)?;
+                Some(self.ipfs.put_dag(bytes).await?)
+            }
+        };
+
+        self.set_root_document(document).await
+    }
+
     async fn request_list(&self) -> Result<Vec<Request>, Error> {
         let cid = match self.cid {
             Some(cid) => cid,
@@ -501,7 +1150,7 @@ impl RootDocumentInner {
             .await
             .and_then(|bytes| {
                 let bytes = ecdh_decrypt(self.keypair(), None, bytes)?;
-                serde_json::from_slice(&bytes).map_err(anyhow::Error::from)
+                codec::decode(&bytes).map_err(anyhow::Error::from)
             })
             .unwrap_or_default();
 
@@ -519,19 +1168,24 @@ impl RootDocumentInner {
                 .await
                 .and_then(|bytes| {
                     let bytes = ecdh_decrypt(self.keypair(), None, bytes)?;
-                    serde_json::from_slice(&bytes).map_err(anyhow::Error::from)
+                    codec::decode(&bytes).map_err(anyhow::Error::from)
                 })
                 .unwrap_or_default(),
             None => vec![],
         };
 
-        if !list.insert_item(request) {
+        if !list.insert_item(request.clone()) {
             return Err(Error::FriendRequestExist);
         }
 
+        self.record_list_change(&mut document, |changes| {
+            record_change(&mut changes.request, request, false)
+        })
+        .await?;
+
         document.request = match !list.is_empty() {
             true => {
-                let bytes = ecdh_encrypt(self.keypair(), None, serde_json::to_vec(&list)?)?;
+                let bytes = ecdh_encrypt(self.keypair(), None, self.codec.encode(&list)?)?;
                 Some(self.ipfs.put_dag(bytes).await?)
             }
             false => None,
@@ -553,7 +1207,7 @@ impl RootDocumentInner {
                 .await
                 .and_then(|bytes| {
                     let bytes = ecdh_decrypt(self.keypair(), None, bytes)?;
-                    serde_json::from_slice(&bytes).map_err(anyhow::Error::from)
+                    codec::decode(&bytes).map_err(anyhow::Error::from)
                 })
                 .unwrap_or_default(),
             None => vec![],
@@ -563,9 +1217,14 @@ impl RootDocumentInner {
             return Err(Error::FriendRequestExist);
         }
 
+        self.record_list_change(&mut document, |changes| {
+            record_change(&mut changes.request, request, true)
+        })
+        .await?;
+
         document.request = match !list.is_empty() {
             true => {
-                let bytes = ecdh_encrypt(self.keypair(), None, serde_json::to_vec(&list)?)?;
+                let bytes = ecdh_encrypt(self.keypair(), None, self.codec.encode(&list)?)?;
                 Some(self.ipfs.put_dag(bytes).await?)
             }
             false => None,
@@ -589,7 +1248,7 @@ impl RootDocumentInner {
             .await
             .and_then(|bytes| {
                 let bytes = ecdh_decrypt(self.keypair(), None, bytes)?;
-                serde_json::from_slice(&bytes).map_err(anyhow::Error::from)
+                codec::decode(&bytes).map_err(anyhow::Error::from)
             })
             .unwrap_or_default();
         Ok(list)
@@ -607,19 +1266,24 @@ impl RootDocumentInner {
                 .await
                 .and_then(|bytes| {
                     let bytes = ecdh_decrypt(self.keypair(), None, bytes)?;
-                    serde_json::from_slice(&bytes).map_err(anyhow::Error::from)
+                    codec::decode(&bytes).map_err(anyhow::Error::from)
                 })
                 .unwrap_or_default(),
             None => vec![],
         };
 
-        if !list.insert_item(did) {
+        if !list.insert_item(did.clone()) {
             return Err::<_, Error>(Error::FriendExist);
         }
 
+        self.record_list_change(&mut document, |changes| {
+            record_change(&mut changes.friends, did, false)
+        })
+        .await?;
+
         document.friends = match !list.is_empty() {
             true => {
-                let bytes = ecdh_encrypt(self.keypair(), None, serde_json::to_vec(&list)?)?;
+                let bytes = ecdh_encrypt(self.keypair(), None, self.codec.encode(&list)?)?;
                 Some(self.ipfs.put_dag(bytes).await?)
             }
             false => None,
@@ -672,7 +1336,7 @@ impl RootDocumentInner {
                 .await
                 .and_then(|bytes| {
                     let bytes = ecdh_decrypt(self.keypair(), None, bytes)?;
-                    serde_json::from_slice(&bytes).map_err(anyhow::Error::from)
+                    codec::decode(&bytes).map_err(anyhow::Error::from)
                 })
                 .unwrap_or_default(),
             None => vec![],
@@ -682,9 +1346,14 @@ impl RootDocumentInner {
             return Err::<_, Error>(Error::FriendDoesntExist);
         }
 
+        self.record_list_change(&mut document, |changes| {
+            record_change(&mut changes.friends, did, true)
+        })
+        .await?;
+
         document.friends = match !list.is_empty() {
             true => {
-                let bytes = ecdh_encrypt(self.keypair(), None, serde_json::to_vec(&list)?)?;
+                let bytes = ecdh_encrypt(self.keypair(), None, self.codec.encode(&list)?)?;
                 Some(self.ipfs.put_dag(bytes).await?)
             }
             false => None,
@@ -709,7 +1378,7 @@ impl RootDocumentInner {
             .await
             .and_then(|bytes| {
                 let bytes = ecdh_decrypt(self.keypair(), None, bytes)?;
-                serde_json::from_slice(&bytes).map_err(anyhow::Error::from)
+                codec::decode(&bytes).map_err(anyhow::Error::from)
             })
             .unwrap_or_default();
         Ok(list)
@@ -739,19 +1408,24 @@ impl RootDocumentInner {
                 .await
                 .and_then(|bytes| {
                     let bytes = ecdh_decrypt(self.keypair(), None, bytes)?;
-                    serde_json::from_slice(&bytes).map_err(anyhow::Error::from)
+                    codec::decode(&bytes).map_err(anyhow::Error::from)
                 })
                 .unwrap_or_default(),
             None => vec![],
         };
 
-        if !list.insert_item(did) {
+        if !list.insert_item(did.clone()) {
             return Err::<_, Error>(Error::PublicKeyIsBlocked);
         }
 
+        self.record_list_change(&mut document, |changes| {
+            record_change(&mut changes.blocks, did, false)
+        })
+        .await?;
+
         document.blocks = match !list.is_empty() {
             true => {
-                let bytes = ecdh_encrypt(self.keypair(), None, serde_json::to_vec(&list)?)?;
+                let bytes = ecdh_encrypt(self.keypair(), None, self.codec.encode(&list)?)?;
                 Some(self.ipfs.put_dag(bytes).await?)
             }
             false => None,
@@ -774,7 +1448,7 @@ impl RootDocumentInner {
                 .await
                 .and_then(|bytes| {
                     let bytes = ecdh_decrypt(self.keypair(), None, bytes)?;
-                    serde_json::from_slice(&bytes).map_err(anyhow::Error::from)
+                    codec::decode(&bytes).map_err(anyhow::Error::from)
                 })
                 .unwrap_or_default(),
             None => vec![],
@@ -784,9 +1458,14 @@ impl RootDocumentInner {
             return Err::<_, Error>(Error::PublicKeyIsntBlocked);
         }
 
+        self.record_list_change(&mut document, |changes| {
+            record_change(&mut changes.blocks, did, true)
+        })
+        .await?;
+
         document.blocks = match !list.is_empty() {
             true => {
-                let bytes = ecdh_encrypt(self.keypair(), None, serde_json::to_vec(&list)?)?;
+                let bytes = ecdh_encrypt(self.keypair(), None, self.codec.encode(&list)?)?;
                 Some(self.ipfs.put_dag(bytes).await?)
             }
             false => None,
@@ -811,7 +1490,7 @@ impl RootDocumentInner {
             .await
             .and_then(|bytes| {
                 let bytes = ecdh_decrypt(self.keypair(), None, bytes)?;
-                serde_json::from_slice(&bytes).map_err(anyhow::Error::from)
+                codec::decode(&bytes).map_err(anyhow::Error::from)
             })
             .unwrap_or_default();
         Ok(list)
@@ -829,19 +1508,24 @@ impl RootDocumentInner {
                 .await
                 .and_then(|bytes| {
                     let bytes = ecdh_decrypt(self.keypair(), None, bytes)?;
-                    serde_json::from_slice(&bytes).map_err(anyhow::Error::from)
+                    codec::decode(&bytes).map_err(anyhow::Error::from)
                 })
                 .unwrap_or_default(),
             None => vec![],
         };
 
-        if !list.insert_item(did) {
+        if !list.insert_item(did.clone()) {
             return Err::<_, Error>(Error::PublicKeyIsntBlocked);
         }
 
+        self.record_list_change(&mut document, |changes| {
+            record_change(&mut changes.block_by, did, false)
+        })
+        .await?;
+
         document.block_by = match !list.is_empty() {
             true => {
-                let bytes = ecdh_encrypt(self.keypair(), None, serde_json::to_vec(&list)?)?;
+                let bytes = ecdh_encrypt(self.keypair(), None, self.codec.encode(&list)?)?;
                 Some(self.ipfs.put_dag(bytes).await?)
             }
             false => None,
@@ -864,7 +1548,7 @@ impl RootDocumentInner {
                 .await
                 .and_then(|bytes| {
                     let bytes = ecdh_decrypt(self.keypair(), None, bytes)?;
-                    serde_json::from_slice(&bytes).map_err(anyhow::Error::from)
+                    codec::decode(&bytes).map_err(anyhow::Error::from)
                 })
                 .unwrap_or_default(),
             None => vec![],
@@ -874,9 +1558,14 @@ impl RootDocumentInner {
             return Err::<_, Error>(Error::PublicKeyIsntBlocked);
         }
 
+        self.record_list_change(&mut document, |changes| {
+            record_change(&mut changes.block_by, did, true)
+        })
+        .await?;
+
         document.block_by = match !list.is_empty() {
             true => {
-                let bytes = ecdh_encrypt(self.keypair(), None, serde_json::to_vec(&list)?)?;
+                let bytes = ecdh_encrypt(self.keypair(), None, self.codec.encode(&list)?)?;
                 Some(self.ipfs.put_dag(bytes).await?)
             }
             false => None,
@@ -925,6 +1614,63 @@ impl RootDocumentInner {
             .map_err(Error::from)
     }
 
+    async fn get_conversation_map(&self) -> Result<BTreeMap<String, Cid>, Error> {
+        let document = self.get_root_document().await?;
+
+        let cid = match document.conversations {
+            Some(cid) => cid,
+            None => return Ok(BTreeMap::new()),
+        };
+
+        self.ipfs
+            .get_dag(cid)
+            .local()
+            .deserialized()
+            .await
+            .map_err(Error::from)
+    }
+
+    async fn get_community_map(&self) -> Result<BTreeMap<String, Cid>, Error> {
+        let document = self.get_root_document().await?;
+
+        let cid = match document.communities {
+            Some(cid) => cid,
+            None => return Ok(BTreeMap::new()),
+        };
+
+        self.ipfs
+            .get_dag(cid)
+            .local()
+            .deserialized()
+            .await
+            .map_err(Error::from)
+    }
+
+    async fn compact_keystore_map(&mut self) -> Result<usize, Error> {
+        let mut map = self.get_keystore_map().await?;
+        let conversations = self.get_conversation_map().await?;
+        let communities = self.get_community_map().await?;
+
+        let length = map.len();
+        map.retain(|id, _| conversations.contains_key(id) || communities.contains_key(id));
+
+        let removed = length - map.len();
+        if removed > 0 {
+            self.set_keystore(map).await?;
+        }
+
+        Ok(removed)
+    }
+
+    async fn set_conversation_map(&mut self, map: BTreeMap<String, Cid>) -> Result<(), Error> {
+        let mut document = self.get_root_document().await?;
+
+        let cid = self.ipfs.put_dag(map).await?;
+        document.conversations.replace(cid);
+
+        self.set_root_document(document).await
+    }
+
     async fn get_conversation_document(&self, id: Uuid) -> Result<ConversationDocument, Error> {
         let document = self.get_root_document().await?;
 
@@ -951,6 +1697,25 @@ impl RootDocumentInner {
         Ok(document)
     }
 
+    async fn has_conversation_document(&self, id: Uuid) -> bool {
+        let Some(cid) = self
+            .get_root_document()
+            .await
+            .ok()
+            .and_then(|document| document.conversations)
+        else {
+            return false;
+        };
+
+        self.ipfs
+            .get_dag(cid)
+            .local()
+            .deserialized::<BTreeMap<String, Cid>>()
+            .await
+            .map(|list| list.contains_key(&id.to_string()))
+            .unwrap_or_default()
+    }
+
     async fn set_conversation_document<B: Borrow<ConversationDocument>>(
         &mut self,
         conversation_document: B,
@@ -1136,6 +1901,298 @@ impl RootDocumentInner {
         ecdh_encrypt(self.keypair(), None, bytes)
     }
 
+    // Decrypt a list stored in the root document, such as the friends list
+    async fn decrypt_list<T: DeserializeOwned>(&self, cid: Option<Cid>) -> Vec<T> {
+        let Some(cid) = cid else {
+            return vec![];
+        };
+
+        self.ipfs
+            .get_dag(cid)
+            .local()
+            .deserialized::<Vec<u8>>()
+            .await
+            .and_then(|bytes| {
+                let bytes = ecdh_decrypt(self.keypair(), None, bytes)?;
+                codec::decode(&bytes).map_err(anyhow::Error::from)
+            })
+            .unwrap_or_default()
+    }
+
+    async fn encrypt_list<T: Serialize>(&self, list: &[T]) -> Result<Option<Cid>, Error> {
+        if list.is_empty() {
+            return Ok(None);
+        }
+
+        let bytes = ecdh_encrypt(self.keypair(), None, self.codec.encode(&list)?)?;
+        Ok(Some(self.ipfs.put_dag(bytes).await?))
+    }
+
+    async fn merge_lists<T: Serialize + DeserializeOwned + Eq + Clone>(
+        &self,
+        local: Option<Cid>,
+        remote: Option<Cid>,
+        changes: &[ListChange<T>],
+    ) -> Result<Option<Cid>, Error> {
+        let local_list = self.decrypt_list::<T>(local).await;
+        let mut list = local_list.clone();
+
+        if local != remote {
+            for item in self.decrypt_list::<T>(remote).await {
+                list.insert_item(item);
+            }
+        }
+
+        list.retain(|item| !is_removed(changes, item));
+
+        match list == local_list {
+            true => Ok(local),
+            false => self.encrypt_list(&list).await,
+        }
+    }
+
+    async fn list_changes(&self, cid: Option<Cid>) -> ListChanges {
+        let Some(cid) = cid else {
+            return ListChanges::default();
+        };
+
+        self.ipfs
+            .get_dag(cid)
+            .local()
+            .deserialized::<Vec<u8>>()
+            .await
+            .and_then(|bytes| {
+                let bytes = ecdh_decrypt(self.keypair(), None, bytes)?;
+                codec::decode(&bytes).map_err(anyhow::Error::from)
+            })
+            .unwrap_or_default()
+    }
+
+    async fn set_list_changes(
+        &self,
+        document: &mut RootDocument,
+        changes: &ListChanges,
+    ) -> Result<(), Error> {
+        let bytes = ecdh_encrypt(self.keypair(), None, self.codec.encode(changes)?)?;
+        document.list_changes = Some(self.ipfs.put_dag(bytes).await?);
+        Ok(())
+    }
+
+    /// Record an addition or removal of an item of the lists in `document`
+    async fn record_list_change(
+        &self,
+        document: &mut RootDocument,
+        f: impl FnOnce(&mut ListChanges),
+    ) -> Result<(), Error> {
+        let mut changes = self.list_changes(document.list_changes).await;
+        f(&mut changes);
+        self.set_list_changes(document, &changes).await
+    }
+
+    async fn device_list(&self, cid: Option<Cid>) -> Vec<DeviceCertificate> {
+        let Some(cid) = cid else {
+            return vec![];
+        };
+
+        self.ipfs
+            .get_dag(cid)
+            .local()
+            .deserialized()
+            .await
+            .unwrap_or_default()
+    }
+
+    async fn directory_index(&self, cid: Option<Cid>) -> Option<Directory> {
+        let document = self
+            .ipfs
+            .get_dag(cid?)
+            .local()
+            .deserialized::<DirectoryDocument>()
+            .await
+            .ok()?;
+
+        document.resolve(&self.ipfs, true).await.ok()
+    }
+
+    async fn cid_map(&self, cid: Option<Cid>) -> BTreeMap<String, Cid> {
+        let Some(cid) = cid else {
+            return BTreeMap::new();
+        };
+
+        self.ipfs
+            .get_dag(cid)
+            .local()
+            .deserialized()
+            .await
+            .unwrap_or_default()
+    }
+
+    // Merge the root document of another device of the identity. Lists are combined, conversations keep their most
+    // recently modified document and the remaining fields are taken from the most recently modified root document.
+    //
+    // Note: Items removed on only one of the devices are kept, since removals are not recorded
+    async fn merge(&mut self, remote: RootDocument) -> Result<RootDocumentChanges, Error> {
+        remote.verify(&self.ipfs).await?;
+
+        let identity: IdentityDocument = self
+            .ipfs
+            .get_dag(remote.identity)
+            .local()
+            .deserialized()
+            .await?;
+
+        if identity.did.ne(&self.keypair().to_did()?) {
+            return Err(Error::PublicKeyInvalid);
+        }
+
+        let local = self.get_root_document().await?;
+        let mut changes = RootDocumentChanges::default();
+
+        let newer = remote.modified > local.modified;
+        let (latest, other) = match newer {
+            true => (&remote, &local),
+            false => (&local, &remote),
+        };
+
+        let mut merged = latest.clone();
+        merged.created = local.created.min(remote.created);
+
+        // items removed on either device are removed from the merged lists, unless added back afterward
+        let local_list_changes = self.list_changes(local.list_changes).await;
+        let mut list_changes = local_list_changes.clone();
+        list_changes.merge(self.list_changes(remote.list_changes).await);
+
+        merged.friends = self
+            .merge_lists(local.friends, remote.friends, &list_changes.friends)
+            .await?;
+        merged.blocks = self
+            .merge_lists(local.blocks, remote.blocks, &list_changes.blocks)
+            .await?;
+        merged.block_by = self
+            .merge_lists(local.block_by, remote.block_by, &list_changes.block_by)
+            .await?;
+        merged.request = self
+            .merge_lists(local.request, remote.request, &list_changes.request)
+            .await?;
+
+        merged.list_changes = local.list_changes;
+        if list_changes != local_list_changes {
+            self.set_list_changes(&mut merged, &list_changes).await?;
+        }
+
+        let mut conversations = self.cid_map(local.conversations).await;
+        for (id, remote_cid) in self.cid_map(remote.conversations).await {
+            let replace = match conversations.get(&id) {
+                Some(local_cid) if *local_cid == remote_cid => false,
+                Some(local_cid) => {
+                    let local_document = self
+                        .ipfs
+                        .get_dag(*local_cid)
+                        .local()
+                        .deserialized::<ConversationDocument>()
+                        .await;
+                    let remote_document = self
+                        .ipfs
+                        .get_dag(remote_cid)
+                        .local()
+                        .deserialized::<ConversationDocument>()
+                        .await;
+
+                    match (local_document, remote_document) {
+                        (Ok(local), Ok(remote)) => {
+                            remote.verify().is_ok() && remote.modified > local.modified
+                        }
+                        (Err(_), Ok(remote)) => remote.verify().is_ok(),
+                        _ => false,
+                    }
+                }
+                None => true,
+            };
+
+            if replace {
+                if let Ok(conversation_id) = id.parse() {
+                    changes.conversations.push(conversation_id);
+                }
+                conversations.insert(id, remote_cid);
+            }
+        }
+
+        if !changes.conversations.is_empty() {
+            merged.conversations = Some(self.ipfs.put_dag(conversations).await?);
+        } else {
+            merged.conversations = local.conversations;
+        }
+
+        for (field, latest, other) in [
+            (
+                &mut merged.communities,
+                latest.communities,
+                other.communities,
+            ),
+            (&mut merged.keystore, latest.keystore, other.keystore),
+        ] {
+            if latest == other {
+                continue;
+            }
+            let mut map = self.cid_map(other).await;
+            map.extend(self.cid_map(latest).await);
+            *field = Some(self.ipfs.put_dag(map).await?);
+        }
+
+        if local.devices != remote.devices {
+            let mut devices = self.device_list(local.devices).await;
+
+            for certificate in self.device_list(remote.devices).await {
+                if !identity.revoked_devices.contains(&certificate.device) {
+                    devices.insert_item(certificate);
+                }
+            }
+
+            merged.devices = match devices.is_empty() {
+                true => None,
+                false => Some(self.ipfs.put_dag(devices).await?),
+            };
+        }
+
+        merged.file_index = local.file_index;
+        if local.file_index != remote.file_index {
+            let local_index = self.directory_index(local.file_index).await;
+            let remote_index = self.directory_index(remote.file_index).await;
+
+            match (local_index, remote_index) {
+                (Some(local_index), Some(remote_index)) => {
+                    if merge_directory(&local_index, &remote_index) {
+                        let document = DirectoryDocument::new(&self.ipfs, &local_index).await?;
+                        merged.file_index = Some(self.ipfs.put_dag(document).await?);
+                        changes.file_index = true;
+                    }
+                }
+                (None, Some(_)) => {
+                    merged.file_index = remote.file_index;
+                    changes.file_index = true;
+                }
+                _ => {}
+            }
+        }
+
+        // The document is left as is if the other device has not made any change that we do not have, so both
+        // devices do not keep replacing each other's document
+        let unsigned = |document: &RootDocument| {
+            let mut document = document.clone();
+            document.modified = DateTime::<Utc>::default();
+            document.signature = None;
+            serde_json::to_vec(&document)
+        };
+
+        if unsigned(&merged)? == unsigned(&local)? {
+            return Ok(changes);
+        }
+
+        self._set_root_document(merged, false).await?;
+
+        Ok(changes)
+    }
+
     async fn set_root_cid(&mut self, cid: Cid) -> Result<(), Error> {
         let root_document = self
             .ipfs
@@ -1148,3 +2205,28 @@ impl RootDocumentInner {
         Ok(())
     }
 }
+
+// Add the items of `other` that are missing from `directory`, replacing the files that were modified more recently.
+// Returns true if `directory` was changed
+pub(crate) fn merge_directory(directory: &Directory, other: &Directory) -> bool {
+    let mut changed = false;
+
+    for item in other.get_items() {
+        let Ok(current) = directory.get_item(&item.name()) else {
+            changed |= directory.add_item(item).is_ok();
+            continue;
+        };
+
+        match (current.get_directory(), item.get_directory()) {
+            (Ok(current), Ok(other)) => changed |= merge_directory(&current, &other),
+            _ if item.modified() > current.modified() => {
+                if directory.remove_item(&item.name()).is_ok() {
+                    changed |= directory.add_item(item).is_ok();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    changed
+}
@@ -0,0 +1,172 @@
+//! Emoji packs available for custom emoji reactions.
+//!
+//! Custom emoji are referenced in reactions by the id of their pack and of the item within it (see
+//! [`CustomEmoji`]). Packs are kept in the datastore of the node, and a reaction with a custom emoji can only be sent
+//! if its pack is installed and contains the item. Reactions received from other participants are only checked to be
+//! well formed, since they may use packs that are not installed on this device.
+use std::{collections::BTreeMap, sync::Arc};
+
+use parking_lot::RwLock;
+use rust_ipfs::Ipfs;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use warp::{
+    error::Error,
+    raygun::{CustomEmoji, Reaction},
+};
+
+use super::ds_key::DataStoreKey;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EmojiPack {
+    pub id: Uuid,
+    pub name: String,
+    /// Shortcode of each item, keyed by the id of the item
+    pub items: BTreeMap<Uuid, String>,
+}
+
+impl EmojiPack {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            name: name.into(),
+            items: BTreeMap::new(),
+        }
+    }
+
+    /// Add an item to the pack, returning the emoji that references it
+    pub fn add_item(&mut self, shortcode: impl Into<String>) -> CustomEmoji {
+        let item_id = Uuid::new_v4();
+        self.items.insert(item_id, shortcode.into());
+        CustomEmoji::new(self.id, item_id)
+    }
+}
+
+#[derive(Clone)]
+pub struct EmojiPacks {
+    ipfs: Ipfs,
+    packs: Arc<RwLock<BTreeMap<Uuid, EmojiPack>>>,
+}
+
+impl EmojiPacks {
+    pub async fn new(ipfs: &Ipfs) -> Self {
+        let key = ipfs.emoji_packs();
+
+        let packs = ipfs
+            .repo()
+            .data_store()
+            .get(key.as_bytes())
+            .await
+            .unwrap_or_default()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+
+        Self {
+            ipfs: ipfs.clone(),
+            packs: Arc::new(RwLock::new(packs)),
+        }
+    }
+
+    pub fn list(&self) -> Vec<EmojiPack> {
+        self.packs.read().values().cloned().collect()
+    }
+
+    /// Install the pack, replacing any pack with the same id
+    pub async fn install(&self, pack: EmojiPack) -> Result<(), Error> {
+        if pack.name.trim().is_empty() || pack.items.is_empty() {
+            return Err(Error::OtherWithContext(
+                "Emoji pack requires a name and at least one item".into(),
+            ));
+        }
+
+        self.packs.write().insert(pack.id, pack);
+        self.save().await
+    }
+
+    pub async fn remove(&self, pack_id: Uuid) -> Result<(), Error> {
+        if self.packs.write().remove(&pack_id).is_none() {
+            return Err(Error::EmojiNotAvailable);
+        }
+        self.save().await
+    }
+
+    pub fn contains(&self, emoji: &CustomEmoji) -> bool {
+        self.packs
+            .read()
+            .get(&emoji.pack_id)
+            .is_some_and(|pack| pack.items.contains_key(&emoji.item_id))
+    }
+
+    /// Check that the reaction is well formed and, if it is a custom emoji, that it is in an installed pack
+    pub fn validate(&self, reaction: &str) -> Result<Reaction, Error> {
+        let reaction = reaction.parse::<Reaction>()?;
+        if let Some(emoji) = reaction.custom_emoji() {
+            if !self.contains(&emoji) {
+                return Err(Error::EmojiNotAvailable);
+            }
+        }
+        Ok(reaction)
+    }
+
+    async fn save(&self) -> Result<(), Error> {
+        let key = self.ipfs.emoji_packs();
+        let bytes = serde_json::to_vec(&*self.packs.read())?;
+
+        self.ipfs
+            .repo()
+            .data_store()
+            .put(key.as_bytes(), &bytes)
+            .await
+            .map_err(anyhow::Error::from)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rust_ipfs::UninitializedIpfsDefault;
+    use uuid::Uuid;
+    use warp::{
+        error::Error,
+        raygun::{CustomEmoji, Reaction},
+    };
+
+    use super::{EmojiPack, EmojiPacks};
+
+    #[tokio::test]
+    async fn custom_reactions_require_installed_pack() -> anyhow::Result<()> {
+        let ipfs = UninitializedIpfsDefault::new()
+            .start()
+            .await
+            .expect("constructed ipfs instance");
+
+        let packs = EmojiPacks::new(&ipfs).await;
+
+        let mut pack = EmojiPack::new("cats");
+        let emoji = pack.add_item("cat_wave");
+        let reaction = emoji.to_reaction();
+
+        assert!(matches!(
+            packs.validate(&reaction),
+            Err(Error::EmojiNotAvailable)
+        ));
+
+        packs.install(pack).await?;
+        assert_eq!(packs.validate(&reaction)?, Reaction::Custom(emoji));
+
+        let unknown = CustomEmoji::new(emoji.pack_id, Uuid::new_v4());
+        assert!(packs.validate(&unknown.to_reaction()).is_err());
+
+        assert_eq!(packs.validate("👍")?, Reaction::Emoji("👍".into()));
+        assert!(matches!(
+            packs.validate(":not-a-pack:"),
+            Err(Error::InvalidReaction)
+        ));
+
+        // packs persist in the datastore
+        let packs = EmojiPacks::new(&ipfs).await;
+        assert!(packs.contains(&emoji));
+        Ok(())
+    }
+}
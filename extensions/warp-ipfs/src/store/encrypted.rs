@@ -0,0 +1,140 @@
+//! Local storage for data that holds decrypted message content, such as drafts, search indexes and caches.
+//!
+//! Messages are only stored encrypted in the repo, so anything derived from their decrypted content has to be
+//! encrypted before it is written to the datastore as well. The key is generated once and kept in [`Tesseract`],
+//! so the data can only be read while tesseract is unlocked.
+use std::sync::Arc;
+
+use rust_ipfs::Ipfs;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use warp::crypto::cipher::Cipher;
+use warp::crypto::zeroize::Zeroizing;
+use warp::error::Error;
+use warp::tesseract::Tesseract;
+
+use crate::store::ds_key::DataStoreKey;
+
+/// Entry in tesseract holding the key used to encrypt local data
+const LOCAL_DATA_KEY: &str = "local_data_key";
+
+#[derive(Clone)]
+pub struct EncryptedStore {
+    ipfs: Ipfs,
+    namespace: String,
+    cipher: Arc<Cipher>,
+}
+
+impl std::fmt::Debug for EncryptedStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncryptedStore")
+            .field("namespace", &self.namespace)
+            .finish_non_exhaustive()
+    }
+}
+
+impl EncryptedStore {
+    /// Open the store for `namespace` (eg "drafts" or "search_index"). Each namespace has its own set of keys
+    /// while sharing the same encryption key
+    pub fn new(ipfs: &Ipfs, tesseract: &Tesseract, namespace: &str) -> Result<Self, Error> {
+        let key = local_data_key(tesseract)?;
+        Ok(Self {
+            ipfs: ipfs.clone(),
+            namespace: namespace.into(),
+            cipher: Arc::new(Cipher::from_bytes(&key)),
+        })
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    pub async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        let key = self.ipfs.encrypted(&self.namespace, key);
+        let Some(mut bytes) = self
+            .ipfs
+            .repo()
+            .data_store()
+            .get(key.as_bytes())
+            .await
+            .map_err(anyhow::Error::from)?
+        else {
+            return Ok(None);
+        };
+
+        self.cipher.decrypt_in_place(&mut bytes)?;
+        Ok(Some(bytes))
+    }
+
+    pub async fn put(&self, key: &str, value: &[u8]) -> Result<(), Error> {
+        let key = self.ipfs.encrypted(&self.namespace, key);
+        let bytes = self.cipher.encrypt(value, None)?;
+        self.ipfs
+            .repo()
+            .data_store()
+            .put(key.as_bytes(), &bytes)
+            .await
+            .map_err(anyhow::Error::from)?;
+        Ok(())
+    }
+
+    pub async fn remove(&self, key: &str) -> Result<(), Error> {
+        let key = self.ipfs.encrypted(&self.namespace, key);
+        self.ipfs
+            .repo()
+            .data_store()
+            .remove(key.as_bytes())
+            .await
+            .map_err(anyhow::Error::from)?;
+        Ok(())
+    }
+
+    pub async fn get_serialized<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, Error> {
+        let Some(bytes) = self.get(key).await? else {
+            return Ok(None);
+        };
+
+        let value = serde_json::from_slice(&bytes)?;
+        Ok(Some(value))
+    }
+
+    pub async fn put_serialized<T: Serialize>(&self, key: &str, value: &T) -> Result<(), Error> {
+        let bytes = Zeroizing::new(serde_json::to_vec(value)?);
+        self.put(key, &bytes).await
+    }
+}
+
+/// Returns the key used to encrypt local data, generating it if it does not exist yet
+fn local_data_key(tesseract: &Tesseract) -> Result<Zeroizing<Vec<u8>>, Error> {
+    if tesseract.exist(LOCAL_DATA_KEY) {
+        let encoded = Zeroizing::new(tesseract.retrieve(LOCAL_DATA_KEY)?);
+        let key = bs58::decode(encoded.as_str()).into_vec()?;
+        return Ok(Zeroizing::new(key));
+    }
+
+    let key = Zeroizing::new(warp::crypto::generate::<32>().to_vec());
+    let encoded = Zeroizing::new(bs58::encode(key.as_slice()).into_string());
+    tesseract.set(LOCAL_DATA_KEY, &encoded)?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod test {
+    use warp::tesseract::Tesseract;
+
+    use super::local_data_key;
+
+    #[test]
+    fn local_data_key_is_kept_in_tesseract() -> anyhow::Result<()> {
+        let tesseract = Tesseract::default();
+        tesseract.unlock(b"local data key passphrase")?;
+
+        let key = local_data_key(&tesseract)?;
+        assert_eq!(key.len(), 32);
+        assert_eq!(local_data_key(&tesseract)?, key);
+
+        tesseract.lock();
+        assert!(local_data_key(&tesseract).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+//! Best-effort erasure of deleted messages.
+//!
+//! Deleting a message only removes its reference from the conversation, so the blocks of the message and of its
+//! attachments would otherwise stay in the repo until they are garbage collected. Once a message is deleted, its blocks
+//! are removed along with the data store entries derived from them, and a tombstone is recorded so the message is not
+//! added back when a peer sends it again.
+//!
+//! Note: This only covers the local node. Peers that received the message keep their own copy, and blocks that are
+//! pinned elsewhere (eg an attachment that is also in the constellation of the user) are left untouched.
+//!
+//! Blocks are removed through the blockstore, which deletes the files of a persistent repo without overwriting them.
+//! They are not overwritten beforehand since the blockstore does not expose where a block is stored, and overwriting a
+//! file in place does not reliably destroy its previous content on journaling filesystems or flash storage. Full
+//! disk encryption remains the way to protect removed blocks from being recovered.
+use std::str::FromStr;
+
+use chrono::Utc;
+use ipld_core::cid::Cid;
+use rust_ipfs::Ipfs;
+use uuid::Uuid;
+
+use crate::store::conversation::message::MessageDocument;
+use crate::store::document::image_dag::remove_image_variants;
+use crate::store::ds_key::DataStoreKey;
+
+/// Remove the blocks of a deleted message and record its tombstone.
+/// `message` is the document of the message, if it was still available before the message was deleted
+pub async fn erase_message(
+    ipfs: &Ipfs,
+    message_id: Uuid,
+    cid: Cid,
+    message: Option<&MessageDocument>,
+) {
+    let mut blocks = vec![cid];
+
+    if let Some(message) = message {
+        for attachment in &message.attachments {
+            if let Some(thumbnail) = attachment.thumbnail {
+                remove_image_variants(ipfs, thumbnail).await;
+                blocks.push(thumbnail);
+            }
+
+            match Cid::from_str(&attachment.data) {
+                Ok(cid) => blocks.push(cid),
+                Err(e) => {
+                    tracing::warn!(%message_id, error = %e, "invalid attachment cid");
+                }
+            }
+        }
+    }
+
+    record_tombstone(ipfs, message_id).await;
+
+    for cid in blocks {
+        // blocks that are still pinned are referenced by something other than the message
+        if ipfs.is_pinned(cid).await.unwrap_or_default() {
+            tracing::debug!(%message_id, %cid, "block is pinned. Skipping erasure");
+            continue;
+        }
+
+        if let Err(e) = ipfs.remove_block(cid, true).await {
+            tracing::warn!(%message_id, %cid, error = %e, "unable to remove block");
+        }
+    }
+}
+
+/// Returns true if the message was erased
+pub async fn is_erased(ipfs: &Ipfs, message_id: Uuid) -> bool {
+    let key = ipfs.tombstone(message_id);
+    ipfs.repo()
+        .data_store()
+        .get(key.as_bytes())
+        .await
+        .unwrap_or_default()
+        .is_some()
+}
+
+async fn record_tombstone(ipfs: &Ipfs, message_id: Uuid) {
+    let key = ipfs.tombstone(message_id);
+    let date = Utc::now().timestamp().to_string();
+    if let Err(e) = ipfs
+        .repo()
+        .data_store()
+        .put(key.as_bytes(), date.as_bytes())
+        .await
+    {
+        tracing::error!(%message_id, error = %e, "unable to record tombstone");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rust_ipfs::UninitializedIpfsDefault;
+    use uuid::Uuid;
+
+    use super::{erase_message, is_erased};
+
+    #[tokio::test]
+    async fn erased_message_is_removed_and_tombstoned() -> anyhow::Result<()> {
+        let ipfs = UninitializedIpfsDefault::new()
+            .start()
+            .await
+            .expect("constructed ipfs instance");
+
+        let message_id = Uuid::new_v4();
+        let cid = ipfs.put_dag(vec!["secret message"]).await?;
+        assert!(!is_erased(&ipfs, message_id).await);
+
+        erase_message(&ipfs, message_id, cid, None).await;
+
+        assert!(is_erased(&ipfs, message_id).await);
+        assert!(!ipfs.repo().contains(&cid).await?);
+
+        // other messages are not affected by the tombstone
+        assert!(!is_erased(&ipfs, Uuid::new_v4()).await);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn pinned_blocks_are_kept() -> anyhow::Result<()> {
+        let ipfs = UninitializedIpfsDefault::new()
+            .start()
+            .await
+            .expect("constructed ipfs instance");
+
+        let message_id = Uuid::new_v4();
+        let cid = ipfs.put_dag(vec!["shared attachment"]).pin(true).await?;
+
+        erase_message(&ipfs, message_id, cid, None).await;
+
+        // the block is referenced by something other than the message, while the message is still tombstoned
+        assert!(ipfs.repo().contains(&cid).await?);
+        assert!(is_erased(&ipfs, message_id).await);
+        Ok(())
+    }
+}
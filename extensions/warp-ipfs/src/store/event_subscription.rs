@@ -5,18 +5,30 @@ use futures::{
         oneshot,
     },
     stream::BoxStream,
-    SinkExt, StreamExt,
+    task::AtomicWaker,
+    SinkExt, Stream, StreamExt,
 };
+use parking_lot::Mutex;
 use std::collections::VecDeque;
 use std::fmt::Debug;
-use std::task::{Poll, Waker};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
 use warp::error::Error;
+use warp::subscription::{Coalesce, OverflowPolicy, SubscriptionMetrics, SubscriptionOptions};
+
+use crate::rt;
 
 #[allow(clippy::large_enum_variant)]
 enum Command<T: Clone + Debug + Send + 'static> {
     Subscribe {
         response: oneshot::Sender<Receiver<T>>,
     },
+    SubscribeWith {
+        options: SubscriptionOptions,
+        supersedes: fn(&T, &T) -> bool,
+        response: oneshot::Sender<Subscription<T>>,
+    },
     Emit {
         event: T,
     },
@@ -36,11 +48,12 @@ impl<T: Clone + Debug + Send + 'static> EventSubscription<T> {
         let mut task = EventSubscriptionTask {
             queue: Default::default(),
             senders: Default::default(),
+            subscribers: Default::default(),
             waker: None,
             rx,
         };
 
-        let _handle = async_rt::task::spawn_abortable(async move { task.run().await });
+        let _handle = rt::spawn_abortable(async move { task.run().await });
 
         Self { tx, _handle }
     }
@@ -66,8 +79,183 @@ impl<T: Clone + Debug + Send + 'static> EventSubscription<T> {
     }
 }
 
+impl<T: Clone + Debug + Send + Coalesce + 'static> EventSubscription<T> {
+    /// Subscribe with a buffer bounded by the options, applying the overflow policy when the subscriber falls behind.
+    /// Unlike [`EventSubscription::subscribe`], a slow subscriber does not hold back the events of the other subscribers,
+    /// at the cost of the events lost to the overflow policy
+    pub async fn subscribe_with<'a>(
+        &self,
+        options: SubscriptionOptions,
+    ) -> Result<BoxStream<'a, T>, Error> {
+        let (tx, rx) = futures::channel::oneshot::channel();
+
+        let _ = self
+            .tx
+            .clone()
+            .send(Command::SubscribeWith {
+                options,
+                supersedes: T::supersedes,
+                response: tx,
+            })
+            .await;
+
+        Ok(rx.await.map_err(anyhow::Error::from)?.boxed())
+    }
+}
+
+/// Forward the events of `stream` into a bounded subscription. The events are pulled from `stream` as they arrive,
+/// regardless of how fast they are consumed, so the subscription is the only place where events are buffered.
+pub fn bounded_stream<T, S>(stream: S, options: SubscriptionOptions) -> Subscription<T>
+where
+    T: Coalesce + Send + 'static,
+    S: Stream<Item = T> + Send + 'static,
+{
+    let buffer = Arc::new(SubscriberBuffer::new(options, T::supersedes));
+
+    let handle = rt::spawn_abortable({
+        let buffer = buffer.clone();
+        async move {
+            let mut stream = std::pin::pin!(stream);
+            while let Some(event) = stream.next().await {
+                if !buffer.push(event) {
+                    return;
+                }
+            }
+            buffer.close();
+        }
+    });
+
+    Subscription {
+        buffer,
+        _forwarder: Some(handle),
+    }
+}
+
+struct BufferState<T> {
+    events: VecDeque<T>,
+    closed: bool,
+}
+
+/// Events buffered for a single subscriber
+struct SubscriberBuffer<T> {
+    state: Mutex<BufferState<T>>,
+    waker: AtomicWaker,
+    capacity: usize,
+    overflow: OverflowPolicy,
+    supersedes: fn(&T, &T) -> bool,
+    metrics: SubscriptionMetrics,
+}
+
+impl<T> SubscriberBuffer<T> {
+    fn new(options: SubscriptionOptions, supersedes: fn(&T, &T) -> bool) -> Self {
+        Self {
+            state: Mutex::new(BufferState {
+                events: VecDeque::new(),
+                closed: false,
+            }),
+            waker: AtomicWaker::new(),
+            capacity: options.capacity(),
+            overflow: options.overflow(),
+            supersedes,
+            metrics: options.metrics(),
+        }
+    }
+
+    /// Returns false if the subscription has ended, in which case the event was not buffered
+    fn push(&self, event: T) -> bool {
+        let mut state = self.state.lock();
+
+        if state.closed {
+            return false;
+        }
+
+        if state.events.len() >= self.capacity {
+            match self.overflow {
+                OverflowPolicy::DropOldest => {
+                    state.events.pop_front();
+                    self.metrics.record_dropped(1);
+                }
+                OverflowPolicy::Coalesce => {
+                    let superseded = state
+                        .events
+                        .iter()
+                        .rposition(|previous| (self.supersedes)(&event, previous));
+
+                    match superseded {
+                        Some(index) => {
+                            state.events.remove(index);
+                            self.metrics.record_coalesced();
+                        }
+                        None => {
+                            state.events.pop_front();
+                            self.metrics.record_dropped(1);
+                        }
+                    }
+                }
+                OverflowPolicy::Error => {
+                    state.closed = true;
+                    self.metrics.record_overflow();
+                    self.metrics.record_dropped(1);
+                    drop(state);
+                    self.waker.wake();
+                    return false;
+                }
+            }
+        }
+
+        state.events.push_back(event);
+        self.metrics.record_lag(state.events.len());
+        drop(state);
+        self.waker.wake();
+        true
+    }
+
+    fn close(&self) {
+        self.state.lock().closed = true;
+        self.waker.wake();
+    }
+}
+
+/// Stream of the events buffered for a subscriber
+pub struct Subscription<T> {
+    buffer: Arc<SubscriberBuffer<T>>,
+    _forwarder: Option<AbortableJoinHandle<()>>,
+}
+
+impl<T> Stream for Subscription<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let buffer = &self.buffer;
+
+        // registered before checking the buffer so an event pushed in between is not missed
+        buffer.waker.register(cx.waker());
+
+        let mut state = buffer.state.lock();
+
+        if let Some(event) = state.events.pop_front() {
+            buffer.metrics.record_delivered();
+            buffer.metrics.record_lag(state.events.len());
+            return Poll::Ready(Some(event));
+        }
+
+        if state.closed {
+            return Poll::Ready(None);
+        }
+
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for Subscription<T> {
+    fn drop(&mut self) {
+        self.buffer.state.lock().closed = true;
+    }
+}
+
 struct EventSubscriptionTask<T: Clone + Send + Debug + 'static> {
     senders: Vec<Sender<T>>,
+    subscribers: Vec<Arc<SubscriberBuffer<T>>>,
     queue: VecDeque<T>,
     rx: Receiver<Command<T>>,
     waker: Option<Waker>,
@@ -122,6 +310,13 @@ impl<T: Clone + Send + 'static + Debug> EventSubscriptionTask<T> {
                                 w.wake();
                             }
                         },
+                        Command::SubscribeWith {
+                            options,
+                            supersedes,
+                            response,
+                        } => {
+                            _ = response.send(self.subscribe_with(options, supersedes));
+                        }
                         Command::Emit { event } => self.emit(event),
                     }
                 }
@@ -135,14 +330,46 @@ impl<T: Clone + Send + 'static + Debug> EventSubscriptionTask<T> {
         rx
     }
 
+    fn subscribe_with(
+        &mut self,
+        options: SubscriptionOptions,
+        supersedes: fn(&T, &T) -> bool,
+    ) -> Subscription<T> {
+        let buffer = Arc::new(SubscriberBuffer::new(options, supersedes));
+
+        // events emitted while there were no subscribers are handed to the first subscriber.
+        // otherwise the queue holds the events still to be delivered to the existing subscribers
+        if self.senders.is_empty() {
+            for event in self.queue.drain(..) {
+                buffer.push(event);
+            }
+        }
+
+        self.subscribers.push(buffer.clone());
+
+        Subscription {
+            buffer,
+            _forwarder: None,
+        }
+    }
+
     fn emit(&mut self, event: T) {
-        self.queue.push_back(event);
+        self.subscribers
+            .retain(|subscriber| subscriber.push(event.clone()));
+
+        // the queue is only needed by the backpressured subscribers, or to hold the event until the first subscription
+        if !self.senders.is_empty() || self.subscribers.is_empty() {
+            self.queue.push_back(event);
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
     use futures::{FutureExt, StreamExt};
+    use warp::crypto::DID;
+    use warp::multipass::MultiPassEventKind;
+    use warp::subscription::{OverflowPolicy, SubscriptionOptions};
 
     use crate::store::event_subscription::EventSubscription;
 
@@ -185,4 +412,102 @@ mod test {
         assert_eq!(list[1], "World, Hello");
         Ok(())
     }
+
+    #[tokio::test]
+    async fn slow_subscriber_receives_every_event() -> anyhow::Result<()> {
+        let pubsub = EventSubscription::<usize>::new();
+        let stream = pubsub.subscribe().await?;
+
+        // more events than the channel of the subscriber can hold
+        for event in 0..1000 {
+            pubsub.emit(event).await;
+        }
+
+        let list = stream.take(1000).collect::<Vec<_>>().await;
+
+        assert_eq!(list, (0..1000).collect::<Vec<_>>());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn drop_oldest_on_overflow() -> anyhow::Result<()> {
+        let pubsub = EventSubscription::<MultiPassEventKind>::new();
+        let options = SubscriptionOptions::new(2, OverflowPolicy::DropOldest);
+        let metrics = options.metrics();
+        let mut stream = pubsub.subscribe_with(options).await?;
+
+        let events = (0..3)
+            .map(|_| MultiPassEventKind::FriendAdded {
+                did: DID::default(),
+            })
+            .collect::<Vec<_>>();
+
+        for event in events.clone() {
+            pubsub.emit(event).await;
+        }
+
+        assert_eq!(metrics.dropped(), 1);
+        assert_eq!(metrics.lag(), 2);
+
+        assert_eq!(stream.next().await, Some(events[1].clone()));
+        assert_eq!(stream.next().await, Some(events[2].clone()));
+        assert_eq!(metrics.delivered(), 2);
+        assert_eq!(metrics.lag(), 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn coalesce_on_overflow() -> anyhow::Result<()> {
+        let pubsub = EventSubscription::<MultiPassEventKind>::new();
+        let options = SubscriptionOptions::new(2, OverflowPolicy::Coalesce);
+        let metrics = options.metrics();
+        let mut stream = pubsub.subscribe_with(options).await?;
+
+        let did = DID::default();
+        let added = MultiPassEventKind::FriendAdded {
+            did: DID::default(),
+        };
+
+        pubsub
+            .emit(MultiPassEventKind::IdentityOnline { did: did.clone() })
+            .await;
+        pubsub.emit(added.clone()).await;
+        pubsub
+            .emit(MultiPassEventKind::IdentityOffline { did: did.clone() })
+            .await;
+
+        assert_eq!(metrics.coalesced(), 1);
+        assert_eq!(metrics.dropped(), 0);
+
+        assert_eq!(stream.next().await, Some(added));
+        assert_eq!(
+            stream.next().await,
+            Some(MultiPassEventKind::IdentityOffline { did })
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn end_subscription_on_overflow() -> anyhow::Result<()> {
+        let pubsub = EventSubscription::<MultiPassEventKind>::new();
+        let options = SubscriptionOptions::new(1, OverflowPolicy::Error);
+        let metrics = options.metrics();
+        let mut stream = pubsub.subscribe_with(options).await?;
+
+        let event = MultiPassEventKind::FriendAdded {
+            did: DID::default(),
+        };
+
+        pubsub.emit(event.clone()).await;
+        pubsub
+            .emit(MultiPassEventKind::FriendAdded {
+                did: DID::default(),
+            })
+            .await;
+
+        assert!(metrics.overflowed());
+        assert_eq!(stream.next().await, Some(event));
+        assert_eq!(stream.next().await, None);
+        Ok(())
+    }
 }
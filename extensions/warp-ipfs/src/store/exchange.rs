@@ -0,0 +1,199 @@
+use std::time::Duration;
+
+use async_rt::AbortableJoinHandle;
+use bytes::Bytes;
+use futures::{
+    future::BoxFuture,
+    stream::{BoxStream, FuturesUnordered},
+    FutureExt, StreamExt,
+};
+use futures_timeout::TimeoutExt;
+use futures_timer::Delay;
+use rust_ipfs::{libp2p::request_response::InboundRequestId, Ipfs, PeerId};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::{Instrument, Span};
+use warp::{crypto::DID, error::Error};
+use web_time::Instant;
+
+use super::{identity::IdentityStore, protocols, DidExt};
+use crate::connection::ConnectionEvent;
+use crate::rt;
+
+/// Interval between latency samples of connected friends
+const LATENCY_SAMPLE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Duration to wait for a response before giving up on a sample
+const PING_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ExchangeRequest {
+    Ping { nonce: u64 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ExchangeResponse {
+    Pong { nonce: u64 },
+}
+
+/// Answers requests over the exchange protocol and samples the latency of connected friends,
+/// reporting it alongside the connection events
+#[derive(Clone)]
+pub struct ExchangeStore {
+    _handle: AbortableJoinHandle<()>,
+}
+
+impl ExchangeStore {
+    pub async fn new(
+        ipfs: &Ipfs,
+        identity: &IdentityStore,
+        event: broadcast::Sender<ConnectionEvent>,
+        span: &Span,
+    ) -> Result<Self, Error> {
+        // The node of a replica does not support request-response
+        let stream = match identity.is_replica() {
+            true => futures::stream::pending().boxed(),
+            false => ipfs
+                .requests_subscribe(protocols::EXCHANGE_PROTOCOL)
+                .await?
+                .boxed(),
+        };
+
+        let task = ExchangeTask {
+            ipfs: ipfs.clone(),
+            identity: identity.clone(),
+            event,
+        };
+
+        let span = span.clone();
+
+        let _handle = rt::spawn_abortable(async move { task.run(stream).await }.instrument(span));
+
+        Ok(Self { _handle })
+    }
+}
+
+struct ExchangeTask {
+    ipfs: Ipfs,
+    identity: IdentityStore,
+    event: broadcast::Sender<ConnectionEvent>,
+}
+
+impl ExchangeTask {
+    async fn run(self, mut stream: BoxStream<'static, (PeerId, InboundRequestId, Bytes)>) {
+        let mut connection_events = self.event.subscribe();
+        let mut pending: FuturesUnordered<BoxFuture<'static, ()>> = FuturesUnordered::new();
+        let mut tick = Delay::new(LATENCY_SAMPLE_INTERVAL);
+
+        loop {
+            tokio::select! {
+                Some((peer_id, id, request)) = stream.next() => {
+                    pending.push(self.respond(peer_id, id, request).boxed());
+                }
+                Ok(event) = connection_events.recv() => {
+                    // Sample the latency of a friend once they are connected
+                    if let ConnectionEvent::Connected { did: Some(did), connections: 1, .. } = event {
+                        if self.identity.is_friend(&did).await.unwrap_or_default() {
+                            pending.push(self.sample(did).boxed());
+                        }
+                    }
+                }
+                _ = &mut tick => {
+                    let friends = self.identity.friends_list().await.unwrap_or_default();
+                    for did in friends {
+                        let Ok(peer_id) = did.to_peer_id() else {
+                            continue;
+                        };
+
+                        if self.ipfs.is_connected(peer_id).await.unwrap_or_default() {
+                            pending.push(self.sample(did).boxed());
+                        }
+                    }
+                    tick.reset(LATENCY_SAMPLE_INTERVAL);
+                }
+                Some(_) = pending.next() => {}
+            }
+        }
+    }
+
+    fn respond(
+        &self,
+        peer_id: PeerId,
+        id: InboundRequestId,
+        request: Bytes,
+    ) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let ipfs = self.ipfs.clone();
+        async move {
+            let response = match serde_json::from_slice(&request) {
+                Ok(ExchangeRequest::Ping { nonce }) => ExchangeResponse::Pong { nonce },
+                Err(e) => {
+                    tracing::warn!(%peer_id, error = %e, "invalid exchange request");
+                    return;
+                }
+            };
+
+            let bytes = match serde_json::to_vec(&response) {
+                Ok(bytes) => Bytes::from(bytes),
+                Err(e) => {
+                    tracing::error!(%peer_id, error = %e, "unable to construct exchange response");
+                    return;
+                }
+            };
+
+            if let Err(e) = ipfs
+                .send_response(peer_id, id, (protocols::EXCHANGE_PROTOCOL, bytes))
+                .await
+            {
+                tracing::warn!(%peer_id, error = %e, "unable to send exchange response");
+            }
+        }
+    }
+
+    fn sample(&self, did: DID) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let ipfs = self.ipfs.clone();
+        let event = self.event.clone();
+        async move {
+            let Ok(peer_id) = did.to_peer_id() else {
+                return;
+            };
+
+            match ping(&ipfs, peer_id).await {
+                Ok(rtt) => {
+                    let _ = event.send(ConnectionEvent::Latency {
+                        peer_id,
+                        did: Some(did),
+                        rtt,
+                    });
+                }
+                Err(e) => {
+                    tracing::debug!(%peer_id, error = %e, "unable to sample latency");
+                }
+            }
+        }
+    }
+}
+
+async fn ping(ipfs: &Ipfs, peer_id: PeerId) -> Result<Duration, Error> {
+    let nonce = warp::crypto::rand::random::<u64>();
+    let request = Bytes::from(serde_json::to_vec(&ExchangeRequest::Ping { nonce })?);
+
+    let timer = Instant::now();
+
+    let response = ipfs
+        .send_request(peer_id, (protocols::EXCHANGE_PROTOCOL, request))
+        .timeout(PING_TIMEOUT)
+        .await
+        .map_err(|_| Error::OtherWithContext("timed out waiting for response".into()))?
+        .map_err(anyhow::Error::from)?;
+
+    let rtt = timer.elapsed();
+
+    match serde_json::from_slice(&response)? {
+        ExchangeResponse::Pong { nonce: n } if n == nonce => Ok(rtt),
+        ExchangeResponse::Pong { .. } => Err(Error::OtherWithContext(
+            "response does not match the request".into(),
+        )),
+    }
+}
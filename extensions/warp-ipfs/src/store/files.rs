@@ -11,14 +11,24 @@ use futures::{
     FutureExt, SinkExt, StreamExt, TryStreamExt,
 };
 use futures_finally::try_stream::FinallyTryStreamExt;
-use std::{collections::VecDeque, path::PathBuf, sync::Arc};
+use futures_timer::Delay;
+use ipld_core::cid::Cid;
+use std::{
+    collections::{HashSet, VecDeque},
+    path::PathBuf,
+    sync::Arc,
+};
 
 use rust_ipfs::{unixfs::UnixfsStatus, Ipfs, IpfsPath};
+use sha2::{Digest, Sha256};
+use web_time::Instant;
 
 use tracing::{Instrument, Span};
+use uuid::Uuid;
 use warp::{
     constellation::{
-        directory::Directory, ConstellationEventKind, ConstellationProgressStream, Progression,
+        directory::Directory, file::File, ConstellationEventKind, ConstellationProgressStream,
+        IntegrityIssue, IntegrityIssueKind, IntegrityReport, Progression,
     },
     error::Error,
 };
@@ -27,11 +37,18 @@ use parking_lot::RwLock;
 use warp::constellation::item::{Item, ItemType};
 
 use super::{
-    document::root::RootDocumentMap, event_subscription::EventSubscription,
+    cold_storage::{ColdStorage, ColdStorageReport},
+    document::root::{merge_directory, RootDocumentMap},
+    event_subscription::EventSubscription,
+    sync::{normalize_path, SelectiveSync},
+    upload::{ResumableUpload, UploadSessions},
     MAX_THUMBNAIL_STREAM_SIZE,
 };
 use crate::{
     config::{self, Config},
+    metadata,
+    readiness::{Readiness, Subsystem},
+    rt,
     thumbnail::ThumbnailGenerator,
     to_file_type,
 };
@@ -41,7 +58,9 @@ pub struct FileStore {
     index: Directory,
     path: Arc<RwLock<PathBuf>>,
     config: config::Config,
+    strip_metadata: Option<bool>,
     command_sender: mpsc::Sender<FileTaskCommand>,
+    uploads: UploadSessions,
     _handle: AbortableJoinHandle<()>,
 }
 
@@ -50,7 +69,9 @@ impl FileStore {
         ipfs: &Ipfs,
         root: &RootDocumentMap,
         config: &Config,
+        selective_sync: &SelectiveSync,
         constellation_tx: EventSubscription<ConstellationEventKind>,
+        readiness: &Readiness,
         span: &Span,
     ) -> Self {
         let config = config.clone();
@@ -59,6 +80,10 @@ impl FileStore {
 
         let thumbnail_store = ThumbnailGenerator::new(ipfs);
 
+        let cold_storage = ColdStorage::new(ipfs, config.store_setting().cold_storage).await;
+
+        let uploads = UploadSessions::load(ipfs).await;
+
         let (command_sender, command_receiver) = futures::channel::mpsc::channel(1);
         let (export_tx, export_rx) = futures::channel::mpsc::channel(0);
         let (signal_tx, signal_rx) = futures::channel::mpsc::unbounded();
@@ -69,6 +94,8 @@ impl FileStore {
             root: root.clone(),
             thumbnail_store,
             ipfs: ipfs.clone(),
+            selective_sync: selective_sync.clone(),
+            cold_storage,
             constellation_tx,
             config,
             export_rx,
@@ -78,27 +105,31 @@ impl FileStore {
             command_receiver,
         };
 
-        if let Err(e) = task.import_v1().await {
-            tracing::warn!("Unable to import index: {e}");
-        }
-
-        let mut index = task.index.clone();
+        let index = task.index.clone();
         let path = task.path.clone();
         let config = task.config.clone();
 
-        let signal = Some(task.signal_tx.clone());
-        index.rebuild_paths(&signal);
-
         let span = span.clone();
-
-        let _handle =
-            async_rt::task::spawn_abortable(async move { task.run().await }.instrument(span));
+        let readiness = readiness.clone();
+
+        // the index is resolved in the background, so it is empty until the store is ready. Items added in the
+        // meantime are merged into the resolved index
+        let _handle = rt::spawn_abortable(
+            async move {
+                task.load_index(true).await;
+                readiness.set_ready(Subsystem::Files);
+                task.run().await
+            }
+            .instrument(span),
+        );
 
         FileStore {
             index,
             config,
+            strip_metadata: None,
             path,
             command_sender,
+            uploads,
             _handle,
         }
     }
@@ -146,6 +177,17 @@ impl FileStore {
         PathBuf::from(self.path.read().to_string_lossy().replace('\\', "/"))
     }
 
+    /// Override whether metadata is stripped from images uploaded through this instance.
+    /// If `None`, the setting from the config is used.
+    pub fn with_strip_metadata(mut self, strip: Option<bool>) -> Self {
+        self.strip_metadata = strip;
+        self
+    }
+
+    fn strip_metadata(&self) -> bool {
+        self.strip_metadata.unwrap_or(self.config.strip_metadata())
+    }
+
     #[cfg(not(target_arch = "wasm32"))]
     pub async fn put(
         &mut self,
@@ -159,6 +201,7 @@ impl FileStore {
             .send(FileTaskCommand::Put {
                 name: name.into(),
                 path: path.into(),
+                strip_metadata: self.strip_metadata(),
                 response: tx,
             })
             .await;
@@ -196,6 +239,7 @@ impl FileStore {
             .send(FileTaskCommand::PutBuffer {
                 name: name.into(),
                 buffer: Bytes::from(Vec::from(buffer)),
+                strip_metadata: self.strip_metadata(),
                 response: tx,
             })
             .await;
@@ -230,12 +274,55 @@ impl FileStore {
                 name: name.into(),
                 total_size: total_size.into(),
                 stream,
+                strip_metadata: self.strip_metadata(),
                 response: tx,
             })
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
 
+    /// Used to start an upload that can be resumed from the last confirmed chunk if interrupted
+    pub async fn put_resumable(
+        &self,
+        name: impl Into<String>,
+        total_size: usize,
+    ) -> Result<ResumableUpload, Error> {
+        let name = name.into();
+
+        if self.current_directory()?.get_item_by_path(&name).is_ok() {
+            return Err(Error::FileExist);
+        }
+
+        // uploads that were not completed will take up their full size once they are
+        let current_size = self.current_size() + self.uploads.reserved().await;
+
+        if total_size + current_size > self.max_size() {
+            return Err(Error::InvalidLength {
+                context: "stream".into(),
+                minimum: None,
+                maximum: Some(self.max_size()),
+                current: current_size + total_size,
+            });
+        }
+
+        self.uploads.create(self, &name, total_size).await
+    }
+
+    /// Used to reopen an upload that was not completed
+    pub async fn upload_session(&self, id: Uuid) -> Result<ResumableUpload, Error> {
+        self.uploads.open(self, id).await
+    }
+
+    /// Uploads that were not completed
+    pub async fn upload_sessions(&self) -> Vec<Uuid> {
+        self.uploads.list().await
+    }
+
+    /// Used to discard an upload that was not completed, unpinning the chunks that were stored
+    pub async fn cancel_upload(&self, id: Uuid) -> Result<(), Error> {
+        self.uploads.remove(id).await
+    }
+
     /// Used to download data from the filesystem using a stream
     pub async fn get_stream(
         &self,
@@ -316,6 +403,110 @@ impl FileStore {
             .await;
         rx.await.map_err(anyhow::Error::from)??.await
     }
+
+    pub async fn verify(&self, refetch: bool) -> Result<IntegrityReport, Error> {
+        let (tx, rx) = oneshot::channel();
+        let _ = self
+            .command_sender
+            .clone()
+            .send(FileTaskCommand::Verify {
+                refetch,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?.await
+    }
+
+    /// Exclude the directory at `path` from being kept on this device, or include it again. The content of files
+    /// within an excluded directory is unpinned while the files remain in the index as placeholders. Including the
+    /// directory fetches and pins the content again.
+    pub async fn set_directory_excluded(
+        &self,
+        path: impl Into<String>,
+        excluded: bool,
+    ) -> Result<(), Error> {
+        let (tx, rx) = oneshot::channel();
+        let _ = self
+            .command_sender
+            .clone()
+            .send(FileTaskCommand::SetDirectoryExcluded {
+                path: path.into(),
+                excluded,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)??.await
+    }
+
+    /// Report of the files in cold storage, and of the files that are eligible to be moved to cold storage
+    pub async fn cold_storage_report(&self) -> Result<ColdStorageReport, Error> {
+        let (tx, rx) = oneshot::channel();
+        let _ = self
+            .command_sender
+            .clone()
+            .send(FileTaskCommand::ColdStorageReport { response: tx })
+            .await;
+        Ok(rx.await.map_err(anyhow::Error::from)?)
+    }
+
+    /// Move the content of every eligible file to cold storage without waiting for the next interval of the policy
+    pub async fn apply_cold_storage(&self) -> Result<ColdStorageReport, Error> {
+        let (tx, rx) = oneshot::channel();
+        let _ = self
+            .command_sender
+            .clone()
+            .send(FileTaskCommand::ApplyColdStorage { response: tx })
+            .await;
+        Ok(rx.await.map_err(anyhow::Error::from)?.await)
+    }
+
+    /// Keep the content of the file at `path` locally regardless of the cold storage policy. If the file is already
+    /// in cold storage, its content is fetched again
+    pub async fn set_pinned_locally(
+        &self,
+        path: impl Into<String>,
+        pinned: bool,
+    ) -> Result<(), Error> {
+        let (tx, rx) = oneshot::channel();
+        let _ = self
+            .command_sender
+            .clone()
+            .send(FileTaskCommand::SetPinnedLocally {
+                path: path.into(),
+                pinned,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)??.await
+    }
+
+    /// Fetch and pin the content of the file, or every file within the directory, at `path`
+    pub async fn hydrate(&self, path: impl Into<String>) -> Result<(), Error> {
+        let (tx, rx) = oneshot::channel();
+        let _ = self
+            .command_sender
+            .clone()
+            .send(FileTaskCommand::Hydrate {
+                path: path.into(),
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)??.await
+    }
+
+    /// Returns true if the content of the file at `path` is pinned on this device
+    pub async fn is_hydrated(&self, path: impl Into<String>) -> Result<bool, Error> {
+        let (tx, rx) = oneshot::channel();
+        let _ = self
+            .command_sender
+            .clone()
+            .send(FileTaskCommand::IsHydrated {
+                path: path.into(),
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)??.await
+    }
 }
 
 type GetStream = BoxStream<'static, Result<Bytes, std::io::Error>>;
@@ -325,17 +516,20 @@ enum FileTaskCommand {
     Put {
         name: String,
         path: String,
+        strip_metadata: bool,
         response: oneshot::Sender<Result<ConstellationProgressStream, Error>>,
     },
     PutBuffer {
         name: String,
         buffer: Bytes,
+        strip_metadata: bool,
         response: oneshot::Sender<Result<BoxFuture<'static, Result<(), Error>>, Error>>,
     },
     PutStream {
         name: String,
         total_size: Option<usize>,
         stream: BoxStream<'static, std::io::Result<Bytes>>,
+        strip_metadata: bool,
         response: oneshot::Sender<Result<ConstellationProgressStream, Error>>,
     },
     #[cfg(not(target_arch = "wasm32"))]
@@ -372,6 +566,34 @@ enum FileTaskCommand {
         path: String,
         response: oneshot::Sender<Result<BoxFuture<'static, Result<(), Error>>, Error>>,
     },
+    Verify {
+        refetch: bool,
+        response: oneshot::Sender<BoxFuture<'static, Result<IntegrityReport, Error>>>,
+    },
+    SetDirectoryExcluded {
+        path: String,
+        excluded: bool,
+        response: oneshot::Sender<Result<BoxFuture<'static, Result<(), Error>>, Error>>,
+    },
+    Hydrate {
+        path: String,
+        response: oneshot::Sender<Result<BoxFuture<'static, Result<(), Error>>, Error>>,
+    },
+    IsHydrated {
+        path: String,
+        response: oneshot::Sender<Result<BoxFuture<'static, Result<bool, Error>>, Error>>,
+    },
+    ColdStorageReport {
+        response: oneshot::Sender<ColdStorageReport>,
+    },
+    ApplyColdStorage {
+        response: oneshot::Sender<BoxFuture<'static, ColdStorageReport>>,
+    },
+    SetPinnedLocally {
+        path: String,
+        pinned: bool,
+        response: oneshot::Sender<Result<BoxFuture<'static, Result<(), Error>>, Error>>,
+    },
 }
 
 struct FileTask {
@@ -385,12 +607,24 @@ struct FileTask {
     signal_tx: futures::channel::mpsc::UnboundedSender<()>,
     signal_rx: futures::channel::mpsc::UnboundedReceiver<()>,
     thumbnail_store: ThumbnailGenerator,
+    selective_sync: SelectiveSync,
+    cold_storage: ColdStorage,
     constellation_tx: EventSubscription<ConstellationEventKind>,
     command_receiver: futures::channel::mpsc::Receiver<FileTaskCommand>,
 }
 
 impl FileTask {
     async fn run(&mut self) {
+        let cold_storage_interval = self
+            .cold_storage
+            .policy()
+            .map(|policy| policy.interval)
+            .unwrap_or_default();
+
+        let mut cold_storage_timer = Delay::new(cold_storage_interval);
+
+        let mut root_changes = self.root.subscribe_changes();
+
         loop {
             tokio::select! {
                 biased;
@@ -400,24 +634,27 @@ impl FileTask {
                         FileTaskCommand::Put {
                             name,
                             path,
+                            strip_metadata,
                             response,
                         } => {
-                           let _ = response.send(self.put(&name, &path).await);
+                           let _ = response.send(self.put(&name, &path, strip_metadata).await);
                         },
                         FileTaskCommand::PutBuffer {
                             name,
                             buffer,
+                            strip_metadata,
                             response,
                         } => {
-                            let _ = response.send(self.put_buffer(name, buffer));
+                            let _ = response.send(self.put_buffer(name, buffer, strip_metadata));
                         },
                         FileTaskCommand::PutStream {
                             name,
                             total_size,
                             stream,
+                            strip_metadata,
                             response,
                         } => {
-                           let _ = response.send(self.put_stream(&name, total_size, stream));
+                           let _ = response.send(self.put_stream(&name, total_size, stream, strip_metadata));
                         },
                         #[cfg(not(target_arch = "wasm32"))]
                         FileTaskCommand::Get {
@@ -457,8 +694,39 @@ impl FileTask {
                         FileTaskCommand::SyncRef { path, response } => {
                             let _ = response.send(self.sync_ref(&path));
                         },
+                        FileTaskCommand::Verify { refetch, response } => {
+                            let _ = response.send(self.verify(refetch));
+                        },
+                        FileTaskCommand::SetDirectoryExcluded { path, excluded, response } => {
+                            let _ = response.send(self.set_directory_excluded(&path, excluded));
+                        },
+                        FileTaskCommand::Hydrate { path, response } => {
+                            let _ = response.send(self.hydrate(&path));
+                        },
+                        FileTaskCommand::IsHydrated { path, response } => {
+                            let _ = response.send(self.is_hydrated(&path));
+                        },
+                        FileTaskCommand::ColdStorageReport { response } => {
+                            let _ = response.send(self.cold_storage_report());
+                        },
+                        FileTaskCommand::ApplyColdStorage { response } => {
+                            let _ = response.send(self.apply_cold_storage());
+                        },
+                        FileTaskCommand::SetPinnedLocally { path, pinned, response } => {
+                            let _ = response.send(self.set_pinned_locally(&path, pinned));
+                        },
                     }
                 },
+                _ = &mut cold_storage_timer, if self.cold_storage.policy().is_some() => {
+                    let report = self.apply_cold_storage().await;
+                    tracing::debug!(evicted = report.evicted, reclaimable = report.reclaimable, "applied cold storage policy");
+                    cold_storage_timer.reset(cold_storage_interval);
+                },
+                Ok(changes) = root_changes.recv() => {
+                    if changes.file_index {
+                        self.load_index(false).await;
+                    }
+                }
                 Some(_) = self.export_rx.next() => {
                     let _ = self.export().await;
                 }
@@ -471,9 +739,37 @@ impl FileTask {
         }
     }
 
-    async fn import_v1(&self) -> Result<(), Error> {
+    /// Resolve the index from the root document. With `keep_pending`, items added while the index was being
+    /// resolved during startup are merged into it rather than discarded
+    async fn load_index(&self, keep_pending: bool) {
+        let started = Instant::now();
+
+        if let Err(e) = self.import_v1(keep_pending).await {
+            tracing::warn!("Unable to import index: {e}");
+        }
+
+        let mut index = self.index.clone();
+        let signal = Some(self.signal_tx.clone());
+        index.rebuild_paths(&signal);
+
+        tracing::info!(
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "File index loaded"
+        );
+    }
+
+    async fn import_v1(&self, keep_pending: bool) -> Result<(), Error> {
         let index = self.root.get_directory_index().await?;
+
+        let pending = Directory::new("root");
+        if keep_pending {
+            pending.set_items(self.index.get_items());
+        }
+
         self.index.set_items(index.get_items());
+
+        // the pending items take precedence over those of the index unless the latter were modified more recently
+        merge_directory(&self.index, &pending);
         Ok(())
     }
 
@@ -526,7 +822,12 @@ impl FileTask {
     }
 
     #[cfg(not(target_arch = "wasm32"))]
-    async fn put(&mut self, name: &str, path: &str) -> Result<ConstellationProgressStream, Error> {
+    async fn put(
+        &mut self,
+        name: &str,
+        path: &str,
+        strip_metadata: bool,
+    ) -> Result<ConstellationProgressStream, Error> {
         let (name, dest_path) = split_file_from_path(name)?;
 
         let ipfs = self.ipfs.clone();
@@ -560,6 +861,20 @@ impl FileTask {
             return Err(Error::FileExist);
         }
 
+        // the file is read as a stream so the image is not held in memory while its metadata is removed
+        let stripped = match strip_metadata && metadata::is_supported(&path) {
+            true => {
+                use crate::utils::ReaderStream;
+                use tokio_util::compat::TokioAsyncReadCompatExt;
+
+                let file = tokio::fs::File::open(&path).await?;
+                let stream =
+                    ReaderStream::from_reader_with_cap(file.compat(), 64 * 1024, None).boxed();
+                Some(metadata::strip_stream(stream, self.config.max_file_size()))
+            }
+            false => None,
+        };
+
         let ((width, height), exact) = (
             self.config.thumbnail_size(),
             self.config.thumbnail_exact_format(),
@@ -578,7 +893,10 @@ impl FileTask {
             let mut total_written = 0;
             let mut returned_path = None;
 
-            let mut stream = ipfs.add_unixfs(path);
+            let mut stream = match stripped {
+                Some(stream) => ipfs.add_unixfs(stream),
+                None => ipfs.add_unixfs(path),
+            };
 
             while let Some(status) = stream.next().await {
                 let name = name.clone();
@@ -727,6 +1045,7 @@ impl FileTask {
         &self,
         name: String,
         buffer: Bytes,
+        strip_metadata: bool,
     ) -> Result<BoxFuture<'static, Result<(), Error>>, Error> {
         let ipfs = self.ipfs.clone();
         let thumbnail_store = self.thumbnail_store.clone();
@@ -737,6 +1056,11 @@ impl FileTask {
 
         let (name, dest_path) = split_file_from_path(name)?;
 
+        let buffer = match strip_metadata && metadata::is_supported(&name) {
+            true => metadata::strip(&buffer).map(Bytes::from).unwrap_or(buffer),
+            false => buffer,
+        };
+
         if self.current_size() + buffer.len() >= self.max_size() {
             return Err(Error::InvalidLength {
                 context: "buffer".into(),
@@ -858,6 +1182,7 @@ impl FileTask {
         name: &str,
         total_size: Option<usize>,
         stream: BoxStream<'static, std::io::Result<Bytes>>,
+        strip_metadata: bool,
     ) -> Result<ConstellationProgressStream, Error> {
         let (name, dest_path) = split_file_from_path(name)?;
 
@@ -900,6 +1225,12 @@ impl FileTask {
         let max_file_size = self.config.max_file_size();
         let root = self.root_directory();
 
+        // the size of the stream no longer applies once the metadata is removed from it
+        let (stream, total_size) = match strip_metadata && metadata::is_supported(&name) {
+            true => (metadata::strip_stream(stream, max_file_size), None),
+            false => (stream, total_size),
+        };
+
         let thumbnail_store = self.thumbnail_store.clone();
         let thumbnail_size = self.config.thumbnail_size();
         let thumbnail_format = self.config.thumbnail_exact_format();
@@ -1114,8 +1445,8 @@ impl FileTask {
     async fn rename(&mut self, current: &str, new: &str) -> Result<(), Error> {
         let (current, dest_path) = split_file_from_path(current)?;
 
-        let current_directory = match dest_path {
-            Some(dest) => self.root_directory().get_last_directory_from_path(&dest)?,
+        let current_directory = match &dest_path {
+            Some(dest) => self.root_directory().get_last_directory_from_path(dest)?,
             None => self.current_directory()?,
         };
 
@@ -1125,6 +1456,16 @@ impl FileTask {
 
         current_directory.rename_item(&current, new)?;
 
+        // Exclusions are kept by path, so they are moved along with the directory
+        let parent = dest_path.unwrap_or_else(|| self.get_path().to_string_lossy().to_string());
+        if let Err(e) = self
+            .selective_sync
+            .rename_directory(&format!("{parent}/{current}"), &format!("{parent}/{new}"))
+            .await
+        {
+            tracing::warn!(error = %e, "unable to update selective sync settings of renamed item");
+        }
+
         self.export().await?;
 
         self.constellation_tx
@@ -1195,6 +1536,313 @@ impl FileTask {
         }
         .boxed())
     }
+
+    fn verify(&self, refetch: bool) -> BoxFuture<'static, Result<IntegrityReport, Error>> {
+        let ipfs = self.ipfs.clone();
+        let index = self.index.clone();
+        let settings = self.selective_sync.settings();
+        let cold_storage = self.cold_storage.clone();
+
+        async move {
+            let mut files = vec![];
+            collect_files(&index, &mut files);
+
+            // Placeholders of excluded directories are expected to not be available locally
+            let mut placeholders = vec![];
+            for path in &settings.excluded_directories {
+                if let Ok(directory) = index
+                    .get_item_by_path(path)
+                    .and_then(|item| item.get_directory())
+                {
+                    collect_files(&directory, &mut placeholders);
+                }
+            }
+            let placeholders = placeholders.iter().map(File::id).collect::<HashSet<_>>();
+
+            let mut report = IntegrityReport {
+                checked: files.len(),
+                issues: vec![],
+            };
+
+            for file in files {
+                let Some(kind) = verify_file(&ipfs, &file, false).await else {
+                    continue;
+                };
+
+                if (placeholders.contains(&file.id()) || cold_storage.is_evicted(file.id()))
+                    && matches!(
+                        kind,
+                        IntegrityIssueKind::Missing | IntegrityIssueKind::Unpinned
+                    )
+                {
+                    continue;
+                }
+
+                let repaired = refetch
+                    && matches!(
+                        kind,
+                        IntegrityIssueKind::Missing | IntegrityIssueKind::Unpinned
+                    )
+                    && verify_file(&ipfs, &file, true).await.is_none();
+
+                let path = match file.path() {
+                    "" => file.name(),
+                    path => path.to_string(),
+                };
+
+                tracing::warn!(%path, ?kind, repaired, "integrity issue found");
+                report.issues.push(IntegrityIssue {
+                    path,
+                    kind,
+                    repaired,
+                });
+            }
+
+            Ok(report)
+        }
+        .boxed()
+    }
+
+    fn set_directory_excluded(
+        &self,
+        path: &str,
+        excluded: bool,
+    ) -> Result<BoxFuture<'static, Result<(), Error>>, Error> {
+        let path = normalize_path(path);
+        let directory = self.open_directory(&path)?;
+        let ipfs = self.ipfs.clone();
+        let selective_sync = self.selective_sync.clone();
+
+        Ok(async move {
+            if !selective_sync
+                .set_directory_excluded(&path, excluded)
+                .await?
+            {
+                return Ok(());
+            }
+
+            let mut files = vec![];
+
+            match excluded {
+                true => collect_files(&directory, &mut files),
+                // The directory may still be excluded through one of its parents
+                false if selective_sync.is_directory_excluded(&path) => {}
+                false => {
+                    collect_synced_files(&directory, &path, &selective_sync, &mut files);
+                }
+            }
+
+            for file in files {
+                if let Err(e) = set_file_pinned(&ipfs, &file, !excluded).await {
+                    tracing::warn!(%path, file = %file.name(), error = %e, "unable to update pin of file");
+                }
+            }
+
+            Ok(())
+        }
+        .boxed())
+    }
+
+    fn cold_storage_report(&self) -> ColdStorageReport {
+        let mut files = vec![];
+        collect_files(&self.index, &mut files);
+        self.cold_storage.report(&files)
+    }
+
+    fn apply_cold_storage(&self) -> BoxFuture<'static, ColdStorageReport> {
+        let mut files = vec![];
+        collect_files(&self.index, &mut files);
+        let cold_storage = self.cold_storage.clone();
+
+        async move { cold_storage.apply(&files).await }.boxed()
+    }
+
+    fn set_pinned_locally(
+        &self,
+        path: &str,
+        pinned: bool,
+    ) -> Result<BoxFuture<'static, Result<(), Error>>, Error> {
+        let file = self
+            .root_directory()
+            .get_item_by_path(&normalize_path(path))
+            .and_then(|item| item.get_file())?;
+        let cold_storage = self.cold_storage.clone();
+
+        Ok(async move { cold_storage.set_pinned_locally(&file, pinned).await }.boxed())
+    }
+
+    fn hydrate(&self, path: &str) -> Result<BoxFuture<'static, Result<(), Error>>, Error> {
+        let item = self
+            .root_directory()
+            .get_item_by_path(&normalize_path(path))?;
+        let ipfs = self.ipfs.clone();
+
+        let mut files = vec![];
+        match item {
+            Item::File(file) => files.push(file),
+            Item::Directory(directory) => collect_files(&directory, &mut files),
+        }
+
+        Ok(async move {
+            for file in files {
+                set_file_pinned(&ipfs, &file, true).await?;
+            }
+            Ok(())
+        }
+        .boxed())
+    }
+
+    fn is_hydrated(&self, path: &str) -> Result<BoxFuture<'static, Result<bool, Error>>, Error> {
+        let file = self
+            .root_directory()
+            .get_item_by_path(&normalize_path(path))
+            .and_then(|item| item.get_file())?;
+        let cid = file_cid(&file)?;
+        let ipfs = self.ipfs.clone();
+
+        Ok(async move { Ok(ipfs.is_pinned(cid).await?) }.boxed())
+    }
+}
+
+fn collect_files(directory: &Directory, files: &mut Vec<File>) {
+    for item in directory.get_items() {
+        match item {
+            Item::File(file) => files.push(file),
+            Item::Directory(directory) => collect_files(&directory, files),
+        }
+    }
+}
+
+/// Same as [`collect_files`], but skips any subdirectory that is excluded from sync
+fn collect_synced_files(
+    directory: &Directory,
+    path: &str,
+    selective_sync: &SelectiveSync,
+    files: &mut Vec<File>,
+) {
+    for item in directory.get_items() {
+        match item {
+            Item::File(file) => files.push(file),
+            Item::Directory(directory) => {
+                let path = format!("{path}/{}", directory.name());
+                if !selective_sync.is_directory_excluded(&path) {
+                    collect_synced_files(&directory, &path, selective_sync, files);
+                }
+            }
+        }
+    }
+}
+
+pub(super) fn file_cid(file: &File) -> Result<Cid, Error> {
+    file.reference()
+        .and_then(|reference| reference.parse::<IpfsPath>().ok())
+        .and_then(|path| path.root().cid().copied())
+        .ok_or(Error::FileNotFound)
+}
+
+/// Pin or unpin the content of a file. Pinning fetches any missing blocks from the network
+async fn set_file_pinned(ipfs: &Ipfs, file: &File, pinned: bool) -> Result<(), Error> {
+    let cid = file_cid(file)?;
+
+    match (pinned, ipfs.is_pinned(cid).await?) {
+        (true, false) => ipfs.insert_pin(cid).recursive().await?,
+        (false, true) => ipfs.remove_pin(cid).recursive().await?,
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Check the content of a file against the index, returning the first issue found. With `fetch`, missing blocks are
+/// requested from the network and the content is pinned
+async fn verify_file(ipfs: &Ipfs, file: &File, fetch: bool) -> Option<IntegrityIssueKind> {
+    let Some(path) = file
+        .reference()
+        .and_then(|reference| reference.parse::<IpfsPath>().ok())
+    else {
+        return Some(IntegrityIssueKind::MissingReference);
+    };
+
+    let Some(cid) = path.root().cid().copied() else {
+        return Some(IntegrityIssueKind::MissingReference);
+    };
+
+    let mut stream = ipfs.cat_unixfs(path).set_local(!fetch);
+    let mut hasher = Sha256::new();
+    let mut size = 0;
+
+    while let Some(result) = stream.next().await {
+        match result {
+            Ok(bytes) => {
+                size += bytes.len();
+                hasher.update(&bytes);
+            }
+            Err(_) => return Some(IntegrityIssueKind::Missing),
+        }
+    }
+
+    if size != file.size() {
+        return Some(IntegrityIssueKind::Corrupted);
+    }
+
+    if let Some(expected) = file.hash().sha256() {
+        if !matches_sha256(&expected, &hasher.finalize()) {
+            return Some(IntegrityIssueKind::Corrupted);
+        }
+    }
+
+    let pinned = match fetch {
+        true => ipfs.insert_pin(cid).recursive().await.is_ok(),
+        false => ipfs.is_pinned(cid).await.unwrap_or_default(),
+    };
+
+    match pinned {
+        true => None,
+        false => Some(IntegrityIssueKind::Unpinned),
+    }
+}
+
+/// Compute the sha256 digest of the file at `path`, reading it in chunks
+pub(super) async fn file_sha256(path: impl AsRef<std::path::Path>) -> std::io::Result<Vec<u8>> {
+    let mut hasher = Sha256::new();
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        use tokio::io::AsyncReadExt;
+
+        let mut file = tokio::fs::File::open(path).await?;
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let read = file.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+        }
+    }
+
+    // files are kept whole in local storage
+    #[cfg(target_arch = "wasm32")]
+    hasher.update(fs::read(path).await?);
+
+    Ok(hasher.finalize().to_vec())
+}
+
+/// Hashes are stored either as hex or as a base58 encoded multihash, depending on how they were generated
+pub(super) fn matches_sha256(expected: &str, digest: &[u8]) -> bool {
+    let hex = digest
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+
+    if expected.eq_ignore_ascii_case(&hex) {
+        return true;
+    }
+
+    // sha2-256 multihash code followed by the length of the digest
+    let mut multihash = vec![0x12, 0x20];
+    multihash.extend_from_slice(digest);
+    expected == bs58::encode(multihash).into_string()
 }
 
 fn split_file_from_path(name: impl Into<String>) -> Result<(String, Option<String>), Error> {
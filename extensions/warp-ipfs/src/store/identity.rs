@@ -6,7 +6,8 @@ use std::{
 
 use bytes::Bytes;
 use chrono::{DateTime, Utc};
-use futures::{channel::oneshot, StreamExt};
+use futures::future::{BoxFuture, Future, Shared};
+use futures::{channel::oneshot, FutureExt, StreamExt};
 use futures_timeout::TimeoutExt;
 use futures_timer::Delay;
 use indexmap::IndexMap;
@@ -19,8 +20,14 @@ use tokio::sync::RwLock;
 use tracing::Span;
 use web_time::Instant;
 
-use warp::multipass::identity::{FriendRequest, Identifier, ShortId};
-use warp::multipass::GetIdentity;
+use warp::multipass::contact::{self, Contact, ContactFormat};
+use warp::multipass::identity::{
+    FriendRequest, Identifier, RelationshipChange, RelationshipHistoryEntry, ShortId,
+};
+use warp::multipass::notification::NotificationPreferences;
+use warp::multipass::share::{ShareCode, MAX_SHARE_CODE_HINTS};
+use warp::multipass::{GetIdentity, IdentityChange, IdentityChangeStream};
+use warp::subscription::SubscriptionOptions;
 use warp::{
     constellation::file::FileType,
     multipass::identity::{IdentityImage, Platform},
@@ -29,33 +36,51 @@ use warp::{
     crypto::{DIDKey, Ed25519KeyPair, Fingerprint, DID},
     error::Error,
     multipass::{
-        identity::{Identity, IdentityStatus, SHORT_ID_SIZE},
+        identity::{
+            FriendsPrivacy, Identity, IdentityActivity, IdentityStatus, ProfileFields,
+            SHORT_ID_SIZE,
+        },
         MultiPassEventKind,
     },
 };
 
 use super::payload::PayloadBuilder;
+use super::usage::UsageTracker;
 use super::{
     connected_to_peer,
+    device::{DeviceCertificate, DeviceKey, DeviceLink, MAX_DEVICES, MAX_REVOKED_DEVICES},
     document::{
-        cache::IdentityCache, identity::IdentityDocument, image_dag::get_image,
-        root::RootDocumentMap, ResolvedRootDocument, RootDocument,
+        cache::IdentityCache,
+        identity::{
+            validate_activity, validate_profile_fields, Feature, FriendsSummary, IdentityDocument,
+        },
+        image_dag::{get_image, get_image_variant, remove_image_variants},
+        root::RootDocumentMap,
+        ResolvedRootDocument, RootDocument,
     },
-    ecdh_encrypt,
+    ecdh_decrypt, ecdh_encrypt,
     event_subscription::EventSubscription,
+    inbox::PendingEvents,
     payload::PayloadMessage,
     phonebook::PhoneBook,
     protocols,
     queue::Queue,
     topics::IDENTITY_ANNOUNCEMENT,
-    MAX_IMAGE_SIZE, MAX_METADATA_ENTRIES, MAX_METADATA_KEY_LENGTH, MAX_METADATA_VALUE_LENGTH,
+    MAX_FRIEND_REQUEST_MESSAGE_LENGTH, MAX_IMAGE_SIZE, MAX_METADATA_ENTRIES,
+    MAX_METADATA_KEY_LENGTH, MAX_METADATA_VALUE_LENGTH,
 };
+use crate::inbox::PendingEventKind;
+use crate::shuttle::identity::organization::{MembershipCertificate, OrganizationRoster};
 use crate::shuttle::identity::protocol::{
-    LookupResponse, MailboxResponse, RegisterResponse, Response, SynchronizedResponse,
+    LookupResponse, MailboxResponse, Organization, OrganizationError, OrganizationResponse,
+    RegisterError, RegisterResponse, Response, SynchronizedResponse,
 };
+use crate::tasks::TaskTracker;
+use crate::time::Clock;
+use crate::usage::UsageCategory;
 use crate::{
     config::{self, Discovery as DiscoveryConfig},
-    store::{discovery::Discovery, topics::PeerTopic, DidExt, PeerIdExt},
+    store::{compression::Compression, discovery::Discovery, topics::PeerTopic, DidExt, PeerIdExt},
 };
 
 // TODO: Split into its own task
@@ -83,26 +108,157 @@ pub struct IdentityStore {
 
     config: config::Config,
 
+    usage: UsageTracker,
+
     span: Span,
 
     event: EventSubscription<MultiPassEventKind>,
+
+    identity_changes: EventSubscription<(DID, IdentityChange)>,
+
+    // previous metadata of identities that was requested after an update
+    pending_metadata: Arc<RwLock<HashMap<DID, Option<Cid>>>>,
+
+    // friend count and mutual friends shared with us by other identities
+    friends_summaries: Arc<RwLock<HashMap<DID, FriendsSummary>>>,
+
+    // lookups of identities from shuttle nodes that are in flight
+    inflight_lookups: Arc<parking_lot::Mutex<InflightLookups>>,
+
+    // last roster of the organization that was applied
+    organization_roster: Arc<RwLock<Option<OrganizationRoster>>>,
+
+    // key of this device, certified by the identity
+    device: DeviceKey,
+
+    // mentions and friend requests not acknowledged by the application
+    pending_events: PendingEvents,
+
+    tasks: TaskTracker,
+}
+
+type SharedLookup<T> = Shared<BoxFuture<'static, Arc<Vec<T>>>>;
+
+struct InflightLookups<T = IdentityDocument> {
+    next_id: u64,
+    lookups: HashMap<DID, (u64, SharedLookup<T>)>,
+}
+
+impl<T> Default for InflightLookups<T> {
+    fn default() -> Self {
+        Self {
+            next_id: 0,
+            lookups: HashMap::new(),
+        }
+    }
+}
+
+/// Lookup `dids`, sharing the lookup of any did that is already in flight. `request` is only called with the dids
+/// that are not in flight, and `did_of` is used so that only the items of the requested dids are returned
+async fn coalesce_lookup<T, F, Fut>(
+    inflight: &Arc<parking_lot::Mutex<InflightLookups<T>>>,
+    dids: HashSet<DID>,
+    did_of: fn(&T) -> &DID,
+    request: F,
+) -> Vec<T>
+where
+    T: Clone + Send + Sync + 'static,
+    F: FnOnce(Vec<DID>) -> Fut,
+    Fut: Future<Output = Vec<T>> + Send + 'static,
+{
+    let mut pending = HashMap::new();
+
+    {
+        let lookups = &mut *inflight.lock();
+
+        let mut fresh = vec![];
+        for did in &dids {
+            match lookups.lookups.get(did) {
+                Some((id, lookup)) => {
+                    pending.entry(*id).or_insert_with(|| lookup.clone());
+                }
+                None => fresh.push(did.clone()),
+            }
+        }
+
+        if !fresh.is_empty() {
+            lookups.next_id += 1;
+            let id = lookups.next_id;
+
+            let inflight = inflight.clone();
+            let list = fresh.clone();
+            let request = request(fresh.clone());
+            let lookup = async move {
+                let items = request.await;
+
+                let lookups = &mut *inflight.lock();
+                for did in &list {
+                    if lookups
+                        .lookups
+                        .get(did)
+                        .is_some_and(|(current, _)| *current == id)
+                    {
+                        lookups.lookups.remove(did);
+                    }
+                }
+
+                Arc::new(items)
+            }
+            .boxed()
+            .shared();
+
+            for did in fresh {
+                lookups.lookups.insert(did, (id, lookup.clone()));
+            }
+
+            pending.insert(id, lookup);
+        }
+    }
+
+    let mut found = HashSet::new();
+
+    futures::future::join_all(pending.into_values())
+        .await
+        .iter()
+        .flat_map(|items| items.iter())
+        .filter(|item| dids.contains(did_of(item)) && found.insert(did_of(item).clone()))
+        .cloned()
+        .collect()
 }
 
 #[derive(Debug, Clone, Eq, Serialize, Deserialize)]
 #[serde(tag = "direction", rename_all = "lowercase")]
 pub enum Request {
-    In { did: DID, date: DateTime<Utc> },
-    Out { did: DID, date: DateTime<Utc> },
+    In {
+        did: DID,
+        date: DateTime<Utc>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        message: Option<String>,
+    },
+    Out {
+        did: DID,
+        date: DateTime<Utc>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        message: Option<String>,
+    },
 }
 
 impl Request {
     pub fn request_in(did: DID) -> Self {
         let date = Utc::now();
-        Request::In { did, date }
+        Request::In {
+            did,
+            date,
+            message: None,
+        }
     }
     pub fn request_out(did: DID) -> Self {
         let date = Utc::now();
-        Request::Out { did, date }
+        Request::Out {
+            did,
+            date,
+            message: None,
+        }
     }
 }
 
@@ -149,6 +305,37 @@ impl Request {
             Request::Out { date, .. } => *date,
         }
     }
+
+    pub fn message(&self) -> Option<&str> {
+        match self {
+            Request::In { message, .. } => message.as_deref(),
+            Request::Out { message, .. } => message.as_deref(),
+        }
+    }
+
+    /// Whether the request is outgoing and was sent more than `ttl` before `now`
+    pub fn is_expired_at(&self, ttl: Duration, now: DateTime<Utc>) -> bool {
+        let Request::Out { date, .. } = self else {
+            return false;
+        };
+
+        chrono::Duration::from_std(ttl)
+            .ok()
+            .and_then(|ttl| date.checked_add_signed(ttl))
+            .is_some_and(|expiry| expiry <= now)
+    }
+}
+
+impl From<Request> for FriendRequest {
+    fn from(request: Request) -> Self {
+        let (did, date, message) = match request {
+            Request::In { did, date, message } => (did, date, message),
+            Request::Out { did, date, message } => (did, date, message),
+        };
+        let mut request = FriendRequest::new(did, Some(date));
+        request.set_message(message);
+        request
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Hash, Eq)]
@@ -180,8 +367,15 @@ pub struct RequestResponsePayload {
     pub event: Event,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub created: Option<DateTime<Utc>>,
+    /// Greeting message encrypted for the recipient of the request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<Vec<u8>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub signature: Option<Vec<u8>>,
+    /// Signature over the payload along with `message` and `signature`. Only set when a greeting message is
+    /// attached, leaving `signature` to cover the fields known to older peers, which drop `message`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_signature: Option<Vec<u8>>,
 }
 
 impl PartialOrd for RequestResponsePayload {
@@ -222,26 +416,100 @@ impl RequestResponsePayload {
             sender,
             event,
             created: None,
+            message: None,
             signature: None,
+            message_signature: None,
+        }
+    }
+
+    /// Attach a greeting message that is encrypted for the recipient.
+    /// Note: The payload should be signed after the message is set
+    pub fn set_message(
+        &mut self,
+        keypair: &Keypair,
+        recipient: &DID,
+        message: &str,
+    ) -> Result<(), Error> {
+        let message = message.trim();
+        if message.is_empty() || message.len() > MAX_FRIEND_REQUEST_MESSAGE_LENGTH {
+            return Err(Error::InvalidLength {
+                context: "message".into(),
+                current: message.len(),
+                minimum: Some(1),
+                maximum: Some(MAX_FRIEND_REQUEST_MESSAGE_LENGTH),
+            });
+        }
+
+        self.message = Some(ecdh_encrypt(keypair, Some(recipient), message)?);
+        Ok(())
+    }
+
+    /// Decrypt the greeting message, if any, using the keypair of either the sender or recipient
+    pub fn decrypt_message(&self, keypair: &Keypair, remote: &DID) -> Option<String> {
+        let data = self.message.as_ref()?;
+
+        let message = ecdh_decrypt(keypair, Some(remote), data)
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok());
+
+        match message {
+            Some(message) if message.len() <= MAX_FRIEND_REQUEST_MESSAGE_LENGTH => Some(message),
+            _ => {
+                tracing::warn!(sender = %self.sender, "unable to decrypt friend request message or message is invalid");
+                None
+            }
         }
     }
 
     pub fn sign(mut self, keypair: &Keypair) -> Result<Self, Error> {
         self.signature = None;
+        self.message_signature = None;
         self.created = Some(Utc::now());
-        let bytes = serde_json::to_vec(&self)?;
-        let signature = keypair.sign(&bytes).expect("not RSA");
-        self.signature = Some(signature);
+
+        let bytes = self.signing_bytes(false)?;
+        self.signature = Some(keypair.sign(&bytes).expect("not RSA"));
+
+        if self.message.is_some() {
+            let bytes = self.signing_bytes(true)?;
+            self.message_signature = Some(keypair.sign(&bytes).expect("not RSA"));
+        }
+
         Ok(self)
     }
 
+    // Bytes covered by `signature`, which match the serialization of the payload by peers that do not know of
+    // `message`, or by `message_signature`, which cover the whole payload including `signature`
+    fn signing_bytes(&self, with_message: bool) -> Result<Vec<u8>, Error> {
+        let mut payload = self.clone();
+        payload.message_signature = None;
+
+        if !with_message {
+            payload.message = None;
+            payload.signature = None;
+        }
+
+        serde_json::to_vec(&payload).map_err(Error::from)
+    }
+
     pub fn verify(&self) -> Result<(), Error> {
-        let mut doc = self.clone();
-        let signature = doc.signature.take().ok_or(Error::InvalidSignature)?;
-        let bytes = serde_json::to_vec(&doc)?;
-        let sender_pk = doc.sender.to_public_key()?;
+        let sender_pk = self.sender.to_public_key()?;
+
+        let signature = self.signature.as_ref().ok_or(Error::InvalidSignature)?;
+        let bytes = self.signing_bytes(false)?;
+        if !sender_pk.verify(&bytes, signature) {
+            return Err(Error::InvalidSignature);
+        }
 
-        if !sender_pk.verify(&bytes, &signature) {
+        if self.message.is_none() {
+            return Ok(());
+        }
+
+        let signature = self
+            .message_signature
+            .as_ref()
+            .ok_or(Error::InvalidSignature)?;
+        let bytes = self.signing_bytes(true)?;
+        if !sender_pk.verify(&bytes, signature) {
             return Err(Error::InvalidSignature);
         }
 
@@ -323,10 +591,14 @@ impl IdentityStore {
     #[allow(clippy::too_many_arguments)]
     pub async fn new(
         ipfs: &Ipfs,
+        keypair: &Keypair,
         config: &config::Config,
         tx: EventSubscription<MultiPassEventKind>,
         phonebook: &PhoneBook,
         discovery: &Discovery,
+        device: Keypair,
+        usage: &UsageTracker,
+        tasks: &TaskTracker,
         span: &Span,
     ) -> Result<Self, Error> {
         let config = config.clone();
@@ -335,14 +607,29 @@ impl IdentityStore {
 
         let event = tx.clone();
 
-        let root_document = RootDocumentMap::new(ipfs, None).await;
+        let root_document = match config.audit_replica() {
+            // the node of a replica does not use the key of the account
+            Some(root) => RootDocumentMap::replica(ipfs, Some(keypair.clone()), root).await?,
+            None => {
+                let root_document =
+                    RootDocumentMap::new(ipfs, None, config.store_setting().document_codec).await;
+                if config.store_setting().migrate_document_payloads {
+                    root_document.migrate_payloads().await;
+                }
+                root_document
+            }
+        };
 
         let did_key = root_document
             .keypair()
             .to_did()
             .expect("valid ed25519 keypair");
 
-        let queue = Queue::new(ipfs.clone(), &root_document, discovery.clone());
+        let device = DeviceKey::load(ipfs, root_document.keypair(), device).await?;
+
+        let pending_events = PendingEvents::load(ipfs).await;
+
+        let queue = Queue::new(ipfs.clone(), &root_document, discovery.clone(), tasks);
 
         let signal = Default::default();
 
@@ -353,35 +640,77 @@ impl IdentityStore {
             discovery: discovery.clone(),
             config,
             event,
+            identity_changes: EventSubscription::new(),
+            pending_metadata: Default::default(),
+            inflight_lookups: Default::default(),
+            friends_summaries: Default::default(),
+            organization_roster: Default::default(),
+            device,
+            pending_events,
             did_key,
             queue,
             phonebook: phonebook.clone(),
             signal,
+            usage: usage.clone(),
+            tasks: tasks.clone(),
             span: span.clone(),
         };
 
+        let audit = store.is_replica();
+
+        if audit {
+            // The replica is only usable with the key of the account that exported the root document
+            let identity = store.own_identity_document().await?;
+            if identity.did.ne(&store.did_key) {
+                return Err(Error::PublicKeyInvalid);
+            }
+        }
+
         // Move shuttle logic logic into its own task
-        // TODO: Maybe push into a joinset or futureunordered and poll?
-        async_rt::task::dispatch({
-            let mut store = store.clone();
-            async move {
-                if let Ok(ident) = store.own_identity().await {
-                    tracing::info!(did = %ident.did_key(), "Identity loaded");
-                    match store.is_registered().await.is_ok() {
-                        true => {
-                            if let Err(e) = store.fetch_mailbox().await {
-                                tracing::warn!(error = %e, "Unable to fetch or process mailbox");
-                            }
+        // Note: A replica does not register or fetch the mailbox, since the mailbox is cleared once fetched
+        if !audit {
+            store.tasks.spawn({
+                let mut store = store.clone();
+                async move {
+                    if let Ok(ident) = store.own_identity().await {
+                        tracing::info!(did = %ident.did_key(), "Identity loaded");
+                        if let Err(e) = store.migrate_handle().await {
+                            tracing::warn!(did = %ident.did_key(), error = %e, "Unable to migrate identity handle");
+                        }
+                        if let Err(e) = store.register_device().await {
+                            tracing::warn!(did = %ident.did_key(), error = %e, "Unable to register device");
                         }
-                        false => {
-                            if let Err(e) = store.register().await {
-                                tracing::warn!(did = %ident.did_key(), error = %e, "Unable to register identity");
+                        match store.is_registered().await.is_ok() {
+                            true => {
+                                if let Err(e) = store.fetch_mailbox().await {
+                                    tracing::warn!(error = %e, "Unable to fetch or process mailbox");
+                                }
+                            }
+                            false => {
+                                if let Err(e) = store.register().await {
+                                    tracing::warn!(did = %ident.did_key(), error = %e, "Unable to register identity");
+                                }
                             }
                         }
                     }
                 }
-            }
-        });
+            });
+        }
+
+        if let Some(organization) = store.config.store_setting().organization.clone() {
+            store.tasks.spawn({
+                let store = store.clone();
+                async move {
+                    store.organization_task(organization).await;
+                }
+            });
+        }
+
+        // A replica does not take part in discovery nor answer other peers, which would require publishing on behalf
+        // of the account
+        if audit {
+            return Ok(store);
+        }
 
         store.discovery.start().await?;
 
@@ -412,7 +741,7 @@ impl IdentityStore {
             }
         }
 
-        async_rt::task::dispatch({
+        store.tasks.spawn({
             let mut store = store.clone();
             async move {
                 let event_stream = store
@@ -445,6 +774,20 @@ impl IdentityStore {
 
                 let mut tick = Delay::new(interval);
 
+                // root document is pulled from shuttle when the identity is used from several devices
+                let sync_root_document = matches!(
+                    store.discovery.discovery_config(),
+                    DiscoveryConfig::Shuttle { .. }
+                );
+                let sync_interval = Duration::from_secs(60);
+                let mut sync_tick = Delay::new(sync_interval);
+
+                let request_ttl = store.config.store_setting().friend_request_ttl;
+                let expiry_interval = Duration::from_secs(60);
+                // the interval is measured with the clock of the store, so requests expire once it is advanced
+                let mut expiry_tick = Delay::new(Duration::ZERO);
+                let mut last_expiry = None;
+
                 loop {
                     tokio::select! {
                         biased;
@@ -486,6 +829,8 @@ impl IdentityStore {
                                 false => identity.did.clone()
                             };
 
+                            let announced = identity.modified;
+
                             let event = IdentityEvent::Receive {
                                 option: ResponseOption::Identity { identity },
                             };
@@ -503,6 +848,23 @@ impl IdentityStore {
                             //     }
                             // }
 
+                            store.usage.record_received(UsageCategory::IdentitySync, None, Some(&from_did), message.data.len());
+
+                            // Announcements only carry the public profile fields, so we request the identity
+                            // from friends directly to not lose the fields that are only shared with friends.
+                            // Announcements are received repeatedly, so the identity is only requested if it
+                            // changed since it was cached
+                            if store.is_friend(&from_did).await.unwrap_or_default() {
+                                let cached = store.identity_cache.get(&from_did).await.ok();
+                                if cached.is_some_and(|cached| cached.modified >= announced) {
+                                    continue;
+                                }
+
+                                if store.request(&from_did, RequestOption::Identity).await.is_ok() {
+                                    continue;
+                                }
+                            }
+
                             //Ignore requesting images if there is a change for now.
                             if let Err(e) = store.process_message(&from_did, event, false).await {
                                tracing::error!("Failed to process identity message from {from_did}: {e}");
@@ -526,6 +888,8 @@ impl IdentityStore {
 
                             tracing::info!("Received event from {in_did}");
 
+                            store.usage.record_received(UsageCategory::IdentitySync, None, Some(&in_did), message.data.len());
+
                             let payload: PayloadMessage<IdentityEvent> = match PayloadMessage::from_bytes(&message.data) {
                                 Ok(p) => p,
                                 Err(e) => {
@@ -604,6 +968,25 @@ impl IdentityStore {
                             }
                             tick.reset(interval)
                         }
+                        _ = &mut sync_tick => {
+                            let linked = store.own_devices().await.map(|devices| devices.len() > 1).unwrap_or_default();
+                            if sync_root_document && linked {
+                                if let Err(e) = store.synchronize_root_document().await {
+                                    tracing::warn!(error = %e, "Unable to synchronize root document");
+                                }
+                            }
+                            sync_tick.reset(sync_interval)
+                        }
+                        _ = &mut expiry_tick => {
+                            let due = !last_expiry.is_some_and(|last| store.clock().elapsed(last) < expiry_interval);
+                            if let Some(ttl) = request_ttl.filter(|_| due) {
+                                last_expiry = Some(store.clock().instant());
+                                if let Err(e) = store.expire_requests(ttl).await {
+                                    tracing::warn!(error = %e, "Unable to expire friend requests");
+                                }
+                            }
+                            expiry_tick.reset(Duration::from_secs(1))
+                        }
                     }
                 }
             }
@@ -612,6 +995,15 @@ impl IdentityStore {
         Ok(store)
     }
 
+    pub fn config(&self) -> &config::Config {
+        &self.config
+    }
+
+    /// Whether the store is a read-only replica of the account, in which case nothing is published on its behalf
+    pub fn is_replica(&self) -> bool {
+        self.config.audit_replica().is_some()
+    }
+
     pub(crate) fn phonebook(&self) -> &PhoneBook {
         &self.phonebook
     }
@@ -620,6 +1012,11 @@ impl IdentityStore {
         self.did_key.clone()
     }
 
+    /// Time source shared by the stores
+    pub fn clock(&self) -> &Clock {
+        self.config.clock()
+    }
+
     //TODO: Implement Errors
     #[tracing::instrument(skip(self, data, signal))]
     async fn check_request_message(
@@ -718,9 +1115,36 @@ impl IdentityStore {
                 } else {
                     let from = data.sender.clone();
 
+                    // a request that was resent replaces the one previously received, while a request that was
+                    // delivered again is only acknowledged
+                    if let Some(existing) = list.iter().find(|request| {
+                        request.r#type() == RequestType::Incoming && from.eq(request.did())
+                    }) {
+                        if !data
+                            .created
+                            .is_some_and(|created| created > existing.date())
+                        {
+                            tracing::debug!(%from, "Request already received. Remitting response");
+
+                            let payload = RequestResponsePayload::new(
+                                self.root_document.keypair(),
+                                Event::Response,
+                            )?;
+
+                            return self
+                                .broadcast_request(&data.sender, &payload, false, false)
+                                .await;
+                        }
+
+                        self.root_document.remove_request(existing).await?;
+                    }
+
+                    let message = data.decrypt_message(self.root_document.keypair(), &from);
+
                     let req = Request::In {
                         did: from.clone(),
                         date: data.created.unwrap_or_else(Utc::now),
+                        message: message.clone(),
                     };
 
                     self.root_document.add_request(&req).await?;
@@ -739,6 +1163,7 @@ impl IdentityStore {
                     self.emit_event(MultiPassEventKind::FriendRequestReceived {
                         from,
                         date: req.date(),
+                        message,
                     })
                     .await;
                 }
@@ -890,8 +1315,13 @@ impl IdentityStore {
                 .from_ipfs(&self.ipfs)
                 .await?;
             let bytes = payload.to_bytes()?;
+            let size = bytes.len();
             match self.ipfs.pubsub_publish(IDENTITY_ANNOUNCEMENT, bytes).await {
-                Ok(_) => tracing::debug!("identity announced to mesh"),
+                Ok(_) => {
+                    self.usage
+                        .record_sent(UsageCategory::IdentitySync, None, None, size);
+                    tracing::debug!("identity announced to mesh")
+                }
                 Err(_) => tracing::warn!("unable to announce identity to mesh"),
             }
         }
@@ -926,7 +1356,10 @@ impl IdentityStore {
             .contains(&out_peer_id)
         {
             let timer = Instant::now();
+            let size = bytes.len();
             self.ipfs.pubsub_publish(out_did.events(), bytes).await?;
+            self.usage
+                .record_sent(UsageCategory::IdentitySync, None, Some(out_did), size);
             let end = timer.elapsed();
             tracing::info!(to = %out_did, event = ?event, "Event sent");
             tracing::trace!("Took {}ms to send event", end.as_millis());
@@ -952,6 +1385,8 @@ impl IdentityStore {
         let is_blocked_by = self.is_blocked_by(out_did).await.unwrap_or_default();
 
         identity.metadata.platform = Some(self.own_platform());
+        identity.metadata.compression = Compression::supported();
+        identity.metadata.features = Feature::supported();
 
         let metadata = identity.metadata;
         identity.metadata = Default::default();
@@ -963,6 +1398,30 @@ impl IdentityStore {
             identity.metadata = metadata;
         }
 
+        if is_friend {
+            let profile = self.root_document.get_profile_fields().await?;
+            identity.profile = (!profile.is_empty()).then_some(profile);
+        }
+
+        // The summary is always included when pushing directly so the recipient is able to tell
+        // when we stop sharing, however the friends list itself is never included
+        let summary = match is_blocked || is_blocked_by {
+            true => FriendsSummary::default(),
+            false => {
+                let privacy = self.root_document.get_friends_privacy().await?;
+                let friends = self.friends_list().await?;
+                FriendsSummary::new(
+                    self.root_document.keypair(),
+                    &friends,
+                    out_did,
+                    is_friend,
+                    &privacy,
+                )?
+            }
+        };
+
+        identity.friends_summary = Some(summary);
+
         let kp_did = self.root_document.keypair();
 
         let payload = identity.sign(kp_did)?;
@@ -986,7 +1445,10 @@ impl IdentityStore {
             .contains(&out_peer_id)
         {
             let timer = Instant::now();
+            let size = bytes.len();
             self.ipfs.pubsub_publish(out_did.events(), bytes).await?;
+            self.usage
+                .record_sent(UsageCategory::IdentitySync, None, Some(out_did), size);
             let end = timer.elapsed();
             tracing::info!(to = %out_did, event = ?event, "Event sent");
             tracing::info!("Took {}ms to send event", end.as_millis());
@@ -1043,7 +1505,10 @@ impl IdentityStore {
             .contains(&out_peer_id)
         {
             let timer = Instant::now();
+            let size = bytes.len();
             self.ipfs.pubsub_publish(out_did.events(), bytes).await?;
+            self.usage
+                .record_sent(UsageCategory::IdentitySync, None, Some(out_did), size);
             let end = timer.elapsed();
             tracing::info!(to = %out_did, event = ?event, "Event sent");
             tracing::trace!("Took {}ms to send event", end.as_millis());
@@ -1122,7 +1587,10 @@ impl IdentityStore {
             .contains(&out_peer_id)
         {
             let timer = Instant::now();
+            let size = bytes.len();
             self.ipfs.pubsub_publish(out_did.events(), bytes).await?;
+            self.usage
+                .record_sent(UsageCategory::IdentitySync, None, Some(out_did), size);
             let end = timer.elapsed();
             tracing::info!(to = %out_did, event = ?event, "Event sent");
             tracing::trace!("Took {}ms to send event", end.as_millis());
@@ -1178,7 +1646,10 @@ impl IdentityStore {
             .contains(&out_peer_id)
         {
             let timer = Instant::now();
+            let size = bytes.len();
             self.ipfs.pubsub_publish(out_did.events(), bytes).await?;
+            self.usage
+                .record_sent(UsageCategory::IdentitySync, None, Some(out_did), size);
             let end = timer.elapsed();
             tracing::info!("Event sent to {out_did}");
             tracing::trace!("Took {}ms to send event", end.as_millis());
@@ -1210,7 +1681,7 @@ impl IdentityStore {
                 }
             },
             IdentityEvent::Receive {
-                option: ResponseOption::Identity { identity },
+                option: ResponseOption::Identity { mut identity },
             } => {
                 //TODO: Validate public key against peer that sent it
                 // let _pk = did_to_libp2p_pub(&raw_object.did)?;
@@ -1234,8 +1705,25 @@ impl IdentityStore {
                     }
                 }
 
+                // Only set when pushed directly to us. Identities that are announced or resolved
+                // through other means will not include a summary so the existing one is kept
+                if let Some(summary) = identity.friends_summary.take() {
+                    let summaries = &mut *self.friends_summaries.write().await;
+                    match summary.is_empty() {
+                        true => summaries.remove(&identity.did),
+                        false => summaries.insert(identity.did.clone(), summary),
+                    };
+                }
+
                 let previous_identity = self.identity_cache.get(&identity.did).await.ok();
 
+                if let Some(document) = previous_identity.as_ref() {
+                    if document.handle.is_some() && document.handle() != identity.handle() {
+                        tracing::warn!(did = %identity.did, "Identity handle cannot be changed. Ignoring update");
+                        return Ok(());
+                    }
+                }
+
                 self.identity_cache.insert(&identity).await?;
 
                 match previous_identity {
@@ -1249,11 +1737,22 @@ impl IdentityStore {
                             })
                             .await;
 
+                            for change in document.changes(&identity) {
+                                self.identity_changes
+                                    .emit((document.did.clone(), change))
+                                    .await;
+                            }
+
                             if !exclude_images {
                                 if document.metadata.arb_data != identity.metadata.arb_data
                                     && identity.metadata.arb_data.is_some()
                                 {
                                     if !self.config.store_setting().fetch_over_bitswap {
+                                        self.pending_metadata
+                                            .write()
+                                            .await
+                                            .insert(in_did.clone(), document.metadata.arb_data);
+
                                         if let Err(e) =
                                             self.request(in_did, RequestOption::Metadata).await
                                         {
@@ -1264,7 +1763,8 @@ impl IdentityStore {
                                     } else {
                                         let identity_meta_cid =
                                             identity.metadata.arb_data.expect("Cid is provided");
-                                        async_rt::task::spawn({
+                                        let previous_meta_cid = document.metadata.arb_data;
+                                        self.tasks.spawn({
                                             let ipfs = self.ipfs.clone();
                                             let store = self.clone();
                                             let did = in_did.clone();
@@ -1273,6 +1773,13 @@ impl IdentityStore {
                                                 ipfs.get_dag(identity_meta_cid)
                                                     .provider(peer_id)
                                                     .await?;
+                                                store
+                                                    .emit_metadata_changes(
+                                                        &did,
+                                                        previous_meta_cid,
+                                                        identity_meta_cid,
+                                                    )
+                                                    .await;
                                                 store
                                                     .emit_event(
                                                         MultiPassEventKind::IdentityUpdate { did },
@@ -1294,6 +1801,12 @@ impl IdentityStore {
                                         identity.did
                                     );
 
+                                    if let Some(old_cid) = document.metadata.profile_picture {
+                                        if Some(old_cid) != identity.metadata.profile_banner {
+                                            remove_image_variants(&self.ipfs, old_cid).await;
+                                        }
+                                    }
+
                                     if !self.config.store_setting().fetch_over_bitswap {
                                         if let Err(e) = self
                                             .request(
@@ -1314,7 +1827,7 @@ impl IdentityStore {
                                             .metadata
                                             .profile_picture
                                             .expect("Cid is provided");
-                                        async_rt::task::spawn({
+                                        self.tasks.spawn({
                                             let ipfs = self.ipfs.clone();
                                             let store = self.clone();
                                             let did = in_did.clone();
@@ -1357,6 +1870,12 @@ impl IdentityStore {
                                         identity.did
                                     );
 
+                                    if let Some(old_cid) = document.metadata.profile_banner {
+                                        if Some(old_cid) != identity.metadata.profile_picture {
+                                            remove_image_variants(&self.ipfs, old_cid).await;
+                                        }
+                                    }
+
                                     if !self.config.store_setting().fetch_over_bitswap {
                                         if let Err(e) = self
                                             .request(
@@ -1377,7 +1896,7 @@ impl IdentityStore {
                                             .metadata
                                             .profile_banner
                                             .expect("Cid is provided");
-                                        async_rt::task::dispatch({
+                                        self.tasks.spawn({
                                             let ipfs = self.ipfs.clone();
                                             let did = in_did.clone();
                                             let store = self.clone();
@@ -1435,7 +1954,7 @@ impl IdentityStore {
                                         .await?;
                                 } else {
                                     if let Some(picture) = picture {
-                                        async_rt::task::dispatch({
+                                        self.tasks.spawn({
                                             let ipfs = self.ipfs.clone();
                                             let did = in_did.clone();
                                             let store = self.clone();
@@ -1469,7 +1988,7 @@ impl IdentityStore {
                                         });
                                     }
                                     if let Some(banner) = banner {
-                                        async_rt::task::dispatch({
+                                        self.tasks.spawn({
                                             let store = self.clone();
                                             let ipfs = self.ipfs.clone();
 
@@ -1518,7 +2037,7 @@ impl IdentityStore {
                 if cache.metadata.profile_picture == Some(cid)
                     || cache.metadata.profile_banner == Some(cid)
                 {
-                    async_rt::task::dispatch({
+                    self.tasks.spawn({
                         let store = self.clone();
                         let did = in_did.clone();
                         async move {
@@ -1552,12 +2071,40 @@ impl IdentityStore {
                     }
                 }
 
-                self.ipfs.put_dag(data).await?;
+                let cid = self.ipfs.put_dag(data).await?;
+
+                let previous = self.pending_metadata.write().await.remove(in_did);
+
+                if let Some(previous_cid) = previous {
+                    self.emit_metadata_changes(in_did, previous_cid, cid).await;
+                }
             }
         };
         Ok(())
     }
 
+    async fn emit_metadata_changes(&self, did: &DID, previous: Option<Cid>, current: Cid) {
+        let load = |cid: Option<Cid>| async move {
+            match cid {
+                Some(cid) => self
+                    .ipfs
+                    .get_dag(cid)
+                    .local()
+                    .deserialized::<IndexMap<String, String>>()
+                    .await
+                    .unwrap_or_default(),
+                None => IndexMap::new(),
+            }
+        };
+
+        let previous = load(previous).await;
+        let current = load(Some(current)).await;
+
+        for change in metadata_changes(&previous, &current) {
+            self.identity_changes.emit((did.clone(), change)).await;
+        }
+    }
+
     fn own_platform(&self) -> Platform {
         if cfg!(any(
             target_os = "windows",
@@ -1607,6 +2154,10 @@ impl IdentityStore {
             }
         }
 
+        if let Err(e) = self.migrate_handle().await {
+            tracing::warn!(error = %e, "Unable to migrate identity handle");
+        }
+
         match self.is_registered().await.is_ok() {
             true => {
                 if let Err(e) = self.fetch_mailbox().await {
@@ -1643,12 +2194,15 @@ impl IdentityStore {
             .map(str::to_string)
             .unwrap_or_else(warp::multipass::generator::generate_name);
 
+        self.is_username_available(&username).await?;
+
         let fingerprint = public_key.fingerprint();
         let bytes = fingerprint.as_bytes();
 
         let time = Utc::now();
 
         let identity = IdentityDocument {
+            handle: Some(username.clone()),
             username,
             short_id: bytes[bytes.len() - SHORT_ID_SIZE..]
                 .try_into()
@@ -1657,9 +2211,15 @@ impl IdentityStore {
             created: time,
             modified: time,
             status_message: None,
+            activity: None,
+            profile: None,
+            friends_summary: None,
             metadata: Default::default(),
+            devices: vec![],
+            revoked_devices: vec![],
             version: Default::default(),
             signature: None,
+            extended_signature: None,
         };
 
         let identity = identity.sign(self.root_document.keypair())?;
@@ -1705,6 +2265,10 @@ impl IdentityStore {
             }
         }
 
+        if let Err(e) = self.migrate_handle().await {
+            tracing::warn!(error = %e, "Unable to migrate identity handle");
+        }
+
         match self.is_registered().await.is_ok() {
             true => {
                 if let Err(e) = self.fetch_mailbox().await {
@@ -1840,7 +2404,7 @@ impl IdentityStore {
         Ok(())
     }
 
-    async fn is_registered(&self) -> Result<(), Error> {
+    pub(crate) async fn is_registered(&self) -> Result<(), Error> {
         if let DiscoveryConfig::Shuttle { addresses } = self.discovery.discovery_config() {
             if addresses.is_empty() {
                 return Err(Error::Other);
@@ -1898,7 +2462,7 @@ impl IdentityStore {
         Err(Error::IdentityDoesntExist)
     }
 
-    async fn register(&self) -> Result<(), Error> {
+    pub(crate) async fn register(&self) -> Result<(), Error> {
         if let DiscoveryConfig::Shuttle { addresses } = self.discovery.discovery_config() {
             if addresses.is_empty() {
                 return Err(Error::Other);
@@ -1940,6 +2504,9 @@ impl IdentityStore {
 
                 match payload.message(None)? {
                     Response::RegisterResponse(RegisterResponse::Ok) => return Ok(()),
+                    Response::RegisterResponse(RegisterResponse::Error(
+                        RegisterError::UsernameTaken,
+                    )) => return Err(Error::UsernameTaken),
                     Response::InvalidPayload => {
                         tracing::error!(%peer_id, "request was invalid");
                         continue;
@@ -1957,71 +2524,340 @@ impl IdentityStore {
         Ok(())
     }
 
-    async fn fetch_mailbox(&mut self) -> Result<(), Error> {
-        if let DiscoveryConfig::Shuttle { addresses } = self.discovery.discovery_config() {
-            if addresses.is_empty() {
-                return Err(Error::Other);
+    /// Keep the friends list in line with the roster of the organization, applying the roster fetched from the
+    /// directory and any roster pushed by the directory afterward
+    async fn organization_task(mut self, organization: DID) {
+        let Ok(directory) = organization.to_peer_id() else {
+            return;
+        };
+
+        let roster_stream = match self
+            .ipfs
+            .pubsub_subscribe(organization.organization())
+            .await
+        {
+            Ok(stream) => stream,
+            Err(e) => {
+                tracing::error!(%organization, error = %e, "unable to subscribe to organization roster");
+                return;
             }
+        };
 
-            let payload = PayloadBuilder::new(
-                self.root_document.keypair(),
-                crate::shuttle::identity::protocol::Request::from(
-                    crate::shuttle::identity::protocol::Mailbox::FetchAll,
-                ),
-            )
-            .build()?;
+        futures::pin_mut!(roster_stream);
 
-            let bytes = payload.to_bytes().expect("valid deserialization");
+        match self.fetch_organization_roster(&organization).await {
+            Ok(roster) => {
+                if let Err(e) = self.apply_organization_roster(&organization, roster).await {
+                    tracing::warn!(%organization, error = %e, "unable to apply organization roster");
+                }
+            }
+            Err(e) => {
+                tracing::warn!(%organization, error = %e, "unable to fetch organization roster");
+            }
+        }
 
-            for peer_id in addresses.iter().filter_map(|addr| addr.peer_id()) {
-                let response = match self
-                    .ipfs
-                    .send_request(peer_id, (protocols::SHUTTLE_IDENTITY, bytes.clone()))
-                    .await
-                {
-                    Ok(response) => response,
-                    Err(e) => {
-                        tracing::warn!(error = %e, %peer_id, "unable to send request to shuttle node");
-                        continue;
-                    }
-                };
+        while let Some(message) = roster_stream.next().await {
+            let Ok(payload) = PayloadMessage::<OrganizationRoster>::from_bytes(&message.data)
+            else {
+                continue;
+            };
 
-                let payload: PayloadMessage<crate::shuttle::identity::protocol::Response> =
-                    match PayloadMessage::from_bytes(&response) {
-                        Ok(payload) => payload,
-                        Err(e) => {
-                            tracing::error!(error = %e, %peer_id, "unable to process payload");
-                            continue;
-                        }
-                    };
+            // Only the directory holds the organization key
+            if payload.sender().ne(&directory) {
+                continue;
+            }
 
-                match payload.message(None)? {
-                    Response::MailboxResponse(MailboxResponse::Receive { list, .. }) => {
-                        let list = list.clone();
+            // The roster is encrypted to the members of the organization
+            let Ok(roster) = payload.message(self.root_document.keypair()) else {
+                continue;
+            };
 
-                        for req in list {
-                            let from = req.sender.clone();
-                            if let Err(e) = self.check_request_message(req, &mut None).await {
-                                tracing::warn!(
-                                    "Error processing request from {from}: {e}. Skipping"
-                                );
-                                continue;
-                            }
-                        }
-                        return Ok(());
-                    }
-                    Response::InvalidPayload => {
-                        tracing::error!(%peer_id, "request was invalid");
-                        continue;
-                    }
-                    Response::Error(e) => {
-                        tracing::error!(error = %e, %peer_id, "error handling request");
-                    }
-                    _ => {
-                        tracing::error!(%peer_id, "response from shuttle node was invalid");
-                        continue;
-                    }
-                }
+            if let Err(e) = self.apply_organization_roster(&organization, roster).await {
+                tracing::warn!(%organization, error = %e, "unable to apply organization roster");
+            }
+        }
+    }
+
+    async fn apply_organization_roster(
+        &mut self,
+        organization: &DID,
+        roster: OrganizationRoster,
+    ) -> Result<(), Error> {
+        roster.verify(organization)?;
+
+        {
+            let current = &mut *self.organization_roster.write().await;
+            if current
+                .as_ref()
+                .is_some_and(|current| current.updated >= roster.updated)
+            {
+                return Ok(());
+            }
+            current.replace(roster.clone());
+        }
+
+        let own_did = self.did_key.clone();
+
+        for did in roster.revoked.iter().filter(|did| own_did.ne(did)) {
+            if self.is_blocked(did).await? {
+                continue;
+            }
+
+            tracing::info!(%organization, %did, "membership revoked");
+            if let Err(e) = self.block(did).await {
+                tracing::warn!(%organization, %did, error = %e, "unable to block revoked member");
+            }
+        }
+
+        if !roster.is_member(&self.did_key) {
+            tracing::warn!(%organization, "identity is not a member of the organization");
+            return Ok(());
+        }
+
+        for certificate in &roster.members {
+            let did = &certificate.member;
+
+            if self.did_key.eq(did) || self.is_friend(did).await? || self.is_blocked(did).await? {
+                continue;
+            }
+
+            // Members are friends by the roster, so any pending request between them is no longer needed
+            let list = self.list_all_raw_request().await?;
+            for req in list.iter().filter(|req| req.did().eq(did)) {
+                self.root_document.remove_request(req).await?;
+            }
+
+            if let Err(e) = self.add_friend(did).await {
+                tracing::warn!(%organization, %did, error = %e, "unable to add member as friend");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Last roster of the organization that was applied, if the identity belongs to an organization
+    pub async fn organization_roster(&self) -> Option<OrganizationRoster> {
+        self.organization_roster.read().await.clone()
+    }
+
+    async fn fetch_organization_roster(
+        &self,
+        organization: &DID,
+    ) -> Result<OrganizationRoster, Error> {
+        match self
+            .organization_request(organization, Organization::Roster)
+            .await?
+        {
+            OrganizationResponse::Roster(roster) => Ok(roster),
+            _ => Err(Error::OtherWithContext(
+                "response from directory was invalid".into(),
+            )),
+        }
+    }
+
+    /// Provision `did` as a member of the organization. Only available to administrators of the organization
+    pub async fn enroll_organization_member(
+        &self,
+        did: &DID,
+    ) -> Result<MembershipCertificate, Error> {
+        let organization = self.organization()?;
+        match self
+            .organization_request(&organization, Organization::Enroll { did: did.clone() })
+            .await?
+        {
+            OrganizationResponse::Certificate(certificate) => {
+                certificate.verify(&organization)?;
+                Ok(certificate)
+            }
+            _ => Err(Error::OtherWithContext(
+                "response from directory was invalid".into(),
+            )),
+        }
+    }
+
+    /// Revoke the membership of `did`. Only available to administrators of the organization
+    pub async fn revoke_organization_member(&self, did: &DID) -> Result<(), Error> {
+        let organization = self.organization()?;
+        match self
+            .organization_request(&organization, Organization::Revoke { did: did.clone() })
+            .await?
+        {
+            OrganizationResponse::Roster(_) => Ok(()),
+            _ => Err(Error::OtherWithContext(
+                "response from directory was invalid".into(),
+            )),
+        }
+    }
+
+    fn organization(&self) -> Result<DID, Error> {
+        self.config
+            .store_setting()
+            .organization
+            .clone()
+            .ok_or(Error::OtherWithContext(
+                "identity does not belong to an organization".into(),
+            ))
+    }
+
+    async fn organization_request(
+        &self,
+        organization: &DID,
+        request: Organization,
+    ) -> Result<OrganizationResponse, Error> {
+        let directory = organization.to_peer_id()?;
+
+        let payload = PayloadBuilder::new(
+            self.root_document.keypair(),
+            crate::shuttle::identity::protocol::Request::from(request),
+        )
+        .build()?;
+
+        let bytes = payload.to_bytes().expect("valid deserialization");
+
+        let response = self
+            .ipfs
+            .send_request(directory, (protocols::SHUTTLE_IDENTITY, bytes))
+            .await
+            .map_err(anyhow::Error::from)?;
+
+        let payload: PayloadMessage<Response> = PayloadMessage::from_bytes(&response)?;
+
+        match payload.message(None)? {
+            Response::OrganizationResponse(OrganizationResponse::Error(e)) => Err(match e {
+                OrganizationError::Unauthorized => Error::Unauthorized,
+                OrganizationError::AlreadyMember => Error::IdentityExist,
+                OrganizationError::NotMember => Error::IdentityDoesntExist,
+                OrganizationError::NotEnabled | OrganizationError::InternalError => {
+                    Error::OtherWithContext(format!(
+                        "directory was unable to process request: {e:?}"
+                    ))
+                }
+            }),
+            Response::OrganizationResponse(response) => Ok(response),
+            _ => Err(Error::OtherWithContext(
+                "response from directory was invalid".into(),
+            )),
+        }
+    }
+
+    /// Check with the shuttle nodes that the username is not used by another registered identity.
+    /// Nodes that do not enforce unique usernames will always accept the username
+    pub async fn is_username_available(&self, username: &str) -> Result<(), Error> {
+        if let DiscoveryConfig::Shuttle { addresses } = self.discovery.discovery_config() {
+            let payload = PayloadBuilder::new(
+                self.root_document.keypair(),
+                crate::shuttle::identity::protocol::Request::from(
+                    crate::shuttle::identity::protocol::Register::IsUsernameAvailable {
+                        username: username.to_string(),
+                    },
+                ),
+            )
+            .build()?;
+
+            let bytes = payload.to_bytes().expect("valid deserialization");
+
+            for peer_id in addresses.iter().filter_map(|addr| addr.peer_id()) {
+                let response = match self
+                    .ipfs
+                    .send_request(peer_id, (protocols::SHUTTLE_IDENTITY, bytes.clone()))
+                    .await
+                {
+                    Ok(response) => response,
+                    Err(e) => {
+                        tracing::warn!(error = %e, %peer_id, "unable to send request to shuttle node");
+                        continue;
+                    }
+                };
+
+                let payload: PayloadMessage<crate::shuttle::identity::protocol::Response> =
+                    match PayloadMessage::from_bytes(&response) {
+                        Ok(payload) => payload,
+                        Err(e) => {
+                            tracing::error!(error = %e, %peer_id, "unable to process payload");
+                            continue;
+                        }
+                    };
+
+                match payload.message(None)? {
+                    Response::RegisterResponse(RegisterResponse::Ok) => return Ok(()),
+                    Response::RegisterResponse(RegisterResponse::Error(
+                        RegisterError::UsernameTaken,
+                    )) => return Err(Error::UsernameTaken),
+                    _ => {
+                        tracing::error!(%peer_id, "response from shuttle node was invalid");
+                        continue;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn fetch_mailbox(&mut self) -> Result<(), Error> {
+        if let DiscoveryConfig::Shuttle { addresses } = self.discovery.discovery_config() {
+            if addresses.is_empty() {
+                return Err(Error::Other);
+            }
+
+            let payload = PayloadBuilder::new(
+                self.root_document.keypair(),
+                crate::shuttle::identity::protocol::Request::from(
+                    crate::shuttle::identity::protocol::Mailbox::FetchAll,
+                ),
+            )
+            .build()?;
+
+            let bytes = payload.to_bytes().expect("valid deserialization");
+
+            for peer_id in addresses.iter().filter_map(|addr| addr.peer_id()) {
+                let response = match self
+                    .ipfs
+                    .send_request(peer_id, (protocols::SHUTTLE_IDENTITY, bytes.clone()))
+                    .await
+                {
+                    Ok(response) => response,
+                    Err(e) => {
+                        tracing::warn!(error = %e, %peer_id, "unable to send request to shuttle node");
+                        continue;
+                    }
+                };
+
+                let payload: PayloadMessage<crate::shuttle::identity::protocol::Response> =
+                    match PayloadMessage::from_bytes(&response) {
+                        Ok(payload) => payload,
+                        Err(e) => {
+                            tracing::error!(error = %e, %peer_id, "unable to process payload");
+                            continue;
+                        }
+                    };
+
+                match payload.message(None)? {
+                    Response::MailboxResponse(MailboxResponse::Receive { list, .. }) => {
+                        let list = list.clone();
+
+                        for req in list {
+                            let from = req.sender.clone();
+                            if let Err(e) = self.check_request_message(req, &mut None).await {
+                                tracing::warn!(
+                                    "Error processing request from {from}: {e}. Skipping"
+                                );
+                                continue;
+                            }
+                        }
+                        return Ok(());
+                    }
+                    Response::InvalidPayload => {
+                        tracing::error!(%peer_id, "request was invalid");
+                        continue;
+                    }
+                    Response::Error(e) => {
+                        tracing::error!(error = %e, %peer_id, "error handling request");
+                    }
+                    _ => {
+                        tracing::error!(%peer_id, "response from shuttle node was invalid");
+                        continue;
+                    }
+                }
             }
         }
         Ok(())
@@ -2173,7 +3009,7 @@ impl IdentityStore {
 
                     if split_data.len() != 2 {
                         for await document in cache {
-                            if document.username.to_lowercase().contains(&username.to_lowercase()) {
+                            if document.name_matches(username) {
                                 let id = resolve_identity(&store, document).await;
                                 yield id;
                             }
@@ -2183,7 +3019,7 @@ impl IdentityStore {
                         split_data.last().map(|s| s.to_lowercase()),
                     ) {
                         for await document in cache {
-                            if document.username.to_lowercase().eq(&name) && String::from_utf8_lossy(&document.short_id).to_lowercase().eq(&code) {
+                            if document.name_eq(&name) && String::from_utf8_lossy(&document.short_id).to_lowercase().eq(&code) {
                                 let id = resolve_identity(&store, document).await;
                                 yield id;
                             }
@@ -2191,9 +3027,8 @@ impl IdentityStore {
                     }
                 }
                 LookupBy::Username(ref username) => {
-                    let username = username.to_lowercase();
                     for await document in cache {
-                        if document.username.to_lowercase().contains(&username) {
+                        if document.name_matches(username) {
                             let id = resolve_identity(&store, document).await;
                             yield id;
                         }
@@ -2212,97 +3047,326 @@ impl IdentityStore {
             }
 
             if !missing.is_empty() || matches!(lookup, LookupBy::Username(_) | LookupBy::ShortId(_)) {
-                let kind = match lookup {
-                    LookupBy::DidKey(did) => {
-                        crate::shuttle::identity::protocol::Lookup::PublicKey { did }
-                    }
-                    LookupBy::DidKeys(list) => {
-                        crate::shuttle::identity::protocol::Lookup::PublicKeys { dids: list }
+                let identities = match lookup {
+                    LookupBy::DidKey(_) | LookupBy::DidKeys(_) => {
+                        store.coalesced_shuttle_lookup(missing).await
                     }
                     LookupBy::Username(username) => {
-                        crate::shuttle::identity::protocol::Lookup::Username { username, count: 0 }
+                        store.shuttle_lookup(crate::shuttle::identity::protocol::Lookup::Username { username, count: 0 }).await
                     }
                     LookupBy::ShortId(short_id) => {
-                        crate::shuttle::identity::protocol::Lookup::ShortId { short_id }
+                        store.shuttle_lookup(crate::shuttle::identity::protocol::Lookup::ShortId { short_id }).await
                     }
                 };
-                if let DiscoveryConfig::Shuttle { addresses } = store.discovery.discovery_config() {
-                    if addresses.is_empty() {
-                        return;
-                    }
 
-                    let payload = PayloadBuilder::new(
-                        store.root_document.keypair(),
-                        crate::shuttle::identity::protocol::Request::from(kind)
-                    )
-                    .build()
-                    .expect("valid payload construction");
+                for identity in identities {
+                    yield resolve_identity(&store, identity).await;
+                }
+            }
+        };
+
+        GetIdentity::new(id, stream.boxed())
+    }
+
+    /// Lookup identities by their did from shuttle nodes. Concurrent lookups of the same identity share a single
+    /// in-flight request, with its result broadcasted to every lookup waiting on it
+    async fn coalesced_shuttle_lookup(&self, dids: HashSet<DID>) -> Vec<IdentityDocument> {
+        let store = self.clone();
+        coalesce_lookup(
+            &self.inflight_lookups,
+            dids,
+            |identity| &identity.did,
+            move |list| async move {
+                let kind = match list.as_slice() {
+                    [did] => {
+                        crate::shuttle::identity::protocol::Lookup::PublicKey { did: did.clone() }
+                    }
+                    _ => crate::shuttle::identity::protocol::Lookup::PublicKeys {
+                        dids: list.clone(),
+                    },
+                };
 
-                    let bytes = payload.to_bytes().expect("valid deserialization");
+                store.shuttle_lookup(kind).await
+            },
+        )
+        .await
+    }
 
-                    for peer_id in addresses.iter().filter_map(|addr| addr.peer_id()) {
+    /// Lookup identities from shuttle nodes, caching any identity found
+    async fn shuttle_lookup(
+        &self,
+        kind: crate::shuttle::identity::protocol::Lookup,
+    ) -> Vec<IdentityDocument> {
+        let DiscoveryConfig::Shuttle { addresses } = self.discovery.discovery_config() else {
+            return vec![];
+        };
 
-                        let response = match store
-                            .ipfs
-                            .send_request(peer_id, (protocols::SHUTTLE_IDENTITY, bytes.clone()))
-                            .await
-                        {
-                            Ok(response) => response,
-                            Err(e) => {
-                                tracing::warn!(error = %e, %peer_id, "unable to send request to shuttle node");
-                                continue;
-                            }
-                        };
+        if addresses.is_empty() {
+            return vec![];
+        }
 
-                        let payload: PayloadMessage<crate::shuttle::identity::protocol::Response> =
-                            match PayloadMessage::from_bytes(&response) {
-                                Ok(payload) => payload,
-                                Err(e) => {
-                                    tracing::error!(error = %e, %peer_id, "unable to process payload");
-                                    continue;
-                                }
-                            };
+        let payload = PayloadBuilder::new(
+            self.root_document.keypair(),
+            crate::shuttle::identity::protocol::Request::from(kind),
+        )
+        .build()
+        .expect("valid payload construction");
 
-                        match payload.message(None) {
-                            Ok(Response::LookupResponse(LookupResponse::Ok { identity })) => {
-                                for ident in identity {
-                                    let ident = ident.clone();
-                                    let did = ident.did.clone();
+        let bytes = payload.to_bytes().expect("valid deserialization");
 
-                                    let _ = store.identity_cache.insert(&ident).await;
+        for peer_id in addresses.iter().filter_map(|addr| addr.peer_id()) {
+            let response = match self
+                .ipfs
+                .send_request(peer_id, (protocols::SHUTTLE_IDENTITY, bytes.clone()))
+                .await
+            {
+                Ok(response) => response,
+                Err(e) => {
+                    tracing::warn!(error = %e, %peer_id, "unable to send request to shuttle node");
+                    continue;
+                }
+            };
 
-                                    yield resolve_identity(&store, ident).await;
+            let payload: PayloadMessage<crate::shuttle::identity::protocol::Response> =
+                match PayloadMessage::from_bytes(&response) {
+                    Ok(payload) => payload,
+                    Err(e) => {
+                        tracing::error!(error = %e, %peer_id, "unable to process payload");
+                        continue;
+                    }
+                };
 
-                                    if store.discovery.contains(&did).await {
-                                        continue;
-                                    }
-                                    let _ = store.discovery.insert(&did).await;
-                                }
+            match payload.message(None) {
+                Ok(Response::LookupResponse(LookupResponse::Ok { identity })) => {
+                    for ident in &identity {
+                        let _ = self.identity_cache.insert(ident).await;
 
-                                break;
-                            },
-                            Ok(Response::InvalidPayload) => {
-                                tracing::error!(%peer_id, "request was invalid");
-                                continue;
-                            }
-                            Ok(Response::Error(e)) => {
-                                tracing::error!(error = %e, %peer_id, "error handling request");
-                            }
-                            Ok(_) => {
-                                tracing::error!(%peer_id, "response from shuttle node was invalid");
-                                continue;
-                            }
-                            Err(e) => {
-                                tracing::error!(%peer_id, error = %e, "invalid message");
-                                continue;
-                            }
+                        if self.discovery.contains(&ident.did).await {
+                            continue;
                         }
+                        let _ = self.discovery.insert(&ident.did).await;
                     }
+
+                    return identity;
+                }
+                Ok(Response::InvalidPayload) => {
+                    tracing::error!(%peer_id, "request was invalid");
+                    continue;
+                }
+                Ok(Response::Error(e)) => {
+                    tracing::error!(error = %e, %peer_id, "error handling request");
                 }
+                Ok(_) => {
+                    tracing::error!(%peer_id, "response from shuttle node was invalid");
+                    continue;
+                }
+                Err(e) => {
+                    tracing::error!(%peer_id, error = %e, "invalid message");
+                    continue;
+                }
+            }
+        }
+
+        vec![]
+    }
+
+    // Identities created before handles were introduced use their current username as their handle
+    async fn migrate_handle(&mut self) -> Result<(), Error> {
+        let mut identity = self.own_identity_document().await?;
+
+        if identity.handle.is_some() {
+            return Ok(());
+        }
+
+        tracing::info!(did = %identity.did, "Setting identity handle to {}", identity.username);
+        identity.handle = Some(identity.username.clone());
+        self.identity_update(identity).await
+    }
+
+    /// Publish the certificate of this device in the identity document if it is not listed yet
+    async fn register_device(&mut self) -> Result<(), Error> {
+        let mut identity = self.own_identity_document().await?;
+
+        let certificate = self.device.certificate();
+
+        if identity.revoked_devices.contains(&certificate.device) {
+            return Err(Error::OtherWithContext(
+                "This device has been revoked".into(),
+            ));
+        }
+
+        if identity.devices.contains(certificate) {
+            return Ok(());
+        }
+
+        if identity.devices.len() >= MAX_DEVICES {
+            return Err(Error::InvalidLength {
+                context: "devices".into(),
+                current: identity.devices.len(),
+                minimum: None,
+                maximum: Some(MAX_DEVICES),
+            });
+        }
+
+        tracing::info!(device = %certificate.device, "Registering device");
+        identity.devices.push(certificate.clone());
+        self.identity_update(identity).await
+    }
+
+    /// Remove the device from the identity document so keys are no longer shared with it, recording it as revoked so
+    /// it is not registered again
+    pub async fn revoke_device(&mut self, device: &DID) -> Result<(), Error> {
+        if device == self.device.did() {
+            return Err(Error::OtherWithContext(
+                "The current device cannot be revoked".into(),
+            ));
+        }
+
+        let mut identity = self.own_identity_document().await?;
+
+        let length = identity.devices.len();
+        identity
+            .devices
+            .retain(|certificate| certificate.device.ne(device));
+
+        if identity.devices.len() == length {
+            return Err(Error::ObjectNotFound);
+        }
+
+        if !identity.revoked_devices.contains(device) {
+            if identity.revoked_devices.len() >= MAX_REVOKED_DEVICES {
+                identity.revoked_devices.remove(0);
             }
+            identity.revoked_devices.push(device.clone());
+        }
+
+        tracing::info!(%device, "Revoking device");
+        self.identity_update(identity).await?;
+
+        let mut linked = self.root_document.get_devices().await?;
+        let length = linked.len();
+        linked.retain(|certificate| certificate.device.ne(device));
+        if linked.len() != length {
+            self.root_document.set_devices(linked).await?;
+        }
+
+        if let Err(e) = self.export_root_document().await {
+            tracing::warn!(%device, error = %e, "Unable to export root document");
+        }
+
+        Ok(())
+    }
+
+    /// Authorize a new device, recording its certificate in the root document and publishing it in the identity
+    /// document
+    pub async fn link_device(&mut self, device: &DID) -> Result<DeviceLink, Error> {
+        let mut identity = self.own_identity_document().await?;
+
+        if identity
+            .devices
+            .iter()
+            .any(|certificate| certificate.device.eq(device))
+        {
+            return Err(Error::OtherWithContext("Device is already linked".into()));
+        }
+
+        if identity.revoked_devices.contains(device) {
+            return Err(Error::OtherWithContext("Device has been revoked".into()));
+        }
+
+        if identity.devices.len() >= MAX_DEVICES {
+            return Err(Error::InvalidLength {
+                context: "devices".into(),
+                current: identity.devices.len(),
+                minimum: None,
+                maximum: Some(MAX_DEVICES),
+            });
+        }
+
+        let keypair = self.root_document.keypair();
+        let certificate = DeviceCertificate::new(keypair, device.clone(), Utc::now())?;
+        let link = DeviceLink::new(certificate.clone());
+
+        let mut linked = self.root_document.get_devices().await?;
+        linked.push(certificate.clone());
+        self.root_document.set_devices(linked).await?;
+
+        tracing::info!(%device, "Linking device");
+        identity.devices.push(certificate);
+        self.identity_update(identity).await?;
+
+        // the new device loads the root document from shuttle
+        self.export_root_document().await?;
+
+        Ok(link)
+    }
+
+    /// Merge the root document published to shuttle by another device of the identity into our own. Returns true if
+    /// the root document was changed, in which case the messaging and file stores are notified of the changes through
+    /// [`RootDocumentMap::subscribe_changes`]
+    pub async fn synchronize_root_document(&mut self) -> Result<bool, Error> {
+        let remote = self.import_identity_remote().await?;
+        let local = self.root_document.export_root_cid().await?;
+
+        if remote == local {
+            return Ok(false);
+        }
+
+        tracing::info!(%remote, "Synchronizing root document from another device");
+        self.root_document
+            .merge(remote)
+            .timeout(Duration::from_secs(60))
+            .await
+            .map_err(anyhow::Error::from)??;
+
+        if self.root_document.export_root_cid().await? == local {
+            return Ok(false);
+        }
+
+        // our changes are published so the other device can merge them as well
+        if let Err(e) = self.export_root_document().await {
+            tracing::warn!(error = %e, "Unable to export root document");
+        }
+
+        self.emit_event(MultiPassEventKind::IdentityUpdate {
+            did: self.did_key.clone(),
+        })
+        .await;
+
+        Ok(true)
+    }
+
+    /// Certificates of the devices of the identity
+    pub async fn own_devices(&self) -> Result<Vec<DeviceCertificate>, Error> {
+        Ok(self.own_identity_document().await?.devices)
+    }
+
+    /// Devices published by the identity. Empty if the identity does not publish any, in which case keys are shared
+    /// with the identity itself
+    pub async fn devices(&self, did: &DID) -> Vec<DID> {
+        let document = match did == &self.did_key {
+            true => self.own_identity_document().await,
+            false => self.identity_cache.get(did).await,
         };
 
-        GetIdentity::new(id, stream.boxed())
+        document
+            .map(|document| {
+                document
+                    .devices
+                    .into_iter()
+                    .map(|certificate| certificate.device)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Key of this device
+    pub fn device_key(&self) -> &DeviceKey {
+        &self.device
+    }
+
+    pub fn pending_events(&self) -> &PendingEvents {
+        &self.pending_events
     }
 
     pub async fn identity_update(&mut self, identity: IdentityDocument) -> Result<(), Error> {
@@ -2350,27 +3414,129 @@ impl IdentityStore {
             self.lookup(did).await?;
         }
 
-        let status: IdentityStatus = connected_to_peer(&self.ipfs, did.clone())
-            .await
-            .map(|ctype| ctype.into())
-            .map_err(Error::from)?;
+        let status: IdentityStatus = connected_to_peer(&self.ipfs, did.clone())
+            .await
+            .map(|ctype| ctype.into())
+            .map_err(Error::from)?;
+
+        if matches!(status, IdentityStatus::Offline) {
+            return Ok(status);
+        }
+
+        self.identity_cache
+            .get(did)
+            .await
+            .ok()
+            .and_then(|cache| cache.metadata.status)
+            .or(Some(status))
+            .ok_or(Error::IdentityDoesntExist)
+    }
+
+    pub async fn get_app_data(&self, namespace: &str) -> Result<Bytes, Error> {
+        self.root_document.get_app_data(namespace).await
+    }
+
+    pub async fn set_app_data(&mut self, namespace: &str, data: Bytes) -> Result<(), Error> {
+        self.root_document.set_app_data(namespace, data).await?;
+
+        let _ = self.export_root_document().await;
+
+        self.emit_event(MultiPassEventKind::AppDataUpdated {
+            namespace: namespace.to_string(),
+        })
+        .await;
+        Ok(())
+    }
+
+    pub async fn notification_preferences(&self) -> Result<NotificationPreferences, Error> {
+        self.root_document.get_notification_preferences().await
+    }
+
+    pub async fn set_notification_preferences(
+        &mut self,
+        preferences: NotificationPreferences,
+    ) -> Result<(), Error> {
+        self.root_document
+            .set_notification_preferences(preferences)
+            .await?;
+
+        let _ = self.export_root_document().await;
+
+        self.emit_event(MultiPassEventKind::NotificationPreferencesUpdated)
+            .await;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn set_identity_status(&mut self, status: IdentityStatus) -> Result<(), Error> {
+        self.root_document.set_status_indicator(status).await?;
+
+        let _ = self.export_root_document().await;
+
+        self.push_to_all().await;
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn identity_activity(&self, did: &DID) -> Result<Option<IdentityActivity>, Error> {
+        let identity = self.own_identity_document().await?;
+
+        let activity = match identity.did.eq(did) {
+            true => identity.activity,
+            false => {
+                // Activity is only shown while the identity is online
+                let identity_status = self.identity_status(did).await?;
+
+                if matches!(identity_status, IdentityStatus::Offline) {
+                    return Ok(None);
+                }
+
+                self.identity_cache.get(did).await?.activity
+            }
+        };
+
+        let now = self.clock().now();
+        Ok(activity.filter(|activity| !activity.is_expired_at(now)))
+    }
+
+    pub async fn set_identity_activity(
+        &mut self,
+        activity: Option<IdentityActivity>,
+    ) -> Result<(), Error> {
+        if let Some(activity) = &activity {
+            validate_activity(activity)?;
+
+            if activity.is_expired_at(self.clock().now()) {
+                return Err(Error::OtherWithContext("activity has already ended".into()));
+            }
+        }
+
+        self.root_document.set_activity(activity).await?;
 
-        if matches!(status, IdentityStatus::Offline) {
-            return Ok(status);
+        let _ = self.export_root_document().await;
+
+        self.push_to_all().await;
+        Ok(())
+    }
+
+    pub async fn identity_profile_fields(&self, did: &DID) -> Result<ProfileFields, Error> {
+        let identity = self.own_identity_document().await?;
+
+        if identity.did.eq(did) {
+            return self.root_document.get_profile_fields().await;
         }
 
-        self.identity_cache
-            .get(did)
-            .await
-            .ok()
-            .and_then(|cache| cache.metadata.status)
-            .or(Some(status))
-            .ok_or(Error::IdentityDoesntExist)
+        let profile = self.identity_cache.get(did).await?.profile;
+        Ok(profile.unwrap_or_default())
     }
 
-    #[tracing::instrument(skip(self))]
-    pub async fn set_identity_status(&mut self, status: IdentityStatus) -> Result<(), Error> {
-        self.root_document.set_status_indicator(status).await?;
+    pub async fn set_identity_profile_fields(
+        &mut self,
+        fields: ProfileFields,
+    ) -> Result<(), Error> {
+        validate_profile_fields(&fields)?;
+
+        self.root_document.set_profile_fields(fields).await?;
 
         let _ = self.export_root_document().await;
 
@@ -2404,6 +3570,41 @@ impl IdentityStore {
             .ok_or(Error::IdentityDoesntExist)
     }
 
+    /// Whether every identity in `recipients` advertised that it is able to read messages compressed with
+    /// `compression`. Identities that are unknown are assumed to not support it
+    pub async fn supports_compression(&self, recipients: &[DID], compression: Compression) -> bool {
+        for did in recipients.iter().filter(|did| **did != self.did_key) {
+            let advertised = self
+                .identity_cache
+                .get(did)
+                .await
+                .ok()
+                .and_then(|cache| cache.metadata.compression);
+
+            if advertised != Some(compression) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Whether every identity in `recipients` advertised that it is able to process the events of `feature`.
+    /// Identities that are unknown are assumed to not support it
+    pub async fn supports_feature(&self, recipients: &[DID], feature: Feature) -> bool {
+        for did in recipients.iter().filter(|did| **did != self.did_key) {
+            let advertised = self
+                .identity_cache
+                .get(did)
+                .await
+                .is_ok_and(|cache| cache.metadata.features.contains(&feature));
+
+            if !advertised {
+                return false;
+            }
+        }
+        true
+    }
+
     pub fn get_raw_keypair(&self) -> anyhow::Result<ipfs::libp2p::identity::ed25519::Keypair> {
         self.root_document
             .keypair()
@@ -2538,25 +3739,178 @@ impl IdentityStore {
         Err(Error::InvalidIdentityBanner)
     }
 
+    #[tracing::instrument(skip(self))]
+    pub async fn identity_picture_sized(
+        &self,
+        did: &DID,
+        dimension: u32,
+    ) -> Result<IdentityImage, Error> {
+        if self.config.store_setting().disable_images {
+            return Err(Error::InvalidIdentityPicture);
+        }
+
+        let document = match self.own_identity_document().await {
+            Ok(document) if document.did.eq(did) => document,
+            Err(_) | Ok(_) => self.identity_cache.get(did).await?,
+        };
+
+        match document.metadata.profile_picture {
+            Some(cid) => get_image_variant(&self.ipfs, cid, dimension, Some(MAX_IMAGE_SIZE))
+                .await
+                .map_err(|_| Error::InvalidIdentityPicture),
+            // Note: The default profile picture is generated on request so we would serve it as is
+            None => self.identity_picture(did).await,
+        }
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn identity_banner_sized(
+        &self,
+        did: &DID,
+        dimension: u32,
+    ) -> Result<IdentityImage, Error> {
+        if self.config.store_setting().disable_images {
+            return Err(Error::InvalidIdentityBanner);
+        }
+
+        let document = match self.own_identity_document().await {
+            Ok(document) if document.did.eq(did) => document,
+            Err(_) | Ok(_) => self.identity_cache.get(did).await?,
+        };
+
+        if let Some(cid) = document.metadata.profile_banner {
+            return get_image_variant(&self.ipfs, cid, dimension, Some(MAX_IMAGE_SIZE))
+                .await
+                .map_err(|_| Error::InvalidIdentityBanner);
+        }
+
+        Err(Error::InvalidIdentityBanner)
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn delete_photo(&mut self, cid: Cid) -> Result<(), Error> {
         let ipfs = &self.ipfs;
         if ipfs.is_pinned(cid).await? {
             ipfs.remove_pin(cid).recursive().await?;
         }
+        remove_image_variants(ipfs, cid).await;
         Ok(())
     }
 
     pub fn clear_internal_cache(&mut self) {}
 
     pub async fn emit_event(&self, event: MultiPassEventKind) {
+        if self.config.store_setting().relationship_history {
+            self.record_relationship_change(&event).await;
+        }
+        self.update_pending_events(&event).await;
         self.event.emit(event).await;
     }
+
+    /// Keep incoming friend requests pending until they are acknowledged or answered
+    async fn update_pending_events(&self, event: &MultiPassEventKind) {
+        let result = match event {
+            MultiPassEventKind::FriendRequestReceived { from, date, .. } => self
+                .pending_events
+                .record(
+                    PendingEventKind::FriendRequest { from: from.clone() },
+                    *date,
+                )
+                .await
+                .map(|_| ()),
+            MultiPassEventKind::IncomingFriendRequestRejected { did }
+            | MultiPassEventKind::IncomingFriendRequestClosed { did }
+            | MultiPassEventKind::FriendAdded { did }
+            | MultiPassEventKind::Blocked { did } => self
+                .pending_events
+                .acknowledge_request(did)
+                .await
+                .map(|_| ()),
+            _ => Ok(()),
+        };
+
+        if let Err(e) = result {
+            tracing::warn!(error = %e, "unable to update pending events");
+        }
+    }
+
+    async fn record_relationship_change(&self, event: &MultiPassEventKind) {
+        let (did, change, date) = match event {
+            MultiPassEventKind::FriendRequestReceived { from, date, .. } => {
+                (from, RelationshipChange::RequestReceived, Some(*date))
+            }
+            MultiPassEventKind::FriendRequestSent { to, date, .. } => {
+                (to, RelationshipChange::RequestSent, Some(*date))
+            }
+            MultiPassEventKind::IncomingFriendRequestRejected { did } => {
+                (did, RelationshipChange::IncomingRequestRejected, None)
+            }
+            MultiPassEventKind::OutgoingFriendRequestRejected { did } => {
+                (did, RelationshipChange::OutgoingRequestRejected, None)
+            }
+            MultiPassEventKind::IncomingFriendRequestClosed { did } => {
+                (did, RelationshipChange::IncomingRequestClosed, None)
+            }
+            MultiPassEventKind::OutgoingFriendRequestClosed { did } => {
+                (did, RelationshipChange::OutgoingRequestClosed, None)
+            }
+            MultiPassEventKind::FriendRequestExpired { did } => {
+                (did, RelationshipChange::OutgoingRequestExpired, None)
+            }
+            MultiPassEventKind::FriendAdded { did } => (did, RelationshipChange::FriendAdded, None),
+            MultiPassEventKind::FriendRemoved { did } => {
+                (did, RelationshipChange::FriendRemoved, None)
+            }
+            MultiPassEventKind::Blocked { did } => (did, RelationshipChange::Blocked, None),
+            MultiPassEventKind::BlockedBy { did } => (did, RelationshipChange::BlockedBy, None),
+            MultiPassEventKind::Unblocked { did } => (did, RelationshipChange::Unblocked, None),
+            MultiPassEventKind::UnblockedBy { did } => (did, RelationshipChange::UnblockedBy, None),
+            _ => return,
+        };
+
+        let entry = RelationshipHistoryEntry::new(change, date);
+
+        if let Err(e) = self
+            .root_document
+            .add_relationship_history(did, entry)
+            .await
+        {
+            tracing::warn!(%did, error = %e, "unable to record relationship change");
+        }
+    }
+
+    pub async fn relationship_history(
+        &self,
+        did: &DID,
+    ) -> Result<Vec<RelationshipHistoryEntry>, Error> {
+        if !self.config.store_setting().relationship_history {
+            return Err(Error::OtherWithContext(
+                "relationship history is not enabled".into(),
+            ));
+        }
+        self.root_document.get_relationship_history(did).await
+    }
 }
 
 impl IdentityStore {
-    #[tracing::instrument(skip(self))]
     pub async fn send_request(&mut self, pubkey: &DID) -> Result<(), Error> {
+        self.send_request_inner(pubkey, None).await
+    }
+
+    pub async fn send_request_with_message(
+        &mut self,
+        pubkey: &DID,
+        message: &str,
+    ) -> Result<(), Error> {
+        self.send_request_inner(pubkey, Some(message)).await
+    }
+
+    #[tracing::instrument(skip(self, message))]
+    async fn send_request_inner(
+        &mut self,
+        pubkey: &DID,
+        message: Option<&str>,
+    ) -> Result<(), Error> {
         let local_public_key = self.did_key.clone();
 
         if local_public_key.eq(pubkey) {
@@ -2589,7 +3943,15 @@ impl IdentityStore {
             return Err(Error::FriendRequestExist);
         }
 
-        let payload = RequestResponsePayload::new(self.root_document.keypair(), Event::Request)?;
+        let keypair = self.root_document.keypair();
+
+        let mut payload = RequestResponsePayload::new_unsigned(keypair, Event::Request);
+
+        if let Some(message) = message {
+            payload.set_message(keypair, pubkey, message)?;
+        }
+
+        let payload = payload.sign(keypair)?;
 
         self.broadcast_request(pubkey, &payload, true, true).await
     }
@@ -2690,6 +4052,62 @@ impl IdentityStore {
         self.broadcast_request(pubkey, &payload, false, true).await
     }
 
+    #[tracing::instrument(skip(self))]
+    pub async fn resend_request(&mut self, pubkey: &DID) -> Result<(), Error> {
+        let list = self.list_all_raw_request().await?;
+
+        let internal_request = list
+            .iter()
+            .find(|request| request.r#type() == RequestType::Outgoing && request.did().eq(pubkey))
+            .ok_or(Error::CannotFindFriendRequest)?;
+
+        let message = internal_request.message().map(ToString::to_string);
+
+        self.root_document.remove_request(internal_request).await?;
+
+        let _ = self.export_root_document().await;
+
+        self.queue.remove(pubkey).await;
+
+        self.send_request_inner(pubkey, message.as_deref()).await
+    }
+
+    /// Retract outgoing requests that were sent more than `ttl` ago
+    #[tracing::instrument(skip(self))]
+    pub async fn expire_requests(&self, ttl: Duration) -> Result<(), Error> {
+        let now = self.clock().now();
+
+        let expired = self
+            .list_all_raw_request()
+            .await?
+            .into_iter()
+            .filter(|request| request.is_expired_at(ttl, now))
+            .collect::<Vec<_>>();
+
+        if expired.is_empty() {
+            return Ok(());
+        }
+
+        let keypair = self.root_document.keypair();
+
+        for request in &expired {
+            let did = request.did();
+
+            self.root_document.remove_request(request).await?;
+
+            // replaces the request if it is still queued, otherwise lets the recipient drop the incoming request
+            let payload = RequestResponsePayload::new(keypair, Event::Retract)?;
+            self.queue.insert(did, payload).await;
+
+            self.emit_event(MultiPassEventKind::FriendRequestExpired { did: did.clone() })
+                .await;
+        }
+
+        let _ = self.export_root_document().await;
+
+        Ok(())
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn has_request_from(&self, pubkey: &DID) -> Result<bool, Error> {
         self.list_incoming_request().await.map(|list| {
@@ -2795,6 +4213,113 @@ impl IdentityStore {
         self.root_document.get_friends().await
     }
 
+    pub async fn export_contacts(&self, format: ContactFormat) -> Result<Vec<u8>, Error> {
+        let friends = self.friends_list().await?;
+
+        let identities = self
+            .lookup(friends.clone())
+            .map(|identity| (identity.did_key().clone(), identity.username().to_string()))
+            .collect::<HashMap<_, _>>()
+            .await;
+
+        let contacts = friends
+            .into_iter()
+            .map(|did| {
+                let username = identities.get(&did).cloned();
+                Contact::new(did, username)
+            })
+            .collect::<Vec<_>>();
+
+        contact::encode(&contacts, format)
+    }
+
+    #[tracing::instrument(skip(self, data))]
+    pub async fn import_contacts(
+        &mut self,
+        format: ContactFormat,
+        data: &[u8],
+    ) -> Result<Vec<DID>, Error> {
+        let contacts = contact::decode(data, format)?;
+
+        let mut imported = vec![];
+
+        for contact in contacts {
+            let did = contact.did();
+
+            if did == &self.did_key
+                || self.is_friend(did).await?
+                || self.is_blocked(did).await?
+                || self.sent_friend_request_to(did).await?
+            {
+                continue;
+            }
+
+            let result = match self.has_request_from(did).await? {
+                true => self.accept_request(did).await,
+                false => self.send_request(did).await,
+            };
+
+            match result {
+                Ok(_) => imported.push(did.clone()),
+                Err(e) => tracing::warn!(%did, error = %e, "unable to import contact"),
+            }
+        }
+
+        Ok(imported)
+    }
+
+    pub async fn share_code(&self) -> Result<String, Error> {
+        let identity = self.own_identity().await?;
+        let keypair = super::sealed::get_keypair_did(self.root_document.keypair())?;
+
+        let hints = match self.discovery.discovery_config() {
+            DiscoveryConfig::Shuttle { addresses } => addresses
+                .iter()
+                .take(MAX_SHARE_CODE_HINTS)
+                .map(|addr| addr.to_string())
+                .collect(),
+            _ => vec![],
+        };
+
+        identity.to_share_code(&keypair, hints)
+    }
+
+    pub fn sign_detached(
+        &self,
+        data: &[u8],
+        trusted_comment: Option<&str>,
+    ) -> Result<String, Error> {
+        let keypair = super::sealed::get_keypair_did(self.root_document.keypair())?;
+        warp::crypto::minisign::sign(&keypair, data, trusted_comment)
+    }
+
+    pub async fn add_from_share_code(&mut self, code: &str) -> Result<DID, Error> {
+        let code = ShareCode::decode(code)?;
+        let did = code.did().clone();
+
+        // Shuttle hints are added to the address book so that the identity can be located if
+        // the shuttle is not one we already know about
+        for hint in code.hints() {
+            let Ok(mut addr) = hint.parse::<ipfs::Multiaddr>() else {
+                tracing::warn!(%did, %hint, "invalid shuttle hint");
+                continue;
+            };
+
+            let Some(peer_id) = addr.extract_peer_id() else {
+                tracing::warn!(%did, %hint, "shuttle hint does not contain a peer id");
+                continue;
+            };
+
+            if let Err(e) = self.ipfs.add_peer((peer_id, addr)).await {
+                tracing::warn!(%did, %hint, error = %e, "unable to add shuttle hint to address book");
+            }
+        }
+
+        self.send_request(&did).await?;
+
+        Ok(did)
+    }
+
     // Should not be called directly but only after a request is accepted
     #[tracing::instrument(skip(self))]
     pub async fn add_friend(&mut self, pubkey: &DID) -> Result<(), Error> {
@@ -2860,6 +4385,41 @@ impl IdentityStore {
     }
 
     #[tracing::instrument(skip(self))]
+    pub async fn friends_privacy(&self) -> Result<FriendsPrivacy, Error> {
+        self.root_document.get_friends_privacy().await
+    }
+
+    pub async fn set_friends_privacy(&mut self, privacy: FriendsPrivacy) -> Result<(), Error> {
+        self.root_document.set_friends_privacy(privacy).await?;
+
+        let _ = self.export_root_document().await;
+
+        self.push_to_all().await;
+        Ok(())
+    }
+
+    pub async fn friend_count(&self, pubkey: &DID) -> Result<Option<usize>, Error> {
+        if pubkey.eq(&self.did_key) {
+            return self.friends_list().await.map(|list| Some(list.len()));
+        }
+
+        let summaries = self.friends_summaries.read().await;
+        Ok(summaries.get(pubkey).and_then(|summary| summary.count))
+    }
+
+    pub async fn mutual_friends(&self, pubkey: &DID) -> Result<Vec<DID>, Error> {
+        if pubkey.eq(&self.did_key) {
+            return Err(Error::CannotUseSelfAsFriend);
+        }
+
+        let Some(summary) = self.friends_summaries.read().await.get(pubkey).cloned() else {
+            return Ok(vec![]);
+        };
+
+        let friends = self.friends_list().await?;
+        summary.mutual_friends(self.root_document.keypair(), pubkey, &friends)
+    }
+
     pub async fn is_friend(&self, pubkey: &DID) -> Result<bool, Error> {
         self.friends_list().await.map(|list| list.contains(pubkey))
     }
@@ -2870,6 +4430,58 @@ impl IdentityStore {
     ) -> Result<futures::stream::BoxStream<'static, MultiPassEventKind>, Error> {
         self.event.subscribe().await
     }
+
+    pub async fn subscribe_with(
+        &self,
+        options: SubscriptionOptions,
+    ) -> Result<futures::stream::BoxStream<'static, MultiPassEventKind>, Error> {
+        self.event.subscribe_with(options).await
+    }
+
+    pub async fn watch_identity(&self, did: &DID) -> Result<IdentityChangeStream, Error> {
+        if did == &self.did_key {
+            return Err(Error::OtherWithContext(
+                "changes to own identity are not announced".into(),
+            ));
+        }
+
+        let did = did.clone();
+        let stream = self.identity_changes.subscribe().await?;
+
+        Ok(stream
+            .filter_map(move |(id, change)| {
+                let change = (id == did).then_some(change);
+                futures::future::ready(change)
+            })
+            .boxed())
+    }
+}
+
+fn metadata_changes(
+    previous: &IndexMap<String, String>,
+    current: &IndexMap<String, String>,
+) -> Vec<IdentityChange> {
+    let mut changes = vec![];
+
+    for (key, value) in current {
+        if previous.get(key) != Some(value) {
+            changes.push(IdentityChange::MetadataKey {
+                key: key.clone(),
+                value: Some(value.clone()),
+            });
+        }
+    }
+
+    for key in previous.keys() {
+        if !current.contains_key(key) {
+            changes.push(IdentityChange::MetadataKey {
+                key: key.clone(),
+                value: None,
+            });
+        }
+    }
+
+    changes
 }
 
 impl IdentityStore {
@@ -2883,14 +4495,31 @@ impl IdentityStore {
             .map(|list| list.iter().any(|request| request.identity().eq(did)))
     }
 
+    /// Friend request event queued for the recipient, if it has yet to be sent
+    pub async fn queued_request(&self, recipient: &DID) -> Option<RequestResponsePayload> {
+        self.queue.get(recipient).await
+    }
+
+    /// Processes a friend request event that was received outside of pubsub
+    pub async fn import_request(
+        &self,
+        sender: &DID,
+        payload: RequestResponsePayload,
+    ) -> Result<(), Error> {
+        if payload.sender.ne(sender) {
+            return Err(Error::PublicKeyInvalid);
+        }
+
+        let mut store = self.clone();
+        store.check_request_message(payload, &mut None).await
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn list_incoming_request(&self) -> Result<Vec<FriendRequest>, Error> {
         self.list_all_raw_request().await.map(|list| {
             list.into_iter()
-                .filter_map(|request| match request {
-                    Request::In { did, date } => Some(FriendRequest::new(did, Some(date))),
-                    _ => None,
-                })
+                .filter(|request| request.r#type() == RequestType::Incoming)
+                .map(FriendRequest::from)
                 .collect::<Vec<_>>()
         })
     }
@@ -2906,10 +4535,8 @@ impl IdentityStore {
     pub async fn list_outgoing_request(&self) -> Result<Vec<FriendRequest>, Error> {
         self.list_all_raw_request().await.map(|list| {
             list.into_iter()
-                .filter_map(|request| match request {
-                    Request::Out { did, date } => Some(FriendRequest::new(did, Some(date))),
-                    _ => None,
-                })
+                .filter(|request| request.r#type() == RequestType::Outgoing)
+                .map(FriendRequest::from)
                 .collect::<Vec<_>>()
         })
     }
@@ -2930,10 +4557,13 @@ impl IdentityStore {
 
         let mut outgoing_request_date = None;
 
+        let message = payload.decrypt_message(self.root_document.keypair(), recipient);
+
         if store_request {
             let outgoing_request = Request::Out {
                 did: recipient.clone(),
                 date: payload.created.unwrap_or_else(Utc::now),
+                message: message.clone(),
             };
 
             outgoing_request_date.replace(outgoing_request.date());
@@ -3016,6 +4646,7 @@ impl IdentityStore {
                 self.emit_event(MultiPassEventKind::FriendRequestSent {
                     to: recipient.clone(),
                     date: outgoing_request_date.expect("date is valid"),
+                    message,
                 })
                 .await;
             }
@@ -3070,3 +4701,111 @@ async fn resolve_identity(store: &IdentityStore, identity: IdentityDocument) ->
     identity.set_metadata(metadata);
     identity
 }
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use chrono::{DateTime, Utc};
+    use parking_lot::Mutex;
+    use rust_ipfs::Keypair;
+    use serde::{Deserialize, Serialize};
+    use warp::crypto::DID;
+
+    use super::{
+        coalesce_lookup, Event, InflightLookups, RequestResponsePayload,
+        RequestResponsePayloadVersion,
+    };
+    use crate::store::{DidExt, PeerIdExt};
+
+    // Fields of the payload known to peers from before greeting messages
+    #[derive(Serialize, Deserialize)]
+    struct LegacyPayload {
+        #[serde(default)]
+        version: RequestResponsePayloadVersion,
+        sender: DID,
+        event: Event,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        created: Option<DateTime<Utc>>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        signature: Option<Vec<u8>>,
+    }
+
+    #[tokio::test]
+    async fn concurrent_lookups_share_request() {
+        let inflight = Arc::new(Mutex::new(InflightLookups::<DID>::default()));
+        let requests = Arc::new(AtomicUsize::new(0));
+
+        let dids = (0..3).map(|_| DID::default()).collect::<Vec<_>>();
+
+        let lookup = |list: &[DID]| {
+            let requests = requests.clone();
+            coalesce_lookup(
+                &inflight,
+                list.iter().cloned().collect(),
+                |did| did,
+                move |list| async move {
+                    requests.fetch_add(1, Ordering::SeqCst);
+                    // keeps the request in flight while the other lookup starts
+                    tokio::task::yield_now().await;
+                    list
+                },
+            )
+        };
+
+        let (first, second) = futures::join!(lookup(&dids[..2]), lookup(&dids[1..]));
+
+        // the second lookup only requests the did that is not already being looked up
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            first.into_iter().collect::<HashSet<_>>(),
+            dids[..2].iter().cloned().collect()
+        );
+        assert_eq!(
+            second.into_iter().collect::<HashSet<_>>(),
+            dids[1..].iter().cloned().collect()
+        );
+
+        // completed lookups are no longer shared
+        assert!(inflight.lock().lookups.is_empty());
+        lookup(&dids[..1]).await;
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn greeted_request_verifies_with_legacy_fields() -> anyhow::Result<()> {
+        let keypair = Keypair::generate_ed25519();
+        let recipient = Keypair::generate_ed25519().to_did()?;
+
+        let mut request = RequestResponsePayload::new_unsigned(&keypair, Event::Request);
+        request.set_message(&keypair, &recipient, "Hello, World")?;
+        let request = request.sign(&keypair)?;
+        request.verify()?;
+
+        // An older peer drops the greeting and its signature when deserializing
+        let mut legacy: LegacyPayload = serde_json::from_slice(&serde_json::to_vec(&request)?)?;
+        let signature = legacy.signature.take().expect("signed");
+        assert!(request
+            .sender
+            .to_public_key()?
+            .verify(&serde_json::to_vec(&legacy)?, &signature));
+        legacy.signature = Some(signature);
+
+        // and the request it forwards is still accepted, without the greeting
+        let forwarded: RequestResponsePayload =
+            serde_json::from_slice(&serde_json::to_vec(&legacy)?)?;
+        assert!(forwarded.message.is_none());
+        forwarded.verify()?;
+
+        let mut tampered = request.clone();
+        tampered.message = Some(b"Hello, Warp".to_vec());
+        assert!(tampered.verify().is_err());
+
+        let mut stripped = request;
+        stripped.message_signature = None;
+        assert!(stripped.verify().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,175 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use rust_ipfs::Ipfs;
+use uuid::Uuid;
+use warp::crypto::DID;
+use warp::error::Error;
+
+use super::{ds_key::DataStoreKey, MAX_PENDING_EVENTS};
+use crate::inbox::{PendingEvent, PendingEventKind};
+
+/// Queue of the events that were not acknowledged by the application, kept in the datastore
+#[derive(Clone)]
+pub struct PendingEvents {
+    ipfs: Ipfs,
+    events: Arc<tokio::sync::Mutex<Vec<PendingEvent>>>,
+}
+
+impl PendingEvents {
+    pub async fn load(ipfs: &Ipfs) -> Self {
+        let key = ipfs.pending_events();
+
+        let events = ipfs
+            .repo()
+            .data_store()
+            .get(key.as_bytes())
+            .await
+            .unwrap_or_default()
+            .and_then(|bytes| serde_json::from_slice::<Vec<PendingEvent>>(&bytes).ok())
+            .unwrap_or_default();
+
+        Self {
+            ipfs: ipfs.clone(),
+            events: Arc::new(tokio::sync::Mutex::new(events)),
+        }
+    }
+
+    pub async fn list(&self) -> Vec<PendingEvent> {
+        self.events.lock().await.clone()
+    }
+
+    /// Record an event, dropping the oldest events once [`MAX_PENDING_EVENTS`] is reached. The event is written
+    /// to the datastore before returning
+    pub async fn record(&self, kind: PendingEventKind, date: DateTime<Utc>) -> Result<Uuid, Error> {
+        let mut events = self.events.lock().await;
+
+        // an event that is already pending, such as a friend request sent again, is not recorded twice
+        if let Some(event) = events.iter().find(|event| event.kind == kind) {
+            return Ok(event.id);
+        }
+
+        let id = Uuid::new_v4();
+        events.push(PendingEvent { id, kind, date });
+
+        if events.len() > MAX_PENDING_EVENTS {
+            let excess = events.len() - MAX_PENDING_EVENTS;
+            events.drain(..excess);
+        }
+
+        self.save(&events).await?;
+        Ok(id)
+    }
+
+    /// Remove the events with the given ids, returning the amount of events removed
+    pub async fn acknowledge(&self, ids: &[Uuid]) -> Result<usize, Error> {
+        self.remove_where(|event| ids.contains(&event.id)).await
+    }
+
+    /// Remove the mentions of a conversation, or of a single message of the conversation
+    pub async fn acknowledge_mentions(
+        &self,
+        conversation_id: Uuid,
+        message_id: Option<Uuid>,
+    ) -> Result<usize, Error> {
+        self.remove_where(|event| match &event.kind {
+            PendingEventKind::Mention {
+                conversation_id: id,
+                message_id: m_id,
+                ..
+            } => *id == conversation_id && (message_id.is_none() || message_id == Some(*m_id)),
+            _ => false,
+        })
+        .await
+    }
+
+    /// Remove the pending friend request of `did`, once it was answered
+    pub async fn acknowledge_request(&self, did: &DID) -> Result<usize, Error> {
+        self.remove_where(
+            |event| matches!(&event.kind, PendingEventKind::FriendRequest { from } if from == did),
+        )
+        .await
+    }
+
+    async fn remove_where(&self, f: impl Fn(&PendingEvent) -> bool) -> Result<usize, Error> {
+        let mut events = self.events.lock().await;
+        let length = events.len();
+        events.retain(|event| !f(event));
+
+        let removed = length - events.len();
+        if removed > 0 {
+            self.save(&events).await?;
+        }
+
+        Ok(removed)
+    }
+
+    async fn save(&self, events: &[PendingEvent]) -> Result<(), Error> {
+        let key = self.ipfs.pending_events();
+        let bytes = serde_json::to_vec(events)?;
+        self.ipfs
+            .repo()
+            .data_store()
+            .put(key.as_bytes(), &bytes)
+            .await
+            .map_err(anyhow::Error::from)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+    use rust_ipfs::{Keypair, UninitializedIpfsDefault};
+    use uuid::Uuid;
+
+    use super::PendingEvents;
+    use crate::inbox::PendingEventKind;
+    use crate::store::PeerIdExt;
+
+    #[tokio::test]
+    async fn pending_events_are_kept_until_acknowledged() -> anyhow::Result<()> {
+        let ipfs = UninitializedIpfsDefault::new()
+            .start()
+            .await
+            .expect("constructed ipfs instance");
+
+        let sender = Keypair::generate_ed25519().to_did()?;
+        let conversation_id = Uuid::new_v4();
+
+        let events = PendingEvents::load(&ipfs).await;
+
+        let request = events
+            .record(
+                PendingEventKind::FriendRequest {
+                    from: sender.clone(),
+                },
+                Utc::now(),
+            )
+            .await?;
+
+        for _ in 0..2 {
+            events
+                .record(
+                    PendingEventKind::Mention {
+                        conversation_id,
+                        message_id: Uuid::new_v4(),
+                        sender: sender.clone(),
+                    },
+                    Utc::now(),
+                )
+                .await?;
+        }
+
+        // events survive a restart
+        let events = PendingEvents::load(&ipfs).await;
+        assert_eq!(events.list().await.len(), 3);
+
+        assert_eq!(events.acknowledge(&[request]).await?, 1);
+        assert_eq!(events.acknowledge_mentions(conversation_id, None).await?, 2);
+
+        let events = PendingEvents::load(&ipfs).await;
+        assert!(events.list().await.is_empty());
+        Ok(())
+    }
+}
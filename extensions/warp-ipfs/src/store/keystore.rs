@@ -3,6 +3,7 @@ use std::{
     fmt::Debug,
 };
 
+use chrono::{DateTime, Utc};
 use rust_ipfs::Keypair;
 use serde::{Deserialize, Serialize};
 use warp::{
@@ -13,6 +14,9 @@ use warp::{
 #[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct Keystore {
     recipient_key: HashMap<DID, BTreeSet<KeyEntry>>,
+    /// Epoch of our own key at the time a member joined
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    member_epochs: HashMap<DID, usize>,
 }
 
 #[allow(dead_code)]
@@ -34,8 +38,8 @@ impl Keystore {
                 if entry.get().iter().any(|e| e.key == key) {
                     return Err(Error::PublicKeyInvalid);
                 }
-                let len = entry.get().len();
-                entry.get_mut().insert(KeyEntry::new(len, key));
+                let epoch = entry.get().last().map(|e| e.id + 1).unwrap_or_default();
+                entry.get_mut().insert(KeyEntry::new(epoch, key));
             }
             Entry::Vacant(entry) => {
                 let mut set = BTreeSet::new();
@@ -47,6 +51,27 @@ impl Keystore {
         Ok(())
     }
 
+    /// Insert a key for a specific epoch, as shared by the recipient
+    pub fn insert_with_epoch<K: AsRef<[u8]>>(
+        &mut self,
+        keypair: &Keypair,
+        recipient: &DID,
+        epoch: usize,
+        key: K,
+    ) -> Result<(), Error> {
+        let key = super::ecdh_encrypt(keypair, None, key)?;
+
+        let list = self.recipient_key.entry(recipient.clone()).or_default();
+
+        if list.iter().any(|e| e.id == epoch || e.key == key) {
+            return Err(Error::PublicKeyInvalid);
+        }
+
+        list.insert(KeyEntry::new(epoch, key));
+
+        Ok(())
+    }
+
     pub fn exist(&self, recipient: &DID) -> bool {
         self.recipient_key.contains_key(recipient)
     }
@@ -61,6 +86,40 @@ impl Keystore {
             .ok_or(Error::PublicKeyDoesntExist)
     }
 
+    /// Returns the latest key of the recipient along with its epoch
+    pub fn get_latest_with_epoch(
+        &self,
+        keypair: &Keypair,
+        recipient: &DID,
+    ) -> Result<(usize, Vec<u8>), Error> {
+        self.recipient_key
+            .get(recipient)
+            .and_then(|list| {
+                list.last().and_then(|entry| {
+                    super::ecdh_decrypt(keypair, None, entry)
+                        .ok()
+                        .map(|key| (entry.id, key))
+                })
+            })
+            .ok_or(Error::PublicKeyDoesntExist)
+    }
+
+    pub fn latest_epoch(&self, recipient: &DID) -> Option<usize> {
+        self.recipient_key
+            .get(recipient)
+            .and_then(|list| list.last())
+            .map(|entry| entry.id)
+    }
+
+    /// Returns the key of the recipient for the given epoch
+    pub fn get(&self, keypair: &Keypair, recipient: &DID, epoch: usize) -> Result<Vec<u8>, Error> {
+        self.recipient_key
+            .get(recipient)
+            .and_then(|list| list.iter().find(|entry| entry.id == epoch))
+            .and_then(|entry| super::ecdh_decrypt(keypair, None, entry).ok())
+            .ok_or(Error::PublicKeyDoesntExist)
+    }
+
     pub fn get_all(&self, keypair: &Keypair, recipient: &DID) -> Result<Vec<Vec<u8>>, Error> {
         self.recipient_key
             .get(recipient)
@@ -72,6 +131,95 @@ impl Keystore {
             .ok_or(Error::PublicKeyDoesntExist)
     }
 
+    /// Returns every key of the recipient along with its epoch, starting from the given epoch
+    pub fn get_from_epoch(
+        &self,
+        keypair: &Keypair,
+        recipient: &DID,
+        epoch: usize,
+    ) -> Result<Vec<(usize, Vec<u8>)>, Error> {
+        self.recipient_key
+            .get(recipient)
+            .map(|list| {
+                list.iter()
+                    .filter(|entry| entry.id >= epoch)
+                    .filter_map(|entry| {
+                        super::ecdh_decrypt(keypair, None, entry)
+                            .ok()
+                            .map(|key| (entry.id, key))
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .ok_or(Error::PublicKeyDoesntExist)
+    }
+
+    /// Record the epoch at which the member joined
+    pub fn set_member_epoch(&mut self, member: &DID, epoch: usize) {
+        self.member_epochs.insert(member.clone(), epoch);
+    }
+
+    /// Epoch at which the member joined. Members that were part of the conversation from the start, or joined
+    /// prior to epochs being tracked, start at the first epoch
+    pub fn member_epoch(&self, member: &DID) -> usize {
+        self.member_epochs.get(member).copied().unwrap_or_default()
+    }
+
+    pub fn remove_member_epoch(&mut self, member: &DID) {
+        self.member_epochs.remove(member);
+    }
+
+    /// Mark every open epoch of the recipient as closed at `date`, such as when the recipient leaves or when our key is
+    /// rotated. Closed epochs are kept to decrypt existing messages
+    pub fn close_epochs(&mut self, recipient: &DID, date: DateTime<Utc>) {
+        let Some(list) = self.recipient_key.remove(recipient) else {
+            return;
+        };
+
+        let list = list
+            .into_iter()
+            .map(|mut entry| {
+                entry.closed.get_or_insert(date);
+                entry
+            })
+            .collect();
+
+        self.recipient_key.insert(recipient.clone(), list);
+    }
+
+    /// Returns true if the epoch of the recipient was closed
+    pub fn is_closed(&self, recipient: &DID, epoch: usize) -> bool {
+        self.recipient_key
+            .get(recipient)
+            .and_then(|list| list.iter().find(|entry| entry.id == epoch))
+            .is_some_and(|entry| entry.closed.is_some())
+    }
+
+    /// Remove the keys of recipients whose every epoch was closed before `before`, unless `needed` returns true for
+    /// the recipient (eg if messages encrypted with their keys remain). Returns the recipients that were removed
+    pub fn prune(&mut self, before: DateTime<Utc>, needed: impl Fn(&DID) -> bool) -> Vec<DID> {
+        let expired = self
+            .recipient_key
+            .iter()
+            .filter(|(_, list)| {
+                list.iter()
+                    .all(|entry| entry.closed.is_some_and(|closed| closed < before))
+            })
+            .map(|(recipient, _)| recipient.clone())
+            .filter(|recipient| !needed(recipient))
+            .collect::<Vec<_>>();
+
+        for recipient in &expired {
+            if let Some(list) = self.recipient_key.remove(recipient) {
+                for mut entry in list {
+                    entry.zeroize();
+                }
+            }
+            self.member_epochs.remove(recipient);
+        }
+
+        expired
+    }
+
     pub fn count(&self, recipient: &DID) -> Result<usize, Error> {
         self.recipient_key
             .get(recipient)
@@ -96,12 +244,34 @@ impl Keystore {
         }
         Err(Error::DecryptionError)
     }
+
+    /// Decrypt the data with the key of the given epoch, falling back to every known key of the recipient
+    /// if the epoch is unknown or the key does not match
+    pub fn decrypt_with_epoch(
+        &self,
+        keypair: &Keypair,
+        recipient: &DID,
+        epoch: Option<usize>,
+        data: &[u8],
+    ) -> Result<Vec<u8>, Error> {
+        if let Some(data) = epoch
+            .and_then(|epoch| self.get(keypair, recipient, epoch).ok())
+            .and_then(|key| Cipher::direct_decrypt(data, &key).ok())
+        {
+            return Ok(data);
+        }
+
+        self.try_decrypt(keypair, recipient, data)
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct KeyEntry {
     id: usize,
     key: Vec<u8>,
+    /// Date the epoch was closed, after which the key is no longer used to encrypt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    closed: Option<DateTime<Utc>>,
 }
 
 impl AsRef<[u8]> for KeyEntry {
@@ -124,7 +294,11 @@ impl Debug for KeyEntry {
 
 impl KeyEntry {
     pub fn new(id: usize, key: Vec<u8>) -> Self {
-        Self { id, key }
+        Self {
+            id,
+            key,
+            closed: None,
+        }
     }
 }
 
@@ -151,6 +325,7 @@ impl Eq for KeyEntry {}
 #[cfg(test)]
 mod test {
     use crate::store::PeerIdExt;
+    use chrono::{Duration, Utc};
 
     use super::Keystore;
     use rust_ipfs::Keypair;
@@ -242,4 +417,100 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn keystore_epochs() -> anyhow::Result<()> {
+        let mut keystore = Keystore::default();
+
+        let keypair = Keypair::generate_ed25519();
+        let recipient = DID::default();
+
+        let key_1 = generate::<32>();
+        let key_2 = generate::<32>();
+        let key_5 = generate::<32>();
+
+        keystore.insert(&keypair, &recipient, key_1)?;
+        keystore.insert(&keypair, &recipient, key_2)?;
+        keystore.insert_with_epoch(&keypair, &recipient, 5, key_5)?;
+
+        // an epoch can only be set once
+        assert!(keystore
+            .insert_with_epoch(&keypair, &recipient, 1, generate::<32>())
+            .is_err());
+
+        assert_eq!(keystore.latest_epoch(&recipient), Some(5));
+        assert_eq!(keystore.get(&keypair, &recipient, 1)?, key_2);
+        assert_eq!(
+            keystore.get_latest_with_epoch(&keypair, &recipient)?,
+            (5, key_5.to_vec())
+        );
+
+        let cipher_message = Cipher::direct_encrypt(b"message", &key_1)?;
+        // the wrong epoch falls back to the other keys
+        let decrypted_message =
+            keystore.decrypt_with_epoch(&keypair, &recipient, Some(5), &cipher_message)?;
+
+        assert_eq!(decrypted_message, b"message");
+
+        Ok(())
+    }
+
+    #[test]
+    fn keystore_member_epochs() -> anyhow::Result<()> {
+        let mut keystore = Keystore::default();
+
+        let keypair = Keypair::generate_ed25519();
+        let own_did = keypair.to_did()?;
+        let member = DID::default();
+
+        for _ in 0..3 {
+            keystore.insert(&keypair, &own_did, generate::<32>())?;
+        }
+
+        assert_eq!(keystore.member_epoch(&member), 0);
+
+        keystore.set_member_epoch(&member, 2);
+
+        let keys = keystore.get_from_epoch(&keypair, &own_did, keystore.member_epoch(&member))?;
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].0, 2);
+
+        keystore.remove_member_epoch(&member);
+        assert_eq!(keystore.member_epoch(&member), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn keystore_prune_closed_epochs() -> anyhow::Result<()> {
+        let mut keystore = Keystore::default();
+
+        let keypair = Keypair::generate_ed25519();
+        let departed = DID::default();
+        let member = DID::default();
+        let now = Utc::now();
+
+        keystore.insert(&keypair, &departed, generate::<32>())?;
+        keystore.insert(&keypair, &departed, generate::<32>())?;
+        keystore.insert(&keypair, &member, generate::<32>())?;
+        keystore.set_member_epoch(&departed, 1);
+
+        keystore.close_epochs(&departed, now - Duration::days(40));
+        assert!(keystore.is_closed(&departed, 0));
+        assert!(keystore.is_closed(&departed, 1));
+        assert!(!keystore.is_closed(&member, 0));
+
+        let before = now - Duration::days(30);
+
+        // keys that are still needed are kept
+        assert!(keystore.prune(before, |_| true).is_empty());
+        assert!(keystore.exist(&departed));
+
+        assert_eq!(keystore.prune(before, |_| false), vec![departed.clone()]);
+        assert!(!keystore.exist(&departed));
+        assert!(keystore.exist(&member));
+        assert_eq!(keystore.member_epoch(&departed), 0);
+
+        Ok(())
+    }
 }
@@ -1,10 +1,14 @@
 mod attachment;
+mod chunk;
 mod community_task;
+mod search;
+mod sequence;
+pub mod snippet;
 mod task;
 
 use community_task::CommunityTaskCommand;
 use futures_timer::Delay;
-use task::ConversationTaskCommand;
+use task::{ConversationTaskCommand, ConversationTaskSender};
 
 use async_rt::AbortableJoinHandle;
 use bytes::Bytes;
@@ -12,7 +16,7 @@ use std::borrow::BorrowMut;
 use std::path::PathBuf;
 use std::time::Duration;
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     path::Path,
     sync::Arc,
 };
@@ -27,50 +31,77 @@ use futures::{
 use indexmap::{IndexMap, IndexSet};
 use ipld_core::cid::Cid;
 
-use rust_ipfs::{Ipfs, PeerId};
+use rust_ipfs::{p2p::MultiaddrExt, Ipfs, PeerId};
 
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use super::community::CommunityInviteDocument;
 use super::topics::ConversationTopic;
-use super::{document::root::RootDocumentMap, ds_key::DataStoreKey, PeerIdExt};
+use super::{
+    document::root::{RootDocumentChanges, RootDocumentMap},
+    ds_key::DataStoreKey,
+    PeerIdExt,
+};
+use crate::bundle::{BundleImport, BundleMessage};
+use crate::config::Discovery as DiscoveryConfig;
+use crate::doctor::{ConversationDamage, ConversationRepairReport, DamagedConversation};
+use crate::readiness::{Readiness, Subsystem};
+use crate::rt;
 use crate::store::CommunityJoinEvents;
 use crate::store::{
-    conversation::ConversationDocument,
+    conversation::{derive_direct_conversation_id, ConversationDocument},
     discovery::Discovery,
-    event_subscription::EventSubscription,
-    files::FileStore,
-    generate_shared_topic,
+    event_subscription::{bounded_stream, EventSubscription},
+    files::{file_sha256, matches_sha256, FileStore},
     identity::IdentityStore,
     keystore::Keystore,
     payload::{PayloadBuilder, PayloadMessage},
     sign_serde,
+    sync::SelectiveSync,
     topics::PeerTopic,
+    usage::UsageTracker,
     ConversationEvents, ConversationRequestKind, ConversationRequestResponse, DidExt,
 };
 
 use crate::store::community::CommunityDocument;
+use crate::store::encrypted::EncryptedStore;
 use chrono::{DateTime, Utc};
 use warp::raygun::community::{
     Community, CommunityChannel, CommunityChannelPermission, CommunityChannelType, CommunityInvite,
     CommunityPermission, CommunityRole, RoleId,
 };
-use warp::raygun::{ConversationImage, GroupPermissionOpt, Message};
+use warp::raygun::{
+    ConversationImage, ConversationStats, GroupPermissionOpt, HistoryVisibility, Message,
+    RetentionPolicy, SearchQuery, SearchResultStream,
+};
+use warp::subscription::SubscriptionOptions;
 use warp::{
-    constellation::ConstellationProgressStream,
+    constellation::{ConstellationProgressStream, Progression},
     crypto::DID,
     error::Error,
     multipass::MultiPassEventKind,
     raygun::{
-        AttachmentEventStream, Conversation, ConversationType, Location, MessageEvent,
-        MessageEventKind, MessageOptions, MessageReference, MessageStatus, Messages, PinState,
-        RayGunEventKind, ReactionState,
+        processor::MessageProcessorPipeline, AttachmentEventStream, AttachmentOptions,
+        Conversation, ConversationType, Location, MessageEvent, MessageEventKind, MessageOptions,
+        MessageReference, MessageStatus, Messages, PinState, RayGunEventKind, ReactionState,
     },
 };
 
 const CHAT_DIRECTORY: &str = "chat_media";
 
+/// Interval in which the conversation tasks are checked for having stopped
+const SUPERVISOR_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Delay before restarting a conversation task that failed again after being restarted.
+/// Doubled for every consecutive failure
+const RESTART_BACKOFF: Duration = Duration::from_secs(1);
+
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Time a restarted conversation task has to keep running for its previous failures to be forgotten
+const RESTART_RESET_PERIOD: Duration = Duration::from_secs(600);
+
 pub type DownloadStream = BoxStream<'static, Result<Bytes, std::io::Error>>;
 
 #[derive(Clone)]
@@ -80,18 +111,26 @@ pub struct MessageStore {
 }
 
 impl MessageStore {
+    /// Create the store. Conversations are loaded in the background, with [`Subsystem::Messaging`] set as ready
+    /// once they are. Calls made in the meantime wait for the conversations to be loaded
+    #[allow(clippy::too_many_arguments)]
     pub async fn new(
         ipfs: &Ipfs,
         discovery: Discovery,
         file: &FileStore,
         event: EventSubscription<RayGunEventKind>,
         identity: &IdentityStore,
+        processors: MessageProcessorPipeline,
+        usage: &UsageTracker,
+        selective_sync: &SelectiveSync,
+        search: Option<EncryptedStore>,
+        readiness: &Readiness,
     ) -> Self {
         tracing::info!("Initializing MessageStore");
 
         let root = identity.root_document().clone();
 
-        let mut inner = ConversationInner {
+        let inner = ConversationInner {
             ipfs: ipfs.clone(),
             conversation_task: HashMap::new(),
             community_task: HashMap::new(),
@@ -101,24 +140,27 @@ impl MessageStore {
             discovery,
             file: file.clone(),
             event,
+            processors,
+            usage: usage.clone(),
+            selective_sync: selective_sync.clone(),
+            search,
+            restarts: HashMap::new(),
             queue: Default::default(),
         };
 
-        if let Err(e) = inner.migrate().await {
-            tracing::warn!(error = %e, "unable to migrate conversations to root document");
-        }
-
-        inner.load_conversations().await;
-
         let inner = Arc::new(tokio::sync::RwLock::new(inner));
 
         let task = ConversationTask {
             inner: inner.clone(),
             ipfs: ipfs.clone(),
             identity: identity.clone(),
+            readiness: readiness.clone(),
         };
 
-        let _handle = async_rt::task::spawn_abortable(task.run());
+        // the lock is taken before returning so calls to the store wait for the conversations to be loaded
+        let guard = inner.clone().write_owned().await;
+
+        let _handle = rt::spawn_abortable(task.run(guard));
 
         Self { inner, _handle }
     }
@@ -152,6 +194,28 @@ impl MessageStore {
         })
     }
 
+    /// Same as [`MessageStore::get_conversation_stream`], but buffers at most [`SubscriptionOptions::capacity`] events
+    pub async fn get_conversation_stream_with(
+        &self,
+        conversation_id: Uuid,
+        options: SubscriptionOptions,
+    ) -> Result<impl Stream<Item = MessageEventKind>, Error> {
+        let mut rx = self.subscribe(conversation_id).await?.subscribe();
+        let metrics = options.metrics();
+        let stream = async_stream::stream! {
+            loop {
+                match rx.recv().await {
+                    Ok(event) => yield event,
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(amount)) => {
+                        metrics.record_dropped(amount)
+                    }
+                };
+            }
+        };
+        Ok(bounded_stream(stream, options))
+    }
+
     pub async fn get_community_stream(
         &self,
         community_id: Uuid,
@@ -214,6 +278,17 @@ impl MessageStore {
         inner.subscribe_community(id).await
     }
 
+    pub async fn set_conversation_excluded(
+        &self,
+        conversation_id: Uuid,
+        excluded: bool,
+    ) -> Result<(), Error> {
+        let inner = &mut *self.inner.write().await;
+        inner
+            .set_conversation_excluded(conversation_id, excluded)
+            .await
+    }
+
     pub async fn create_conversation(&self, did: &DID) -> Result<Conversation, Error> {
         let inner = &mut *self.inner.write().await;
         inner.create_conversation(did).await
@@ -272,7 +347,9 @@ impl MessageStore {
                 response: tx,
             })
             .await;
-        rx.await.map_err(anyhow::Error::from)?
+        let mut message = rx.await.map_err(anyhow::Error::from)??;
+        inner.processors.apply(&mut message);
+        Ok(message)
     }
 
     pub async fn get_messages(
@@ -294,7 +371,8 @@ impl MessageStore {
                 response: tx,
             })
             .await;
-        rx.await.map_err(anyhow::Error::from)?
+        let messages = rx.await.map_err(anyhow::Error::from)??;
+        Ok(inner.processors.apply_messages(messages))
     }
 
     pub async fn messages_count(&self, conversation_id: Uuid) -> Result<usize, Error> {
@@ -312,6 +390,24 @@ impl MessageStore {
         rx.await.map_err(anyhow::Error::from)?
     }
 
+    pub async fn conversation_stats(
+        &self,
+        conversation_id: Uuid,
+    ) -> Result<ConversationStats, Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::GetStats { response: tx })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
     pub async fn get_message_reference(
         &self,
         conversation_id: Uuid,
@@ -356,6 +452,68 @@ impl MessageStore {
         rx.await.map_err(anyhow::Error::from)?
     }
 
+    /// Search the messages of a conversation, or of every conversation loaded on this device if none is given
+    pub async fn search_messages(
+        &self,
+        conversation_id: Option<Uuid>,
+        query: SearchQuery,
+    ) -> Result<SearchResultStream, Error> {
+        let senders = {
+            let inner = &*self.inner.read().await;
+            match conversation_id {
+                Some(conversation_id) => {
+                    let conversation_meta = inner
+                        .conversation_task
+                        .get(&conversation_id)
+                        .ok_or(Error::InvalidConversation)?;
+                    vec![(conversation_id, conversation_meta.command_tx.clone())]
+                }
+                // conversations excluded from sync are not loaded just to be searched
+                None => inner
+                    .conversation_task
+                    .iter()
+                    .filter(|(id, _)| !inner.selective_sync.is_conversation_excluded(**id))
+                    .map(|(id, meta)| (*id, meta.command_tx.clone()))
+                    .collect::<Vec<_>>(),
+            }
+        };
+
+        let single = conversation_id.is_some();
+        let mut results = vec![];
+
+        for (conversation_id, mut command_tx) in senders {
+            let (tx, rx) = oneshot::channel();
+            let _ = command_tx
+                .send(ConversationTaskCommand::SearchMessages {
+                    query: query.clone(),
+                    response: tx,
+                })
+                .await;
+
+            let result = rx
+                .await
+                .map_err(anyhow::Error::from)
+                .map_err(Error::from)
+                .and_then(|result| result);
+
+            match result {
+                Ok(list) => results.extend(list),
+                Err(e) if single => return Err(e),
+                Err(e) => {
+                    tracing::warn!(%conversation_id, error = %e, "unable to search conversation")
+                }
+            }
+        }
+
+        results.sort_by(|a, b| b.message().date().cmp(&a.message().date()));
+
+        if let Some(limit) = query.limit() {
+            results.truncate(limit);
+        }
+
+        Ok(futures::stream::iter(results).boxed())
+    }
+
     pub async fn update_conversation_name(
         &self,
         conversation_id: Uuid,
@@ -402,9 +560,43 @@ impl MessageStore {
 
     pub async fn delete_conversation(&self, conversation_id: Uuid) -> Result<(), Error> {
         let inner = &mut *self.inner.write().await;
+
+        let document = inner.get(conversation_id).await?;
+        if document
+            .retention_policy()
+            .is_some_and(|policy| policy.legal_hold)
+        {
+            return Err(Error::MessageRetained);
+        }
+
         inner.delete_conversation(conversation_id, true).await
     }
 
+    pub async fn merge_conversations(&self, primary: Uuid, duplicate: Uuid) -> Result<(), Error> {
+        let inner = &mut *self.inner.write().await;
+        inner.merge_conversations(primary, duplicate, true).await
+    }
+
+    /// Scan the conversation index for damaged documents. With `repair`, documents that cannot be resolved locally are
+    /// fetched from participants and shuttle nodes and the index is rebuilt. Documents with an invalid signature are
+    /// reported but left in the index
+    pub async fn repair_conversations(
+        &self,
+        repair: bool,
+    ) -> Result<ConversationRepairReport, Error> {
+        let inner = &mut *self.inner.write().await;
+        inner.repair_conversations(repair).await
+    }
+
+    pub async fn replay_events(
+        &self,
+        conversation_id: Uuid,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<BoxStream<'static, MessageEventKind>, Error> {
+        let inner = &*self.inner.read().await;
+        inner.replay_events(conversation_id, since).await
+    }
+
     pub async fn add_participant(&self, conversation_id: Uuid, did: &DID) -> Result<(), Error> {
         let inner = &*self.inner.read().await;
         let conversation_meta = inner
@@ -464,6 +656,24 @@ impl MessageStore {
         rx.await.map_err(anyhow::Error::from)?
     }
 
+    pub async fn mark_read(&self, conversation_id: Uuid, message_id: Uuid) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::MarkRead {
+                message_id,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
     pub async fn send_message(
         &self,
         conversation_id: Uuid,
@@ -612,6 +822,7 @@ impl MessageStore {
         message_id: Option<Uuid>,
         locations: Vec<Location>,
         messages: Vec<String>,
+        options: AttachmentOptions,
     ) -> Result<(Uuid, AttachmentEventStream), Error> {
         let inner = &*self.inner.read().await;
         let conversation_meta = inner
@@ -626,6 +837,7 @@ impl MessageStore {
                 message_id,
                 locations,
                 lines: messages,
+                options,
                 response: tx,
             })
             .await;
@@ -683,6 +895,93 @@ impl MessageStore {
         rx.await.map_err(anyhow::Error::from)?
     }
 
+    pub async fn save_attachment<P: AsRef<Path>>(
+        &self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        file: &str,
+        path: P,
+    ) -> Result<ConstellationProgressStream, Error> {
+        let path = path.as_ref().to_path_buf();
+
+        if path.is_dir() {
+            return Err(Error::InvalidPath);
+        }
+
+        let file_name = path
+            .file_name()
+            .ok_or(Error::InvalidPath)?
+            .to_string_lossy()
+            .to_string();
+
+        let message = self.get_message(conversation_id, message_id).await?;
+
+        let attachment = message
+            .attachments()
+            .iter()
+            .find(|attachment| attachment.name() == file)
+            .ok_or(Error::FileNotFound)?;
+
+        let expected_size = attachment.size();
+        let expected_hash = attachment.hash().sha256();
+
+        // write to a hidden file next to the destination so the file only appears once it is complete
+        let partial_path = path.with_file_name(format!(".{file_name}.part"));
+
+        let mut stream = self
+            .download(conversation_id, message_id, file, &partial_path)
+            .await?;
+
+        let name = file.to_owned();
+
+        let stream = async_stream::stream! {
+            while let Some(progress) = stream.next().await {
+                let Progression::ProgressComplete { total, .. } = progress else {
+                    // failures are cleaned up by the download itself
+                    yield progress;
+                    continue;
+                };
+
+                // the file written must match the size and, if the sender recorded it, the hash of the attachment
+                let result = match fs::file_size(&partial_path).await {
+                    Ok(size) if size == expected_size => {
+                        verify_attachment(&partial_path, expected_hash.as_deref()).await
+                    }
+                    Ok(size) => Err(Error::InvalidLength {
+                        context: name.clone(),
+                        current: size,
+                        minimum: Some(expected_size),
+                        maximum: Some(expected_size),
+                    }),
+                    Err(e) => Err(Error::from(e)),
+                };
+
+                let result = match result {
+                    Ok(()) => fs::rename(&partial_path, &path).await.map_err(Error::from),
+                    Err(e) => Err(e),
+                };
+
+                match result {
+                    Ok(_) => {
+                        yield Progression::ProgressComplete { name: name.clone(), total };
+                    }
+                    Err(error) => {
+                        if let Err(e) = fs::remove_file(&partial_path).await {
+                            tracing::error!("Error removing file: {e}");
+                        }
+                        yield Progression::ProgressFailed {
+                            name: name.clone(),
+                            last_size: total,
+                            error,
+                        };
+                    }
+                }
+            }
+        };
+
+        Ok(stream.boxed())
+    }
+
     pub async fn send_event(
         &self,
         conversation_id: Uuid,
@@ -836,6 +1135,112 @@ impl MessageStore {
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
+
+    /// Payloads queued for the recipient that have yet to be sent
+    pub async fn export_queue(&self, recipient: &DID) -> (Vec<Bytes>, Vec<BundleMessage>) {
+        let inner = &*self.inner.read().await;
+
+        let conversations = inner
+            .queue
+            .get(recipient)
+            .map(|items| {
+                items
+                    .iter()
+                    .filter(|item| !item.sent)
+                    .map(|item| item.data.clone())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut messages = vec![];
+
+        for (conversation_id, conversation_meta) in inner.conversation_task.iter() {
+            let (tx, rx) = oneshot::channel();
+            let _ = conversation_meta
+                .command_tx
+                .clone()
+                .send(ConversationTaskCommand::ExportQueue {
+                    recipient: recipient.clone(),
+                    response: tx,
+                })
+                .await;
+
+            let Ok(items) = rx.await else {
+                continue;
+            };
+
+            messages.extend(items.into_iter().map(|(topic, data)| BundleMessage {
+                conversation_id: *conversation_id,
+                topic,
+                data,
+            }));
+        }
+
+        (conversations, messages)
+    }
+
+    /// Processes payloads exported by the sender as if they were received over pubsub. Conversations are
+    /// processed first so the messages of a conversation the recipient was invited to can be processed
+    pub async fn import_queue(
+        &self,
+        sender: &DID,
+        conversations: Vec<Bytes>,
+        messages: Vec<BundleMessage>,
+        summary: &mut BundleImport,
+    ) {
+        let inner = &mut *self.inner.write().await;
+
+        for data in conversations {
+            match process_messaging_payload(inner, &data, Some(sender)).await {
+                Ok(_) => summary.conversations += 1,
+                Err(e) => {
+                    tracing::warn!(%sender, error = %e, "unable to import conversation payload");
+                    summary.rejected += 1;
+                }
+            }
+        }
+
+        let Ok(sender_peer_id) = sender.to_peer_id() else {
+            summary.rejected += messages.len();
+            return;
+        };
+
+        for message in messages {
+            let BundleMessage {
+                conversation_id,
+                topic,
+                data,
+            } = message;
+
+            let Some(conversation_meta) = inner.conversation_task.get(&conversation_id) else {
+                tracing::warn!(%conversation_id, %sender, "conversation of imported payload does not exist");
+                summary.rejected += 1;
+                continue;
+            };
+
+            let (tx, rx) = oneshot::channel();
+            let _ = conversation_meta
+                .command_tx
+                .clone()
+                .send(ConversationTaskCommand::ImportPayload {
+                    sender: sender_peer_id,
+                    topic,
+                    data,
+                    response: tx,
+                })
+                .await;
+
+            match rx.await {
+                Ok(Ok(_)) => summary.messages += 1,
+                Ok(Err(e)) => {
+                    tracing::warn!(%conversation_id, %sender, error = %e, "unable to import conversation payload");
+                    summary.rejected += 1;
+                }
+                Err(_) => summary.rejected += 1,
+            }
+        }
+    }
+
     pub async fn set_description(
         &self,
         conversation_id: Uuid,
@@ -857,6 +1262,87 @@ impl MessageStore {
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
+
+    pub async fn set_history_visibility(
+        &self,
+        conversation_id: Uuid,
+        visibility: HistoryVisibility,
+    ) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::SetHistoryVisibility {
+                visibility,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    pub async fn set_slow_mode(&self, conversation_id: Uuid, slow_mode: u64) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::SetSlowMode {
+                slow_mode,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    pub async fn set_retention_policy(
+        &self,
+        conversation_id: Uuid,
+        policy: Option<RetentionPolicy>,
+    ) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        let conversation_meta = inner
+            .conversation_task
+            .get(&conversation_id)
+            .ok_or(Error::InvalidConversation)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::SetRetentionPolicy {
+                policy,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
+
+    /// Rotate our key in every group conversation, such as after a device was revoked
+    pub async fn rotate_group_keys(&self) {
+        let inner = &*self.inner.read().await;
+        for (conversation_id, conversation_meta) in inner.conversation_task.iter() {
+            let (tx, rx) = oneshot::channel();
+            let _ = conversation_meta
+                .command_tx
+                .clone()
+                .send(ConversationTaskCommand::RotateKey { response: tx })
+                .await;
+
+            let result = rx.await.map_err(anyhow::Error::from).map_err(Error::from);
+            if let Err(e) = result.and_then(|result| result) {
+                tracing::warn!(%conversation_id, error = %e, "unable to rotate conversation key");
+            }
+        }
+    }
+
     pub async fn archived_conversation(&self, conversation_id: Uuid) -> Result<(), Error> {
         let inner = &*self.inner.read().await;
         let conversation_meta = inner
@@ -1526,6 +2012,29 @@ impl MessageStore {
             .await;
         rx.await.map_err(anyhow::Error::from)?
     }
+    pub async fn edit_community_channel_slow_mode(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        slow_mode: u64,
+    ) -> Result<(), Error> {
+        let inner = &*self.inner.read().await;
+        let community_meta = inner
+            .community_task
+            .get(&community_id)
+            .ok_or(Error::InvalidCommunity)?;
+        let (tx, rx) = oneshot::channel();
+        let _ = community_meta
+            .command_tx
+            .clone()
+            .send(CommunityTaskCommand::EditCommunityChannelSlowMode {
+                channel_id,
+                slow_mode,
+                response: tx,
+            })
+            .await;
+        rx.await.map_err(anyhow::Error::from)?
+    }
     pub async fn grant_community_channel_permission(
         &mut self,
         community_id: Uuid,
@@ -2044,10 +2553,20 @@ struct ConversationTask {
     inner: Arc<tokio::sync::RwLock<ConversationInner>>,
     ipfs: Ipfs,
     identity: IdentityStore,
+    readiness: Readiness,
 }
 
 impl ConversationTask {
-    async fn run(self) {
+    async fn run(self, mut guard: tokio::sync::OwnedRwLockWriteGuard<ConversationInner>) {
+        guard.hydrate().await;
+        drop(guard);
+        self.readiness.set_ready(Subsystem::Messaging);
+
+        // A replica neither receives conversation requests nor delivers the queued messages
+        if self.identity.is_replica() {
+            return;
+        }
+
         let mut identity_stream = self
             .identity
             .subscribe()
@@ -2062,8 +2581,12 @@ impl ConversationTask {
 
         pin_mut!(stream);
 
+        let mut root_changes = self.inner.read().await.root.subscribe_changes();
+
         let mut queue_timer = Delay::new(Duration::from_secs(5));
 
+        let mut supervisor_timer = Delay::new(SUPERVISOR_INTERVAL);
+
         loop {
             tokio::select! {
                 biased;
@@ -2073,42 +2596,21 @@ impl ConversationTask {
                     }
                 }
                 Some(message) = stream.next() => {
-                    let payload = match PayloadMessage::<ConversationEvents>::from_bytes(&message.data) {
-                        Ok(payload) => payload,
-                        Err(e) => {
-                            tracing::warn!("Failed to parse payload data: {e}");
-                            continue;
-                        }
-                    };
-
-                    let sender_peer_id = payload.sender();
-
-                    let sender = match sender_peer_id.to_did() {
-                        Ok(did) => did,
-                        Err(e) => {
-                            tracing::warn!(sender = %sender_peer_id, error = %e, "unable to convert to did");
-                            continue;
-                        }
-                    };
-
-                    let event = match payload.message(self.identity.root_document().keypair()) {
-                        Ok(m) => m,
-                        Err(e) => {
-                            tracing::error!(%sender, error = %e, "unable to obtain message from payload");
-                            continue
-                        }
-                    };
-
-                    if let Err(e) = process_conversation(&mut *self.inner.write().await, *sender_peer_id, event).await {
-                        tracing::error!(%sender, error = %e, "error processing conversation");
+                    if let Err(e) = process_messaging_payload(&mut *self.inner.write().await, &message.data, None).await {
+                        tracing::error!(from = ?message.source, error = %e, "error processing conversation");
                     }
                 }
+                Ok(changes) = root_changes.recv() => {
+                    self.inner.write().await.apply_root_changes(changes).await;
+                }
                 _ = &mut queue_timer => {
                     let _ = _process_queue(&mut *self.inner.write().await).await;
                     queue_timer.reset(Duration::from_secs(5));
                 }
-
-
+                _ = &mut supervisor_timer => {
+                    self.inner.write().await.supervise_conversations().await;
+                    supervisor_timer.reset(SUPERVISOR_INTERVAL);
+                }
             }
         }
     }
@@ -2116,9 +2618,65 @@ impl ConversationTask {
 
 #[derive(Clone, Debug)]
 struct ConversationInnerMeta {
-    pub command_tx: mpsc::Sender<ConversationTaskCommand>,
+    pub command_tx: ConversationTaskSender,
     pub handle: AbortableJoinHandle<()>,
 }
+
+/// Restart state of a conversation task that stopped unexpectedly
+#[derive(Clone, Copy, Debug)]
+struct ConversationRestart {
+    /// Consecutive failures of the task
+    failures: u32,
+    /// Set while the task is stopped and waiting to be restarted
+    degraded: bool,
+    /// Time of the next restart attempt while degraded, otherwise the time of the last restart
+    at: Instant,
+}
+
+impl ConversationRestart {
+    /// The first failure is restarted immediately
+    fn delay(&self) -> Duration {
+        if self.failures <= 1 {
+            return Duration::ZERO;
+        }
+        RESTART_BACKOFF
+            .saturating_mul(2u32.saturating_pow(self.failures - 2))
+            .min(MAX_RESTART_BACKOFF)
+    }
+
+    /// Record that the task stopped, scheduling its restart after the backoff of its consecutive failures
+    fn stopped(previous: Option<Self>, now: Instant) -> Self {
+        let failures = previous.map(|restart| restart.failures).unwrap_or_default() + 1;
+        let mut restart = ConversationRestart {
+            failures,
+            degraded: true,
+            at: now,
+        };
+        restart.at = now + restart.delay();
+        restart
+    }
+
+    /// Whether the restart of a degraded task is due
+    fn is_due(&self, now: Instant) -> bool {
+        !self.degraded || now >= self.at
+    }
+
+    /// Whether the failures are still counted, which is the case until the task ran for the reset period
+    fn is_active(&self, now: Instant) -> bool {
+        self.degraded || now.duration_since(self.at) < RESTART_RESET_PERIOD
+    }
+
+    fn restarted(&mut self, now: Instant) {
+        self.degraded = false;
+        self.at = now;
+    }
+
+    fn restart_failed(&mut self, now: Instant) {
+        self.failures += 1;
+        self.at = now + self.delay();
+    }
+}
+
 #[derive(Clone)]
 struct CommunityInnerMeta {
     pub command_tx: mpsc::Sender<CommunityTaskCommand>,
@@ -2135,6 +2693,12 @@ struct ConversationInner {
     event: EventSubscription<RayGunEventKind>,
     identity: IdentityStore,
     discovery: Discovery,
+    processors: MessageProcessorPipeline,
+    usage: UsageTracker,
+    selective_sync: SelectiveSync,
+    /// Store in which the search index of each conversation is kept, if local data can be encrypted
+    search: Option<EncryptedStore>,
+    restarts: HashMap<Uuid, ConversationRestart>,
 
     // Note: Temporary
     queue: HashMap<DID, Vec<Queue>>,
@@ -2142,67 +2706,397 @@ struct ConversationInner {
 
 impl ConversationInner {
     async fn migrate(&mut self) -> Result<(), Error> {
+        self.migrate_direct_conversation_generation().await
+    }
+
+    /// Record the derivation generation of direct conversations created prior to generations being tracked
+    async fn migrate_direct_conversation_generation(&mut self) -> Result<(), Error> {
+        let keypair = self.root.keypair();
+        let own_did = self.identity.did_key();
+
+        let mut migrated = false;
+        let mut stream = self.list_stream().await;
+
+        while let Some(mut document) = stream.next().await {
+            if document.conversation_type() != ConversationType::Direct
+                || document.generation.is_some()
+            {
+                continue;
+            }
+
+            let conversation_id = document.id();
+
+            let Some(recipient) = document
+                .recipients()
+                .into_iter()
+                .find(|did| own_did.ne(did))
+            else {
+                continue;
+            };
+
+            match derive_direct_conversation_id(keypair, &recipient, 0) {
+                Ok(id) if id == conversation_id => {}
+                Ok(_) => {
+                    tracing::warn!(%conversation_id, "conversation id does not match the derived id. Skipping migration");
+                    continue;
+                }
+                Err(e) => {
+                    tracing::warn!(%conversation_id, error = %e, "unable to derive conversation id");
+                    continue;
+                }
+            }
+
+            document.generation = Some(0);
+            self.root.set_conversation_document(&document).await?;
+            migrated = true;
+        }
+
+        if migrated {
+            self.identity.export_root_document().await?;
+        }
+
         Ok(())
     }
 
+    /// Migrate the conversations and start their tasks
+    async fn hydrate(&mut self) {
+        let started = Instant::now();
+
+        // A replica loads the conversations as they were exported, since its root document cannot be changed
+        if !self.identity.is_replica() {
+            if let Err(e) = self.migrate().await {
+                tracing::warn!(error = %e, "unable to migrate conversations to root document");
+            }
+
+            match self.root.compact_keystore_map().await {
+                Ok(0) => {}
+                Ok(removed) => tracing::info!(
+                    removed,
+                    "removed keystores of conversations that no longer exist"
+                ),
+                Err(e) => tracing::warn!(error = %e, "unable to compact keystore map"),
+            }
+        }
+
+        self.load_conversations().await;
+
+        tracing::info!(
+            conversations = self.conversation_task.len(),
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            "Conversations loaded"
+        );
+    }
+
     async fn load_conversations(&mut self) {
         let mut stream = self.list_stream().await;
         while let Some(conversation) = stream.next().await {
             let id = conversation.id();
 
             if let Err(e) = self.create_conversation_task(id).await {
-                tracing::error!(id = %id, error = %e, "Failed to load conversation");
+                tracing::error!(id = %id, error = %e, "Failed to load conversation");
+            }
+        }
+
+        let ipfs = &self.ipfs;
+        let key = ipfs.messaging_queue();
+
+        if let Ok(data) = futures::future::ready(
+            ipfs.repo()
+                .data_store()
+                .get(key.as_bytes())
+                .await
+                .unwrap_or_default()
+                .ok_or(Error::Other),
+        )
+        .and_then(|bytes| async move {
+            let cid_str = String::from_utf8_lossy(&bytes).to_string();
+
+            let cid = cid_str.parse::<Cid>().map_err(anyhow::Error::from)?;
+
+            Ok(cid)
+        })
+        .and_then(|cid| async move {
+            ipfs.get_dag(cid)
+                .local()
+                .deserialized::<HashMap<_, _>>()
+                .await
+                .map_err(anyhow::Error::from)
+                .map_err(Error::from)
+        })
+        .await
+        {
+            self.queue = data;
+        }
+    }
+
+    /// Peers that may hold conversation documents, being known participants, shuttle nodes and connected peers
+    async fn conversation_providers(&self) -> Vec<PeerId> {
+        let mut providers = self
+            .discovery
+            .list()
+            .await
+            .iter()
+            .map(|entry| entry.peer_id())
+            .collect::<HashSet<_>>();
+
+        if let DiscoveryConfig::Shuttle { addresses } = self.discovery.discovery_config() {
+            providers.extend(addresses.iter().filter_map(|addr| addr.peer_id()));
+        }
+
+        providers.extend(self.ipfs.connected().await.unwrap_or_default());
+        providers.into_iter().collect()
+    }
+
+    async fn repair_conversations(
+        &mut self,
+        repair: bool,
+    ) -> Result<ConversationRepairReport, Error> {
+        let map = self.root.get_conversation_map().await?;
+
+        let mut report = ConversationRepairReport {
+            checked: map.len(),
+            damaged: vec![],
+        };
+
+        let providers = match repair {
+            true => self.conversation_providers().await,
+            false => vec![],
+        };
+
+        let mut index = BTreeMap::new();
+        let mut restored = vec![];
+
+        for (key, cid) in map {
+            let local = self
+                .ipfs
+                .get_dag(cid)
+                .local()
+                .deserialized::<ConversationDocument>()
+                .await;
+
+            let (document, fetched) = match local {
+                Ok(document) => (Some(document), false),
+                Err(_) if repair && !providers.is_empty() => {
+                    let document = self
+                        .ipfs
+                        .get_dag(cid)
+                        .providers(&providers)
+                        .timeout(Duration::from_secs(30))
+                        .deserialized::<ConversationDocument>()
+                        .await
+                        .ok();
+                    (document, true)
+                }
+                Err(_) => (None, false),
+            };
+
+            let Some(document) = document else {
+                tracing::warn!(%key, %cid, "conversation document is unresolvable");
+                report.damaged.push(DamagedConversation {
+                    key: key.clone(),
+                    cid,
+                    damage: ConversationDamage::Unresolvable,
+                    repaired: false,
+                });
+                // kept in the index so the document can still be resolved once a provider is reachable
+                index.insert(key, cid);
+                continue;
+            };
+
+            if let Err(e) = document.verify() {
+                tracing::warn!(%key, %cid, error = %e, "conversation document has an invalid signature");
+                report.damaged.push(DamagedConversation {
+                    key: key.clone(),
+                    cid,
+                    damage: ConversationDamage::InvalidSignature,
+                    repaired: false,
+                });
+                // never dropped automatically, since the conversation and its messages would be lost for good. The
+                // document is left in the index to be reviewed, and deleted with the conversation if not trusted
+                index.insert(key, cid);
+                continue;
+            }
+
+            let id = document.id();
+
+            if fetched {
+                report.damaged.push(DamagedConversation {
+                    key: key.clone(),
+                    cid,
+                    damage: ConversationDamage::Unresolvable,
+                    repaired: true,
+                });
+                if !document.deleted {
+                    restored.push(id);
+                }
+            }
+
+            if id.to_string() != key {
+                tracing::warn!(%key, %cid, conversation_id = %id, "conversation document is indexed under the wrong id");
+                report.damaged.push(DamagedConversation {
+                    key,
+                    cid,
+                    damage: ConversationDamage::MismatchedId(id),
+                    repaired: repair,
+                });
+                if !document.deleted {
+                    restored.push(id);
+                }
+            }
+
+            // a document already indexed under its own id takes precedence over a misplaced one
+            index.entry(id.to_string()).or_insert(cid);
+        }
+
+        if !repair || report.damaged.iter().all(|damaged| !damaged.repaired) {
+            return Ok(report);
+        }
+
+        self.root.set_conversation_map(index.clone()).await?;
+        self.identity.export_root_document().await?;
+
+        let stale = self
+            .conversation_task
+            .keys()
+            .filter(|id| !index.contains_key(&id.to_string()))
+            .copied()
+            .collect::<Vec<_>>();
+
+        for id in stale {
+            if let Some(mut meta) = self.conversation_task.remove(&id) {
+                meta.command_tx.close_channel();
+                meta.handle.abort();
+            }
+        }
+
+        for id in restored {
+            if self.conversation_task.contains_key(&id) {
+                continue;
+            }
+
+            if let Err(e) = self.create_conversation_task(id).await {
+                tracing::error!(%id, error = %e, "unable to load repaired conversation");
             }
         }
 
-        let ipfs = &self.ipfs;
-        let key = ipfs.messaging_queue();
+        Ok(report)
+    }
 
-        if let Ok(data) = futures::future::ready(
-            ipfs.repo()
-                .data_store()
-                .get(key.as_bytes())
-                .await
-                .unwrap_or_default()
-                .ok_or(Error::Other),
-        )
-        .and_then(|bytes| async move {
-            let cid_str = String::from_utf8_lossy(&bytes).to_string();
+    /// Restart the conversation tasks that stopped without the conversation being deleted, backing off
+    /// exponentially for tasks that keep failing. The task is rehydrated from the stored conversation document.
+    async fn supervise_conversations(&mut self) {
+        let now = self.identity.clock().instant();
 
-            let cid = cid_str.parse::<Cid>().map_err(anyhow::Error::from)?;
+        self.restarts.retain(|_, restart| restart.is_active(now));
 
-            Ok(cid)
-        })
-        .and_then(|cid| async move {
-            ipfs.get_dag(cid)
-                .local()
-                .deserialized::<HashMap<_, _>>()
-                .await
-                .map_err(anyhow::Error::from)
-                .map_err(Error::from)
-        })
-        .await
-        {
-            self.queue = data;
+        let stopped = self
+            .conversation_task
+            .iter()
+            .filter(|(_, meta)| meta.command_tx.is_closed())
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+
+        for conversation_id in stopped {
+            let restart = self.restarts.get(&conversation_id).copied();
+
+            if restart.is_some_and(|restart| !restart.is_due(now)) {
+                continue;
+            }
+
+            // A task also stops once its conversation is deleted, which does not require a restart
+            match self.get(conversation_id).await {
+                Ok(_) => {}
+                Err(Error::InvalidConversation) => {
+                    if let Some(meta) = self.conversation_task.remove(&conversation_id) {
+                        meta.handle.abort();
+                    }
+                    self.restarts.remove(&conversation_id);
+                    continue;
+                }
+                Err(e) => {
+                    tracing::warn!(%conversation_id, error = %e, "unable to resolve conversation of stopped task");
+                }
+            }
+
+            let mut restart = match restart {
+                Some(restart) if restart.degraded => restart,
+                previous => {
+                    let restart = ConversationRestart::stopped(previous, now);
+                    tracing::warn!(%conversation_id, failures = restart.failures, "conversation task stopped unexpectedly");
+                    self.event
+                        .emit(RayGunEventKind::ConversationDegraded { conversation_id })
+                        .await;
+                    restart
+                }
+            };
+
+            if !restart.is_due(now) {
+                self.restarts.insert(conversation_id, restart);
+                continue;
+            }
+
+            if let Some(meta) = self.conversation_task.get(&conversation_id) {
+                meta.handle.abort();
+            }
+
+            match self.create_conversation_task(conversation_id).await {
+                Ok(_) => {
+                    tracing::info!(%conversation_id, "conversation task restarted");
+                    restart.restarted(now);
+                    self.event
+                        .emit(RayGunEventKind::ConversationRecovered { conversation_id })
+                        .await;
+                }
+                Err(e) => {
+                    restart.restart_failed(now);
+                    tracing::error!(%conversation_id, error = %e, retry_in = ?restart.delay(), "unable to restart conversation task");
+                }
+            }
+
+            self.restarts.insert(conversation_id, restart);
         }
     }
 
     async fn create_conversation_task(&mut self, conversation_id: Uuid) -> Result<(), Error> {
-        let (ctx, crx) = mpsc::channel(256);
+        let (ctx, crx) = ConversationTaskSender::channel(256);
 
-        let task = task::ConversationTask::new(
-            conversation_id,
-            &self.ipfs,
-            &self.root,
-            &self.identity,
-            &self.file,
-            &self.discovery,
-            crx,
-            self.event.clone(),
-        )
-        .await?;
+        // Conversations excluded from sync on this device are only loaded once they are accessed
+        let handle = match self
+            .selective_sync
+            .is_conversation_excluded(conversation_id)
+        {
+            true => rt::spawn_abortable(task::ConversationTask::run_on_demand(
+                conversation_id,
+                self.ipfs.clone(),
+                self.root.clone(),
+                self.identity.clone(),
+                self.file.clone(),
+                self.discovery.clone(),
+                crx,
+                self.event.clone(),
+                self.processors.clone(),
+                self.usage.clone(),
+                self.search.clone(),
+            )),
+            false => {
+                let task = task::ConversationTask::new(
+                    conversation_id,
+                    &self.ipfs,
+                    &self.root,
+                    &self.identity,
+                    &self.file,
+                    &self.discovery,
+                    crx,
+                    self.event.clone(),
+                    self.processors.clone(),
+                    &self.usage,
+                    self.search.clone(),
+                )
+                .await?;
 
-        let handle = async_rt::task::spawn_abortable(task.run());
+                rt::spawn_abortable(task.run())
+            }
+        };
 
         tracing::info!(%conversation_id, "started conversation");
 
@@ -2216,6 +3110,55 @@ impl ConversationInner {
         Ok(())
     }
 
+    /// Load the conversations added or replaced by another device of the identity. The tasks of the conversations
+    /// that were replaced are restarted so they use the newer document
+    async fn apply_root_changes(&mut self, changes: RootDocumentChanges) {
+        for conversation_id in changes.conversations {
+            let existing = self.conversation_task.remove(&conversation_id);
+
+            if let Some(meta) = existing.as_ref() {
+                meta.handle.abort();
+            }
+
+            if let Err(e) = self.create_conversation_task(conversation_id).await {
+                tracing::error!(%conversation_id, error = %e, "Failed to load synchronized conversation");
+                continue;
+            }
+
+            if existing.is_none() {
+                self.event
+                    .emit(RayGunEventKind::ConversationCreated { conversation_id })
+                    .await;
+            }
+        }
+    }
+
+    /// Exclude the conversation from being loaded on this device until it is accessed, or include it again.
+    /// The task of the conversation is restarted so the change applies right away
+    async fn set_conversation_excluded(
+        &mut self,
+        conversation_id: Uuid,
+        excluded: bool,
+    ) -> Result<(), Error> {
+        if !self.conversation_task.contains_key(&conversation_id) {
+            return Err(Error::InvalidConversation);
+        }
+
+        if !self
+            .selective_sync
+            .set_conversation_excluded(conversation_id, excluded)
+            .await?
+        {
+            return Ok(());
+        }
+
+        if let Some(meta) = self.conversation_task.remove(&conversation_id) {
+            meta.handle.abort();
+        }
+
+        self.create_conversation_task(conversation_id).await
+    }
+
     async fn create_conversation(&mut self, did: &DID) -> Result<Conversation, Error> {
         //TODO: maybe use root document to directly check
         // if self.with_friends.load(Ordering::SeqCst) && !self.identity.is_friend(did_key).await? {
@@ -2251,8 +3194,26 @@ impl ConversationInner {
         //     return Err(Error::ConversationLimitReached);
         // }
 
-        let mut conversation =
-            ConversationDocument::new_direct(self.root.keypair(), [own_did.clone(), did.clone()])?;
+        // Use the first generation of the id that was never used so the conversation does not collide
+        // with a conversation that was previously deleted
+        let mut generation = 0;
+        while self
+            .root
+            .has_conversation_document(derive_direct_conversation_id(
+                self.root.keypair(),
+                did,
+                generation,
+            )?)
+            .await
+        {
+            generation += 1;
+        }
+
+        let mut conversation = ConversationDocument::new_direct(
+            self.root.keypair(),
+            [own_did.clone(), did.clone()],
+            generation,
+        )?;
 
         let convo_id = conversation.id();
 
@@ -2264,6 +3225,7 @@ impl ConversationInner {
 
         let event = ConversationEvents::NewConversation {
             recipient: own_did.clone(),
+            generation,
         };
 
         let payload = PayloadBuilder::new(self.root.keypair(), event)
@@ -2688,6 +3650,247 @@ impl ConversationInner {
         Ok(())
     }
 
+    /// Reconstruct the events of a conversation from its current document. Membership and conversation details
+    /// are not timestamped, so they are always replayed, while pinned messages are limited to those sent after `since`
+    pub async fn replay_events(
+        &self,
+        conversation_id: Uuid,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<BoxStream<'static, MessageEventKind>, Error> {
+        let document = self.get(conversation_id).await?;
+        let own_did = self.identity.did_key();
+
+        let mut events = vec![];
+
+        events.extend(
+            document
+                .recipients()
+                .into_iter()
+                .filter(|did| own_did.ne(did))
+                .map(|recipient| MessageEventKind::RecipientAdded {
+                    conversation_id,
+                    recipient,
+                }),
+        );
+
+        events.extend(document.excluded.keys().cloned().map(|recipient| {
+            MessageEventKind::RecipientRemoved {
+                conversation_id,
+                recipient,
+            }
+        }));
+
+        if let Some(name) = document.name.clone() {
+            events.push(MessageEventKind::ConversationNameUpdated {
+                conversation_id,
+                name,
+            });
+        }
+
+        if document.description.is_some() {
+            events.push(MessageEventKind::ConversationDescriptionChanged {
+                conversation_id,
+                description: document.description.clone(),
+            });
+        }
+
+        if document.history_visibility != HistoryVisibility::default() {
+            events.push(MessageEventKind::ConversationHistoryVisibilityChanged {
+                conversation_id,
+                visibility: document.history_visibility,
+            });
+        }
+
+        if document.slow_mode > 0 {
+            events.push(MessageEventKind::ConversationSlowModeChanged {
+                conversation_id,
+                slow_mode: document.slow_mode,
+            });
+        }
+
+        if let Some(policy) = document.retention_policy() {
+            events.push(MessageEventKind::ConversationRetentionPolicyChanged {
+                conversation_id,
+                policy: Some(policy),
+            });
+        }
+
+        if document.icon.is_some() {
+            events.push(MessageEventKind::ConversationUpdatedIcon { conversation_id });
+        }
+
+        if document.banner.is_some() {
+            events.push(MessageEventKind::ConversationUpdatedBanner { conversation_id });
+        }
+
+        let added = document
+            .permissions
+            .iter()
+            .flat_map(|(did, permissions)| {
+                permissions
+                    .iter()
+                    .map(|permission| (did.clone(), *permission))
+            })
+            .collect::<Vec<_>>();
+
+        if !added.is_empty() {
+            events.push(MessageEventKind::ConversationPermissionsUpdated {
+                conversation_id,
+                added,
+                removed: vec![],
+            });
+        }
+
+        if document.messages.is_some() {
+            let messages = document.get_message_list(&self.ipfs).await?;
+            events.extend(
+                messages
+                    .iter()
+                    .filter(|message| message.pinned())
+                    .filter(|message| since.map_or(true, |since| message.date() >= since))
+                    .map(|message| MessageEventKind::MessagePinned {
+                        conversation_id,
+                        message_id: message.id(),
+                    }),
+            );
+        }
+
+        Ok(futures::stream::iter(events).boxed())
+    }
+
+    /// Fold the messages of a duplicate direct conversation into `primary` and remove the duplicate
+    pub async fn merge_conversations(
+        &mut self,
+        primary: Uuid,
+        duplicate: Uuid,
+        broadcast: bool,
+    ) -> Result<(), Error> {
+        if primary == duplicate {
+            return Err(Error::InvalidConversation);
+        }
+
+        let primary_document = self.get(primary).await?;
+        let duplicate_document = self.get(duplicate).await?;
+
+        let recipients = primary_document.recipients();
+
+        if primary_document.conversation_type() != ConversationType::Direct
+            || duplicate_document.conversation_type() != ConversationType::Direct
+            || recipients.len() != duplicate_document.recipients().len()
+            || !duplicate_document
+                .recipients()
+                .iter()
+                .all(|did| recipients.contains(did))
+        {
+            return Err(Error::OtherWithContext(
+                "only duplicate direct conversations can be merged".into(),
+            ));
+        }
+
+        let messages = duplicate_document
+            .get_message_list(&self.ipfs)
+            .await?
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        let conversation_meta = self
+            .conversation_task
+            .get(&primary)
+            .ok_or(Error::InvalidConversation)?;
+
+        let (tx, rx) = oneshot::channel();
+        let _ = conversation_meta
+            .command_tx
+            .clone()
+            .send(ConversationTaskCommand::MergeMessages {
+                messages,
+                response: tx,
+            })
+            .await;
+
+        let merged = rx.await.map_err(anyhow::Error::from)??;
+
+        tracing::info!(conversation_id = %primary, %duplicate, merged, "merged duplicate conversation");
+
+        self.delete(duplicate).await?;
+
+        if broadcast {
+            let own_did = self.identity.did_key();
+            let event = ConversationEvents::MergeConversations {
+                conversation_id: primary,
+                duplicate_id: duplicate,
+            };
+
+            for recipient in recipients.iter().filter(|did| own_did.ne(did)) {
+                let peer_id = recipient.to_peer_id()?;
+
+                let payload = PayloadBuilder::new(self.root.keypair(), event.clone())
+                    .add_recipient(recipient)?
+                    .from_ipfs(&self.ipfs)
+                    .await?;
+
+                let payload_bytes = payload.to_bytes()?;
+
+                let peers = self.ipfs.pubsub_peers(Some(recipient.messaging())).await?;
+
+                if !peers.contains(&peer_id)
+                    || (peers.contains(&peer_id)
+                        && self
+                            .ipfs
+                            .pubsub_publish(recipient.messaging(), payload_bytes.clone())
+                            .await
+                            .is_err())
+                {
+                    tracing::warn!(conversation_id = %primary, "Unable to publish to topic. Queuing event");
+                    self.queue_event(
+                        recipient.clone(),
+                        Queue::direct(peer_id, recipient.messaging(), payload_bytes.to_vec()),
+                    )
+                    .await;
+                }
+            }
+        }
+
+        self.event
+            .emit(RayGunEventKind::ConversationMerged { primary, duplicate })
+            .await;
+
+        Ok(())
+    }
+
+    /// Find a direct conversation with the same recipient as `conversation_id`, returning the ids
+    /// of the primary and duplicate conversation if one exist.
+    /// The conversation with the highest generation is used as the primary so that both parties
+    /// select the same conversation.
+    async fn find_duplicate_conversation(&self, conversation_id: Uuid) -> Option<(Uuid, Uuid)> {
+        let document = self.get(conversation_id).await.ok()?;
+
+        if document.conversation_type() != ConversationType::Direct {
+            return None;
+        }
+
+        let recipients = document.recipients();
+
+        let other = self.list().await.into_iter().find(|other| {
+            other.id() != conversation_id
+                && other.conversation_type() == ConversationType::Direct
+                && other.recipients().len() == recipients.len()
+                && other
+                    .recipients()
+                    .iter()
+                    .all(|did| recipients.contains(did))
+        })?;
+
+        let key = |document: &ConversationDocument| {
+            (document.generation.unwrap_or_default(), document.id())
+        };
+
+        match key(&document) > key(&other) {
+            true => Some((document.id(), other.id())),
+            false => Some((other.id(), document.id())),
+        }
+    }
+
     pub async fn delete_conversation(
         &mut self,
         conversation_id: Uuid,
@@ -2889,7 +4092,7 @@ impl ConversationInner {
         )
         .await?;
 
-        let handle = async_rt::task::spawn_abortable(task.run());
+        let handle = rt::spawn_abortable(task.run());
 
         tracing::info!(%community_id, "started community");
 
@@ -3064,18 +4267,57 @@ impl ConversationInner {
     }
 }
 
+/// Check the file written at `path` against the sha256 hash recorded for the attachment, if any
+async fn verify_attachment(path: &Path, expected: Option<&str>) -> Result<(), Error> {
+    let Some(expected) = expected else {
+        return Ok(());
+    };
+
+    let digest = file_sha256(path).await?;
+
+    match matches_sha256(expected, &digest) {
+        true => Ok(()),
+        false => Err(Error::OtherWithContext(
+            "attachment does not match its hash".into(),
+        )),
+    }
+}
+
+/// Processes a payload sent to the messaging topic of the identity. If `expected_sender` is supplied,
+/// the payload is rejected when it was sent by a different identity
+async fn process_messaging_payload(
+    this: &mut ConversationInner,
+    data: &[u8],
+    expected_sender: Option<&DID>,
+) -> Result<(), Error> {
+    let payload = PayloadMessage::<ConversationEvents>::from_bytes(data)?;
+
+    let sender_peer_id = *payload.sender();
+    let sender = sender_peer_id.to_did()?;
+
+    if expected_sender.is_some_and(|expected| expected != &sender) {
+        return Err(Error::PublicKeyInvalid);
+    }
+
+    let event = payload.message(this.root.keypair())?;
+
+    process_conversation(this, sender_peer_id, event).await
+}
+
 async fn process_conversation(
     this: &mut ConversationInner,
     sender: PeerId,
     event: ConversationEvents,
 ) -> Result<(), Error> {
     match event {
-        ConversationEvents::NewConversation { recipient } => {
+        ConversationEvents::NewConversation {
+            recipient,
+            generation,
+        } => {
             let keypair = this.root.keypair();
             let did = this.identity.did_key();
             tracing::info!("New conversation event received from {recipient}");
-            let conversation_id =
-                generate_shared_topic(keypair, &recipient, Some("direct-conversation"))?;
+            let conversation_id = derive_direct_conversation_id(keypair, &recipient, generation)?;
 
             if this.contains(conversation_id).await {
                 tracing::warn!(%conversation_id, "Conversation exist");
@@ -3093,7 +4335,7 @@ async fn process_conversation(
             let list = [did.clone(), recipient];
             tracing::info!(%conversation_id, "Creating conversation");
 
-            let convo = ConversationDocument::new_direct(keypair, list)?;
+            let convo = ConversationDocument::new_direct(keypair, list, generation)?;
             let conversation_type = convo.conversation_type();
 
             this.set_document(convo).await?;
@@ -3105,6 +4347,16 @@ async fn process_conversation(
             this.event
                 .emit(RayGunEventKind::ConversationCreated { conversation_id })
                 .await;
+
+            // Both parties may have created a conversation at the same time (or one party may have missed
+            // the deletion of a previous conversation), in which case the duplicate is merged
+            if let Some((primary, duplicate)) =
+                this.find_duplicate_conversation(conversation_id).await
+            {
+                if let Err(e) = this.merge_conversations(primary, duplicate, true).await {
+                    tracing::warn!(conversation_id = %primary, %duplicate, error = %e, "unable to merge duplicate conversation");
+                }
+            }
         }
         ConversationEvents::NewGroupConversation { mut conversation } => {
             let did = this.identity.did_key();
@@ -3199,6 +4451,32 @@ async fn process_conversation(
 
             this.delete_conversation(conversation_id, false).await?;
         }
+        ConversationEvents::MergeConversations {
+            conversation_id,
+            duplicate_id,
+        } => {
+            tracing::trace!(%conversation_id, %duplicate_id, "Merge conversation event received");
+
+            // The duplicate may have already been merged locally
+            if !this.contains(duplicate_id).await {
+                return Ok(());
+            }
+
+            let sender = sender.to_did()?;
+
+            for id in [conversation_id, duplicate_id] {
+                let conversation = this.get(id).await?;
+                if !conversation.recipients().contains(&sender) {
+                    return Err(anyhow::anyhow!(
+                        "Conversation exist but did not match condition required"
+                    )
+                    .into());
+                }
+            }
+
+            this.merge_conversations(conversation_id, duplicate_id, false)
+                .await?;
+        }
         ConversationEvents::NewCommunityInvite {
             community_id,
             invite,
@@ -3572,3 +4850,62 @@ async fn _process_queue(this: &mut ConversationInner) {
         this.save_queue().await;
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use web_time::Instant;
+
+    use super::{ConversationRestart, MAX_RESTART_BACKOFF, RESTART_BACKOFF, RESTART_RESET_PERIOD};
+
+    #[test]
+    fn first_failure_is_restarted_immediately() {
+        let now = Instant::now();
+        let restart = ConversationRestart::stopped(None, now);
+
+        assert_eq!(restart.failures, 1);
+        assert!(restart.degraded);
+        assert!(restart.is_due(now));
+    }
+
+    #[test]
+    fn repeated_failures_back_off_exponentially() {
+        let now = Instant::now();
+
+        let mut restart = ConversationRestart::stopped(None, now);
+        restart.restarted(now);
+
+        // the task crashed again shortly after it was restarted
+        let now = now + Duration::from_secs(1);
+        let mut restart = ConversationRestart::stopped(Some(restart), now);
+        assert_eq!(restart.failures, 2);
+        assert_eq!(restart.delay(), RESTART_BACKOFF);
+        assert!(!restart.is_due(now));
+        assert!(restart.is_due(now + RESTART_BACKOFF));
+
+        // failing to restart doubles the delay
+        let now = now + RESTART_BACKOFF;
+        restart.restart_failed(now);
+        assert_eq!(restart.delay(), RESTART_BACKOFF * 2);
+        assert!(!restart.is_due(now + RESTART_BACKOFF));
+        assert!(restart.is_due(now + RESTART_BACKOFF * 2));
+
+        for _ in 0..32 {
+            restart.restart_failed(now);
+        }
+        assert_eq!(restart.delay(), MAX_RESTART_BACKOFF);
+    }
+
+    #[test]
+    fn failures_are_reset_after_running_for_reset_period() {
+        let now = Instant::now();
+
+        let mut restart = ConversationRestart::stopped(None, now);
+        assert!(restart.is_active(now + RESTART_RESET_PERIOD));
+
+        restart.restarted(now);
+        assert!(restart.is_active(now));
+        assert!(!restart.is_active(now + RESTART_RESET_PERIOD));
+    }
+}
@@ -33,6 +33,7 @@ pub struct AttachmentStream {
     locations: Vec<Location>,
     directory: Directory,
     lines: Option<Vec<String>>,
+    max_message_size: usize,
     keystore: Either<DID, Keystore>,
     file_store: FileStore,
     state: AttachmentState,
@@ -70,6 +71,7 @@ impl AttachmentStream {
             reply_to: None,
             locations: Vec::new(),
             lines: None,
+            max_message_size: MAX_MESSAGE_SIZE,
             state: AttachmentState::Initialize,
             progressed: Some(SelectAll::new()),
             successful_attachment: Vec::new(),
@@ -92,17 +94,17 @@ impl AttachmentStream {
             return Ok(self);
         }
 
-        if lines_value_length > MAX_MESSAGE_SIZE {
+        if lines_value_length > self.max_message_size {
             tracing::error!(
                 current_size = lines_value_length,
-                max = MAX_MESSAGE_SIZE,
+                max = self.max_message_size,
                 "length of message is invalid"
             );
             return Err(Error::InvalidLength {
                 context: "message".into(),
                 current: lines_value_length,
                 minimum: None,
-                maximum: Some(MAX_MESSAGE_SIZE),
+                maximum: Some(self.max_message_size),
             });
         }
 
@@ -134,27 +136,8 @@ impl AttachmentStream {
             return Err(Error::NoAttachments);
         }
 
-        let root_directory = self.file_store.root_directory();
-
-        if !root_directory.has_item(CHAT_DIRECTORY) {
-            let new_dir = Directory::new(CHAT_DIRECTORY);
-            root_directory.add_directory(new_dir)?;
-        }
-
-        let mut media_dir = root_directory
-            .get_last_directory_from_path(&format!("/{CHAT_DIRECTORY}/{conversation_id}"))?;
-
-        // if the directory that returned is the chat directory, this means we should create
-        // the directory specific to the conversation
-        if media_dir.name() == CHAT_DIRECTORY {
-            let new_dir = Directory::new(&conversation_id.to_string());
-            media_dir.add_directory(new_dir)?;
-            media_dir = media_dir.get_last_directory_from_path(&conversation_id.to_string())?;
-        }
-
-        assert_eq!(media_dir.name(), conversation_id.to_string());
         self.locations = files;
-        self.directory = media_dir;
+        self.directory = media_directory(&self.file_store, conversation_id)?;
         Ok(self)
     }
 
@@ -162,6 +145,42 @@ impl AttachmentStream {
         self.reply_to = message_id.into();
         self
     }
+
+    /// Override whether metadata is stripped from images before they are uploaded
+    pub fn set_strip_metadata(mut self, strip: Option<bool>) -> Self {
+        self.file_store = self.file_store.with_strip_metadata(strip);
+        self
+    }
+
+    /// Maximum amount of characters allowed in the message. Must be set before [`AttachmentStream::set_lines`]
+    pub fn set_max_message_size(mut self, size: usize) -> Self {
+        self.max_message_size = size;
+        self
+    }
+}
+
+/// Directory holding the media of the conversation, which is created if it does not exist
+pub fn media_directory(file_store: &FileStore, conversation_id: Uuid) -> Result<Directory, Error> {
+    let root_directory = file_store.root_directory();
+
+    if !root_directory.has_item(CHAT_DIRECTORY) {
+        let new_dir = Directory::new(CHAT_DIRECTORY);
+        root_directory.add_directory(new_dir)?;
+    }
+
+    let mut media_dir = root_directory
+        .get_last_directory_from_path(&format!("/{CHAT_DIRECTORY}/{conversation_id}"))?;
+
+    // if the directory that returned is the chat directory, this means we should create
+    // the directory specific to the conversation
+    if media_dir.name() == CHAT_DIRECTORY {
+        let new_dir = Directory::new(&conversation_id.to_string());
+        media_dir.add_directory(new_dir)?;
+        media_dir = media_dir.get_last_directory_from_path(&conversation_id.to_string())?;
+    }
+
+    assert_eq!(media_dir.name(), conversation_id.to_string());
+    Ok(media_dir)
 }
 
 impl Stream for AttachmentStream {
@@ -0,0 +1,287 @@
+//! Splitting of payloads that exceed the pubsub payload limit into chunks, and their reassembly.
+//!
+//! Each chunk carries the sha256 hash and size of the full payload so the reassembled payload can be checked
+//! before it is handed to the rest of the conversation task. The payload itself is still signed and verified as usual.
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use bytes::{Bytes, BytesMut};
+use rust_ipfs::PeerId;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use warp::crypto::hash::sha256_hash;
+use warp::error::Error;
+use web_time::Instant;
+
+use crate::store::{MAX_CHUNKED_PAYLOAD_SIZE, MAX_PUBSUB_CHUNK_SIZE};
+use crate::time::Clock;
+
+/// Maximum amount of payloads that can be pending reassembly at once
+const MAX_PENDING_PAYLOADS: usize = 32;
+
+/// Duration after which a payload that has not been completed is discarded
+const PENDING_PAYLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PayloadChunk {
+    id: Uuid,
+    index: u32,
+    total: u32,
+    size: u64,
+    hash: Vec<u8>,
+    data: Bytes,
+}
+
+impl PayloadChunk {
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Error> {
+        let chunk: Self = cbor4ii::serde::from_slice(data).map_err(std::io::Error::other)?;
+        Ok(chunk)
+    }
+
+    pub fn to_bytes(&self) -> Result<Bytes, Error> {
+        cbor4ii::serde::to_vec(Vec::new(), self)
+            .map_err(std::io::Error::other)
+            .map_err(Error::from)
+            .map(Bytes::from)
+    }
+}
+
+/// Split the payload into chunks if it exceeds [`MAX_PUBSUB_CHUNK_SIZE`].
+/// Payloads within the limit are returned as is so they remain readable by peers that do not support chunking.
+pub fn split(payload: Bytes) -> Result<Vec<Bytes>, Error> {
+    if payload.len() <= MAX_PUBSUB_CHUNK_SIZE {
+        return Ok(vec![payload]);
+    }
+
+    if payload.len() > MAX_CHUNKED_PAYLOAD_SIZE {
+        return Err(Error::InvalidLength {
+            context: "payload".into(),
+            current: payload.len(),
+            minimum: None,
+            maximum: Some(MAX_CHUNKED_PAYLOAD_SIZE),
+        });
+    }
+
+    let id = Uuid::new_v4();
+    let hash = sha256_hash(&payload, None);
+    let total = payload.len().div_ceil(MAX_PUBSUB_CHUNK_SIZE) as u32;
+
+    payload
+        .chunks(MAX_PUBSUB_CHUNK_SIZE)
+        .enumerate()
+        .map(|(index, data)| {
+            PayloadChunk {
+                id,
+                index: index as u32,
+                total,
+                size: payload.len() as u64,
+                hash: hash.clone(),
+                data: payload.slice_ref(data),
+            }
+            .to_bytes()
+        })
+        .collect()
+}
+
+struct PendingPayload {
+    total: u32,
+    size: u64,
+    hash: Vec<u8>,
+    chunks: BTreeMap<u32, Bytes>,
+    received: usize,
+    started: Instant,
+}
+
+/// Reassembles chunked payloads received from peers
+#[derive(Default)]
+pub struct ChunkAssembler {
+    pending: HashMap<(Option<PeerId>, Uuid), PendingPayload>,
+    clock: Clock,
+}
+
+impl ChunkAssembler {
+    pub fn new(clock: &Clock) -> Self {
+        Self {
+            pending: HashMap::new(),
+            clock: clock.clone(),
+        }
+    }
+
+    /// Insert a chunk received from `source`, returning the full payload once every chunk has been received
+    pub fn insert(
+        &mut self,
+        source: Option<PeerId>,
+        chunk: PayloadChunk,
+    ) -> Result<Option<Bytes>, Error> {
+        let clock = &self.clock;
+        self.pending
+            .retain(|_, pending| clock.elapsed(pending.started) < PENDING_PAYLOAD_TIMEOUT);
+
+        let PayloadChunk {
+            id,
+            index,
+            total,
+            size,
+            hash,
+            data,
+        } = chunk;
+
+        if size > MAX_CHUNKED_PAYLOAD_SIZE as u64
+            || total == 0
+            || index >= total
+            || data.len() > MAX_PUBSUB_CHUNK_SIZE
+            || (total as u64) > size.div_ceil(MAX_PUBSUB_CHUNK_SIZE as u64)
+        {
+            return Err(Error::InvalidLength {
+                context: "chunk".into(),
+                current: data.len(),
+                minimum: None,
+                maximum: Some(MAX_PUBSUB_CHUNK_SIZE),
+            });
+        }
+
+        let key = (source, id);
+
+        if !self.pending.contains_key(&key) && self.pending.len() >= MAX_PENDING_PAYLOADS {
+            return Err(Error::OtherWithContext(
+                "too many payloads pending reassembly".into(),
+            ));
+        }
+
+        let pending = self.pending.entry(key).or_insert_with(|| PendingPayload {
+            total,
+            size,
+            hash: hash.clone(),
+            chunks: BTreeMap::new(),
+            received: 0,
+            started: self.clock.instant(),
+        });
+
+        if pending.total != total || pending.size != size || pending.hash != hash {
+            self.pending.remove(&key);
+            return Err(Error::OtherWithContext(
+                "chunk does not belong to the payload".into(),
+            ));
+        }
+
+        if pending.chunks.contains_key(&index) {
+            return Ok(None);
+        }
+
+        pending.received += data.len();
+
+        if pending.received as u64 > pending.size {
+            self.pending.remove(&key);
+            return Err(Error::InvalidLength {
+                context: "payload".into(),
+                current: size as usize,
+                minimum: None,
+                maximum: Some(MAX_CHUNKED_PAYLOAD_SIZE),
+            });
+        }
+
+        pending.chunks.insert(index, data);
+
+        if pending.chunks.len() < pending.total as usize {
+            return Ok(None);
+        }
+
+        let pending = self.pending.remove(&key).expect("payload is pending");
+
+        let mut payload = BytesMut::with_capacity(pending.size as usize);
+        for data in pending.chunks.into_values() {
+            payload.extend_from_slice(&data);
+        }
+
+        if payload.len() as u64 != pending.size || sha256_hash(&payload, None) != pending.hash {
+            return Err(Error::OtherWithContext(
+                "reassembled payload failed integrity check".into(),
+            ));
+        }
+
+        Ok(Some(payload.freeze()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use super::{split, ChunkAssembler, PayloadChunk, PENDING_PAYLOAD_TIMEOUT};
+    use crate::store::{MAX_CHUNKED_PAYLOAD_SIZE, MAX_PUBSUB_CHUNK_SIZE};
+    use crate::time::{Clock, ManualClock};
+
+    fn payload(size: usize) -> Bytes {
+        (0..size)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>()
+            .into()
+    }
+
+    #[test]
+    fn small_payload_is_not_chunked() {
+        let data = payload(128);
+        let chunks = split(data.clone()).unwrap();
+        assert_eq!(chunks, vec![data]);
+    }
+
+    #[test]
+    fn split_and_reassemble() {
+        let data = payload(MAX_PUBSUB_CHUNK_SIZE * 3 + 17);
+        let mut chunks = split(data.clone()).unwrap();
+        assert_eq!(chunks.len(), 4);
+
+        // chunks may arrive out of order
+        chunks.reverse();
+
+        let mut assembler = ChunkAssembler::default();
+        let mut output = None;
+        for chunk in chunks {
+            let chunk = PayloadChunk::from_bytes(&chunk).unwrap();
+            assert!(output.is_none());
+            output = assembler.insert(None, chunk).unwrap();
+        }
+
+        assert_eq!(output, Some(data));
+        assert!(assembler.pending.is_empty());
+    }
+
+    #[test]
+    fn tampered_chunk_is_rejected() {
+        let data = payload(MAX_PUBSUB_CHUNK_SIZE + 1);
+        let chunks = split(data).unwrap();
+
+        let mut assembler = ChunkAssembler::default();
+        let first = PayloadChunk::from_bytes(&chunks[0]).unwrap();
+        let mut last = PayloadChunk::from_bytes(&chunks[1]).unwrap();
+        last.data = Bytes::from_static(&[0xFF]);
+
+        assert!(assembler.insert(None, first).unwrap().is_none());
+        assert!(assembler.insert(None, last).is_err());
+    }
+
+    #[test]
+    fn stale_payload_is_dropped() {
+        let data = payload(MAX_PUBSUB_CHUNK_SIZE * 2 + 1);
+        let chunks = split(data).unwrap();
+
+        let manual = ManualClock::new();
+        let mut assembler = ChunkAssembler::new(&Clock::new(manual.clone()));
+
+        let first = PayloadChunk::from_bytes(&chunks[0]).unwrap();
+        assert!(assembler.insert(None, first).unwrap().is_none());
+
+        manual.advance(PENDING_PAYLOAD_TIMEOUT);
+
+        // the first chunk expired, so the payload is incomplete again
+        let second = PayloadChunk::from_bytes(&chunks[1]).unwrap();
+        assert!(assembler.insert(None, second).unwrap().is_none());
+        assert_eq!(assembler.pending.len(), 1);
+        assert_eq!(assembler.pending.values().next().unwrap().chunks.len(), 1);
+    }
+
+    #[test]
+    fn oversized_payload_is_rejected() {
+        assert!(split(payload(MAX_CHUNKED_PAYLOAD_SIZE + 1)).is_err());
+    }
+}
@@ -8,8 +8,8 @@ use futures_timer::Delay;
 use indexmap::{IndexMap, IndexSet};
 use ipld_core::cid::Cid;
 use rust_ipfs::libp2p::gossipsub::Message;
+use rust_ipfs::PeerId;
 use rust_ipfs::{Ipfs, IpfsPath};
-use rust_ipfs::{PeerId, SubscriptionStream};
 use serde::{Deserialize, Serialize};
 use std::borrow::BorrowMut;
 use std::collections::HashMap;
@@ -37,6 +37,7 @@ use web_time::Instant;
 use crate::store::community::{
     CommunityChannelDocument, CommunityDocument, CommunityInviteDocument, CommunityRoleDocument,
 };
+use crate::store::conversation::clock::HybridClock;
 use crate::store::conversation::message::{MessageDocument, MessageDocumentBuilder};
 use crate::store::discovery::Discovery;
 use crate::store::document::files::FileDocument;
@@ -47,8 +48,10 @@ use crate::store::topics::PeerTopic;
 use crate::store::{
     CommunityJoinEvents, CommunityUpdateKind, ConversationEvents, ConversationImageType,
     MAX_COMMUNITY_CHANNELS, MAX_COMMUNITY_DESCRIPTION, MAX_CONVERSATION_BANNER_SIZE,
-    MAX_CONVERSATION_ICON_SIZE, MAX_MESSAGE_SIZE, MIN_MESSAGE_SIZE,
+    MAX_CONVERSATION_ICON_SIZE, MAX_MESSAGE_SIZE_LIMIT, MAX_SLOW_MODE, MIN_MESSAGE_SIZE,
+    SLOW_MODE_CLOCK_SKEW,
 };
+use crate::time::Clock;
 use crate::utils::{ByteCollection, ExtensionType};
 use crate::{
     // rt::LocalExecutor,
@@ -186,6 +189,11 @@ pub enum CommunityTaskCommand {
         description: Option<String>,
         response: oneshot::Sender<Result<(), Error>>,
     },
+    EditCommunityChannelSlowMode {
+        channel_id: Uuid,
+        slow_mode: u64,
+        response: oneshot::Sender<Result<(), Error>>,
+    },
     GrantCommunityChannelPermission {
         channel_id: Uuid,
         permission: CommunityChannelPermission,
@@ -324,16 +332,20 @@ pub struct CommunityTask {
     pending_key_exchange: IndexMap<DID, Vec<(Bytes, bool)>>,
     document: CommunityDocument,
     keystore: Keystore,
+    clock: HybridClock,
+    time: Clock,
 
-    messaging_stream: SubscriptionStream,
-    event_stream: SubscriptionStream,
-    request_stream: SubscriptionStream,
-    join_stream: SubscriptionStream,
+    messaging_stream: BoxStream<'static, Message>,
+    event_stream: BoxStream<'static, Message>,
+    request_stream: BoxStream<'static, Message>,
+    join_stream: BoxStream<'static, Message>,
 
     attachment_tx: futures::channel::mpsc::Sender<AttachmentOneshot>,
     attachment_rx: futures::channel::mpsc::Receiver<AttachmentOneshot>,
     event_broadcast: tokio::sync::broadcast::Sender<MessageEventKind>,
     _event_subscription: EventSubscription<RayGunEventKind>,
+    /// Date of the latest message of each member within a channel, used to enforce slow mode
+    last_message_dates: HashMap<(Uuid, DID), DateTime<Utc>>,
 
     command_rx: futures::channel::mpsc::Receiver<CommunityTaskCommand>,
 
@@ -388,10 +400,22 @@ impl CommunityTask {
         let request_topic = document.exchange_topic(&identity.did_key());
         let join_topic = document.join_topic();
 
-        let messaging_stream = ipfs.pubsub_subscribe(main_topic).await?;
-        let event_stream = ipfs.pubsub_subscribe(event_topic).await?;
-        let request_stream = ipfs.pubsub_subscribe(request_topic).await?;
-        let join_stream = ipfs.pubsub_subscribe(join_topic).await?;
+        // A replica does not subscribe to the topics of the community since it is not able to answer them
+        let (messaging_stream, event_stream, request_stream, join_stream) =
+            match identity.is_replica() {
+                true => (
+                    futures::stream::pending().boxed(),
+                    futures::stream::pending().boxed(),
+                    futures::stream::pending().boxed(),
+                    futures::stream::pending().boxed(),
+                ),
+                false => (
+                    ipfs.pubsub_subscribe(main_topic).await?.boxed(),
+                    ipfs.pubsub_subscribe(event_topic).await?.boxed(),
+                    ipfs.pubsub_subscribe(request_topic).await?.boxed(),
+                    ipfs.pubsub_subscribe(join_topic).await?.boxed(),
+                ),
+            };
 
         let (atx, arx) = futures::channel::mpsc::channel(256);
         let (btx, _) = tokio::sync::broadcast::channel(1024);
@@ -405,6 +429,8 @@ impl CommunityTask {
             pending_key_exchange: Default::default(),
             document,
             keystore: Keystore::default(),
+            clock: HybridClock::default(),
+            time: identity.clock().clone(),
 
             messaging_stream,
             request_stream,
@@ -415,6 +441,7 @@ impl CommunityTask {
             attachment_rx: arx,
             event_broadcast: btx,
             _event_subscription,
+            last_message_dates: HashMap::new(),
             command_rx,
             queue: Default::default(),
             terminate: CommunityTermination::default(),
@@ -458,6 +485,22 @@ impl CommunityTask {
             task.queue = data;
         }
 
+        let mut last = None;
+        for channel in task.document.channels.values() {
+            if let Some(timestamp) = channel
+                .get_message_list(ipfs)
+                .await
+                .ok()
+                .and_then(|list| list.last().map(MessageDocument::timestamp))
+            {
+                last = last.max(Some(timestamp));
+            }
+        }
+
+        if let Some(last) = last {
+            task.clock = HybridClock::new(last);
+        }
+
         tracing::info!(%community_id, "community task created");
         Ok(task)
     }
@@ -469,6 +512,22 @@ impl CommunityTask {
 
         let community_id = this.community_id;
 
+        // A replica only answers the commands of its read-only handles
+        if this.identity.is_replica() {
+            loop {
+                tokio::select! {
+                    biased;
+                    _ = &mut this.terminate => {
+                        break;
+                    }
+                    Some(command) = this.command_rx.next() => {
+                        this.process_command(command).await;
+                    }
+                }
+            }
+            return;
+        }
+
         let mut queue_timer = Delay::new(Duration::from_secs(1));
 
         let mut pending_exchange_timer = Delay::new(Duration::from_secs(1));
@@ -876,6 +935,16 @@ impl CommunityTask {
                     .await;
                 let _ = response.send(result);
             }
+            CommunityTaskCommand::EditCommunityChannelSlowMode {
+                response,
+                channel_id,
+                slow_mode,
+            } => {
+                let result = self
+                    .edit_community_channel_slow_mode(channel_id, slow_mode)
+                    .await;
+                let _ = response.send(result);
+            }
             CommunityTaskCommand::GrantCommunityChannelPermission {
                 response,
                 channel_id,
@@ -1278,7 +1347,7 @@ impl CommunityTask {
 
         match data.message(None)? {
             CommunityJoinEvents::Join => {
-                let now = Utc::now();
+                let now = self.time.now();
 
                 if !self.document.invites.iter().any(|(_, invite)| {
                     invite.expiry.is_none_or(|expiry| expiry > now)
@@ -2471,6 +2540,62 @@ impl CommunityTask {
         )
         .await
     }
+    pub async fn edit_community_channel_slow_mode(
+        &mut self,
+        channel_id: Uuid,
+        slow_mode: u64,
+    ) -> Result<(), Error> {
+        let own_did = &self.identity.did_key();
+        if !self
+            .document
+            .has_permission(own_did, &CommunityPermission::EditChannels)
+        {
+            return Err(Error::Unauthorized);
+        }
+
+        if slow_mode > MAX_SLOW_MODE {
+            return Err(Error::InvalidLength {
+                context: "slow_mode".into(),
+                current: slow_mode as _,
+                minimum: None,
+                maximum: Some(MAX_SLOW_MODE as _),
+            });
+        }
+
+        let channel_doc = self
+            .document
+            .channels
+            .get_mut(&channel_id.to_string())
+            .ok_or(Error::CommunityChannelDoesntExist)?;
+
+        if channel_doc.slow_mode == slow_mode {
+            return Ok(());
+        }
+
+        channel_doc.slow_mode = slow_mode;
+        self.set_document().await?;
+
+        let _ = self
+            .event_broadcast
+            .send(MessageEventKind::EditedCommunityChannelSlowMode {
+                community_id: self.community_id,
+                channel_id,
+                slow_mode,
+            });
+
+        self.publish(
+            None,
+            CommunityMessagingEvents::UpdateCommunity {
+                community: self.document.clone(),
+                kind: CommunityUpdateKind::EditCommunityChannelSlowMode {
+                    channel_id,
+                    slow_mode,
+                },
+            },
+            true,
+        )
+        .await
+    }
     pub async fn grant_community_channel_permission(
         &mut self,
         channel_id: Uuid,
@@ -2863,10 +2988,20 @@ impl CommunityTask {
             return Err(Error::CommunityChannelDoesntExist);
         }
 
+        check_slow_mode(
+            &self.document,
+            &self.last_message_dates,
+            channel_id,
+            own_did,
+            self.time.now(),
+        )?;
+
         if messages.is_empty() {
             return Err(Error::EmptyMessage);
         }
 
+        let max_message_size = self.identity.config().max_message_size();
+
         let lines_value_length: usize = messages
             .iter()
             .filter(|s| !s.is_empty())
@@ -2874,17 +3009,17 @@ impl CommunityTask {
             .map(|s| s.chars().count())
             .sum();
 
-        if lines_value_length == 0 || lines_value_length > MAX_MESSAGE_SIZE {
+        if lines_value_length == 0 || lines_value_length > max_message_size {
             tracing::error!(
                 current_size = lines_value_length,
-                max = MAX_MESSAGE_SIZE,
+                max = max_message_size,
                 "length of message is invalid"
             );
             return Err(Error::InvalidLength {
                 context: "message".into(),
                 current: lines_value_length,
                 minimum: Some(MIN_MESSAGE_SIZE),
-                maximum: Some(MAX_MESSAGE_SIZE),
+                maximum: Some(max_message_size),
             });
         }
 
@@ -2896,6 +3031,7 @@ impl CommunityTask {
         let message = MessageDocumentBuilder::new(keypair, keystore.as_ref())
             .set_conversation_id(channel_id)
             .set_sender(own_did.clone())
+            .set_clock(self.clock.tick(self.time.now()))
             .set_message(messages.clone())?
             .build()?;
 
@@ -2914,6 +3050,13 @@ impl CommunityTask {
 
         self.set_document().await?;
 
+        record_slow_mode(
+            &mut self.last_message_dates,
+            channel_id,
+            own_did,
+            message.date,
+        );
+
         let event = MessageEventKind::CommunityMessageSent {
             community_id: self.community_id,
             channel_id,
@@ -2975,6 +3118,8 @@ impl CommunityTask {
             return Err(Error::EmptyMessage);
         }
 
+        let max_message_size = self.identity.config().max_message_size();
+
         let lines_value_length: usize = messages
             .iter()
             .filter(|s| !s.is_empty())
@@ -2982,17 +3127,17 @@ impl CommunityTask {
             .map(|s| s.chars().count())
             .sum();
 
-        if lines_value_length == 0 || lines_value_length > MAX_MESSAGE_SIZE {
+        if lines_value_length == 0 || lines_value_length > max_message_size {
             tracing::error!(
                 current_size = lines_value_length,
-                max = MAX_MESSAGE_SIZE,
+                max = max_message_size,
                 "length of message is invalid"
             );
             return Err(Error::InvalidLength {
                 context: "message".into(),
                 current: lines_value_length,
                 minimum: Some(MIN_MESSAGE_SIZE),
-                maximum: Some(MAX_MESSAGE_SIZE),
+                maximum: Some(max_message_size),
             });
         }
 
@@ -3010,6 +3155,7 @@ impl CommunityTask {
         if message_document.sender() != self.identity.did_key() {
             return Err(Error::InvalidMessage);
         }
+        message_document.set_modified_clock(self.clock.tick(self.time.now()));
         message_document.set_message(keypair, keystore.as_ref(), &messages)?;
 
         let nonce = message_document.nonce_from_message()?;
@@ -3037,6 +3183,7 @@ impl CommunityTask {
             lines: messages,
             nonce: nonce.to_vec(),
             signature: signature.into(),
+            modified_clock: message_document.modified_clock,
         };
 
         // if !recipients.is_empty() {
@@ -3074,12 +3221,22 @@ impl CommunityTask {
             return Err(Error::Unauthorized);
         }
 
+        check_slow_mode(
+            &self.document,
+            &self.last_message_dates,
+            channel_id,
+            own_did,
+            self.time.now(),
+        )?;
+
         let tx = self.event_broadcast.clone();
 
         if messages.is_empty() {
             return Err(Error::EmptyMessage);
         }
 
+        let max_message_size = self.identity.config().max_message_size();
+
         let lines_value_length: usize = messages
             .iter()
             .filter(|s| !s.is_empty())
@@ -3087,17 +3244,17 @@ impl CommunityTask {
             .map(|s| s.chars().count())
             .sum();
 
-        if lines_value_length == 0 || lines_value_length > MAX_MESSAGE_SIZE {
+        if lines_value_length == 0 || lines_value_length > max_message_size {
             tracing::error!(
                 current_size = lines_value_length,
-                max = MAX_MESSAGE_SIZE,
+                max = max_message_size,
                 "length of message is invalid"
             );
             return Err(Error::InvalidLength {
                 context: "message".into(),
                 current: lines_value_length,
                 minimum: Some(MIN_MESSAGE_SIZE),
-                maximum: Some(MAX_MESSAGE_SIZE),
+                maximum: Some(max_message_size),
             });
         }
 
@@ -3111,6 +3268,7 @@ impl CommunityTask {
             .set_conversation_id(channel_id)
             .set_sender(own_did.clone())
             .set_replied(message_id)
+            .set_clock(self.clock.tick(self.time.now()))
             .set_message(messages)?
             .build()?;
 
@@ -3129,6 +3287,13 @@ impl CommunityTask {
 
         self.set_document().await?;
 
+        record_slow_mode(
+            &mut self.last_message_dates,
+            channel_id,
+            own_did,
+            message.date,
+        );
+
         let event = MessageEventKind::CommunityMessageSent {
             community_id: self.community_id,
             channel_id,
@@ -3465,6 +3630,14 @@ impl CommunityTask {
             return Err(Error::Unauthorized);
         }
 
+        check_slow_mode(
+            &self.document,
+            &self.last_message_dates,
+            channel_id,
+            own_did,
+            self.time.now(),
+        )?;
+
         let keystore = pubkey_or_keystore(&*self)?;
 
         let stream = AttachmentStream::new(
@@ -3477,6 +3650,7 @@ impl CommunityTask {
         )
         .set_reply(message_id)
         .set_locations(locations)?
+        .set_max_message_size(self.identity.config().max_message_size())
         .set_lines(messages)?;
 
         let message_id = stream.message_id();
@@ -3569,10 +3743,15 @@ impl CommunityTask {
         Ok(stream)
     }
 
-    async fn store_direct_for_attachment(&mut self, message: MessageDocument) -> Result<(), Error> {
+    async fn store_direct_for_attachment(
+        &mut self,
+        mut message: MessageDocument,
+    ) -> Result<(), Error> {
         let channel_id = message.conversation_id;
         let message_id = message.id;
 
+        message.set_clock(self.root.keypair(), self.clock.tick(self.time.now()))?;
+
         let channel = match self.document.channels.get_mut(&channel_id.to_string()) {
             Some(c) => c,
             None => return Err(Error::CommunityChannelDoesntExist),
@@ -3586,6 +3765,13 @@ impl CommunityTask {
 
         self.set_document().await?;
 
+        record_slow_mode(
+            &mut self.last_message_dates,
+            channel_id,
+            &self.identity.did_key(),
+            message.date,
+        );
+
         let event = MessageEventKind::CommunityMessageSent {
             community_id: self.community_id,
             channel_id,
@@ -3742,6 +3928,51 @@ impl CommunityTask {
     }
 }
 
+/// Check that a message of `member` in the channel dated at `date` is far enough from their previous message
+fn check_slow_mode(
+    document: &CommunityDocument,
+    last_message_dates: &HashMap<(Uuid, DID), DateTime<Utc>>,
+    channel_id: Uuid,
+    member: &DID,
+    date: DateTime<Utc>,
+) -> Result<(), Error> {
+    if document.slow_mode_exempt(member, channel_id) {
+        return Ok(());
+    }
+
+    let Some(channel) = document.channels.get(&channel_id.to_string()) else {
+        return Err(Error::CommunityChannelDoesntExist);
+    };
+
+    let slow_mode = channel.slow_mode as i64;
+
+    if let Some(last) = last_message_dates.get(&(channel_id, member.clone())) {
+        // messages may arrive out of order so the distance is checked in either direction
+        let elapsed = (date - *last).num_seconds().abs();
+        if elapsed < slow_mode {
+            return Err(Error::SlowModeActive {
+                remaining: (slow_mode - elapsed) as u64,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Record the date of a message of `member` in the channel once it is stored so a message that failed to send
+/// does not count towards slow mode
+fn record_slow_mode(
+    last_message_dates: &mut HashMap<(Uuid, DID), DateTime<Utc>>,
+    channel_id: Uuid,
+    member: &DID,
+    date: DateTime<Utc>,
+) {
+    last_message_dates
+        .entry((channel_id, member.clone()))
+        .and_modify(|last| *last = (*last).max(date))
+        .or_insert(date);
+}
+
 async fn message_event(
     this: &mut CommunityTask,
     sender: &DID,
@@ -3772,7 +4003,7 @@ async fn message_event(
                 return Err(Error::IdentityDoesntExist);
             }
 
-            let channel = match this.document.channels.get_mut(&channel_id.to_string()) {
+            let channel = match this.document.channels.get(&channel_id.to_string()) {
                 Some(c) => c,
                 None => return Err(Error::CommunityChannelDoesntExist),
             };
@@ -3781,6 +4012,26 @@ async fn message_event(
                 return Err(Error::MessageFound);
             }
 
+            let message_sender = message.sender.to_did();
+
+            if !this.document.slow_mode_exempt(&message_sender, channel_id) {
+                // a message dated ahead of time would otherwise shift the window of the member
+                if (message.date - this.time.now()).num_seconds() > SLOW_MODE_CLOCK_SKEW {
+                    return Err(Error::InvalidMessage);
+                }
+                check_slow_mode(
+                    &this.document,
+                    &this.last_message_dates,
+                    channel_id,
+                    &message_sender,
+                    message.date,
+                )?;
+            }
+
+            if let Some(clock) = message.clock {
+                this.clock.update(clock, this.time.now());
+            }
+
             let resolved_message = message
                 .resolve(&this.ipfs, keypair, false, keystore.as_ref())
                 .await?;
@@ -3793,7 +4044,7 @@ async fn message_event(
                 .map(|s| s.chars().count())
                 .sum();
 
-            if lines_value_length == 0 && lines_value_length > MAX_MESSAGE_SIZE {
+            if lines_value_length == 0 && lines_value_length > MAX_MESSAGE_SIZE_LIMIT {
                 tracing::error!(
                     message_length = lines_value_length,
                     "Length of message is invalid."
@@ -3802,16 +4053,28 @@ async fn message_event(
                     context: "message".into(),
                     current: lines_value_length,
                     minimum: Some(MIN_MESSAGE_SIZE),
-                    maximum: Some(MAX_MESSAGE_SIZE),
+                    maximum: Some(MAX_MESSAGE_SIZE_LIMIT),
                 });
             }
 
+            let channel = match this.document.channels.get_mut(&channel_id.to_string()) {
+                Some(c) => c,
+                None => return Err(Error::CommunityChannelDoesntExist),
+            };
+
             channel
                 .insert_message_document(&this.ipfs, &message)
                 .await?;
 
             this.set_document().await?;
 
+            record_slow_mode(
+                &mut this.last_message_dates,
+                channel_id,
+                &message_sender,
+                message.date,
+            );
+
             if let Err(e) = this
                 .event_broadcast
                 .send(MessageEventKind::CommunityMessageReceived {
@@ -3831,6 +4094,7 @@ async fn message_event(
             lines,
             nonce,
             signature,
+            modified_clock,
         } => {
             let channel = match this.document.channels.get_mut(&channel_id.to_string()) {
                 Some(c) => c,
@@ -3841,6 +4105,18 @@ async fn message_event(
 
             message_document.verify()?;
 
+            // edits are resolved by their clock so that an edit delayed in transit does not overwrite a newer one
+            let incoming = modified_clock.unwrap_or_else(|| modified.into());
+            if matches!(message_document.modified_timestamp(), Some(current) if incoming < current)
+            {
+                tracing::debug!(%channel_id, %message_id, "ignoring edit that is older than the current message");
+                return Ok(());
+            }
+
+            if let Some(clock) = modified_clock {
+                this.clock.update(clock, this.time.now());
+            }
+
             let lines_value_length: usize = lines
                 .iter()
                 .map(|s| s.trim())
@@ -3848,17 +4124,17 @@ async fn message_event(
                 .map(|s| s.chars().count())
                 .sum();
 
-            if lines_value_length == 0 && lines_value_length > MAX_MESSAGE_SIZE {
+            if lines_value_length == 0 && lines_value_length > MAX_MESSAGE_SIZE_LIMIT {
                 tracing::error!(
                     current_size = lines_value_length,
-                    max = MAX_MESSAGE_SIZE,
+                    max = MAX_MESSAGE_SIZE_LIMIT,
                     "length of message is invalid"
                 );
                 return Err(Error::InvalidLength {
                     context: "message".into(),
                     current: lines_value_length,
                     minimum: Some(MIN_MESSAGE_SIZE),
-                    maximum: Some(MAX_MESSAGE_SIZE),
+                    maximum: Some(MAX_MESSAGE_SIZE_LIMIT),
                 });
             }
 
@@ -3866,6 +4142,7 @@ async fn message_event(
                 keypair,
                 keystore.as_ref(),
                 modified,
+                modified_clock,
                 lines,
                 (!signature.is_empty() && sender.ne(&own_did)).then_some(signature),
                 Some(nonce.as_slice()),
@@ -4327,6 +4604,24 @@ async fn message_event(
                         tracing::warn!(%community_id, error = %e, "Error broadcasting event");
                     }
                 }
+                CommunityUpdateKind::EditCommunityChannelSlowMode {
+                    channel_id,
+                    slow_mode,
+                } => {
+                    if slow_mode > MAX_SLOW_MODE {
+                        return Err(Error::InvalidCommunity);
+                    }
+                    this.replace_document(community).await?;
+                    if let Err(e) = this.event_broadcast.send(
+                        MessageEventKind::EditedCommunityChannelSlowMode {
+                            community_id,
+                            channel_id,
+                            slow_mode,
+                        },
+                    ) {
+                        tracing::warn!(%community_id, error = %e, "Error broadcasting event");
+                    }
+                }
                 CommunityUpdateKind::GrantCommunityChannelPermission {
                     channel_id,
                     permission,
@@ -4445,7 +4740,11 @@ async fn process_request_response_event(
 
                 let response = ConversationRequestResponse::Response {
                     conversation_id,
-                    kind: ConversationResponseKind::Key { key },
+                    kind: ConversationResponseKind::Key {
+                        key,
+                        epoch: None,
+                        devices: vec![],
+                    },
                 };
 
                 let topic = this.document.exchange_topic(&sender);
@@ -4490,7 +4789,7 @@ async fn process_request_response_event(
             conversation_id,
             kind,
         } => match kind {
-            ConversationResponseKind::Key { key } => {
+            ConversationResponseKind::Key { key, .. } => {
                 if !this.document.participants().contains(&sender) {
                     return Err(Error::IdentityDoesntExist);
                 }
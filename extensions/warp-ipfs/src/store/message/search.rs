@@ -0,0 +1,277 @@
+//! Local full-text index over the messages of a conversation.
+//!
+//! Messages are only stored encrypted, so the index is built from their decrypted lines by the conversation task as
+//! messages are inserted, edited or removed. The index maps each term to the messages containing it and is kept in
+//! the [`EncryptedStore`] under the id of the conversation. Offsets of the matches are not indexed, but computed from
+//! the lines of the message when it is returned from a search.
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Bound;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use warp::error::Error;
+use warp::raygun::SearchHighlight;
+
+use crate::store::encrypted::EncryptedStore;
+
+/// Interval at which changes to the index are written to the store
+pub const SEARCH_INDEX_SAVE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+#[derive(Default, Serialize, Deserialize)]
+struct TermIndex {
+    terms: BTreeMap<String, BTreeSet<Uuid>>,
+    messages: HashMap<Uuid, BTreeSet<String>>,
+}
+
+pub struct SearchIndex {
+    store: Option<EncryptedStore>,
+    conversation_id: Uuid,
+    index: TermIndex,
+    /// Set once the index holds every message of the conversation, either by being loaded from the store or rebuilt
+    built: bool,
+    dirty: bool,
+}
+
+impl SearchIndex {
+    /// Index that is only kept in memory
+    pub fn new(conversation_id: Uuid) -> Self {
+        Self {
+            store: None,
+            conversation_id,
+            index: TermIndex::default(),
+            built: false,
+            dirty: false,
+        }
+    }
+
+    /// Load the index of the conversation from `store`, if it was written before
+    pub async fn load(store: Option<EncryptedStore>, conversation_id: Uuid) -> Self {
+        let mut index = Self::new(conversation_id);
+
+        let Some(store) = store else {
+            return index;
+        };
+
+        match store
+            .get_serialized::<TermIndex>(&conversation_id.to_string())
+            .await
+        {
+            Ok(Some(terms)) => {
+                index.index = terms;
+                index.built = true;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                tracing::warn!(%conversation_id, error = %e, "unable to load search index. Rebuilding on next search")
+            }
+        }
+
+        index.store = Some(store);
+        index
+    }
+
+    pub fn is_built(&self) -> bool {
+        self.built
+    }
+
+    /// Mark the index as holding every message of the conversation after it was rebuilt
+    pub fn set_built(&mut self) {
+        self.built = true;
+        self.dirty = true;
+    }
+
+    /// Index the lines of a message, replacing the terms indexed for it previously
+    pub fn insert(&mut self, message_id: Uuid, lines: &[String]) {
+        self.remove(message_id);
+
+        let terms = lines
+            .iter()
+            .flat_map(|line| tokenize(line))
+            .map(|(_, _, term)| term)
+            .collect::<BTreeSet<_>>();
+
+        if terms.is_empty() {
+            return;
+        }
+
+        for term in &terms {
+            self.index
+                .terms
+                .entry(term.clone())
+                .or_default()
+                .insert(message_id);
+        }
+
+        self.index.messages.insert(message_id, terms);
+        self.dirty = true;
+    }
+
+    pub fn remove(&mut self, message_id: Uuid) {
+        let Some(terms) = self.index.messages.remove(&message_id) else {
+            return;
+        };
+
+        for term in terms {
+            if let Some(ids) = self.index.terms.get_mut(&term) {
+                ids.remove(&message_id);
+                if ids.is_empty() {
+                    self.index.terms.remove(&term);
+                }
+            }
+        }
+
+        self.dirty = true;
+    }
+
+    pub fn clear(&mut self) {
+        self.index = TermIndex::default();
+        self.built = false;
+        self.dirty = true;
+    }
+
+    /// Messages containing every term of the query. The last term also matches as a prefix
+    pub fn candidates(&self, terms: &[String]) -> BTreeSet<Uuid> {
+        let Some((last, terms)) = terms.split_last() else {
+            return BTreeSet::new();
+        };
+
+        let mut candidates = self
+            .index
+            .terms
+            .range::<str, _>((Bound::Included(last.as_str()), Bound::Unbounded))
+            .take_while(|(term, _)| term.starts_with(last.as_str()))
+            .flat_map(|(_, ids)| ids.iter().copied())
+            .collect::<BTreeSet<_>>();
+
+        for term in terms {
+            let Some(ids) = self.index.terms.get(term) else {
+                return BTreeSet::new();
+            };
+            candidates.retain(|id| ids.contains(id));
+        }
+
+        candidates
+    }
+
+    /// Write the index to the store if it changed since it was last written
+    pub async fn save(&mut self) -> Result<(), Error> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        let Some(store) = self.store.as_ref() else {
+            self.dirty = false;
+            return Ok(());
+        };
+
+        let key = self.conversation_id.to_string();
+
+        match self.built {
+            true => store.put_serialized(&key, &self.index).await?,
+            // an index missing messages is rebuilt on the next search rather than loaded
+            false => store.remove(&key).await?,
+        }
+
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+/// Split the text of a query into the terms that are looked up in the index
+pub fn query_terms(text: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    for (_, _, term) in tokenize(text) {
+        if !terms.contains(&term) {
+            terms.push(term);
+        }
+    }
+    terms
+}
+
+/// Locations of the terms within the lines of a message. The last term also matches as a prefix
+pub fn highlights(lines: &[String], terms: &[String]) -> Vec<SearchHighlight> {
+    let Some((last, terms)) = terms.split_last() else {
+        return vec![];
+    };
+
+    lines
+        .iter()
+        .enumerate()
+        .flat_map(|(line, text)| {
+            tokenize(text)
+                .filter(|(_, _, term)| term.starts_with(last.as_str()) || terms.contains(term))
+                .map(move |(start, end, _)| SearchHighlight { line, start, end })
+        })
+        .collect()
+}
+
+/// Split the text into lowercase words, along with the byte offsets of each word in `text`
+fn tokenize(text: &str) -> impl Iterator<Item = (usize, usize, String)> + '_ {
+    let mut chars = text.char_indices().peekable();
+    std::iter::from_fn(move || {
+        let (start, _) = chars.find(|(_, c)| c.is_alphanumeric())?;
+        let mut end = text.len();
+        while let Some((index, c)) = chars.peek().copied() {
+            if !c.is_alphanumeric() {
+                end = index;
+                break;
+            }
+            chars.next();
+        }
+        Some((start, end, text[start..end].to_lowercase()))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use uuid::Uuid;
+    use warp::raygun::SearchHighlight;
+
+    use super::{highlights, query_terms, SearchIndex};
+
+    #[test]
+    fn messages_are_found_by_their_terms() {
+        let mut index = SearchIndex::new(Uuid::new_v4());
+
+        let greeting = Uuid::new_v4();
+        let farewell = Uuid::new_v4();
+
+        index.insert(greeting, &["Hello, World!".into()]);
+        index.insert(farewell, &["Goodbye world".into(), "see you".into()]);
+
+        let terms = query_terms("WORLD");
+        assert_eq!(index.candidates(&terms).len(), 2);
+
+        // the last term matches as a prefix
+        let terms = query_terms("world hel");
+        assert_eq!(
+            index.candidates(&terms).into_iter().collect::<Vec<_>>(),
+            [greeting]
+        );
+
+        let lines = vec!["Hello, World!".to_string()];
+        assert_eq!(
+            highlights(&lines, &terms),
+            [
+                SearchHighlight {
+                    line: 0,
+                    start: 0,
+                    end: 5
+                },
+                SearchHighlight {
+                    line: 0,
+                    start: 7,
+                    end: 12
+                }
+            ]
+        );
+
+        // edits replace the terms of the message
+        index.insert(greeting, &["Hi there".into()]);
+        assert!(index.candidates(&terms).is_empty());
+
+        index.remove(farewell);
+        assert!(index.candidates(&query_terms("world")).is_empty());
+        assert!(index.index.terms.get("goodbye").is_none());
+    }
+}
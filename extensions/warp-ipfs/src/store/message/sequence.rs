@@ -0,0 +1,277 @@
+//! Ordering of the events published to a conversation.
+//!
+//! Each payload published to the conversation topic carries a sequence number that is incremented by the sender for
+//! every payload, so events applied out of order (eg an edit arriving before the message it edits) can be detected.
+//! Events that arrive ahead of a gap are held by the [`ReorderBuffer`] until the missing events arrive, while the
+//! receiver asks the sender to publish the missing range again from the payloads it keeps in its [`Outbox`]. If the
+//! gap is not filled within [`REORDER_TIMEOUT`], the held events are applied anyway so a lost payload cannot stall the
+//! conversation.
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+use bytes::Bytes;
+use warp::crypto::DID;
+use web_time::Instant;
+
+use crate::time::Clock;
+
+/// Duration after which events held behind a gap are applied without waiting for the missing events
+pub const REORDER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Maximum amount of events that can be held for a sender before they are applied without waiting for a gap
+const MAX_HELD_EVENTS: usize = 64;
+
+/// Amount of published payloads kept so they can be published again on request
+pub const OUTBOX_CAPACITY: usize = 256;
+
+/// Events that can be applied in order, along with the range of sequence numbers that is missing, if any
+pub struct Received<T> {
+    pub ready: Vec<T>,
+    pub missing: Option<RangeInclusive<u64>>,
+}
+
+struct HeldEvents<T> {
+    events: BTreeMap<u64, T>,
+    since: Instant,
+}
+
+/// Holds events received ahead of the sequence of their sender
+pub struct ReorderBuffer<T> {
+    /// Sequence number of the last event applied from each sender
+    applied: HashMap<DID, u64>,
+    held: HashMap<DID, HeldEvents<T>>,
+    clock: Clock,
+}
+
+impl<T> Default for ReorderBuffer<T> {
+    fn default() -> Self {
+        Self::new(&Clock::default())
+    }
+}
+
+impl<T> ReorderBuffer<T> {
+    pub fn new(clock: &Clock) -> Self {
+        Self {
+            applied: HashMap::new(),
+            held: HashMap::new(),
+            clock: clock.clone(),
+        }
+    }
+
+    /// Insert the event with the sequence number given by `sender`, returning the events that can now be applied
+    pub fn insert(&mut self, sender: &DID, sequence: u64, event: T) -> Received<T> {
+        // The first event seen from a sender sets the point from which its events are ordered
+        let applied = *self
+            .applied
+            .entry(sender.clone())
+            .or_insert(sequence.saturating_sub(1));
+
+        // Events at or behind the sequence are redeliveries, or were sent before the sender
+        // lost its sequence, and are applied as is
+        if sequence <= applied {
+            return Received {
+                ready: vec![event],
+                missing: None,
+            };
+        }
+
+        if sequence == applied + 1 {
+            self.applied.insert(sender.clone(), sequence);
+            let mut ready = vec![event];
+            ready.extend(self.drain_contiguous(sender));
+            return Received {
+                ready,
+                missing: None,
+            };
+        }
+
+        let now = self.clock.instant();
+        let held = self
+            .held
+            .entry(sender.clone())
+            .or_insert_with(|| HeldEvents {
+                events: BTreeMap::new(),
+                since: now,
+            });
+
+        let newly_missing = held.events.is_empty();
+        held.events.insert(sequence, event);
+
+        if held.events.len() > MAX_HELD_EVENTS {
+            return Received {
+                ready: self.release(sender),
+                missing: None,
+            };
+        }
+
+        Received {
+            ready: vec![],
+            missing: newly_missing.then_some(applied + 1..=sequence - 1),
+        }
+    }
+
+    /// Apply the events that have been held longer than [`REORDER_TIMEOUT`], skipping over their gap
+    pub fn expire(&mut self) -> Vec<(DID, Vec<T>)> {
+        let expired = self
+            .held
+            .iter()
+            .filter(|(_, held)| self.clock.elapsed(held.since) >= REORDER_TIMEOUT)
+            .map(|(sender, _)| sender.clone())
+            .collect::<Vec<_>>();
+
+        expired
+            .into_iter()
+            .map(|sender| {
+                let events = self.release(&sender);
+                (sender, events)
+            })
+            .collect()
+    }
+
+    /// Sequence number of the last event applied from each sender
+    pub fn known(&self) -> Vec<(DID, u64)> {
+        self.applied
+            .iter()
+            .map(|(sender, sequence)| (sender.clone(), *sequence))
+            .collect()
+    }
+
+    /// Remove the state of a sender, such as when they leave the conversation
+    pub fn remove(&mut self, sender: &DID) {
+        self.applied.remove(sender);
+        self.held.remove(sender);
+    }
+
+    fn drain_contiguous(&mut self, sender: &DID) -> Vec<T> {
+        let Some(held) = self.held.get_mut(sender) else {
+            return vec![];
+        };
+
+        let mut applied = self.applied.get(sender).copied().unwrap_or_default();
+        let mut ready = vec![];
+
+        while let Some(event) = held.events.remove(&(applied + 1)) {
+            applied += 1;
+            ready.push(event);
+        }
+
+        match held.events.is_empty() {
+            true => {
+                self.held.remove(sender);
+            }
+            // the remaining events are waiting on another gap
+            false => held.since = self.clock.instant(),
+        }
+
+        self.applied.insert(sender.clone(), applied);
+        ready
+    }
+
+    /// Release every held event of the sender in order, skipping over any gap
+    fn release(&mut self, sender: &DID) -> Vec<T> {
+        let Some(held) = self.held.remove(sender) else {
+            return vec![];
+        };
+
+        if let Some(last) = held.events.keys().next_back() {
+            self.applied.insert(sender.clone(), *last);
+        }
+
+        held.events.into_values().collect()
+    }
+}
+
+/// Payloads recently published by this node, kept so they can be published again when a gap is reported
+#[derive(Default)]
+pub struct Outbox {
+    payloads: VecDeque<(u64, Bytes)>,
+}
+
+impl Outbox {
+    pub fn insert(&mut self, sequence: u64, payload: Bytes) {
+        if self.payloads.len() >= OUTBOX_CAPACITY {
+            self.payloads.pop_front();
+        }
+        self.payloads.push_back((sequence, payload));
+    }
+
+    /// Payloads within the range that are still kept, in order
+    pub fn range(&self, range: RangeInclusive<u64>) -> Vec<Bytes> {
+        self.payloads
+            .iter()
+            .filter(|(sequence, _)| range.contains(sequence))
+            .map(|(_, payload)| payload.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use bytes::Bytes;
+    use warp::crypto::DID;
+
+    use super::{Outbox, ReorderBuffer, OUTBOX_CAPACITY, REORDER_TIMEOUT};
+    use crate::time::{Clock, ManualClock};
+
+    #[test]
+    fn events_are_applied_in_order() {
+        let sender = DID::default();
+        let mut buffer = ReorderBuffer::default();
+
+        let received = buffer.insert(&sender, 1, "message");
+        assert_eq!(received.ready, vec!["message"]);
+
+        // the edit arrives before the reply it follows
+        let received = buffer.insert(&sender, 3, "edit");
+        assert!(received.ready.is_empty());
+        assert_eq!(received.missing, Some(2..=2));
+
+        let received = buffer.insert(&sender, 2, "reply");
+        assert_eq!(received.ready, vec!["reply", "edit"]);
+        assert!(received.missing.is_none());
+        assert_eq!(buffer.known(), vec![(sender.clone(), 3)]);
+
+        // redelivered events are passed through
+        let received = buffer.insert(&sender, 2, "reply");
+        assert_eq!(received.ready, vec!["reply"]);
+    }
+
+    #[test]
+    fn held_events_are_applied_after_timeout() {
+        let manual = ManualClock::new();
+        let clock = Clock::new(manual.clone());
+        let sender = DID::default();
+        let mut buffer = ReorderBuffer::new(&clock);
+
+        buffer.insert(&sender, 5, "first");
+        let received = buffer.insert(&sender, 8, "fourth");
+        assert_eq!(received.missing, Some(6..=7));
+        buffer.insert(&sender, 7, "third");
+
+        assert!(buffer.expire().is_empty());
+
+        manual.advance(REORDER_TIMEOUT + Duration::from_secs(1));
+        let expired = buffer.expire();
+        assert_eq!(expired, vec![(sender.clone(), vec!["third", "fourth"])]);
+
+        let received = buffer.insert(&sender, 9, "fifth");
+        assert_eq!(received.ready, vec!["fifth"]);
+    }
+
+    #[test]
+    fn outbox_keeps_latest_payloads() {
+        let mut outbox = Outbox::default();
+        for sequence in 1..=(OUTBOX_CAPACITY as u64 + 10) {
+            outbox.insert(sequence, Bytes::from(sequence.to_string()));
+        }
+
+        assert!(outbox.range(1..=10).is_empty());
+        assert_eq!(
+            outbox.range(11..=12),
+            vec![Bytes::from("11"), Bytes::from("12")]
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! Snippets of text that are too long to be sent inline.
+//!
+//! Rather than rejecting a message over the maximum message size, its text is stored in constellation and attached
+//! to the message, while the lines of the message are replaced by a preview of the text. This keeps long logs or
+//! code from exceeding the pubsub payload limit or inflating the conversation document, and recipients fetch the
+//! full text on demand. The syntax of the text, taken from a fenced code block or detected from its content, is
+//! recorded as a parameter of the media type of the file (eg `text/plain; syntax=rust`) and can be read back with
+//! [`syntax`].
+//!
+//! Like other attachments, the file is not encrypted for the conversation, so snippets are only sent once enabled
+//! in the configuration.
+use std::str::FromStr;
+
+use bytes::Bytes;
+use mediatype::MediaTypeBuf;
+use uuid::Uuid;
+use warp::constellation::file::{File, FileType};
+
+/// Maximum amount of lines of the text kept in the preview
+pub const SNIPPET_PREVIEW_LINES: usize = 8;
+
+/// Maximum amount of characters of the text kept in the preview
+pub const SNIPPET_PREVIEW_SIZE: usize = 512;
+
+const SYNTAX_PARAM: &str = "syntax";
+
+const FENCE: &str = "```";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snippet {
+    /// Name of the file holding the text
+    pub name: String,
+    pub syntax: Option<String>,
+    /// Lines sent in place of the text
+    pub preview: Vec<String>,
+    pub data: Bytes,
+}
+
+impl Snippet {
+    pub fn new(message_id: Uuid, lines: &[String]) -> Self {
+        let text = lines.join("\n");
+
+        let (syntax, body) = match unfence(&text) {
+            Some((syntax, body)) => (syntax.or_else(|| detect_syntax(body)), body),
+            None => (detect_syntax(&text), text.as_str()),
+        };
+
+        let extension = syntax.as_deref().map(extension).unwrap_or("txt");
+        let name = format!("snippet-{}.{extension}", message_id.simple());
+
+        Self {
+            name,
+            preview: preview(body),
+            syntax,
+            data: Bytes::copy_from_slice(body.as_bytes()),
+        }
+    }
+
+    pub fn file_type(&self) -> FileType {
+        let mime = match &self.syntax {
+            Some(syntax) => format!("text/plain; {SYNTAX_PARAM}={syntax}"),
+            None => "text/plain".to_string(),
+        };
+
+        MediaTypeBuf::from_str(&mime)
+            .map(FileType::Mime)
+            .unwrap_or_default()
+    }
+}
+
+/// Syntax of a snippet attached to a message, if the file is a snippet and its syntax is known
+pub fn syntax(file: &File) -> Option<String> {
+    let FileType::Mime(mime) = file.file_type() else {
+        return None;
+    };
+
+    mime.get_param(mediatype::Name::new(SYNTAX_PARAM)?)
+        .map(|value| value.as_str().to_string())
+}
+
+/// Syntax and body of a text that is entirely a fenced code block
+fn unfence(text: &str) -> Option<(Option<String>, &str)> {
+    let text = text.trim();
+    let inner = text.strip_prefix(FENCE)?.strip_suffix(FENCE)?;
+    let (info, body) = inner.split_once('\n')?;
+    Some((normalize_syntax(info), body.trim_end_matches('\n')))
+}
+
+fn detect_syntax(text: &str) -> Option<String> {
+    let trimmed = text.trim_start();
+
+    if let Some(shebang) = trimmed.strip_prefix("#!") {
+        let interpreter = shebang.lines().next().unwrap_or_default();
+        let syntax = match interpreter {
+            i if i.contains("python") => "python",
+            i if i.contains("node") => "javascript",
+            i if i.ends_with("sh") || i.contains("bash") => "shell",
+            _ => return None,
+        };
+        return Some(syntax.into());
+    }
+
+    if (trimmed.starts_with('{') || trimmed.starts_with('['))
+        && serde_json::from_str::<serde_json::Value>(text).is_ok()
+    {
+        return Some("json".into());
+    }
+
+    None
+}
+
+/// Syntax restricted to characters that are valid in a media type parameter
+fn normalize_syntax(info: &str) -> Option<String> {
+    let syntax = info.split_whitespace().next()?.to_lowercase();
+    let valid = syntax.len() <= 32
+        && syntax
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.' | '_'));
+    valid.then_some(syntax)
+}
+
+fn extension(syntax: &str) -> &'static str {
+    match syntax {
+        "rust" | "rs" => "rs",
+        "python" | "py" => "py",
+        "javascript" | "js" => "js",
+        "typescript" | "ts" => "ts",
+        "json" => "json",
+        "toml" => "toml",
+        "yaml" | "yml" => "yaml",
+        "shell" | "sh" | "bash" => "sh",
+        "html" => "html",
+        "css" => "css",
+        "c" => "c",
+        "cpp" | "c++" => "cpp",
+        "go" => "go",
+        "java" => "java",
+        "kotlin" | "kt" => "kt",
+        "swift" => "swift",
+        "sql" => "sql",
+        "diff" | "patch" => "diff",
+        "log" => "log",
+        _ => "txt",
+    }
+}
+
+/// First lines of the text, truncated to [`SNIPPET_PREVIEW_SIZE`] characters
+fn preview(text: &str) -> Vec<String> {
+    let mut preview = vec![];
+    let mut remaining = SNIPPET_PREVIEW_SIZE;
+    let mut truncated = false;
+
+    for (index, line) in text.lines().enumerate() {
+        if index == SNIPPET_PREVIEW_LINES || remaining == 0 {
+            truncated = true;
+            break;
+        }
+
+        let count = line.chars().count();
+        if count > remaining {
+            preview.push(line.chars().take(remaining).collect());
+            truncated = true;
+            break;
+        }
+
+        remaining -= count;
+        preview.push(line.to_string());
+    }
+
+    if truncated {
+        match preview.last_mut() {
+            Some(line) if !line.trim().is_empty() => line.push('…'),
+            _ => preview.push("…".into()),
+        }
+    }
+
+    preview
+}
+
+#[cfg(test)]
+mod test {
+    use uuid::Uuid;
+    use warp::constellation::file::File;
+
+    use super::{syntax, Snippet, SNIPPET_PREVIEW_LINES, SNIPPET_PREVIEW_SIZE};
+
+    #[test]
+    fn fenced_code_is_stored_with_its_syntax() {
+        let body = (0..100)
+            .map(|i| format!("    let value_{i} = {i};"))
+            .collect::<Vec<_>>();
+
+        let mut lines = vec!["```rust".to_string()];
+        lines.extend(body.iter().cloned());
+        lines.push("```".into());
+
+        let id = Uuid::new_v4();
+        let snippet = Snippet::new(id, &lines);
+
+        assert_eq!(snippet.syntax.as_deref(), Some("rust"));
+        assert_eq!(snippet.name, format!("snippet-{}.rs", id.simple()));
+        assert_eq!(snippet.data, body.join("\n").as_bytes());
+
+        assert_eq!(snippet.preview.len(), SNIPPET_PREVIEW_LINES);
+        assert!(snippet.preview[SNIPPET_PREVIEW_LINES - 1].ends_with('…'));
+
+        let file = File::new(&snippet.name);
+        file.set_file_type(snippet.file_type());
+        assert_eq!(syntax(&file).as_deref(), Some("rust"));
+    }
+
+    #[test]
+    fn plain_text_has_no_syntax() {
+        let lines = vec!["2024-01-01T00:00:00Z INFO request handled".repeat(200)];
+        let snippet = Snippet::new(Uuid::new_v4(), &lines);
+
+        assert_eq!(snippet.syntax, None);
+        assert!(snippet.name.ends_with(".txt"));
+        assert_eq!(snippet.preview.len(), 1);
+        assert_eq!(snippet.preview[0].chars().count(), SNIPPET_PREVIEW_SIZE + 1);
+
+        let file = File::new(&snippet.name);
+        file.set_file_type(snippet.file_type());
+        assert_eq!(syntax(&file), None);
+    }
+}
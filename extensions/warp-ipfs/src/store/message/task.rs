@@ -1,60 +1,83 @@
 use bytes::Bytes;
+use chrono::{DateTime, Utc};
 use either::Either;
-use futures::channel::oneshot;
+use futures::channel::{mpsc, oneshot};
 use futures::stream::BoxStream;
-use futures::{StreamExt, TryFutureExt};
+use futures::{SinkExt, StreamExt, TryFutureExt};
+use futures_timeout::TimeoutExt;
 use futures_timer::Delay;
 use indexmap::{IndexMap, IndexSet};
 use ipld_core::cid::Cid;
-use rust_ipfs::{libp2p::gossipsub::Message, Ipfs};
-use rust_ipfs::{IpfsPath, PeerId, SubscriptionStream};
+use rust_ipfs::{
+    libp2p::gossipsub::{Message, TopicHash},
+    Ipfs,
+};
+use rust_ipfs::{IpfsPath, PeerId};
 use serde::{Deserialize, Serialize};
 use std::borrow::BorrowMut;
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::future::Future;
+use std::ops::RangeInclusive;
 use std::path::PathBuf;
 use std::pin::Pin;
 use std::str::FromStr;
 use std::task::{Context, Poll, Waker};
 use std::time::Duration;
+use tracing::{Instrument, Span};
 use uuid::Uuid;
-use warp::constellation::ConstellationProgressStream;
+use warp::constellation::{ConstellationProgressStream, Progression};
 use warp::crypto::DID;
 use warp::raygun::{
-    AttachmentEventStream, ConversationImage, GroupPermissionOpt, Location, MessageEvent,
-    MessageOptions, MessageReference, MessageStatus, MessageType, Messages, MessagesType,
-    RayGunEventKind,
+    AttachmentEventStream, AttachmentOptions, ConversationImage, ConversationStats,
+    GroupPermissionOpt, HistoryVisibility, Location, MessageEvent, MessageOptions,
+    MessageReference, MessageStatus, MessageType, Messages, MessagesType, RayGunEventKind,
+    RetentionPolicy, SearchQuery, SearchResult,
 };
 use warp::{
     crypto::generate,
     error::Error,
     raygun::{
-        ConversationType, GroupPermission, ImplGroupPermissions, MessageEventKind, PinState,
-        ReactionState,
+        processor::MessageProcessorPipeline, ConversationType, GroupPermission,
+        ImplGroupPermissions, MessageEventKind, PinState, ReactionState, SystemMessage,
     },
 };
 use web_time::Instant;
 
 // use crate::config;
 // use crate::shuttle::message::client::MessageCommand;
-use crate::store::conversation::message::{MessageDocument, MessageDocumentBuilder};
+use crate::inbox::PendingEventKind;
+use crate::store::compression::Compression;
+use crate::store::conversation::clock::HybridClock;
+use crate::store::conversation::message::{MessageDocument, MessageDocumentBuilder, Receipt};
+use crate::store::conversation::retention::RetentionPolicyDocument;
 use crate::store::discovery::Discovery;
 use crate::store::document::files::FileDocument;
+use crate::store::document::identity::Feature;
 use crate::store::document::image_dag::ImageDag;
 use crate::store::ds_key::DataStoreKey;
+use crate::store::encrypted::EncryptedStore;
+use crate::store::erasure;
 use crate::store::event_subscription::EventSubscription;
-use crate::store::message::attachment::AttachmentStream;
+use crate::store::message::attachment::{media_directory, AttachmentStream};
+use crate::store::message::chunk::{self, ChunkAssembler, PayloadChunk};
+use crate::store::message::search::{self, SearchIndex, SEARCH_INDEX_SAVE_INTERVAL};
+use crate::store::message::sequence::{Outbox, ReorderBuffer, OUTBOX_CAPACITY};
+use crate::store::message::snippet::Snippet;
 use crate::store::topics::PeerTopic;
+use crate::store::usage::UsageTracker;
 use crate::store::{
     ecdh_shared_key, verify_serde_sig, ConversationEvents, ConversationImageType,
     MAX_CONVERSATION_BANNER_SIZE, MAX_CONVERSATION_ICON_SIZE,
 };
+use crate::time::Clock;
+use crate::usage::UsageCategory;
 use crate::utils::{ByteCollection, ExtensionType};
 use crate::{
     // rt::LocalExecutor,
     store::{
         conversation::ConversationDocument,
+        device,
         document::root::RootDocumentMap,
         ecdh_decrypt, ecdh_encrypt,
         files::FileStore,
@@ -63,13 +86,14 @@ use crate::{
         payload::{PayloadBuilder, PayloadMessage},
         ConversationRequestKind, ConversationRequestResponse, ConversationResponseKind,
         ConversationUpdateKind, DidExt, MessagingEvents, PeerIdExt, MAX_CONVERSATION_DESCRIPTION,
-        MAX_MESSAGE_SIZE, MIN_MESSAGE_SIZE,
+        MAX_MESSAGE_SIZE_LIMIT, MAX_SLOW_MODE, MAX_SNIPPET_SIZE, MIN_MESSAGE_SIZE,
+        SLOW_MODE_CLOCK_SKEW,
     },
 };
 
 type AttachmentOneshot = (MessageDocument, oneshot::Sender<Result<(), Error>>);
 
-use super::DownloadStream;
+use super::{DownloadStream, CHAT_DIRECTORY};
 
 #[derive(Debug)]
 #[allow(dead_code)]
@@ -78,6 +102,18 @@ pub enum ConversationTaskCommand {
         desc: Option<String>,
         response: oneshot::Sender<Result<(), Error>>,
     },
+    SetHistoryVisibility {
+        visibility: HistoryVisibility,
+        response: oneshot::Sender<Result<(), Error>>,
+    },
+    SetSlowMode {
+        slow_mode: u64,
+        response: oneshot::Sender<Result<(), Error>>,
+    },
+    SetRetentionPolicy {
+        policy: Option<RetentionPolicy>,
+        response: oneshot::Sender<Result<(), Error>>,
+    },
     FavoriteConversation {
         favorite: bool,
         response: oneshot::Sender<Result<(), Error>>,
@@ -93,6 +129,9 @@ pub enum ConversationTaskCommand {
     GetMessagesCount {
         response: oneshot::Sender<Result<usize, Error>>,
     },
+    GetStats {
+        response: oneshot::Sender<Result<ConversationStats, Error>>,
+    },
     GetMessageReference {
         message_id: Uuid,
         response: oneshot::Sender<Result<MessageReference, Error>>,
@@ -122,6 +161,10 @@ pub enum ConversationTaskCommand {
         message_id: Uuid,
         response: oneshot::Sender<Result<MessageStatus, Error>>,
     },
+    MarkRead {
+        message_id: Uuid,
+        response: oneshot::Sender<Result<(), Error>>,
+    },
 
     SendMessage {
         lines: Vec<String>,
@@ -156,6 +199,7 @@ pub enum ConversationTaskCommand {
         message_id: Option<Uuid>,
         locations: Vec<Location>,
         lines: Vec<String>,
+        options: AttachmentOptions,
         response: oneshot::Sender<Result<(Uuid, AttachmentEventStream), Error>>,
     },
     DownloadAttachment {
@@ -220,11 +264,81 @@ pub enum ConversationTaskCommand {
     EventHandler {
         response: oneshot::Sender<tokio::sync::broadcast::Sender<MessageEventKind>>,
     },
+    MergeMessages {
+        messages: Vec<MessageDocument>,
+        response: oneshot::Sender<Result<usize, Error>>,
+    },
+    RotateKey {
+        response: oneshot::Sender<Result<(), Error>>,
+    },
+    SearchMessages {
+        query: SearchQuery,
+        response: oneshot::Sender<Result<Vec<SearchResult>, Error>>,
+    },
+    ExportQueue {
+        recipient: DID,
+        response: oneshot::Sender<Vec<(String, Bytes)>>,
+    },
+    ImportPayload {
+        sender: PeerId,
+        topic: String,
+        data: Bytes,
+        response: oneshot::Sender<Result<(), Error>>,
+    },
     Delete {
         response: oneshot::Sender<Result<(), Error>>,
     },
 }
 
+pub type ConversationTaskReceiver = mpsc::Receiver<(ConversationTaskCommand, Span)>;
+
+/// Sender of the commands of a conversation task. Each command is sent along with the span it is sent from, so the
+/// logs of the task are correlated with the operation that sent it
+#[derive(Clone, Debug)]
+pub struct ConversationTaskSender {
+    tx: mpsc::Sender<(ConversationTaskCommand, Span)>,
+}
+
+impl ConversationTaskSender {
+    pub fn channel(buffer: usize) -> (Self, ConversationTaskReceiver) {
+        let (tx, rx) = mpsc::channel(buffer);
+        (Self { tx }, rx)
+    }
+
+    pub async fn send(&mut self, command: ConversationTaskCommand) -> Result<(), mpsc::SendError> {
+        self.tx.send((command, Span::current())).await
+    }
+
+    pub fn close_channel(&mut self) {
+        self.tx.close_channel();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+}
+
+/// Maximum duration the message processors have to process a received message
+const MESSAGE_PROCESSOR_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Maximum attempts at publishing a queued item before it is marked as failed
+const MAX_QUEUE_ATTEMPTS: u32 = 8;
+
+/// Maximum amount of queued items published to a single peer each time the queue is processed
+const MAX_QUEUE_ITEMS_PER_INTERVAL: usize = 16;
+
+/// Upper bound, in seconds, of the delay between attempts at publishing a queued item
+const MAX_QUEUE_BACKOFF_SECS: i64 = 300;
+
+/// Interval at which messages that outlived the maximum retention of the conversation are removed
+const RETENTION_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Duration the keys of members that left are kept after their epochs are closed
+const KEY_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Interval at which participants that became reachable are asked for the events that were missed
+const CATCH_UP_INTERVAL: Duration = Duration::from_secs(5);
+
 pub struct ConversationTask {
     conversation_id: Uuid,
     ipfs: Ipfs,
@@ -235,17 +349,36 @@ pub struct ConversationTask {
     pending_key_exchange: IndexMap<DID, Vec<(Bytes, bool)>>,
     document: ConversationDocument,
     keystore: Keystore,
+    clock: HybridClock,
+    time: Clock,
 
-    messaging_stream: SubscriptionStream,
-    event_stream: SubscriptionStream,
-    request_stream: SubscriptionStream,
+    messaging_stream: BoxStream<'static, Message>,
+    event_stream: BoxStream<'static, Message>,
+    request_stream: BoxStream<'static, Message>,
 
     attachment_tx: futures::channel::mpsc::Sender<AttachmentOneshot>,
     attachment_rx: futures::channel::mpsc::Receiver<AttachmentOneshot>,
     event_broadcast: tokio::sync::broadcast::Sender<MessageEventKind>,
     event_subscription: EventSubscription<RayGunEventKind>,
-
-    command_rx: futures::channel::mpsc::Receiver<ConversationTaskCommand>,
+    processors: MessageProcessorPipeline,
+    usage: UsageTracker,
+    chunks: ChunkAssembler,
+    /// Date of the latest message of each member, used to enforce slow mode
+    last_message_dates: HashMap<DID, DateTime<Utc>>,
+    /// Date at which the next message outlives the maximum retention, if known. Messages are only scanned for
+    /// removal once it is reached
+    retention_deadline: Option<DateTime<Utc>>,
+    /// Statistics of the conversation, updated as messages are added or removed
+    stats: ConversationStats,
+    /// Sequence number of the latest payload published to the conversation
+    sequence: u64,
+    outbox: Outbox,
+    reorder: ReorderBuffer<MessagingEvents>,
+    /// Participants that were reachable on the conversation topic when last checked
+    reachable: HashSet<DID>,
+    search: SearchIndex,
+
+    command_rx: ConversationTaskReceiver,
 
     //TODO: replace queue
     queue: HashMap<DID, Vec<QueueItem>>,
@@ -289,19 +422,30 @@ impl ConversationTask {
         identity: &IdentityStore,
         file: &FileStore,
         discovery: &Discovery,
-        command_rx: futures::channel::mpsc::Receiver<ConversationTaskCommand>,
+        command_rx: ConversationTaskReceiver,
         event_subscription: EventSubscription<RayGunEventKind>,
+        processors: MessageProcessorPipeline,
+        usage: &UsageTracker,
+        search: Option<EncryptedStore>,
     ) -> Result<Self, Error> {
         let document = root.get_conversation_document(conversation_id).await?;
         let main_topic = document.topic();
         let event_topic = document.event_topic();
         let request_topic = document.exchange_topic(&identity.did_key());
 
-        let messaging_stream = ipfs.pubsub_subscribe(main_topic).await?;
-
-        let event_stream = ipfs.pubsub_subscribe(event_topic).await?;
-
-        let request_stream = ipfs.pubsub_subscribe(request_topic).await?;
+        // A replica does not subscribe to the topics of the conversation since it is not able to answer them
+        let (messaging_stream, event_stream, request_stream) = match identity.is_replica() {
+            true => (
+                futures::stream::pending().boxed(),
+                futures::stream::pending().boxed(),
+                futures::stream::pending().boxed(),
+            ),
+            false => (
+                ipfs.pubsub_subscribe(main_topic).await?.boxed(),
+                ipfs.pubsub_subscribe(event_topic).await?.boxed(),
+                ipfs.pubsub_subscribe(request_topic).await?.boxed(),
+            ),
+        };
 
         let (atx, arx) = futures::channel::mpsc::channel(256);
         let (btx, _) = tokio::sync::broadcast::channel(1024);
@@ -315,6 +459,8 @@ impl ConversationTask {
             pending_key_exchange: Default::default(),
             document,
             keystore: Keystore::default(),
+            clock: HybridClock::default(),
+            time: identity.clock().clone(),
 
             messaging_stream,
             request_stream,
@@ -324,6 +470,17 @@ impl ConversationTask {
             attachment_rx: arx,
             event_broadcast: btx,
             event_subscription,
+            processors,
+            usage: usage.clone(),
+            chunks: ChunkAssembler::new(identity.clock()),
+            last_message_dates: HashMap::new(),
+            retention_deadline: None,
+            stats: ConversationStats::default(),
+            sequence: 0,
+            outbox: Outbox::default(),
+            reorder: ReorderBuffer::new(identity.clock()),
+            reachable: HashSet::new(),
+            search: SearchIndex::load(search, conversation_id).await,
             command_rx,
             queue: Default::default(),
             terminate: ConversationTermination::default(),
@@ -370,6 +527,19 @@ impl ConversationTask {
             task.queue = data;
         }
 
+        if let Some(last) = task
+            .document
+            .get_message_list(ipfs)
+            .await
+            .ok()
+            .and_then(|list| list.last().map(MessageDocument::timestamp))
+        {
+            task.clock = HybridClock::new(last);
+        }
+
+        task.load_stats().await;
+        task.load_sequence().await;
+
         for participant in task.document.recipients.iter() {
             if !task.discovery.contains(participant).await {
                 let _ = task.discovery.insert(participant).await;
@@ -382,42 +552,139 @@ impl ConversationTask {
 }
 
 impl ConversationTask {
+    /// Load the conversation once the first command is received, used for conversations that are excluded from
+    /// sync on this device
+    #[allow(clippy::too_many_arguments)]
+    pub async fn run_on_demand(
+        conversation_id: Uuid,
+        ipfs: Ipfs,
+        root: RootDocumentMap,
+        identity: IdentityStore,
+        file: FileStore,
+        discovery: Discovery,
+        mut command_rx: ConversationTaskReceiver,
+        event_subscription: EventSubscription<RayGunEventKind>,
+        processors: MessageProcessorPipeline,
+        usage: UsageTracker,
+        search: Option<EncryptedStore>,
+    ) {
+        let Some((command, span)) = command_rx.next().await else {
+            return;
+        };
+
+        let mut task = match Self::new(
+            conversation_id,
+            &ipfs,
+            &root,
+            &identity,
+            &file,
+            &discovery,
+            command_rx,
+            event_subscription,
+            processors,
+            &usage,
+            search,
+        )
+        .await
+        {
+            Ok(task) => task,
+            Err(e) => {
+                tracing::error!(%conversation_id, error = %e, "unable to hydrate conversation");
+                return;
+            }
+        };
+
+        tracing::info!(%conversation_id, "hydrated conversation");
+
+        task.process_command(command).instrument(span).await;
+        task.run().await
+    }
+
     pub async fn run(mut self) {
         let this = &mut self;
 
         let conversation_id = this.conversation_id;
 
+        // The timers of the task would publish on behalf of the account or change the conversation, so a replica only
+        // answers the commands of its read-only handles
+        if this.identity.is_replica() {
+            loop {
+                tokio::select! {
+                    biased;
+                    _ = &mut this.terminate => {
+                        break;
+                    }
+                    Some((command, span)) = this.command_rx.next() => {
+                        this.process_command(command).instrument(span).await;
+                    }
+                }
+            }
+            return;
+        }
+
         let mut queue_timer = Delay::new(Duration::from_secs(1));
 
         let mut pending_exchange_timer = Delay::new(Duration::from_secs(1));
 
         let mut check_mailbox = Delay::new(Duration::from_secs(5));
 
+        let mut retention_timer = Delay::new(RETENTION_INTERVAL);
+
+        let mut reorder_timer = Delay::new(Duration::from_secs(1));
+
+        let mut catch_up_timer = Delay::new(Duration::from_secs(1));
+
+        let compaction_interval = this
+            .identity
+            .config()
+            .store_setting()
+            .compaction
+            .map(|policy| policy.interval)
+            .unwrap_or(RETENTION_INTERVAL);
+
+        let mut compaction_timer = Delay::new(compaction_interval);
+
+        let mut search_index_timer = Delay::new(SEARCH_INDEX_SAVE_INTERVAL);
+
         loop {
             tokio::select! {
                 biased;
                 _ = &mut this.terminate => {
                     break;
                 }
-                Some(command) = this.command_rx.next() => {
-                    this.process_command(command).await;
+                Some((command, span)) = this.command_rx.next() => {
+                    this.process_command(command).instrument(span).await;
                 }
                 Some((message, response)) = this.attachment_rx.next() => {
                     let _ = response.send(this.store_direct_for_attachment(message).await);
                 }
-                Some(request) = this.request_stream.next() => {
+                // The task ends once a subscription ends so that it is restarted by the supervisor instead of
+                // silently no longer receiving from the topic
+                request = this.request_stream.next() => {
+                    let Some(request) = request else {
+                        tracing::error!(%conversation_id, name = "request", "subscription ended");
+                        break;
+                    };
                     let source = request.source;
                     if let Err(e) = process_request_response_event(this, request).await {
                         tracing::error!(%conversation_id, sender = ?source, error = %e, name = "request", "Failed to process payload");
                     }
                 }
-                Some(event) = this.event_stream.next() => {
+                event = this.event_stream.next() => {
+                    let Some(event) = event else {
+                        tracing::error!(%conversation_id, name = "ev", "subscription ended");
+                        break;
+                    };
                     let source = event.source;
                     if let Err(e) = process_conversation_event(this, event).await {
                         tracing::error!(%conversation_id, sender = ?source, error = %e, name = "ev", "Failed to process payload");
                     }
                 }
-                Some(message) = this.messaging_stream.next() => {
+                message = this.messaging_stream.next() => {
+                    let Some(message) = message else {
+                        tracing::error!(%conversation_id, name = "msg", "subscription ended");
+                        break;
+                    };
                     let source = message.source;
                     if let Err(e) = this.process_msg_event(message).await {
                         tracing::error!(%conversation_id, sender = ?source, error = %e, name = "msg", "Failed to process payload");
@@ -436,8 +703,37 @@ impl ConversationTask {
                     // _ = this.load_from_mailbox().await;
                     check_mailbox.reset(Duration::from_secs(60));
                 }
+                _ = &mut retention_timer => {
+                    if let Err(e) = this.remove_expired_messages().await {
+                        tracing::warn!(%conversation_id, error = %e, "unable to apply retention policy");
+                    }
+                    if let Err(e) = this.prune_keystore().await {
+                        tracing::warn!(%conversation_id, error = %e, "unable to prune keystore");
+                    }
+                    retention_timer.reset(RETENTION_INTERVAL);
+                }
+                _ = &mut reorder_timer => {
+                    process_held_events(this).await;
+                    reorder_timer.reset(Duration::from_secs(1));
+                }
+                _ = &mut catch_up_timer => {
+                    this.catch_up().await;
+                    catch_up_timer.reset(CATCH_UP_INTERVAL);
+                }
+                _ = &mut compaction_timer => {
+                    if let Err(e) = this.compact_history().await {
+                        tracing::warn!(%conversation_id, error = %e, "unable to compact conversation history");
+                    }
+                    compaction_timer.reset(compaction_interval);
+                }
+                _ = &mut search_index_timer => {
+                    this.save_search_index().await;
+                    search_index_timer.reset(SEARCH_INDEX_SAVE_INTERVAL);
+                }
             }
         }
+
+        this.save_search_index().await;
     }
 }
 
@@ -635,6 +931,24 @@ impl ConversationTask {
                 let result = self.set_description(desc.as_deref()).await;
                 let _ = response.send(result);
             }
+            ConversationTaskCommand::SetHistoryVisibility {
+                visibility,
+                response,
+            } => {
+                let result = self.set_history_visibility(visibility).await;
+                let _ = response.send(result);
+            }
+            ConversationTaskCommand::SetSlowMode {
+                slow_mode,
+                response,
+            } => {
+                let result = self.set_slow_mode(slow_mode).await;
+                let _ = response.send(result);
+            }
+            ConversationTaskCommand::SetRetentionPolicy { policy, response } => {
+                let result = self.set_retention_policy(policy).await;
+                let _ = response.send(result);
+            }
             ConversationTaskCommand::FavoriteConversation { favorite, response } => {
                 let result = self.set_favorite_conversation(favorite).await;
                 let _ = response.send(result);
@@ -654,6 +968,9 @@ impl ConversationTask {
                 let result = self.messages_count().await;
                 let _ = response.send(result);
             }
+            ConversationTaskCommand::GetStats { response } => {
+                let _ = response.send(Ok(self.stats.clone()));
+            }
             ConversationTaskCommand::GetMessageReference {
                 message_id,
                 response,
@@ -695,6 +1012,13 @@ impl ConversationTask {
                 let result = self.message_status(message_id).await;
                 let _ = response.send(result);
             }
+            ConversationTaskCommand::MarkRead {
+                message_id,
+                response,
+            } => {
+                let result = self.mark_read(message_id).await;
+                let _ = response.send(result);
+            }
             ConversationTaskCommand::SendMessage { lines, response } => {
                 let result = self.send_message(lines).await;
                 let _ = response.send(result);
@@ -743,9 +1067,10 @@ impl ConversationTask {
                 message_id,
                 locations,
                 lines,
+                options,
                 response,
             } => {
-                let result = self.attach(message_id, locations, lines);
+                let result = self.attach(message_id, locations, lines, options);
                 let _ = response.send(result);
             }
             ConversationTaskCommand::DownloadAttachment {
@@ -833,6 +1158,33 @@ impl ConversationTask {
                 let sender = self.event_broadcast.clone();
                 let _ = response.send(sender);
             }
+            ConversationTaskCommand::MergeMessages { messages, response } => {
+                let result = self.merge_messages(messages).await;
+                let _ = response.send(result);
+            }
+            ConversationTaskCommand::RotateKey { response } => {
+                let result = self.rotate_key().await;
+                let _ = response.send(result);
+            }
+            ConversationTaskCommand::SearchMessages { query, response } => {
+                let result = self.search_messages(query).await;
+                let _ = response.send(result);
+            }
+            ConversationTaskCommand::ExportQueue {
+                recipient,
+                response,
+            } => {
+                let _ = response.send(self.export_queue(&recipient));
+            }
+            ConversationTaskCommand::ImportPayload {
+                sender,
+                topic,
+                data,
+                response,
+            } => {
+                let result = self.import_payload(sender, topic, data).await;
+                let _ = response.send(result);
+            }
             ConversationTaskCommand::Delete { response } => {
                 let result = self.delete().await;
                 let _ = response.send(result);
@@ -842,11 +1194,213 @@ impl ConversationTask {
 }
 
 impl ConversationTask {
+    // Statistics are only computed from the messages once, after which they are updated as messages are added or
+    // removed and kept in the datastore
+    async fn load_stats(&mut self) {
+        let key = self.ipfs.conversation_stats(self.conversation_id);
+
+        if let Some(stats) = self
+            .ipfs
+            .repo()
+            .data_store()
+            .get(key.as_bytes())
+            .await
+            .unwrap_or_default()
+            .and_then(|bytes| serde_json::from_slice::<ConversationStats>(&bytes).ok())
+        {
+            self.stats = stats;
+            return;
+        }
+
+        let list = match self.document.get_message_list(&self.ipfs).await {
+            Ok(list) => list,
+            Err(e) => {
+                // the stats are rebuilt on the next load rather than storing them empty
+                tracing::warn!(conversation_id = %self.conversation_id, error = %e, "unable to load messages for stats");
+                return;
+            }
+        };
+
+        let mut stats = ConversationStats::default();
+
+        for message in list
+            .iter()
+            .filter(|message| message.message_type != MessageType::Event)
+        {
+            stats.record(&message.sender(), message.date(), attachment_bytes(message));
+        }
+
+        self.stats = stats;
+        self.save_stats().await;
+    }
+
+    async fn save_stats(&self) {
+        let key = self.ipfs.conversation_stats(self.conversation_id);
+
+        let Ok(bytes) = serde_json::to_vec(&self.stats) else {
+            return;
+        };
+
+        if let Err(e) = self
+            .ipfs
+            .repo()
+            .data_store()
+            .put(key.as_bytes(), &bytes)
+            .await
+        {
+            tracing::warn!(conversation_id = %self.conversation_id, error = %e, "unable to store conversation stats");
+        }
+    }
+
+    async fn load_sequence(&mut self) {
+        let key = self.ipfs.message_sequence(self.conversation_id);
+
+        self.sequence = self
+            .ipfs
+            .repo()
+            .data_store()
+            .get(key.as_bytes())
+            .await
+            .unwrap_or_default()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+    }
+
+    /// Sequence number of the next payload published to the conversation
+    async fn next_sequence(&mut self) -> u64 {
+        self.sequence += 1;
+
+        let key = self.ipfs.message_sequence(self.conversation_id);
+        if let Ok(bytes) = serde_json::to_vec(&self.sequence) {
+            if let Err(e) = self
+                .ipfs
+                .repo()
+                .data_store()
+                .put(key.as_bytes(), &bytes)
+                .await
+            {
+                tracing::warn!(conversation_id = %self.conversation_id, error = %e, "unable to store message sequence");
+            }
+        }
+
+        self.sequence
+    }
+
+    async fn record_stats(&mut self, message: &MessageDocument) {
+        if message.message_type == MessageType::Event {
+            return;
+        }
+        self.stats
+            .record(&message.sender(), message.date(), attachment_bytes(message));
+        self.save_stats().await;
+    }
+
+    async fn forget_stats(&mut self, message: &MessageDocument) {
+        if message.message_type == MessageType::Event {
+            return;
+        }
+        self.stats
+            .forget(&message.sender(), message.date(), attachment_bytes(message));
+        self.save_stats().await;
+    }
+
+    /// Record a change to the conversation as a system message. Each participant records its own copy, signed by
+    /// its own key, so system messages are never sent to other participants
+    async fn record_system_message(&mut self, system: SystemMessage) {
+        let conversation_id = self.conversation_id;
+
+        let result = async {
+            let keypair = self.root.keypair();
+            let own_did = self.identity.did_key();
+
+            let message = MessageDocumentBuilder::new(keypair, Either::Left(&own_did))
+                .set_conversation_id(conversation_id)
+                .set_sender(own_did.clone())
+                .set_clock(self.clock.tick(self.time.now()))
+                .set_system_message(system)
+                .build()?;
+
+            self.document
+                .insert_message_document(&self.ipfs, &message)
+                .await?;
+            self.set_document().await?;
+            Ok::<_, Error>(message.id)
+        }
+        .await;
+
+        match result {
+            Ok(message_id) => {
+                let _ = self
+                    .event_broadcast
+                    .send(MessageEventKind::MessageReceived {
+                        conversation_id,
+                        message_id,
+                    });
+            }
+            Err(e) => {
+                tracing::warn!(%conversation_id, error = %e, "unable to record system message");
+            }
+        }
+    }
+
+    /// Insert messages from a duplicate conversation, skipping any that are invalid or already exist. Messages are
+    /// moved to this conversation. Returns the amount of messages inserted
+    pub async fn merge_messages(&mut self, messages: Vec<MessageDocument>) -> Result<usize, Error> {
+        let conversation_id = self.conversation_id;
+        let recipients = self.document.recipients();
+
+        let mut list = self.document.message_reference_list(&self.ipfs).await?;
+        let mut inserted = 0;
+
+        for mut message in messages {
+            let message_id = message.id();
+
+            if message.verify().is_err() || !recipients.contains(&message.sender.to_did()) {
+                tracing::warn!(%conversation_id, %message_id, "invalid message. Skipping");
+                continue;
+            }
+
+            message.move_to(conversation_id);
+
+            if list.contains(&self.ipfs, message_id).await
+                || erasure::is_erased(&self.ipfs, message_id).await
+            {
+                continue;
+            }
+
+            list.insert(&self.ipfs, &message).await?;
+            if message.message_type != MessageType::Event {
+                self.stats.record(
+                    &message.sender(),
+                    message.date(),
+                    attachment_bytes(&message),
+                );
+            }
+            self.index_message(&message).await;
+            inserted += 1;
+        }
+
+        if inserted > 0 {
+            self.document
+                .set_message_reference_list(&self.ipfs, list)
+                .await?;
+            self.set_document().await?;
+            self.save_stats().await;
+        }
+
+        Ok(inserted)
+    }
+
     pub async fn delete(&mut self) -> Result<(), Error> {
         // TODO: Maybe announce to network of the local node removal here
         self.document.messages.take();
         self.document.deleted = true;
+        self.search.clear();
+        self.save_search_index().await;
+        self.forget_mentions(None).await;
         self.set_document().await?;
+        let key = self.ipfs.conversation_stats(self.conversation_id);
+        let _ = self.ipfs.repo().data_store().remove(key.as_bytes()).await;
         if let Ok(mut ks_map) = self.root.get_keystore_map().await {
             if ks_map.remove(&self.conversation_id.to_string()).is_some() {
                 if let Err(e) = self.root.set_keystore_map(ks_map).await {
@@ -890,6 +1444,27 @@ impl ConversationTask {
         Ok(())
     }
 
+    /// Run a received message through the registered processors so the derived metadata
+    /// is cached before the message is surfaced in an event
+    async fn process_message(&self, message: &warp::raygun::Message) {
+        if self.processors.is_empty() {
+            return;
+        }
+
+        let conversation_id = self.conversation_id;
+        let message_id = message.id();
+
+        if self
+            .processors
+            .process(message)
+            .timeout(MESSAGE_PROCESSOR_TIMEOUT)
+            .await
+            .is_err()
+        {
+            tracing::warn!(%conversation_id, %message_id, "processing of message timed out");
+        }
+    }
+
     pub async fn replace_document(
         &mut self,
         mut document: ConversationDocument,
@@ -1036,8 +1611,26 @@ impl ConversationTask {
     }
 
     async fn process_msg_event(&mut self, msg: Message) -> Result<(), Error> {
-        let data = PayloadMessage::<MessagingEvents>::from_bytes(&msg.data)?;
+        // Payloads exceeding the pubsub limit are split into chunks by the sender and are only processed
+        // once every chunk has been received
+        let bytes = match PayloadChunk::from_bytes(&msg.data) {
+            Ok(chunk) => match self.chunks.insert(msg.source, chunk)? {
+                Some(bytes) => bytes,
+                None => return Ok(()),
+            },
+            Err(_) => Bytes::from(msg.data),
+        };
+
+        let data = PayloadMessage::<MessagingEvents>::from_bytes(&bytes)?;
         let sender = data.sender().to_did()?;
+        let sequence = data.sequence();
+
+        self.usage.record_received(
+            UsageCategory::Messages,
+            Some(self.conversation_id),
+            Some(&sender),
+            bytes.len(),
+        );
 
         let keypair = self.root.keypair();
 
@@ -1067,7 +1660,7 @@ impl ConversationTask {
             }
             ConversationType::Group => {
                 let bytes = data.to_bytes()?;
-                match self.keystore.get_latest(keypair, &sender) {
+                match self.conversation_key(Some(&sender), data.key_epoch()) {
                     Ok(key) => data.message_from_key(&key)?,
                     Err(Error::PublicKeyDoesntExist) => {
                         // Lets first try to get the message from the payload. If we are not apart of the list of recipients, we will then
@@ -1105,9 +1698,7 @@ impl ConversationTask {
             }
         };
 
-        message_event(self, &sender, event).await?;
-
-        Ok(())
+        sequenced_message_event(self, &sender, sequence, event).await
     }
 
     async fn messages_count(&self) -> Result<usize, Error> {
@@ -1131,17 +1722,146 @@ impl ConversationTask {
             .map(|document| document.into())
     }
 
-    async fn get_message_references<'a>(
-        &self,
-        opt: MessageOptions,
-    ) -> Result<BoxStream<'a, MessageReference>, Error> {
-        self.document
-            .get_messages_reference_stream(&self.ipfs, opt)
-            .await
-    }
+    /// Search the messages of the conversation, rebuilding the search index first if it does not hold every message
+    async fn search_messages(&mut self, query: SearchQuery) -> Result<Vec<SearchResult>, Error> {
+        let terms = search::query_terms(query.text());
+        if terms.is_empty() {
+            return Ok(vec![]);
+        }
 
-    pub async fn get_messages(&self, opt: MessageOptions) -> Result<Messages, Error> {
-        let keypair = self.root.keypair();
+        if !self.search.is_built() {
+            self.rebuild_search_index().await?;
+        }
+
+        let keypair = self.root.keypair();
+        let keystore = pubkey_or_keystore(self)?;
+
+        let mut results = vec![];
+
+        for message_id in self.search.candidates(&terms) {
+            let Ok(document) = self
+                .document
+                .get_message_document(&self.ipfs, message_id)
+                .await
+            else {
+                continue;
+            };
+
+            if matches!(query.sender(), Some(sender) if document.sender.to_did().ne(sender)) {
+                continue;
+            }
+
+            let message = match document
+                .resolve(&self.ipfs, keypair, true, keystore.as_ref())
+                .await
+            {
+                Ok(message) => message,
+                Err(e) => {
+                    tracing::warn!(conversation_id = %self.conversation_id, %message_id, error = %e, "unable to resolve message for search");
+                    continue;
+                }
+            };
+
+            let highlights = search::highlights(message.lines(), &terms);
+            if highlights.is_empty() {
+                continue;
+            }
+
+            results.push(SearchResult::new(message, highlights));
+        }
+
+        results.sort_by(|a, b| b.message().date().cmp(&a.message().date()));
+
+        if let Some(limit) = query.limit() {
+            results.truncate(limit);
+        }
+
+        Ok(results)
+    }
+
+    /// Index every message of the conversation, such as after the index was lost or for messages
+    /// stored before it existed
+    async fn rebuild_search_index(&mut self) -> Result<(), Error> {
+        let keypair = self.root.keypair();
+        let keystore = pubkey_or_keystore(self)?;
+
+        self.search.clear();
+
+        let messages = self.document.get_message_list(&self.ipfs).await?;
+
+        for document in messages {
+            if document.message_type == MessageType::Event {
+                continue;
+            }
+
+            match document
+                .resolve(&self.ipfs, keypair, true, keystore.as_ref())
+                .await
+            {
+                Ok(message) => self.search.insert(message.id(), message.lines()),
+                Err(e) => {
+                    tracing::warn!(conversation_id = %self.conversation_id, message_id = %document.id, error = %e, "unable to index message")
+                }
+            }
+        }
+
+        self.search.set_built();
+        self.save_search_index().await;
+        Ok(())
+    }
+
+    /// Index a message whose lines are not known without resolving it
+    async fn index_message(&mut self, document: &MessageDocument) {
+        if document.message_type == MessageType::Event {
+            return;
+        }
+
+        let keypair = self.root.keypair();
+        let result = async {
+            let keystore = pubkey_or_keystore(self)?;
+            document
+                .resolve(&self.ipfs, keypair, true, keystore.as_ref())
+                .await
+        }
+        .await;
+
+        match result {
+            Ok(message) => self.search.insert(message.id(), message.lines()),
+            Err(e) => {
+                tracing::warn!(conversation_id = %self.conversation_id, message_id = %document.id, error = %e, "unable to index message")
+            }
+        }
+    }
+
+    /// Remove the pending mentions of a message, or of the conversation, once they no longer apply
+    async fn forget_mentions(&self, message_id: Option<Uuid>) {
+        if let Err(e) = self
+            .identity
+            .pending_events()
+            .acknowledge_mentions(self.conversation_id, message_id)
+            .await
+        {
+            tracing::warn!(conversation_id = %self.conversation_id, error = %e, "unable to remove pending mentions");
+        }
+    }
+
+    async fn save_search_index(&mut self) {
+        if let Err(e) = self.search.save().await {
+            tracing::warn!(conversation_id = %self.conversation_id, error = %e, "unable to save search index");
+        }
+    }
+
+    async fn get_message_references<'a>(
+        &self,
+        opt: MessageOptions,
+    ) -> Result<BoxStream<'a, MessageReference>, Error> {
+        self.document
+            .get_messages_reference_stream(&self.ipfs, opt)
+            .await
+    }
+
+    pub async fn get_messages(&self, opt: MessageOptions) -> Result<Messages, Error> {
+        let keypair = self.root.keypair();
 
         let keystore = pubkey_or_keystore(self)?;
 
@@ -1169,7 +1889,13 @@ impl ConversationTask {
         }
     }
 
-    fn conversation_key(&self, member: Option<&DID>) -> Result<Vec<u8>, Error> {
+    /// Returns the key of the member, or our own key if no member is provided, for the given epoch.
+    /// If no epoch is provided, the latest key is used.
+    fn conversation_key(
+        &self,
+        member: Option<&DID>,
+        epoch: Option<usize>,
+    ) -> Result<Vec<u8>, Error> {
         let keypair = self.root.keypair();
         let own_did = self.identity.did_key();
 
@@ -1189,11 +1915,382 @@ impl ConversationTask {
             }
             ConversationType::Group => {
                 let recipient = member.unwrap_or(&own_did);
-                self.keystore.get_latest(keypair, recipient)
+                match epoch {
+                    Some(epoch) => self.keystore.get(keypair, recipient, epoch),
+                    None => self.keystore.get_latest(keypair, recipient),
+                }
+            }
+        }
+    }
+
+    /// Epoch of our latest key. Only group conversations have key epochs
+    fn conversation_key_epoch(&self) -> Option<usize> {
+        match self.document.conversation_type() {
+            ConversationType::Direct => None,
+            ConversationType::Group => self.keystore.latest_epoch(&self.identity.did_key()),
+        }
+    }
+
+    /// Rotate our key to a new epoch and share it with the current members of the group.
+    /// Messages encrypted with previous epochs are not re-encrypted and remain readable with the keys of their epoch,
+    /// while members that are removed will not receive the key of the new epoch.
+    async fn rotate_key(&mut self) -> Result<(), Error> {
+        if !matches!(self.document.conversation_type(), ConversationType::Group) {
+            return Ok(());
+        }
+
+        let keypair = self.root.keypair();
+        let own_did = self.identity.did_key();
+
+        self.keystore.close_epochs(&own_did, self.time.now());
+        self.keystore.insert(keypair, &own_did, generate::<64>())?;
+        self.set_keystore(None).await?;
+
+        tracing::info!(id = %self.conversation_id, epoch = ?self.conversation_key_epoch(), "rotated conversation key");
+
+        let recipients = self
+            .document
+            .recipients()
+            .into_iter()
+            .filter(|did| own_did.ne(did))
+            .collect::<Vec<_>>();
+
+        for recipient in recipients {
+            if let Err(e) = self.send_key(&recipient).await {
+                tracing::warn!(id = %self.conversation_id, %recipient, error = %e, "unable to send key");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Send our keys to the member. The epochs that are shared depend on the history visibility of the group
+    async fn send_key(&mut self, did: &DID) -> Result<(), Error> {
+        let conversation_id = self.conversation_id;
+        let keypair = &self.root.keypair().clone();
+        let own_did = self.identity.did_key();
+
+        if !self.keystore.exist(&own_did) {
+            self.keystore.insert(keypair, &own_did, generate::<64>())?;
+            self.set_keystore(None).await?;
+        }
+
+        let keys = match self.document.history_visibility {
+            HistoryVisibility::All => self.keystore.get_from_epoch(keypair, &own_did, 0),
+            HistoryVisibility::FromJoin => {
+                let epoch = self.keystore.member_epoch(did);
+                self.keystore.get_from_epoch(keypair, &own_did, epoch)
+            }
+            HistoryVisibility::None => self
+                .keystore
+                .get_latest_with_epoch(keypair, &own_did)
+                .map(|entry| vec![entry]),
+        }
+        .inspect_err(|e| {
+            tracing::error!(%conversation_id, error = %e, "Error getting key from store");
+        })?;
+
+        for (epoch, raw_key) in keys {
+            self.send_key_epoch(did, epoch, raw_key).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn send_key_epoch(
+        &mut self,
+        did: &DID,
+        epoch: usize,
+        raw_key: Vec<u8>,
+    ) -> Result<(), Error> {
+        let conversation_id = self.conversation_id;
+        let keypair = self.root.keypair();
+
+        // Once the member publishes devices, the key is only wrapped for them so a revoked device is unable to
+        // read messages sent after the next rotation
+        let devices = self.identity.devices(did).await;
+        let (key, devices) = match devices.is_empty() {
+            true => (ecdh_encrypt(keypair, Some(did), raw_key)?, vec![]),
+            false => (vec![], device::wrap_key(keypair, &devices, &raw_key)?),
+        };
+
+        let response = ConversationRequestResponse::Response {
+            conversation_id,
+            kind: ConversationResponseKind::Key {
+                key,
+                epoch: Some(epoch),
+                devices,
+            },
+        };
+
+        let topic = self.document.exchange_topic(did);
+
+        let payload = PayloadBuilder::new(keypair, response)
+            .add_recipient(did)?
+            .from_ipfs(&self.ipfs)
+            .await?;
+
+        let peers = self.ipfs.pubsub_peers(Some(topic.clone())).await?;
+
+        let peer_id = did.to_peer_id()?;
+
+        let bytes = payload.to_bytes()?;
+
+        tracing::trace!(%conversation_id, "Payload size: {} bytes", bytes.len());
+
+        tracing::info!(%conversation_id, "Responding to {did}");
+
+        if !peers.contains(&peer_id)
+            || (peers.contains(&peer_id)
+                && self
+                    .ipfs
+                    .pubsub_publish(topic.clone(), bytes.clone())
+                    .await
+                    .is_err())
+        {
+            tracing::warn!(%conversation_id, "Unable to publish to topic. Queuing event");
+            // TODO
+            self.queue_event(
+                did.clone(),
+                QueueItem::direct(None, peer_id, topic.clone(), bytes.clone()),
+            )
+            .await;
+        }
+
+        Ok(())
+    }
+
+    /// Rotate our key when a member joins, unless the whole history is visible to new members, and record the
+    /// epoch they joined at
+    async fn member_joined(&mut self, did: &DID) -> Result<(), Error> {
+        if self.document.history_visibility != HistoryVisibility::All {
+            self.rotate_key().await?;
+        }
+
+        if let Some(epoch) = self.keystore.latest_epoch(&self.identity.did_key()) {
+            self.keystore.set_member_epoch(did, epoch);
+            self.set_keystore(None).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Ask `did` to publish the payloads in the range again. The request is not queued since held events are applied
+    /// once the gap times out regardless
+    async fn request_resend(
+        &mut self,
+        did: &DID,
+        missing: RangeInclusive<u64>,
+    ) -> Result<(), Error> {
+        let request = ConversationRequestResponse::Request {
+            conversation_id: self.conversation_id,
+            kind: ConversationRequestKind::ResendEvents {
+                from: *missing.start(),
+                to: *missing.end(),
+            },
+        };
+
+        let keypair = self.root.keypair();
+
+        let payload = PayloadBuilder::new(keypair, request)
+            .add_recipient(did)?
+            .from_ipfs(&self.ipfs)
+            .await?;
+
+        let topic = self.document.exchange_topic(did);
+
+        let peers = self.ipfs.pubsub_peers(Some(topic.clone())).await?;
+        if !peers.contains(&did.to_peer_id()?) {
+            return Err(Error::IdentityDoesntExist);
+        }
+
+        self.ipfs.pubsub_publish(topic, payload.to_bytes()?).await?;
+        Ok(())
+    }
+
+    /// Ask the participants that became reachable since the last check for the events that were missed while
+    /// either side was offline
+    async fn catch_up(&mut self) {
+        let conversation_id = self.conversation_id;
+        let own_did = self.identity.did_key();
+
+        let Ok(peers) = self.ipfs.pubsub_peers(Some(self.document.topic())).await else {
+            return;
+        };
+
+        let reachable = self
+            .document
+            .recipients()
+            .into_iter()
+            .filter(|did| *did != own_did)
+            .filter(|did| {
+                did.to_peer_id()
+                    .map(|peer_id| peers.contains(&peer_id))
+                    .unwrap_or_default()
+            })
+            .collect::<HashSet<_>>();
+
+        let reconnected = reachable
+            .difference(&self.reachable)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        self.reachable = reachable;
+
+        for did in reconnected {
+            // Older versions are unable to deserialize the request
+            if !self
+                .identity
+                .supports_feature(std::slice::from_ref(&did), Feature::CatchUp)
+                .await
+            {
+                continue;
+            }
+
+            if let Err(e) = self.request_catch_up(&did).await {
+                tracing::warn!(%conversation_id, %did, error = %e, "unable to request catch up");
             }
         }
     }
 
+    async fn request_catch_up(&mut self, did: &DID) -> Result<(), Error> {
+        let request = ConversationRequestResponse::Request {
+            conversation_id: self.conversation_id,
+            kind: ConversationRequestKind::CatchUp {
+                known: self.reorder.known(),
+            },
+        };
+
+        let keypair = self.root.keypair();
+
+        let payload = PayloadBuilder::new(keypair, request)
+            .add_recipient(did)?
+            .from_ipfs(&self.ipfs)
+            .await?;
+
+        let topic = self.document.exchange_topic(did);
+        self.ipfs.pubsub_publish(topic, payload.to_bytes()?).await?;
+        Ok(())
+    }
+
+    /// Respond with the cid of our document and publish the events that `did` has not seen, as far as the outbox allows
+    async fn send_catch_up(&mut self, did: &DID, known: &[(DID, u64)]) -> Result<(), Error> {
+        let own_did = self.identity.did_key();
+        let document = self.ipfs.put_dag(&self.document).await?;
+
+        let response = ConversationRequestResponse::Response {
+            conversation_id: self.conversation_id,
+            kind: ConversationResponseKind::CatchUp { document },
+        };
+
+        let keypair = self.root.keypair();
+
+        let payload = PayloadBuilder::new(keypair, response)
+            .add_recipient(did)?
+            .from_ipfs(&self.ipfs)
+            .await?;
+
+        let topic = self.document.exchange_topic(did);
+        self.ipfs.pubsub_publish(topic, payload.to_bytes()?).await?;
+
+        let seen = known
+            .iter()
+            .find(|(sender, _)| *sender == own_did)
+            .map(|(_, sequence)| *sequence)
+            .unwrap_or_default();
+
+        if seen >= self.sequence {
+            return Ok(());
+        }
+
+        // Anything older than the outbox is covered by the messages of the document
+        let from = (seen + 1).max(self.sequence.saturating_sub(OUTBOX_CAPACITY as u64 - 1));
+        self.resend_events(from, self.sequence).await
+    }
+
+    /// Merge the messages of a document received from another participant that are missing from ours
+    async fn apply_remote_document(&mut self, cid: Cid) -> Result<(), Error> {
+        let conversation_id = self.conversation_id;
+
+        if self.ipfs.put_dag(&self.document).await? == cid {
+            return Ok(());
+        }
+
+        let document: ConversationDocument = self
+            .ipfs
+            .get_dag(cid)
+            .timeout(Duration::from_secs(10))
+            .deserialized()
+            .await?;
+
+        if document.id() != conversation_id
+            || document.conversation_type() != self.document.conversation_type()
+            || document.creator != self.document.creator
+        {
+            return Err(Error::InvalidConversation);
+        }
+
+        document.verify()?;
+
+        let messages = document.get_message_list(&self.ipfs).await?;
+        let inserted = self.merge_messages(messages.into_iter().collect()).await?;
+
+        if inserted > 0 {
+            tracing::info!(%conversation_id, inserted, "caught up on missed messages");
+        }
+
+        Ok(())
+    }
+
+    /// Publish the payloads with the sequence numbers from `from` to `to` again, if they are still in the outbox
+    async fn resend_events(&mut self, from: u64, to: u64) -> Result<(), Error> {
+        if to < from || to - from >= OUTBOX_CAPACITY as u64 {
+            return Err(Error::InvalidLength {
+                context: "sequence range".into(),
+                current: to.saturating_sub(from) as usize,
+                minimum: None,
+                maximum: Some(OUTBOX_CAPACITY),
+            });
+        }
+
+        for payload in self.outbox.range(from..=to) {
+            self.publish_chunked(payload).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Rotate our key when a member leaves so they are unable to read new messages
+    async fn member_left(&mut self, did: &DID) -> Result<(), Error> {
+        self.keystore.remove_member_epoch(did);
+        self.keystore.close_epochs(did, self.time.now());
+        self.rotate_key().await
+    }
+
+    /// Remove the keys of members that left more than [`KEY_RETENTION`] ago once none of their messages remain
+    async fn prune_keystore(&mut self) -> Result<(), Error> {
+        if !matches!(self.document.conversation_type(), ConversationType::Group) {
+            return Ok(());
+        }
+
+        let mut members = self.document.recipients();
+        members.push(self.identity.did_key());
+        let retention = chrono::Duration::from_std(KEY_RETENTION).map_err(anyhow::Error::from)?;
+        let before = self.time.now() - retention;
+
+        let messages = self.document.get_message_list(&self.ipfs).await;
+        let removed = prune_departed_keys(&mut self.keystore, before, &members, messages);
+
+        if removed.is_empty() {
+            return Ok(());
+        }
+
+        self.set_keystore(None).await?;
+
+        tracing::info!(conversation_id = %self.conversation_id, removed = removed.len(), "pruned keys of departed members");
+        Ok(())
+    }
+
     async fn request_key(&mut self, did: &DID) -> Result<(), Error> {
         let request = ConversationRequestResponse::Request {
             conversation_id: self.conversation_id,
@@ -1241,52 +2338,82 @@ impl ConversationTask {
         Ok(())
     }
 
-    //TODO: Send a request to recipient(s) of the chat to ack if message been delivered if message is marked "sent" unless we receive an event acknowledging the message itself
-    //Note:
-    //  - For group chat, this can be ignored unless we decide to have a full acknowledgement from all recipients in which case, we can mark it as "sent"
-    //    until all confirm to have received the message
-    //  - If member sends an event stating that they do not have the message to grab the message from the store
-    //    and send it them, with a map marking the attempt(s)
     async fn message_status(&self, message_id: Uuid) -> Result<MessageStatus, Error> {
-        if matches!(self.document.conversation_type(), ConversationType::Group) {
-            //TODO: Handle message status for group
-            return Err(Error::Unimplemented);
-        }
-
         let messages = self.document.get_message_list(&self.ipfs).await?;
 
-        if !messages.iter().any(|document| document.id == message_id) {
+        let Some(message) = messages.iter().find(|document| document.id == message_id) else {
             return Err(Error::MessageNotFound);
+        };
+
+        if let Some(status) = self.queued_status(message_id) {
+            return Ok(status);
         }
 
+        // Not a guarantee that it been sent but for now since the message exist locally and not marked in queue, we
+        // will assume it have been sent until the recipients acknowledge it
+        Ok(message.delivery_status(&self.document.recipients()))
+    }
+
+    /// Mark a message sent by another member as read, sending a receipt to the members of the conversation
+    pub async fn mark_read(&mut self, message_id: Uuid) -> Result<(), Error> {
         let own_did = self.identity.did_key();
 
-        let _list = self
+        let mut message_document = self
             .document
-            .recipients()
-            .iter()
-            .filter(|did| own_did.ne(did))
-            .cloned()
-            .collect::<Vec<_>>();
+            .get_message_document(&self.ipfs, message_id)
+            .await?;
 
-        // TODO:
-        // for peer in list {
-        //     if let Some(list) = self.queue.get(&peer) {
-        //         for item in list {
-        //             let Queue { id, m_id, .. } = item;
-        //             if self.document.id() == *id {
-        //                 if let Some(m_id) = m_id {
-        //                     if message_id == *m_id {
-        //                         return Ok(MessageStatus::NotSent);
-        //                     }
-        //                 }
-        //             }
-        //         }
-        //     }
-        // }
+        if message_document.sender() == own_did
+            || !message_document.set_receipt(own_did, Receipt::Read)
+        {
+            return Ok(());
+        }
+
+        self.document
+            .update_message_document(&self.ipfs, &message_document)
+            .await?;
+
+        self.set_document().await?;
+
+        self.send_receipt(message_id, Receipt::Read).await
+    }
+
+    async fn send_receipt(&mut self, message_id: Uuid, receipt: Receipt) -> Result<(), Error> {
+        // older peers are unable to deserialize the receipt, in which case it is only recorded locally
+        if !self
+            .identity
+            .supports_feature(&self.document.recipients(), Feature::Receipts)
+            .await
+        {
+            return Ok(());
+        }
+
+        let event = MessagingEvents::Receipt {
+            conversation_id: self.conversation_id,
+            member: self.identity.did_key(),
+            message_id,
+            receipt,
+        };
+
+        // delivery receipts are not queued for recipients that are offline, as a read receipt implies the delivery
+        self.publish(None, event, receipt == Receipt::Read).await
+    }
+
+    /// Status of a message that has items remaining in the outbound queue
+    fn queued_status(&self, message_id: Uuid) -> Option<MessageStatus> {
+        queue_status(self.queue.values().flatten(), message_id)
+    }
 
-        //Not a guarantee that it been sent but for now since the message exist locally and not marked in queue, we will assume it have been sent
-        Ok(MessageStatus::Sent)
+    fn emit_status(&self, message_id: Uuid, status: MessageStatus) {
+        let event = MessageEventKind::MessageStatusUpdated {
+            conversation_id: self.conversation_id,
+            message_id,
+            status,
+        };
+
+        if let Err(e) = self.event_broadcast.send(event) {
+            tracing::error!(conversation_id=%self.conversation_id, error = %e, "Error broadcasting event");
+        }
     }
 
     pub async fn send_message(&mut self, messages: Vec<String>) -> Result<Uuid, Error> {
@@ -1294,6 +2421,8 @@ impl ConversationTask {
             return Err(Error::EmptyMessage);
         }
 
+        let max_message_size = self.identity.config().max_message_size();
+
         let lines_value_length: usize = messages
             .iter()
             .filter(|s| !s.is_empty())
@@ -1301,28 +2430,49 @@ impl ConversationTask {
             .map(|s| s.chars().count())
             .sum();
 
-        if lines_value_length == 0 || lines_value_length > MAX_MESSAGE_SIZE {
+        if lines_value_length > max_message_size
+            && lines_value_length <= MAX_SNIPPET_SIZE
+            && self.identity.config().snippets()
+        {
+            return self.send_snippet(messages).await;
+        }
+
+        if lines_value_length == 0 || lines_value_length > max_message_size {
             tracing::error!(
                 current_size = lines_value_length,
-                max = MAX_MESSAGE_SIZE,
+                max = max_message_size,
                 "length of message is invalid"
             );
             return Err(Error::InvalidLength {
                 context: "message".into(),
                 current: lines_value_length,
                 minimum: Some(MIN_MESSAGE_SIZE),
-                maximum: Some(MAX_MESSAGE_SIZE),
+                maximum: Some(max_message_size),
             });
         }
 
+        check_slow_mode(
+            &self.document,
+            &self.last_message_dates,
+            &self.identity.did_key(),
+            self.time.now(),
+        )?;
+
         let keypair = self.root.keypair();
         let own_did = self.identity.did_key();
 
         let keystore = pubkey_or_keystore(&*self)?;
 
+        let compress = self
+            .identity
+            .supports_compression(&self.document.recipients(), Compression::Zstd)
+            .await;
+
         let message = MessageDocumentBuilder::new(keypair, keystore.as_ref())
             .set_conversation_id(self.conversation_id)
             .set_sender(own_did.clone())
+            .set_clock(self.clock.tick(self.time.now()))
+            .set_compression(compress)
             .set_message(messages.clone())?
             .build()?;
 
@@ -1337,6 +2487,12 @@ impl ConversationTask {
 
         self.set_document().await?;
 
+        record_slow_mode(&mut self.last_message_dates, &own_did, message.date);
+
+        self.record_stats(&message).await;
+
+        self.search.insert(message_id, &messages);
+
         let event = MessageEventKind::MessageSent {
             conversation_id: self.conversation_id,
             message_id,
@@ -1384,6 +2540,8 @@ impl ConversationTask {
             return Err(Error::EmptyMessage);
         }
 
+        let max_message_size = self.identity.config().max_message_size();
+
         let lines_value_length: usize = messages
             .iter()
             .filter(|s| !s.is_empty())
@@ -1391,17 +2549,17 @@ impl ConversationTask {
             .map(|s| s.chars().count())
             .sum();
 
-        if lines_value_length == 0 || lines_value_length > MAX_MESSAGE_SIZE {
+        if lines_value_length == 0 || lines_value_length > max_message_size {
             tracing::error!(
                 current_size = lines_value_length,
-                max = MAX_MESSAGE_SIZE,
+                max = max_message_size,
                 "length of message is invalid"
             );
             return Err(Error::InvalidLength {
                 context: "message".into(),
                 current: lines_value_length,
                 minimum: Some(MIN_MESSAGE_SIZE),
-                maximum: Some(MAX_MESSAGE_SIZE),
+                maximum: Some(max_message_size),
             });
         }
 
@@ -1414,10 +2572,13 @@ impl ConversationTask {
             .get_message_document(&self.ipfs, message_id)
             .await?;
 
-        if message_document.sender() != self.identity.did_key() {
+        if message_document.sender() != self.identity.did_key()
+            || message_document.message_type() == MessageType::Event
+        {
             return Err(Error::InvalidMessage);
         }
 
+        message_document.set_modified_clock(self.clock.tick(self.time.now()));
         message_document.set_message(keypair, keystore.as_ref(), &messages)?;
 
         let nonce = message_document.nonce_from_message()?;
@@ -1432,6 +2593,8 @@ impl ConversationTask {
 
         self.set_document().await?;
 
+        self.search.insert(message_id, &messages);
+
         let _ = tx.send(MessageEventKind::MessageEdited {
             conversation_id: self.conversation_id,
             message_id,
@@ -1444,6 +2607,7 @@ impl ConversationTask {
             lines: messages,
             nonce: nonce.to_vec(),
             signature: signature.into(),
+            modified_clock: message_document.modified_clock,
         };
 
         // if !recipients.is_empty() {
@@ -1478,6 +2642,8 @@ impl ConversationTask {
             return Err(Error::EmptyMessage);
         }
 
+        let max_message_size = self.identity.config().max_message_size();
+
         let lines_value_length: usize = messages
             .iter()
             .filter(|s| !s.is_empty())
@@ -1485,31 +2651,45 @@ impl ConversationTask {
             .map(|s| s.chars().count())
             .sum();
 
-        if lines_value_length == 0 || lines_value_length > MAX_MESSAGE_SIZE {
+        if lines_value_length == 0 || lines_value_length > max_message_size {
             tracing::error!(
                 current_size = lines_value_length,
-                max = MAX_MESSAGE_SIZE,
+                max = max_message_size,
                 "length of message is invalid"
             );
             return Err(Error::InvalidLength {
                 context: "message".into(),
                 current: lines_value_length,
                 minimum: Some(MIN_MESSAGE_SIZE),
-                maximum: Some(MAX_MESSAGE_SIZE),
+                maximum: Some(max_message_size),
             });
         }
 
+        check_slow_mode(
+            &self.document,
+            &self.last_message_dates,
+            &self.identity.did_key(),
+            self.time.now(),
+        )?;
+
         let keypair = self.root.keypair();
 
         let own_did = self.identity.did_key();
 
         let keystore = pubkey_or_keystore(&*self)?;
 
+        let compress = self
+            .identity
+            .supports_compression(&self.document.recipients(), Compression::Zstd)
+            .await;
+
         let message = MessageDocumentBuilder::new(keypair, keystore.as_ref())
             .set_conversation_id(self.conversation_id)
             .set_sender(own_did.clone())
             .set_replied(message_id)
-            .set_message(messages)?
+            .set_clock(self.clock.tick(self.time.now()))
+            .set_compression(compress)
+            .set_message(messages.clone())?
             .build()?;
 
         let message_id = message.id;
@@ -1523,6 +2703,12 @@ impl ConversationTask {
 
         self.set_document().await?;
 
+        record_slow_mode(&mut self.last_message_dates, &own_did, message.date);
+
+        self.record_stats(&message).await;
+
+        self.search.insert(message_id, &messages);
+
         let event = MessageEventKind::MessageSent {
             conversation_id: self.conversation_id,
             message_id,
@@ -1565,10 +2751,40 @@ impl ConversationTask {
             message_id,
         };
 
-        self.document.delete_message(&self.ipfs, message_id).await?;
+        let message = self
+            .document
+            .get_message_document(&self.ipfs, message_id)
+            .await
+            .ok();
+
+        if let (Some(policy), Some(message)) = (self.document.retention_policy(), &message) {
+            if !policy.can_delete(message.date, self.time.now()) {
+                return Err(Error::MessageRetained);
+            }
+        }
+
+        let cid = self.document.delete_message(&self.ipfs, message_id).await?;
 
         self.set_document().await?;
 
+        if let Some(message) = &message {
+            self.forget_stats(message).await;
+        }
+
+        self.search.remove(message_id);
+
+        self.forget_mentions(Some(message_id)).await;
+
+        if self.queued_status(message_id).is_some() {
+            self.queue.retain(|_, queue| {
+                queue.retain(|item| item.m_id != Some(message_id));
+                !queue.is_empty()
+            });
+            self.save_queue().await;
+        }
+
+        erasure::erase_message(&self.ipfs, message_id, cid, message.as_ref()).await;
+
         // if let config::Discovery::Shuttle { addresses } = self.discovery.discovery_config() {
         //     for peer_id in addresses.iter().filter_map(|addr| addr.peer_id()) {
         //         let _ = self
@@ -1775,13 +2991,60 @@ impl ConversationTask {
         self.send_message_event(event).await
     }
 
+    /// Sends a message that is over the maximum message size as a snippet. The text is stored in the media
+    /// directory of the conversation and attached to a message holding a preview of it
+    async fn send_snippet(&mut self, messages: Vec<String>) -> Result<Uuid, Error> {
+        let conversation_id = self.conversation_id;
+
+        check_slow_mode(
+            &self.document,
+            &self.last_message_dates,
+            &self.identity.did_key(),
+            self.time.now(),
+        )?;
+
+        let keystore = pubkey_or_keystore(&*self)?;
+
+        let message_id = Uuid::new_v4();
+        let snippet = Snippet::new(message_id, &messages);
+
+        let directory = media_directory(&self.file, conversation_id)?;
+        self.file
+            .put_buffer(
+                format!("/{CHAT_DIRECTORY}/{conversation_id}/{}", snippet.name),
+                &snippet.data,
+            )
+            .await?;
+
+        let file = directory
+            .get_item_by_path(&snippet.name)
+            .and_then(|item| item.get_file())?;
+        file.set_file_type(snippet.file_type());
+
+        let message = MessageDocumentBuilder::new(self.root.keypair(), keystore.as_ref())
+            .set_message_id(message_id)
+            .set_message_type(MessageType::Attachment)
+            .set_conversation_id(conversation_id)
+            .set_sender(self.identity.did_key())
+            .set_message(snippet.preview)?
+            .add_attachment(file)?
+            .build()?;
+
+        tracing::debug!(%conversation_id, %message_id, syntax = ?snippet.syntax, "sending message as snippet");
+
+        self.store_direct_for_attachment(message).await?;
+        Ok(message_id)
+    }
+
     pub async fn send_message_event(&self, event: MessagingEvents) -> Result<(), Error> {
-        let key = self.conversation_key(None)?;
+        let key = self.conversation_key(None, None)?;
+        let epoch = self.conversation_key_epoch();
 
         let recipients = self.document.recipients();
 
         let payload = PayloadBuilder::new(self.root.keypair(), event)
             .set_key(key)
+            .set_key_epoch(epoch)
             .from_ipfs(&self.ipfs)
             .add_recipients(recipients)?
             .await?;
@@ -1792,12 +3055,32 @@ impl ConversationTask {
             .await?;
 
         if !peers.is_empty() {
-            if let Err(e) = self
+            let bytes = payload.to_bytes()?;
+            let size = bytes.len();
+            match self
                 .ipfs
-                .pubsub_publish(self.document.event_topic(), payload.to_bytes()?)
+                .pubsub_publish(self.document.event_topic(), bytes)
                 .await
             {
-                tracing::error!(id=%self.conversation_id, "Unable to send event: {e}");
+                Ok(_) => {
+                    let own_did = self.identity.did_key();
+                    let recipients = self.document.recipients();
+                    let members = recipients.iter().filter(|did| {
+                        own_did.ne(did)
+                            && did
+                                .to_peer_id()
+                                .is_ok_and(|peer_id| peers.contains(&peer_id))
+                    });
+                    self.usage.record_sent(
+                        UsageCategory::Messages,
+                        Some(self.conversation_id),
+                        members,
+                        size,
+                    )
+                }
+                Err(e) => {
+                    tracing::error!(id=%self.conversation_id, "Unable to send event: {e}")
+                }
             }
         }
         Ok(())
@@ -1844,6 +3127,12 @@ impl ConversationTask {
 
         self.set_document().await?;
 
+        self.record_system_message(SystemMessage::MemberAdded {
+            actor: own_did.clone(),
+            member: did_key.clone(),
+        })
+        .await;
+
         let event = MessagingEvents::UpdateConversation {
             conversation: self.document.clone(),
             kind: ConversationUpdateKind::AddParticipant {
@@ -1863,6 +3152,10 @@ impl ConversationTask {
 
         self.publish(None, event, true).await?;
 
+        if let Err(e) = self.member_joined(did_key).await {
+            tracing::warn!(id = %self.conversation_id, error = %e, "unable to rotate key");
+        }
+
         let new_event = ConversationEvents::NewGroupConversation {
             conversation: self.document.clone(),
         };
@@ -1908,6 +3201,12 @@ impl ConversationTask {
         self.document.recipients.retain(|did| did.ne(did_key));
         self.set_document().await?;
 
+        self.record_system_message(SystemMessage::MemberRemoved {
+            actor: own_did.clone(),
+            member: did_key.clone(),
+        })
+        .await;
+
         let event = MessagingEvents::UpdateConversation {
             conversation: self.document.clone(),
             kind: ConversationUpdateKind::RemoveParticipant {
@@ -1923,6 +3222,10 @@ impl ConversationTask {
 
         self.publish(None, event, true).await?;
 
+        if let Err(e) = self.member_left(did_key).await {
+            tracing::warn!(id = %self.conversation_id, error = %e, "unable to rotate key");
+        }
+
         if broadcast {
             let new_event = ConversationEvents::DeleteConversation {
                 conversation_id: self.conversation_id,
@@ -2053,6 +3356,17 @@ impl ConversationTask {
 
         self.set_document().await?;
 
+        let system = match self.document.name() {
+            Some(name) => SystemMessage::NameChanged {
+                actor: own_did.clone(),
+                name,
+            },
+            None => SystemMessage::NameRemoved {
+                actor: own_did.clone(),
+            },
+        };
+        self.record_system_message(system).await;
+
         let new_name = self.document.name();
 
         let event = MessagingEvents::UpdateConversation {
@@ -2346,6 +3660,16 @@ impl ConversationTask {
 
         self.set_document().await?;
 
+        let actor = self.identity.did_key();
+        let system = match desc {
+            Some(description) => SystemMessage::DescriptionChanged {
+                actor,
+                description: description.to_string(),
+            },
+            None => SystemMessage::DescriptionRemoved { actor },
+        };
+        self.record_system_message(system).await;
+
         let ev = MessageEventKind::ConversationDescriptionChanged {
             conversation_id,
             description: desc.map(ToString::to_string),
@@ -2363,14 +3687,270 @@ impl ConversationTask {
         self.publish(None, event, true).await
     }
 
+    pub async fn set_history_visibility(
+        &mut self,
+        visibility: HistoryVisibility,
+    ) -> Result<(), Error> {
+        let conversation_id = self.conversation_id;
+
+        if self.document.conversation_type() != ConversationType::Group {
+            return Err(Error::InvalidConversation);
+        }
+
+        let Some(creator) = self.document.creator.as_ref() else {
+            return Err(Error::InvalidConversation);
+        };
+
+        // The setting is covered by the signature of the creator so only they are able to change it
+        if self.identity.did_key().ne(creator) {
+            return Err(Error::Unauthorized);
+        }
+
+        if self.document.history_visibility == visibility {
+            return Ok(());
+        }
+
+        self.document.history_visibility = visibility;
+
+        self.set_document().await?;
+
+        let _ = self
+            .event_broadcast
+            .send(MessageEventKind::ConversationHistoryVisibilityChanged {
+                conversation_id,
+                visibility,
+            });
+
+        let event = MessagingEvents::UpdateConversation {
+            conversation: self.document.clone(),
+            kind: ConversationUpdateKind::ChangeHistoryVisibility { visibility },
+        };
+
+        self.publish(None, event, true).await
+    }
+
+    pub async fn set_slow_mode(&mut self, slow_mode: u64) -> Result<(), Error> {
+        let conversation_id = self.conversation_id;
+
+        if self.document.conversation_type() != ConversationType::Group {
+            return Err(Error::InvalidConversation);
+        }
+
+        let Some(creator) = self.document.creator.as_ref() else {
+            return Err(Error::InvalidConversation);
+        };
+
+        // The setting is covered by the signature of the creator so only they are able to change it
+        if self.identity.did_key().ne(creator) {
+            return Err(Error::Unauthorized);
+        }
+
+        if slow_mode > MAX_SLOW_MODE {
+            return Err(Error::InvalidLength {
+                context: "slow_mode".into(),
+                current: slow_mode as _,
+                minimum: None,
+                maximum: Some(MAX_SLOW_MODE as _),
+            });
+        }
+
+        if self.document.slow_mode == slow_mode {
+            return Ok(());
+        }
+
+        self.document.slow_mode = slow_mode;
+
+        self.set_document().await?;
+
+        let _ = self
+            .event_broadcast
+            .send(MessageEventKind::ConversationSlowModeChanged {
+                conversation_id,
+                slow_mode,
+            });
+
+        let event = MessagingEvents::UpdateConversation {
+            conversation: self.document.clone(),
+            kind: ConversationUpdateKind::ChangeSlowMode { slow_mode },
+        };
+
+        self.publish(None, event, true).await
+    }
+
+    pub async fn set_retention_policy(
+        &mut self,
+        policy: Option<RetentionPolicy>,
+    ) -> Result<(), Error> {
+        let conversation_id = self.conversation_id;
+
+        if self.document.conversation_type() != ConversationType::Group {
+            return Err(Error::InvalidConversation);
+        }
+
+        if !self.is_retention_issuer(&self.identity.did_key()).await {
+            return Err(Error::Unauthorized);
+        }
+
+        if self.document.retention_policy() == policy {
+            return Ok(());
+        }
+
+        // older peers are unable to deserialize the update so the policy is only set once every member supports it
+        if !self
+            .identity
+            .supports_feature(&self.document.recipients(), Feature::RetentionPolicy)
+            .await
+        {
+            return Err(Error::OtherWithContext(
+                "Not every member supports retention policies".into(),
+            ));
+        }
+
+        self.document.retention = policy
+            .map(|policy| {
+                RetentionPolicyDocument::new(
+                    self.root.keypair(),
+                    conversation_id,
+                    policy,
+                    self.time.now(),
+                )
+            })
+            .transpose()?;
+
+        self.set_document().await?;
+
+        self.retention_deadline = None;
+
+        let _ = self
+            .event_broadcast
+            .send(MessageEventKind::ConversationRetentionPolicyChanged {
+                conversation_id,
+                policy,
+            });
+
+        let event = MessagingEvents::UpdateConversation {
+            conversation: self.document.clone(),
+            kind: ConversationUpdateKind::ChangeRetentionPolicy { policy },
+        };
+
+        self.publish(None, event, true).await
+    }
+
+    /// Returns true if `did` is allowed to issue the retention policy of the conversation, which is the creator of
+    /// the group or an administrator of the organization of the identity
+    async fn is_retention_issuer(&self, did: &DID) -> bool {
+        self.document
+            .creator
+            .as_ref()
+            .is_some_and(|creator| creator == did)
+            || self
+                .identity
+                .organization_roster()
+                .await
+                .is_some_and(|roster| roster.is_admin(did))
+    }
+
+    /// Remove the messages that outlived the maximum retention of the conversation.
+    /// Every member applies the policy on their own, so the removal is not broadcasted
+    async fn remove_expired_messages(&mut self) -> Result<(), Error> {
+        let Some(policy) = self.document.retention_policy() else {
+            return Ok(());
+        };
+
+        let Some(maximum) = policy.maximum.filter(|_| !policy.legal_hold) else {
+            return Ok(());
+        };
+
+        let now = self.time.now();
+
+        if self
+            .retention_deadline
+            .is_some_and(|deadline| now < deadline)
+        {
+            return Ok(());
+        }
+
+        let maximum = chrono::Duration::seconds(maximum as i64);
+
+        // messages sent from now on expire after the remaining messages
+        let mut deadline = now + maximum;
+        let mut expired = vec![];
+
+        for message in self.document.get_message_list(&self.ipfs).await? {
+            match policy.is_expired(message.date, now) {
+                true => expired.push(message.id),
+                false => deadline = deadline.min(message.date + maximum),
+            }
+        }
+
+        self.retention_deadline = Some(deadline);
+
+        for message_id in expired {
+            if let Err(e) = self.delete_message(message_id, false).await {
+                tracing::warn!(conversation_id = %self.conversation_id, %message_id, error = %e, "unable to remove expired message");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Move the retention deadline forward for a received message, which may be older than the messages that were
+    /// scanned
+    fn schedule_expiry(&mut self, date: DateTime<Utc>) {
+        let Some(deadline) = self.retention_deadline.as_mut() else {
+            return;
+        };
+
+        if let Some(maximum) = self
+            .document
+            .retention_policy()
+            .and_then(|policy| policy.maximum)
+        {
+            *deadline = (*deadline).min(date + chrono::Duration::seconds(maximum as i64));
+        }
+    }
+
+    /// Drop the references left behind by removed messages outside of the window of the compaction policy
+    async fn compact_history(&mut self) -> Result<(), Error> {
+        let Some(policy) = self.identity.config().store_setting().compaction else {
+            return Ok(());
+        };
+
+        let window = chrono::Duration::from_std(policy.window).map_err(anyhow::Error::from)?;
+        let cutoff = self.time.now() - window;
+
+        let list = self.document.message_reference_list(&self.ipfs).await?;
+
+        let Some((list, removed)) = list.compact(&self.ipfs, cutoff, policy.min_removed).await?
+        else {
+            return Ok(());
+        };
+
+        // The messages are unchanged, so the modified date of the conversation is left as is
+        let cid = self.ipfs.put_dag(list).await?;
+        self.document.messages.replace(cid);
+        self.set_document().await?;
+
+        tracing::info!(conversation_id = %self.conversation_id, removed, "compacted conversation history");
+        Ok(())
+    }
+
     pub fn attach(
         &mut self,
         reply_id: Option<Uuid>,
         locations: Vec<Location>,
         messages: Vec<String>,
+        options: AttachmentOptions,
     ) -> Result<(Uuid, AttachmentEventStream), Error> {
         let conversation_id = self.conversation_id;
 
+        check_slow_mode(
+            &self.document,
+            &self.last_message_dates,
+            &self.identity.did_key(),
+            self.time.now(),
+        )?;
+
         let keystore = pubkey_or_keystore(&*self)?;
 
         let stream = AttachmentStream::new(
@@ -2382,7 +3962,9 @@ impl ConversationTask {
             self.attachment_tx.clone(),
         )
         .set_reply(reply_id)
+        .set_strip_metadata(options.strip_metadata())
         .set_locations(locations)?
+        .set_max_message_size(self.identity.config().max_message_size())
         .set_lines(messages)?;
 
         let message_id = stream.message_id();
@@ -2390,10 +3972,15 @@ impl ConversationTask {
         Ok((message_id, stream.boxed()))
     }
 
-    async fn store_direct_for_attachment(&mut self, message: MessageDocument) -> Result<(), Error> {
+    async fn store_direct_for_attachment(
+        &mut self,
+        mut message: MessageDocument,
+    ) -> Result<(), Error> {
         let conversation_id = self.conversation_id;
         let message_id = message.id;
 
+        message.set_clock(self.root.keypair(), self.clock.tick(self.time.now()))?;
+
         let _message_cid = self
             .document
             .insert_message_document(&self.ipfs, &message)
@@ -2403,6 +3990,16 @@ impl ConversationTask {
 
         self.set_document().await?;
 
+        record_slow_mode(
+            &mut self.last_message_dates,
+            &self.identity.did_key(),
+            message.date,
+        );
+
+        self.record_stats(&message).await;
+
+        self.index_message(&message).await;
+
         let event = MessageEventKind::MessageSent {
             conversation_id,
             message_id,
@@ -2462,7 +4059,25 @@ impl ConversationTask {
             .find(|attachment| attachment.name == file)
             .ok_or(Error::FileNotFound)?;
 
-        let stream = attachment.download(&self.ipfs, path, &members, None);
+        let usage = self.usage.clone();
+        let conversation_id = self.conversation_id;
+
+        let stream = attachment
+            .download(&self.ipfs, path, &members, None)
+            .inspect(move |progress| {
+                if let Progression::ProgressComplete {
+                    total: Some(total), ..
+                } = progress
+                {
+                    usage.record_received(
+                        UsageCategory::Attachments,
+                        Some(conversation_id),
+                        None,
+                        *total,
+                    );
+                }
+            })
+            .boxed();
 
         Ok(stream)
     }
@@ -2493,7 +4108,22 @@ impl ConversationTask {
             .find(|attachment| attachment.name == file)
             .ok_or(Error::FileNotFound)?;
 
-        let stream = attachment.download_stream(&self.ipfs, &members, None);
+        let usage = self.usage.clone();
+        let conversation_id = self.conversation_id;
+
+        let stream = attachment
+            .download_stream(&self.ipfs, &members, None)
+            .inspect(move |result| {
+                if let Ok(bytes) = result {
+                    usage.record_received(
+                        UsageCategory::Attachments,
+                        Some(conversation_id),
+                        None,
+                        bytes.len(),
+                    );
+                }
+            })
+            .boxed();
 
         Ok(stream)
     }
@@ -2504,7 +4134,6 @@ impl ConversationTask {
         event: MessagingEvents,
         queue: bool,
     ) -> Result<(), Error> {
-        let keypair = self.root.keypair();
         let own_did = self.identity.did_key();
 
         let recipients = self.document.recipients();
@@ -2514,7 +4143,11 @@ impl ConversationTask {
             .filter(|did| own_did.ne(did))
             .collect::<Vec<_>>();
 
-        let key = self.conversation_key(None)?;
+        let key = self.conversation_key(None, None)?;
+        let epoch = self.conversation_key_epoch();
+        let sequence = self.next_sequence().await;
+
+        let keypair = self.root.keypair();
 
         let payload = PayloadBuilder::new(keypair, event)
             .add_recipients(participants)?
@@ -2522,14 +4155,19 @@ impl ConversationTask {
             //       could be encrypted with the conversation key
             // TODO: Determine if we should use the conversation key at the payload level.
             .set_key(key)
+            .set_key_epoch(epoch)
+            .set_sequence(sequence)
             .from_ipfs(&self.ipfs)
             .await?;
 
         let payload_bytes = payload.to_bytes()?;
 
+        self.outbox.insert(sequence, payload_bytes.clone());
+
         let peers = self.ipfs.pubsub_peers(Some(self.document.topic())).await?;
 
-        let mut can_publish = false;
+        let mut subscribed = vec![];
+        let mut queued = false;
 
         for recipient in recipients.iter().filter(|did| own_did.ne(did)) {
             let peer_id = recipient.to_peer_id()?;
@@ -2537,7 +4175,7 @@ impl ConversationTask {
             // We want to confirm that there is atleast one peer subscribed before attempting to send a message
             match peers.contains(&peer_id) {
                 true => {
-                    can_publish = true;
+                    subscribed.push((recipient.clone(), peer_id));
                 }
                 false => {
                     if queue {
@@ -2551,29 +4189,111 @@ impl ConversationTask {
                             ),
                         )
                         .await;
+                        queued = true;
                     }
                 }
             };
         }
 
-        if can_publish {
+        if !subscribed.is_empty() {
             let bytes = payload.to_bytes()?;
-            tracing::trace!(id = %self.conversation_id, "Payload size: {} bytes", bytes.len());
+            let size = bytes.len();
+            tracing::trace!(id = %self.conversation_id, "Payload size: {} bytes", size);
             let timer = Instant::now();
-            let mut time = true;
-            if let Err(_e) = self.ipfs.pubsub_publish(self.document.topic(), bytes).await {
-                tracing::error!(id = %self.conversation_id, "Error publishing: {_e}");
-                time = false;
+            match self.publish_chunked(bytes).await {
+                Ok(_) => {
+                    self.usage.record_sent(
+                        UsageCategory::Messages,
+                        Some(self.conversation_id),
+                        subscribed.iter().map(|(did, _)| did),
+                        size,
+                    );
+                    let end = timer.elapsed();
+                    tracing::trace!(id = %self.conversation_id, "Took {}ms to send event", end.as_millis());
+                }
+                Err(_e) => {
+                    tracing::error!(id = %self.conversation_id, "Error publishing: {_e}");
+                    // retry through the queue for the peers that would have received it
+                    if queue {
+                        for (recipient, peer_id) in subscribed {
+                            self.queue_event(
+                                recipient,
+                                QueueItem::direct(
+                                    message_id,
+                                    peer_id,
+                                    self.document.topic(),
+                                    payload_bytes.clone(),
+                                ),
+                            )
+                            .await;
+                        }
+                        queued = true;
+                    }
+                }
             }
-            if time {
-                let end = timer.elapsed();
-                tracing::trace!(id = %self.conversation_id, "Took {}ms to send event", end.as_millis());
+        }
+
+        if queued {
+            if let Some(message_id) = message_id {
+                self.emit_status(message_id, MessageStatus::Queued);
             }
         }
 
         Ok(())
     }
 
+    /// Publish the payload to the conversation topic, splitting it into chunks if it exceeds the pubsub limit
+    async fn publish_chunked(&self, bytes: Bytes) -> Result<(), Error> {
+        for chunk in chunk::split(bytes)? {
+            self.ipfs
+                .pubsub_publish(self.document.topic(), chunk)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Payloads queued for the recipient that have yet to be sent, in the order they were queued
+    fn export_queue(&self, recipient: &DID) -> Vec<(String, Bytes)> {
+        self.queue
+            .get(recipient)
+            .map(|items| {
+                items
+                    .iter()
+                    .filter(|item| !item.sent && !item.failed)
+                    .map(|item| (item.topic.clone(), item.data.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Processes a payload that was received outside of pubsub as if it was published to its topic
+    async fn import_payload(
+        &mut self,
+        sender: PeerId,
+        topic: String,
+        data: Bytes,
+    ) -> Result<(), Error> {
+        let message = Message {
+            source: Some(sender),
+            data: data.into(),
+            sequence_number: None,
+            topic: TopicHash::from_raw(topic.clone()),
+        };
+
+        match topic {
+            topic if topic == self.document.topic() => self.process_msg_event(message).await,
+            topic if topic == self.document.event_topic() => {
+                process_conversation_event(self, message).await
+            }
+            topic if topic == self.document.exchange_topic(&self.identity.did_key()) => {
+                process_request_response_event(self, message).await
+            }
+            _ => Err(Error::OtherWithContext(
+                "payload does not belong to the conversation".into(),
+            )),
+        }
+    }
+
     async fn queue_event(&mut self, did: DID, queue: QueueItem) {
         self.queue.entry(did).or_default().push(queue);
         self.save_queue().await
@@ -2686,6 +4406,56 @@ impl ConversationTask {
     }
 }
 
+/// Apply the event in the order it was published by the sender, holding it back while an earlier event is missing.
+/// Payloads without a sequence number, such as those from peers that do not set one, are applied as they arrive
+async fn sequenced_message_event(
+    this: &mut ConversationTask,
+    sender: &DID,
+    sequence: Option<u64>,
+    event: MessagingEvents,
+) -> Result<(), Error> {
+    let Some(sequence) = sequence else {
+        return message_event(this, sender, event).await;
+    };
+
+    let conversation_id = this.conversation_id;
+
+    let received = this.reorder.insert(sender, sequence, event);
+
+    if let Some(missing) = received.missing {
+        tracing::debug!(%conversation_id, %sender, ?missing, "events are missing. Requesting them again");
+        if let Err(e) = this.request_resend(sender, missing).await {
+            tracing::warn!(%conversation_id, %sender, error = %e, "unable to request missing events");
+        }
+    }
+
+    let mut result = Ok(());
+    for event in received.ready {
+        if let Err(e) = message_event(this, sender, event).await {
+            match result {
+                Ok(_) => result = Err(e),
+                Err(_) => {
+                    tracing::warn!(%conversation_id, %sender, error = %e, "failed to process event")
+                }
+            }
+        }
+    }
+    result
+}
+
+/// Apply the events that were held behind a gap that was not filled in time
+async fn process_held_events(this: &mut ConversationTask) {
+    let conversation_id = this.conversation_id;
+    for (sender, events) in this.reorder.expire() {
+        tracing::warn!(%conversation_id, %sender, amount = events.len(), "missing events were not received. Applying held events");
+        for event in events {
+            if let Err(e) = message_event(this, &sender, event).await {
+                tracing::warn!(%conversation_id, %sender, error = %e, "failed to process event");
+            }
+        }
+    }
+}
+
 async fn message_event(
     this: &mut ConversationTask,
     sender: &DID,
@@ -2702,10 +4472,16 @@ async fn message_event(
         MessagingEvents::New { message } => {
             message.verify()?;
 
-            if this.document.id != message.conversation_id {
+            // messages are only moved to another conversation when merging a duplicate conversation locally
+            if this.document.id != message.conversation_id || message.origin.is_some() {
                 return Err(Error::InvalidConversation);
             }
 
+            // system messages are recorded by each participant and never sent
+            if message.message_type == MessageType::Event || message.system.is_some() {
+                return Err(Error::InvalidMessage);
+            }
+
             let message_id = message.id;
 
             if !this
@@ -2720,6 +4496,30 @@ async fn message_event(
                 return Err(Error::MessageFound);
             }
 
+            // the message was deleted and should not be added back
+            if erasure::is_erased(&this.ipfs, message_id).await {
+                return Err(Error::MessageNotFound);
+            }
+
+            let message_sender = message.sender.to_did();
+
+            if !this.document.slow_mode_exempt(&message_sender) {
+                // a message dated ahead of time would otherwise shift the window of the member
+                if (message.date - this.time.now()).num_seconds() > SLOW_MODE_CLOCK_SKEW {
+                    return Err(Error::InvalidMessage);
+                }
+                check_slow_mode(
+                    &this.document,
+                    &this.last_message_dates,
+                    &message_sender,
+                    message.date,
+                )?;
+            }
+
+            if let Some(clock) = message.clock {
+                this.clock.update(clock, this.time.now());
+            }
+
             let resolved_message = message
                 .resolve(&this.ipfs, keypair, false, keystore.as_ref())
                 .await?;
@@ -2732,7 +4532,7 @@ async fn message_event(
                 .map(|s| s.chars().count())
                 .sum();
 
-            if lines_value_length == 0 && lines_value_length > MAX_MESSAGE_SIZE {
+            if lines_value_length == 0 && lines_value_length > MAX_MESSAGE_SIZE_LIMIT {
                 tracing::error!(
                     message_length = lines_value_length,
                     "Length of message is invalid."
@@ -2741,7 +4541,7 @@ async fn message_event(
                     context: "message".into(),
                     current: lines_value_length,
                     minimum: Some(MIN_MESSAGE_SIZE),
-                    maximum: Some(MAX_MESSAGE_SIZE),
+                    maximum: Some(MAX_MESSAGE_SIZE_LIMIT),
                 });
             }
 
@@ -2753,6 +4553,35 @@ async fn message_event(
 
             this.set_document().await?;
 
+            record_slow_mode(&mut this.last_message_dates, &message_sender, message.date);
+
+            this.record_stats(&message).await;
+
+            this.search
+                .insert(resolved_message.id(), resolved_message.lines());
+
+            // recorded before the event is emitted so the mention is not lost if the application stops before
+            // processing it
+            if resolved_message.mentions().contains(&own_did) {
+                let kind = PendingEventKind::Mention {
+                    conversation_id,
+                    message_id,
+                    sender: message_sender.clone(),
+                };
+                if let Err(e) = this
+                    .identity
+                    .pending_events()
+                    .record(kind, resolved_message.date())
+                    .await
+                {
+                    tracing::warn!(%conversation_id, %message_id, error = %e, "unable to record mention");
+                }
+            }
+
+            this.schedule_expiry(message.date);
+
+            this.process_message(&resolved_message).await;
+
             if let Err(e) = this
                 .event_broadcast
                 .send(MessageEventKind::MessageReceived {
@@ -2762,6 +4591,12 @@ async fn message_event(
             {
                 tracing::warn!(%conversation_id, "Error broadcasting event: {e}");
             }
+
+            if message_sender != own_did {
+                if let Err(e) = this.send_receipt(message_id, Receipt::Delivered).await {
+                    tracing::warn!(%conversation_id, %message_id, error = %e, "unable to send delivery receipt");
+                }
+            }
         }
         MessagingEvents::Edit {
             conversation_id,
@@ -2770,6 +4605,7 @@ async fn message_event(
             lines,
             nonce,
             signature,
+            modified_clock,
         } => {
             let mut message_document = this
                 .document
@@ -2778,6 +4614,18 @@ async fn message_event(
 
             message_document.verify()?;
 
+            // edits are resolved by their clock so that an edit delayed in transit does not overwrite a newer one
+            let incoming = modified_clock.unwrap_or_else(|| modified.into());
+            if matches!(message_document.modified_timestamp(), Some(current) if incoming < current)
+            {
+                tracing::debug!(%conversation_id, %message_id, "ignoring edit that is older than the current message");
+                return Ok(());
+            }
+
+            if let Some(clock) = modified_clock {
+                this.clock.update(clock, this.time.now());
+            }
+
             let lines_value_length: usize = lines
                 .iter()
                 .map(|s| s.trim())
@@ -2785,17 +4633,17 @@ async fn message_event(
                 .map(|s| s.chars().count())
                 .sum();
 
-            if lines_value_length == 0 && lines_value_length > MAX_MESSAGE_SIZE {
+            if lines_value_length == 0 && lines_value_length > MAX_MESSAGE_SIZE_LIMIT {
                 tracing::error!(
                     current_size = lines_value_length,
-                    max = MAX_MESSAGE_SIZE,
+                    max = MAX_MESSAGE_SIZE_LIMIT,
                     "length of message is invalid"
                 );
                 return Err(Error::InvalidLength {
                     context: "message".into(),
                     current: lines_value_length,
                     minimum: Some(MIN_MESSAGE_SIZE),
-                    maximum: Some(MAX_MESSAGE_SIZE),
+                    maximum: Some(MAX_MESSAGE_SIZE_LIMIT),
                 });
             }
 
@@ -2803,7 +4651,8 @@ async fn message_event(
                 keypair,
                 keystore.as_ref(),
                 modified,
-                lines,
+                modified_clock,
+                lines.clone(),
                 (!signature.is_empty() && sender.ne(&own_did)).then_some(signature),
                 Some(nonce.as_slice()),
             )?;
@@ -2814,6 +4663,22 @@ async fn message_event(
 
             this.set_document().await?;
 
+            this.search.insert(message_id, &lines);
+
+            this.processors.invalidate(message_id);
+
+            if !this.processors.is_empty() {
+                match message_document
+                    .resolve(&this.ipfs, keypair, false, keystore.as_ref())
+                    .await
+                {
+                    Ok(message) => this.process_message(&message).await,
+                    Err(e) => {
+                        tracing::warn!(%conversation_id, %message_id, error = %e, "unable to resolve edited message for processing")
+                    }
+                }
+            }
+
             if let Err(e) = this.event_broadcast.send(MessageEventKind::MessageEdited {
                 conversation_id,
                 message_id,
@@ -2839,10 +4704,34 @@ async fn message_event(
             //     }
             // }
 
-            this.document.delete_message(&this.ipfs, message_id).await?;
+            let message = this
+                .document
+                .get_message_document(&this.ipfs, message_id)
+                .await
+                .ok();
+
+            if let (Some(policy), Some(message)) = (this.document.retention_policy(), &message) {
+                if !policy.can_delete(message.date, this.time.now()) {
+                    return Err(Error::MessageRetained);
+                }
+            }
+
+            let cid = this.document.delete_message(&this.ipfs, message_id).await?;
 
             this.set_document().await?;
 
+            if let Some(message) = &message {
+                this.forget_stats(message).await;
+            }
+
+            this.search.remove(message_id);
+
+            this.forget_mentions(Some(message_id)).await;
+
+            this.processors.invalidate(message_id);
+
+            erasure::erase_message(&this.ipfs, message_id, cid, message.as_ref()).await;
+
             if let Err(e) = this.event_broadcast.send(MessageEventKind::MessageDeleted {
                 conversation_id,
                 message_id,
@@ -2961,6 +4850,11 @@ async fn message_event(
             conversation.favorite = this.document.favorite;
             conversation.archived = this.document.archived;
 
+            // the retention policy is only changed by its own update, where its issuer is checked
+            if !matches!(kind, ConversationUpdateKind::ChangeRetentionPolicy { .. }) {
+                conversation.retention.clone_from(&this.document.retention);
+            }
+
             match kind {
                 ConversationUpdateKind::AddParticipant { did } => {
                     if !this.document.creator.as_ref().is_some_and(|c| c == sender)
@@ -2982,6 +4876,16 @@ async fn message_event(
 
                     this.replace_document(conversation).await?;
 
+                    this.record_system_message(SystemMessage::MemberAdded {
+                        actor: sender.clone(),
+                        member: did.clone(),
+                    })
+                    .await;
+
+                    if let Err(e) = this.member_joined(&did).await {
+                        tracing::warn!(%conversation_id, error = %e, "unable to rotate key");
+                    }
+
                     if let Err(e) = this.request_key(&did).await {
                         tracing::error!(%conversation_id, error = %e, "error requesting key");
                     }
@@ -3016,6 +4920,18 @@ async fn message_event(
 
                     this.replace_document(conversation).await?;
 
+                    this.record_system_message(SystemMessage::MemberRemoved {
+                        actor: sender.clone(),
+                        member: did.clone(),
+                    })
+                    .await;
+
+                    if did != this.identity.did_key() {
+                        if let Err(e) = this.member_left(&did).await {
+                            tracing::warn!(%conversation_id, error = %e, "unable to rotate key");
+                        }
+                    }
+
                     if can_emit {
                         if let Err(e) =
                             this.event_broadcast
@@ -3057,6 +4973,12 @@ async fn message_event(
 
                     this.replace_document(conversation).await?;
 
+                    this.record_system_message(SystemMessage::NameChanged {
+                        actor: sender.clone(),
+                        name: name.to_string(),
+                    })
+                    .await;
+
                     if let Err(e) =
                         this.event_broadcast
                             .send(MessageEventKind::ConversationNameUpdated {
@@ -3080,6 +5002,11 @@ async fn message_event(
 
                     this.replace_document(conversation).await?;
 
+                    this.record_system_message(SystemMessage::NameRemoved {
+                        actor: sender.clone(),
+                    })
+                    .await;
+
                     if let Err(e) =
                         this.event_broadcast
                             .send(MessageEventKind::ConversationNameUpdated {
@@ -3184,6 +5111,18 @@ async fn message_event(
                     }
 
                     this.replace_document(conversation).await?;
+
+                    let system = match description.clone() {
+                        Some(description) => SystemMessage::DescriptionChanged {
+                            actor: sender.clone(),
+                            description,
+                        },
+                        None => SystemMessage::DescriptionRemoved {
+                            actor: sender.clone(),
+                        },
+                    };
+                    this.record_system_message(system).await;
+
                     if let Err(e) = this.event_broadcast.send(
                         MessageEventKind::ConversationDescriptionChanged {
                             conversation_id,
@@ -3193,6 +5132,132 @@ async fn message_event(
                         tracing::warn!(%conversation_id, error = %e, "Error broadcasting event");
                     }
                 }
+                ConversationUpdateKind::ChangeHistoryVisibility { visibility } => {
+                    if this.document.conversation_type != ConversationType::Group
+                        || !this.document.creator.as_ref().is_some_and(|c| c == sender)
+                    {
+                        return Err(Error::Unauthorized);
+                    }
+
+                    if conversation.history_visibility != visibility {
+                        return Err(Error::InvalidConversation);
+                    }
+
+                    if this.document.history_visibility == visibility {
+                        return Ok(());
+                    }
+
+                    this.replace_document(conversation).await?;
+                    if let Err(e) = this.event_broadcast.send(
+                        MessageEventKind::ConversationHistoryVisibilityChanged {
+                            conversation_id,
+                            visibility,
+                        },
+                    ) {
+                        tracing::warn!(%conversation_id, error = %e, "Error broadcasting event");
+                    }
+                }
+                ConversationUpdateKind::ChangeSlowMode { slow_mode } => {
+                    if this.document.conversation_type != ConversationType::Group
+                        || !this.document.creator.as_ref().is_some_and(|c| c == sender)
+                    {
+                        return Err(Error::Unauthorized);
+                    }
+
+                    if conversation.slow_mode != slow_mode || slow_mode > MAX_SLOW_MODE {
+                        return Err(Error::InvalidConversation);
+                    }
+
+                    if this.document.slow_mode == slow_mode {
+                        return Ok(());
+                    }
+
+                    this.replace_document(conversation).await?;
+                    if let Err(e) =
+                        this.event_broadcast
+                            .send(MessageEventKind::ConversationSlowModeChanged {
+                                conversation_id,
+                                slow_mode,
+                            })
+                    {
+                        tracing::warn!(%conversation_id, error = %e, "Error broadcasting event");
+                    }
+                }
+                ConversationUpdateKind::ChangeRetentionPolicy { policy } => {
+                    if this.document.conversation_type != ConversationType::Group
+                        || !this.is_retention_issuer(sender).await
+                        || conversation
+                            .retention
+                            .as_ref()
+                            .is_some_and(|document| document.issuer.ne(sender))
+                    {
+                        return Err(Error::Unauthorized);
+                    }
+
+                    // the policy has to be signed by the sender, which `retention_policy` checks along with the
+                    // issuer above
+                    if conversation.retention_policy() != policy {
+                        return Err(Error::InvalidConversation);
+                    }
+
+                    if this.document.retention_policy() == policy {
+                        return Ok(());
+                    }
+
+                    this.replace_document(conversation).await?;
+                    this.retention_deadline = None;
+                    if let Err(e) = this.event_broadcast.send(
+                        MessageEventKind::ConversationRetentionPolicyChanged {
+                            conversation_id,
+                            policy,
+                        },
+                    ) {
+                        tracing::warn!(%conversation_id, error = %e, "Error broadcasting event");
+                    }
+                }
+            }
+        }
+        MessagingEvents::Receipt {
+            conversation_id,
+            member,
+            message_id,
+            receipt,
+        } => {
+            if member != *sender {
+                return Err(Error::Unauthorized);
+            }
+
+            let mut message_document = this
+                .document
+                .get_message_document(&this.ipfs, message_id)
+                .await?;
+
+            let recipients = this.document.recipients();
+            let previous_status = message_document.delivery_status(&recipients);
+
+            if !message_document.set_receipt(member.clone(), receipt) {
+                return Ok(());
+            }
+
+            this.document
+                .update_message_document(&this.ipfs, &message_document)
+                .await?;
+
+            this.set_document().await?;
+
+            if receipt == Receipt::Read {
+                if let Err(e) = this.event_broadcast.send(MessageEventKind::MessageRead {
+                    conversation_id,
+                    message_id,
+                    did_key: member,
+                }) {
+                    tracing::warn!(%conversation_id, error = %e, "Error broadcasting event");
+                }
+            }
+
+            let status = message_document.delivery_status(&recipients);
+            if message_document.sender() == own_did && status != previous_status {
+                this.emit_status(message_id, status);
             }
         }
         _ => {}
@@ -3200,17 +5265,62 @@ async fn message_event(
     Ok(())
 }
 
+/// Check that a message of `member` dated at `date` is far enough from their previous message
+fn check_slow_mode(
+    document: &ConversationDocument,
+    last_message_dates: &HashMap<DID, DateTime<Utc>>,
+    member: &DID,
+    date: DateTime<Utc>,
+) -> Result<(), Error> {
+    if document.slow_mode_exempt(member) {
+        return Ok(());
+    }
+
+    let slow_mode = document.slow_mode as i64;
+
+    if let Some(last) = last_message_dates.get(member) {
+        // messages may arrive out of order so the distance is checked in either direction
+        let elapsed = (date - *last).num_seconds().abs();
+        if elapsed < slow_mode {
+            return Err(Error::SlowModeActive {
+                remaining: (slow_mode - elapsed) as u64,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Record the date of a message of `member` once it is stored so a message that failed to send does not count
+/// towards slow mode
+fn record_slow_mode(
+    last_message_dates: &mut HashMap<DID, DateTime<Utc>>,
+    member: &DID,
+    date: DateTime<Utc>,
+) {
+    last_message_dates
+        .entry(member.clone())
+        .and_modify(|last| *last = (*last).max(date))
+        .or_insert(date);
+}
+
 async fn process_request_response_event(
     this: &mut ConversationTask,
     req: Message,
 ) -> Result<(), Error> {
     let keypair = &this.root.keypair().clone();
-    let own_did = this.identity.did_key();
 
     let payload = PayloadMessage::<ConversationRequestResponse>::from_bytes(&req.data)?;
 
     let sender = payload.sender().to_did()?;
 
+    this.usage.record_received(
+        UsageCategory::Messages,
+        Some(this.conversation_id),
+        Some(&sender),
+        req.data.len(),
+    );
+
     let event = payload.message(keypair)?;
 
     tracing::debug!(id=%this.conversation_id, ?event, "Event received");
@@ -3230,63 +5340,21 @@ async fn process_request_response_event(
                     return Err(Error::IdentityDoesntExist);
                 }
 
-                let keystore = &mut this.keystore;
-
-                let raw_key = match keystore.get_latest(keypair, &own_did) {
-                    Ok(key) => key,
-                    Err(Error::PublicKeyDoesntExist) => {
-                        let key = generate::<64>().into();
-                        keystore.insert(keypair, &own_did, &key)?;
-
-                        this.set_keystore(None).await?;
-                        key
-                    }
-                    Err(e) => {
-                        tracing::error!(%conversation_id, error = %e, "Error getting key from store");
-                        return Err(e);
-                    }
-                };
-
-                let key = ecdh_encrypt(keypair, Some(&sender), raw_key)?;
-
-                let response = ConversationRequestResponse::Response {
-                    conversation_id,
-                    kind: ConversationResponseKind::Key { key },
-                };
-
-                let topic = this.document.exchange_topic(&sender);
-
-                let payload = PayloadBuilder::new(keypair, response)
-                    .add_recipient(&sender)?
-                    .from_ipfs(&this.ipfs)
-                    .await?;
-
-                let peers = this.ipfs.pubsub_peers(Some(topic.clone())).await?;
-
-                let peer_id = sender.to_peer_id()?;
-
-                let bytes = payload.to_bytes()?;
-
-                tracing::trace!(%conversation_id, "Payload size: {} bytes", bytes.len());
-
-                tracing::info!(%conversation_id, "Responding to {sender}");
+                this.send_key(&sender).await?;
+            }
+            ConversationRequestKind::ResendEvents { from, to } => {
+                if !this.document.recipients().contains(&sender) {
+                    return Err(Error::IdentityDoesntExist);
+                }
 
-                if !peers.contains(&peer_id)
-                    || (peers.contains(&peer_id)
-                        && this
-                            .ipfs
-                            .pubsub_publish(topic.clone(), bytes.clone())
-                            .await
-                            .is_err())
-                {
-                    tracing::warn!(%conversation_id, "Unable to publish to topic. Queuing event");
-                    // TODO
-                    this.queue_event(
-                        sender.clone(),
-                        QueueItem::direct(None, peer_id, topic.clone(), bytes.clone()),
-                    )
-                    .await;
+                this.resend_events(from, to).await?;
+            }
+            ConversationRequestKind::CatchUp { known } => {
+                if !this.document.recipients().contains(&sender) {
+                    return Err(Error::IdentityDoesntExist);
                 }
+
+                this.send_catch_up(&sender, &known).await?;
             }
             _ => {
                 tracing::info!(%conversation_id, "Unimplemented/Unsupported Event");
@@ -3296,7 +5364,11 @@ async fn process_request_response_event(
             conversation_id,
             kind,
         } => match kind {
-            ConversationResponseKind::Key { key } => {
+            ConversationResponseKind::Key {
+                key,
+                epoch,
+                devices,
+            } => {
                 if !matches!(this.document.conversation_type(), ConversationType::Group) {
                     //Only group conversations support keys
                     tracing::error!(%conversation_id, "Invalid conversation type");
@@ -3308,9 +5380,17 @@ async fn process_request_response_event(
                 }
                 let keystore = &mut this.keystore;
 
-                let raw_key = ecdh_decrypt(keypair, Some(&sender), key)?;
+                let raw_key = match devices.is_empty() {
+                    true => ecdh_decrypt(keypair, Some(&sender), key)?,
+                    false => device::unwrap_key(this.identity.device_key(), &sender, &devices)?,
+                };
 
-                keystore.insert(keypair, &sender, raw_key)?;
+                match epoch {
+                    // the key of the epoch may have already been shared during a rotation
+                    Some(epoch) if keystore.get(keypair, &sender, epoch).is_ok() => {}
+                    Some(epoch) => keystore.insert_with_epoch(keypair, &sender, epoch, raw_key)?,
+                    None => keystore.insert(keypair, &sender, raw_key)?,
+                }
 
                 this.set_keystore(None).await?;
 
@@ -3320,6 +5400,13 @@ async fn process_request_response_event(
                     }
                 }
             }
+            ConversationResponseKind::CatchUp { document } => {
+                if !this.document.recipients().contains(&sender) {
+                    return Err(Error::IdentityDoesntExist);
+                }
+
+                this.apply_remote_document(document).await?;
+            }
             _ => {
                 tracing::info!(%conversation_id, "Unimplemented/Unsupported Event");
             }
@@ -3358,13 +5445,16 @@ async fn process_pending_payload(this: &mut ConversationTask) {
 
         let event_fn = || {
             let keypair = root.keypair();
-            let key = store.get_latest(keypair, &sender)?;
             let payload = PayloadMessage::<MessagingEvents>::from_bytes(&data)?;
+            let key = match payload.key_epoch() {
+                Some(epoch) => store.get(keypair, &sender, epoch)?,
+                None => store.get_latest(keypair, &sender)?,
+            };
             let event = payload.message_from_key(&key)?;
-            Ok::<_, Error>(event)
+            Ok::<_, Error>((event, payload.sequence()))
         };
 
-        let event = match event_fn() {
+        let (event, sequence) = match event_fn() {
             Ok(event) => event,
             Err(e) => {
                 tracing::error!(name = "process_pending_payload", %conversation_id, %sender, error = %e, "failed to process message");
@@ -3372,7 +5462,7 @@ async fn process_pending_payload(this: &mut ConversationTask) {
             }
         };
 
-        if let Err(e) = message_event(this, &sender, event).await {
+        if let Err(e) = sequenced_message_event(this, &sender, sequence, event).await {
             tracing::error!(name = "process_pending_payload", %conversation_id, %sender, error = %e, "failed to process message")
         }
     }
@@ -3385,7 +5475,14 @@ async fn process_conversation_event(
     let payload = PayloadMessage::<MessagingEvents>::from_bytes(&message.data)?;
     let sender = payload.sender().to_did()?;
 
-    let key = this.conversation_key(Some(&sender))?;
+    this.usage.record_received(
+        UsageCategory::Messages,
+        Some(this.conversation_id),
+        Some(&sender),
+        message.data.len(),
+    );
+
+    let key = this.conversation_key(Some(&sender), payload.key_epoch())?;
 
     let event = match payload.message_from_key(&key)? {
         event @ MessagingEvents::Event { .. } => event,
@@ -3427,6 +5524,12 @@ struct QueueItem {
     topic: String,
     data: Bytes,
     sent: bool,
+    #[serde(default)]
+    attempts: u32,
+    #[serde(default)]
+    next_attempt: Option<DateTime<Utc>>,
+    #[serde(default)]
+    failed: bool,
 }
 
 impl QueueItem {
@@ -3438,13 +5541,70 @@ impl QueueItem {
             topic,
             data,
             sent: false,
+            attempts: 0,
+            next_attempt: None,
+            failed: false,
+        }
+    }
+}
+
+/// Remove the keys of departed members whose epochs were closed before `before`. The keys of the senders of stored
+/// messages are kept so the messages remain readable, and nothing is removed if the messages could not be loaded
+fn prune_departed_keys(
+    keystore: &mut Keystore,
+    before: DateTime<Utc>,
+    members: &[DID],
+    messages: Result<BTreeSet<MessageDocument>, Error>,
+) -> Vec<DID> {
+    let messages = match messages {
+        Ok(messages) => messages,
+        Err(e) => {
+            tracing::warn!(error = %e, "unable to load messages. Keeping keys");
+            return vec![];
+        }
+    };
+
+    let senders = messages
+        .iter()
+        .map(MessageDocument::sender)
+        .collect::<HashSet<_>>();
+
+    keystore.prune(before, |did| members.contains(did) || senders.contains(did))
+}
+
+/// Status of a message from its items in the outbound queue. A message is failed if any of its items failed,
+/// and queued if any of its items are still waiting to be sent
+fn queue_status<'a>(
+    items: impl IntoIterator<Item = &'a QueueItem>,
+    message_id: Uuid,
+) -> Option<MessageStatus> {
+    let mut status = None;
+
+    for item in items
+        .into_iter()
+        .filter(|item| item.m_id == Some(message_id))
+    {
+        if item.failed {
+            return Some(MessageStatus::Failed);
+        }
+
+        if !item.sent {
+            status = Some(MessageStatus::Queued);
         }
     }
+
+    status
 }
 
-//TODO: Replace
+// Items are published in the order they were queued for each peer, stopping at the first item that cannot be sent
+// so that later messages are not received before earlier ones. Items that fail to publish are retried with an
+// exponential backoff until `MAX_QUEUE_ATTEMPTS` is reached, at which point they are marked as failed and kept
+// so the status of the message can be reported until it is deleted.
 async fn process_queue(this: &mut ConversationTask) {
+    let now = this.time.now();
     let mut changed = false;
+    let mut updated = IndexSet::new();
+
     for (did, items) in this.queue.iter_mut() {
         let Ok(peer_id) = did.to_peer_id() else {
             continue;
@@ -3454,14 +5614,26 @@ async fn process_queue(this: &mut ConversationTask) {
             continue;
         }
 
-        // TODO:
-        for item in items {
+        let mut published = 0;
+
+        for item in items.iter_mut().filter(|item| !item.sent && !item.failed) {
+            if published >= MAX_QUEUE_ITEMS_PER_INTERVAL {
+                break;
+            }
+
+            if item.next_attempt.is_some_and(|date| date > now) {
+                break;
+            }
+
             let QueueItem {
+                m_id,
                 peer,
                 topic,
                 data,
                 sent,
-                ..
+                attempts,
+                next_attempt,
+                failed,
             } = item;
 
             if !this
@@ -3471,26 +5643,60 @@ async fn process_queue(this: &mut ConversationTask) {
                 .map(|list| list.contains(peer))
                 .unwrap_or_default()
             {
-                continue;
+                break;
             }
 
-            if *sent {
-                continue;
-            }
+            changed = true;
+
+            let result = match *topic == this.document.topic() {
+                true => this.publish_chunked(data.clone()).await,
+                false => this
+                    .ipfs
+                    .pubsub_publish(topic.clone(), data.clone())
+                    .await
+                    .map_err(Error::from),
+            };
 
-            if let Err(e) = this.ipfs.pubsub_publish(topic.clone(), data.clone()).await {
+            if let Err(e) = result {
                 tracing::error!("Error publishing to topic: {e}");
-                continue;
+                *attempts += 1;
+
+                if *attempts >= MAX_QUEUE_ATTEMPTS {
+                    tracing::warn!(conversation_id = %this.conversation_id, peer_id = %peer, "unable to publish queued item. Marking as failed");
+                    *failed = true;
+                    updated.extend(*m_id);
+                    continue;
+                }
+
+                let backoff = 2i64.pow(*attempts).min(MAX_QUEUE_BACKOFF_SECS);
+                next_attempt.replace(now + chrono::Duration::seconds(backoff));
+                break;
             }
 
+            this.usage.record_sent(
+                UsageCategory::Messages,
+                Some(this.conversation_id),
+                Some(did),
+                data.len(),
+            );
+
             *sent = true;
+            published += 1;
+            updated.extend(*m_id);
+        }
+    }
 
-            changed = true;
+    for message_id in updated {
+        match this.queued_status(message_id) {
+            Some(MessageStatus::Failed) => this.emit_status(message_id, MessageStatus::Failed),
+            Some(_) => {}
+            None => this.emit_status(message_id, MessageStatus::Sent),
         }
     }
 
     this.queue.retain(|_, queue| {
-        queue.retain(|item| !item.sent);
+        // failed items are only kept if there is a message to report the status of
+        queue.retain(|item| !item.sent && !(item.failed && item.m_id.is_none()));
         !queue.is_empty()
     });
 
@@ -3524,3 +5730,105 @@ fn pubkey_or_keystore(conversation: &ConversationTask) -> Result<Either<DID, Key
 
     Ok(keystore)
 }
+
+fn attachment_bytes(message: &MessageDocument) -> u64 {
+    message
+        .attachments
+        .iter()
+        .map(|attachment| attachment.size as u64)
+        .sum()
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use chrono::{Duration, Utc};
+    use either::Either;
+    use rust_ipfs::{Keypair, PeerId};
+    use uuid::Uuid;
+    use warp::crypto::{generate, DID};
+    use warp::error::Error;
+    use warp::raygun::{MessageStatus, MessageType};
+
+    use super::{prune_departed_keys, queue_status, QueueItem};
+    use crate::store::conversation::message::MessageDocumentBuilder;
+    use crate::store::keystore::Keystore;
+    use crate::store::PeerIdExt;
+
+    fn item(message_id: Uuid) -> QueueItem {
+        QueueItem::direct(
+            Some(message_id),
+            PeerId::random(),
+            String::from("topic"),
+            vec![0u8],
+        )
+    }
+
+    #[test]
+    fn message_status_from_queue() {
+        let message_id = Uuid::new_v4();
+        let mut items = vec![item(message_id), item(message_id), item(Uuid::new_v4())];
+
+        assert_eq!(queue_status(&items, Uuid::new_v4()), None);
+        assert_eq!(
+            queue_status(&items, message_id),
+            Some(MessageStatus::Queued)
+        );
+
+        // still queued while one of the recipients has not received it
+        items[0].sent = true;
+        assert_eq!(
+            queue_status(&items, message_id),
+            Some(MessageStatus::Queued)
+        );
+
+        items[1].sent = true;
+        assert_eq!(queue_status(&items, message_id), None);
+
+        // failing for a single recipient fails the message
+        items[1].sent = false;
+        items[1].failed = true;
+        assert_eq!(
+            queue_status(&items, message_id),
+            Some(MessageStatus::Failed)
+        );
+    }
+
+    #[test]
+    fn keys_of_senders_are_kept() -> anyhow::Result<()> {
+        let keypair = Keypair::generate_ed25519();
+        let own_did = keypair.to_did()?;
+        let departed_keypair = Keypair::generate_ed25519();
+        let departed = departed_keypair.to_did()?;
+        let now = Utc::now();
+
+        let mut keystore = Keystore::default();
+        keystore.insert(&keypair, &departed, generate::<32>())?;
+        keystore.close_epochs(&departed, now - Duration::days(40));
+
+        let before = now - Duration::days(30);
+        let members = [own_did];
+
+        // the keys are kept when the messages could not be loaded
+        let removed = prune_departed_keys(&mut keystore, before, &members, Err(Error::Other));
+        assert!(removed.is_empty());
+        assert!(keystore.exist(&departed));
+
+        // as well as while a message of the departed member remains, regardless of its type
+        let message = MessageDocumentBuilder::new(&departed_keypair, Either::Left(&departed))
+            .set_conversation_id(Uuid::new_v4())
+            .set_sender(departed.clone())
+            .set_message_type(MessageType::Event)
+            .build()?;
+        let messages = BTreeSet::from([message]);
+        let removed = prune_departed_keys(&mut keystore, before, &members, Ok(messages));
+        assert!(removed.is_empty());
+        assert!(keystore.exist(&departed));
+
+        let removed = prune_departed_keys(&mut keystore, before, &members, Ok(BTreeSet::new()));
+        assert_eq!(removed, vec![departed.clone()]);
+        assert!(!keystore.exist(&departed));
+        Ok(())
+    }
+}
@@ -1,18 +1,32 @@
+pub mod cold_storage;
 pub mod community;
+pub mod compression;
 pub mod conversation;
+pub mod device;
 pub mod discovery;
 pub mod document;
+pub mod emoji;
+pub mod encrypted;
+pub mod erasure;
 pub mod event_subscription;
+pub mod exchange;
 pub mod files;
 pub mod identity;
+pub mod inbox;
 pub mod keystore;
 pub mod message;
+pub mod moderation;
 pub mod payload;
 pub mod phonebook;
 pub mod queue;
+pub mod rpc;
+pub mod sync;
+pub mod upload;
+pub mod usage;
 
 use chrono::{DateTime, Utc};
 use community::{CommunityChannelDocument, CommunityDocument, CommunityRoleDocument};
+use ipld_core::cid::Cid;
 use rust_ipfs as ipfs;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
@@ -31,24 +45,43 @@ use warp::{
     multipass::identity::IdentityStatus,
     raygun::{
         community::{CommunityChannelPermission, CommunityPermission, RoleId},
-        GroupPermissions, MessageEvent, PinState, ReactionState,
+        GroupPermissions, HistoryVisibility, MessageEvent, PinState, ReactionState,
+        RetentionPolicy,
     },
 };
 
-use conversation::{message::MessageDocument, ConversationDocument};
+use conversation::{
+    clock::HybridTimestamp,
+    message::{MessageDocument, Receipt},
+    ConversationDocument,
+};
 
 pub const MAX_THUMBNAIL_SIZE: usize = 5_242_880;
 pub const MAX_IMAGE_SIZE: usize = 2_097_152;
 pub const MIN_USERNAME_LENGTH: usize = 4;
 pub const MAX_USERNAME_LENGTH: usize = 64;
 pub const MAX_STATUS_LENGTH: usize = 512;
+pub const MAX_ACTIVITY_TITLE_LENGTH: usize = 128;
+pub const MAX_ACTIVITY_DETAILS_LENGTH: usize = 128;
+pub const MAX_PRONOUNS_LENGTH: usize = 32;
+pub const MAX_TIMEZONE_LENGTH: usize = 64;
+pub const MAX_PROFILE_LINKS: usize = 5;
+pub const MAX_PROFILE_LINK_LENGTH: usize = 256;
 pub const MIN_MESSAGE_SIZE: usize = 1;
 pub const MAX_MESSAGE_SIZE: usize = 4_096;
+pub const MAX_MESSAGE_SIZE_LIMIT: usize = 65_536;
+// Maximum amount of characters of a message that is sent as a snippet
+pub const MAX_SNIPPET_SIZE: usize = 1024 * 1024;
+// Leaves room for the chunk header below the default gossipsub transmit size of relays
+pub const MAX_PUBSUB_CHUNK_SIZE: usize = 60 * 1024;
+pub const MAX_CHUNKED_PAYLOAD_SIZE: usize = 2 * 1024 * 1024;
 pub const MAX_ATTACHMENT: usize = 32;
 pub const MIN_ATTACHMENT: usize = 1;
 pub const MAX_CONVERSATIONS: usize = 1_000;
 pub const MAX_FRIENDS: usize = 1_000;
 pub const MAX_REQUEST: usize = 1_000;
+pub const MAX_FRIEND_REQUEST_MESSAGE_LENGTH: usize = 256;
+pub const MAX_RELATIONSHIP_HISTORY_ENTRIES: usize = 100;
 pub const MAX_METADATA_KEY_LENGTH: usize = 32;
 pub const MAX_METADATA_VALUE_LENGTH: usize = 128;
 pub const MAX_METADATA_ENTRIES: usize = 20;
@@ -56,6 +89,18 @@ pub const MAX_THUMBNAIL_STREAM_SIZE: usize = 20 * 1024 * 1024;
 pub const MAX_CONVERSATION_ICON_SIZE: usize = 4 * 1024 * 1024;
 pub const MAX_CONVERSATION_BANNER_SIZE: usize = 8 * 1024 * 1024;
 pub const MAX_COMMUNITY_CHANNELS: usize = 20;
+pub const MAX_APP_DATA_NAMESPACES: usize = 16;
+pub const MAX_APP_DATA_NAMESPACE_LENGTH: usize = 64;
+pub const MAX_APP_DATA_SIZE: usize = 64 * 1024;
+pub const MAX_RPC_NAMESPACE_LENGTH: usize = 64;
+pub const MAX_RPC_PAYLOAD_SIZE: usize = 64 * 1024;
+pub const MAX_SLOW_MODE: u64 = 6 * 60 * 60;
+// Tolerated difference between the clock of the sender and our own when enforcing slow mode
+pub const SLOW_MODE_CLOCK_SKEW: i64 = 30;
+pub const MAX_REPORT_REASON_LENGTH: usize = 1024;
+pub const MAX_MODERATION_REPORTS: usize = 1_000;
+pub const MAX_PENDING_REPORTS_PER_REPORTER: usize = 20;
+pub const MAX_PENDING_EVENTS: usize = 1_000;
 
 pub(crate) mod protocols {
     use rust_ipfs::libp2p::StreamProtocol;
@@ -66,6 +111,8 @@ pub(crate) mod protocols {
     pub const IDENTITY_PROTOCOL: StreamProtocol = StreamProtocol::new("/warp/identity");
     /// Bootstrap discovery
     pub const DISCOVERY_PROTOCOL: StreamProtocol = StreamProtocol::new("/warp/discovery");
+    /// Protocol for custom application requests between friends
+    pub const PEER_RPC_PROTOCOL: StreamProtocol = StreamProtocol::new("/warp/rpc");
 
     // shuttle various protocols
     pub const SHUTTLE_IDENTITY: StreamProtocol = StreamProtocol::new("/shuttle/identity/0.0.1");
@@ -80,6 +127,8 @@ pub(crate) enum ConversationImageType {
 pub const MAX_CONVERSATION_DESCRIPTION: usize = 256;
 pub const MAX_COMMUNITY_DESCRIPTION: usize = 256;
 pub const MAX_REACTIONS: usize = 30;
+/// Maximum amount of distinct custom emoji a message can be reacted with, as part of [`MAX_REACTIONS`]
+pub const MAX_CUSTOM_REACTIONS: usize = 10;
 
 pub(super) mod topics {
     use std::fmt::Display;
@@ -101,6 +150,12 @@ pub(super) mod topics {
         fn messaging(&self) -> String {
             format!("/id/{self}/messaging")
         }
+        fn moderation(&self) -> String {
+            format!("/id/{self}/moderation")
+        }
+        fn organization(&self) -> String {
+            format!("/id/{self}/organization")
+        }
 
         // fn invites(&self) -> String {
         //     format!("/id/{self}/messaging/invites")
@@ -131,7 +186,9 @@ pub(super) mod topics {
 }
 
 pub(super) mod ds_key {
+    use ipld_core::cid::Cid;
     use rust_ipfs::{Ipfs, Keypair, PeerId, PublicKey};
+    use uuid::Uuid;
 
     pub trait DataStoreKey {
         fn base(&self) -> String;
@@ -151,6 +208,54 @@ pub(super) mod ds_key {
         fn request_queue(&self) -> String {
             self.base() + "/request_queue"
         }
+
+        fn moderation_queue(&self) -> String {
+            self.base() + "/moderation_queue"
+        }
+
+        fn image_variant(&self, cid: &Cid, dimension: u32) -> String {
+            format!("{}/image_variants/{cid}/{dimension}", self.base())
+        }
+
+        fn encrypted(&self, namespace: &str, key: &str) -> String {
+            format!("{}/encrypted/{namespace}/{key}", self.base())
+        }
+
+        fn tombstone(&self, message_id: Uuid) -> String {
+            format!("{}/tombstone/{message_id}", self.base())
+        }
+
+        fn conversation_stats(&self, conversation_id: Uuid) -> String {
+            format!("{}/conversation_stats/{conversation_id}", self.base())
+        }
+
+        fn selective_sync(&self) -> String {
+            self.base() + "/selective_sync"
+        }
+
+        fn cold_storage(&self) -> String {
+            self.base() + "/cold_storage"
+        }
+
+        fn emoji_packs(&self) -> String {
+            self.base() + "/emoji_packs"
+        }
+
+        fn message_sequence(&self, conversation_id: Uuid) -> String {
+            format!("{}/message_sequence/{conversation_id}", self.base())
+        }
+
+        fn device_key(&self) -> String {
+            self.base() + "/device_key"
+        }
+
+        fn pending_events(&self) -> String {
+            self.base() + "/pending_events"
+        }
+
+        fn upload_sessions(&self) -> String {
+            self.base() + "/upload_sessions"
+        }
     }
 
     impl DataStoreKey for Ipfs {
@@ -310,6 +415,8 @@ where
 pub enum ConversationEvents {
     NewConversation {
         recipient: DID,
+        #[serde(default)]
+        generation: u32,
     },
     NewGroupConversation {
         conversation: ConversationDocument,
@@ -322,6 +429,10 @@ pub enum ConversationEvents {
     DeleteConversation {
         conversation_id: Uuid,
     },
+    MergeConversations {
+        conversation_id: Uuid,
+        duplicate_id: Uuid,
+    },
 
     NewCommunityInvite {
         community_id: Uuid,
@@ -366,15 +477,40 @@ pub enum ConversationRequestKind {
     WantMessage {
         message_id: Uuid,
     },
+    /// Publish the payloads with the sequence numbers in the range again
+    ResendEvents {
+        from: u64,
+        to: u64,
+    },
+    /// Request the latest document and the events published since the last sequence number known of each sender
+    CatchUp {
+        known: Vec<(DID, u64)>,
+    },
 }
 
 #[derive(Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum ConversationResponseKind {
-    Key { key: Vec<u8> },
+    Key {
+        /// Key wrapped for the identity. Empty if the key is only wrapped for the devices of the identity
+        #[serde(default)]
+        key: Vec<u8>,
+        /// Epoch of the key. Absent when sent by peers that do not track key epochs
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        epoch: Option<usize>,
+        /// Key wrapped for each device of the identity, if it publishes any
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        devices: Vec<(DID, Vec<u8>)>,
+    },
     Pong,
-    HaveMessages { messages: Vec<Uuid> },
+    HaveMessages {
+        messages: Vec<Uuid>,
+    },
     AcknowledgementConfirmed,
+    /// Cid of the latest conversation document of the sender
+    CatchUp {
+        document: Cid,
+    },
 }
 
 impl std::fmt::Debug for ConversationResponseKind {
@@ -397,6 +533,8 @@ pub enum MessagingEvents {
         lines: Vec<String>,
         nonce: Vec<u8>,
         signature: Vec<u8>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        modified_clock: Option<HybridTimestamp>,
     },
     Delete {
         conversation_id: Uuid,
@@ -415,6 +553,12 @@ pub enum MessagingEvents {
         state: ReactionState,
         emoji: String,
     },
+    Receipt {
+        conversation_id: Uuid,
+        member: DID,
+        message_id: Uuid,
+        receipt: Receipt,
+    },
     UpdateConversation {
         conversation: ConversationDocument,
         kind: ConversationUpdateKind,
@@ -444,6 +588,8 @@ pub enum CommunityMessagingEvents {
         lines: Vec<String>,
         nonce: Vec<u8>,
         signature: Vec<u8>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        modified_clock: Option<HybridTimestamp>,
     },
     Delete {
         community_id: Uuid,
@@ -504,6 +650,9 @@ pub enum ConversationUpdateKind {
     RemovedIcon,
     RemovedBanner,
     ChangeDescription { description: Option<String> },
+    ChangeHistoryVisibility { visibility: HistoryVisibility },
+    ChangeSlowMode { slow_mode: u64 },
+    ChangeRetentionPolicy { policy: Option<RetentionPolicy> },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -575,6 +724,10 @@ pub enum CommunityUpdateKind {
         channel_id: Uuid,
         description: Option<String>,
     },
+    EditCommunityChannelSlowMode {
+        channel_id: Uuid,
+        slow_mode: u64,
+    },
     GrantCommunityChannelPermission {
         channel_id: Uuid,
         permission: CommunityChannelPermission,
@@ -687,6 +840,17 @@ pub(crate) fn ecdh_encrypt<K: AsRef<[u8]>>(
     Ok(data)
 }
 
+/// Same as [`ecdh_encrypt`], but encrypts `data` in place so the allocation of the serialized payload is reused
+pub(crate) fn ecdh_encrypt_in_place(
+    did: &Keypair,
+    recipient: Option<&DID>,
+    data: &mut Vec<u8>,
+) -> Result<(), Error> {
+    let prik = Zeroizing::new(ecdh_shared_key(did, recipient)?);
+    Cipher::direct_encrypt_in_place(data, &prik)?;
+    Ok(())
+}
+
 pub(crate) fn ecdh_encrypt_with_nonce<K: AsRef<[u8]>>(
     keypair: &Keypair,
     recipient: Option<&DID>,
@@ -783,10 +947,18 @@ pub async fn connected_to_peer<I: Into<PeerType>>(
     })
 }
 
-pub fn extract_data_slice<const N: usize>(data: &[u8]) -> (&[u8], &[u8]) {
-    let extracted = &data[data.len() - N..];
-    let payload = &data[..data.len() - N];
-    (extracted, payload)
+pub fn extract_data_slice<const N: usize>(data: &[u8]) -> Result<(&[u8], &[u8]), Error> {
+    if data.len() < N {
+        return Err(Error::InvalidLength {
+            context: "data".into(),
+            current: data.len(),
+            minimum: Some(N),
+            maximum: None,
+        });
+    }
+
+    let (payload, extracted) = data.split_at(data.len() - N);
+    Ok((extracted, payload))
 }
 
 #[cfg(test)]
@@ -0,0 +1,626 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_rt::AbortableJoinHandle;
+use chrono::Utc;
+use futures::StreamExt;
+use futures_timer::Delay;
+use indexmap::IndexSet;
+use ipld_core::cid::Cid;
+use parking_lot::RwLock;
+use rust_ipfs::{Ipfs, Keypair};
+use serde::{Deserialize, Serialize};
+use tracing::{Instrument, Span};
+use uuid::Uuid;
+use warp::raygun::community::CommunityPermission;
+use warp::raygun::ConversationType;
+use warp::{crypto::DID, error::Error};
+
+use super::{
+    ds_key::DataStoreKey,
+    encrypted::EncryptedStore,
+    identity::IdentityStore,
+    message::MessageStore,
+    payload::{PayloadBuilder, PayloadMessage},
+    topics::PeerTopic,
+    DidExt, PeerIdExt, MAX_MODERATION_REPORTS, MAX_PENDING_REPORTS_PER_REPORTER,
+    MAX_REPORT_REASON_LENGTH,
+};
+use crate::moderation::{ModerationReport, ReportAction, ReportStatus, ReportTarget};
+use crate::rt;
+
+type Reports = Arc<RwLock<Vec<ModerationReport>>>;
+type Outbox = Arc<RwLock<Vec<OutboxItem>>>;
+
+/// Key of the received reports in the encrypted store
+const REPORTS_KEY: &str = "reports";
+
+/// Key of the reports waiting to be delivered in the encrypted store
+const OUTBOX_KEY: &str = "outbox";
+
+/// Interval at which delivery of the reports in the outbox is attempted
+const OUTBOX_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Report waiting for a moderator to subscribe to their moderation topic
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct OutboxItem {
+    moderator: DID,
+    data: Vec<u8>,
+}
+
+#[derive(Clone)]
+pub struct ModerationStore {
+    ipfs: Ipfs,
+    identity: IdentityStore,
+    messaging: MessageStore,
+    reports: Reports,
+    outbox: Outbox,
+    store: Option<EncryptedStore>,
+    _handle: AbortableJoinHandle<()>,
+}
+
+impl ModerationStore {
+    /// Reports hold the reason given by the reporter and are only persisted when `store` is available,
+    /// otherwise they are kept in memory
+    pub async fn new(
+        ipfs: &Ipfs,
+        identity: &IdentityStore,
+        messaging: &MessageStore,
+        store: Option<EncryptedStore>,
+        span: &Span,
+    ) -> Result<Self, Error> {
+        // The node of a replica does not support pubsub
+        let stream = match identity.is_replica() {
+            true => futures::stream::pending().boxed(),
+            false => ipfs
+                .pubsub_subscribe(identity.did_key().moderation())
+                .await?
+                .boxed(),
+        };
+
+        let reports = Reports::default();
+        let outbox = Outbox::default();
+
+        if let Some(store) = store.as_ref() {
+            match load_reports(ipfs, store).await {
+                Ok(list) => *reports.write() = list,
+                Err(e) => tracing::warn!(error = %e, "unable to load moderation reports"),
+            }
+
+            if let Ok(Some(list)) = store.get_serialized(OUTBOX_KEY).await {
+                *outbox.write() = list;
+            }
+        }
+
+        let task = ModerationTask {
+            ipfs: ipfs.clone(),
+            identity: identity.clone(),
+            messaging: messaging.clone(),
+            reports: reports.clone(),
+            outbox: outbox.clone(),
+            store: store.clone(),
+        };
+
+        let span = span.clone();
+
+        let _handle = rt::spawn_abortable(
+            async move {
+                futures::pin_mut!(stream);
+                let mut outbox_timer = Delay::new(OUTBOX_INTERVAL);
+                loop {
+                    tokio::select! {
+                        Some(message) = stream.next() => {
+                            if let Err(e) = task.process_report(&message.data).await {
+                                tracing::warn!(error = %e, "unable to process moderation report");
+                            }
+                        }
+                        _ = &mut outbox_timer => {
+                            task.process_outbox().await;
+                            outbox_timer.reset(OUTBOX_INTERVAL);
+                        }
+                    }
+                }
+            }
+            .instrument(span),
+        );
+
+        Ok(Self {
+            ipfs: ipfs.clone(),
+            identity: identity.clone(),
+            messaging: messaging.clone(),
+            reports,
+            outbox,
+            store,
+            _handle,
+        })
+    }
+}
+
+impl ModerationStore {
+    /// Sign a report and deliver it to every moderator of `target`. Moderators that are not subscribed to their
+    /// moderation topic receive the report once they are
+    pub async fn report(
+        &self,
+        target: ReportTarget,
+        message_id: Uuid,
+        message_sender: DID,
+        reason: &str,
+    ) -> Result<Uuid, Error> {
+        let reason = reason.trim();
+        validate_reason(reason)?;
+
+        let own_did = self.identity.did_key();
+
+        if message_sender == own_did {
+            return Err(Error::OtherWithContext(
+                "cannot report your own message".into(),
+            ));
+        }
+
+        let mut moderators = moderators(&self.messaging, target).await?;
+        moderators.shift_remove(&own_did);
+
+        if moderators.is_empty() {
+            return Err(Error::OtherWithContext(
+                "no moderators are available to receive the report".into(),
+            ));
+        }
+
+        let keypair = self.identity.root_document().keypair();
+
+        let mut report = ModerationReport {
+            id: Uuid::new_v4(),
+            target,
+            message_id,
+            message_sender,
+            reporter: own_did.clone(),
+            reason: reason.to_string(),
+            created: Utc::now(),
+            status: ReportStatus::Pending,
+            signature: None,
+        };
+
+        report.sign(keypair)?;
+
+        let mut queued = false;
+
+        for moderator in moderators {
+            let payload = PayloadBuilder::new(keypair, report.clone())
+                .add_recipient(&moderator)?
+                .build()?;
+
+            let data = payload.to_bytes()?.to_vec();
+
+            if let Err(e) = publish(&self.ipfs, &moderator, data.clone()).await {
+                tracing::debug!(%moderator, error = %e, "moderator is unavailable. Queuing report");
+                push_outbox(&self.outbox, OutboxItem { moderator, data });
+                queued = true;
+            }
+        }
+
+        if queued {
+            let outbox = self.outbox.read().clone();
+            save(&self.store, OUTBOX_KEY, &outbox).await;
+        }
+
+        Ok(report.id)
+    }
+
+    pub fn list(&self) -> Vec<ModerationReport> {
+        let mut list = self.reports.read().clone();
+        // pending reports first, oldest first
+        list.sort_by_key(|report| (report.status != ReportStatus::Pending, report.created));
+        list
+    }
+
+    pub fn get(&self, id: Uuid) -> Result<ModerationReport, Error> {
+        self.reports
+            .read()
+            .iter()
+            .find(|report| report.id == id)
+            .cloned()
+            .ok_or(Error::ObjectNotFound)
+    }
+
+    pub async fn set_resolved(&self, id: Uuid, action: ReportAction) -> Result<(), Error> {
+        {
+            let mut reports = self.reports.write();
+            let report = reports
+                .iter_mut()
+                .find(|report| report.id == id)
+                .ok_or(Error::ObjectNotFound)?;
+
+            report.status = ReportStatus::Resolved {
+                action,
+                resolved: Utc::now(),
+            };
+        }
+
+        let reports = self.reports.read().clone();
+        save(&self.store, REPORTS_KEY, &reports).await;
+        Ok(())
+    }
+}
+
+struct ModerationTask {
+    ipfs: Ipfs,
+    identity: IdentityStore,
+    messaging: MessageStore,
+    reports: Reports,
+    outbox: Outbox,
+    store: Option<EncryptedStore>,
+}
+
+impl ModerationTask {
+    async fn process_report(&self, data: &[u8]) -> Result<(), Error> {
+        let payload: PayloadMessage<ModerationReport> = PayloadMessage::from_bytes(data)?;
+
+        let sender = payload.sender().to_did()?;
+
+        let mut report = payload.message(self.identity.root_document().keypair())?;
+
+        if report.reporter != sender {
+            return Err(Error::SenderMismatch);
+        }
+
+        if self.identity.is_blocked(&sender).await? {
+            return Ok(());
+        }
+
+        validate_reason(&report.reason)?;
+        report.verify()?;
+
+        // only members are able to report messages, and only to the moderators of the conversation or community
+        if !members(&self.messaging, report.target)
+            .await?
+            .contains(&sender)
+        {
+            return Err(Error::Unauthorized);
+        }
+
+        if !moderators(&self.messaging, report.target)
+            .await?
+            .contains(&self.identity.did_key())
+        {
+            return Err(Error::Unauthorized);
+        }
+
+        report.status = ReportStatus::Pending;
+
+        {
+            let mut reports = self.reports.write();
+
+            if reports.iter().any(|existing| existing.id == report.id) {
+                return Ok(());
+            }
+
+            let pending_from_reporter = reports
+                .iter()
+                .filter(|existing| {
+                    existing.reporter == sender && existing.status == ReportStatus::Pending
+                })
+                .count();
+
+            if pending_from_reporter >= MAX_PENDING_REPORTS_PER_REPORTER {
+                return Err(Error::OtherWithContext(format!(
+                    "{sender} has too many pending reports"
+                )));
+            }
+
+            if reports.len() >= MAX_MODERATION_REPORTS {
+                // make room by discarding the oldest resolved report
+                let Some(index) = reports
+                    .iter()
+                    .position(|existing| existing.status != ReportStatus::Pending)
+                else {
+                    return Err(Error::OtherWithContext("moderation queue is full".into()));
+                };
+                reports.remove(index);
+            }
+
+            tracing::info!(id = %report.id, reporter = %sender, "received moderation report");
+            reports.push(report);
+        }
+
+        let reports = self.reports.read().clone();
+        save(&self.store, REPORTS_KEY, &reports).await;
+        Ok(())
+    }
+
+    /// Deliver the reports in the outbox to the moderators that are now subscribed to their moderation topic
+    async fn process_outbox(&self) {
+        let items = self.outbox.read().clone();
+
+        if items.is_empty() {
+            return;
+        }
+
+        let mut delivered = Vec::new();
+
+        for item in items {
+            if publish(&self.ipfs, &item.moderator, item.data.clone())
+                .await
+                .is_ok()
+            {
+                delivered.push(item);
+            }
+        }
+
+        if delivered.is_empty() {
+            return;
+        }
+
+        let outbox = {
+            let mut outbox = self.outbox.write();
+            outbox.retain(|item| !delivered.contains(item));
+            outbox.clone()
+        };
+
+        save(&self.store, OUTBOX_KEY, &outbox).await;
+    }
+}
+
+/// Publish a report to `moderator`, failing if they are not subscribed to their moderation topic
+async fn publish(ipfs: &Ipfs, moderator: &DID, data: Vec<u8>) -> Result<(), Error> {
+    let peer_id = moderator.to_peer_id()?;
+    let topic = moderator.moderation();
+
+    let subscribed = ipfs
+        .pubsub_peers(Some(topic.clone()))
+        .await
+        .map(|peers| peers.contains(&peer_id))
+        .unwrap_or_default();
+
+    if !subscribed {
+        return Err(Error::OtherWithContext(
+            "moderator is not subscribed to their moderation topic".into(),
+        ));
+    }
+
+    ipfs.pubsub_publish(topic, data).await?;
+    Ok(())
+}
+
+fn push_outbox(outbox: &Outbox, item: OutboxItem) {
+    let mut outbox = outbox.write();
+    // the oldest report is dropped once the outbox is full
+    if outbox.len() >= MAX_MODERATION_REPORTS {
+        outbox.remove(0);
+    }
+    outbox.push(item);
+}
+
+/// Identities that are able to report messages sent to `target`
+async fn members(messaging: &MessageStore, target: ReportTarget) -> Result<IndexSet<DID>, Error> {
+    match target {
+        ReportTarget::Conversation { conversation_id } => {
+            let conversation = messaging.get_conversation(conversation_id).await?;
+            Ok(conversation.recipients().iter().cloned().collect())
+        }
+        ReportTarget::CommunityChannel { community_id, .. } => {
+            let community = messaging.clone().get_community(community_id).await?;
+            let mut members = community.members().clone();
+            members.insert(community.creator().clone());
+            Ok(members)
+        }
+    }
+}
+
+/// Identities that receive the reports of messages sent to `target`
+async fn moderators(
+    messaging: &MessageStore,
+    target: ReportTarget,
+) -> Result<IndexSet<DID>, Error> {
+    match target {
+        ReportTarget::Conversation { conversation_id } => {
+            let conversation = messaging.get_conversation(conversation_id).await?;
+
+            // direct conversations have no one to moderate them
+            if conversation.conversation_type() == ConversationType::Direct {
+                return Err(Error::InvalidConversation);
+            }
+
+            let creator = conversation
+                .creator()
+                .cloned()
+                .ok_or(Error::InvalidConversation)?;
+
+            Ok(IndexSet::from([creator]))
+        }
+        ReportTarget::CommunityChannel { community_id, .. } => {
+            let mut messaging = messaging.clone();
+            let community = messaging.get_community(community_id).await?;
+
+            let mut moderators = IndexSet::from([community.creator().clone()]);
+
+            // Note: A permission without any roles is granted to every member, in which case only the creator
+            //       is considered a moderator
+            for permission in [
+                CommunityPermission::DeleteMessages,
+                CommunityPermission::RemoveMembers,
+            ] {
+                let Some(roles) = community.permissions().get(&permission) else {
+                    continue;
+                };
+
+                for role_id in roles {
+                    if let Ok(role) = messaging.get_community_role(community_id, *role_id).await {
+                        moderators.extend(role.members().iter().cloned());
+                    }
+                }
+            }
+
+            moderators
+                .retain(|did| community.members().contains(did) || did == community.creator());
+
+            Ok(moderators)
+        }
+    }
+}
+
+impl ModerationReport {
+    fn construct(&self) -> Vec<u8> {
+        let target = match self.target {
+            ReportTarget::Conversation { conversation_id } => conversation_id.into_bytes().to_vec(),
+            ReportTarget::CommunityChannel {
+                community_id,
+                channel_id,
+            } => [community_id.into_bytes(), channel_id.into_bytes()].concat(),
+        };
+
+        warp::crypto::hash::sha256_iter(
+            [
+                Some(self.id.into_bytes().to_vec()),
+                Some(target),
+                Some(self.message_id.into_bytes().to_vec()),
+                Some(self.message_sender.to_string().into_bytes()),
+                Some(self.reporter.to_string().into_bytes()),
+                Some(self.reason.as_bytes().to_vec()),
+                Some(self.created.timestamp_millis().to_be_bytes().to_vec()),
+            ]
+            .into_iter(),
+            None,
+        )
+    }
+
+    pub(crate) fn sign(&mut self, keypair: &Keypair) -> Result<(), Error> {
+        let construct = self.construct();
+        let signature = keypair.sign(&construct).expect("not RSA");
+        self.signature = Some(bs58::encode(signature).into_string());
+        Ok(())
+    }
+
+    pub(crate) fn verify(&self) -> Result<(), Error> {
+        let reporter_pk = self.reporter.to_public_key()?;
+
+        let Some(signature) = self.signature.as_ref() else {
+            return Err(Error::InvalidSignature);
+        };
+
+        let signature = bs58::decode(signature).into_vec()?;
+
+        if !reporter_pk.verify(&self.construct(), &signature) {
+            return Err(Error::InvalidSignature);
+        }
+
+        Ok(())
+    }
+}
+
+fn validate_reason(reason: &str) -> Result<(), Error> {
+    if reason.is_empty() || reason.len() > MAX_REPORT_REASON_LENGTH {
+        return Err(Error::InvalidLength {
+            context: "reason".into(),
+            current: reason.len(),
+            minimum: Some(1),
+            maximum: Some(MAX_REPORT_REASON_LENGTH),
+        });
+    }
+    Ok(())
+}
+
+/// Load the received reports. Reports were previously stored unencrypted in the repo, in which case they are moved
+/// into `store`
+async fn load_reports(ipfs: &Ipfs, store: &EncryptedStore) -> Result<Vec<ModerationReport>, Error> {
+    if let Some(reports) = store.get_serialized(REPORTS_KEY).await? {
+        return Ok(reports);
+    }
+
+    let key = ipfs.moderation_queue();
+
+    let Some(bytes) = ipfs
+        .repo()
+        .data_store()
+        .get(key.as_bytes())
+        .await
+        .unwrap_or_default()
+    else {
+        return Ok(Vec::new());
+    };
+
+    let cid_str = String::from_utf8_lossy(&bytes).to_string();
+    let cid = cid_str.parse::<Cid>().map_err(anyhow::Error::from)?;
+
+    let reports = ipfs
+        .get_dag(cid)
+        .local()
+        .deserialized::<Vec<ModerationReport>>()
+        .await
+        .map_err(anyhow::Error::from)?;
+
+    store.put_serialized(REPORTS_KEY, &reports).await?;
+
+    let _ = ipfs.repo().data_store().remove(key.as_bytes()).await;
+
+    if ipfs.is_pinned(cid).await.unwrap_or_default() {
+        let _ = ipfs.remove_pin(cid).recursive().await;
+    }
+
+    Ok(reports)
+}
+
+async fn save<T: Serialize>(store: &Option<EncryptedStore>, key: &str, value: &T) {
+    let Some(store) = store else {
+        return;
+    };
+
+    if let Err(e) = store.put_serialized(key, value).await {
+        tracing::error!(error = %e, key, "unable to save moderation state");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+    use rust_ipfs::Keypair;
+    use uuid::Uuid;
+
+    use crate::moderation::{ModerationReport, ReportStatus, ReportTarget};
+    use crate::store::PeerIdExt;
+
+    fn report(keypair: &Keypair) -> ModerationReport {
+        let reporter = keypair.to_did().unwrap();
+        let sender = Keypair::generate_ed25519().to_did().unwrap();
+        ModerationReport {
+            id: Uuid::new_v4(),
+            target: ReportTarget::Conversation {
+                conversation_id: Uuid::new_v4(),
+            },
+            message_id: Uuid::new_v4(),
+            message_sender: sender,
+            reporter,
+            reason: "spam".into(),
+            created: Utc::now(),
+            status: ReportStatus::Pending,
+            signature: None,
+        }
+    }
+
+    #[test]
+    fn report_signature() {
+        let keypair = Keypair::generate_ed25519();
+        let mut report = report(&keypair);
+        assert!(report.verify().is_err());
+
+        report.sign(&keypair).unwrap();
+        report.verify().unwrap();
+
+        // the status is local to the moderator and is not covered by the signature
+        report.status = ReportStatus::Resolved {
+            action: crate::moderation::ReportAction::Dismiss,
+            resolved: Utc::now(),
+        };
+        report.verify().unwrap();
+
+        report.reason = "something else".into();
+        assert!(report.verify().is_err());
+    }
+
+    #[test]
+    fn report_signed_by_other_identity() {
+        let keypair = Keypair::generate_ed25519();
+        let mut report = report(&keypair);
+        report.sign(&Keypair::generate_ed25519()).unwrap();
+        assert!(report.verify().is_err());
+    }
+}
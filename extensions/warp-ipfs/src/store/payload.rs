@@ -42,6 +42,44 @@ pub struct PayloadMessage<M> {
     /// signature of the co-signer
     #[serde(skip_serializing_if = "Option::is_none")]
     co_signature: Option<Vec<u8>>,
+
+    /// fields that are not covered by the signatures above
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    envelope: Option<PayloadEnvelope>,
+}
+
+/// Fields of a payload that were added after its original format.
+///
+/// Peers verify the signature of a payload by serializing it again, which drops any field they do not know of. To keep
+/// payloads verifiable by older peers, these fields are left out of the bytes covered by the signature of the payload
+/// and are covered by a signature of their own, made by the sender over the signature of the payload and the fields.
+/// Older peers ignore the envelope, while a payload whose envelope was stripped is handled like one from an older peer.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct PayloadEnvelope {
+    /// epoch of the custom key used to encrypt the message, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key_epoch: Option<usize>,
+
+    /// sequence number of the payload among the payloads of the sender, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sequence: Option<u64>,
+
+    /// signature of the sender over the signature of the payload and the fields above
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    signature: Vec<u8>,
+}
+
+impl PayloadEnvelope {
+    fn is_empty(&self) -> bool {
+        self.key_epoch.is_none() && self.sequence.is_none()
+    }
+
+    fn signing_bytes(&self, payload_signature: &[u8]) -> Result<Vec<u8>, Error> {
+        let fields = (payload_signature, self.key_epoch, self.sequence);
+        cbor4ii::serde::to_vec(Vec::new(), &fields)
+            .map_err(std::io::Error::other)
+            .map_err(Error::from)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -57,6 +95,8 @@ pub struct PayloadBuilder<'a, M> {
     cosigner_keypair: Option<&'a Keypair>,
     recipients: HashSet<PeerId>,
     key: Option<Bytes>,
+    key_epoch: Option<usize>,
+    sequence: Option<u64>,
     message: M,
     ipfs: Option<&'a Ipfs>,
     addresses: Vec<Multiaddr>,
@@ -70,6 +110,8 @@ impl<'a, M: Serialize + DeserializeOwned + Clone> PayloadBuilder<'a, M> {
             keypair,
             cosigner_keypair: None,
             key: None,
+            key_epoch: None,
+            sequence: None,
             message,
             recipients: HashSet::new(),
             ipfs: None,
@@ -134,6 +176,18 @@ impl<'a, M: Serialize + DeserializeOwned + Clone> PayloadBuilder<'a, M> {
         self
     }
 
+    /// Set the epoch of the custom encryption key so the recipients can select the matching key
+    pub fn set_key_epoch(mut self, epoch: impl Into<Option<usize>>) -> Self {
+        self.key_epoch = epoch.into();
+        self
+    }
+
+    /// Set the sequence number of the payload so the recipients can apply the payloads of the sender in order
+    pub fn set_sequence(mut self, sequence: impl Into<Option<u64>>) -> Self {
+        self.sequence = sequence.into();
+        self
+    }
+
     pub fn add_addresses(mut self, addresses: Vec<Multiaddr>) -> Self {
         for address in addresses {
             self = self.add_address(address);
@@ -155,6 +209,8 @@ impl<'a, M: Serialize + DeserializeOwned + Clone> PayloadBuilder<'a, M> {
             self.keypair,
             self.cosigner_keypair,
             self.key,
+            self.key_epoch,
+            self.sequence,
             self.recipients,
             self.message,
             self.addresses,
@@ -182,6 +238,8 @@ where
                 self.keypair,
                 self.cosigner_keypair,
                 self.key,
+                self.key_epoch,
+                self.sequence,
                 self.recipients,
                 self.message,
                 self.addresses,
@@ -193,10 +251,13 @@ where
 
 impl<M: Serialize + DeserializeOwned + Clone> PayloadMessage<M> {
     /// Creates a new payload
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         keypair: &Keypair,
         cosigner: Option<&Keypair>,
         key: Option<Bytes>,
+        key_epoch: Option<usize>,
+        sequence: Option<u64>,
         recipients: HashSet<PeerId>,
         message: M,
         addresses: Vec<Multiaddr>,
@@ -217,6 +278,7 @@ impl<M: Serialize + DeserializeOwned + Clone> PayloadMessage<M> {
             date: Utc::now(),
             signature: Vec::new(),
             co_signature: None,
+            envelope: None,
         };
 
         if !recipients.is_empty() || key.is_some() {
@@ -264,6 +326,18 @@ impl<M: Serialize + DeserializeOwned + Clone> PayloadMessage<M> {
 
         payload.signature = signature;
 
+        let mut envelope = PayloadEnvelope {
+            key_epoch: key.as_ref().and(key_epoch),
+            sequence,
+            signature: Vec::new(),
+        };
+
+        if !envelope.is_empty() {
+            let bytes = envelope.signing_bytes(&payload.signature)?;
+            envelope.signature = keypair.sign(&bytes).expect("Valid signing");
+            payload.envelope = Some(envelope);
+        }
+
         let payload = match cosigner {
             Some(kp) if keypair.public() != kp.public() => payload.co_sign(kp)?,
             _ => payload,
@@ -291,6 +365,7 @@ impl<M: Serialize + DeserializeOwned + Clone> PayloadMessage<M> {
     #[inline]
     pub fn verify(&self) -> Result<(), Error> {
         self.verify_original()?;
+        self.verify_envelope()?;
         self.verify_cosigner()
     }
 
@@ -309,11 +384,14 @@ impl<M: Serialize + DeserializeOwned + Clone> PayloadMessage<M> {
 
         self.on_behalf = Some(sender);
 
+        let envelope = self.envelope.take();
+
         let bytes = cbor4ii::serde::to_vec(Vec::new(), &self).map_err(std::io::Error::other)?;
 
         let signature = keypair.sign(&bytes).expect("Valid signing");
 
         self.co_signature = Some(signature);
+        self.envelope = envelope;
 
         Ok(self)
     }
@@ -326,6 +404,7 @@ impl<M: Serialize + DeserializeOwned + Clone> PayloadMessage<M> {
         let signature = std::mem::take(&mut payload.signature);
         payload.on_behalf.take();
         payload.co_signature.take();
+        payload.envelope.take();
 
         let bytes = cbor4ii::serde::to_vec(Vec::new(), &payload).map_err(std::io::Error::other)?;
 
@@ -338,6 +417,22 @@ impl<M: Serialize + DeserializeOwned + Clone> PayloadMessage<M> {
         Ok(())
     }
 
+    fn verify_envelope(&self) -> Result<(), Error> {
+        let Some(envelope) = self.envelope.as_ref() else {
+            return Ok(());
+        };
+
+        let bytes = envelope.signing_bytes(&self.signature)?;
+
+        let public_key = self.sender.to_public_key()?;
+
+        if !public_key.verify(&bytes, &envelope.signature) {
+            return Err(Error::InvalidSignature);
+        }
+
+        Ok(())
+    }
+
     fn verify_cosigner(&self) -> Result<(), Error> {
         if self.on_behalf.is_none() && self.co_signature.is_none() {
             return Ok(());
@@ -353,6 +448,7 @@ impl<M: Serialize + DeserializeOwned + Clone> PayloadMessage<M> {
 
         let mut payload = self.clone();
         payload.co_signature.take();
+        payload.envelope.take();
 
         let bytes = cbor4ii::serde::to_vec(Vec::new(), &payload).map_err(std::io::Error::other)?;
 
@@ -440,6 +536,22 @@ impl<M> PayloadMessage<M> {
     pub fn addresses(&self) -> &[Multiaddr] {
         &self.addresses
     }
+
+    /// Epoch of the key used to encrypt the message, if the message was encrypted with a custom key
+    #[inline]
+    pub fn key_epoch(&self) -> Option<usize> {
+        self.envelope
+            .as_ref()
+            .and_then(|envelope| envelope.key_epoch)
+    }
+
+    /// Sequence number of the payload among the payloads of the sender, if set
+    #[inline]
+    pub fn sequence(&self) -> Option<u64> {
+        self.envelope
+            .as_ref()
+            .and_then(|envelope| envelope.sequence)
+    }
 }
 
 #[cfg(test)]
@@ -619,4 +731,87 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn payload_key_epoch() -> anyhow::Result<()> {
+        let data = String::from("Request");
+        let key = generate::<32>().to_vec();
+        let keypair = Keypair::generate_ed25519();
+
+        let payload = PayloadBuilder::new(&keypair, data)
+            .set_key(key.clone())
+            .set_key_epoch(3)
+            .build()?;
+
+        let bytes = payload.to_bytes()?;
+        let de_payload: PayloadMessage<String> = PayloadMessage::from_bytes(&bytes)?;
+        assert_eq!(de_payload.key_epoch(), Some(3));
+        assert_eq!(de_payload.message_from_key(&key)?, "Request");
+
+        // the epoch is only kept when a custom key is used
+        let payload = PayloadBuilder::new(&keypair, String::from("Request"))
+            .set_key_epoch(3)
+            .build()?;
+        assert_eq!(payload.key_epoch(), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn payload_envelope_is_not_required_by_older_peers() -> anyhow::Result<()> {
+        let key = generate::<32>().to_vec();
+        let keypair = Keypair::generate_ed25519();
+        let cosigner_keypair = Keypair::generate_ed25519();
+
+        let payload = PayloadBuilder::new(&keypair, String::from("Request"))
+            .cosign(&cosigner_keypair)
+            .set_key(key.clone())
+            .set_key_epoch(3)
+            .set_sequence(7)
+            .build()?;
+
+        let bytes = payload.to_bytes()?;
+        let de_payload: PayloadMessage<String> = PayloadMessage::from_bytes(&bytes)?;
+        assert_eq!(de_payload.key_epoch(), Some(3));
+        assert_eq!(de_payload.sequence(), Some(7));
+
+        // older peers drop the envelope as they do not know of it
+        let mut legacy: PayloadMessage<String> = cbor4ii::serde::from_slice(&bytes)?;
+        legacy.envelope.take();
+        legacy.verify()?;
+        assert_eq!(legacy.key_epoch(), None);
+        assert_eq!(legacy.sequence(), None);
+        assert_eq!(legacy.message_from_key(&key)?, "Request");
+
+        Ok(())
+    }
+
+    #[test]
+    fn payload_envelope_cannot_be_altered() -> anyhow::Result<()> {
+        let key = generate::<32>().to_vec();
+        let keypair = Keypair::generate_ed25519();
+
+        let payload = PayloadBuilder::new(&keypair, String::from("Request"))
+            .set_key(key)
+            .set_key_epoch(3)
+            .set_sequence(5)
+            .build()?;
+
+        let mut altered = payload.clone();
+        if let Some(envelope) = altered.envelope.as_mut() {
+            envelope.key_epoch = Some(2);
+        }
+        assert!(altered.verify().is_err());
+
+        let mut altered = payload.clone();
+        if let Some(envelope) = altered.envelope.as_mut() {
+            envelope.sequence = Some(1);
+        }
+        assert!(altered.verify().is_err());
+
+        let bytes = altered.to_bytes()?;
+        assert!(PayloadMessage::<String>::from_bytes(&bytes).is_err());
+
+        Ok(())
+    }
 }
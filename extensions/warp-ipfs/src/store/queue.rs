@@ -1,8 +1,10 @@
 use futures::{channel::mpsc, StreamExt, TryFutureExt};
 
+use crate::rt;
 use crate::store::{
     ds_key::DataStoreKey, ecdh_encrypt, payload::PayloadBuilder, topics::PeerTopic, PeerIdExt,
 };
+use crate::tasks::TaskTracker;
 use async_rt::AbortableJoinHandle;
 use ipld_core::cid::Cid;
 use rust_ipfs::{Ipfs, Keypair};
@@ -26,7 +28,12 @@ pub struct Queue {
 }
 
 impl Queue {
-    pub fn new(ipfs: Ipfs, root: &RootDocumentMap, discovery: Discovery) -> Queue {
+    pub fn new(
+        ipfs: Ipfs,
+        root: &RootDocumentMap,
+        discovery: Discovery,
+        tasks: &TaskTracker,
+    ) -> Queue {
         let (tx, mut rx) = mpsc::unbounded();
         let keypair = root.keypair().clone();
         let queue = Queue {
@@ -37,7 +44,7 @@ impl Queue {
             discovery,
         };
 
-        async_rt::task::dispatch({
+        tasks.spawn({
             let queue = queue.clone();
 
             async move {
@@ -324,7 +331,7 @@ impl QueueEntry {
             }
         };
 
-        let _handle = async_rt::task::spawn_abortable(fut);
+        let _handle = rt::spawn_abortable(fut);
         unsafe {
             entry.drop_guard.replace(_handle);
         }
@@ -0,0 +1,315 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use async_rt::AbortableJoinHandle;
+use bytes::Bytes;
+use futures::{
+    channel::{mpsc, oneshot},
+    stream::{BoxStream, FuturesUnordered},
+    SinkExt, StreamExt,
+};
+use futures_timeout::TimeoutExt;
+use parking_lot::RwLock;
+use rust_ipfs::{libp2p::request_response::InboundRequestId, Ipfs, PeerId};
+use serde::{Deserialize, Serialize};
+use tracing::{Instrument, Span};
+use warp::{crypto::DID, error::Error};
+
+use super::{
+    identity::IdentityStore,
+    payload::{PayloadBuilder, PayloadMessage},
+    protocols, DidExt, PeerIdExt, MAX_RPC_NAMESPACE_LENGTH, MAX_RPC_PAYLOAD_SIZE,
+};
+use crate::rpc::{RpcRequest, RpcRequestStream};
+use crate::rt;
+
+/// Duration to wait for the local subscriber to answer an incoming request
+const RPC_RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RpcRequestMessage {
+    namespace: String,
+    data: Bytes,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum RpcResponseMessage {
+    Ok { data: Bytes },
+    Rejected { reason: String },
+    Unavailable,
+    Unauthorized,
+    InvalidPayload,
+}
+
+type Handlers = Arc<RwLock<HashMap<String, mpsc::Sender<RpcRequest>>>>;
+
+#[derive(Clone)]
+pub struct RpcStore {
+    ipfs: Ipfs,
+    identity: IdentityStore,
+    handlers: Handlers,
+    _handle: AbortableJoinHandle<()>,
+}
+
+impl RpcStore {
+    pub async fn new(ipfs: &Ipfs, identity: &IdentityStore, span: &Span) -> Result<Self, Error> {
+        // The node of a replica does not support request-response
+        let stream = match identity.is_replica() {
+            true => futures::stream::pending().boxed(),
+            false => ipfs
+                .requests_subscribe(protocols::PEER_RPC_PROTOCOL)
+                .await?
+                .boxed(),
+        };
+
+        let handlers = Handlers::default();
+
+        let task = RpcTask {
+            ipfs: ipfs.clone(),
+            identity: identity.clone(),
+            handlers: handlers.clone(),
+        };
+
+        let span = span.clone();
+
+        let _handle = rt::spawn_abortable(async move { task.run(stream).await }.instrument(span));
+
+        Ok(Self {
+            ipfs: ipfs.clone(),
+            identity: identity.clone(),
+            handlers,
+            _handle,
+        })
+    }
+}
+
+impl RpcStore {
+    pub async fn request(
+        &self,
+        did: &DID,
+        namespace: &str,
+        payload: Bytes,
+    ) -> Result<Bytes, Error> {
+        validate_request(namespace, &payload)?;
+
+        if self.identity.is_blocked(did).await? {
+            return Err(Error::PublicKeyIsBlocked);
+        }
+
+        if self.identity.is_blocked_by(did).await? {
+            return Err(Error::BlockedByUser);
+        }
+
+        if !self.identity.is_friend(did).await? {
+            return Err(Error::FriendDoesntExist);
+        }
+
+        let keypair = self.identity.root_document().keypair();
+
+        let message = RpcRequestMessage {
+            namespace: namespace.to_string(),
+            data: payload,
+        };
+
+        let payload = PayloadBuilder::new(keypair, message)
+            .add_recipient(did)?
+            .build()?;
+
+        let bytes = payload.to_bytes()?;
+
+        let peer_id = did.to_peer_id()?;
+
+        let response = self
+            .ipfs
+            .send_request(peer_id, (protocols::PEER_RPC_PROTOCOL, bytes))
+            .await
+            .map_err(anyhow::Error::from)?;
+
+        let payload: PayloadMessage<RpcResponseMessage> = PayloadMessage::from_bytes(&response)?;
+
+        if payload.sender() != &peer_id {
+            return Err(Error::SenderMismatch);
+        }
+
+        match payload.message(keypair)? {
+            RpcResponseMessage::Ok { data } => {
+                if data.len() > MAX_RPC_PAYLOAD_SIZE {
+                    return Err(Error::InvalidLength {
+                        context: "payload".into(),
+                        current: data.len(),
+                        minimum: None,
+                        maximum: Some(MAX_RPC_PAYLOAD_SIZE),
+                    });
+                }
+                Ok(data)
+            }
+            RpcResponseMessage::Rejected { reason } => Err(Error::OtherWithContext(reason)),
+            RpcResponseMessage::Unavailable => Err(Error::OtherWithContext(format!(
+                "{did} is not accepting requests for \"{namespace}\""
+            ))),
+            RpcResponseMessage::Unauthorized => Err(Error::Unauthorized),
+            RpcResponseMessage::InvalidPayload => Err(Error::InvalidDataType),
+        }
+    }
+
+    pub fn subscribe(&self, namespace: &str) -> Result<RpcRequestStream, Error> {
+        validate_namespace(namespace)?;
+
+        let mut handlers = self.handlers.write();
+
+        if let Some(sender) = handlers.get(namespace) {
+            if !sender.is_closed() {
+                return Err(Error::AlreadySubscribed);
+            }
+        }
+
+        let (tx, rx) = mpsc::channel(64);
+
+        handlers.insert(namespace.to_string(), tx);
+
+        Ok(rx.boxed())
+    }
+}
+
+struct RpcTask {
+    ipfs: Ipfs,
+    identity: IdentityStore,
+    handlers: Handlers,
+}
+
+impl RpcTask {
+    async fn run(self, mut stream: BoxStream<'static, (PeerId, InboundRequestId, Bytes)>) {
+        let mut pending = FuturesUnordered::new();
+        loop {
+            tokio::select! {
+                Some((peer_id, id, request)) = stream.next() => {
+                    pending.push(self.process_request(peer_id, id, request));
+                }
+                Some(_) = pending.next() => {}
+                else => break,
+            }
+        }
+    }
+
+    async fn process_request(&self, peer_id: PeerId, id: InboundRequestId, request: Bytes) {
+        let keypair = self.identity.root_document().keypair();
+
+        let response = match self.handle_request(peer_id, request).await {
+            Ok(response) => response,
+            Err(e) => {
+                tracing::warn!(%peer_id, error = %e, "unable to process rpc request");
+                RpcResponseMessage::InvalidPayload
+            }
+        };
+
+        let builder = PayloadBuilder::new(keypair, response);
+
+        // Note: We only encrypt the response if we are able to resolve the peer to an identity. Otherwise
+        //       the response should not contain any sensitive information
+        let payload = match peer_id.to_did() {
+            Ok(did) => builder
+                .add_recipient(&did)
+                .and_then(|builder| builder.build()),
+            Err(_) => builder.build(),
+        };
+
+        let bytes = match payload.and_then(|payload| payload.to_bytes()) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                tracing::error!(%peer_id, error = %e, "unable to construct rpc response");
+                return;
+            }
+        };
+
+        if let Err(e) = self
+            .ipfs
+            .send_response(peer_id, id, (protocols::PEER_RPC_PROTOCOL, bytes))
+            .await
+        {
+            tracing::warn!(%peer_id, error = %e, "unable to send rpc response");
+        }
+    }
+
+    async fn handle_request(
+        &self,
+        peer_id: PeerId,
+        request: Bytes,
+    ) -> Result<RpcResponseMessage, Error> {
+        let payload: PayloadMessage<RpcRequestMessage> = PayloadMessage::from_bytes(&request)?;
+
+        if payload.sender() != &peer_id {
+            return Err(Error::SenderMismatch);
+        }
+
+        let sender = payload.sender().to_did()?;
+
+        if self.identity.is_blocked(&sender).await? || !self.identity.is_friend(&sender).await? {
+            return Ok(RpcResponseMessage::Unauthorized);
+        }
+
+        let keypair = self.identity.root_document().keypair();
+
+        let RpcRequestMessage { namespace, data } = payload.message(keypair)?;
+
+        validate_request(&namespace, &data)?;
+
+        let handler = self.handlers.read().get(&namespace).cloned();
+
+        let Some(mut handler) = handler else {
+            return Ok(RpcResponseMessage::Unavailable);
+        };
+
+        let (tx, rx) = oneshot::channel();
+
+        let request = RpcRequest::new(sender, namespace.clone(), data, tx);
+
+        if handler.send(request).await.is_err() {
+            self.handlers.write().remove(&namespace);
+            return Ok(RpcResponseMessage::Unavailable);
+        }
+
+        let response = match rx.timeout(RPC_RESPONSE_TIMEOUT).await {
+            Ok(Ok(Ok(data))) if data.len() > MAX_RPC_PAYLOAD_SIZE => RpcResponseMessage::Rejected {
+                reason: "response exceeded the maximum payload size".into(),
+            },
+            Ok(Ok(Ok(data))) => RpcResponseMessage::Ok { data },
+            Ok(Ok(Err(reason))) => RpcResponseMessage::Rejected { reason },
+            Ok(Err(_)) => RpcResponseMessage::Rejected {
+                reason: "request was dropped".into(),
+            },
+            Err(_) => RpcResponseMessage::Rejected {
+                reason: "request timed out".into(),
+            },
+        };
+
+        Ok(response)
+    }
+}
+
+fn validate_namespace(namespace: &str) -> Result<(), Error> {
+    let namespace = namespace.trim();
+    if namespace.is_empty() || namespace.len() > MAX_RPC_NAMESPACE_LENGTH {
+        return Err(Error::InvalidLength {
+            context: "namespace".into(),
+            current: namespace.len(),
+            minimum: Some(1),
+            maximum: Some(MAX_RPC_NAMESPACE_LENGTH),
+        });
+    }
+    Ok(())
+}
+
+fn validate_request(namespace: &str, payload: &[u8]) -> Result<(), Error> {
+    validate_namespace(namespace)?;
+
+    if payload.len() > MAX_RPC_PAYLOAD_SIZE {
+        return Err(Error::InvalidLength {
+            context: "payload".into(),
+            current: payload.len(),
+            minimum: None,
+            maximum: Some(MAX_RPC_PAYLOAD_SIZE),
+        });
+    }
+
+    Ok(())
+}
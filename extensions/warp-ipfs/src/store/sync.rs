@@ -0,0 +1,214 @@
+//! Selective sync settings of the device.
+//!
+//! Settings are kept in the datastore of the node rather than the root document, so each device of an account can
+//! exclude different directories and conversations. The content of files within an excluded directory is not kept
+//! pinned, leaving the file in the index as a placeholder that is fetched from the network when read or when
+//! hydrated explicitly. Directories are excluded by path, and the exclusion is moved along with the directory when
+//! it is renamed. Excluded conversations are not loaded on startup and only hydrate once they are accessed.
+use std::{collections::BTreeSet, sync::Arc};
+
+use parking_lot::RwLock;
+use rust_ipfs::Ipfs;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use warp::error::Error;
+
+use super::ds_key::DataStoreKey;
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SelectiveSyncSettings {
+    /// Paths of directories whose files are not kept locally
+    pub excluded_directories: BTreeSet<String>,
+    /// Conversations that are only loaded on demand
+    pub excluded_conversations: BTreeSet<Uuid>,
+}
+
+impl SelectiveSyncSettings {
+    /// Returns true if `path`, or any directory it is in, is excluded
+    pub fn is_directory_excluded(&self, path: &str) -> bool {
+        let path = normalize_path(path);
+        self.excluded_directories.iter().any(|excluded| {
+            path == *excluded
+                || path
+                    .strip_prefix(excluded.as_str())
+                    .is_some_and(|rest| rest.starts_with('/'))
+        })
+    }
+
+    pub fn is_conversation_excluded(&self, conversation_id: Uuid) -> bool {
+        self.excluded_conversations.contains(&conversation_id)
+    }
+
+    fn rename_directory(&mut self, from: &str, to: &str) -> bool {
+        let from = normalize_path(from);
+        let to = normalize_path(to);
+
+        let renamed = self
+            .excluded_directories
+            .iter()
+            .filter_map(|excluded| match excluded.strip_prefix(from.as_str()) {
+                Some("") => Some((excluded.clone(), to.clone())),
+                Some(rest) if rest.starts_with('/') => {
+                    Some((excluded.clone(), format!("{to}{rest}")))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        for (excluded, path) in &renamed {
+            self.excluded_directories.remove(excluded);
+            self.excluded_directories.insert(path.clone());
+        }
+
+        !renamed.is_empty()
+    }
+}
+
+#[derive(Clone)]
+pub struct SelectiveSync {
+    ipfs: Ipfs,
+    settings: Arc<RwLock<SelectiveSyncSettings>>,
+}
+
+impl SelectiveSync {
+    pub async fn new(ipfs: &Ipfs) -> Self {
+        let key = ipfs.selective_sync();
+
+        let settings = ipfs
+            .repo()
+            .data_store()
+            .get(key.as_bytes())
+            .await
+            .unwrap_or_default()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+
+        Self {
+            ipfs: ipfs.clone(),
+            settings: Arc::new(RwLock::new(settings)),
+        }
+    }
+
+    pub fn settings(&self) -> SelectiveSyncSettings {
+        self.settings.read().clone()
+    }
+
+    pub fn is_directory_excluded(&self, path: &str) -> bool {
+        self.settings.read().is_directory_excluded(path)
+    }
+
+    pub fn is_conversation_excluded(&self, conversation_id: Uuid) -> bool {
+        self.settings
+            .read()
+            .is_conversation_excluded(conversation_id)
+    }
+
+    /// Exclude or include the directory at `path`, returning true if the settings changed
+    pub async fn set_directory_excluded(&self, path: &str, excluded: bool) -> Result<bool, Error> {
+        let path = normalize_path(path);
+
+        if path.is_empty() {
+            return Err(Error::InvalidDirectory);
+        }
+
+        let changed = {
+            let settings = &mut *self.settings.write();
+            match excluded {
+                true => settings.excluded_directories.insert(path),
+                false => settings.excluded_directories.remove(&path),
+            }
+        };
+
+        if changed {
+            self.save().await?;
+        }
+
+        Ok(changed)
+    }
+
+    /// Move the exclusion of the directory at `from`, and of any directory within it, to `to` once the directory
+    /// was renamed, returning true if the settings changed
+    pub async fn rename_directory(&self, from: &str, to: &str) -> Result<bool, Error> {
+        let changed = self.settings.write().rename_directory(from, to);
+
+        if changed {
+            self.save().await?;
+        }
+
+        Ok(changed)
+    }
+
+    /// Exclude or include the conversation, returning true if the settings changed
+    pub async fn set_conversation_excluded(
+        &self,
+        conversation_id: Uuid,
+        excluded: bool,
+    ) -> Result<bool, Error> {
+        let changed = {
+            let settings = &mut *self.settings.write();
+            match excluded {
+                true => settings.excluded_conversations.insert(conversation_id),
+                false => settings.excluded_conversations.remove(&conversation_id),
+            }
+        };
+
+        if changed {
+            self.save().await?;
+        }
+
+        Ok(changed)
+    }
+
+    async fn save(&self) -> Result<(), Error> {
+        let key = self.ipfs.selective_sync();
+        let bytes = serde_json::to_vec(&self.settings())?;
+
+        self.ipfs
+            .repo()
+            .data_store()
+            .put(key.as_bytes(), &bytes)
+            .await
+            .map_err(anyhow::Error::from)?;
+
+        Ok(())
+    }
+}
+
+pub fn normalize_path(path: &str) -> String {
+    path.trim().replace('\\', "/").trim_matches('/').to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::SelectiveSyncSettings;
+
+    #[test]
+    fn subdirectories_of_excluded_directory_are_excluded() {
+        let mut settings = SelectiveSyncSettings::default();
+        settings.excluded_directories.insert("videos".into());
+
+        assert!(settings.is_directory_excluded("videos"));
+        assert!(settings.is_directory_excluded("/videos/2024/"));
+        assert!(!settings.is_directory_excluded("videos-archive"));
+        assert!(!settings.is_directory_excluded("photos"));
+    }
+
+    #[test]
+    fn exclusions_follow_renamed_directory() {
+        let mut settings = SelectiveSyncSettings::default();
+        settings.excluded_directories.insert("media/videos".into());
+        settings
+            .excluded_directories
+            .insert("media/music/live".into());
+        settings.excluded_directories.insert("media-archive".into());
+
+        assert!(settings.rename_directory("/media/", "library"));
+
+        assert!(settings.is_directory_excluded("library/videos"));
+        assert!(settings.is_directory_excluded("library/music/live"));
+        assert!(!settings.is_directory_excluded("media/videos"));
+        assert!(settings.is_directory_excluded("media-archive"));
+
+        assert!(!settings.rename_directory("photos", "pictures"));
+    }
+}
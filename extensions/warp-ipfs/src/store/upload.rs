@@ -0,0 +1,431 @@
+//! Resumable uploads to constellation.
+//!
+//! The data of a resumable upload is split into chunks of [`UPLOAD_CHUNK_SIZE`], each of which is added to the repo
+//! as its own unixfs dag and pinned. The cid of every confirmed chunk is recorded in the datastore, so an upload that
+//! was interrupted, including by the application being restarted, continues from the end of the last confirmed chunk.
+//! Once every chunk is confirmed, the chunks are read back in order into a regular upload of the file, and are then
+//! unpinned. As the chunk size is a multiple of the unixfs chunker size, the blocks of the file are the same as the
+//! blocks of the chunks and are not stored twice.
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::Arc,
+};
+
+use bytes::{Bytes, BytesMut};
+use chrono::{DateTime, Utc};
+use futures::{stream::BoxStream, StreamExt, TryStreamExt};
+use ipld_core::cid::Cid;
+use parking_lot::RwLock;
+use rust_ipfs::{Ipfs, IpfsPath};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use warp::{
+    constellation::{ConstellationProgressStream, Progression, UploadProgress, UploadSession},
+    error::Error,
+};
+
+use super::{ds_key::DataStoreKey, files::FileStore};
+
+/// Size of the chunks of a resumable upload. This is a multiple of the default unixfs chunker size
+pub const UPLOAD_CHUNK_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct UploadState {
+    id: Uuid,
+    name: String,
+    total_size: usize,
+    /// Cid of each confirmed chunk, in order
+    chunks: Vec<String>,
+    created: DateTime<Utc>,
+}
+
+impl UploadState {
+    fn confirmed(&self) -> usize {
+        (self.chunks.len() * UPLOAD_CHUNK_SIZE).min(self.total_size)
+    }
+
+    fn progress(&self) -> UploadProgress {
+        UploadProgress {
+            confirmed: self.confirmed(),
+            total: self.total_size,
+        }
+    }
+
+    fn chunks(&self) -> Result<Vec<Cid>, Error> {
+        self.chunks
+            .iter()
+            .map(|cid| {
+                cid.parse::<Cid>()
+                    .map_err(anyhow::Error::from)
+                    .map_err(Error::from)
+            })
+            .collect()
+    }
+}
+
+/// Uploads that were not completed, kept in the datastore
+#[derive(Clone)]
+pub struct UploadSessions {
+    ipfs: Ipfs,
+    sessions: Arc<tokio::sync::Mutex<BTreeMap<Uuid, UploadState>>>,
+    /// Sessions with data currently being uploaded
+    active: Arc<parking_lot::Mutex<HashSet<Uuid>>>,
+}
+
+impl UploadSessions {
+    pub async fn load(ipfs: &Ipfs) -> Self {
+        let key = ipfs.upload_sessions();
+
+        let sessions = ipfs
+            .repo()
+            .data_store()
+            .get(key.as_bytes())
+            .await
+            .unwrap_or_default()
+            .and_then(|bytes| serde_json::from_slice::<BTreeMap<Uuid, UploadState>>(&bytes).ok())
+            .unwrap_or_default();
+
+        Self {
+            ipfs: ipfs.clone(),
+            sessions: Arc::new(tokio::sync::Mutex::new(sessions)),
+            active: Arc::default(),
+        }
+    }
+
+    pub async fn list(&self) -> Vec<Uuid> {
+        self.sessions.lock().await.keys().copied().collect()
+    }
+
+    /// Storage reserved by the uploads that were not completed
+    pub async fn reserved(&self) -> usize {
+        self.sessions
+            .lock()
+            .await
+            .values()
+            .map(|state| state.total_size)
+            .sum()
+    }
+
+    pub async fn create(
+        &self,
+        file_store: &FileStore,
+        name: &str,
+        total_size: usize,
+    ) -> Result<ResumableUpload, Error> {
+        let state = UploadState {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            total_size,
+            chunks: vec![],
+            created: Utc::now(),
+        };
+
+        let mut sessions = self.sessions.lock().await;
+        sessions.insert(state.id, state.clone());
+        self.save(&sessions).await?;
+
+        Ok(ResumableUpload {
+            state: Arc::new(RwLock::new(state)),
+            sessions: self.clone(),
+            file_store: file_store.clone(),
+        })
+    }
+
+    pub async fn open(&self, file_store: &FileStore, id: Uuid) -> Result<ResumableUpload, Error> {
+        let state = self
+            .sessions
+            .lock()
+            .await
+            .get(&id)
+            .cloned()
+            .ok_or(Error::ObjectNotFound)?;
+
+        Ok(ResumableUpload {
+            state: Arc::new(RwLock::new(state)),
+            sessions: self.clone(),
+            file_store: file_store.clone(),
+        })
+    }
+
+    /// Remove the session and unpin the chunks that were stored
+    pub async fn remove(&self, id: Uuid) -> Result<(), Error> {
+        if self.active.lock().contains(&id) {
+            return Err(Error::OtherWithContext(
+                "Upload is currently in progress".into(),
+            ));
+        }
+
+        let mut sessions = self.sessions.lock().await;
+        let state = sessions.remove(&id).ok_or(Error::ObjectNotFound)?;
+        self.save(&sessions).await?;
+        drop(sessions);
+
+        for cid in state.chunks()? {
+            if let Err(e) = self.ipfs.remove_pin(cid).recursive().await {
+                tracing::warn!(%cid, error = %e, "unable to unpin chunk of upload");
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn update(&self, state: &UploadState) -> Result<(), Error> {
+        let mut sessions = self.sessions.lock().await;
+        sessions.insert(state.id, state.clone());
+        self.save(&sessions).await
+    }
+
+    async fn save(&self, sessions: &BTreeMap<Uuid, UploadState>) -> Result<(), Error> {
+        let key = self.ipfs.upload_sessions();
+        let bytes = serde_json::to_vec(sessions)?;
+        self.ipfs
+            .repo()
+            .data_store()
+            .put(key.as_bytes(), &bytes)
+            .await
+            .map_err(anyhow::Error::from)?;
+        Ok(())
+    }
+
+    fn acquire(&self, id: Uuid) -> Option<ActiveUpload> {
+        match self.active.lock().insert(id) {
+            true => Some(ActiveUpload {
+                id,
+                active: self.active.clone(),
+            }),
+            false => None,
+        }
+    }
+}
+
+/// Marks a session as being uploaded for as long as it is held
+struct ActiveUpload {
+    id: Uuid,
+    active: Arc<parking_lot::Mutex<HashSet<Uuid>>>,
+}
+
+impl Drop for ActiveUpload {
+    fn drop(&mut self) {
+        self.active.lock().remove(&self.id);
+    }
+}
+
+pub struct ResumableUpload {
+    state: Arc<RwLock<UploadState>>,
+    sessions: UploadSessions,
+    file_store: FileStore,
+}
+
+#[async_trait::async_trait]
+impl UploadSession for ResumableUpload {
+    fn id(&self) -> Uuid {
+        self.state.read().id
+    }
+
+    fn name(&self) -> String {
+        self.state.read().name.clone()
+    }
+
+    fn progress(&self) -> UploadProgress {
+        self.state.read().progress()
+    }
+
+    async fn resume(
+        &mut self,
+        stream: BoxStream<'static, std::io::Result<Bytes>>,
+    ) -> Result<ConstellationProgressStream, Error> {
+        let id = self.id();
+
+        let active = self
+            .sessions
+            .acquire(id)
+            .ok_or_else(|| Error::OtherWithContext("Upload is already in progress".into()))?;
+
+        // the session may have progressed, been completed or been cancelled through another handle
+        let current = self
+            .sessions
+            .sessions
+            .lock()
+            .await
+            .get(&id)
+            .cloned()
+            .ok_or(Error::ObjectNotFound)?;
+        *self.state.write() = current;
+
+        let ipfs = self.sessions.ipfs.clone();
+        let sessions = self.sessions.clone();
+        let state = self.state.clone();
+        let mut file_store = self.file_store.clone();
+
+        let progress_stream = async_stream::stream! {
+            let mut stream = stream;
+            let (name, total_size) = {
+                let state = state.read();
+                (state.name.clone(), state.total_size)
+            };
+
+            let mut buffer = BytesMut::new();
+
+            loop {
+                let confirmed = state.read().confirmed();
+                if confirmed == total_size {
+                    break;
+                }
+
+                let remaining = total_size - confirmed;
+                let chunk_size = remaining.min(UPLOAD_CHUNK_SIZE);
+
+                while buffer.len() < chunk_size {
+                    match stream.next().await {
+                        Some(Ok(bytes)) => buffer.extend_from_slice(&bytes),
+                        Some(Err(e)) => {
+                            yield Progression::ProgressFailed {
+                                name,
+                                last_size: Some(confirmed),
+                                error: e.into(),
+                            };
+                            return;
+                        }
+                        None => break,
+                    }
+                }
+
+                if buffer.len() > remaining {
+                    yield Progression::ProgressFailed {
+                        name,
+                        last_size: Some(confirmed),
+                        error: Error::InvalidLength {
+                            context: "stream".into(),
+                            current: confirmed + buffer.len(),
+                            minimum: None,
+                            maximum: Some(total_size),
+                        },
+                    };
+                    return;
+                }
+
+                if buffer.len() < chunk_size {
+                    yield Progression::ProgressFailed {
+                        name,
+                        last_size: Some(confirmed),
+                        error: Error::OtherWithContext(format!(
+                            "Stream ended before the upload was complete. Resume from offset {confirmed}"
+                        )),
+                    };
+                    return;
+                }
+
+                let chunk = buffer.split_to(chunk_size).freeze();
+
+                let cid = match ipfs.add_unixfs(chunk).await {
+                    Ok(path) => *path.root().cid().expect("valid cid"),
+                    Err(e) => {
+                        yield Progression::ProgressFailed {
+                            name,
+                            last_size: Some(confirmed),
+                            error: e.into(),
+                        };
+                        return;
+                    }
+                };
+
+                let snapshot = {
+                    let mut state = state.write();
+                    state.chunks.push(cid.to_string());
+                    state.clone()
+                };
+
+                if let Err(e) = sessions.update(&snapshot).await {
+                    yield Progression::ProgressFailed {
+                        name,
+                        last_size: Some(confirmed),
+                        error: e,
+                    };
+                    return;
+                }
+
+                yield Progression::CurrentProgress {
+                    name: name.clone(),
+                    current: snapshot.confirmed(),
+                    total: Some(total_size),
+                };
+            }
+
+            let chunks = match state.read().chunks() {
+                Ok(chunks) => chunks,
+                Err(e) => {
+                    yield Progression::ProgressFailed {
+                        name,
+                        last_size: Some(total_size),
+                        error: e,
+                    };
+                    return;
+                }
+            };
+
+            let reader = {
+                let ipfs = ipfs.clone();
+                futures::stream::iter(chunks)
+                    .flat_map(move |cid| {
+                        ipfs.cat_unixfs(IpfsPath::from(cid))
+                            .map_err(std::io::Error::other)
+                    })
+                    .boxed()
+            };
+
+            let mut upload = match file_store.put_stream(&name, Some(total_size), reader).await {
+                Ok(upload) => upload,
+                Err(e) => {
+                    yield Progression::ProgressFailed {
+                        name,
+                        last_size: Some(total_size),
+                        error: e,
+                    };
+                    return;
+                }
+            };
+
+            while let Some(progress) = upload.next().await {
+                if let Progression::ProgressComplete { .. } = progress {
+                    drop(active);
+                    if let Err(e) = sessions.remove(id).await {
+                        tracing::warn!(%id, error = %e, "unable to remove completed upload session");
+                    }
+                    yield progress;
+                    return;
+                }
+                yield progress;
+            }
+        };
+
+        Ok(progress_stream.boxed())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{UploadState, UPLOAD_CHUNK_SIZE};
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    #[test]
+    fn confirmed_offset_is_clamped_to_the_last_chunk() {
+        let mut state = UploadState {
+            id: Uuid::new_v4(),
+            name: "video.mp4".into(),
+            total_size: UPLOAD_CHUNK_SIZE * 2 + 10,
+            chunks: vec![],
+            created: Utc::now(),
+        };
+        assert_eq!(state.confirmed(), 0);
+
+        state.chunks.push("chunk".into());
+        state.chunks.push("chunk".into());
+        assert_eq!(state.confirmed(), UPLOAD_CHUNK_SIZE * 2);
+        assert!(!state.progress().is_complete());
+
+        state.chunks.push("chunk".into());
+        assert_eq!(state.confirmed(), state.total_size);
+        assert!(state.progress().is_complete());
+    }
+}
@@ -0,0 +1,156 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::Utc;
+use parking_lot::RwLock;
+use uuid::Uuid;
+use warp::crypto::DID;
+
+use crate::usage::{DataUsage, UsageCategory};
+
+#[derive(Default)]
+struct UsagePeriods {
+    current: DataUsage,
+    previous: Option<DataUsage>,
+}
+
+/// Shared tracker of the data sent and received by the stores
+#[derive(Clone, Default)]
+pub struct UsageTracker {
+    periods: Arc<RwLock<UsagePeriods>>,
+    period: Option<Duration>,
+}
+
+impl UsageTracker {
+    pub fn new(period: Option<Duration>) -> Self {
+        Self {
+            periods: Arc::default(),
+            period,
+        }
+    }
+
+    pub fn record_sent<'a>(
+        &self,
+        category: UsageCategory,
+        conversation_id: Option<Uuid>,
+        peers: impl IntoIterator<Item = &'a DID>,
+        bytes: usize,
+    ) {
+        self.record(category, conversation_id, peers, true, bytes);
+    }
+
+    pub fn record_received<'a>(
+        &self,
+        category: UsageCategory,
+        conversation_id: Option<Uuid>,
+        peers: impl IntoIterator<Item = &'a DID>,
+        bytes: usize,
+    ) {
+        self.record(category, conversation_id, peers, false, bytes);
+    }
+
+    fn record<'a>(
+        &self,
+        category: UsageCategory,
+        conversation_id: Option<Uuid>,
+        peers: impl IntoIterator<Item = &'a DID>,
+        sent: bool,
+        bytes: usize,
+    ) {
+        let periods = &mut *self.periods.write();
+        self.rollover(periods);
+        periods
+            .current
+            .record(category, conversation_id, peers, sent, bytes);
+    }
+
+    fn rollover(&self, periods: &mut UsagePeriods) {
+        let Some(period) = self
+            .period
+            .and_then(|period| chrono::Duration::from_std(period).ok())
+        else {
+            return;
+        };
+
+        if Utc::now() - periods.current.since() < period {
+            return;
+        }
+
+        Self::complete(periods);
+    }
+
+    fn complete(periods: &mut UsagePeriods) -> DataUsage {
+        let mut completed = std::mem::take(&mut periods.current);
+        completed.complete(periods.current.since());
+        periods.previous = Some(completed.clone());
+        completed
+    }
+
+    pub fn current(&self) -> DataUsage {
+        let periods = &mut *self.periods.write();
+        self.rollover(periods);
+        periods.current.clone()
+    }
+
+    pub fn previous(&self) -> Option<DataUsage> {
+        let periods = &mut *self.periods.write();
+        self.rollover(periods);
+        periods.previous.clone()
+    }
+
+    pub fn reset(&self) -> DataUsage {
+        Self::complete(&mut self.periods.write())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use uuid::Uuid;
+    use warp::crypto::DID;
+
+    use super::UsageTracker;
+    use crate::usage::UsageCategory;
+
+    #[test]
+    fn record_and_reset() {
+        let tracker = UsageTracker::default();
+        let conversation_id = Uuid::new_v4();
+        let peer = DID::default();
+
+        tracker.record_sent(UsageCategory::Messages, Some(conversation_id), None, 100);
+        tracker.record_received(
+            UsageCategory::Messages,
+            Some(conversation_id),
+            Some(&peer),
+            50,
+        );
+        tracker.record_received(UsageCategory::IdentitySync, None, Some(&peer), 25);
+
+        let usage = tracker.current();
+        assert_eq!(usage.total().sent, 100);
+        assert_eq!(usage.total().received, 75);
+        assert_eq!(usage.category(UsageCategory::Messages).total(), 150);
+        assert_eq!(usage.conversation(conversation_id).received, 50);
+        assert_eq!(usage.peer(&peer).received, 75);
+        assert!(usage.until().is_none());
+
+        let completed = tracker.reset();
+        assert_eq!(completed.total().total(), 175);
+        assert!(completed.until().is_some());
+
+        assert_eq!(tracker.current().total().total(), 0);
+        assert_eq!(tracker.previous(), Some(completed));
+    }
+
+    #[test]
+    fn rollover_after_period() {
+        let tracker = UsageTracker::new(Some(Duration::ZERO));
+
+        tracker.record_sent(UsageCategory::Attachments, None, None, 10);
+
+        // the period has already elapsed so the usage is moved to the previous period
+        assert_eq!(tracker.current().total().total(), 0);
+        assert!(tracker.previous().is_some());
+    }
+}
@@ -0,0 +1,135 @@
+//! Registry of background tasks that are not owned by a single store, so that they can be awaited or aborted
+//! when the instance shuts down instead of outliving it.
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+
+use async_rt::AbortableJoinHandle;
+use parking_lot::Mutex;
+
+use crate::rt::{self, MaybeSend};
+
+#[derive(Clone, Default)]
+pub struct TaskTracker {
+    inner: Arc<Mutex<TaskTrackerInner>>,
+}
+
+#[derive(Default)]
+struct TaskTrackerInner {
+    next_id: u64,
+    tasks: HashMap<u64, AbortableJoinHandle<()>>,
+    closed: bool,
+}
+
+impl TaskTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Spawn a task that is tracked until it completes. The output of the task is discarded.
+    /// Tasks spawned after the tracker is closed are not started.
+    pub fn spawn<F>(&self, fut: F)
+    where
+        F: Future + MaybeSend + 'static,
+        F::Output: MaybeSend + 'static,
+    {
+        let mut inner = self.inner.lock();
+        if inner.closed {
+            tracing::debug!("task tracker is closed. Ignoring task");
+            return;
+        }
+
+        let id = inner.next_id;
+        inner.next_id += 1;
+
+        let registry = Arc::downgrade(&self.inner);
+        let handle = rt::spawn_abortable(async move {
+            let _ = fut.await;
+            if let Some(registry) = registry.upgrade() {
+                registry.lock().tasks.remove(&id);
+            }
+        });
+
+        inner.tasks.insert(id, handle);
+    }
+
+    /// Amount of tracked tasks that are still running
+    #[cfg(test)]
+    pub fn running(&self) -> usize {
+        self.inner.lock().tasks.len()
+    }
+
+    #[cfg(test)]
+    pub fn is_closed(&self) -> bool {
+        self.inner.lock().closed
+    }
+
+    /// Close the tracker and wait for the tracked tasks to complete
+    #[cfg(test)]
+    pub async fn wait(&self) {
+        let tasks = self.close();
+        for handle in tasks {
+            let _ = handle.await;
+        }
+    }
+
+    /// Close the tracker, abort the tracked tasks and wait for them to end
+    pub async fn shutdown(&self) {
+        let tasks = self.close();
+        for handle in &tasks {
+            handle.abort();
+        }
+        for handle in tasks {
+            let _ = handle.await;
+        }
+    }
+
+    fn close(&self) -> Vec<AbortableJoinHandle<()>> {
+        let mut inner = self.inner.lock();
+        inner.closed = true;
+        inner.tasks.drain().map(|(_, handle)| handle).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use futures::channel::oneshot;
+
+    use super::TaskTracker;
+
+    #[tokio::test]
+    async fn completed_tasks_are_removed() {
+        let tracker = TaskTracker::new();
+        let (tx, rx) = oneshot::channel::<()>();
+        tracker.spawn(rx);
+        assert_eq!(tracker.running(), 1);
+
+        tx.send(()).expect("task is running");
+        tracker.wait().await;
+        assert_eq!(tracker.running(), 0);
+    }
+
+    #[tokio::test]
+    async fn shutdown_aborts_tasks() {
+        let tracker = TaskTracker::new();
+        let (tx, rx) = oneshot::channel::<()>();
+        tracker.spawn(async move {
+            // dropping the sender signals the task being aborted
+            let _tx = tx;
+            futures::future::pending::<()>().await
+        });
+
+        tokio::time::timeout(Duration::from_secs(5), tracker.shutdown())
+            .await
+            .expect("tasks are aborted");
+
+        assert!(rx.await.is_err());
+        assert_eq!(tracker.running(), 0);
+        assert!(tracker.is_closed());
+
+        tracker.spawn(futures::future::pending::<()>());
+        assert_eq!(tracker.running(), 0);
+    }
+}
@@ -27,6 +27,7 @@ use tokio::sync::Mutex;
 use warp::{constellation::file::FileType, error::Error};
 use web_time::Instant;
 
+use crate::rt;
 use crate::utils::ByteCollection;
 use crate::{store::document::image_dag::ImageDag, utils::ExtensionType};
 
@@ -89,7 +90,7 @@ impl ThumbnailGenerator {
 
         let ipfs = self.ipfs.clone();
 
-        let handle = async_rt::task::spawn_abortable(async move {
+        let handle = rt::spawn_abortable(async move {
             let instance = Instant::now();
             //TODO: Read file header to determine real file type for anything like images, videos and documents.
             let extension = own_path
@@ -100,21 +101,22 @@ impl ThumbnailGenerator {
 
             let result = match extension.into() {
                 FileType::Mime(media) => match media.ty().as_str() {
-                    "image" => tokio::task::spawn_blocking(move || {
-                        let format: ImageFormat = extension.try_into()?;
-                        let file = io::BufReader::new(std::fs::File::open(own_path)?);
-                        let output_format = match (output_exact, format) {
-                            (false, _) => ImageFormat::Jpeg,
-                            (true, format) => format,
-                        };
-                        let t_buffer = generate_thumbnail(file, output_format, width, height)?;
-                        Ok::<_, Error>((
-                            ExtensionType::try_from(output_format)?,
-                            Bytes::from(t_buffer.into_inner()),
-                        ))
-                    })
-                    .await
-                    .map_err(anyhow::Error::from)?,
+                    "image" => {
+                        rt::spawn_blocking(move || {
+                            let format: ImageFormat = extension.try_into()?;
+                            let file = io::BufReader::new(std::fs::File::open(own_path)?);
+                            let output_format = match (output_exact, format) {
+                                (false, _) => ImageFormat::Jpeg,
+                                (true, format) => format,
+                            };
+                            let t_buffer = generate_thumbnail(file, output_format, width, height)?;
+                            Ok::<_, Error>((
+                                ExtensionType::try_from(output_format)?,
+                                Bytes::from(t_buffer.into_inner()),
+                            ))
+                        })
+                        .await?
+                    }
                     _ => Err(Error::Unimplemented),
                 },
                 _ => Err(Error::Other),
@@ -172,7 +174,7 @@ impl ThumbnailGenerator {
 
         let ipfs = self.ipfs.clone();
 
-        let handle = async_rt::task::spawn_abortable(async move {
+        let handle = rt::spawn_abortable(async move {
             let instant = Instant::now();
 
             let extension = name
@@ -192,7 +194,10 @@ impl ThumbnailGenerator {
                             (false, _) => ImageFormat::Jpeg,
                             (true, format) => format,
                         };
-                        let t_buffer = generate_thumbnail(cursor, output_format, width, height)?;
+                        let t_buffer = rt::spawn_blocking(move || {
+                            generate_thumbnail(cursor, output_format, width, height)
+                        })
+                        .await??;
                         Ok::<_, Error>((
                             ExtensionType::try_from(output_format)?,
                             Bytes::from(t_buffer.into_inner()),
@@ -245,7 +250,7 @@ impl ThumbnailGenerator {
 
         let ipfs = self.ipfs.clone();
 
-        let handle = async_rt::task::spawn_abortable(async move {
+        let handle = rt::spawn_abortable(async move {
             let instance = Instant::now();
 
             let extension = name
@@ -262,7 +267,10 @@ impl ThumbnailGenerator {
                             (false, _) => ImageFormat::Jpeg,
                             (true, format) => format,
                         };
-                        let t_buffer = generate_thumbnail(buffer, output_format, width, height)?;
+                        let t_buffer = rt::spawn_blocking(move || {
+                            generate_thumbnail(buffer, output_format, width, height)
+                        })
+                        .await??;
                         Ok::<_, Error>((
                             ExtensionType::try_from(output_format)?,
                             Bytes::from(t_buffer.into_inner()),
@@ -306,6 +314,26 @@ impl ThumbnailGenerator {
     }
 }
 
+/// Resize the image so it fits within `dimension` pixels while keeping its format.
+/// Returns `None` if the image already fits within the dimension
+pub fn resize_image(data: &[u8], dimension: u32) -> Result<Option<(ImageFormat, Bytes)>, Error> {
+    let reader = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .map_err(anyhow::Error::from)?;
+
+    let format = reader.format().ok_or(Error::InvalidConversion)?;
+
+    let (width, height) = reader.into_dimensions().map_err(anyhow::Error::from)?;
+
+    if width.max(height) <= dimension {
+        return Ok(None);
+    }
+
+    let t_buffer = generate_thumbnail(Cursor::new(data), format, dimension, dimension)?;
+
+    Ok(Some((format, Bytes::from(t_buffer.into_inner()))))
+}
+
 pub fn generate_thumbnail<R: BufRead + Seek>(
     data: R,
     output_format: ImageFormat,
@@ -338,3 +366,38 @@ pub fn generate_thumbnail<R: BufRead + Seek>(
     }
     Ok(t_buffer)
 }
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use image::{DynamicImage, ImageFormat, ImageReader};
+
+    use super::resize_image;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut buffer = Cursor::new(vec![]);
+        DynamicImage::new_rgb8(width, height)
+            .write_to(&mut buffer, ImageFormat::Png)
+            .unwrap();
+        buffer.into_inner()
+    }
+
+    #[test]
+    fn resize_keeps_aspect_ratio_and_format() {
+        let (format, data) = resize_image(&png(512, 256), 64).unwrap().unwrap();
+        assert_eq!(format, ImageFormat::Png);
+
+        let dimensions = ImageReader::new(Cursor::new(data))
+            .with_guessed_format()
+            .unwrap()
+            .into_dimensions()
+            .unwrap();
+        assert_eq!(dimensions, (64, 32));
+    }
+
+    #[test]
+    fn small_image_is_not_resized() {
+        assert!(resize_image(&png(32, 32), 64).unwrap().is_none());
+    }
+}
@@ -0,0 +1,144 @@
+//! Source of the current time used by the stores to expire invites, retry queued messages and drop stale payloads.
+//!
+//! The system clock is used by default. Tests can set a [`ManualClock`] in the [`Config`](crate::config::Config)
+//! and advance it instead of sleeping.
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use parking_lot::RwLock;
+use web_time::Instant;
+
+pub trait TimeSource: Send + Sync + 'static {
+    /// Current date, used for dates that are stored or compared against stored dates
+    fn now(&self) -> DateTime<Utc>;
+
+    /// Current monotonic instant, used to measure elapsed time
+    fn instant(&self) -> Instant;
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+pub struct SystemClock;
+
+impl TimeSource for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Clock that starts at the current time and only moves when it is advanced
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    inner: Arc<RwLock<ManualClockInner>>,
+}
+
+#[derive(Debug)]
+struct ManualClockInner {
+    date: DateTime<Utc>,
+    instant: Instant,
+    elapsed: Duration,
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(ManualClockInner {
+                date: Utc::now(),
+                instant: Instant::now(),
+                elapsed: Duration::ZERO,
+            })),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.inner.write().elapsed += duration;
+    }
+}
+
+impl TimeSource for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        let inner = &*self.inner.read();
+        inner.date + chrono::Duration::from_std(inner.elapsed).expect("elapsed time within range")
+    }
+
+    fn instant(&self) -> Instant {
+        let inner = &*self.inner.read();
+        inner.instant + inner.elapsed
+    }
+}
+
+/// Handle to the time source shared by the stores
+#[derive(Clone)]
+pub struct Clock {
+    source: Arc<dyn TimeSource>,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::system()
+    }
+}
+
+impl Debug for Clock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Clock").finish_non_exhaustive()
+    }
+}
+
+impl Clock {
+    pub fn new<T: TimeSource>(source: T) -> Self {
+        Self {
+            source: Arc::new(source),
+        }
+    }
+
+    pub fn system() -> Self {
+        Self::new(SystemClock)
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        self.source.now()
+    }
+
+    pub fn instant(&self) -> Instant {
+        self.source.instant()
+    }
+
+    /// Time elapsed since `earlier`, which is zero if `earlier` is later than the current instant
+    pub fn elapsed(&self, earlier: Instant) -> Duration {
+        self.instant().saturating_duration_since(earlier)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{Clock, ManualClock};
+
+    #[test]
+    fn manual_clock_only_moves_when_advanced() {
+        let manual = ManualClock::new();
+        let clock = Clock::new(manual.clone());
+
+        let date = clock.now();
+        let instant = clock.instant();
+        assert_eq!(clock.now(), date);
+        assert_eq!(clock.elapsed(instant), Duration::ZERO);
+
+        manual.advance(Duration::from_secs(90));
+        assert_eq!((clock.now() - date).num_seconds(), 90);
+        assert_eq!(clock.elapsed(instant), Duration::from_secs(90));
+    }
+}
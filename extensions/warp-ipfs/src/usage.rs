@@ -0,0 +1,179 @@
+//! Accounting of the data sent and received over the network.
+//!
+//! Usage is broken down by [`UsageCategory`], conversation and peer so applications can show how much data is
+//! being used and enforce their own limits (eg on cellular connections). Usage is kept for the current period, which
+//! can be reset manually or rolled over automatically with [`Config::set_data_usage_period`](crate::config::Config::set_data_usage_period).
+//! Transfers served by the underlying block exchange (eg peers fetching an attachment or a profile picture from us)
+//! are not accounted for.
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use warp::{
+    constellation::Constellation, crypto::DID, error::Error, multipass::MultiPass, raygun::RayGun,
+    warp::Warp,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageCategory {
+    /// Messages and events of conversations
+    Messages,
+    /// Attachments downloaded from conversations
+    Attachments,
+    /// Identity announcements, updates and requests
+    IdentitySync,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageCounter {
+    /// Bytes sent
+    pub sent: u64,
+    /// Bytes received
+    pub received: u64,
+}
+
+impl UsageCounter {
+    /// Total amount of bytes sent and received
+    pub fn total(&self) -> u64 {
+        self.sent.saturating_add(self.received)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataUsage {
+    since: DateTime<Utc>,
+    until: Option<DateTime<Utc>>,
+    categories: HashMap<UsageCategory, UsageCounter>,
+    conversations: HashMap<Uuid, UsageCounter>,
+    peers: HashMap<DID, UsageCounter>,
+}
+
+impl Default for DataUsage {
+    fn default() -> Self {
+        Self {
+            since: Utc::now(),
+            until: None,
+            categories: HashMap::new(),
+            conversations: HashMap::new(),
+            peers: HashMap::new(),
+        }
+    }
+}
+
+impl DataUsage {
+    pub(crate) fn record<'a>(
+        &mut self,
+        category: UsageCategory,
+        conversation_id: Option<Uuid>,
+        peers: impl IntoIterator<Item = &'a DID>,
+        sent: bool,
+        bytes: usize,
+    ) {
+        let bytes = bytes as u64;
+        let mut update = |counter: &mut UsageCounter| match sent {
+            true => counter.sent = counter.sent.saturating_add(bytes),
+            false => counter.received = counter.received.saturating_add(bytes),
+        };
+
+        update(self.categories.entry(category).or_default());
+
+        if let Some(conversation_id) = conversation_id {
+            update(self.conversations.entry(conversation_id).or_default());
+        }
+
+        for peer in peers {
+            update(self.peers.entry(peer.clone()).or_default());
+        }
+    }
+
+    pub(crate) fn complete(&mut self, date: DateTime<Utc>) {
+        self.until = Some(date);
+    }
+}
+
+impl DataUsage {
+    /// Start of the period
+    pub fn since(&self) -> DateTime<Utc> {
+        self.since
+    }
+
+    /// End of the period, if it was completed
+    pub fn until(&self) -> Option<DateTime<Utc>> {
+        self.until
+    }
+
+    /// Usage across all categories
+    pub fn total(&self) -> UsageCounter {
+        self.categories
+            .values()
+            .fold(UsageCounter::default(), |total, counter| UsageCounter {
+                sent: total.sent.saturating_add(counter.sent),
+                received: total.received.saturating_add(counter.received),
+            })
+    }
+
+    pub fn category(&self, category: UsageCategory) -> UsageCounter {
+        self.categories.get(&category).copied().unwrap_or_default()
+    }
+
+    pub fn conversation(&self, conversation_id: Uuid) -> UsageCounter {
+        self.conversations
+            .get(&conversation_id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Usage exchanged with the peer. Data published to a conversation is accounted to every member it was sent to,
+    /// so the usage of the peers can add up to more than [`DataUsage::total`]
+    pub fn peer(&self, did: &DID) -> UsageCounter {
+        self.peers.get(did).copied().unwrap_or_default()
+    }
+
+    pub fn conversations(&self) -> &HashMap<Uuid, UsageCounter> {
+        &self.conversations
+    }
+
+    pub fn peers(&self) -> &HashMap<DID, UsageCounter> {
+        &self.peers
+    }
+}
+
+#[async_trait::async_trait]
+pub trait DataUsageAccounting: Sync + Send {
+    /// Data usage of the current period
+    async fn data_usage(&self) -> Result<DataUsage, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Data usage of the last completed period, if any
+    async fn previous_data_usage(&self) -> Result<Option<DataUsage>, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Start a new period, returning the usage of the period that was completed
+    async fn reset_data_usage(&self) -> Result<DataUsage, Error> {
+        Err(Error::Unimplemented)
+    }
+}
+
+#[async_trait::async_trait]
+impl<M, R, C> DataUsageAccounting for Warp<M, R, C>
+where
+    M: MultiPass + DataUsageAccounting,
+    R: RayGun,
+    C: Constellation,
+{
+    async fn data_usage(&self) -> Result<DataUsage, Error> {
+        self.multipass().data_usage().await
+    }
+
+    async fn previous_data_usage(&self) -> Result<Option<DataUsage>, Error> {
+        self.multipass().previous_data_usage().await
+    }
+
+    async fn reset_data_usage(&self) -> Result<DataUsage, Error> {
+        self.multipass().reset_data_usage().await
+    }
+}
@@ -6,10 +6,21 @@ mod test {
     use std::time::Duration;
 
     use crate::common::{self, create_account, create_accounts};
+    use bytes::Bytes;
+    use chrono::Utc;
     use futures::StreamExt;
+    use uuid::Uuid;
     use warp::constellation::file::FileType;
-    use warp::multipass::identity::{IdentityStatus, IdentityUpdate, Platform};
+    use warp::crypto::{minisign, DID};
+    use warp::multipass::identity::{
+        ActivityKind, FieldVisibility, IdentityActivity, IdentityStatus, IdentityUpdate, Platform,
+        ProfileField, ProfileFields,
+    };
+    use warp::multipass::notification::{
+        NotificationEvent, NotificationMode, NotificationPreferences,
+    };
     use warp::tesseract::Tesseract;
+    use warp_ipfs::config::{Discovery, DocumentCodec};
     use warp_ipfs::WarpIpfsBuilder;
 
     #[cfg(target_arch = "wasm32")]
@@ -20,7 +31,10 @@ mod test {
 
     #[cfg(not(target_arch = "wasm32"))]
     use tokio::test as async_test;
-    use warp::multipass::{IdentityInformation, LocalIdentity, MultiPass};
+    use warp::multipass::{
+        IdentityChange, IdentityInformation, LocalIdentity, MultiPass, MultiPassEvent,
+        MultiPassEventKind,
+    };
 
     #[async_test]
     async fn create_identity() -> anyhow::Result<()> {
@@ -146,6 +160,30 @@ mod test {
         Ok(())
     }
 
+    #[async_test]
+    async fn update_identity_username_keeps_handle() -> anyhow::Result<()> {
+        let (mut account, _, _) = create_account(
+            Some("JohnDoe"),
+            None,
+            Some("test::update_identity_username_keeps_handle".into()),
+        )
+        .await?;
+
+        let old_identity = account.identity().await?;
+        assert_eq!(old_identity.handle(), "JohnDoe");
+
+        account
+            .update_identity(IdentityUpdate::Username("Johnny".into()))
+            .await?;
+
+        let updated_identity = account.identity().await?;
+
+        assert_eq!(updated_identity.username(), "Johnny");
+        assert_eq!(updated_identity.handle(), "JohnDoe");
+
+        Ok(())
+    }
+
     #[async_test]
     async fn update_identity_status_message() -> anyhow::Result<()> {
         let tesseract = Tesseract::default();
@@ -252,6 +290,296 @@ mod test {
         Ok(())
     }
 
+    #[async_test]
+    async fn update_identity_activity() -> anyhow::Result<()> {
+        let (mut account, did, _) = create_account(
+            Some("JohnDoe"),
+            None,
+            Some("test::update_identity_activity".into()),
+        )
+        .await?;
+        assert_eq!(account.identity_activity(&did).await?, None);
+
+        let mut activity = IdentityActivity::new(ActivityKind::Listening, "Some song");
+        activity.set_started(Some(Utc::now()));
+
+        account
+            .set_identity_activity(Some(activity.clone()))
+            .await?;
+        assert_eq!(account.identity_activity(&did).await?, Some(activity));
+
+        account.set_identity_activity(None).await?;
+        assert_eq!(account.identity_activity(&did).await?, None);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn invalid_identity_activity() -> anyhow::Result<()> {
+        let (mut account, did, _) = create_account(
+            Some("JohnDoe"),
+            None,
+            Some("test::invalid_identity_activity".into()),
+        )
+        .await?;
+
+        let activity = IdentityActivity::new(ActivityKind::Playing, "");
+        assert!(account.set_identity_activity(Some(activity)).await.is_err());
+
+        let activity = IdentityActivity::new(ActivityKind::Playing, "a".repeat(256));
+        assert!(account.set_identity_activity(Some(activity)).await.is_err());
+
+        let mut activity = IdentityActivity::new(ActivityKind::Playing, "Some game");
+        activity.set_started(Some(Utc::now()));
+        activity.set_ends(Some(Utc::now() - chrono::Duration::minutes(5)));
+        assert!(account.set_identity_activity(Some(activity)).await.is_err());
+
+        assert_eq!(account.identity_activity(&did).await?, None);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn get_identity_activity() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (
+                Some("JohnDoe"),
+                None,
+                Some("test::get_identity_activity".into()),
+            ),
+            (
+                Some("JaneDoe"),
+                None,
+                Some("test::get_identity_activity".into()),
+            ),
+        ])
+        .await?;
+
+        let (account_a, _, _) = accounts.first().unwrap();
+
+        let (mut account_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if account_a.identity_status(&did_b).await.is_ok() {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let activity = IdentityActivity::new(ActivityKind::Watching, "Some movie");
+
+        account_b
+            .set_identity_activity(Some(activity.clone()))
+            .await?;
+
+        let remote_activity = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Ok(Some(activity)) = account_a.identity_activity(&did_b).await {
+                    break activity;
+                }
+            }
+        })
+        .await?;
+
+        assert_eq!(remote_activity, activity);
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn update_identity_profile_fields() -> anyhow::Result<()> {
+        let (mut account, did, _) = create_account(
+            Some("JohnDoe"),
+            None,
+            Some("test::update_identity_profile_fields".into()),
+        )
+        .await?;
+        assert!(account.identity_profile_fields(&did).await?.is_empty());
+
+        let mut fields = ProfileFields::default();
+        fields.set_pronouns(Some(ProfileField::new(
+            "they/them",
+            FieldVisibility::Public,
+        )));
+        fields.set_timezone(Some(ProfileField::new(
+            "Europe/Berlin",
+            FieldVisibility::Friends,
+        )));
+        fields.add_link(ProfileField::new(
+            "https://example.com/johndoe",
+            FieldVisibility::Public,
+        ));
+
+        account.set_identity_profile_fields(fields.clone()).await?;
+        assert_eq!(account.identity_profile_fields(&did).await?, fields);
+
+        account
+            .set_identity_profile_fields(ProfileFields::default())
+            .await?;
+        assert!(account.identity_profile_fields(&did).await?.is_empty());
+        Ok(())
+    }
+
+    #[async_test]
+    async fn invalid_identity_profile_fields() -> anyhow::Result<()> {
+        let (mut account, did, _) = create_account(
+            Some("JohnDoe"),
+            None,
+            Some("test::invalid_identity_profile_fields".into()),
+        )
+        .await?;
+
+        let mut fields = ProfileFields::default();
+        fields.add_link(ProfileField::new(
+            "ftp://example.com",
+            FieldVisibility::Public,
+        ));
+        assert!(account.set_identity_profile_fields(fields).await.is_err());
+
+        let mut fields = ProfileFields::default();
+        fields.add_link(ProfileField::new("https://", FieldVisibility::Public));
+        assert!(account.set_identity_profile_fields(fields).await.is_err());
+
+        let mut fields = ProfileFields::default();
+        fields.set_links(vec![
+            ProfileField::new(
+                "https://example.com",
+                FieldVisibility::Public
+            );
+            6
+        ]);
+        assert!(account.set_identity_profile_fields(fields).await.is_err());
+
+        let mut fields = ProfileFields::default();
+        fields.set_timezone(Some(ProfileField::new(
+            "Europe/Ber lin",
+            FieldVisibility::Public,
+        )));
+        assert!(account.set_identity_profile_fields(fields).await.is_err());
+
+        let mut fields = ProfileFields::default();
+        fields.set_pronouns(Some(ProfileField::new(
+            "a".repeat(64),
+            FieldVisibility::Public,
+        )));
+        assert!(account.set_identity_profile_fields(fields).await.is_err());
+
+        assert!(account.identity_profile_fields(&did).await?.is_empty());
+        Ok(())
+    }
+
+    #[async_test]
+    async fn profile_fields_hidden_from_non_friends() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (
+                Some("JohnDoe"),
+                None,
+                Some("test::profile_fields_hidden_from_non_friends".into()),
+            ),
+            (
+                Some("JaneDoe"),
+                None,
+                Some("test::profile_fields_hidden_from_non_friends".into()),
+            ),
+        ])
+        .await?;
+
+        let (account_a, _, _) = accounts.first().unwrap();
+
+        let (mut account_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if account_a.identity_status(&did_b).await.is_ok() {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let mut fields = ProfileFields::default();
+        fields.set_pronouns(Some(ProfileField::new("she/her", FieldVisibility::Public)));
+        fields.set_timezone(Some(ProfileField::new(
+            "America/New_York",
+            FieldVisibility::Friends,
+        )));
+        fields.add_link(ProfileField::new(
+            "https://example.com/private",
+            FieldVisibility::Friends,
+        ));
+
+        account_b
+            .set_identity_profile_fields(fields.clone())
+            .await?;
+
+        let remote_fields = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Ok(fields) = account_a.identity_profile_fields(&did_b).await {
+                    if fields.pronouns().is_some() {
+                        break fields;
+                    }
+                }
+            }
+        })
+        .await?;
+
+        assert_eq!(remote_fields, fields.visible_to(false));
+        assert!(remote_fields.timezone().is_none());
+        assert!(remote_fields.links().is_empty());
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn watch_identity() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (Some("JohnDoe"), None, Some("test::watch_identity".into())),
+            (Some("JaneDoe"), None, Some("test::watch_identity".into())),
+        ])
+        .await?;
+
+        let (account_a, did_a, _) = accounts.first().unwrap();
+
+        let (mut account_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if account_a.identity_status(&did_b).await.is_ok() {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        assert!(account_a.watch_identity(did_a).await.is_err());
+
+        let stream = account_a.watch_identity(&did_b).await?;
+
+        account_b
+            .update_identity(IdentityUpdate::StatusMessage(Some("Blast off".into())))
+            .await?;
+
+        let change = crate::common::timeout(
+            Duration::from_secs(60),
+            stream
+                .filter(|change| {
+                    futures::future::ready(matches!(change, IdentityChange::StatusMessage { .. }))
+                })
+                .next(),
+        )
+        .await?
+        .expect("stream is active");
+
+        assert_eq!(
+            change,
+            IdentityChange::StatusMessage {
+                status_message: Some("Blast off".into())
+            }
+        );
+
+        Ok(())
+    }
+
     #[async_test]
     async fn identity_platform() -> anyhow::Result<()> {
         let (account, did, _) = create_account(
@@ -387,4 +715,341 @@ mod test {
         assert_eq!(platform_b, Platform::Desktop);
         Ok(())
     }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[async_test]
+    async fn migrate_document_payloads_after_restart() -> anyhow::Result<()> {
+        let path = std::env::temp_dir().join(Uuid::new_v4().to_string());
+
+        let mut config = common::config(Discovery::None);
+        *config.path_mut() = Some(path.clone());
+        *config.persist_mut() = true;
+
+        let mut account = common::create_instance_with_config(config.clone()).await;
+        account.create_identity(None, None).await?;
+        account
+            .set_app_data("settings", Bytes::from_static(b"theme=dark"))
+            .await?;
+        account.multipass().shutdown().await;
+        drop(account);
+
+        // payloads are migrated to cbor and back, remaining readable each time
+        for codec in [DocumentCodec::Cbor, DocumentCodec::Json] {
+            config.store_setting_mut().document_codec = codec;
+            config.store_setting_mut().migrate_document_payloads = true;
+
+            let account = common::create_instance_with_config(config.clone()).await;
+
+            let data = crate::common::timeout(Duration::from_secs(30), async {
+                loop {
+                    if let Ok(data) = account.get_app_data("settings").await {
+                        break data;
+                    }
+                    futures_timer::Delay::new(Duration::from_millis(100)).await;
+                }
+            })
+            .await?;
+
+            assert_eq!(data, Bytes::from_static(b"theme=dark"));
+            account.multipass().shutdown().await;
+        }
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn set_and_get_app_data() -> anyhow::Result<()> {
+        let (mut account, _, _) =
+            create_account(Some("JohnDoe"), None, Some("test::app_data".into())).await?;
+
+        let mut stream = account.multipass_subscribe().await?;
+
+        assert!(account.get_app_data("settings").await.is_err());
+
+        account
+            .set_app_data("settings", Bytes::from_static(b"theme=dark"))
+            .await?;
+
+        crate::common::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some(MultiPassEventKind::AppDataUpdated { namespace }) = stream.next().await
+                {
+                    assert_eq!(namespace, "settings");
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let data = account.get_app_data("settings").await?;
+        assert_eq!(data, Bytes::from_static(b"theme=dark"));
+
+        account.set_app_data("settings", Bytes::new()).await?;
+        assert!(account.get_app_data("settings").await.is_err());
+
+        let oversized = Bytes::from(vec![0u8; 64 * 1024 + 1]);
+        assert!(account.set_app_data("settings", oversized).await.is_err());
+        Ok(())
+    }
+
+    #[async_test]
+    async fn set_and_get_notification_preferences() -> anyhow::Result<()> {
+        let (mut account, _, _) = create_account(
+            Some("JohnDoe"),
+            None,
+            Some("test::notification_preferences".into()),
+        )
+        .await?;
+
+        let conversation_id = Uuid::new_v4();
+        let event = NotificationEvent::new(Some(conversation_id), false, Utc::now());
+
+        assert_eq!(
+            account.notification_preferences().await?,
+            NotificationPreferences::default()
+        );
+        assert!(account.should_notify(&event).await?);
+
+        let mut preferences = NotificationPreferences::default();
+        preferences.set_conversation_mode(conversation_id, Some(NotificationMode::MentionsOnly));
+
+        account
+            .set_notification_preferences(preferences.clone())
+            .await?;
+
+        assert_eq!(account.notification_preferences().await?, preferences);
+        assert!(!account.should_notify(&event).await?);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn sign_detached() -> anyhow::Result<()> {
+        let (account, did, _) =
+            create_account(Some("JohnDoe"), None, Some("test::sign_detached".into())).await?;
+
+        let data = b"Hello, World";
+        let signature = account.sign_detached(data, Some("file:hello.txt")).await?;
+
+        let comment = minisign::verify(&did, data, &signature)?;
+        assert_eq!(comment, "file:hello.txt");
+        assert!(minisign::verify(&did, b"Hello, Warp", &signature).is_err());
+        Ok(())
+    }
+
+    #[async_test]
+    async fn revoked_device_is_not_linked_again() -> anyhow::Result<()> {
+        let (account, _, _) = create_account(
+            Some("JohnDoe"),
+            None,
+            Some("test::revoked_device_is_not_linked_again".into()),
+        )
+        .await?;
+
+        let device = DID::default();
+
+        account.multipass().link_device(&device).await?;
+        let devices = account.multipass().devices().await?;
+        assert!(devices
+            .iter()
+            .any(|certificate| certificate.device == device));
+
+        account.multipass().revoke_device(&device).await?;
+        let devices = account.multipass().devices().await?;
+        assert!(!devices
+            .iter()
+            .any(|certificate| certificate.device == device));
+
+        assert!(account.multipass().link_device(&device).await.is_err());
+
+        // the current device is still registered
+        let current = account.multipass().current_device().await?;
+        let devices = account.multipass().devices().await?;
+        assert!(devices.contains(&current));
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[async_test]
+    async fn linked_device_synchronizes_root_document() -> anyhow::Result<()> {
+        use warp::crypto::keypair::{generate_mnemonic_phrase, PhraseType};
+        use warp::multipass::ImportLocation;
+        use warp::raygun::RayGunEventKind;
+        use warp_ipfs::config::Discovery;
+
+        let (_shuttle, addresses) = common::create_shuttle().await?;
+        let discovery = Discovery::Shuttle { addresses };
+
+        let phrase = generate_mnemonic_phrase(PhraseType::Standard).into_phrase();
+
+        let (mut account_a, did_a, _) = common::create_account_with_discovery(
+            Some("JohnDoe"),
+            Some(&phrase),
+            Some("test::linked_device_synchronizes_root_document".into()),
+            discovery.clone(),
+        )
+        .await?;
+
+        let (account_c, did_c, _) = common::create_account_with_discovery(
+            Some("JaneDoe"),
+            None,
+            Some("test::linked_device_synchronizes_root_document".into()),
+            discovery.clone(),
+        )
+        .await?;
+
+        common::wait_for_registration(&account_a).await?;
+        common::wait_for_registration(&account_c).await?;
+
+        let mut account_b = common::create_instance(discovery).await;
+
+        let device = account_b.multipass().device_link_request()?;
+        let code = account_a.multipass().link_device(&device).await?;
+
+        // the code alone does not give the device access to the identity
+        assert!(account_b
+            .multipass()
+            .complete_device_link(
+                &code,
+                &generate_mnemonic_phrase(PhraseType::Standard).into_phrase()
+            )
+            .await
+            .is_err());
+
+        let identity = account_b
+            .multipass()
+            .complete_device_link(&code, &phrase)
+            .await?;
+        assert_eq!(identity.did_key(), &did_a);
+        assert_eq!(account_b.multipass().current_device().await?.device, device);
+
+        let mut rg_stream_b = account_b.raygun_subscribe().await?;
+
+        common::timeout(
+            Duration::from_secs(60),
+            common::mesh_connect(vec![
+                common::node(&account_a),
+                common::node(&account_b),
+                common::node(&account_c),
+            ]),
+        )
+        .await??;
+
+        let conversation_id = account_a.create_conversation(&did_c).await?.id();
+        account_a.export_identity(ImportLocation::Remote).await?;
+
+        assert!(account_b.multipass().synchronize_devices().await?);
+
+        let conversations = account_b.list_conversations().await?;
+        assert!(conversations
+            .iter()
+            .any(|conversation| conversation.id() == conversation_id));
+
+        let id = common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    rg_stream_b.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+        assert_eq!(id, conversation_id);
+
+        // nothing is left to merge once the device is up to date
+        assert!(!account_b.multipass().synchronize_devices().await?);
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[async_test]
+    async fn removed_friend_stays_removed_after_synchronizing() -> anyhow::Result<()> {
+        use warp::crypto::keypair::{generate_mnemonic_phrase, PhraseType};
+        use warp::multipass::{Friends, ImportLocation};
+
+        let (_shuttle, addresses) = common::create_shuttle().await?;
+        let discovery = Discovery::Shuttle { addresses };
+
+        let phrase = generate_mnemonic_phrase(PhraseType::Standard).into_phrase();
+
+        let (mut account_a, _, _) = common::create_account_with_discovery(
+            Some("JohnDoe"),
+            Some(&phrase),
+            Some("test::removed_friend_stays_removed_after_synchronizing".into()),
+            discovery.clone(),
+        )
+        .await?;
+
+        let (mut account_c, did_c, _) = common::create_account_with_discovery(
+            Some("JaneDoe"),
+            None,
+            Some("test::removed_friend_stays_removed_after_synchronizing".into()),
+            discovery.clone(),
+        )
+        .await?;
+
+        common::wait_for_registration(&account_a).await?;
+        common::wait_for_registration(&account_c).await?;
+
+        let mut account_b = common::create_instance(discovery).await;
+
+        let device = account_b.multipass().device_link_request()?;
+        let code = account_a.multipass().link_device(&device).await?;
+        account_b
+            .multipass()
+            .complete_device_link(&code, &phrase)
+            .await?;
+
+        common::timeout(
+            Duration::from_secs(60),
+            common::mesh_connect(vec![
+                common::node(&account_a),
+                common::node(&account_b),
+                common::node(&account_c),
+            ]),
+        )
+        .await??;
+
+        let mut subscribe_a = account_a.multipass_subscribe().await?;
+        let mut subscribe_c = account_c.multipass_subscribe().await?;
+
+        account_a.send_request(&did_c).await?;
+
+        common::timeout(Duration::from_secs(60), async {
+            let did = loop {
+                if let Some(MultiPassEventKind::FriendRequestReceived { from, .. }) =
+                    subscribe_c.next().await
+                {
+                    break from;
+                }
+            };
+            account_c.accept_request(&did).await
+        })
+        .await??;
+
+        common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MultiPassEventKind::FriendAdded { .. }) = subscribe_a.next().await {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        account_a.export_identity(ImportLocation::Remote).await?;
+        account_b.multipass().synchronize_devices().await?;
+        assert!(account_b.has_friend(&did_c).await?);
+
+        // the other device publishes its document, which still has the friend, after the friend is removed
+        account_a.remove_friend(&did_c).await?;
+        account_b.export_identity(ImportLocation::Remote).await?;
+
+        account_a.multipass().synchronize_devices().await?;
+        assert!(!account_a.has_friend(&did_c).await?);
+
+        account_a.export_identity(ImportLocation::Remote).await?;
+        account_b.multipass().synchronize_devices().await?;
+        assert!(!account_b.has_friend(&did_c).await?);
+        Ok(())
+    }
 }
@@ -0,0 +1,119 @@
+mod common;
+
+#[cfg(test)]
+#[cfg(not(target_arch = "wasm32"))]
+mod test {
+    use std::time::Duration;
+
+    use futures::StreamExt;
+    use warp::multipass::{LocalIdentity, MultiPassEvent};
+    use warp::raygun::{MessageEventKind, RayGun, RayGunEventKind, RayGunEvents, RayGunStream};
+    use warp_ipfs::audit::AuditReplica;
+    use warp_ipfs::config::Discovery;
+
+    use crate::common;
+
+    use tokio::test as async_test;
+
+    // The replica loads the root document that the account exported to shuttle
+    #[async_test]
+    async fn replica_does_not_publish() -> anyhow::Result<()> {
+        let (_shuttle, addresses) = common::create_shuttle().await?;
+        let discovery = Discovery::Shuttle { addresses };
+
+        let (mut instance_a, _, _) = common::create_account_with_discovery(
+            None,
+            None,
+            Some("test::replica_does_not_publish".into()),
+            discovery.clone(),
+        )
+        .await?;
+
+        let (mut instance_b, did_b, _) = common::create_account_with_discovery(
+            None,
+            None,
+            Some("test::replica_does_not_publish".into()),
+            discovery.clone(),
+        )
+        .await?;
+
+        common::wait_for_registration(&instance_a).await?;
+        common::wait_for_registration(&instance_b).await?;
+
+        common::timeout(
+            Duration::from_secs(60),
+            common::mesh_connect(vec![common::node(&instance_a), common::node(&instance_b)]),
+        )
+        .await??;
+
+        let mut rg_stream_b = instance_b.raygun_subscribe().await?;
+
+        let conversation_id = instance_a.create_conversation(&did_b).await?.id();
+
+        common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { .. }) = rg_stream_b.next().await
+                {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
+
+        instance_a
+            .send(conversation_id, vec!["Hello, World".into()])
+            .await?;
+
+        common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived { .. }) = conversation_b.next().await
+                {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let mp_stream_a = instance_a.multipass_subscribe().await?;
+        let mp_stream_b = instance_b.multipass_subscribe().await?;
+        let rg_stream_a = instance_a.raygun_subscribe().await?;
+        let conversation_a = instance_a.get_conversation_stream(conversation_id).await?;
+
+        let mut events = futures::stream::select_all([
+            mp_stream_a.map(|event| format!("{event:?}")).boxed(),
+            mp_stream_b.map(|event| format!("{event:?}")).boxed(),
+            rg_stream_a.map(|event| format!("{event:?}")).boxed(),
+            rg_stream_b.map(|event| format!("{event:?}")).boxed(),
+            conversation_a.map(|event| format!("{event:?}")).boxed(),
+            conversation_b.map(|event| format!("{event:?}")).boxed(),
+        ]);
+
+        // the accounts are left to settle so that any later event would come from the replica
+        while common::timeout(Duration::from_secs(3), events.next())
+            .await
+            .is_ok()
+        {}
+
+        let root = instance_a.multipass().export_audit_root().await?;
+
+        let replica =
+            AuditReplica::new(common::config(discovery), root, &instance_a.tesseract()).await?;
+
+        let conversations = replica.raygun().list_conversations().await?;
+        assert!(conversations
+            .iter()
+            .any(|conversation| conversation.id() == conversation_id));
+        assert_eq!(
+            replica.raygun().get_message_count(conversation_id).await?,
+            1
+        );
+
+        // Neither the account nor its peers hear from the replica while it is running
+        let emitted = common::timeout(Duration::from_secs(10), events.next()).await;
+
+        assert!(emitted.is_err(), "unexpected event: {emitted:?}");
+        Ok(())
+    }
+}
@@ -0,0 +1,90 @@
+pub mod common;
+#[cfg(test)]
+mod test {
+    use crate::common::create_account;
+    use warp::multipass::Friends;
+    use warp::raygun::RayGun;
+    use warp_ipfs::bundle::OfflineBundles;
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::wasm_bindgen_test as async_test;
+
+    #[cfg(target_arch = "wasm32")]
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    use tokio::test as async_test;
+
+    #[async_test]
+    async fn export_and_import_bundle() -> anyhow::Result<()> {
+        // Accounts are not connected to each other so everything is queued
+        let (mut account_a, did_a, _) = create_account(
+            Some("JohnDoe"),
+            None,
+            Some("test::export_and_import_bundle".into()),
+        )
+        .await?;
+
+        let (account_b, did_b, _) = create_account(
+            Some("JaneDoe"),
+            None,
+            Some("test::export_and_import_bundle".into()),
+        )
+        .await?;
+
+        account_a.send_request(&did_b).await?;
+
+        let conversation_id = account_a.create_conversation(&did_b).await?.id();
+        let message_id = account_a
+            .send(conversation_id, vec!["Hello, World!".into()])
+            .await?;
+
+        let bundle = account_a.multipass().export_bundle(&did_b).await?;
+
+        let summary = account_b.multipass().import_bundle(&bundle).await?;
+        assert_eq!(summary.sender, did_a);
+        assert_eq!(summary.requests, 1);
+        assert_eq!(summary.conversations, 1);
+        assert_eq!(summary.messages, 1);
+        assert_eq!(summary.rejected, 0);
+
+        assert!(account_b.received_friend_request_from(&did_a).await?);
+
+        let message = account_b.get_message(conversation_id, message_id).await?;
+        assert_eq!(message.lines(), ["Hello, World!".to_string()]);
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn bundle_rejected_by_other_identity() -> anyhow::Result<()> {
+        let (mut account_a, _, _) = create_account(
+            Some("JohnDoe"),
+            None,
+            Some("test::bundle_rejected_by_other_identity".into()),
+        )
+        .await?;
+
+        let (_account_b, did_b, _) = create_account(
+            Some("JaneDoe"),
+            None,
+            Some("test::bundle_rejected_by_other_identity".into()),
+        )
+        .await?;
+
+        let (account_c, _, _) = create_account(
+            Some("Dave"),
+            None,
+            Some("test::bundle_rejected_by_other_identity".into()),
+        )
+        .await?;
+
+        account_a.send_request(&did_b).await?;
+
+        let bundle = account_a.multipass().export_bundle(&did_b).await?;
+
+        assert!(account_c.multipass().import_bundle(&bundle).await.is_err());
+
+        Ok(())
+    }
+}
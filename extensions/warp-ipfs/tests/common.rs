@@ -9,7 +9,8 @@ use warp::{
     SingleHandle,
 };
 use warp_ipfs::{
-    config::{Bootstrap, Discovery},
+    config::{Bootstrap, Config, Discovery},
+    doctor::{AccountDoctor, DoctorCheckKind, DoctorStatus},
     WarpIpfsBuilder, WarpIpfsInstance,
 };
 
@@ -54,14 +55,50 @@ pub async fn mesh_connect(nodes: Vec<Ipfs>) -> anyhow::Result<()> {
 
 #[allow(dead_code)]
 pub async fn create_account(
+    username: Option<&str>,
+    passphrase: Option<&str>,
+    context: Option<String>,
+) -> anyhow::Result<(WarpIpfsInstance, DID, Identity)> {
+    create_account_with_discovery(username, passphrase, context, Discovery::None).await
+}
+
+#[allow(dead_code)]
+pub async fn create_account_with_discovery(
     username: Option<&str>,
     passphrase: Option<&str>,
     _: Option<String>,
+    discovery: Discovery,
 ) -> anyhow::Result<(WarpIpfsInstance, DID, Identity)> {
-    let mut config = warp_ipfs::config::Config::development();
+    let mut instance = create_instance(discovery).await;
+
+    let profile = instance.create_identity(username, passphrase).await?;
+    let identity = profile.identity().clone();
+
+    Ok((instance, identity.did_key().clone(), identity))
+}
+
+/// Instance with an unlocked tesseract that has yet to create or import an identity
+#[allow(dead_code)]
+pub async fn create_instance(discovery: Discovery) -> WarpIpfsInstance {
+    create_instance_with_config(config(discovery)).await
+}
+
+#[allow(dead_code)]
+pub async fn create_instance_with_config(config: Config) -> WarpIpfsInstance {
+    let instance = WarpIpfsBuilder::default().set_config(config).await;
+
+    instance.tesseract().unlock(b"internal pass").unwrap();
+
+    instance
+}
+
+/// Configuration of the instances used in tests, running in memory
+#[allow(dead_code)]
+pub fn config(discovery: Discovery) -> Config {
+    let mut config = Config::development();
     *config.listen_on_mut() = vec![Multiaddr::empty().with(Protocol::Memory(0))];
     config.ipfs_setting_mut().memory_transport = true;
-    config.store_setting_mut().discovery = Discovery::None;
+    config.store_setting_mut().discovery = discovery;
     config.ipfs_setting_mut().relay_client.relay_address = vec![];
     config.ipfs_setting_mut().mdns.enable = false;
     config.store_setting_mut().announce_to_mesh = true;
@@ -69,14 +106,97 @@ pub async fn create_account(
 
     *config.bootstrap_mut() = Bootstrap::None;
 
-    let mut instance = WarpIpfsBuilder::default().set_config(config).await;
+    config
+}
 
-    instance.tesseract().unlock(b"internal pass").unwrap();
+/// Shuttle node running in memory, returned along with the addresses to use with [`Discovery::Shuttle`]
+#[allow(dead_code)]
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn create_shuttle(
+) -> anyhow::Result<(warp_ipfs::shuttle::server::ShuttleServer, Vec<Multiaddr>)> {
+    use rust_ipfs::Keypair;
 
-    let profile = instance.create_identity(username, passphrase).await?;
-    let identity = profile.identity().clone();
+    let keypair = Keypair::generate_ed25519();
+    let peer_id = keypair.public().to_peer_id();
 
-    Ok((instance, identity.did_key().clone(), identity))
+    let server =
+        shuttle_server(&keypair, Multiaddr::empty().with(Protocol::Memory(0)), &[]).await?;
+
+    let addresses = timeout(Duration::from_secs(10), async {
+        loop {
+            let addresses = server
+                .addresses()
+                .await
+                .map(|addr| addr.with(Protocol::P2p(peer_id)))
+                .collect::<Vec<_>>();
+
+            if !addresses.is_empty() {
+                break addresses;
+            }
+
+            futures_timer::Delay::new(Duration::from_millis(100)).await;
+        }
+    })
+    .await?;
+
+    Ok((server, addresses))
+}
+
+/// Shuttle node acting as the directory of an organization, using `keypair` as the organization key. The node
+/// listens on `address` so that the instances of the members can be configured before it is started
+#[allow(dead_code)]
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn create_organization_directory(
+    keypair: &rust_ipfs::Keypair,
+    address: Multiaddr,
+    admins: &[DID],
+) -> anyhow::Result<warp_ipfs::shuttle::server::ShuttleServer> {
+    shuttle_server(keypair, address, admins).await
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+async fn shuttle_server(
+    keypair: &rust_ipfs::Keypair,
+    address: Multiaddr,
+    organization_admins: &[DID],
+) -> anyhow::Result<warp_ipfs::shuttle::server::ShuttleServer> {
+    warp_ipfs::shuttle::server::ShuttleServer::new::<&str>(
+        keypair,
+        None,
+        None,
+        false,
+        true,
+        &[address],
+        &[],
+        false,
+        false,
+        None,
+        None,
+        false,
+        false,
+        organization_admins,
+    )
+    .await
+}
+
+/// Wait until the identity of the instance is registered with the shuttle nodes it is configured with
+#[allow(dead_code)]
+pub async fn wait_for_registration(instance: &WarpIpfsInstance) -> anyhow::Result<()> {
+    timeout(Duration::from_secs(60), async {
+        loop {
+            let report = instance.multipass().diagnose().await?;
+            let registered = report.checks.iter().any(|check| {
+                check.kind == DoctorCheckKind::Shuttle && check.status == DoctorStatus::Ok
+            });
+
+            if registered {
+                return Ok::<_, anyhow::Error>(());
+            }
+
+            futures_timer::Delay::new(Duration::from_millis(500)).await;
+        }
+    })
+    .await?
 }
 
 #[allow(dead_code)]
@@ -108,6 +228,17 @@ pub async fn create_accounts(
     Ok(accounts)
 }
 
+/// Ipfs node of the instance
+#[allow(dead_code)]
+pub fn node(instance: &WarpIpfsInstance) -> Ipfs {
+    instance
+        .handle()
+        .expect("Handle accessible")
+        .downcast_ref::<Ipfs>()
+        .cloned()
+        .unwrap()
+}
+
 #[allow(dead_code)]
 pub async fn timeout<F>(duration: Duration, future: F) -> Result<F::Output, std::io::Error>
 where
@@ -2164,6 +2164,110 @@ mod test {
         Ok(())
     }
     #[async_test]
+    async fn unauthorized_edit_community_channel_slow_mode() -> anyhow::Result<()> {
+        let context = Some("test::unauthorized_edit_community_channel_slow_mode".into());
+        let acc = (None, None, context);
+        let accounts = create_accounts(vec![acc.clone(), acc]).await?;
+        let (instance_a, _, _) = &mut accounts[0].clone();
+        let (instance_b, did_b, _) = &mut accounts[1].clone();
+
+        let community = instance_a.create_community("Community0").await?;
+        let channel = instance_a
+            .create_community_channel(community.id(), "Channel0", CommunityChannelType::Standard)
+            .await?;
+
+        let mut rg_stream_b = instance_b.raygun_subscribe().await?;
+        let invite = instance_a
+            .create_community_invite(community.id(), Some(did_b.clone()), None)
+            .await?;
+        assert_eq!(
+            next_event(&mut rg_stream_b, Duration::from_secs(60)).await?,
+            RayGunEventKind::CommunityInvited {
+                community_id: community.id(),
+                invite_id: invite.id()
+            }
+        );
+
+        let mut stream_a = instance_a.get_community_stream(community.id()).await?;
+        instance_b.request_join_community(community.id()).await?;
+        assert_eq!(
+            next_event(&mut stream_a, Duration::from_secs(60)).await?,
+            MessageEventKind::CommunityJoined {
+                community_id: community.id(),
+                user: did_b.clone()
+            }
+        );
+
+        let result = instance_b
+            .edit_community_channel_slow_mode(community.id(), channel.id(), 30)
+            .await;
+        assert_eq!(
+            format!("{:?}", result),
+            format!("{:?}", Err::<Community, Error>(Error::Unauthorized))
+        );
+        Ok(())
+    }
+    #[async_test]
+    async fn community_channel_slow_mode() -> anyhow::Result<()> {
+        let context = Some("test::community_channel_slow_mode".into());
+        let acc = (None, None, context);
+        let accounts = create_accounts(vec![acc.clone(), acc]).await?;
+        let (instance_a, _, _) = &mut accounts[0].clone();
+        let (instance_b, did_b, _) = &mut accounts[1].clone();
+
+        let community = instance_a.create_community("Community0").await?;
+        let channel = instance_a
+            .create_community_channel(community.id(), "Channel0", CommunityChannelType::Standard)
+            .await?;
+        instance_a
+            .edit_community_channel_slow_mode(community.id(), channel.id(), 60)
+            .await?;
+
+        let mut rg_stream_b = instance_b.raygun_subscribe().await?;
+        let invite = instance_a
+            .create_community_invite(community.id(), Some(did_b.clone()), None)
+            .await?;
+        assert_eq!(
+            next_event(&mut rg_stream_b, Duration::from_secs(60)).await?,
+            RayGunEventKind::CommunityInvited {
+                community_id: community.id(),
+                invite_id: invite.id()
+            }
+        );
+
+        let mut stream_a = instance_a.get_community_stream(community.id()).await?;
+        instance_b.request_join_community(community.id()).await?;
+        assert_eq!(
+            next_event(&mut stream_a, Duration::from_secs(60)).await?,
+            MessageEventKind::CommunityJoined {
+                community_id: community.id(),
+                user: did_b.clone()
+            }
+        );
+
+        let channel = instance_b
+            .get_community_channel(community.id(), channel.id())
+            .await?;
+        assert_eq!(channel.slow_mode(), 60);
+
+        instance_b
+            .send_community_channel_message(community.id(), channel.id(), vec!["Hello".into()])
+            .await?;
+        let result = instance_b
+            .send_community_channel_message(community.id(), channel.id(), vec!["World".into()])
+            .await;
+        assert!(matches!(result, Err(Error::SlowModeActive { .. })));
+
+        // the owner is not subject to slow mode
+        instance_a
+            .send_community_channel_message(community.id(), channel.id(), vec!["Hello".into()])
+            .await?;
+        instance_a
+            .send_community_channel_message(community.id(), channel.id(), vec!["World".into()])
+            .await?;
+        Ok(())
+    }
+    #[async_test]
     async fn unauthorized_edit_community_channel_permissions() -> anyhow::Result<()> {
         let context = Some("test::unauthorized_edit_community_channel_permissions".into());
         let acc = (None, None, context);
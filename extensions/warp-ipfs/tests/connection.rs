@@ -0,0 +1,78 @@
+pub mod common;
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::common::{create_account, mesh_connect};
+    use futures::StreamExt;
+    use rust_ipfs::Ipfs;
+    use warp::SingleHandle;
+    use warp_ipfs::connection::{
+        ConnectionEvent, ConnectionRoute, ConnectionTransport, PeerConnections,
+    };
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::wasm_bindgen_test as async_test;
+
+    #[cfg(target_arch = "wasm32")]
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    use tokio::test as async_test;
+
+    #[async_test]
+    async fn connection_events() -> anyhow::Result<()> {
+        let (account_a, _, _) = create_account(
+            Some("JohnDoe"),
+            None,
+            Some("test::connection_events".into()),
+        )
+        .await?;
+
+        let (account_b, did_b, _) = create_account(
+            Some("JaneDoe"),
+            None,
+            Some("test::connection_events".into()),
+        )
+        .await?;
+
+        let mut stream = account_a.multipass().connection_subscribe().await?;
+
+        let nodes = [&account_a, &account_b]
+            .iter()
+            .map(|account| {
+                account
+                    .handle()
+                    .expect("Handle accessible")
+                    .downcast_ref::<Ipfs>()
+                    .cloned()
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        mesh_connect(nodes).await?;
+
+        let event = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(event @ ConnectionEvent::Connected { .. }) = stream.next().await {
+                    if event.did() == Some(&did_b) {
+                        break event;
+                    }
+                }
+            }
+        })
+        .await?;
+
+        let ConnectionEvent::Connected {
+            route, transport, ..
+        } = event
+        else {
+            unreachable!()
+        };
+
+        assert_eq!(route, ConnectionRoute::Direct);
+        assert_eq!(transport, ConnectionTransport::Memory);
+
+        Ok(())
+    }
+}
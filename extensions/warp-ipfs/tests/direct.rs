@@ -3,17 +3,23 @@ mod common;
 #[cfg(test)]
 mod test {
     use futures::{StreamExt, TryStreamExt};
+    use indexmap::IndexMap;
+    use std::sync::Arc;
     use std::time::Duration;
     use warp::{
         constellation::Progression,
         multipass::MultiPassEventKind,
         raygun::{
-            AttachmentKind, ConversationType, Location, MessageEvent, MessageEventKind,
-            MessageType, PinState, RayGunEventKind, ReactionState,
+            processor::MessageProcessor, AttachmentKind, ConversationType, Location, Message,
+            MessageEvent, MessageEventKind, MessageStatus, MessageType, PinState, RayGunEventKind,
+            ReactionState, SearchQuery,
         },
     };
 
-    use crate::common::{create_accounts, PROFILE_IMAGE};
+    use crate::common::{
+        config, create_account, create_accounts, create_instance_with_config, mesh_connect, node,
+        PROFILE_IMAGE,
+    };
 
     #[cfg(target_arch = "wasm32")]
     use wasm_bindgen_test::wasm_bindgen_test as async_test;
@@ -28,6 +34,10 @@ mod test {
     use warp::raygun::{
         RayGun, RayGunAttachment, RayGunConversationInformation, RayGunEvents, RayGunStream,
     };
+    use warp::scoped::SingleConversationRayGun;
+    use warp_ipfs::config::Discovery;
+    use warp_ipfs::store::MAX_MESSAGE_SIZE_LIMIT;
+    use warp_ipfs::usage::{DataUsageAccounting, UsageCategory};
 
     #[async_test]
     async fn create_conversation() -> anyhow::Result<()> {
@@ -203,6 +213,200 @@ mod test {
         Ok(())
     }
 
+    #[async_test]
+    async fn failed_operation_carries_operation_id() -> anyhow::Result<()> {
+        let (mut instance_a, _, _) = create_account(
+            None,
+            None,
+            Some("test::failed_operation_carries_operation_id".into()),
+        )
+        .await?;
+
+        let mut failures = instance_a.multipass().operation_failure_subscribe();
+
+        let conversation_id = uuid::Uuid::new_v4();
+        let result = instance_a
+            .send(conversation_id, vec!["Hello, World".into()])
+            .await;
+
+        // the error is returned as is
+        let Err(error) = result else {
+            anyhow::bail!("message sent to an unknown conversation");
+        };
+        assert!(matches!(error, warp::error::Error::InvalidConversation));
+
+        let failure = crate::common::timeout(Duration::from_secs(10), failures.next())
+            .await?
+            .expect("failure published");
+
+        assert_eq!(failure.error(), error.to_string());
+        assert_eq!(failure.context().operation(), "send");
+        assert_eq!(failure.context().conversation_id(), Some(conversation_id));
+        assert!(!failure.context().operation_id().is_nil());
+        Ok(())
+    }
+
+    #[async_test]
+    async fn merge_conversations_requires_duplicate() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (
+                None,
+                None,
+                Some("test::merge_conversations_requires_duplicate".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::merge_conversations_requires_duplicate".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts.first().cloned().unwrap();
+        let (_, did_b, _) = accounts.last().cloned().unwrap();
+
+        let conversation = instance_a.create_conversation(&did_b).await?;
+        let id = conversation.id();
+
+        assert!(instance_a.merge_conversations(id, id).await.is_err());
+        assert!(instance_a
+            .merge_conversations(id, uuid::Uuid::new_v4())
+            .await
+            .is_err());
+
+        // the conversation should be left untouched
+        instance_a.get_conversation(id).await?;
+        Ok(())
+    }
+
+    #[async_test]
+    async fn replay_conversation_events() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (None, None, Some("test::replay_conversation_events".into())),
+            (None, None, Some("test::replay_conversation_events".into())),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts.first().cloned().unwrap();
+        let (_, did_b, _) = accounts.last().cloned().unwrap();
+
+        let conversation = instance_a.create_conversation(&did_b).await?;
+        let conversation_id = conversation.id();
+
+        let message_id = instance_a
+            .send(conversation_id, vec!["Hello, World".into()])
+            .await?;
+
+        instance_a
+            .pin(conversation_id, message_id, PinState::Pin)
+            .await?;
+
+        let events = instance_a
+            .replay_events(conversation_id, None)
+            .await?
+            .collect::<Vec<_>>()
+            .await;
+
+        assert!(events.contains(&MessageEventKind::RecipientAdded {
+            conversation_id,
+            recipient: did_b.clone(),
+        }));
+
+        assert!(events.contains(&MessageEventKind::MessagePinned {
+            conversation_id,
+            message_id,
+        }));
+
+        // messages sent prior to `since` are not replayed
+        let since = chrono::Utc::now() + chrono::Duration::minutes(1);
+        let events = instance_a
+            .replay_events(conversation_id, Some(since))
+            .await?
+            .collect::<Vec<_>>()
+            .await;
+
+        assert!(!events
+            .iter()
+            .any(|event| matches!(event, MessageEventKind::MessagePinned { .. })));
+        Ok(())
+    }
+
+    #[async_test]
+    async fn recreate_conversation_after_delete() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (
+                None,
+                None,
+                Some("test::recreate_conversation_after_delete".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::recreate_conversation_after_delete".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts.first().cloned().unwrap();
+        let (mut instance_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        let mut previous_id = None;
+
+        for _ in 0..2 {
+            instance_a.create_conversation(&did_b).await?;
+
+            let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+                let mut id_a = None;
+                let mut id_b = None;
+                loop {
+                    tokio::select! {
+                        Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_a.next() => {
+                            id_a.replace(conversation_id);
+                        },
+                        Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_b.next() => {
+                            id_b.replace(conversation_id);
+                        },
+                    }
+
+                    if id_a.is_some() && id_b.is_some() {
+                        assert_eq!(id_a, id_b);
+                        break id_a.expect("valid conversation_id")
+                    }
+                }
+            }).await?;
+
+            // the recreated conversation should not reuse the id of the deleted conversation
+            assert_ne!(previous_id, Some(conversation_id));
+            previous_id = Some(conversation_id);
+
+            instance_a.delete(conversation_id, None).await?;
+
+            crate::common::timeout(Duration::from_secs(60), async {
+                let mut a_del = false;
+                let mut b_del = false;
+                loop {
+                    tokio::select! {
+                        Some(RayGunEventKind::ConversationDeleted { .. }) = chat_subscribe_a.next() => {
+                            a_del = true;
+                        },
+                        Some(RayGunEventKind::ConversationDeleted { .. }) = chat_subscribe_b.next() => {
+                            b_del = true;
+                        },
+                    }
+
+                    if a_del && b_del {
+                        break;
+                    }
+                }
+            }).await?;
+        }
+
+        Ok(())
+    }
+
     #[async_test]
     async fn send_message_in_conversation() -> anyhow::Result<()> {
         let accounts = create_accounts(vec![
@@ -219,8 +423,1012 @@ mod test {
         ])
         .await?;
 
-        let (mut instance_a, _, _) = accounts.first().cloned().unwrap();
-        let (mut instance_b, did_b, _) = accounts.last().cloned().unwrap();
+        let (mut instance_a, _, _) = accounts.first().cloned().unwrap();
+        let (mut instance_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            let mut id_a = None;
+            let mut id_b = None;
+            loop {
+                tokio::select! {
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_a.next() => {
+                        id_a.replace(conversation_id);
+                    },
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_b.next() => {
+                        id_b.replace(conversation_id);
+                    },
+                }
+
+                if id_a.is_some() && id_b.is_some() {
+                    assert_eq!(id_a, id_b);
+                    break id_a.expect("valid conversation_id")
+                }
+            }
+        }).await?;
+
+        let mut conversation_a = instance_a.get_conversation_stream(conversation_id).await?;
+        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
+
+        instance_a
+            .send(conversation_id, vec!["Hello, World".into()])
+            .await?;
+
+        let message_a = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageSent {
+                    conversation_id,
+                    message_id,
+                }) = conversation_a.next().await
+                {
+                    break instance_a.get_message(conversation_id, message_id).await;
+                }
+            }
+        })
+        .await??;
+
+        let message_b = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived {
+                    conversation_id,
+                    message_id,
+                }) = conversation_b.next().await
+                {
+                    break instance_a.get_message(conversation_id, message_id).await;
+                }
+            }
+        })
+        .await??;
+
+        assert_eq!(message_a, message_b);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn data_usage_attributed_to_conversation_and_peers() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (
+                None,
+                None,
+                Some("test::data_usage_attributed_to_conversation_and_peers".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::data_usage_attributed_to_conversation_and_peers".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut instance_a, did_a, _) = accounts.first().cloned().unwrap();
+        let (mut instance_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_b.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
+
+        instance_a.reset_data_usage().await?;
+        instance_b.reset_data_usage().await?;
+
+        instance_a
+            .send(conversation_id, vec!["Hello, World".into()])
+            .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived { .. }) = conversation_b.next().await
+                {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let usage_a = instance_a.data_usage().await?;
+        assert!(usage_a.category(UsageCategory::Messages).sent > 0);
+        assert!(usage_a.conversation(conversation_id).sent > 0);
+        assert!(usage_a.peer(&did_b).sent > 0);
+
+        let usage_b = instance_b.data_usage().await?;
+        assert!(usage_b.conversation(conversation_id).received > 0);
+        assert!(usage_b.peer(&did_a).received > 0);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn events_received_out_of_order_are_applied_in_order() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (None, None, Some("test::out_of_order".into())),
+            (None, None, Some("test::out_of_order".into())),
+            (None, None, Some("test::out_of_order".into())),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts[0].clone();
+        let (mut instance_b, did_b, _) = accounts[1].clone();
+        let (instance_c, _, _) = accounts[2].clone();
+
+        let node_a = node(&instance_a);
+        let node_b = node(&instance_b);
+        let node_c = node(&instance_c);
+        let peer_a = node_a.keypair().public().to_peer_id();
+        let peer_b = node_b.keypair().public().to_peer_id();
+        let peer_c = node_c.keypair().public().to_peer_id();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            let mut id_a = None;
+            let mut id_b = None;
+            loop {
+                tokio::select! {
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_a.next() => {
+                        id_a.replace(conversation_id);
+                    },
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_b.next() => {
+                        id_b.replace(conversation_id);
+                    },
+                }
+
+                if id_a.is_some() && id_b.is_some() {
+                    assert_eq!(id_a, id_b);
+                    break id_a.expect("valid conversation_id")
+                }
+            }
+        }).await?;
+
+        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
+
+        // the first message sets the point from which the events of the sender are ordered
+        instance_a
+            .send(conversation_id, vec!["Hello".into()])
+            .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived { .. }) = conversation_b.next().await
+                {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        // the third node captures the payloads published to the conversation while the recipient is unreachable
+        let topic = format!("/conversation/{conversation_id}");
+        let mut captured_stream = node_c.pubsub_subscribe(topic.clone()).await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            while !node_a
+                .pubsub_peers(Some(topic.clone()))
+                .await
+                .unwrap_or_default()
+                .contains(&peer_c)
+            {
+                futures_timer::Delay::new(Duration::from_millis(100)).await;
+            }
+        })
+        .await?;
+
+        node_b.ban_peer(peer_a).await?;
+        node_b.ban_peer(peer_c).await?;
+
+        let lines = ["One", "Two", "Three"];
+        for line in lines {
+            instance_a.send(conversation_id, vec![line.into()]).await?;
+        }
+
+        let mut captured = vec![];
+        while captured.len() < lines.len() {
+            let message = crate::common::timeout(Duration::from_secs(60), captured_stream.next())
+                .await?
+                .expect("subscribed");
+            if !captured.contains(&message.data) {
+                captured.push(message.data);
+            }
+        }
+
+        // the third node delivers the payloads to the recipient in reverse order
+        node_b.unban_peer(peer_c).await?;
+        node_b.connect(peer_c).await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            while !node_c
+                .pubsub_peers(Some(topic.clone()))
+                .await
+                .unwrap_or_default()
+                .contains(&peer_b)
+            {
+                futures_timer::Delay::new(Duration::from_millis(100)).await;
+            }
+        })
+        .await?;
+
+        for data in captured.into_iter().rev() {
+            node_c.pubsub_publish(topic.clone(), data).await?;
+        }
+
+        let received = crate::common::timeout(Duration::from_secs(60), async {
+            let mut received = vec![];
+            while received.len() < lines.len() {
+                if let Some(MessageEventKind::MessageReceived {
+                    conversation_id,
+                    message_id,
+                }) = conversation_b.next().await
+                {
+                    let message = instance_b.get_message(conversation_id, message_id).await?;
+                    received.extend(message.lines().to_vec());
+                }
+            }
+            Ok::<_, anyhow::Error>(received)
+        })
+        .await??;
+
+        assert_eq!(received, lines);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn messages_missed_while_unreachable_are_caught_up() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (None, None, Some("test::catch_up".into())),
+            (None, None, Some("test::catch_up".into())),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts[0].clone();
+        let (mut instance_b, did_b, _) = accounts[1].clone();
+
+        let node_a = node(&instance_a);
+        let node_b = node(&instance_b);
+        let peer_a = node_a.keypair().public().to_peer_id();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            let mut id_a = None;
+            let mut id_b = None;
+            loop {
+                tokio::select! {
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_a.next() => {
+                        id_a.replace(conversation_id);
+                    },
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_b.next() => {
+                        id_b.replace(conversation_id);
+                    },
+                }
+
+                if id_a.is_some() && id_b.is_some() {
+                    assert_eq!(id_a, id_b);
+                    break id_a.expect("valid conversation_id")
+                }
+            }
+        }).await?;
+
+        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
+
+        instance_a
+            .send(conversation_id, vec!["Hello".into()])
+            .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived { .. }) = conversation_b.next().await
+                {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        node_b.ban_peer(peer_a).await?;
+
+        let lines = ["One", "Two", "Three"];
+        for line in lines {
+            instance_a.send(conversation_id, vec![line.into()]).await?;
+        }
+
+        node_b.unban_peer(peer_a).await?;
+        node_b.connect(peer_a).await?;
+
+        // the messages are requested from the sender once it is reachable again
+        crate::common::timeout(Duration::from_secs(60), async {
+            while instance_b.get_message_count(conversation_id).await? < lines.len() + 1 {
+                futures_timer::Delay::new(Duration::from_millis(500)).await;
+            }
+            Ok::<_, anyhow::Error>(())
+        })
+        .await??;
+
+        let messages_a = instance_a.get_message_count(conversation_id).await?;
+        let messages_b = instance_b.get_message_count(conversation_id).await?;
+        assert_eq!(messages_a, messages_b);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn excluded_conversation_loads_on_demand() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (None, None, Some("test::excluded_conversation".into())),
+            (None, None, Some("test::excluded_conversation".into())),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts[0].clone();
+        let (_, did_b, _) = accounts[1].clone();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_a.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        let message_id = instance_a
+            .send(conversation_id, vec!["Hello, World".into()])
+            .await?;
+
+        instance_a
+            .raygun()
+            .set_conversation_excluded(conversation_id, true)
+            .await?;
+
+        assert!(instance_a
+            .raygun()
+            .selective_sync()?
+            .is_conversation_excluded(conversation_id));
+
+        // the conversation is loaded once it is accessed
+        let message = instance_a.get_message(conversation_id, message_id).await?;
+        assert_eq!(message.lines(), ["Hello, World"]);
+
+        let message_id = instance_a
+            .send(conversation_id, vec!["Still here".into()])
+            .await?;
+        let message = instance_a.get_message(conversation_id, message_id).await?;
+        assert_eq!(message.lines(), ["Still here"]);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn send_message_with_scoped_handle() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (
+                None,
+                None,
+                Some("test::send_message_with_scoped_handle".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::send_message_with_scoped_handle".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts.first().cloned().unwrap();
+        let (_, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_a.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        let mut handle = SingleConversationRayGun::new(instance_a.clone(), conversation_id);
+        let mut conversation_stream = handle.subscribe().await?;
+
+        let message_id = handle.send(vec!["Hello, World".into()]).await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageSent { message_id: id, .. }) =
+                    conversation_stream.next().await
+                {
+                    assert_eq!(id, message_id);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let message = handle.get_message(message_id).await?;
+        assert_eq!(message.lines(), ["Hello, World"]);
+
+        // files of the account are outside of the scope of the handle
+        instance_a.put_buffer("secret.txt", b"secret").await?;
+
+        let result = handle
+            .attach(
+                None,
+                vec![Location::Constellation {
+                    path: "/secret.txt".into(),
+                }],
+                vec![],
+                Default::default(),
+            )
+            .await;
+        assert!(matches!(result, Err(warp::error::Error::Unauthorized)));
+        Ok(())
+    }
+
+    #[async_test]
+    async fn message_status_in_conversation() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (
+                None,
+                None,
+                Some("test::message_status_in_conversation".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::message_status_in_conversation".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts.first().cloned().unwrap();
+        let (mut instance_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            let mut id_a = None;
+            let mut id_b = None;
+            loop {
+                tokio::select! {
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_a.next() => {
+                        id_a.replace(conversation_id);
+                    },
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_b.next() => {
+                        id_b.replace(conversation_id);
+                    },
+                }
+
+                if id_a.is_some() && id_b.is_some() {
+                    assert_eq!(id_a, id_b);
+                    break id_a.expect("valid conversation_id")
+                }
+            }
+        }).await?;
+
+        let mut conversation_a = instance_a.get_conversation_stream(conversation_id).await?;
+        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
+
+        let message_id = instance_a
+            .send(conversation_id, vec!["Hello, World".into()])
+            .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived { .. }) = conversation_b.next().await
+                {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageStatusUpdated {
+                    status: MessageStatus::Delivered,
+                    ..
+                }) = conversation_a.next().await
+                {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let status = instance_a
+            .message_status(conversation_id, message_id)
+            .await?;
+        assert_eq!(status, MessageStatus::Delivered);
+
+        instance_b.mark_read(conversation_id, message_id).await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageRead {
+                    message_id: id,
+                    did_key,
+                    ..
+                }) = conversation_a.next().await
+                {
+                    assert_eq!(id, message_id);
+                    assert_eq!(did_key, did_b);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let status = instance_a
+            .message_status(conversation_id, message_id)
+            .await?;
+        assert_eq!(status, MessageStatus::Read);
+
+        assert!(instance_a
+            .message_status(conversation_id, uuid::Uuid::new_v4())
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[async_test]
+    async fn message_queued_until_recipient_is_reachable() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (None, None, Some("test::message_queued".into())),
+            (None, None, Some("test::message_queued".into())),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts.first().cloned().unwrap();
+        let (mut instance_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        let node_a = node(&instance_a);
+        let node_b = node(&instance_b);
+        let peer_a = node_a.keypair().public().to_peer_id();
+        let peer_b = node_b.keypair().public().to_peer_id();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            let mut id_a = None;
+            let mut id_b = None;
+            loop {
+                tokio::select! {
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_a.next() => {
+                        id_a.replace(conversation_id);
+                    },
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_b.next() => {
+                        id_b.replace(conversation_id);
+                    },
+                }
+
+                if id_a.is_some() && id_b.is_some() {
+                    assert_eq!(id_a, id_b);
+                    break id_a.expect("valid conversation_id")
+                }
+            }
+        }).await?;
+
+        let mut conversation_a = instance_a.get_conversation_stream(conversation_id).await?;
+        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
+
+        let topic = format!("/conversation/{conversation_id}");
+
+        node_b.ban_peer(peer_a).await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            while node_a
+                .pubsub_peers(Some(topic.clone()))
+                .await
+                .unwrap_or_default()
+                .contains(&peer_b)
+            {
+                futures_timer::Delay::new(Duration::from_millis(100)).await;
+            }
+        })
+        .await?;
+
+        let message_id = instance_a
+            .send(conversation_id, vec!["Hello, World".into()])
+            .await?;
+
+        let status = instance_a
+            .message_status(conversation_id, message_id)
+            .await?;
+        assert_eq!(status, MessageStatus::Queued);
+
+        node_b.unban_peer(peer_a).await?;
+        node_b.connect(peer_a).await?;
+
+        // the queued message is sent once the recipient is reachable again
+        crate::common::timeout(Duration::from_secs(60), async {
+            let mut statuses = vec![];
+            loop {
+                if let Some(MessageEventKind::MessageStatusUpdated {
+                    message_id: id,
+                    status,
+                    ..
+                }) = conversation_a.next().await
+                {
+                    if id == message_id {
+                        statuses.push(status);
+                    }
+                }
+
+                if statuses.last() == Some(&MessageStatus::Sent) {
+                    break;
+                }
+            }
+            assert_eq!(statuses, [MessageStatus::Queued, MessageStatus::Sent]);
+        })
+        .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived { message_id: id, .. }) =
+                    conversation_b.next().await
+                {
+                    if id == message_id {
+                        break;
+                    }
+                }
+            }
+        })
+        .await?;
+
+        let status = instance_a
+            .message_status(conversation_id, message_id)
+            .await?;
+        assert_eq!(status, MessageStatus::Sent);
+        Ok(())
+    }
+
+    struct Uppercase;
+
+    #[async_trait::async_trait]
+    impl MessageProcessor for Uppercase {
+        fn name(&self) -> &str {
+            "uppercase"
+        }
+
+        async fn process(
+            &self,
+            message: &Message,
+        ) -> Result<IndexMap<String, String>, warp::error::Error> {
+            let mut metadata = IndexMap::new();
+            metadata.insert("text".into(), message.lines().join("\n").to_uppercase());
+            Ok(metadata)
+        }
+    }
+
+    #[async_test]
+    async fn process_received_message() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (None, None, Some("test::process_received_message".into())),
+            (None, None, Some("test::process_received_message".into())),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts.first().cloned().unwrap();
+        let (mut instance_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        instance_b
+            .register_message_processor(Arc::new(Uppercase))
+            .await?;
+        assert!(instance_b
+            .register_message_processor(Arc::new(Uppercase))
+            .await
+            .is_err());
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            let mut id_a = None;
+            let mut id_b = None;
+            loop {
+                tokio::select! {
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_a.next() => {
+                        id_a.replace(conversation_id);
+                    },
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_b.next() => {
+                        id_b.replace(conversation_id);
+                    },
+                }
+
+                if id_a.is_some() && id_b.is_some() {
+                    assert_eq!(id_a, id_b);
+                    break id_a.expect("valid conversation_id")
+                }
+            }
+        }).await?;
+
+        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
+
+        instance_a
+            .send(conversation_id, vec!["Hello, World".into()])
+            .await?;
+
+        let message = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived {
+                    conversation_id,
+                    message_id,
+                }) = conversation_b.next().await
+                {
+                    break instance_b.get_message(conversation_id, message_id).await;
+                }
+            }
+        })
+        .await??;
+
+        assert_eq!(
+            message.metadata().get("uppercase.text").map(String::as_str),
+            Some("HELLO, WORLD")
+        );
+
+        instance_b.unregister_message_processor("uppercase").await?;
+        assert!(instance_b
+            .unregister_message_processor("uppercase")
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[async_test]
+    async fn send_and_download_attachment_in_conversation() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (
+                None,
+                None,
+                Some("test::send_and_download_attachment_in_conversation".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::send_and_download_attachment_in_conversation".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts.first().cloned().unwrap();
+        let (mut instance_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            let mut id_a = None;
+            let mut id_b = None;
+            loop {
+                tokio::select! {
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_a.next() => {
+                        id_a.replace(conversation_id);
+                    },
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_b.next() => {
+                        id_b.replace(conversation_id);
+                    },
+                }
+
+                if id_a.is_some() && id_b.is_some() {
+                    assert_eq!(id_a, id_b);
+                    break id_a.expect("valid conversation_id")
+                }
+            }
+        }).await?;
+
+        let mut conversation_a = instance_a.get_conversation_stream(conversation_id).await?;
+        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
+
+        //upload file to constellation to attach file from constellation
+
+        instance_a.put_buffer("image.png", PROFILE_IMAGE).await?;
+
+        let (_, mut stream) = instance_a
+            .attach(
+                conversation_id,
+                None,
+                vec![Location::Constellation {
+                    path: "image.png".into(),
+                }],
+                vec![],
+            )
+            .await?;
+
+        while let Some(event) = stream.next().await {
+            match event {
+                AttachmentKind::AttachedProgress(
+                    _location,
+                    Progression::CurrentProgress { .. },
+                ) => {}
+                AttachmentKind::AttachedProgress(
+                    _location,
+                    Progression::ProgressComplete { name, total },
+                ) => {
+                    assert_eq!(name, "image.png");
+                    assert_eq!(total, Some(PROFILE_IMAGE.len()));
+                }
+                AttachmentKind::AttachedProgress(_location, Progression::ProgressFailed { .. }) => {
+                    unreachable!("should not fail")
+                }
+                AttachmentKind::Pending(result) => {
+                    result?;
+                }
+            }
+        }
+
+        let message_a = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageSent {
+                    conversation_id,
+                    message_id,
+                }) = conversation_a.next().await
+                {
+                    break instance_a.get_message(conversation_id, message_id).await;
+                }
+            }
+        })
+        .await??;
+
+        let message_b = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived {
+                    conversation_id,
+                    message_id,
+                }) = conversation_b.next().await
+                {
+                    break instance_b.get_message(conversation_id, message_id).await;
+                }
+            }
+        })
+        .await??;
+
+        assert_eq!(message_a, message_b);
+        assert_eq!(message_a.message_type(), MessageType::Attachment);
+        let attachments = message_a.attachments();
+        assert!(!attachments.is_empty());
+
+        let file = attachments.first().expect("attachment exist");
+
+        assert_eq!(file.name(), "image.png");
+
+        let stream = instance_b
+            .download_stream(conversation_id, message_a.id(), "image.png")
+            .await?;
+
+        let data = stream.try_collect::<Vec<_>>().await?.concat();
+
+        assert_eq!(data, PROFILE_IMAGE);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn send_message_exceeding_pubsub_limit() -> anyhow::Result<()> {
+        let mut config = config(Discovery::None);
+        config.set_max_message_size(MAX_MESSAGE_SIZE_LIMIT);
+
+        let mut instance_a = create_instance_with_config(config).await;
+        instance_a.create_identity(None, None).await?;
+
+        let (mut instance_b, did_b, _) = create_account(
+            None,
+            None,
+            Some("test::send_message_exceeding_pubsub_limit".into()),
+        )
+        .await?;
+
+        mesh_connect(vec![node(&instance_a), node(&instance_b)]).await?;
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            let mut id_a = None;
+            let mut id_b = None;
+            loop {
+                tokio::select! {
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_a.next() => {
+                        id_a.replace(conversation_id);
+                    },
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_b.next() => {
+                        id_b.replace(conversation_id);
+                    },
+                }
+
+                if id_a.is_some() && id_b.is_some() {
+                    assert_eq!(id_a, id_b);
+                    break id_a.expect("valid conversation_id")
+                }
+            }
+        }).await?;
+
+        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
+
+        // random lines so the payload cannot be compressed below the pubsub limit
+        let lines = (0..1800)
+            .map(|_| uuid::Uuid::new_v4().to_string())
+            .collect::<Vec<_>>();
+
+        // the recipient uses the default size, so the same message is rejected when sent by them
+        assert!(instance_b
+            .send(conversation_id, lines.clone())
+            .await
+            .is_err());
+
+        let message_id = instance_a.send(conversation_id, lines.clone()).await?;
+
+        let message = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived {
+                    conversation_id,
+                    message_id: id,
+                }) = conversation_b.next().await
+                {
+                    assert_eq!(id, message_id);
+                    break instance_b.get_message(conversation_id, message_id).await;
+                }
+            }
+        })
+        .await??;
+
+        assert_eq!(message.message_type(), MessageType::Message);
+        assert_eq!(message.lines(), lines);
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[async_test]
+    async fn send_message_as_snippet() -> anyhow::Result<()> {
+        let mut config = config(Discovery::None);
+        config.set_max_message_size(64);
+        config.set_snippets(true);
+
+        let mut instance_a = create_instance_with_config(config).await;
+        instance_a.create_identity(None, None).await?;
+
+        let (mut instance_b, did_b, _) =
+            create_account(None, None, Some("test::send_message_as_snippet".into())).await?;
+
+        mesh_connect(vec![node(&instance_a), node(&instance_b)]).await?;
 
         let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
         let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
@@ -247,55 +1455,66 @@ mod test {
             }
         }).await?;
 
-        let mut conversation_a = instance_a.get_conversation_stream(conversation_id).await?;
         let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
 
-        instance_a
-            .send(conversation_id, vec!["Hello, World".into()])
-            .await?;
+        let lines = (0..32)
+            .map(|line| format!("line {line} of a long log"))
+            .collect::<Vec<_>>();
 
-        let message_a = crate::common::timeout(Duration::from_secs(60), async {
-            loop {
-                if let Some(MessageEventKind::MessageSent {
-                    conversation_id,
-                    message_id,
-                }) = conversation_a.next().await
-                {
-                    break instance_a.get_message(conversation_id, message_id).await;
-                }
-            }
-        })
-        .await??;
+        // snippets are disabled by default, so the same message is rejected when sent by the other participant
+        assert!(instance_b
+            .send(conversation_id, lines.clone())
+            .await
+            .is_err());
 
-        let message_b = crate::common::timeout(Duration::from_secs(60), async {
+        let message_id = instance_a.send(conversation_id, lines.clone()).await?;
+
+        let message = crate::common::timeout(Duration::from_secs(60), async {
             loop {
                 if let Some(MessageEventKind::MessageReceived {
                     conversation_id,
-                    message_id,
+                    message_id: id,
                 }) = conversation_b.next().await
                 {
-                    break instance_a.get_message(conversation_id, message_id).await;
+                    assert_eq!(id, message_id);
+                    break instance_b.get_message(conversation_id, message_id).await;
                 }
             }
         })
         .await??;
 
-        assert_eq!(message_a, message_b);
+        assert_eq!(message.message_type(), MessageType::Attachment);
+        assert!(message.lines().len() < lines.len());
+
+        let file = message
+            .attachments()
+            .first()
+            .cloned()
+            .expect("snippet attached");
+
+        let stream = instance_b
+            .download_stream(conversation_id, message_id, &file.name())
+            .await?;
+
+        let data = stream.try_collect::<Vec<_>>().await?.concat();
+
+        assert_eq!(String::from_utf8(data)?, lines.join("\n"));
         Ok(())
     }
 
+    #[cfg(not(target_arch = "wasm32"))]
     #[async_test]
-    async fn send_and_download_attachment_in_conversation() -> anyhow::Result<()> {
+    async fn save_attachment_in_conversation() -> anyhow::Result<()> {
         let accounts = create_accounts(vec![
             (
                 None,
                 None,
-                Some("test::send_and_download_attachment_in_conversation".into()),
+                Some("test::save_attachment_in_conversation".into()),
             ),
             (
                 None,
                 None,
-                Some("test::send_and_download_attachment_in_conversation".into()),
+                Some("test::save_attachment_in_conversation".into()),
             ),
         ])
         .await?;
@@ -303,39 +1522,26 @@ mod test {
         let (mut instance_a, _, _) = accounts.first().cloned().unwrap();
         let (mut instance_b, did_b, _) = accounts.last().cloned().unwrap();
 
-        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
         let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
 
         instance_a.create_conversation(&did_b).await?;
 
         let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
-            let mut id_a = None;
-            let mut id_b = None;
             loop {
-                tokio::select! {
-                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_a.next() => {
-                        id_a.replace(conversation_id);
-                    },
-                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_b.next() => {
-                        id_b.replace(conversation_id);
-                    },
-                }
-
-                if id_a.is_some() && id_b.is_some() {
-                    assert_eq!(id_a, id_b);
-                    break id_a.expect("valid conversation_id")
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_b.next().await
+                {
+                    break conversation_id;
                 }
             }
-        }).await?;
+        })
+        .await?;
 
-        let mut conversation_a = instance_a.get_conversation_stream(conversation_id).await?;
         let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
 
-        //upload file to constellation to attach file from constellation
-
         instance_a.put_buffer("image.png", PROFILE_IMAGE).await?;
 
-        let (_, mut stream) = instance_a
+        let (_, stream) = instance_a
             .attach(
                 conversation_id,
                 None,
@@ -346,70 +1552,48 @@ mod test {
             )
             .await?;
 
-        while let Some(event) = stream.next().await {
-            match event {
-                AttachmentKind::AttachedProgress(
-                    _location,
-                    Progression::CurrentProgress { .. },
-                ) => {}
-                AttachmentKind::AttachedProgress(
-                    _location,
-                    Progression::ProgressComplete { name, total },
-                ) => {
-                    assert_eq!(name, "image.png");
-                    assert_eq!(total, Some(PROFILE_IMAGE.len()));
-                }
-                AttachmentKind::AttachedProgress(_location, Progression::ProgressFailed { .. }) => {
-                    unreachable!("should not fail")
-                }
-                AttachmentKind::Pending(result) => {
-                    result?;
-                }
-            }
-        }
-
-        let message_a = crate::common::timeout(Duration::from_secs(60), async {
-            loop {
-                if let Some(MessageEventKind::MessageSent {
-                    conversation_id,
-                    message_id,
-                }) = conversation_a.next().await
-                {
-                    break instance_a.get_message(conversation_id, message_id).await;
-                }
-            }
-        })
-        .await??;
+        stream.collect::<Vec<_>>().await;
 
-        let message_b = crate::common::timeout(Duration::from_secs(60), async {
+        let message_id = crate::common::timeout(Duration::from_secs(60), async {
             loop {
-                if let Some(MessageEventKind::MessageReceived {
-                    conversation_id,
-                    message_id,
-                }) = conversation_b.next().await
+                if let Some(MessageEventKind::MessageReceived { message_id, .. }) =
+                    conversation_b.next().await
                 {
-                    break instance_b.get_message(conversation_id, message_id).await;
+                    break message_id;
                 }
             }
         })
-        .await??;
+        .await?;
 
-        assert_eq!(message_a, message_b);
-        assert_eq!(message_a.message_type(), MessageType::Attachment);
-        let attachments = message_a.attachments();
-        assert!(!attachments.is_empty());
+        let directory = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        tokio::fs::create_dir_all(&directory).await?;
+        let path = directory.join("image.png");
 
-        let file = attachments.first().expect("attachment exist");
+        let mut progress = instance_b
+            .save_attachment(conversation_id, message_id, "image.png", path.clone())
+            .await?;
 
-        assert_eq!(file.name(), "image.png");
+        let mut completed = false;
+        while let Some(event) = progress.next().await {
+            match event {
+                Progression::ProgressComplete { .. } => completed = true,
+                Progression::ProgressFailed { error, .. } => return Err(error.into()),
+                Progression::CurrentProgress { .. } => {}
+            }
+        }
+        assert!(completed);
 
-        let stream = instance_b
-            .download_stream(conversation_id, message_a.id(), "image.png")
-            .await?;
+        // the file only appears once it was verified against the hash of the attachment
+        assert_eq!(tokio::fs::read(&path).await?, PROFILE_IMAGE);
+        assert!(!directory.join(".image.png.part").exists());
 
-        let data = stream.try_collect::<Vec<_>>().await?.concat();
+        // attachments are only saved under the name they were sent with
+        assert!(instance_b
+            .save_attachment(conversation_id, message_id, "other.png", path)
+            .await
+            .is_err());
 
-        assert_eq!(data, PROFILE_IMAGE);
+        tokio::fs::remove_dir_all(&directory).await?;
         Ok(())
     }
 
@@ -646,6 +1830,107 @@ mod test {
         Ok(())
     }
 
+    #[async_test]
+    async fn conversation_stats_follow_messages() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (
+                None,
+                None,
+                Some("test::conversation_stats_follow_messages".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::conversation_stats_follow_messages".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut instance_a, did_a, _) = accounts.first().cloned().unwrap();
+        let (mut instance_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            let mut id_a = None;
+            let mut id_b = None;
+            loop {
+                tokio::select! {
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_a.next() => {
+                        id_a.replace(conversation_id);
+                    },
+                    Some(RayGunEventKind::ConversationCreated { conversation_id }) = chat_subscribe_b.next() => {
+                        id_b.replace(conversation_id);
+                    },
+                }
+
+                if id_a.is_some() && id_b.is_some() {
+                    assert_eq!(id_a, id_b);
+                    break id_a.expect("valid conversation_id")
+                }
+            }
+        }).await?;
+
+        let mut conversation_a = instance_a.get_conversation_stream(conversation_id).await?;
+        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
+
+        for line in ["Hello", "World"] {
+            instance_a.send(conversation_id, vec![line.into()]).await?;
+        }
+
+        let mut sent = vec![];
+        crate::common::timeout(Duration::from_secs(60), async {
+            while sent.len() < 2 {
+                if let Some(MessageEventKind::MessageSent { message_id, .. }) =
+                    conversation_a.next().await
+                {
+                    sent.push(message_id);
+                }
+            }
+        })
+        .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            let mut received = 0;
+            while received < 2 {
+                if let Some(MessageEventKind::MessageReceived { .. }) = conversation_b.next().await
+                {
+                    received += 1;
+                }
+            }
+        })
+        .await?;
+
+        for instance in [&instance_a, &instance_b] {
+            let stats = instance.conversation_stats(conversation_id).await?;
+            assert_eq!(stats.message_count, 2);
+            assert_eq!(stats.messages_from(&did_a), 2);
+            assert_eq!(stats.messages_from(&did_b), 0);
+            assert_eq!(stats.daily_activity.values().sum::<usize>(), 2);
+            assert!(stats.first_activity <= stats.last_activity);
+        }
+
+        instance_a.delete(conversation_id, Some(sent[0])).await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageDeleted { .. }) = conversation_a.next().await {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let stats = instance_a.conversation_stats(conversation_id).await?;
+        assert_eq!(stats.message_count, 1);
+        assert_eq!(stats.messages_from(&did_a), 1);
+
+        Ok(())
+    }
+
     #[async_test]
     async fn edit_message_in_conversation() -> anyhow::Result<()> {
         let accounts = create_accounts(vec![
@@ -1662,4 +2947,99 @@ mod test {
 
         Ok(())
     }
+
+    #[async_test]
+    async fn search_messages_in_conversation() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (
+                None,
+                None,
+                Some("test::search_messages_in_conversation".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::search_messages_in_conversation".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut instance_a, did_a, _) = accounts.first().cloned().unwrap();
+        let (mut instance_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a.create_conversation(&did_b).await?;
+
+        let conversation_id = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_b.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        let mut conversation_b = instance_b.get_conversation_stream(conversation_id).await?;
+
+        let hello_id = instance_a
+            .send(conversation_id, vec!["Hello, World".into()])
+            .await?;
+        let moon_id = instance_a
+            .send(conversation_id, vec!["Goodbye, Moon".into()])
+            .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            let mut received = 0;
+            while received < 2 {
+                if let Some(MessageEventKind::MessageReceived { .. }) = conversation_b.next().await
+                {
+                    received += 1;
+                }
+            }
+        })
+        .await?;
+
+        // received messages are searchable by the recipient
+        let results = instance_b
+            .search_messages(Some(conversation_id), SearchQuery::new("hello"))
+            .await?
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].message().id(), hello_id);
+        assert!(!results[0].highlights().is_empty());
+
+        // the last word of the query also matches as a prefix
+        let results = instance_b
+            .search_messages(None, SearchQuery::new("moo"))
+            .await?
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].message().id(), moon_id);
+
+        let results = instance_a
+            .search_messages(
+                Some(conversation_id),
+                SearchQuery::new("hello").set_sender(did_b.clone()),
+            )
+            .await?
+            .collect::<Vec<_>>()
+            .await;
+        assert!(results.is_empty());
+
+        let results = instance_a
+            .search_messages(
+                Some(conversation_id),
+                SearchQuery::new("hello").set_sender(did_a),
+            )
+            .await?
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(results.len(), 1);
+        Ok(())
+    }
 }
@@ -0,0 +1,126 @@
+pub mod common;
+#[cfg(test)]
+mod test {
+    use crate::common::{create_account, node};
+    use ipld_core::cid::Cid;
+    use warp::raygun::RayGun;
+    use warp_ipfs::doctor::{AccountDoctor, DoctorCheckKind, DoctorRepair, DoctorStatus};
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::wasm_bindgen_test as async_test;
+
+    #[cfg(target_arch = "wasm32")]
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    use tokio::test as async_test;
+
+    #[async_test]
+    async fn diagnose_healthy_account() -> anyhow::Result<()> {
+        let (account, _, _) = create_account(
+            Some("JohnDoe"),
+            None,
+            Some("test::diagnose_healthy_account".into()),
+        )
+        .await?;
+
+        let report = account.multipass().diagnose().await?;
+        assert!(report.is_healthy());
+        assert_eq!(report.repairs().count(), 0);
+
+        for kind in [
+            DoctorCheckKind::Tesseract,
+            DoctorCheckKind::RootDocument,
+            DoctorCheckKind::Pins,
+            DoctorCheckKind::Shuttle,
+            DoctorCheckKind::Connectivity,
+        ] {
+            assert!(report.checks.iter().any(|check| check.kind == kind));
+        }
+
+        let check = |kind| {
+            report
+                .checks
+                .iter()
+                .find(|check| check.kind == kind)
+                .map(|check| check.status)
+        };
+
+        assert_eq!(check(DoctorCheckKind::Tesseract), Some(DoctorStatus::Ok));
+        assert_eq!(check(DoctorCheckKind::RootDocument), Some(DoctorStatus::Ok));
+        assert_eq!(check(DoctorCheckKind::Pins), Some(DoctorStatus::Ok));
+
+        // nothing to repair, so the account is left as is
+        let repaired = account.multipass().repair().await?;
+        assert_eq!(repaired.checks.len(), report.checks.len());
+        assert!(repaired.is_healthy());
+        Ok(())
+    }
+
+    #[async_test]
+    async fn repair_unpinned_root_document() -> anyhow::Result<()> {
+        let (account, _, _) = create_account(
+            Some("JohnDoe"),
+            None,
+            Some("test::repair_unpinned_root_document".into()),
+        )
+        .await?;
+
+        let ipfs = node(&account);
+        let key = format!("/identity/{}/root", ipfs.keypair().public().to_peer_id());
+        let root_cid = ipfs
+            .repo()
+            .data_store()
+            .get(key.as_bytes())
+            .await?
+            .map(|bytes| String::from_utf8_lossy(&bytes).parse::<Cid>())
+            .transpose()?
+            .expect("root cid stored");
+
+        ipfs.remove_pin(root_cid).recursive().await?;
+
+        let report = account.multipass().diagnose().await?;
+        assert!(!report.is_healthy());
+        assert!(report.checks.iter().any(|check| {
+            check.kind == DoctorCheckKind::Pins
+                && check.status == DoctorStatus::Failed
+                && check.repair == Some(DoctorRepair::PinRootDocument)
+        }));
+
+        let repaired = account.multipass().repair().await?;
+        assert!(repaired.is_healthy());
+        assert!(ipfs.is_pinned(root_cid).await?);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn repair_clean_conversations() -> anyhow::Result<()> {
+        let (mut account_a, _, _) = create_account(
+            Some("JohnDoe"),
+            None,
+            Some("test::repair_clean_conversations".into()),
+        )
+        .await?;
+
+        let (_account_b, did_b, _) = create_account(
+            Some("JaneDoe"),
+            None,
+            Some("test::repair_clean_conversations".into()),
+        )
+        .await?;
+
+        account_a.create_conversation(&did_b).await?;
+
+        let report = account_a.multipass().repair_conversations().await?;
+        assert_eq!(report.checked, 1);
+        assert!(report.is_clean());
+
+        let report = account_a.multipass().diagnose().await?;
+        assert!(report
+            .checks
+            .iter()
+            .any(|check| check.kind == DoctorCheckKind::Conversations
+                && check.status == DoctorStatus::Ok));
+        Ok(())
+    }
+}
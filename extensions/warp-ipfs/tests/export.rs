@@ -0,0 +1,82 @@
+pub mod common;
+#[cfg(test)]
+mod test {
+    use crate::common::create_account;
+    use warp::constellation::Constellation;
+    use warp::multipass::Friends;
+    use warp::raygun::RayGun;
+    use warp_ipfs::export::AccountDataExport;
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::wasm_bindgen_test as async_test;
+
+    #[cfg(target_arch = "wasm32")]
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    use tokio::test as async_test;
+
+    #[async_test]
+    async fn export_account_data() -> anyhow::Result<()> {
+        let (mut account_a, did_a, _) = create_account(
+            Some("JohnDoe"),
+            None,
+            Some("test::export_account_data".into()),
+        )
+        .await?;
+
+        let (_account_b, did_b, _) = create_account(
+            Some("JaneDoe"),
+            None,
+            Some("test::export_account_data".into()),
+        )
+        .await?;
+
+        account_a.send_request(&did_b).await?;
+
+        let conversation_id = account_a.create_conversation(&did_b).await?.id();
+        let message_id = account_a
+            .send(conversation_id, vec!["Hello, World!".into()])
+            .await?;
+
+        account_a.create_directory("notes", false).await?;
+        account_a
+            .put_buffer("/notes/todo.txt", b"export account data")
+            .await?;
+
+        let archive = account_a.multipass().export_account_data().await?;
+        let data = &archive.data;
+
+        assert_eq!(data.identity.did, did_a);
+        assert_eq!(data.identity.username, "JohnDoe");
+
+        assert_eq!(data.requests.len(), 1);
+        assert_eq!(data.requests[0].did, did_b);
+        assert!(data.requests[0].outgoing);
+
+        assert_eq!(data.conversations.len(), 1);
+        let conversation = &data.conversations[0];
+        assert_eq!(conversation.id, conversation_id);
+        assert_eq!(conversation.messages.len(), 1);
+        assert_eq!(conversation.messages[0].id, message_id);
+        assert_eq!(
+            conversation.messages[0].lines,
+            ["Hello, World!".to_string()]
+        );
+
+        assert_eq!(data.files.len(), 1);
+        let file = &data.files[0];
+        assert_eq!(file.path, "notes/todo.txt");
+        let media = file.media.as_deref().expect("file content is exported");
+        assert_eq!(media, "media/files/notes/todo.txt");
+
+        let (_, content) = archive
+            .media
+            .iter()
+            .find(|(path, _)| path == media)
+            .expect("media is in the archive");
+        assert_eq!(&content[..], b"export account data");
+
+        Ok(())
+    }
+}
@@ -3,7 +3,15 @@ mod common;
 #[cfg(test)]
 mod test {
 
+    use std::time::Duration;
+
+    use bytes::Bytes;
     use futures::{stream, StreamExt, TryStreamExt};
+    use warp::constellation::Progression;
+    use warp::multipass::MultiPass;
+    use warp_ipfs::config::Discovery;
+    use warp_ipfs::readiness::Subsystem;
+    use warp_ipfs::store::upload::UPLOAD_CHUNK_SIZE;
 
     use crate::common::{create_account, PROFILE_IMAGE};
 
@@ -27,6 +35,28 @@ mod test {
         Ok(())
     }
 
+    #[async_test]
+    async fn directory_created_during_startup_is_kept() -> anyhow::Result<()> {
+        let (mut fs, _, _) = create_account(None, None, None).await?;
+        let root_directory = fs.root_directory();
+        fs.create_directory("data", false).await?;
+
+        // the directory may be created before the index is resolved, which must not discard it
+        let mut readiness = fs.multipass().readiness_subscribe();
+        crate::common::timeout(Duration::from_secs(30), async {
+            while let Some(event) = readiness.next().await {
+                if event.subsystem == Subsystem::Files {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        assert!(fs.multipass().is_ready(Subsystem::Files));
+        assert!(root_directory.has_item("data"));
+        Ok(())
+    }
+
     #[async_test]
     async fn upload_file() -> anyhow::Result<()> {
         let (mut fs, _, _) = create_account(None, None, None).await?;
@@ -148,6 +178,42 @@ mod test {
         Ok(())
     }
 
+    #[async_test]
+    async fn excluded_directory_is_unpinned() -> anyhow::Result<()> {
+        let (mut fs, _, _) = create_account(None, None, None).await?;
+        let root_directory = fs.root_directory();
+        fs.create_directory("images", false).await?;
+        fs.put_buffer("/images/image.png", PROFILE_IMAGE).await?;
+
+        assert!(fs.constellation().is_hydrated("/images/image.png").await?);
+
+        fs.constellation()
+            .set_directory_excluded("images", true)
+            .await?;
+
+        // the file is kept in the index as a placeholder
+        assert!(root_directory.get_item_by_path("/images/image.png").is_ok());
+        assert!(!fs.constellation().is_hydrated("/images/image.png").await?);
+
+        // the exclusion follows the directory when it is renamed
+        fs.rename("images", "pictures").await?;
+
+        let settings = fs.constellation().selective_sync()?;
+        assert!(settings.is_directory_excluded("pictures"));
+        assert!(!settings.is_directory_excluded("images"));
+
+        fs.constellation()
+            .set_directory_excluded("pictures", false)
+            .await?;
+
+        assert!(
+            fs.constellation()
+                .is_hydrated("/pictures/image.png")
+                .await?
+        );
+        Ok(())
+    }
+
     #[async_test]
     async fn remove_file() -> anyhow::Result<()> {
         let (mut fs, _, _) = create_account(None, None, None).await?;
@@ -162,6 +228,19 @@ mod test {
         Ok(())
     }
 
+    #[async_test]
+    async fn verify_files() -> anyhow::Result<()> {
+        let (mut fs, _, _) = create_account(None, None, None).await?;
+        fs.put_buffer("image.png", PROFILE_IMAGE).await?;
+        fs.create_directory("data", false).await?;
+        fs.put_buffer("data/image.png", PROFILE_IMAGE).await?;
+
+        let report = fs.verify(false).await?;
+        assert_eq!(report.checked, 2);
+        assert!(report.issues.is_empty());
+        Ok(())
+    }
+
     #[async_test]
     async fn check_thumbnail_of_file() -> anyhow::Result<()> {
         let (mut fs, _, _) = create_account(None, None, None).await?;
@@ -179,4 +258,95 @@ mod test {
         assert!(item.thumbnail().is_empty());
         Ok(())
     }
+
+    #[async_test]
+    async fn incomplete_uploads_count_against_quota() -> anyhow::Result<()> {
+        let mut config = crate::common::config(Discovery::None);
+        config.set_max_storage_size(Some(UPLOAD_CHUNK_SIZE * 3));
+
+        let mut fs = crate::common::create_instance_with_config(config).await;
+        fs.create_identity(None, None).await?;
+
+        let session = fs.put_resumable("video.mp4", UPLOAD_CHUNK_SIZE * 2).await?;
+
+        // the first upload has yet to send any data, but its size is reserved
+        assert!(fs
+            .put_resumable("other.mp4", UPLOAD_CHUNK_SIZE * 2)
+            .await
+            .is_err());
+
+        fs.cancel_upload(session.id()).await?;
+        fs.put_resumable("other.mp4", UPLOAD_CHUNK_SIZE * 2).await?;
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[async_test]
+    async fn resume_upload_after_restart() -> anyhow::Result<()> {
+        let path = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+
+        let mut config = crate::common::config(Discovery::None);
+        *config.path_mut() = Some(path.clone());
+        *config.persist_mut() = true;
+
+        let data = (0..UPLOAD_CHUNK_SIZE * 2 + 10)
+            .map(|index| index as u8)
+            .collect::<Vec<_>>();
+
+        let mut fs = crate::common::create_instance_with_config(config.clone()).await;
+        fs.create_identity(None, None).await?;
+
+        let mut session = fs.put_resumable("data.bin", data.len()).await?;
+        let id = session.id();
+
+        // only the first chunk is sent before the application is closed
+        let chunk = Bytes::copy_from_slice(&data[..UPLOAD_CHUNK_SIZE]);
+        let mut progress = session
+            .resume(stream::iter(vec![Ok(chunk)]).boxed())
+            .await?;
+        while progress.next().await.is_some() {}
+        assert_eq!(session.progress().confirmed, UPLOAD_CHUNK_SIZE);
+
+        drop(progress);
+        drop(session);
+        fs.multipass().shutdown().await;
+        drop(fs);
+
+        let mut fs = crate::common::create_instance_with_config(config).await;
+
+        // the identity and the stores are loaded once the keystore is unlocked
+        let mut session = crate::common::timeout(Duration::from_secs(30), async {
+            loop {
+                if let Ok(session) = fs.upload_session(id).await {
+                    break session;
+                }
+                futures_timer::Delay::new(Duration::from_millis(100)).await;
+            }
+        })
+        .await?;
+
+        assert_eq!(fs.upload_sessions().await?, vec![id]);
+        assert_eq!(session.progress().confirmed, UPLOAD_CHUNK_SIZE);
+
+        let rest = Bytes::copy_from_slice(&data[UPLOAD_CHUNK_SIZE..]);
+        let mut progress = session.resume(stream::iter(vec![Ok(rest)]).boxed()).await?;
+
+        let mut completed = false;
+        while let Some(event) = progress.next().await {
+            match event {
+                Progression::ProgressComplete { .. } => completed = true,
+                Progression::ProgressFailed { error, .. } => return Err(error.into()),
+                Progression::CurrentProgress { .. } => {}
+            }
+        }
+        assert!(completed);
+
+        assert_eq!(fs.get_buffer("data.bin").await?, data);
+        assert!(fs.upload_sessions().await?.is_empty());
+
+        fs.multipass().shutdown().await;
+        drop(fs);
+        let _ = tokio::fs::remove_dir_all(path).await;
+        Ok(())
+    }
 }
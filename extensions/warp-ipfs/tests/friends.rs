@@ -3,9 +3,20 @@ pub mod common;
 mod test {
     use std::time::Duration;
 
-    use crate::common::{create_account, create_accounts};
+    use crate::common::{
+        config, create_account, create_accounts, create_instance_with_config, mesh_connect, node,
+    };
     use futures::StreamExt;
-    use warp::multipass::{Friends, MultiPassEvent, MultiPassEventKind};
+    use warp::multipass::contact::{self, Contact, ContactFormat};
+    use warp::multipass::identity::{
+        FieldVisibility, FriendsPrivacy, ProfileField, ProfileFields, RelationshipChange,
+    };
+    use warp::multipass::share::ShareCode;
+    use warp::multipass::{
+        Friends, IdentityInformation, LocalIdentity, MultiPass, MultiPassEvent, MultiPassEventKind,
+    };
+    use warp_ipfs::config::Discovery;
+    use warp_ipfs::time::{Clock, ManualClock};
 
     #[cfg(target_arch = "wasm32")]
     use wasm_bindgen_test::wasm_bindgen_test as async_test;
@@ -57,6 +68,281 @@ mod test {
         Ok(())
     }
 
+    #[async_test]
+    async fn friend_count_shared_with_friends() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (
+                Some("JohnDoe"),
+                None,
+                Some("test::friend_count_shared_with_friends".into()),
+            ),
+            (
+                Some("JaneDoe"),
+                None,
+                Some("test::friend_count_shared_with_friends".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut account_a, did_a, _) = accounts.first().cloned().unwrap();
+        let (mut account_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut subscribe_a = account_a.multipass_subscribe().await?;
+        let mut subscribe_b = account_b.multipass_subscribe().await?;
+        account_a.send_request(&did_b).await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            let did = loop {
+                if let Some(MultiPassEventKind::FriendRequestReceived { from, .. }) =
+                    subscribe_b.next().await
+                {
+                    break from;
+                }
+            };
+            account_b.accept_request(&did).await
+        })
+        .await??;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MultiPassEventKind::FriendAdded { .. }) = subscribe_a.next().await {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        // Nothing about the friends list is shared by default
+        assert_eq!(
+            account_a.friends_privacy().await?,
+            FriendsPrivacy::default()
+        );
+        assert_eq!(account_b.friend_count(&did_a).await?, None);
+        assert!(account_b.mutual_friends(&did_a).await?.is_empty());
+        assert_eq!(account_a.friend_count(&did_a).await?, Some(1));
+
+        let mut privacy = FriendsPrivacy::default();
+        privacy.set_friend_count(Some(FieldVisibility::Friends));
+        account_a.set_friends_privacy(privacy).await?;
+        assert_eq!(account_a.friends_privacy().await?, privacy);
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Ok(Some(1)) = account_b.friend_count(&did_a).await {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        account_a
+            .set_friends_privacy(FriendsPrivacy::default())
+            .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Ok(None) = account_b.friend_count(&did_a).await {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn profile_fields_visible_to_friends() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (
+                Some("JohnDoe"),
+                None,
+                Some("test::profile_fields_visible_to_friends".into()),
+            ),
+            (
+                Some("JaneDoe"),
+                None,
+                Some("test::profile_fields_visible_to_friends".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut account_a, did_a, _) = accounts.first().cloned().unwrap();
+        let (mut account_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut subscribe_a = account_a.multipass_subscribe().await?;
+        let mut subscribe_b = account_b.multipass_subscribe().await?;
+        account_a.send_request(&did_b).await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            let did = loop {
+                if let Some(MultiPassEventKind::FriendRequestReceived { from, .. }) =
+                    subscribe_b.next().await
+                {
+                    break from;
+                }
+            };
+            account_b.accept_request(&did).await
+        })
+        .await??;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MultiPassEventKind::FriendAdded { .. }) = subscribe_a.next().await {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let mut fields = ProfileFields::default();
+        fields.set_pronouns(Some(ProfileField::new("he/him", FieldVisibility::Public)));
+        fields.set_timezone(Some(ProfileField::new(
+            "Asia/Tokyo",
+            FieldVisibility::Friends,
+        )));
+
+        account_b
+            .set_identity_profile_fields(fields.clone())
+            .await?;
+
+        let remote_fields = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Ok(fields) = account_a.identity_profile_fields(&did_b).await {
+                    if fields.timezone().is_some() {
+                        break fields;
+                    }
+                }
+            }
+        })
+        .await?;
+
+        assert_eq!(remote_fields, fields);
+        assert!(account_a.has_friend(&did_b).await?);
+        assert!(account_b.has_friend(&did_a).await?);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn send_request_with_message() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (Some("JohnDoe"), None, Some("test::request_message".into())),
+            (Some("JaneDoe"), None, Some("test::request_message".into())),
+        ])
+        .await?;
+
+        let (mut account_a, did_a, _) = accounts.first().cloned().unwrap();
+        let (mut account_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut subscribe_b = account_b.multipass_subscribe().await?;
+
+        let long_message = "a".repeat(1024);
+        assert!(account_a
+            .send_request_with_message(&did_b, &long_message)
+            .await
+            .is_err());
+
+        account_a
+            .send_request_with_message(&did_b, "Hello from JohnDoe")
+            .await?;
+
+        let message = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MultiPassEventKind::FriendRequestReceived { from, message, .. }) =
+                    subscribe_b.next().await
+                {
+                    assert_eq!(from, did_a);
+                    break message;
+                }
+            }
+        })
+        .await?;
+
+        assert_eq!(message.as_deref(), Some("Hello from JohnDoe"));
+
+        let incoming = account_b.list_incoming_request().await?;
+        assert_eq!(incoming.len(), 1);
+        assert_eq!(incoming[0].message(), Some("Hello from JohnDoe"));
+
+        let outgoing = account_a.list_outgoing_request().await?;
+        assert_eq!(outgoing.len(), 1);
+        assert_eq!(outgoing[0].message(), Some("Hello from JohnDoe"));
+        Ok(())
+    }
+
+    #[async_test]
+    async fn relationship_history() -> anyhow::Result<()> {
+        let mut accounts = vec![];
+        for username in ["JohnDoe", "JaneDoe"] {
+            let mut config = config(Discovery::None);
+            config.store_setting_mut().relationship_history = true;
+
+            let mut account = create_instance_with_config(config).await;
+            let profile = account.create_identity(Some(username), None).await?;
+            let did = profile.identity().did_key().clone();
+            accounts.push((account, did));
+        }
+
+        mesh_connect(accounts.iter().map(|(account, _)| node(account)).collect()).await?;
+
+        let (mut account_a, did_a) = accounts.first().cloned().unwrap();
+        let (mut account_b, did_b) = accounts.last().cloned().unwrap();
+
+        let mut subscribe_a = account_a.multipass_subscribe().await?;
+        let mut subscribe_b = account_b.multipass_subscribe().await?;
+
+        account_a.send_request(&did_b).await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            let did = loop {
+                if let Some(MultiPassEventKind::FriendRequestReceived { from, .. }) =
+                    subscribe_b.next().await
+                {
+                    break from;
+                }
+            };
+            account_b.accept_request(&did).await
+        })
+        .await??;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MultiPassEventKind::FriendAdded { .. }) = subscribe_a.next().await {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        account_a.block(&did_b).await?;
+        account_a.unblock(&did_b).await?;
+
+        let history = account_a
+            .relationship_history(&did_b)
+            .await?
+            .iter()
+            .map(|entry| entry.change())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            history,
+            vec![
+                RelationshipChange::RequestSent,
+                RelationshipChange::FriendAdded,
+                RelationshipChange::FriendRemoved,
+                RelationshipChange::Blocked,
+                RelationshipChange::Unblocked,
+            ]
+        );
+
+        let history = account_b.relationship_history(&did_a).await?;
+        assert!(matches!(
+            history.first().map(|entry| entry.change()),
+            Some(RelationshipChange::RequestReceived)
+        ));
+        Ok(())
+    }
+
     #[async_test]
     async fn remove_friend() -> anyhow::Result<()> {
         let accounts = create_accounts(vec![
@@ -214,6 +500,124 @@ mod test {
         Ok(())
     }
 
+    #[async_test]
+    async fn resend_request() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (Some("JohnDoe"), None, Some("test::resend_request".into())),
+            (Some("JaneDoe"), None, Some("test::resend_request".into())),
+        ])
+        .await?;
+
+        let (mut account_a, _, _) = accounts.first().cloned().unwrap();
+        let (mut account_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut subscribe_b = account_b.multipass_subscribe().await?;
+
+        account_a.send_request_with_message(&did_b, "hello").await?;
+
+        let first_date = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MultiPassEventKind::FriendRequestReceived { date, .. }) =
+                    subscribe_b.next().await
+                {
+                    break date;
+                }
+            }
+        })
+        .await?;
+
+        account_a.resend_request(&did_b).await?;
+
+        let (second_date, message) = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MultiPassEventKind::FriendRequestReceived { date, message, .. }) =
+                    subscribe_b.next().await
+                {
+                    break (date, message);
+                }
+            }
+        })
+        .await?;
+
+        assert!(second_date > first_date);
+        assert_eq!(message.as_deref(), Some("hello"));
+
+        let outgoing = account_a.list_outgoing_request().await?;
+        assert_eq!(outgoing.len(), 1);
+
+        let incoming = account_b.list_incoming_request().await?;
+        assert_eq!(incoming.len(), 1);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn outgoing_request_expires() -> anyhow::Result<()> {
+        let clock = ManualClock::new();
+
+        let mut config = config(Discovery::None);
+        config.store_setting_mut().friend_request_ttl = Some(Duration::from_secs(60 * 60));
+        *config.clock_mut() = Clock::new(clock.clone());
+
+        let mut account_a = create_instance_with_config(config).await;
+        account_a.create_identity(Some("JohnDoe"), None).await?;
+
+        let (mut account_b, did_b, _) = create_account(
+            Some("JaneDoe"),
+            None,
+            Some("test::outgoing_request_expires".into()),
+        )
+        .await?;
+
+        mesh_connect(vec![node(&account_a), node(&account_b)]).await?;
+
+        let mut subscribe_a = account_a.multipass_subscribe().await?;
+        let mut subscribe_b = account_b.multipass_subscribe().await?;
+
+        account_a.send_request(&did_b).await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MultiPassEventKind::FriendRequestReceived { .. }) =
+                    subscribe_b.next().await
+                {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        // the request is kept until the ttl elapsed
+        assert_eq!(account_a.list_outgoing_request().await?.len(), 1);
+
+        clock.advance(Duration::from_secs(2 * 60 * 60));
+
+        // requests are checked for expiry every minute of the clock, which was advanced past it
+        let did = crate::common::timeout(Duration::from_secs(10), async {
+            loop {
+                if let Some(MultiPassEventKind::FriendRequestExpired { did }) =
+                    subscribe_a.next().await
+                {
+                    break did;
+                }
+            }
+        })
+        .await?;
+
+        assert_eq!(did, did_b);
+        assert!(account_a.list_outgoing_request().await?.is_empty());
+
+        // the recipient drops the incoming request once the expiry is delivered
+        crate::common::timeout(Duration::from_secs(60), async {
+            while !account_b.list_incoming_request().await?.is_empty() {
+                futures_timer::Delay::new(Duration::from_millis(500)).await;
+            }
+            Ok::<_, anyhow::Error>(())
+        })
+        .await??;
+
+        Ok(())
+    }
+
     #[async_test]
     async fn incoming_request() -> anyhow::Result<()> {
         let accounts = create_accounts(vec![
@@ -383,4 +787,106 @@ mod test {
 
         Ok(())
     }
+
+    #[async_test]
+    async fn import_export_contacts() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (Some("JohnDoe"), None, Some("test::import_contacts".into())),
+            (Some("JaneDoe"), None, Some("test::import_contacts".into())),
+        ])
+        .await?;
+
+        let (mut account_a, did_a, _) = accounts.first().cloned().unwrap();
+        let (mut account_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut subscribe_a = account_a.multipass_subscribe().await?;
+        let mut subscribe_b = account_b.multipass_subscribe().await?;
+
+        let card = contact::encode(
+            &[
+                Contact::new(did_a.clone(), Some("JohnDoe".into())),
+                Contact::new(did_b.clone(), Some("JaneDoe".into())),
+            ],
+            ContactFormat::VCard,
+        )?;
+
+        let imported = account_a
+            .import_contacts(ContactFormat::VCard, &card)
+            .await?;
+        assert_eq!(imported, vec![did_b.clone()]);
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MultiPassEventKind::FriendRequestReceived { from, .. }) =
+                    subscribe_b.next().await
+                {
+                    assert_eq!(from, did_a);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        // importing the same list again should not create any additional request
+        let imported = account_a
+            .import_contacts(ContactFormat::VCard, &card)
+            .await?;
+        assert!(imported.is_empty());
+
+        // an import containing a pending incoming request should accept it
+        let imported = account_b
+            .import_contacts(ContactFormat::VCard, &card)
+            .await?;
+        assert_eq!(imported, vec![did_a.clone()]);
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MultiPassEventKind::FriendAdded { .. }) = subscribe_a.next().await {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let exported = account_a.export_contacts(ContactFormat::Json).await?;
+        let contacts = contact::decode(&exported, ContactFormat::Json)?;
+        assert_eq!(contacts.len(), 1);
+        assert_eq!(contacts[0].did(), &did_b);
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn add_friend_from_share_code() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (Some("JohnDoe"), None, Some("test::share_code".into())),
+            (Some("JaneDoe"), None, Some("test::share_code".into())),
+        ])
+        .await?;
+
+        let (mut account_a, did_a, _) = accounts.first().cloned().unwrap();
+        let (mut account_b, _, _) = accounts.last().cloned().unwrap();
+
+        let mut subscribe_a = account_a.multipass_subscribe().await?;
+
+        let code = account_a.share_code().await?;
+        let share_code: ShareCode = code.parse()?;
+        assert_eq!(share_code.did(), &did_a);
+        assert_eq!(share_code.username(), Some("JohnDoe"));
+
+        let did = account_b.add_from_share_code(&code).await?;
+        assert_eq!(did, did_a);
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MultiPassEventKind::FriendRequestReceived { .. }) =
+                    subscribe_a.next().await
+                {
+                    break;
+                }
+            }
+        })
+        .await?;
+        Ok(())
+    }
 }
@@ -8,7 +8,9 @@ mod test {
     use warp::{
         multipass::MultiPassEventKind,
         raygun::{
-            ConversationType, GroupPermission, GroupPermissions, MessageEventKind, RayGunEventKind,
+            ConversationType, GroupPermission, GroupPermissions, HistoryVisibility,
+            MessageEventKind, MessageOptions, MessageType, Messages, RayGunEventKind,
+            RetentionPolicy, SystemMessage,
         },
     };
 
@@ -22,10 +24,13 @@ mod test {
     use tokio::test as async_test;
 
     use warp::multipass::{Friends, MultiPassEvent};
-    use warp::raygun::{RayGun, RayGunGroupConversation, RayGunStream};
+    use warp::raygun::{
+        RayGun, RayGunConversationInformation, RayGunGroupConversation, RayGunStream,
+    };
 
     use uuid::Uuid;
     use warp::error::Error;
+    use warp_ipfs::WarpIpfsInstance;
 
     #[async_test]
     async fn create_empty_group_conversation() -> anyhow::Result<()> {
@@ -63,6 +68,160 @@ mod test {
         Ok(())
     }
 
+    #[async_test]
+    async fn legal_hold_prevents_deletion() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![(
+            None,
+            None,
+            Some("test::legal_hold_prevents_deletion".into()),
+        )])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts[0].clone();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+
+        instance_a
+            .create_group_conversation(None, vec![], GroupPermissions::new())
+            .await?;
+
+        let id_a = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_a.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        let mut conversation_a = instance_a.get_conversation_stream(id_a).await?;
+
+        let policy = RetentionPolicy {
+            legal_hold: true,
+            ..Default::default()
+        };
+
+        instance_a.set_retention_policy(id_a, Some(policy)).await?;
+
+        let conversation = instance_a.get_conversation(id_a).await?;
+        assert_eq!(conversation.retention_policy(), Some(&policy));
+
+        let message_id = instance_a.send(id_a, vec!["Hello, World".into()]).await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageSent { .. }) = conversation_a.next().await {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let result = instance_a.delete(id_a, Some(message_id)).await;
+        assert!(matches!(result, Err(Error::MessageRetained)));
+
+        let result = instance_a.delete(id_a, None).await;
+        assert!(matches!(result, Err(Error::MessageRetained)));
+
+        instance_a.set_retention_policy(id_a, None).await?;
+        instance_a.delete(id_a, Some(message_id)).await?;
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn retention_policy_is_distributed_to_members() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (
+                None,
+                None,
+                Some("test::retention_policy_is_distributed_to_members".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::retention_policy_is_distributed_to_members".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts[0].clone();
+        let (mut instance_b, did_b, _) = accounts[1].clone();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a
+            .create_group_conversation(None, vec![did_b.clone()], GroupPermissions::new())
+            .await?;
+
+        let id_a = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_a.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        let id_b = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_b.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        let mut conversation_b = instance_b.get_conversation_stream(id_b).await?;
+
+        let policy = RetentionPolicy {
+            minimum: Some(3600),
+            ..Default::default()
+        };
+
+        // members that are neither the creator nor an administrator of the organization can not set the policy
+        let result = instance_b.set_retention_policy(id_b, Some(policy)).await;
+        assert!(matches!(result, Err(Error::Unauthorized)));
+
+        // the policy is set once the features advertised by the member are known
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if instance_a
+                    .set_retention_policy(id_a, Some(policy))
+                    .await
+                    .is_ok()
+                {
+                    break;
+                }
+                futures_timer::Delay::new(Duration::from_millis(500)).await;
+            }
+        })
+        .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::ConversationRetentionPolicyChanged { .. }) =
+                    conversation_b.next().await
+                {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let conversation = instance_b.get_conversation(id_b).await?;
+        assert_eq!(conversation.retention_policy(), Some(&policy));
+
+        Ok(())
+    }
+
     #[async_test]
     async fn update_group_conversation_name() -> anyhow::Result<()> {
         let accounts = create_accounts(vec![(
@@ -131,6 +290,66 @@ mod test {
         Ok(())
     }
 
+    #[async_test]
+    async fn conversation_changes_are_recorded_as_system_messages() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![(
+            None,
+            None,
+            Some("test::conversation_changes_are_recorded_as_system_messages".into()),
+        )])
+        .await?;
+
+        let (mut instance_a, did_a, _) = accounts[0].clone();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+
+        instance_a
+            .create_group_conversation(None, vec![], GroupPermissions::new())
+            .await?;
+
+        let id_a = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_a.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        instance_a.update_conversation_name(id_a, "test").await?;
+        instance_a.update_conversation_name(id_a, "").await?;
+
+        let Messages::List(messages) = instance_a
+            .get_messages(id_a, MessageOptions::default())
+            .await?
+        else {
+            panic!("should be Messages::List");
+        };
+
+        let system_messages = messages
+            .iter()
+            .filter(|message| message.message_type() == MessageType::Event)
+            .filter_map(|message| message.system_message().cloned())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            system_messages,
+            vec![
+                SystemMessage::NameChanged {
+                    actor: did_a.clone(),
+                    name: "test".into(),
+                },
+                SystemMessage::NameRemoved { actor: did_a },
+            ]
+        );
+        assert_eq!(system_messages[0].key(), "conversation.name_changed");
+        assert_eq!(system_messages[0].params()["name"], "test");
+
+        Ok(())
+    }
+
     #[async_test]
     async fn create_group_conversation() -> anyhow::Result<()> {
         let accounts = create_accounts(vec![
@@ -1258,6 +1477,143 @@ mod test {
         Ok(())
     }
 
+    #[async_test]
+    async fn messages_are_readable_across_key_epochs() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (
+                None,
+                None,
+                Some("test::messages_are_readable_across_key_epochs".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::messages_are_readable_across_key_epochs".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::messages_are_readable_across_key_epochs".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts[0].clone();
+        let (mut instance_b, did_b, _) = accounts[1].clone();
+        let (mut instance_c, did_c, _) = accounts[2].clone();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+        let mut chat_subscribe_c = instance_c.raygun_subscribe().await?;
+
+        instance_a
+            .create_group_conversation(
+                None,
+                vec![did_b.clone(), did_c.clone()],
+                GroupPermissions::new(),
+            )
+            .await?;
+
+        let mut ids = vec![];
+        for subscribe in [
+            &mut chat_subscribe_a,
+            &mut chat_subscribe_b,
+            &mut chat_subscribe_c,
+        ] {
+            let id = crate::common::timeout(Duration::from_secs(60), async {
+                loop {
+                    if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                        subscribe.next().await
+                    {
+                        break conversation_id;
+                    }
+                }
+            })
+            .await?;
+            ids.push(id);
+        }
+
+        let id = ids[0];
+        assert!(ids.iter().all(|other| *other == id));
+
+        let mut conversation_a = instance_a.get_conversation_stream(id).await?;
+        let mut conversation_b = instance_b.get_conversation_stream(id).await?;
+
+        instance_a.send(id, vec!["First epoch".into()]).await?;
+
+        let first = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived {
+                    conversation_id,
+                    message_id,
+                }) = conversation_b.next().await
+                {
+                    break instance_b.get_message(conversation_id, message_id);
+                }
+            }
+            .await
+        })
+        .await??;
+
+        assert_eq!(first.lines(), ["First epoch".to_string()]);
+
+        // removing a member rotates the keys of the remaining members to a new epoch
+        instance_a.remove_recipient(id, &did_c).await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::RecipientRemoved { recipient, .. }) =
+                    conversation_b.next().await
+                {
+                    assert_eq!(recipient, did_c);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        instance_a.send(id, vec!["Second epoch".into()]).await?;
+
+        let second = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived {
+                    conversation_id,
+                    message_id,
+                }) = conversation_b.next().await
+                {
+                    break instance_b.get_message(conversation_id, message_id);
+                }
+            }
+            .await
+        })
+        .await??;
+
+        assert_eq!(second.lines(), ["Second epoch".to_string()]);
+
+        instance_b.send(id, vec!["Reply".into()]).await?;
+
+        let reply = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived {
+                    conversation_id,
+                    message_id,
+                }) = conversation_a.next().await
+                {
+                    break instance_a.get_message(conversation_id, message_id);
+                }
+            }
+            .await
+        })
+        .await??;
+
+        assert_eq!(reply.lines(), ["Reply".to_string()]);
+
+        // messages of the previous epoch remain readable
+        let message = instance_b.get_message(id, first.id()).await?;
+        assert_eq!(message.lines(), ["First epoch".to_string()]);
+        Ok(())
+    }
+
     #[async_test]
     async fn remove_recipient_from_conversation_when_blocked() -> anyhow::Result<()> {
         let accounts = create_accounts(vec![
@@ -1629,4 +1985,305 @@ mod test {
 
         Ok(())
     }
+
+    #[async_test]
+    async fn slow_mode_in_group_conversation() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (
+                None,
+                None,
+                Some("test::slow_mode_in_group_conversation".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::slow_mode_in_group_conversation".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts[0].clone();
+        let (mut instance_b, did_b, _) = accounts[1].clone();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a
+            .create_group_conversation(None, vec![did_b.clone()], GroupPermissions::new())
+            .await?;
+
+        let id_a = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_a.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        let id_b = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_b.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        let mut conversation_b = instance_b.get_conversation_stream(id_b).await?;
+
+        // only the creator is able to change slow mode
+        let result = instance_b.set_slow_mode(id_b, 60).await;
+        assert!(matches!(result, Err(Error::Unauthorized)));
+
+        instance_a.set_slow_mode(id_a, 60).await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::ConversationSlowModeChanged {
+                    conversation_id,
+                    slow_mode,
+                }) = conversation_b.next().await
+                {
+                    assert_eq!(conversation_id, id_b);
+                    assert_eq!(slow_mode, 60);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let conversation = instance_b.get_conversation(id_b).await?;
+        assert_eq!(conversation.slow_mode(), 60);
+
+        instance_b.send(id_b, vec!["First".into()]).await?;
+        let result = instance_b.send(id_b, vec!["Second".into()]).await;
+        assert!(matches!(result, Err(Error::SlowModeActive { .. })));
+
+        // the creator is exempt
+        instance_a.send(id_a, vec!["First".into()]).await?;
+        instance_a.send(id_a, vec!["Second".into()]).await?;
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn history_visibility_set_by_creator() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (
+                None,
+                None,
+                Some("test::history_visibility_set_by_creator".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::history_visibility_set_by_creator".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts[0].clone();
+        let (mut instance_b, did_b, _) = accounts[1].clone();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+
+        instance_a
+            .create_group_conversation(None, vec![did_b.clone()], GroupPermissions::new())
+            .await?;
+
+        let mut ids = vec![];
+        for subscribe in [&mut chat_subscribe_a, &mut chat_subscribe_b] {
+            let id = crate::common::timeout(Duration::from_secs(60), async {
+                loop {
+                    if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                        subscribe.next().await
+                    {
+                        break conversation_id;
+                    }
+                }
+            })
+            .await?;
+            ids.push(id);
+        }
+
+        let id = ids[0];
+        assert_eq!(ids[1], id);
+
+        let conversation = instance_b.get_conversation(id).await?;
+        assert_eq!(
+            conversation.history_visibility(),
+            HistoryVisibility::FromJoin
+        );
+
+        let mut conversation_b = instance_b.get_conversation_stream(id).await?;
+
+        let result = instance_b
+            .set_history_visibility(id, HistoryVisibility::All)
+            .await;
+        assert!(matches!(result, Err(Error::Unauthorized)));
+
+        instance_a
+            .set_history_visibility(id, HistoryVisibility::All)
+            .await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::ConversationHistoryVisibilityChanged {
+                    conversation_id,
+                    visibility,
+                }) = conversation_b.next().await
+                {
+                    assert_eq!(conversation_id, id);
+                    assert_eq!(visibility, HistoryVisibility::All);
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let conversation = instance_b.get_conversation(id).await?;
+        assert_eq!(conversation.history_visibility(), HistoryVisibility::All);
+        Ok(())
+    }
+
+    /// Sends a message to a group before and after a new member is added with the given history visibility.
+    /// Returns the new member along with the conversation and the message sent before they joined, once they
+    /// have read the message sent after
+    async fn join_group_with_history(
+        context: &str,
+        visibility: HistoryVisibility,
+    ) -> anyhow::Result<(WarpIpfsInstance, Uuid, Uuid)> {
+        let accounts = create_accounts(vec![
+            (None, None, Some(context.into())),
+            (None, None, Some(context.into())),
+            (None, None, Some(context.into())),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts[0].clone();
+        let (mut instance_b, did_b, _) = accounts[1].clone();
+        let (mut instance_c, did_c, _) = accounts[2].clone();
+
+        let mut chat_subscribe_a = instance_a.raygun_subscribe().await?;
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+        let mut chat_subscribe_c = instance_c.raygun_subscribe().await?;
+
+        instance_a
+            .create_group_conversation(None, vec![did_b.clone()], GroupPermissions::new())
+            .await?;
+
+        let mut ids = vec![];
+        for subscribe in [&mut chat_subscribe_a, &mut chat_subscribe_b] {
+            let id = crate::common::timeout(Duration::from_secs(60), async {
+                loop {
+                    if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                        subscribe.next().await
+                    {
+                        break conversation_id;
+                    }
+                }
+            })
+            .await?;
+            ids.push(id);
+        }
+
+        let id = ids[0];
+        assert_eq!(ids[1], id);
+
+        instance_a.set_history_visibility(id, visibility).await?;
+
+        let mut conversation_b = instance_b.get_conversation_stream(id).await?;
+
+        let before = instance_a.send(id, vec!["Before joining".into()]).await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived { message_id, .. }) =
+                    conversation_b.next().await
+                {
+                    if message_id == before {
+                        break;
+                    }
+                }
+            }
+        })
+        .await?;
+
+        instance_a.add_recipient(id, &did_c).await?;
+
+        let id_c = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_c.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+        assert_eq!(id_c, id);
+
+        let mut conversation_c = instance_c.get_conversation_stream(id).await?;
+
+        let after = instance_a.send(id, vec!["After joining".into()]).await?;
+
+        let message = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived { message_id, .. }) =
+                    conversation_c.next().await
+                {
+                    if message_id == after {
+                        break instance_c.get_message(id, message_id).await;
+                    }
+                }
+            }
+        })
+        .await??;
+
+        assert_eq!(message.lines(), ["After joining".to_string()]);
+
+        Ok((instance_c, id, before))
+    }
+
+    #[async_test]
+    async fn new_member_reads_history_when_visible() -> anyhow::Result<()> {
+        let (instance_c, id, before) = join_group_with_history(
+            "test::new_member_reads_history_when_visible",
+            HistoryVisibility::All,
+        )
+        .await?;
+
+        // the message sent before joining is caught up from the other members and readable with the shared keys
+        let message = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Ok(message) = instance_c.get_message(id, before).await {
+                    break message;
+                }
+                futures_timer::Delay::new(Duration::from_millis(100)).await;
+            }
+        })
+        .await?;
+
+        assert_eq!(message.lines(), ["Before joining".to_string()]);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn new_member_cannot_read_history_from_before_joining() -> anyhow::Result<()> {
+        let (instance_c, id, before) = join_group_with_history(
+            "test::new_member_cannot_read_history_from_before_joining",
+            HistoryVisibility::FromJoin,
+        )
+        .await?;
+
+        // only the keys from the epoch the member joined at are shared with them
+        assert!(instance_c.get_message(id, before).await.is_err());
+        Ok(())
+    }
 }
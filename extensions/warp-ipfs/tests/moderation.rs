@@ -0,0 +1,135 @@
+mod common;
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::common::create_accounts;
+    use futures::StreamExt;
+    use warp::raygun::{GroupPermissions, MessageEventKind, RayGun, RayGunEventKind, RayGunStream};
+    use warp_ipfs::moderation::{MessageReporting, ReportStatus, ReportTarget};
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::wasm_bindgen_test as async_test;
+
+    #[cfg(target_arch = "wasm32")]
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    use tokio::test as async_test;
+
+    #[async_test]
+    async fn report_is_delivered_to_group_creator() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (
+                None,
+                None,
+                Some("test::report_is_delivered_to_group_creator".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::report_is_delivered_to_group_creator".into()),
+            ),
+            (
+                None,
+                None,
+                Some("test::report_is_delivered_to_group_creator".into()),
+            ),
+        ])
+        .await?;
+
+        let (mut instance_a, _, _) = accounts[0].clone();
+        let (mut instance_b, did_b, _) = accounts[1].clone();
+        let (mut instance_c, did_c, _) = accounts[2].clone();
+
+        let mut chat_subscribe_b = instance_b.raygun_subscribe().await?;
+        let mut chat_subscribe_c = instance_c.raygun_subscribe().await?;
+
+        instance_a
+            .create_group_conversation(
+                None,
+                vec![did_b.clone(), did_c.clone()],
+                GroupPermissions::new(),
+            )
+            .await?;
+
+        let id_b = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_b.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        let id_c = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(RayGunEventKind::ConversationCreated { conversation_id }) =
+                    chat_subscribe_c.next().await
+                {
+                    break conversation_id;
+                }
+            }
+        })
+        .await?;
+
+        let mut conversation_b = instance_b.get_conversation_stream(id_b).await?;
+
+        instance_c.send(id_c, vec!["Buy now!".into()]).await?;
+
+        let message_id = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MessageEventKind::MessageReceived { message_id, .. }) =
+                    conversation_b.next().await
+                {
+                    break message_id;
+                }
+            }
+        })
+        .await?;
+
+        // reporting your own message is rejected
+        assert!(instance_c
+            .multipass()
+            .report_message(id_c, message_id, "spam")
+            .await
+            .is_err());
+
+        let report_id = instance_b
+            .multipass()
+            .report_message(id_b, message_id, "spam")
+            .await?;
+
+        let report = crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Ok(report) = instance_a.multipass().moderation_report(report_id).await {
+                    break report;
+                }
+                futures_timer::Delay::new(Duration::from_millis(500)).await;
+            }
+        })
+        .await?;
+
+        assert_eq!(
+            report.target(),
+            ReportTarget::Conversation {
+                conversation_id: id_b
+            }
+        );
+        assert_eq!(report.message_id(), message_id);
+        assert_eq!(report.message_sender(), &did_c);
+        assert_eq!(report.reporter(), &did_b);
+        assert_eq!(report.status(), ReportStatus::Pending);
+
+        // members that do not moderate the conversation never receive the report
+        assert!(instance_c
+            .multipass()
+            .moderation_reports()
+            .await?
+            .is_empty());
+
+        Ok(())
+    }
+}
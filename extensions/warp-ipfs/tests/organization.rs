@@ -0,0 +1,131 @@
+mod common;
+
+#[cfg(test)]
+#[cfg(not(target_arch = "wasm32"))]
+mod test {
+    use std::time::Duration;
+
+    use futures::StreamExt;
+    use rust_ipfs::{Keypair, Multiaddr, Protocol};
+    use uuid::Uuid;
+    use warp::crypto::DID;
+    use warp::error::Error;
+    use warp::multipass::{Friends, MultiPass};
+    use warp_ipfs::config::Discovery;
+    use warp_ipfs::shuttle::identity::organization::OrganizationRoster;
+    use warp_ipfs::store::payload::PayloadMessage;
+    use warp_ipfs::store::PeerIdExt;
+    use warp_ipfs::WarpIpfsInstance;
+
+    use crate::common;
+
+    use tokio::test as async_test;
+
+    async fn create_member(
+        organization: &DID,
+        discovery: &Discovery,
+    ) -> anyhow::Result<(WarpIpfsInstance, DID)> {
+        let mut config = common::config(discovery.clone());
+        config.store_setting_mut().organization = Some(organization.clone());
+
+        let mut instance = common::create_instance_with_config(config).await;
+        let profile = instance.create_identity(None, None).await?;
+        let did = profile.identity().did_key().clone();
+        Ok((instance, did))
+    }
+
+    // Members are added as friends once they are enrolled, and revoked members are blocked
+    #[async_test]
+    async fn roster_is_applied_by_members() -> anyhow::Result<()> {
+        let keypair = Keypair::generate_ed25519();
+        let organization = keypair.to_did()?;
+
+        // the directory listens on a known address so the members can be configured before it is started
+        let port = Uuid::new_v4().as_u64_pair().0;
+        let address = Multiaddr::empty().with(Protocol::Memory(port));
+        let discovery = Discovery::Shuttle {
+            addresses: vec![address
+                .clone()
+                .with(Protocol::P2p(keypair.public().to_peer_id()))],
+        };
+
+        let (admin, did_admin) = create_member(&organization, &discovery).await?;
+        let (member_a, did_a) = create_member(&organization, &discovery).await?;
+        let (member_b, did_b) = create_member(&organization, &discovery).await?;
+        let (outsider, _, _) = common::create_account_with_discovery(
+            None,
+            None,
+            Some("test::roster_is_applied_by_members".into()),
+            discovery.clone(),
+        )
+        .await?;
+
+        let _directory =
+            common::create_organization_directory(&keypair, address, &[did_admin]).await?;
+
+        for instance in [&admin, &member_a, &member_b, &outsider] {
+            common::wait_for_registration(instance).await?;
+        }
+
+        // anyone is able to subscribe to the topic the roster is pushed on
+        let mut roster_stream = common::node(&outsider)
+            .pubsub_subscribe(format!("/id/{organization}/organization"))
+            .await?
+            .boxed();
+
+        // only administrators are able to enroll members
+        let result = member_a
+            .multipass()
+            .enroll_organization_member(&did_b)
+            .await;
+        assert!(matches!(result, Err(Error::Unauthorized)));
+
+        let certificate = admin.multipass().enroll_organization_member(&did_a).await?;
+        assert_eq!(certificate.member, did_a);
+
+        admin.multipass().enroll_organization_member(&did_b).await?;
+
+        // the roster is pushed to the members by the directory
+        common::timeout(Duration::from_secs(60), async {
+            loop {
+                if member_a.has_friend(&did_b).await.unwrap_or_default()
+                    && member_b.has_friend(&did_a).await.unwrap_or_default()
+                {
+                    break;
+                }
+                futures_timer::Delay::new(Duration::from_millis(500)).await;
+            }
+        })
+        .await?;
+
+        // the roster is only readable by the members it is encrypted to
+        let message = common::timeout(Duration::from_secs(60), roster_stream.next())
+            .await?
+            .expect("roster pushed");
+        let payload = PayloadMessage::<OrganizationRoster>::from_bytes(&message.data)?;
+        assert!(payload.message(None).is_err());
+
+        let roster = member_a
+            .multipass()
+            .organization_roster()
+            .await?
+            .expect("roster received");
+        assert!(roster.is_member(&did_a));
+        assert!(roster.is_member(&did_b));
+
+        admin.multipass().revoke_organization_member(&did_b).await?;
+
+        common::timeout(Duration::from_secs(60), async {
+            loop {
+                if member_a.is_blocked(&did_b).await.unwrap_or_default() {
+                    break;
+                }
+                futures_timer::Delay::new(Duration::from_millis(500)).await;
+            }
+        })
+        .await?;
+
+        assert!(!member_a.has_friend(&did_b).await?);
+        Ok(())
+    }
+}
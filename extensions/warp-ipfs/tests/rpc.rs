@@ -0,0 +1,119 @@
+pub mod common;
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::common::create_accounts;
+    use bytes::Bytes;
+    use futures::future::Either;
+    use futures::StreamExt;
+    use warp::error::Error;
+    use warp::multipass::{Friends, MultiPassEvent, MultiPassEventKind};
+    use warp_ipfs::rpc::PeerRpc;
+
+    #[cfg(target_arch = "wasm32")]
+    use wasm_bindgen_test::wasm_bindgen_test as async_test;
+
+    #[cfg(target_arch = "wasm32")]
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    use tokio::test as async_test;
+
+    #[async_test]
+    async fn rpc_request_between_friends() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (Some("JohnDoe"), None, Some("test::rpc_request".into())),
+            (Some("JaneDoe"), None, Some("test::rpc_request".into())),
+        ])
+        .await?;
+
+        let (mut account_a, _, _) = accounts.first().cloned().unwrap();
+        let (mut account_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        let mut subscribe_a = account_a.multipass_subscribe().await?;
+        let mut subscribe_b = account_b.multipass_subscribe().await?;
+
+        account_a.send_request(&did_b).await?;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            let did = loop {
+                if let Some(MultiPassEventKind::FriendRequestReceived { from, .. }) =
+                    subscribe_b.next().await
+                {
+                    break from;
+                }
+            };
+            account_b.accept_request(&did).await
+        })
+        .await??;
+
+        crate::common::timeout(Duration::from_secs(60), async {
+            loop {
+                if let Some(MultiPassEventKind::FriendAdded { .. }) = subscribe_a.next().await {
+                    break;
+                }
+            }
+        })
+        .await?;
+
+        let mut requests = account_b.multipass().rpc_subscribe("game").await?;
+
+        assert!(matches!(
+            account_b.multipass().rpc_subscribe("game").await,
+            Err(Error::AlreadySubscribed)
+        ));
+
+        let handler = async move {
+            while let Some(request) = requests.next().await {
+                let reply = [b"ack:".as_slice(), request.payload()].concat();
+                request.respond(reply).expect("valid response");
+            }
+        };
+
+        let request =
+            account_a
+                .multipass()
+                .rpc_request(&did_b, "game", Bytes::from_static(b"invite"));
+
+        let response = crate::common::timeout(Duration::from_secs(60), async {
+            futures::pin_mut!(request, handler);
+            match futures::future::select(request, handler).await {
+                Either::Left((response, _)) => response,
+                Either::Right(_) => unreachable!("handler should not complete"),
+            }
+        })
+        .await??;
+
+        assert_eq!(response, Bytes::from_static(b"ack:invite"));
+        Ok(())
+    }
+
+    #[async_test]
+    async fn rpc_request_requires_friend() -> anyhow::Result<()> {
+        let accounts = create_accounts(vec![
+            (
+                Some("JohnDoe"),
+                None,
+                Some("test::rpc_request_friend".into()),
+            ),
+            (
+                Some("JaneDoe"),
+                None,
+                Some("test::rpc_request_friend".into()),
+            ),
+        ])
+        .await?;
+
+        let (account_a, _, _) = accounts.first().cloned().unwrap();
+        let (_account_b, did_b, _) = accounts.last().cloned().unwrap();
+
+        let result = account_a
+            .multipass()
+            .rpc_request(&did_b, "game", Bytes::from_static(b"invite"))
+            .await;
+
+        assert!(matches!(result, Err(Error::FriendDoesntExist)));
+        Ok(())
+    }
+}
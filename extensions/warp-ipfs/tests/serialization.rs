@@ -0,0 +1,314 @@
+//! Round-trip tests for the documents stored in ipfs and exchanged between peers, so that a change to their wire
+//! format is caught before it corrupts existing data.
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use either::Either;
+use indexmap::{IndexMap, IndexSet};
+use ipld_core::cid::{multihash::Multihash, Cid};
+use proptest::prelude::*;
+use rust_ipfs::Keypair;
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use warp::crypto::{Fingerprint, DID};
+use warp::multipass::identity::{IdentityStatus, Platform, SHORT_ID_SIZE};
+use warp::raygun::{GroupPermission, GroupPermissions};
+
+use warp_ipfs::store::compression::Compression;
+use warp_ipfs::store::conversation::message::MessageDocumentBuilder;
+use warp_ipfs::store::conversation::ConversationDocument;
+use warp_ipfs::store::document::identity::{Feature, IdentityDocument, IdentityMetadata};
+use warp_ipfs::store::document::RootDocument;
+use warp_ipfs::store::PeerIdExt;
+
+/// Document with fields added by a newer version, which older versions must ignore
+#[derive(Serialize)]
+struct WithUnknownFields<'a, T> {
+    #[serde(flatten)]
+    document: &'a T,
+    unknown_string: String,
+    unknown_map: BTreeMap<String, u64>,
+}
+
+fn encode<T: Serialize>(document: &T) -> Vec<u8> {
+    cbor4ii::serde::to_vec(Vec::new(), document).expect("serializable document")
+}
+
+fn decode<T: DeserializeOwned>(bytes: &[u8]) -> T {
+    cbor4ii::serde::from_slice(bytes).expect("deserializable document")
+}
+
+/// Decode the document and check that encoding it again produces the same bytes
+fn assert_round_trip<T: Serialize + DeserializeOwned>(document: &T) -> T {
+    let bytes = encode(document);
+    let decoded: T = decode(&bytes);
+    assert_eq!(encode(&decoded), bytes);
+    decoded
+}
+
+fn assert_forward_compatible<T: Serialize + DeserializeOwned>(document: &T, unknown: &str) {
+    let bytes = encode(&WithUnknownFields {
+        document,
+        unknown_string: unknown.to_string(),
+        unknown_map: BTreeMap::from_iter([(unknown.to_string(), 0)]),
+    });
+    let decoded: T = decode(&bytes);
+    assert_eq!(encode(&decoded), encode(document));
+}
+
+fn keypair() -> impl Strategy<Value = Keypair> {
+    any::<[u8; 32]>().prop_map(|seed| Keypair::ed25519_from_bytes(seed).expect("valid seed"))
+}
+
+fn did() -> impl Strategy<Value = DID> {
+    keypair().prop_map(|keypair| keypair.to_did().expect("valid keypair"))
+}
+
+fn cid() -> impl Strategy<Value = Cid> {
+    any::<Vec<u8>>().prop_map(|data| {
+        let digest = Sha256::digest(&data);
+        Cid::new_v1(0x71, Multihash::wrap(0x12, &digest).expect("valid digest"))
+    })
+}
+
+// Stored with millisecond precision, which is what the clocks derived from the dates use
+fn date() -> impl Strategy<Value = DateTime<Utc>> {
+    (0i64..4_102_444_800_000)
+        .prop_map(|millis| DateTime::from_timestamp_millis(millis).expect("valid timestamp"))
+}
+
+fn uuid() -> impl Strategy<Value = Uuid> {
+    any::<u128>().prop_map(Uuid::from_u128)
+}
+
+fn status() -> impl Strategy<Value = IdentityStatus> {
+    prop_oneof![
+        Just(IdentityStatus::Online),
+        Just(IdentityStatus::Away),
+        Just(IdentityStatus::Busy),
+        Just(IdentityStatus::Offline),
+    ]
+}
+
+fn platform() -> impl Strategy<Value = Platform> {
+    prop_oneof![
+        Just(Platform::Desktop),
+        Just(Platform::Mobile),
+        Just(Platform::Web),
+        Just(Platform::Unknown),
+    ]
+}
+
+prop_compose! {
+    fn root_document()(
+        identity in cid(),
+        created in date(),
+        modified in date(),
+        friends in proptest::option::of(cid()),
+        blocks in proptest::option::of(cid()),
+        request in proptest::option::of(cid()),
+        conversations in proptest::option::of(cid()),
+        keystore in proptest::option::of(cid()),
+        file_index in proptest::option::of(cid()),
+        profile_fields in proptest::option::of(cid()),
+        status in proptest::option::of(status()),
+        signature in proptest::option::of("[1-9A-HJ-NP-Za-km-z]{64,88}"),
+    ) -> RootDocument {
+        RootDocument {
+            identity,
+            created,
+            modified,
+            friends,
+            blocks,
+            request,
+            conversations,
+            keystore,
+            file_index,
+            profile_fields,
+            status,
+            signature,
+            ..Default::default()
+        }
+    }
+}
+
+prop_compose! {
+    fn identity_document()(
+        keypair in keypair(),
+        username in "\\PC{4,32}",
+        handle in proptest::option::of("[a-z0-9_]{4,32}"),
+        status_message in proptest::option::of("\\PC{0,512}"),
+        created in date(),
+        modified in date(),
+        profile_picture in proptest::option::of(cid()),
+        profile_banner in proptest::option::of(cid()),
+        platform in proptest::option::of(platform()),
+        status in proptest::option::of(status()),
+    ) -> IdentityDocument {
+        let did = keypair.to_did().expect("valid keypair");
+        let fingerprint = did.fingerprint();
+        let bytes = fingerprint.as_bytes();
+
+        IdentityDocument {
+            username,
+            handle,
+            short_id: bytes[bytes.len() - SHORT_ID_SIZE..]
+                .try_into()
+                .expect("valid conversion"),
+            did,
+            created,
+            modified,
+            status_message,
+            activity: None,
+            profile: None,
+            metadata: IdentityMetadata {
+                profile_picture,
+                profile_banner,
+                platform,
+                status,
+                arb_data: None,
+                compression: Compression::supported(),
+                features: Feature::supported(),
+            },
+            devices: vec![],
+            revoked_devices: vec![],
+            friends_summary: None,
+            version: Default::default(),
+            signature: None,
+            extended_signature: None,
+        }
+        .sign(&keypair)
+        .expect("valid document")
+    }
+}
+
+prop_compose! {
+    fn conversation_document()(
+        keypair in keypair(),
+        name in proptest::option::of("\\PC{1,64}"),
+        recipients in proptest::collection::vec(did(), 0..8),
+        restrict in proptest::collection::vec(did(), 0..4),
+        permissions in proptest::collection::vec(
+            (did(), proptest::sample::subsequence(GroupPermission::values(), 0..=4)),
+            0..4,
+        ),
+        // HashMap iteration order differs between instances, so the encoding is only deterministic with one entry
+        excluded in proptest::option::of((did(), "\\PC{0,88}")),
+        messages in proptest::option::of(cid()),
+        icon in proptest::option::of(cid()),
+        description in proptest::option::of("\\PC{0,256}"),
+        favorite in any::<bool>(),
+        archived in any::<bool>(),
+        slow_mode in any::<u64>(),
+    ) -> ConversationDocument {
+        let permissions = permissions
+            .into_iter()
+            .map(|(did, permissions)| (did, IndexSet::from_iter(permissions)))
+            .collect::<GroupPermissions>();
+
+        let mut document =
+            ConversationDocument::new_group(&keypair, name, recipients, &restrict, permissions)
+                .expect("valid document");
+
+        document.excluded.extend(excluded);
+        document.messages = messages;
+        document.icon = icon;
+        document.description = description;
+        document.favorite = favorite;
+        document.archived = archived;
+        document.slow_mode = slow_mode;
+        document.sign(&keypair).expect("valid document");
+        document
+    }
+}
+
+prop_compose! {
+    fn message_document()(
+        keypair in keypair(),
+        conversation_id in uuid(),
+        lines in proptest::collection::vec("\\PC{1,128}", 1..8),
+        date in date(),
+        pinned in any::<bool>(),
+        replied in proptest::option::of(uuid()),
+        reactions in proptest::collection::vec(("\\PC{1,8}", proptest::collection::vec(did(), 1..4)), 0..4),
+        key_epoch in proptest::option::of(0usize..1024),
+    ) -> warp_ipfs::store::conversation::message::MessageDocument {
+        let did = keypair.to_did().expect("valid keypair");
+
+        let mut document = MessageDocumentBuilder::new(&keypair, Either::Left(&did))
+            .set_conversation_id(conversation_id)
+            .set_sender(did.clone())
+            .set_date(date)
+            .set_pin(pinned)
+            .set_replied(replied)
+            .set_message(lines)
+            .expect("valid message")
+            .build()
+            .expect("valid document");
+
+        document.reactions = reactions
+            .into_iter()
+            .map(|(emoji, dids)| (emoji, IndexSet::from_iter(dids)))
+            .collect::<IndexMap<_, _>>();
+        document.key_epoch = key_epoch;
+        document
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn root_document_round_trip(document in root_document(), unknown in "\\PC{0,32}") {
+        let decoded = assert_round_trip(&document);
+        // the signature of the root document is computed over its json encoding
+        prop_assert_eq!(serde_json::to_vec(&decoded)?, serde_json::to_vec(&document)?);
+        assert_forward_compatible(&document, &unknown);
+    }
+
+    #[test]
+    fn identity_document_round_trip(document in identity_document(), unknown in "\\PC{0,32}") {
+        let decoded = assert_round_trip(&document);
+        prop_assert_eq!(&decoded, &document);
+        prop_assert!(decoded.verify().is_ok());
+        assert_forward_compatible(&document, &unknown);
+    }
+
+    #[test]
+    fn conversation_document_round_trip(document in conversation_document(), unknown in "\\PC{0,32}") {
+        let decoded = assert_round_trip(&document);
+        prop_assert!(decoded.verify().is_ok());
+        assert_forward_compatible(&document, &unknown);
+    }
+
+    #[test]
+    fn message_document_round_trip(document in message_document(), unknown in "\\PC{0,32}") {
+        let decoded = assert_round_trip(&document);
+        prop_assert!(decoded.verify().is_ok());
+        prop_assert_eq!(decoded.nonce_from_message()?, document.nonce_from_message()?);
+        assert_forward_compatible(&document, &unknown);
+    }
+
+    #[test]
+    fn merged_message_document_is_verified(mut document in message_document(), primary in uuid(), other in uuid()) {
+        let origin = document.conversation_id();
+
+        // messages of a duplicate conversation are moved to the conversation they are merged into
+        document.move_to(primary);
+        prop_assert_eq!(document.conversation_id(), primary);
+        prop_assert_eq!(document.origin, Some(origin));
+
+        let decoded = assert_round_trip(&document);
+        prop_assert!(decoded.verify().is_ok());
+
+        // the conversation the message was sent in is kept when it is moved again
+        document.move_to(other);
+        prop_assert_eq!(document.origin, Some(origin));
+        prop_assert!(document.verify().is_ok());
+
+        // the signature does not cover a conversation the message was moved to
+        document.origin = None;
+        prop_assert!(document.verify().is_err());
+    }
+}
@@ -67,3 +67,16 @@ pub async fn file_size(path: impl AsRef<Path>) -> io::Result<usize> {
     #[cfg(target_arch = "wasm32")]
     Ok(read(path).await?.len())
 }
+
+/// Rename a file, replacing the destination if it exists
+pub async fn rename(from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<()> {
+    #[cfg(not(target_arch = "wasm32"))]
+    return tokio::fs::rename(from, to).await;
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        let contents = read(&from).await?;
+        write(to, contents).await?;
+        remove_file(from).await
+    }
+}
@@ -0,0 +1,97 @@
+//! Diagnosis of the account, optionally applying the repairs that are safe to perform automatically.
+use comfy_table::Table;
+use serde::Serialize;
+
+use warp_ipfs::doctor::{AccountDoctor, DoctorReport, DoctorStatus};
+use warp_ipfs::WarpIpfsInstance;
+
+use crate::{print_json, OutputFormat};
+
+#[derive(Debug, Serialize)]
+struct CheckOutput {
+    check: String,
+    status: &'static str,
+    detail: String,
+    repair: Option<String>,
+    automatic: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct ReportOutput {
+    healthy: bool,
+    checks: Vec<CheckOutput>,
+}
+
+impl From<&DoctorReport> for ReportOutput {
+    fn from(report: &DoctorReport) -> Self {
+        Self {
+            healthy: report.is_healthy(),
+            checks: report
+                .checks
+                .iter()
+                .map(|check| CheckOutput {
+                    check: check.kind.to_string(),
+                    status: match check.status {
+                        DoctorStatus::Ok => "ok",
+                        DoctorStatus::Warning => "warning",
+                        DoctorStatus::Failed => "failed",
+                    },
+                    detail: check.detail.clone(),
+                    repair: check.repair.as_ref().map(ToString::to_string),
+                    automatic: check
+                        .repair
+                        .as_ref()
+                        .is_some_and(|repair| repair.is_automatic()),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Returns an error if the account is still unhealthy, so the exit code reflects the diagnosis
+pub async fn run(
+    instance: &WarpIpfsInstance,
+    repair: bool,
+    output: OutputFormat,
+) -> anyhow::Result<()> {
+    let doctor = instance.multipass();
+
+    let report = match repair {
+        true => doctor.repair().await?,
+        false => doctor.diagnose().await?,
+    };
+
+    let report = ReportOutput::from(&report);
+
+    match output {
+        OutputFormat::Json => print_json(&report)?,
+        OutputFormat::Table => print_report(&report, repair),
+    }
+
+    anyhow::ensure!(report.healthy, "account is unhealthy");
+    Ok(())
+}
+
+fn print_report(report: &ReportOutput, repaired: bool) {
+    let mut table = Table::new();
+    table.set_header(vec!["Check", "Status", "Detail", "Suggested Repair"]);
+    for check in &report.checks {
+        let repair = match (&check.repair, check.automatic) {
+            (Some(repair), true) => format!("{repair} (automatic)"),
+            (Some(repair), false) => repair.clone(),
+            (None, _) => String::new(),
+        };
+        table.add_row(vec![
+            check.check.clone(),
+            check.status.to_string(),
+            check.detail.clone(),
+            repair,
+        ]);
+    }
+
+    println!("{table}");
+
+    if !report.healthy && !repaired && report.checks.iter().any(|check| check.automatic) {
+        println!("Run again with --repair to apply the automatic repairs");
+    }
+}
@@ -0,0 +1,350 @@
+//! Transfer of files between the local filesystem and Constellation of the account.
+//!
+//! Uploads expand glob patterns and directories recursively, skipping files that already exist in Constellation with
+//! the same size so an interrupted upload can be resumed by running it again. Downloads of files that already exist
+//! locally with a smaller size continue from the end of the local file.
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use clap::Subcommand;
+use comfy_table::Table;
+use futures::StreamExt;
+use indicatif::{ProgressBar, ProgressStyle};
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+
+use warp::constellation::item::Item;
+use warp::constellation::{Constellation, Progression};
+use warp_ipfs::WarpIpfsInstance;
+
+use crate::{print_json, OutputFormat};
+
+#[derive(Debug, Subcommand)]
+pub enum FsCommand {
+    /// List the items of a directory
+    Ls {
+        /// Path of the directory
+        #[clap(default_value = "/")]
+        path: String,
+    },
+    /// Upload files. Directories are uploaded recursively and glob patterns (eg `*.png`) are expanded
+    Put {
+        #[clap(required = true)]
+        sources: Vec<String>,
+        /// Directory the files are uploaded to
+        #[clap(long, default_value = "/")]
+        dest: String,
+    },
+    /// Download the items matching a path or glob pattern (eg `/photos/*.png`)
+    Get {
+        pattern: String,
+        /// Local directory the items are downloaded to
+        #[clap(default_value = ".")]
+        dest: PathBuf,
+    },
+    /// Remove the items matching a path or glob pattern
+    Rm {
+        pattern: String,
+        /// Remove directories and their contents
+        #[clap(long, short)]
+        recursive: bool,
+    },
+}
+
+#[derive(Debug, Serialize)]
+struct FileOutput {
+    name: String,
+    path: String,
+    kind: &'static str,
+    size: usize,
+    modified: String,
+}
+
+pub async fn run(
+    instance: &mut WarpIpfsInstance,
+    command: FsCommand,
+    output: OutputFormat,
+) -> anyhow::Result<()> {
+    match command {
+        FsCommand::Ls { path } => {
+            let files = list(instance, &path)?;
+            match output {
+                OutputFormat::Json => print_json(&files)?,
+                OutputFormat::Table => print_files(&files),
+            }
+        }
+        FsCommand::Put { sources, dest } => {
+            let dest = normalize(&dest);
+            for source in sources {
+                for path in expand_local(&source)? {
+                    put(instance, &path, &dest).await?;
+                }
+            }
+        }
+        FsCommand::Get { pattern, dest } => {
+            let matches = expand_remote(instance, &pattern)?;
+            anyhow::ensure!(!matches.is_empty(), "no items match {pattern}");
+            tokio::fs::create_dir_all(&dest).await?;
+            for (path, item) in matches {
+                get(instance, &path, &item, &dest).await?;
+            }
+        }
+        FsCommand::Rm { pattern, recursive } => {
+            let matches = expand_remote(instance, &pattern)?;
+            anyhow::ensure!(!matches.is_empty(), "no items match {pattern}");
+            for (path, _) in matches {
+                instance.remove(&path, recursive).await?;
+                println!("removed {path}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Remote path without leading or trailing slashes, which is empty for the root directory
+fn normalize(path: &str) -> String {
+    path.trim().trim_matches('/').to_string()
+}
+
+fn join(directory: &str, name: &str) -> String {
+    match directory.is_empty() {
+        true => name.to_string(),
+        false => format!("{directory}/{name}"),
+    }
+}
+
+fn has_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+fn list(instance: &WarpIpfsInstance, path: &str) -> anyhow::Result<Vec<FileOutput>> {
+    let directory = instance.open_directory(&normalize(path))?;
+
+    Ok(directory
+        .get_items()
+        .iter()
+        .map(|item| FileOutput {
+            name: item.name(),
+            path: item.path().to_string(),
+            kind: match item.is_directory() {
+                true => "directory",
+                false => "file",
+            },
+            size: item.size(),
+            modified: item.modified().to_rfc3339(),
+        })
+        .collect())
+}
+
+fn print_files(files: &[FileOutput]) {
+    let mut table = Table::new();
+    table.set_header(vec!["Name", "Type", "Size", "Modified"]);
+    for file in files {
+        table.add_row(vec![
+            file.name.clone(),
+            file.kind.to_string(),
+            file.size.to_string(),
+            file.modified.clone(),
+        ]);
+    }
+
+    println!("{table}");
+}
+
+fn expand_local(source: &str) -> anyhow::Result<Vec<PathBuf>> {
+    if !has_glob(source) {
+        return Ok(vec![PathBuf::from(source)]);
+    }
+
+    let paths = glob::glob(source)?.collect::<Result<Vec<_>, _>>()?;
+    anyhow::ensure!(!paths.is_empty(), "no files match {source}");
+    Ok(paths)
+}
+
+/// Items matching a remote path, where only the last segment of the path may contain a glob pattern
+fn expand_remote(
+    instance: &WarpIpfsInstance,
+    pattern: &str,
+) -> anyhow::Result<Vec<(String, Item)>> {
+    let pattern = normalize(pattern);
+
+    let (directory, name) = match pattern.rsplit_once('/') {
+        Some((directory, name)) => (directory.to_string(), name),
+        None => (String::new(), pattern.as_str()),
+    };
+
+    if !has_glob(name) {
+        let item = instance
+            .root_directory()
+            .get_item_by_path(&pattern)
+            .with_context(|| format!("{pattern} does not exist"))?;
+        return Ok(vec![(pattern.clone(), item)]);
+    }
+
+    anyhow::ensure!(
+        !has_glob(&directory),
+        "glob patterns are only supported in the last segment of a path"
+    );
+
+    let matcher = glob::Pattern::new(name)?;
+
+    Ok(instance
+        .open_directory(&directory)?
+        .get_items()
+        .into_iter()
+        .filter(|item| matcher.matches(&item.name()))
+        .map(|item| (join(&directory, &item.name()), item))
+        .collect())
+}
+
+fn progress_bar(name: &str, total: u64) -> ProgressBar {
+    let bar = ProgressBar::new(total);
+    bar.set_style(
+        ProgressStyle::with_template(
+            "{msg} [{bar:30}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
+        )
+        .expect("valid template")
+        .progress_chars("=> "),
+    );
+    bar.set_message(name.to_string());
+    bar
+}
+
+/// Create a remote directory and its parents, if they do not exist
+async fn ensure_directory(instance: &mut WarpIpfsInstance, path: &str) -> anyhow::Result<()> {
+    let mut parent = String::new();
+
+    for name in path.split('/').filter(|name| !name.is_empty()) {
+        let current = join(&parent, name);
+        if instance
+            .root_directory()
+            .get_item_by_path(&current)
+            .is_err()
+        {
+            instance.set_path(PathBuf::from(&parent));
+            let result = instance.create_directory(name, false).await;
+            instance.set_path(PathBuf::new());
+            result?;
+        }
+        parent = current;
+    }
+
+    Ok(())
+}
+
+async fn put(instance: &mut WarpIpfsInstance, local: &Path, dest: &str) -> anyhow::Result<()> {
+    let name = local
+        .file_name()
+        .and_then(|name| name.to_str())
+        .with_context(|| format!("{} has no valid file name", local.display()))?;
+
+    let remote = join(dest, name);
+
+    if local.is_dir() {
+        ensure_directory(instance, &remote).await?;
+        let mut entries = std::fs::read_dir(local)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.sort();
+        for entry in entries {
+            Box::pin(put(instance, &entry, &remote)).await?;
+        }
+        return Ok(());
+    }
+
+    let size = std::fs::metadata(local)?.len();
+
+    if let Ok(item) = instance.root_directory().get_item_by_path(&remote) {
+        if item.is_file() && item.size() as u64 == size {
+            println!("skipping {remote}: already uploaded");
+            return Ok(());
+        }
+        anyhow::bail!("{remote} already exist with a different size");
+    }
+
+    ensure_directory(instance, dest).await?;
+
+    let bar = progress_bar(&remote, size);
+    let mut progress = instance
+        .put(&remote, &local.to_string_lossy())
+        .await
+        .with_context(|| format!("unable to upload {}", local.display()))?;
+
+    while let Some(event) = progress.next().await {
+        match event {
+            Progression::CurrentProgress { current, .. } => bar.set_position(current as u64),
+            Progression::ProgressComplete { .. } => {
+                bar.finish();
+                return Ok(());
+            }
+            Progression::ProgressFailed { error, .. } => {
+                bar.abandon();
+                return Err(error).with_context(|| format!("unable to upload {remote}"));
+            }
+        }
+    }
+
+    bar.abandon();
+    anyhow::bail!("upload of {remote} ended unexpectedly")
+}
+
+async fn get(
+    instance: &WarpIpfsInstance,
+    remote: &str,
+    item: &Item,
+    dest: &Path,
+) -> anyhow::Result<()> {
+    let local = dest.join(item.name());
+
+    if let Item::Directory(directory) = item {
+        tokio::fs::create_dir_all(&local).await?;
+        for child in directory.get_items() {
+            let path = join(remote, &child.name());
+            Box::pin(get(instance, &path, &child, &local)).await?;
+        }
+        return Ok(());
+    }
+
+    let size = item.size();
+
+    let offset = match tokio::fs::metadata(&local).await {
+        Ok(metadata) if metadata.len() as usize == size => {
+            println!("skipping {remote}: already downloaded");
+            return Ok(());
+        }
+        Ok(metadata) if (metadata.len() as usize) < size => metadata.len() as usize,
+        Ok(_) => anyhow::bail!("{} is larger than {remote}", local.display()),
+        Err(_) => 0,
+    };
+
+    let bar = progress_bar(remote, size as u64);
+    bar.set_position(offset as u64);
+
+    let mut stream = match offset {
+        0 => instance.get_stream(remote).await?,
+        offset => instance.get_stream_range(remote, offset..size).await?,
+    };
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&local)
+        .await?;
+
+    while let Some(bytes) = stream.next().await {
+        let bytes = match bytes {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                bar.abandon();
+                return Err(e).with_context(|| format!("unable to download {remote}"));
+            }
+        };
+        file.write_all(&bytes).await?;
+        bar.inc(bytes.len() as u64);
+    }
+
+    file.flush().await?;
+    bar.finish();
+    Ok(())
+}
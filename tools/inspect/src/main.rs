@@ -1,38 +1,163 @@
+mod doctor;
+mod fs;
+mod profile;
+
 use std::path::{Path, PathBuf};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
-use clap::Parser;
+use anyhow::Context;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
 use comfy_table::Table;
 use futures::StreamExt;
+use serde::Serialize;
 
 use warp::crypto::zeroize::Zeroizing;
-use warp::multipass::identity::Identifier;
+use warp::crypto::DID;
+use warp::multipass::identity::{Identifier, Identity};
 use warp::multipass::{Friends, LocalIdentity, MultiPass};
 use warp::raygun::RayGun;
 use warp::tesseract::Tesseract;
 use warp_ipfs::config::Discovery;
 use warp_ipfs::{WarpIpfsBuilder, WarpIpfsInstance};
 
+use fs::FsCommand;
+use profile::{Profile, Profiles};
+
 #[derive(Debug, Parser)]
 #[clap(name = "inspect")]
 struct Opt {
     /// Path to directory
-    #[clap(long)]
-    path: PathBuf,
+    #[clap(long, global = true, conflicts_with = "profile")]
+    path: Option<PathBuf>,
+
+    /// Name of the profile of the account to use instead of `--path`
+    #[clap(long, global = true)]
+    profile: Option<String>,
 
     /// Name of the tesseract keystore
-    #[clap(long)]
+    #[clap(long, global = true)]
     keystore: Option<String>,
 
     /// Password to unlock keystore
     #[clap(long)]
     password: Option<String>,
+
+    /// Format of the output
+    #[clap(long, value_enum, default_value_t = OutputFormat::Table, global = true)]
+    output: OutputFormat,
+
+    /// Inspect a single part of the account. Identity, friends and conversations are inspected if omitted
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Human readable tables
+    Table,
+    /// Json documents with a stable schema, suitable for scripts
+    Json,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Show the identity of the account
+    Identity,
+    /// List friends of the account
+    Friends,
+    /// List conversations of the account
+    Conversations,
+    /// Transfer and manage files in Constellation
+    Fs {
+        #[clap(subcommand)]
+        command: FsCommand,
+    },
+    /// Manage profiles of accounts
+    Profile {
+        #[clap(subcommand)]
+        command: ProfileCommand,
+    },
+    /// Diagnose the account, reporting suggested repairs
+    Doctor {
+        /// Apply the repairs that are safe to perform automatically
+        #[clap(long)]
+        repair: bool,
+    },
+    /// Generate shell completions
+    Completions {
+        /// Shell to generate the completions for
+        #[clap(value_enum)]
+        shell: Shell,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum ProfileCommand {
+    /// List existing profiles
+    List,
+    /// Create a profile for the account at `path`
+    Create {
+        name: String,
+        path: PathBuf,
+        /// Name of the tesseract keystore of the account
+        #[clap(long)]
+        keystore: Option<String>,
+    },
+    /// Remove a profile. The account itself is left untouched
+    Remove { name: String },
+}
+
+#[derive(Debug, Serialize)]
+struct IdentityOutput {
+    did: DID,
+    username: String,
+    handle: String,
+    short_id: String,
+    status_message: Option<String>,
+}
+
+impl From<&Identity> for IdentityOutput {
+    fn from(identity: &Identity) -> Self {
+        Self {
+            did: identity.did_key().clone(),
+            username: identity.username().to_string(),
+            handle: identity.handle().to_string(),
+            short_id: identity.short_id().to_string(),
+            status_message: identity.status_message().map(ToOwned::to_owned),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct FriendOutput {
+    did: DID,
+    /// Username of the friend, if the identity could be resolved
+    username: Option<String>,
+    short_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ConversationOutput {
+    id: String,
+    name: Option<String>,
+    conversation_type: String,
+    recipients: Vec<DID>,
+    messages: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct AccountOutput {
+    identity: IdentityOutput,
+    friends: Vec<FriendOutput>,
+    conversations: Vec<ConversationOutput>,
 }
 
 async fn setup<P: AsRef<Path>>(
     path: P,
     keystore: Option<String>,
     passphrase: Zeroizing<String>,
+    diagnose: bool,
 ) -> anyhow::Result<WarpIpfsInstance> {
     let path = path.as_ref();
     let keystore_path = path.join(keystore.unwrap_or("tesseract_store".into()));
@@ -41,113 +166,298 @@ async fn setup<P: AsRef<Path>>(
     tesseract.unlock(passphrase.as_bytes())?;
 
     let mut config = warp_ipfs::config::Config::production(path);
-    config.store_setting_mut().discovery = Discovery::None;
-    config.ipfs_setting_mut().mdns.enable = false;
-    *config.enable_relay_mut() = false;
+    // diagnosis checks the registration with, and connectivity to, the shuttle nodes
+    if !diagnose {
+        config.store_setting_mut().discovery = Discovery::None;
+        config.ipfs_setting_mut().mdns.enable = false;
+        *config.enable_relay_mut() = false;
+    }
 
     let instance = WarpIpfsBuilder::default()
         .set_tesseract(tesseract)
         .set_config(config)
         .await;
 
-    //validating that account exist
-    _ = instance.identity().await?;
+    //validating that account exist, unless the account is diagnosed for being broken
+    if !diagnose {
+        _ = instance.identity().await?;
+    }
     Ok(instance)
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    println!("Utility inspector tool.. ");
-    let opt = Opt::parse();
-    //Just in case
-    if fdlimit::raise_fd_limit().is_none() {
-        //
+/// Timings are only reported with table output so json output remains parsable
+fn report_timing(output: OutputFormat, label: &str, elapsed: Duration) {
+    if output == OutputFormat::Table {
+        println!("Took {}ms to {label}", elapsed.as_millis());
     }
+}
 
-    let password = Zeroizing::new(match opt.password {
-        Some(password) => password,
-        None => rpassword::prompt_password("Enter A Password: ")?,
-    });
-
-    let start_time = Instant::now();
-    let instance = setup(&opt.path, opt.keystore.clone(), password).await?;
-    let end_time = start_time.elapsed();
-    println!(
-        "Took {}ms to load the account, messaging and filesystem",
-        end_time.as_millis()
-    );
+fn print_json<T: Serialize>(value: &T) -> anyhow::Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
 
+async fn load_identity(
+    instance: &WarpIpfsInstance,
+    output: OutputFormat,
+) -> anyhow::Result<IdentityOutput> {
     let start_time = Instant::now();
     let identity = instance.identity().await?;
-    let end_time = start_time.elapsed();
-    println!("Took {}ms to load the own identity", end_time.as_millis());
-
-    println!("Username: {}#{}", identity.username(), identity.short_id());
+    report_timing(output, "load the own identity", start_time.elapsed());
+    Ok(IdentityOutput::from(&identity))
+}
 
+async fn load_friends(
+    instance: &WarpIpfsInstance,
+    output: OutputFormat,
+) -> anyhow::Result<Vec<FriendOutput>> {
     let start_time = Instant::now();
     let mut friends = instance.list_friends().await?;
-    let end_time = start_time.elapsed();
-    println!("Took {}ms to load friends list", end_time.as_millis());
+    report_timing(output, "load friends list", start_time.elapsed());
 
-    println!("Total Friends: {}", friends.len());
+    let mut list = Vec::with_capacity(friends.len());
 
-    if !friends.is_empty() {
-        let mut table = Table::new();
-        table.set_header(vec!["Username", "DID"]);
-
-        let start_time = Instant::now();
-        let mut identites = instance.get_identity(Identifier::DIDList(friends.clone()));
-        let end_time = start_time.elapsed();
-        println!("Took {}ms to load friends identities", end_time.as_millis());
-
-        while let Some(identity) = identites.next().await {
-            table.add_row(vec![
-                format!("{}#{}", identity.username(), identity.short_id()),
-                identity.did_key().to_string(),
-            ]);
-            if let Some(position) = friends.iter().position(|key| identity.did_key().eq(key)) {
-                friends.remove(position);
-            }
-        }
+    if friends.is_empty() {
+        return Ok(list);
+    }
 
-        for did in friends {
-            table.add_row(vec!["N/A".into(), did.to_string()]);
-        }
+    let start_time = Instant::now();
+    let mut identites = instance.get_identity(Identifier::DIDList(friends.clone()));
+    report_timing(output, "load friends identities", start_time.elapsed());
 
-        println!("{table}");
+    while let Some(identity) = identites.next().await {
+        list.push(FriendOutput {
+            did: identity.did_key().clone(),
+            username: Some(identity.username().to_string()),
+            short_id: Some(identity.short_id().to_string()),
+        });
+        if let Some(position) = friends.iter().position(|key| identity.did_key().eq(key)) {
+            friends.remove(position);
+        }
     }
 
+    list.extend(friends.into_iter().map(|did| FriendOutput {
+        did,
+        username: None,
+        short_id: None,
+    }));
+
+    Ok(list)
+}
+
+async fn load_conversations(
+    instance: &WarpIpfsInstance,
+    output: OutputFormat,
+) -> anyhow::Result<Vec<ConversationOutput>> {
     let start_time = Instant::now();
     let conversations = instance.list_conversations().await?;
-    let end_time = start_time.elapsed();
-    println!(
-        "Took {}ms to load list of conversations",
-        end_time.as_millis()
-    );
+    report_timing(output, "load list of conversations", start_time.elapsed());
+
+    let mut list = Vec::with_capacity(conversations.len());
+
+    for convo in conversations {
+        let messages = instance.get_message_count(convo.id()).await?;
+
+        list.push(ConversationOutput {
+            id: convo.id().to_string(),
+            name: convo.name().map(ToOwned::to_owned),
+            conversation_type: convo.conversation_type().to_string(),
+            recipients: convo.recipients().to_vec(),
+            messages,
+        });
+    }
+
+    Ok(list)
+}
 
+fn print_identity(identity: &IdentityOutput) {
+    println!("Username: {}#{}", identity.username, identity.short_id);
+}
+
+fn print_friends(friends: &[FriendOutput]) {
+    println!("Total Friends: {}", friends.len());
+
+    if friends.is_empty() {
+        return;
+    }
+
+    let mut table = Table::new();
+    table.set_header(vec!["Username", "DID"]);
+
+    for friend in friends {
+        let username = match (&friend.username, &friend.short_id) {
+            (Some(username), Some(short_id)) => format!("{username}#{short_id}"),
+            _ => "N/A".into(),
+        };
+        table.add_row(vec![username, friend.did.to_string()]);
+    }
+
+    println!("{table}");
+}
+
+async fn print_conversations(instance: &WarpIpfsInstance, conversations: &[ConversationOutput]) {
     println!("Total Conversations: {}", conversations.len());
 
     let mut table = Table::new();
     table.set_header(vec!["ID", "Name", "Type", "Recipients", "# of Messages"]);
     for convo in conversations {
         let recipients = instance
-            .get_identity(convo.recipients())
+            .get_identity(convo.recipients.clone())
             .map(|id| format!("{}#{}", id.username(), id.short_id()))
             .collect::<Vec<_>>()
             .await;
 
-        let count = instance.get_message_count(convo.id()).await?;
-
         table.add_row(vec![
-            convo.id().to_string(),
-            convo.name().map(ToOwned::to_owned).unwrap_or_default(),
-            convo.conversation_type().to_string(),
+            convo.id.clone(),
+            convo.name.clone().unwrap_or_default(),
+            convo.conversation_type.clone(),
             recipients.join(", "),
-            count.to_string(),
+            convo.messages.to_string(),
         ]);
     }
 
     println!("{table}");
+}
+
+fn profile_command(command: ProfileCommand, output: OutputFormat) -> anyhow::Result<()> {
+    let mut profiles = Profiles::load()?;
+
+    match command {
+        ProfileCommand::List => match output {
+            OutputFormat::Json => print_json(&profiles)?,
+            OutputFormat::Table => {
+                let mut table = Table::new();
+                table.set_header(vec!["Name", "Path", "Keystore"]);
+                for (name, profile) in profiles.iter() {
+                    table.add_row(vec![
+                        name.clone(),
+                        profile.path.display().to_string(),
+                        profile.keystore.clone().unwrap_or_default(),
+                    ]);
+                }
+                println!("{table}");
+            }
+        },
+        ProfileCommand::Create {
+            name,
+            path,
+            keystore,
+        } => {
+            let path = std::fs::canonicalize(&path)
+                .with_context(|| format!("{} does not exist", path.display()))?;
+            profiles.insert(&name, Profile { path, keystore })?;
+            profiles.save()?;
+        }
+        ProfileCommand::Remove { name } => {
+            profiles.remove(&name)?;
+            profiles.save()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Resolve the path and keystore of the account from either `--path` or `--profile`
+fn account(opt: &Opt) -> anyhow::Result<(PathBuf, Option<String>)> {
+    if let Some(path) = &opt.path {
+        return Ok((path.clone(), opt.keystore.clone()));
+    }
+
+    let name = opt
+        .profile
+        .as_deref()
+        .context("either --path or --profile is required")?;
+
+    let profiles = Profiles::load()?;
+    let profile = profiles.get(name)?;
+    Ok((
+        profile.path.clone(),
+        opt.keystore.clone().or_else(|| profile.keystore.clone()),
+    ))
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let mut opt = Opt::parse();
+    let output = opt.output;
+
+    match opt.command.take() {
+        Some(Command::Completions { shell }) => {
+            clap_complete::generate(
+                shell,
+                &mut Opt::command(),
+                "inspect",
+                &mut std::io::stdout(),
+            );
+            return Ok(());
+        }
+        Some(Command::Profile { command }) => return profile_command(command, output),
+        command => opt.command = command,
+    }
+
+    let (path, keystore) = account(&opt)?;
+
+    if output == OutputFormat::Table {
+        println!("Utility inspector tool.. ");
+    }
+
+    //Just in case
+    if fdlimit::raise_fd_limit().is_none() {
+        //
+    }
+
+    let password = Zeroizing::new(match opt.password {
+        Some(password) => password,
+        None => rpassword::prompt_password("Enter A Password: ")?,
+    });
+
+    let start_time = Instant::now();
+    let diagnose = matches!(opt.command, Some(Command::Doctor { .. }));
+    let mut instance = setup(&path, keystore, password, diagnose).await?;
+    report_timing(
+        output,
+        "load the account, messaging and filesystem",
+        start_time.elapsed(),
+    );
+
+    match (opt.command, output) {
+        (Some(Command::Identity), OutputFormat::Json) => {
+            print_json(&load_identity(&instance, output).await?)?
+        }
+        (Some(Command::Identity), OutputFormat::Table) => {
+            print_identity(&load_identity(&instance, output).await?)
+        }
+        (Some(Command::Friends), OutputFormat::Json) => {
+            print_json(&load_friends(&instance, output).await?)?
+        }
+        (Some(Command::Friends), OutputFormat::Table) => {
+            print_friends(&load_friends(&instance, output).await?)
+        }
+        (Some(Command::Conversations), OutputFormat::Json) => {
+            print_json(&load_conversations(&instance, output).await?)?
+        }
+        (Some(Command::Conversations), OutputFormat::Table) => {
+            let conversations = load_conversations(&instance, output).await?;
+            print_conversations(&instance, &conversations).await
+        }
+        (Some(Command::Fs { command }), _) => fs::run(&mut instance, command, output).await?,
+        (Some(Command::Doctor { repair }), _) => doctor::run(&instance, repair, output).await?,
+        (None, OutputFormat::Json) => {
+            let account = AccountOutput {
+                identity: load_identity(&instance, output).await?,
+                friends: load_friends(&instance, output).await?,
+                conversations: load_conversations(&instance, output).await?,
+            };
+            print_json(&account)?
+        }
+        (None, OutputFormat::Table) => {
+            print_identity(&load_identity(&instance, output).await?);
+            print_friends(&load_friends(&instance, output).await?);
+            let conversations = load_conversations(&instance, output).await?;
+            print_conversations(&instance, &conversations).await
+        }
+        (Some(Command::Profile { .. } | Command::Completions { .. }), _) => unreachable!(),
+    }
 
     Ok(())
 }
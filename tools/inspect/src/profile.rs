@@ -0,0 +1,82 @@
+//! Named profiles mapping to the data directory (and keystore) of an account, stored in the config directory of the
+//! user so accounts can be addressed with `--profile <name>` instead of their path.
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// Environment variable overriding the directory the profiles are stored in
+pub const CONFIG_DIR_ENV: &str = "WARP_CONFIG_DIR";
+
+const PROFILES_FILE: &str = "profiles.json";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    pub path: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keystore: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Profiles {
+    #[serde(default)]
+    profiles: BTreeMap<String, Profile>,
+}
+
+impl Profiles {
+    pub fn config_dir() -> anyhow::Result<PathBuf> {
+        if let Some(dir) = std::env::var_os(CONFIG_DIR_ENV) {
+            return Ok(PathBuf::from(dir));
+        }
+
+        dirs::config_dir()
+            .map(|dir| dir.join("warp"))
+            .context("unable to determine the config directory. Set WARP_CONFIG_DIR instead")
+    }
+
+    pub fn load() -> anyhow::Result<Self> {
+        let file = Self::config_dir()?.join(PROFILES_FILE);
+        if !file.is_file() {
+            return Ok(Self::default());
+        }
+
+        let bytes = std::fs::read(&file)?;
+        serde_json::from_slice(&bytes)
+            .with_context(|| format!("unable to parse {}", file.display()))
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let dir = Self::config_dir()?;
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join(PROFILES_FILE), serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> anyhow::Result<&Profile> {
+        self.profiles
+            .get(name)
+            .with_context(|| format!("profile \"{name}\" does not exist"))
+    }
+
+    pub fn insert(&mut self, name: &str, profile: Profile) -> anyhow::Result<()> {
+        let name = name.trim();
+        anyhow::ensure!(!name.is_empty(), "profile name cannot be empty");
+        anyhow::ensure!(
+            !self.profiles.contains_key(name),
+            "profile \"{name}\" already exist"
+        );
+        self.profiles.insert(name.to_string(), profile);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> anyhow::Result<Profile> {
+        self.profiles
+            .remove(name)
+            .with_context(|| format!("profile \"{name}\" does not exist"))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Profile)> {
+        self.profiles.iter()
+    }
+}
@@ -0,0 +1,103 @@
+use criterion::{
+    black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput,
+};
+use warp::crypto::cipher::Cipher;
+
+const KEY: &[u8] = b"this is my secret cipher key!";
+
+const SIZES: [usize; 3] = [256, 4 * 1024, 64 * 1024];
+
+fn encrypt(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cipher_encrypt");
+
+    for size in SIZES {
+        let data = vec![0u8; size];
+
+        group.bench_with_input(BenchmarkId::new("copy", size), &data, |b, data| {
+            b.iter(|| Cipher::direct_encrypt(black_box(data), KEY).expect("valid key"))
+        });
+
+        // the serialized payload is consumed by the encryption, so the buffer is handed over as-is
+        group.bench_with_input(BenchmarkId::new("in_place", size), &data, |b, data| {
+            b.iter_batched(
+                || data.clone(),
+                |mut data| {
+                    Cipher::direct_encrypt_in_place(&mut data, KEY).expect("valid key");
+                    data
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+fn decrypt(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cipher_decrypt");
+
+    for size in SIZES {
+        let data = Cipher::direct_encrypt(&vec![0u8; size], KEY).expect("valid key");
+
+        group.bench_with_input(BenchmarkId::new("copy", size), &data, |b, data| {
+            b.iter(|| Cipher::direct_decrypt(black_box(data), KEY).expect("valid data"))
+        });
+
+        group.bench_with_input(BenchmarkId::new("in_place", size), &data, |b, data| {
+            b.iter_batched(
+                || data.clone(),
+                |mut data| {
+                    Cipher::direct_decrypt_in_place(&mut data, KEY).expect("valid data");
+                    data
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+fn stream(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cipher_stream");
+    let cipher = Cipher::from(KEY);
+
+    for size in [64 * 1024, 1024 * 1024, 8 * 1024 * 1024] {
+        let data = vec![0u8; size];
+        let mut encrypted = Vec::new();
+        cipher
+            .encrypt_stream(&mut data.as_slice(), &mut encrypted)
+            .expect("valid stream");
+
+        group.throughput(Throughput::Bytes(size as u64));
+
+        group.bench_with_input(BenchmarkId::new("encrypt", size), &data, |b, data| {
+            b.iter(|| {
+                let mut writer = Vec::with_capacity(encrypted.len());
+                cipher
+                    .encrypt_stream(&mut data.as_slice(), &mut writer)
+                    .expect("valid stream");
+                writer
+            })
+        });
+
+        group.bench_with_input(
+            BenchmarkId::new("decrypt", size),
+            &encrypted,
+            |b, encrypted| {
+                b.iter(|| {
+                    let mut writer = Vec::with_capacity(size);
+                    cipher
+                        .decrypt_stream(&mut encrypted.as_slice(), &mut writer)
+                        .expect("valid stream");
+                    writer
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, encrypt, decrypt, stream);
+criterion_main!(benches);
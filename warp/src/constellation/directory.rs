@@ -695,6 +695,36 @@ impl Directory {
 }
 
 impl Directory {
+    /// Copy of the directory and of its items that does not share any state with them, so changes made to the copy
+    /// are not reflected in the directory
+    pub fn detached(&self) -> Directory {
+        let items = self
+            .items
+            .read()
+            .iter()
+            .map(|item| match item {
+                Item::File(file) => Item::File(file.detached()),
+                Item::Directory(directory) => Item::Directory(directory.detached()),
+            })
+            .collect();
+
+        Directory {
+            id: Arc::new(self.id()),
+            name: Arc::new(RwLock::new(self.name())),
+            description: Arc::new(RwLock::new(self.description())),
+            thumbnail: Arc::new(RwLock::new(self.thumbnail.read().clone())),
+            thumbnail_format: Arc::new(RwLock::new(self.thumbnail_format())),
+            thumbnail_reference: Arc::new(RwLock::new(self.thumbnail_reference())),
+            favorite: Arc::new(RwLock::new(self.favorite())),
+            creation: Arc::new(RwLock::new(self.creation())),
+            modified: Arc::new(RwLock::new(self.modified())),
+            directory_type: Arc::new(RwLock::new(*self.directory_type.read())),
+            items: Arc::new(RwLock::new(items)),
+            path: Arc::new(self.path().to_string()),
+            signal: Arc::default(),
+        }
+    }
+
     pub fn id(&self) -> Uuid {
         *self.id
     }
@@ -711,6 +741,7 @@ impl Directory {
 #[cfg(test)]
 mod test {
     use super::Directory;
+    use crate::constellation::file::File;
 
     #[test]
     fn name_length() {
@@ -725,4 +756,30 @@ mod test {
         assert_eq!(long_directory.name(), &long_name[..256]);
         assert_ne!(long_directory.name(), &long_name[..255]);
     }
+
+    #[test]
+    fn detached_directory_does_not_share_state() -> Result<(), crate::error::Error> {
+        let root = Directory::new("root");
+        let sub = Directory::new("sub");
+        sub.add_file(File::new("file.txt"))?;
+        root.add_directory(sub)?;
+
+        let detached = root.detached();
+        assert_eq!(detached.id(), root.id());
+        assert!(detached.get_item_by_path("/sub/file.txt").is_ok());
+
+        detached.set_name("renamed");
+        detached.remove_item("sub")?;
+        detached.add_file(File::new("other.txt"))?;
+
+        assert_eq!(root.name(), "root");
+        assert!(root.get_item_by_path("/sub/file.txt").is_ok());
+        assert!(!root.has_item("other.txt"));
+
+        let sub = root.get_item("sub")?.get_directory()?;
+        let detached_sub = sub.detached();
+        detached_sub.rename_item("file.txt", "moved.txt")?;
+        assert!(sub.has_item("file.txt"));
+        Ok(())
+    }
 }
@@ -339,6 +339,28 @@ impl File {
         *path = new_path;
     }
 
+    /// Copy of the file that does not share any state with it, so changes made to the copy are not reflected in the
+    /// file
+    pub fn detached(&self) -> File {
+        File {
+            id: Arc::new(RwLock::new(self.id())),
+            name: Arc::new(RwLock::new(self.name())),
+            size: Arc::new(RwLock::new(*self.size.read())),
+            thumbnail: Arc::new(RwLock::new(self.thumbnail.read().clone())),
+            thumbnail_format: Arc::new(RwLock::new(self.thumbnail_format())),
+            thumbnail_reference: Arc::new(RwLock::new(self.thumbnail_reference())),
+            favorite: Arc::new(RwLock::new(self.favorite())),
+            description: Arc::new(RwLock::new(self.description())),
+            creation: Arc::new(RwLock::new(self.creation())),
+            modified: Arc::new(RwLock::new(self.modified())),
+            file_type: Arc::new(RwLock::new(self.file_type())),
+            hash: Arc::new(RwLock::new(self.hash())),
+            reference: Arc::new(RwLock::new(self.reference())),
+            path: Arc::new(self.path().to_string()),
+            signal: Arc::default(),
+        }
+    }
+
     pub(crate) fn set_signal(
         &mut self,
         signal: Option<futures::channel::mpsc::UnboundedSender<()>>,
@@ -3,9 +3,11 @@ pub mod directory;
 pub mod file;
 pub mod item;
 
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 
 use crate::error::Error;
+use crate::subscription::SubscriptionOptions;
 use crate::{Extension, SingleHandle};
 use anyhow::anyhow;
 use bytes::Bytes;
@@ -13,7 +15,8 @@ use chrono::{DateTime, Utc};
 
 use directory::Directory;
 use futures::stream::BoxStream;
-use futures::Stream;
+use futures::{Stream, StreamExt};
+use uuid::Uuid;
 
 #[derive(Debug, Clone)]
 pub enum ConstellationEventKind {
@@ -80,6 +83,76 @@ pub enum Progression {
 
 pub type ConstellationProgressStream = BoxStream<'static, Progression>;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IntegrityIssueKind {
+    /// Item does not reference any content
+    MissingReference,
+    /// Content referenced by the item is not pinned
+    Unpinned,
+    /// Content referenced by the item is missing from the local store
+    Missing,
+    /// Content does not match the size or hash stored in the index
+    Corrupted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntegrityIssue {
+    /// Path of the item within the filesystem
+    pub path: String,
+    pub kind: IntegrityIssueKind,
+    /// Whether the issue was resolved by requesting the content from the network
+    pub repaired: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntegrityReport {
+    /// Number of files checked
+    pub checked: usize,
+    pub issues: Vec<IntegrityIssue>,
+}
+
+impl IntegrityReport {
+    /// Returns true if every issue found, if any, was repaired
+    pub fn is_intact(&self) -> bool {
+        self.issues.iter().all(|issue| issue.repaired)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UploadProgress {
+    /// Number of bytes stored in confirmed chunks. An interrupted upload continues from this offset
+    pub confirmed: usize,
+    /// Total size of the file
+    pub total: usize,
+}
+
+impl UploadProgress {
+    pub fn is_complete(&self) -> bool {
+        self.confirmed >= self.total
+    }
+}
+
+/// Upload started with [`Constellation::put_resumable`] that continues from the last confirmed chunk after being
+/// interrupted
+#[async_trait::async_trait]
+pub trait UploadSession: Sync + Send {
+    /// Identifier of the session, used to reopen it with [`Constellation::upload_session`]
+    fn id(&self) -> Uuid;
+
+    /// Path of the file within the filesystem once the upload is complete
+    fn name(&self) -> String;
+
+    fn progress(&self) -> UploadProgress;
+
+    /// Upload the data of the file, starting at the offset in [`UploadProgress::confirmed`]. The progress reported is
+    /// the offset within the file, and the file is added to the filesystem once every chunk is confirmed. If the
+    /// stream ends early or fails, the upload can be resumed from the last confirmed chunk
+    async fn resume(
+        &mut self,
+        _: BoxStream<'static, std::io::Result<Bytes>>,
+    ) -> Result<ConstellationProgressStream, Error>;
+}
+
 /// Interface that would provide functionality around the filesystem.
 #[async_trait::async_trait]
 pub trait Constellation: ConstellationEvent + Extension + Sync + Send + SingleHandle {
@@ -183,6 +256,27 @@ pub trait Constellation: ConstellationEvent + Extension + Sync + Send + SingleHa
         Err(Error::Unimplemented)
     }
 
+    /// Used to start an upload of a file of `total_size` bytes that can be resumed if interrupted. Data is sent with
+    /// [`UploadSession::resume`]
+    async fn put_resumable(&mut self, _: &str, _: usize) -> Result<Box<dyn UploadSession>, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Used to reopen an upload that was not completed, including one started before the application restarted
+    async fn upload_session(&self, _: Uuid) -> Result<Box<dyn UploadSession>, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Used to list the uploads that were not completed
+    async fn upload_sessions(&self) -> Result<Vec<Uuid>, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Used to discard an upload that was not completed, along with the chunks that were stored
+    async fn cancel_upload(&mut self, _: Uuid) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
     /// Used to download data from the filesystem using a stream
     async fn get_stream(
         &self,
@@ -191,6 +285,26 @@ pub trait Constellation: ConstellationEvent + Extension + Sync + Send + SingleHa
         Err(Error::Unimplemented)
     }
 
+    /// Used to download a byte range of a file using a stream (eg to serve a HTTP range request).
+    /// The end of the range is clamped to the size of the file
+    async fn get_stream_range(
+        &self,
+        name: &str,
+        range: Range<usize>,
+    ) -> Result<BoxStream<'static, Result<Bytes, std::io::Error>>, Error> {
+        if range.start > range.end {
+            return Err(Error::InvalidLength {
+                context: "range".into(),
+                current: range.start,
+                minimum: None,
+                maximum: Some(range.end),
+            });
+        }
+
+        let stream = self.get_stream(name).await?;
+        Ok(slice_stream(stream, range).boxed())
+    }
+
     /// Used to rename a file or directory in the filesystem
     async fn rename(&mut self, _: &str, _: &str) -> Result<(), Error> {
         Err(Error::Unimplemented)
@@ -215,6 +329,54 @@ pub trait Constellation: ConstellationEvent + Extension + Sync + Send + SingleHa
     async fn sync_ref(&mut self, _: &str) -> Result<(), Error> {
         Err(Error::Unimplemented)
     }
+
+    /// Used to verify that the content of every file in the index is present, pinned and matches its stored size and
+    /// hash. Content that is missing or unpinned is requested from the network when `refetch` is true
+    async fn verify(&self, _: bool) -> Result<IntegrityReport, Error> {
+        Err(Error::Unimplemented)
+    }
+}
+
+/// Limit a stream of bytes to the bytes within `range`
+pub fn slice_stream(
+    stream: BoxStream<'static, Result<Bytes, std::io::Error>>,
+    range: Range<usize>,
+) -> impl Stream<Item = Result<Bytes, std::io::Error>> + Send + 'static {
+    async_stream::stream! {
+        let mut stream = stream;
+        let mut offset = 0;
+
+        while let Some(result) = stream.next().await {
+            let bytes = match result {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    yield Err(e);
+                    return;
+                }
+            };
+
+            let start = offset;
+            let end = offset + bytes.len();
+            offset = end;
+
+            if end <= range.start {
+                continue;
+            }
+
+            if start >= range.end {
+                return;
+            }
+
+            let slice_start = range.start.saturating_sub(start);
+            let slice_end = range.end.min(end) - start;
+
+            yield Ok(bytes.slice(slice_start..slice_end));
+
+            if end >= range.end {
+                return;
+            }
+        }
+    }
 }
 
 #[async_trait::async_trait]
@@ -223,6 +385,14 @@ pub trait ConstellationEvent: Sync + Send {
     async fn constellation_subscribe(&mut self) -> Result<ConstellationEventStream, Error> {
         Err(Error::Unimplemented)
     }
+
+    /// Subscribe to an stream of events, buffering at most [`SubscriptionOptions::capacity`] events
+    async fn constellation_subscribe_with(
+        &mut self,
+        _: SubscriptionOptions,
+    ) -> Result<ConstellationEventStream, Error> {
+        Err(Error::Unimplemented)
+    }
 }
 
 /// Types that would be used for import and export
@@ -247,3 +417,34 @@ impl<S: AsRef<str>> From<S> for ConstellationDataType {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+    use futures::{stream, StreamExt, TryStreamExt};
+
+    use super::slice_stream;
+
+    async fn collect(range: std::ops::Range<usize>) -> std::io::Result<Vec<u8>> {
+        let chunks = ["Hello", ", ", "World", "!"]
+            .into_iter()
+            .map(|chunk| Ok(Bytes::from(chunk)));
+
+        let bytes = slice_stream(stream::iter(chunks).boxed(), range)
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        Ok(bytes.concat())
+    }
+
+    #[tokio::test]
+    async fn slice_stream_range() -> std::io::Result<()> {
+        assert_eq!(collect(0..13).await?, b"Hello, World!");
+        assert_eq!(collect(0..5).await?, b"Hello");
+        assert_eq!(collect(3..9).await?, b"lo, Wo");
+        assert_eq!(collect(7..100).await?, b"World!");
+        assert!(collect(20..30).await?.is_empty());
+        assert!(collect(4..4).await?.is_empty());
+        Ok(())
+    }
+}
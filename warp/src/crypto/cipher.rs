@@ -13,13 +13,15 @@ use crate::error::Error;
 
 use aes_gcm::aead::stream::{DecryptorBE32, EncryptorBE32};
 use aes_gcm::{
-    aead::{Aead, KeyInit},
+    aead::{AeadInPlace, KeyInit},
     Aes256Gcm,
 };
 
 type Result<T> = std::result::Result<T, Error>;
 
 const AES256_GCM_TAG_SIZE: usize = 16;
+const AES256_GCM_NONCE_SIZE: usize = 12;
+const SELF_ENCRYPT_KEY_SIZE: usize = 34;
 const AES256_GCM_ENCRYPTION_BUF_SIZE: usize = 512;
 const AES256_GCM_DECRYPTION_BUF_SIZE: usize = AES256_GCM_ENCRYPTION_BUF_SIZE + AES256_GCM_TAG_SIZE;
 
@@ -67,22 +69,27 @@ impl Cipher {
 
     /// Used to generate and encrypt data with a random key
     pub fn self_encrypt(data: &[u8]) -> Result<Vec<u8>> {
-        let cipher = Cipher::new();
-        let mut data = cipher.encrypt(data, None)?;
-        data.extend(cipher.private_key());
-        Ok(data)
+        Self::self_encrypt_inner(data, None)
     }
 
     pub fn self_encrypt_with_nonce(data: &[u8], nonce: &[u8]) -> Result<Vec<u8>> {
+        Self::self_encrypt_inner(data, Some(nonce))
+    }
+
+    fn self_encrypt_inner(data: &[u8], nonce: Option<&[u8]>) -> Result<Vec<u8>> {
         let cipher = Cipher::new();
-        let mut data = cipher.encrypt(data, Some(nonce))?;
-        data.extend(cipher.private_key());
-        Ok(data)
+        let mut buffer = Vec::with_capacity(
+            data.len() + AES256_GCM_TAG_SIZE + AES256_GCM_NONCE_SIZE + SELF_ENCRYPT_KEY_SIZE,
+        );
+        buffer.extend_from_slice(data);
+        cipher.encrypt_in_place(&mut buffer, nonce)?;
+        buffer.extend_from_slice(cipher.private_key());
+        Ok(buffer)
     }
 
     /// Used to decrypt data with a key that was attached to the data
     pub fn self_decrypt(data: &[u8]) -> Result<Vec<u8>> {
-        let (key, data) = extract_data_slice::<34>(data);
+        let (key, data) = extract_data_slice::<SELF_ENCRYPT_KEY_SIZE>(data)?;
         let cipher = Cipher::from_bytes(key);
         let data = cipher.decrypt(data)?;
         Ok(data)
@@ -105,11 +112,41 @@ impl Cipher {
         cipher.decrypt(data)
     }
 
+    /// Used to encrypt data directly with key, reusing the allocation of `data` for the encrypted data
+    pub fn direct_encrypt_in_place(data: &mut Vec<u8>, key: &[u8]) -> Result<()> {
+        let cipher = Cipher::from(key);
+        cipher.encrypt_in_place(data, None)
+    }
+
+    pub fn direct_encrypt_in_place_with_nonce(
+        data: &mut Vec<u8>,
+        key: &[u8],
+        nonce: &[u8],
+    ) -> Result<()> {
+        let cipher = Cipher::from(key);
+        cipher.encrypt_in_place(data, Some(nonce))
+    }
+
+    /// Used to decrypt data directly with key, reusing the allocation of `data` for the decrypted data
+    pub fn direct_decrypt_in_place(data: &mut Vec<u8>, key: &[u8]) -> Result<()> {
+        let cipher = Cipher::from(key);
+        cipher.decrypt_in_place(data)
+    }
+
     /// Used to encrypt data
     pub fn encrypt(&self, data: &[u8], nonce: Option<&[u8]>) -> Result<Vec<u8>> {
-        let nonce = match nonce {
+        let mut buffer =
+            Vec::with_capacity(data.len() + AES256_GCM_TAG_SIZE + AES256_GCM_NONCE_SIZE);
+        buffer.extend_from_slice(data);
+        self.encrypt_in_place(&mut buffer, nonce)?;
+        Ok(buffer)
+    }
+
+    /// Used to encrypt data in place. The tag and nonce are appended to `data`
+    pub fn encrypt_in_place(&self, data: &mut Vec<u8>, nonce: Option<&[u8]>) -> Result<()> {
+        let nonce: [u8; AES256_GCM_NONCE_SIZE] = match nonce {
             Some(nonce) => nonce.try_into().map_err(|_| Error::InvalidConversion)?,
-            None => crate::crypto::generate::<12>(),
+            None => crate::crypto::generate::<AES256_GCM_NONCE_SIZE>(),
         };
 
         let key = match self.private_key.len() {
@@ -117,28 +154,41 @@ impl Cipher {
             _ => zeroize::Zeroizing::new(sha256_hash(&self.private_key, Some(&nonce))),
         };
 
+        data.reserve(AES256_GCM_TAG_SIZE + AES256_GCM_NONCE_SIZE);
+
         let cipher = Aes256Gcm::new(key.as_slice().into());
-        let mut cipher_data = cipher
-            .encrypt(nonce.as_slice().into(), data)
+        cipher
+            .encrypt_in_place(nonce.as_slice().into(), b"", data)
             .map_err(|_| Error::EncryptionError)?;
 
-        cipher_data.extend(nonce);
+        data.extend_from_slice(&nonce);
 
-        Ok(cipher_data)
+        Ok(())
     }
 
     /// Used to decrypt data
     pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
-        let (nonce, payload) = extract_data_slice::<12>(data);
+        let mut buffer = data.to_vec();
+        self.decrypt_in_place(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Used to decrypt data in place. The nonce and tag are removed from `data`
+    pub fn decrypt_in_place(&self, data: &mut Vec<u8>) -> Result<()> {
+        let (nonce, _) = extract_data_slice::<AES256_GCM_NONCE_SIZE>(data)?;
+        let nonce: [u8; AES256_GCM_NONCE_SIZE] =
+            nonce.try_into().map_err(|_| Error::InvalidConversion)?;
+
+        data.truncate(data.len() - AES256_GCM_NONCE_SIZE);
 
         let key = match self.private_key.len() {
             32 => self.private_key.clone(),
-            _ => zeroize::Zeroizing::new(sha256_hash(&self.private_key, Some(nonce))),
+            _ => zeroize::Zeroizing::new(sha256_hash(&self.private_key, Some(&nonce))),
         };
 
         let cipher = Aes256Gcm::new(key.as_slice().into());
         cipher
-            .decrypt(nonce.into(), payload)
+            .decrypt_in_place(nonce.as_slice().into(), b"", data)
             .map_err(|_| Error::DecryptionError)
     }
 
@@ -524,10 +574,19 @@ impl Cipher {
     }
 }
 
-fn extract_data_slice<const N: usize>(data: &[u8]) -> (&[u8], &[u8]) {
-    let extracted = &data[data.len() - N..];
-    let payload = &data[..data.len() - N];
-    (extracted, payload)
+/// Split the last `N` bytes from `data`, returning an error instead of panicking on data that is too short
+fn extract_data_slice<const N: usize>(data: &[u8]) -> Result<(&[u8], &[u8])> {
+    if data.len() < N {
+        return Err(Error::InvalidLength {
+            context: "data".into(),
+            current: data.len(),
+            minimum: Some(N),
+            maximum: None,
+        });
+    }
+
+    let (payload, extracted) = data.split_at(data.len() - N);
+    Ok((extracted, payload))
 }
 
 #[cfg(test)]
@@ -569,6 +628,31 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn cipher_aes256gcm_encrypt_decrypt_in_place() -> anyhow::Result<()> {
+        let key = b"this is my secret cipher key!";
+        let message = b"Hello, World!";
+
+        let mut data = message.to_vec();
+        Cipher::direct_encrypt_in_place(&mut data, key)?;
+
+        assert_ne!(data, message);
+        assert_eq!(Cipher::direct_decrypt(&data, key)?, message);
+
+        Cipher::direct_decrypt_in_place(&mut data, key)?;
+
+        assert_eq!(data, message);
+        Ok(())
+    }
+
+    #[test]
+    fn cipher_aes256gcm_decrypt_short_data() {
+        let cipher = Cipher::from(b"this is my secret cipher key!");
+
+        assert!(cipher.decrypt(&[0; 4]).is_err());
+        assert!(Cipher::self_decrypt(&[0; 16]).is_err());
+    }
+
     #[test]
     fn cipher_aes256gcm_stream_self_encrypt_decrypt() -> anyhow::Result<()> {
         let base = b"this is my message";
@@ -0,0 +1,184 @@
+//! Detached signatures in the [minisign](https://jedisct1.github.io/minisign/) format.
+//!
+//! Signatures produced here can be verified outside of warp with `minisign -V`, using the public
+//! key returned by [`public_key`]. Only the legacy `Ed` algorithm (signature over the raw content)
+//! is produced and accepted, since prehashed signatures require BLAKE2b.
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature, Signer, Verifier};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use super::{KeyMaterial, DID};
+use crate::error::Error;
+
+const SIGNATURE_ALGORITHM: &[u8; 2] = b"Ed";
+const UNTRUSTED_COMMENT: &str = "untrusted comment: ";
+const TRUSTED_COMMENT: &str = "trusted comment: ";
+
+/// Key id derived from the public key, since warp keys are not generated by minisign
+fn key_id(did: &DID) -> [u8; 8] {
+    let hash = Sha256::digest(did.public_key_bytes());
+    let mut id = [0u8; 8];
+    id.copy_from_slice(&hash[..8]);
+    id
+}
+
+fn key_id_hex(id: &[u8; 8]) -> String {
+    format!("{:016X}", u64::from_le_bytes(*id))
+}
+
+/// Public key of the identity in the minisign format
+pub fn public_key(did: &DID) -> String {
+    let id = key_id(did);
+    let mut bytes = Vec::with_capacity(42);
+    bytes.extend_from_slice(SIGNATURE_ALGORITHM);
+    bytes.extend_from_slice(&id);
+    bytes.extend_from_slice(&did.public_key_bytes());
+
+    format!(
+        "{UNTRUSTED_COMMENT}minisign public key {}\n{}\n",
+        key_id_hex(&id),
+        STANDARD.encode(bytes)
+    )
+}
+
+/// Create a detached signature of `data`. If no trusted comment is supplied, one containing the
+/// current timestamp and the DID of the signer is used.
+pub fn sign(keypair: &DID, data: &[u8], trusted_comment: Option<&str>) -> Result<String, Error> {
+    let trusted_comment = match trusted_comment {
+        Some(comment) if comment.contains(['\r', '\n']) => return Err(Error::InvalidDataType),
+        Some(comment) => comment.to_string(),
+        None => format!(
+            "timestamp:{}\tdid:{}",
+            chrono::Utc::now().timestamp(),
+            keypair
+        ),
+    };
+
+    let bytes = Zeroizing::new(keypair.private_key_bytes());
+    let secret = SecretKey::from_bytes(&bytes).map_err(|_| Error::PrivateKeyInvalid)?;
+    let public: PublicKey = (&secret).into();
+    let pair = Keypair { secret, public };
+
+    let signature = pair.sign(data).to_bytes();
+
+    let mut global = Vec::with_capacity(signature.len() + trusted_comment.len());
+    global.extend_from_slice(&signature);
+    global.extend_from_slice(trusted_comment.as_bytes());
+    let global_signature = pair.sign(&global).to_bytes();
+
+    let id = key_id(keypair);
+    let mut signature_bytes = Vec::with_capacity(74);
+    signature_bytes.extend_from_slice(SIGNATURE_ALGORITHM);
+    signature_bytes.extend_from_slice(&id);
+    signature_bytes.extend_from_slice(&signature);
+
+    Ok(format!(
+        "{UNTRUSTED_COMMENT}signature from warp secret key {}\n{}\n{TRUSTED_COMMENT}{trusted_comment}\n{}\n",
+        key_id_hex(&id),
+        STANDARD.encode(signature_bytes),
+        STANDARD.encode(global_signature)
+    ))
+}
+
+/// Verify a detached signature of `data` created by `did`, returning the trusted comment
+pub fn verify(did: &DID, data: &[u8], signature: &str) -> Result<String, Error> {
+    let mut lines = signature.lines().map(|line| line.trim_end_matches('\r'));
+
+    let (Some(untrusted), Some(encoded), Some(trusted), Some(encoded_global)) =
+        (lines.next(), lines.next(), lines.next(), lines.next())
+    else {
+        return Err(Error::InvalidDataType);
+    };
+
+    if !untrusted.starts_with(UNTRUSTED_COMMENT) {
+        return Err(Error::InvalidDataType);
+    }
+
+    let trusted_comment = trusted
+        .strip_prefix(TRUSTED_COMMENT)
+        .ok_or(Error::InvalidDataType)?;
+
+    let signature_bytes = STANDARD
+        .decode(encoded.trim())
+        .map_err(|_| Error::InvalidDataType)?;
+
+    if signature_bytes.len() != 74 {
+        return Err(Error::InvalidSignature);
+    }
+
+    if &signature_bytes[..2] != SIGNATURE_ALGORITHM {
+        return Err(Error::OtherWithContext(
+            "only legacy (Ed) minisign signatures are supported".into(),
+        ));
+    }
+
+    if signature_bytes[2..10] != key_id(did) {
+        return Err(Error::PublicKeyInvalid);
+    }
+
+    let public =
+        PublicKey::from_bytes(&did.public_key_bytes()).map_err(|_| Error::PublicKeyInvalid)?;
+
+    let raw_signature = &signature_bytes[10..];
+    let signature = Signature::from_bytes(raw_signature).map_err(|_| Error::InvalidSignature)?;
+    public
+        .verify(data, &signature)
+        .map_err(|_| Error::InvalidSignature)?;
+
+    let global_bytes = STANDARD
+        .decode(encoded_global.trim())
+        .map_err(|_| Error::InvalidDataType)?;
+    let global_signature =
+        Signature::from_bytes(&global_bytes).map_err(|_| Error::InvalidSignature)?;
+
+    let mut global = Vec::with_capacity(raw_signature.len() + trusted_comment.len());
+    global.extend_from_slice(raw_signature);
+    global.extend_from_slice(trusted_comment.as_bytes());
+
+    public
+        .verify(&global, &global_signature)
+        .map_err(|_| Error::InvalidSignature)?;
+
+    Ok(trusted_comment.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{public_key, sign, verify};
+    use crate::crypto::DID;
+
+    #[test]
+    fn sign_and_verify() -> anyhow::Result<()> {
+        let keypair = DID::default();
+        let data = b"Hello, World";
+
+        let signature = sign(&keypair, data, Some("file:hello.txt"))?;
+        let comment = verify(&keypair, data, &signature)?;
+        assert_eq!(comment, "file:hello.txt");
+
+        let signature = sign(&keypair, data, None)?;
+        let comment = verify(&keypair, data, &signature)?;
+        assert!(comment.contains(&keypair.to_string()));
+
+        assert!(public_key(&keypair).starts_with("untrusted comment: minisign public key "));
+        Ok(())
+    }
+
+    #[test]
+    fn reject_invalid_signature() -> anyhow::Result<()> {
+        let keypair = DID::default();
+        let data = b"Hello, World";
+
+        let signature = sign(&keypair, data, Some("file:hello.txt"))?;
+
+        assert!(verify(&keypair, b"Hello, Warp", &signature).is_err());
+        assert!(verify(&DID::default(), data, &signature).is_err());
+
+        let tampered = signature.replace("file:hello.txt", "file:other.txt");
+        assert!(verify(&keypair, data, &tampered).is_err());
+
+        assert!(sign(&keypair, data, Some("multiple\nlines")).is_err());
+        Ok(())
+    }
+}
@@ -13,6 +13,7 @@ pub use zeroize;
 pub mod cipher;
 pub mod hash;
 pub mod keypair;
+pub mod minisign;
 pub mod multihash;
 
 use serde::{Deserialize, Deserializer, Serialize};
@@ -37,7 +37,150 @@ pub struct Data {
     data_type: DataType,
 
     /// Data that is stored for the Data Object.
+    /// Note: Only used for uncompressed json payloads. Other payloads are stored in `binary`
+    #[serde(default)]
     payload: Value,
+
+    /// Encoding of the payload
+    #[serde(default, skip_serializing_if = "PayloadEncoding::is_json")]
+    encoding: PayloadEncoding,
+
+    /// Whether the encoded payload is compressed with zstd
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    compressed: bool,
+
+    /// Encoded payload for binary encodings or compressed payloads
+    #[serde(default, skip_serializing_if = "Vec::is_empty", with = "binary")]
+    binary: Vec<u8>,
+}
+
+/// Payloads that are encoded to a size larger than this threshold are compressed
+pub const COMPRESSION_THRESHOLD: usize = 1024;
+
+/// Encoding used for the payload of [`Data`]
+#[derive(Hash, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Display, Default)]
+#[serde(rename_all = "lowercase")]
+#[repr(C)]
+pub enum PayloadEncoding {
+    #[display(fmt = "json")]
+    #[default]
+    Json,
+    #[display(fmt = "cbor")]
+    Cbor,
+    #[display(fmt = "bincode")]
+    Bincode,
+}
+
+impl PayloadEncoding {
+    fn is_json(&self) -> bool {
+        matches!(self, PayloadEncoding::Json)
+    }
+
+    fn encode<T: Serialize>(&self, payload: T) -> Result<Vec<u8>, Error> {
+        match self {
+            PayloadEncoding::Json => serde_json::to_vec(&payload).map_err(Error::from),
+            PayloadEncoding::Cbor => serde_cbor::to_vec(&payload).map_err(Error::from),
+            PayloadEncoding::Bincode => bincode::serialize(&payload).map_err(Error::from),
+        }
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Error> {
+        match self {
+            PayloadEncoding::Json => serde_json::from_slice(bytes).map_err(Error::from),
+            PayloadEncoding::Cbor => serde_cbor::from_slice(bytes).map_err(Error::from),
+            PayloadEncoding::Bincode => bincode::deserialize(bytes).map_err(Error::from),
+        }
+    }
+}
+
+/// Binary payloads are represented as base64 in human readable formats (eg json) and as bytes otherwise
+mod binary {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        match serializer.is_human_readable() {
+            true => serializer.serialize_str(&STANDARD.encode(bytes)),
+            false => serializer.serialize_bytes(bytes),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        match deserializer.is_human_readable() {
+            true => {
+                let encoded = String::deserialize(deserializer)?;
+                STANDARD.decode(encoded).map_err(D::Error::custom)
+            }
+            false => deserialize_bytes(deserializer),
+        }
+    }
+
+    fn deserialize_bytes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        struct BytesVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for BytesVisitor {
+            type Value = Vec<u8>;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a byte array")
+            }
+
+            fn visit_bytes<E: Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+                Ok(v.to_vec())
+            }
+
+            fn visit_byte_buf<E: Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+                Ok(v)
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> Result<Self::Value, A::Error> {
+                let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+                while let Some(byte) = seq.next_element()? {
+                    bytes.push(byte);
+                }
+                Ok(bytes)
+            }
+        }
+
+        deserializer.deserialize_byte_buf(BytesVisitor)
+    }
+}
+
+/// Compress `bytes` with zstd
+#[cfg(not(target_arch = "wasm32"))]
+pub fn compress(bytes: &[u8]) -> Result<Vec<u8>, Error> {
+    zstd::encode_all(bytes, 0).map_err(Error::from)
+}
+
+/// Decompress zstd compressed `bytes`
+#[cfg(not(target_arch = "wasm32"))]
+pub fn decompress(bytes: &[u8]) -> Result<Vec<u8>, Error> {
+    zstd::decode_all(bytes).map_err(Error::from)
+}
+
+/// Decompress zstd compressed `bytes`, rejecting payloads that would be larger than `limit` once decompressed
+#[cfg(not(target_arch = "wasm32"))]
+pub fn decompress_bounded(bytes: &[u8], limit: usize) -> Result<Vec<u8>, Error> {
+    zstd::bulk::decompress(bytes, limit).map_err(Error::from)
+}
+
+// zstd is unavailable when targeting wasm, so payloads are left uncompressed
+#[cfg(target_arch = "wasm32")]
+pub fn compress(_: &[u8]) -> Result<Vec<u8>, Error> {
+    Err(Error::Unimplemented)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn decompress(_: &[u8]) -> Result<Vec<u8>, Error> {
+    Err(Error::Unimplemented)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn decompress_bounded(_: &[u8], _: usize) -> Result<Vec<u8>, Error> {
+    Err(Error::Unimplemented)
 }
 
 #[derive(Hash, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Display)]
@@ -101,6 +244,9 @@ impl Default for Data {
             size: 0,
             data_type: DataType::default(),
             payload: Value::Null,
+            encoding: PayloadEncoding::default(),
+            compressed: false,
+            binary: Vec::new(),
         }
     }
 }
@@ -153,12 +299,25 @@ impl Data {
     where
         T: Serialize,
     {
-        let payload = serde_json::to_value(payload)?;
-        Ok(Data {
+        Self::new_with_encoding(data_type, payload, PayloadEncoding::Json)
+    }
+
+    /// Creates a instance of `Data` with `Module` and `Payload`, using the supplied encoding for the payload
+    pub fn new_with_encoding<T>(
+        data_type: DataType,
+        payload: T,
+        encoding: PayloadEncoding,
+    ) -> Result<Self, Error>
+    where
+        T: Serialize,
+    {
+        let mut data = Data {
             data_type,
-            payload,
+            encoding,
             ..Default::default()
-        })
+        };
+        data.set_payload(payload)?;
+        Ok(data)
     }
 
     /// Return the UUID of the data object
@@ -166,12 +325,48 @@ impl Data {
         self.id
     }
 
-    /// Set the payload for `Data`
+    /// Returns the encoding of the payload
+    pub fn encoding(&self) -> PayloadEncoding {
+        self.encoding
+    }
+
+    /// Returns true if the payload is stored compressed
+    pub fn compressed(&self) -> bool {
+        self.compressed
+    }
+
+    /// Set the payload for `Data`, encoded with the encoding of `Data`.
+    /// Payloads larger than [`COMPRESSION_THRESHOLD`] once encoded are compressed
     pub fn set_payload<T>(&mut self, payload: T) -> Result<(), Error>
     where
         T: Serialize,
     {
-        self.payload = serde_json::to_value(payload)?;
+        let bytes = self.encoding.encode(&payload)?;
+
+        if bytes.len() > COMPRESSION_THRESHOLD {
+            match compress(&bytes) {
+                Ok(compressed) if compressed.len() < bytes.len() => {
+                    self.payload = Value::Null;
+                    self.binary = compressed;
+                    self.compressed = true;
+                    return Ok(());
+                }
+                Ok(_) => {}
+                Err(e) => tracing::trace!(error = %e, "payload left uncompressed"),
+            }
+        }
+
+        self.compressed = false;
+        match self.encoding {
+            PayloadEncoding::Json => {
+                self.payload = serde_json::from_slice(&bytes)?;
+                self.binary = Vec::new();
+            }
+            _ => {
+                self.payload = Value::Null;
+                self.binary = bytes;
+            }
+        }
         Ok(())
     }
 
@@ -180,13 +375,23 @@ impl Data {
     where
         T: DeserializeOwned,
     {
-        serde_json::from_value(self.payload.clone()).map_err(Error::from)
+        if self.compressed {
+            let bytes = decompress(&self.binary)?;
+            return self.encoding.decode(&bytes);
+        }
+
+        match self.encoding {
+            PayloadEncoding::Json => {
+                serde_json::from_value(self.payload.clone()).map_err(Error::from)
+            }
+            _ => self.encoding.decode(&self.binary),
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::{Data, DataType};
+    use super::{Data, DataType, PayloadEncoding, COMPRESSION_THRESHOLD};
 
     #[test]
     fn data_default_test() -> Result<(), crate::error::Error> {
@@ -231,4 +436,44 @@ mod test {
         );
         Ok(())
     }
+
+    #[test]
+    fn binary_payload_test() -> Result<(), crate::error::Error> {
+        let payload = vec![String::from("Hello"), String::from("World")];
+
+        for encoding in [PayloadEncoding::Cbor, PayloadEncoding::Bincode] {
+            let data = Data::new_with_encoding(DataType::Unknown, &payload, encoding)?;
+            assert_eq!(data.encoding(), encoding);
+            assert!(!data.compressed());
+            assert_eq!(data.payload::<Vec<String>>()?, payload);
+
+            let json = serde_json::to_string(&data)?;
+            let data = serde_json::from_str::<Data>(&json)?;
+            assert_eq!(data.payload::<Vec<String>>()?, payload);
+
+            let cbor = serde_cbor::to_vec(&data)?;
+            let data = serde_cbor::from_slice::<Data>(&cbor)?;
+            assert_eq!(data.payload::<Vec<String>>()?, payload);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn compressed_payload_test() -> Result<(), crate::error::Error> {
+        let payload = "a".repeat(COMPRESSION_THRESHOLD * 4);
+
+        let data = Data::new(DataType::Unknown, &payload)?;
+        assert!(data.compressed());
+        assert_eq!(data.payload::<String>()?, payload);
+
+        let json = serde_json::to_string(&data)?;
+        assert!(json.len() < payload.len());
+        let data = serde_json::from_str::<Data>(&json)?;
+        assert_eq!(data.payload::<String>()?, payload);
+
+        let data = Data::new(DataType::Unknown, "small")?;
+        assert!(!data.compressed());
+        assert_eq!(data.payload::<String>()?, "small");
+        Ok(())
+    }
 }
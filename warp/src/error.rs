@@ -1,5 +1,10 @@
 /// Errors that would host custom errors for modules, utilities, etc.
+use std::fmt::Display;
+
 use thiserror::Error;
+use uuid::Uuid;
+
+use crate::crypto::DID;
 
 #[allow(clippy::large_enum_variant)]
 #[derive(Error, Debug)]
@@ -65,6 +70,8 @@ pub enum Error {
     IdentityNotCreated,
     #[error("Identity exist with the same information")]
     IdentityExist,
+    #[error("Username is already taken by another identity")]
+    UsernameTaken,
     #[error("Identity does not exist")]
     IdentityDoesntExist,
     #[error("Identity was invalid")]
@@ -145,6 +152,10 @@ pub enum Error {
     ReactionExist,
     #[error("Reaction to the message does not exist")]
     ReactionDoesntExist,
+    #[error("Reaction is invalid")]
+    InvalidReaction,
+    #[error("Custom emoji is not in an available emoji pack")]
+    EmojiNotAvailable,
     #[error("Message is already pinned")]
     MessagePinned,
     #[error("Message is not pinned")]
@@ -155,6 +166,10 @@ pub enum Error {
     MessageNotFound,
     #[error("Page of messages not found")]
     PageNotFound,
+    #[error("Slow mode is enabled. Wait {remaining} seconds before sending another message")]
+    SlowModeActive { remaining: u64 },
+    #[error("Message is kept by the retention policy of the conversation")]
+    MessageRetained,
     #[error("Group could not be created at this time")]
     CannotCreateGroup,
     #[error("Unable to join group")]
@@ -274,6 +289,8 @@ pub enum Error {
     //Misc
     #[error("Unauthorized")]
     Unauthorized,
+    #[error("Instance is a read-only replica")]
+    ReadOnlyReplica,
     #[error("Length for '{context}' is invalid. Current length: {current}. Minimum Length: {minimum:?}, Maximum: {maximum:?}")]
     InvalidLength {
         context: String,
@@ -320,3 +337,126 @@ pub enum Error {
     #[error("An unknown error has occurred")]
     Other,
 }
+
+/// Operation that is performed, along with the entities it is performed on. Implementations record it in the logs
+/// of the operation, so a failure reported to the user can be correlated with the failure of the underlying store
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorContext {
+    operation: &'static str,
+    operation_id: Uuid,
+    conversation_id: Option<Uuid>,
+    did: Option<DID>,
+    cid: Option<String>,
+}
+
+impl ErrorContext {
+    pub fn new(operation: &'static str) -> Self {
+        Self {
+            operation,
+            operation_id: Uuid::new_v4(),
+            conversation_id: None,
+            did: None,
+            cid: None,
+        }
+    }
+
+    pub fn with_conversation(mut self, conversation_id: Uuid) -> Self {
+        self.conversation_id = Some(conversation_id);
+        self
+    }
+
+    pub fn with_did(mut self, did: &DID) -> Self {
+        self.did = Some(did.clone());
+        self
+    }
+
+    pub fn with_cid(mut self, cid: impl Display) -> Self {
+        self.cid = Some(cid.to_string());
+        self
+    }
+
+    pub fn operation(&self) -> &'static str {
+        self.operation
+    }
+
+    pub fn operation_id(&self) -> Uuid {
+        self.operation_id
+    }
+
+    pub fn conversation_id(&self) -> Option<Uuid> {
+        self.conversation_id
+    }
+
+    pub fn did(&self) -> Option<&DID> {
+        self.did.as_ref()
+    }
+
+    pub fn cid(&self) -> Option<&str> {
+        self.cid.as_deref()
+    }
+}
+
+impl Display for ErrorContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "operation: {}, operation_id: {}",
+            self.operation, self.operation_id
+        )?;
+        if let Some(conversation_id) = &self.conversation_id {
+            write!(f, ", conversation_id: {conversation_id}")?;
+        }
+        if let Some(did) = &self.did {
+            write!(f, ", did: {did}")?;
+        }
+        if let Some(cid) = &self.cid {
+            write!(f, ", cid: {cid}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Failure of an operation along with the [`ErrorContext`] it was performed in, so an error returned by the operation
+/// can be correlated with the logs of the operation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OperationFailure {
+    context: ErrorContext,
+    error: String,
+}
+
+impl OperationFailure {
+    pub fn new(context: ErrorContext, error: &Error) -> Self {
+        Self {
+            context,
+            error: error.to_string(),
+        }
+    }
+
+    pub fn context(&self) -> &ErrorContext {
+        &self.context
+    }
+
+    /// Message of the error returned by the operation
+    pub fn error(&self) -> &str {
+        &self.error
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use uuid::Uuid;
+
+    use super::ErrorContext;
+
+    #[test]
+    fn context_is_displayed_with_its_entities() {
+        let conversation_id = Uuid::new_v4();
+        let context = ErrorContext::new("send").with_conversation(conversation_id);
+
+        let display = context.to_string();
+        assert!(display.starts_with("operation: send"));
+        assert!(display.contains(&context.operation_id().to_string()));
+        assert!(display.contains(&format!("conversation_id: {conversation_id}")));
+        assert!(!display.contains("did:"));
+    }
+}
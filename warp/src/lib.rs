@@ -8,6 +8,8 @@ pub mod error;
 pub mod module;
 pub mod multipass;
 pub mod raygun;
+pub mod scoped;
+pub mod subscription;
 pub mod tesseract;
 pub mod warp;
 
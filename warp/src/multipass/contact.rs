@@ -0,0 +1,252 @@
+//! Encoding and decoding of contact lists for import and export.
+use serde::{Deserialize, Serialize};
+
+use crate::{crypto::DID, error::Error};
+
+/// Property used to store the DID of a contact within a vCard
+pub const VCARD_DID_PROPERTY: &str = "X-DID";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContactFormat {
+    /// JSON array of contacts
+    Json,
+    /// vCard 4.0 with the DID stored under [`VCARD_DID_PROPERTY`]
+    VCard,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Contact {
+    did: DID,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    username: Option<String>,
+}
+
+impl Contact {
+    pub fn new(did: DID, username: Option<String>) -> Self {
+        Self { did, username }
+    }
+}
+
+impl Contact {
+    pub fn did(&self) -> &DID {
+        &self.did
+    }
+
+    pub fn username(&self) -> Option<&str> {
+        self.username.as_deref()
+    }
+}
+
+/// Encode a list of contacts into the specified format
+pub fn encode(contacts: &[Contact], format: ContactFormat) -> Result<Vec<u8>, Error> {
+    match format {
+        ContactFormat::Json => serde_json::to_vec(contacts).map_err(Error::from),
+        ContactFormat::VCard => {
+            let mut card = String::new();
+            for contact in contacts {
+                let did = contact.did.to_string();
+                let name = contact.username.as_deref().unwrap_or(&did);
+                card.push_str("BEGIN:VCARD\r\n");
+                card.push_str("VERSION:4.0\r\n");
+                card.push_str(&format!("FN:{}\r\n", escape(name)));
+                card.push_str(&format!("{VCARD_DID_PROPERTY}:{did}\r\n"));
+                card.push_str("END:VCARD\r\n");
+            }
+            Ok(card.into_bytes())
+        }
+    }
+}
+
+/// Decode a list of contacts from the specified format.
+/// Duplicate entries are merged by their DID, keeping the first username found.
+pub fn decode(data: &[u8], format: ContactFormat) -> Result<Vec<Contact>, Error> {
+    let contacts = match format {
+        ContactFormat::Json => serde_json::from_slice::<Vec<Contact>>(data)?,
+        ContactFormat::VCard => {
+            let data = std::str::from_utf8(data).map_err(|_| Error::InvalidDataType)?;
+            decode_vcard(data)?
+        }
+    };
+
+    let mut merged: Vec<Contact> = Vec::with_capacity(contacts.len());
+
+    for contact in contacts {
+        match merged.iter_mut().find(|item| item.did == contact.did) {
+            Some(existing) => {
+                if existing.username.is_none() {
+                    existing.username = contact.username;
+                }
+            }
+            None => merged.push(contact),
+        }
+    }
+
+    Ok(merged)
+}
+
+fn decode_vcard(data: &str) -> Result<Vec<Contact>, Error> {
+    // unfold lines that are continued with a leading space or tab
+    let mut lines: Vec<String> = vec![];
+    for line in data.lines() {
+        match line.strip_prefix([' ', '\t']) {
+            Some(continued) if !lines.is_empty() => {
+                if let Some(last) = lines.last_mut() {
+                    last.push_str(continued);
+                }
+            }
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    let mut contacts = vec![];
+    let mut current: Option<(Option<DID>, Option<String>)> = None;
+
+    for line in lines {
+        let line = line.trim_end();
+        if line.is_empty() {
+            continue;
+        }
+
+        let Some((property, value)) = line.split_once(':') else {
+            return Err(Error::InvalidDataType);
+        };
+
+        // strip out any parameters (eg `FN;CHARSET=UTF-8`)
+        let name = property
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_uppercase();
+
+        match name.as_str() {
+            "BEGIN" if value.eq_ignore_ascii_case("VCARD") => {
+                if current.replace((None, None)).is_some() {
+                    return Err(Error::InvalidDataType);
+                }
+            }
+            "END" if value.eq_ignore_ascii_case("VCARD") => {
+                let Some((did, username)) = current.take() else {
+                    return Err(Error::InvalidDataType);
+                };
+                // Cards without a DID are skipped since they cannot be associated with an identity
+                if let Some(did) = did {
+                    contacts.push(Contact::new(did, username));
+                }
+            }
+            name => {
+                let Some((did, username)) = current.as_mut() else {
+                    return Err(Error::InvalidDataType);
+                };
+
+                match name {
+                    "FN" => {
+                        let value = unescape(value);
+                        if !value.is_empty() {
+                            *username = Some(value);
+                        }
+                    }
+                    VCARD_DID_PROPERTY => *did = Some(value.trim().parse()?),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    if current.is_some() {
+        return Err(Error::InvalidDataType);
+    }
+
+    // Use of the DID as the formatted name is only a placeholder when no username is available
+    for contact in contacts.iter_mut() {
+        if contact.username.as_deref() == Some(contact.did.to_string().as_str()) {
+            contact.username = None;
+        }
+    }
+
+    Ok(contacts)
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '\\' => escaped.push_str("\\\\"),
+            ',' => escaped.push_str("\\,"),
+            ';' => escaped.push_str("\\;"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => match chars.next() {
+                Some('n') | Some('N') => unescaped.push('\n'),
+                Some(ch) => unescaped.push(ch),
+                None => {}
+            },
+            ch => unescaped.push(ch),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode, encode, Contact, ContactFormat};
+    use crate::crypto::DID;
+
+    #[test]
+    fn json_round_trip() -> anyhow::Result<()> {
+        let contacts = vec![
+            Contact::new(DID::default(), Some("JohnDoe".into())),
+            Contact::new(DID::default(), None),
+        ];
+
+        let bytes = encode(&contacts, ContactFormat::Json)?;
+        let decoded = decode(&bytes, ContactFormat::Json)?;
+        assert_eq!(contacts, decoded);
+        Ok(())
+    }
+
+    #[test]
+    fn vcard_round_trip() -> anyhow::Result<()> {
+        let contacts = vec![
+            Contact::new(DID::default(), Some("Doe, John; Jr\\".into())),
+            Contact::new(DID::default(), None),
+        ];
+
+        let bytes = encode(&contacts, ContactFormat::VCard)?;
+        let decoded = decode(&bytes, ContactFormat::VCard)?;
+        assert_eq!(contacts, decoded);
+        Ok(())
+    }
+
+    #[test]
+    fn vcard_folded_lines_and_duplicates() -> anyhow::Result<()> {
+        let did = DID::default().to_string();
+        let (head, tail) = did.split_at(20);
+        let card = format!(
+            "BEGIN:VCARD\nVERSION:4.0\nX-DID:{head}\n {tail}\nEND:VCARD\nBEGIN:VCARD\nVERSION:4.0\nFN;CHARSET=UTF-8:JaneDoe\nX-DID:{did}\nEND:VCARD\n"
+        );
+
+        let decoded = decode(card.as_bytes(), ContactFormat::VCard)?;
+        assert_eq!(decoded.len(), 1);
+        assert_eq!(decoded[0].did().to_string(), did);
+        assert_eq!(decoded[0].username(), Some("JaneDoe"));
+        Ok(())
+    }
+
+    #[test]
+    fn vcard_invalid() {
+        assert!(decode(b"BEGIN:VCARD\nFN:JohnDoe\n", ContactFormat::VCard).is_err());
+        assert!(decode(b"FN:JohnDoe\n", ContactFormat::VCard).is_err());
+    }
+}
@@ -1,4 +1,6 @@
-use crate::{constellation::file::FileType, crypto::DID, error::Error};
+use crate::{
+    constellation::file::FileType, crypto::DID, error::Error, multipass::share::ShareCode,
+};
 use bytes::Bytes;
 use chrono::{DateTime, Utc};
 use derive_more::Display;
@@ -42,6 +44,245 @@ pub enum Platform {
     Unknown,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Display)]
+#[serde(rename_all = "lowercase")]
+#[repr(C)]
+pub enum ActivityKind {
+    #[display(fmt = "playing")]
+    Playing,
+    #[display(fmt = "listening")]
+    Listening,
+    #[display(fmt = "watching")]
+    Watching,
+    #[display(fmt = "streaming")]
+    Streaming,
+    #[display(fmt = "custom")]
+    Custom,
+}
+
+/// Activity published alongside the status of an identity (eg "Listening to ...")
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IdentityActivity {
+    kind: ActivityKind,
+    title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    details: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    started: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ends: Option<DateTime<Utc>>,
+}
+
+impl IdentityActivity {
+    pub fn new(kind: ActivityKind, title: impl Into<String>) -> Self {
+        Self {
+            kind,
+            title: title.into(),
+            details: None,
+            started: None,
+            ends: None,
+        }
+    }
+
+    pub fn set_details(&mut self, details: Option<String>) {
+        self.details = details
+    }
+
+    pub fn set_started(&mut self, started: Option<DateTime<Utc>>) {
+        self.started = started
+    }
+
+    /// Time after which the activity is no longer shown
+    pub fn set_ends(&mut self, ends: Option<DateTime<Utc>>) {
+        self.ends = ends
+    }
+}
+
+impl IdentityActivity {
+    pub fn kind(&self) -> ActivityKind {
+        self.kind
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn details(&self) -> Option<&str> {
+        self.details.as_deref()
+    }
+
+    pub fn started(&self) -> Option<DateTime<Utc>> {
+        self.started
+    }
+
+    pub fn ends(&self) -> Option<DateTime<Utc>> {
+        self.ends
+    }
+
+    /// Returns true if the activity has ended
+    pub fn is_expired(&self) -> bool {
+        self.is_expired_at(Utc::now())
+    }
+
+    /// Returns true if the activity has ended by `now`
+    pub fn is_expired_at(&self, now: DateTime<Utc>) -> bool {
+        self.ends.is_some_and(|ends| ends <= now)
+    }
+}
+
+/// Determines who is able to see a profile field
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, Display)]
+#[serde(rename_all = "lowercase")]
+#[repr(C)]
+pub enum FieldVisibility {
+    #[display(fmt = "public")]
+    #[default]
+    Public,
+    #[display(fmt = "friends")]
+    Friends,
+}
+
+/// Value of a profile field along with who is able to see it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ProfileField {
+    value: String,
+    #[serde(default)]
+    visibility: FieldVisibility,
+}
+
+impl ProfileField {
+    pub fn new(value: impl Into<String>, visibility: FieldVisibility) -> Self {
+        Self {
+            value: value.into(),
+            visibility,
+        }
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    pub fn visibility(&self) -> FieldVisibility {
+        self.visibility
+    }
+
+    fn visible_to(&self, is_friend: bool) -> bool {
+        is_friend || self.visibility == FieldVisibility::Public
+    }
+}
+
+/// Optional structured fields shown on the profile of an identity
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProfileFields {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pronouns: Option<ProfileField>,
+    /// IANA timezone name (eg "Europe/Berlin") or UTC offset (eg "UTC+02:00")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timezone: Option<ProfileField>,
+    /// Website links starting with `http://` or `https://`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    links: Vec<ProfileField>,
+}
+
+impl ProfileFields {
+    pub fn set_pronouns(&mut self, pronouns: Option<ProfileField>) {
+        self.pronouns = pronouns
+    }
+
+    pub fn set_timezone(&mut self, timezone: Option<ProfileField>) {
+        self.timezone = timezone
+    }
+
+    pub fn set_links(&mut self, links: Vec<ProfileField>) {
+        self.links = links
+    }
+
+    pub fn add_link(&mut self, link: ProfileField) {
+        self.links.push(link)
+    }
+}
+
+impl ProfileFields {
+    pub fn pronouns(&self) -> Option<&ProfileField> {
+        self.pronouns.as_ref()
+    }
+
+    pub fn timezone(&self) -> Option<&ProfileField> {
+        self.timezone.as_ref()
+    }
+
+    pub fn links(&self) -> &[ProfileField] {
+        &self.links
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pronouns.is_none() && self.timezone.is_none() && self.links.is_empty()
+    }
+
+    /// Returns only the fields that can be seen by an identity, based on whether they are a friend
+    pub fn visible_to(&self, is_friend: bool) -> ProfileFields {
+        ProfileFields {
+            pronouns: self.pronouns.clone().filter(|f| f.visible_to(is_friend)),
+            timezone: self.timezone.clone().filter(|f| f.visible_to(is_friend)),
+            links: self
+                .links
+                .iter()
+                .filter(|f| f.visible_to(is_friend))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+/// Controls what is shared about the friends list of the identity. Nothing is shared by default
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FriendsPrivacy {
+    /// Who is able to see how many friends the identity has, if anyone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    friend_count: Option<FieldVisibility>,
+    /// Who is able to find out which of their friends are also friends of the identity, if anyone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mutual_friends: Option<FieldVisibility>,
+}
+
+impl FriendsPrivacy {
+    pub fn set_friend_count(&mut self, visibility: Option<FieldVisibility>) {
+        self.friend_count = visibility
+    }
+
+    pub fn set_mutual_friends(&mut self, visibility: Option<FieldVisibility>) {
+        self.mutual_friends = visibility
+    }
+}
+
+impl FriendsPrivacy {
+    pub fn friend_count(&self) -> Option<FieldVisibility> {
+        self.friend_count
+    }
+
+    pub fn mutual_friends(&self) -> Option<FieldVisibility> {
+        self.mutual_friends
+    }
+
+    /// Returns true if the friend count can be shared with an identity
+    pub fn share_friend_count(&self, is_friend: bool) -> bool {
+        Self::allows(self.friend_count, is_friend)
+    }
+
+    /// Returns true if mutual friends can be shared with an identity
+    pub fn share_mutual_friends(&self, is_friend: bool) -> bool {
+        Self::allows(self.mutual_friends, is_friend)
+    }
+
+    fn allows(visibility: Option<FieldVisibility>, is_friend: bool) -> bool {
+        match visibility {
+            Some(FieldVisibility::Public) => true,
+            Some(FieldVisibility::Friends) => is_friend,
+            None => false,
+        }
+    }
+}
+
 /// Profile containing the newly created `Identity` and a passphrase, if applicable.
 #[derive(Default, Debug, PartialEq, Eq)]
 pub struct IdentityProfile {
@@ -109,10 +350,55 @@ pub struct Relationship {
     blocked_by: bool,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RelationshipChange {
+    RequestSent,
+    RequestReceived,
+    IncomingRequestRejected,
+    OutgoingRequestRejected,
+    IncomingRequestClosed,
+    OutgoingRequestClosed,
+    OutgoingRequestExpired,
+    FriendAdded,
+    FriendRemoved,
+    Blocked,
+    BlockedBy,
+    Unblocked,
+    UnblockedBy,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RelationshipHistoryEntry {
+    change: RelationshipChange,
+    date: DateTime<Utc>,
+}
+
+impl RelationshipHistoryEntry {
+    pub fn new(change: RelationshipChange, date: Option<DateTime<Utc>>) -> Self {
+        Self {
+            change,
+            date: date.unwrap_or_else(Utc::now),
+        }
+    }
+}
+
+impl RelationshipHistoryEntry {
+    pub fn change(&self) -> RelationshipChange {
+        self.change
+    }
+
+    pub fn date(&self) -> DateTime<Utc> {
+        self.date
+    }
+}
+
 #[derive(Default, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct FriendRequest {
     identity: DID,
     date: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
 }
 
 impl FriendRequest {
@@ -120,8 +406,13 @@ impl FriendRequest {
         Self {
             identity,
             date: date.unwrap_or_else(Utc::now),
+            message: None,
         }
     }
+
+    pub fn set_message(&mut self, message: Option<String>) {
+        self.message = message;
+    }
 }
 
 impl FriendRequest {
@@ -132,6 +423,11 @@ impl FriendRequest {
     pub fn identity(&self) -> &DID {
         &self.identity
     }
+
+    /// Greeting message that was sent along with the request
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
 }
 
 impl Relationship {
@@ -215,9 +511,13 @@ impl Display for ShortId {
 
 #[derive(Default, Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct Identity {
-    /// Username of the identity
+    /// Display name of the identity, which can be changed at any time
     username: String,
 
+    /// Handle chosen when the identity was created, which cannot be changed afterwards
+    #[serde(default)]
+    handle: String,
+
     /// Short id derived from the DID to be used along side `Identity::username` (eg `Username#0000`)
     short_id: ShortId,
 
@@ -249,6 +549,10 @@ impl Identity {
         self.username = user.to_string()
     }
 
+    pub fn set_handle(&mut self, handle: &str) {
+        self.handle = handle.to_string()
+    }
+
     pub fn set_status_message(&mut self, message: Option<String>) {
         self.status_message = message
     }
@@ -274,10 +578,19 @@ impl Identity {
 }
 
 impl Identity {
+    /// Display name of the identity. See [`Identity::handle`] for a name that cannot be changed
     pub fn username(&self) -> &str {
         &self.username
     }
 
+    /// Handle of the identity. Identities created before handles were introduced use their username instead
+    pub fn handle(&self) -> &str {
+        match self.handle.is_empty() {
+            true => &self.username,
+            false => &self.handle,
+        }
+    }
+
     pub fn status_message(&self) -> Option<&str> {
         self.status_message.as_deref()
     }
@@ -303,6 +616,21 @@ impl Identity {
     }
 }
 
+impl Identity {
+    /// Create a signed share code for the identity that can be used with [`MultiPass::add_from_share_code`].
+    /// The keypair supplied must belong to the identity.
+    ///
+    /// [`MultiPass::add_from_share_code`]: super::MultiPass::add_from_share_code
+    pub fn to_share_code(&self, keypair: &DID, hints: Vec<String>) -> Result<String, Error> {
+        if keypair != &self.did_key {
+            return Err(Error::PublicKeyInvalid);
+        }
+
+        let username = Some(self.username.clone());
+        ShareCode::new(keypair, username, hints)?.encode()
+    }
+}
+
 #[derive(Debug, Clone)]
 #[allow(clippy::large_enum_variant)]
 pub enum Identifier {
@@ -368,12 +696,18 @@ impl From<&[DID]> for Identifier {
 }
 
 pub enum IdentityUpdate {
+    /// Change the display name of the identity. The handle of the identity is left unchanged
     Username(String),
     Picture(Vec<u8>),
     PicturePath(std::path::PathBuf),
     PictureStream(BoxStream<'static, Result<Vec<u8>, std::io::Error>>),
-    AddMetadataKey { key: String, value: String },
-    RemoveMetadataKey { key: String },
+    AddMetadataKey {
+        key: String,
+        value: String,
+    },
+    RemoveMetadataKey {
+        key: String,
+    },
     ClearPicture,
     Banner(Vec<u8>),
     BannerPath(std::path::PathBuf),
@@ -1,5 +1,6 @@
 #![allow(clippy::result_large_err)]
 
+use bytes::Bytes;
 use chrono::{DateTime, Utc};
 use futures::stream::BoxStream;
 use futures::{Stream, StreamExt};
@@ -14,33 +15,87 @@ use identity::Identity;
 use crate::crypto::DID;
 use crate::error::Error;
 use crate::multipass::identity::{FriendRequest, Identifier, IdentityUpdate};
+use crate::subscription::SubscriptionOptions;
 use crate::tesseract::Tesseract;
 use crate::{Extension, SingleHandle};
 
-use self::identity::{IdentityImage, IdentityProfile, IdentityStatus, Platform, Relationship};
+use self::contact::ContactFormat;
+use self::identity::{
+    FriendsPrivacy, IdentityActivity, IdentityImage, IdentityProfile, IdentityStatus, Platform,
+    ProfileFields, Relationship, RelationshipHistoryEntry,
+};
+use self::notification::{NotificationEvent, NotificationPreferences};
+use self::share::ShareCode;
 
+pub mod contact;
 pub mod generator;
 pub mod identity;
+pub mod notification;
+pub mod share;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 #[allow(clippy::large_enum_variant)]
 pub enum MultiPassEventKind {
-    FriendRequestReceived { from: DID, date: DateTime<Utc> },
-    FriendRequestSent { to: DID, date: DateTime<Utc> },
-    IncomingFriendRequestRejected { did: DID },
-    OutgoingFriendRequestRejected { did: DID },
-    IncomingFriendRequestClosed { did: DID },
-    OutgoingFriendRequestClosed { did: DID },
-    FriendAdded { did: DID },
-    FriendRemoved { did: DID },
-    IdentityOnline { did: DID },
-    IdentityOffline { did: DID },
-    IdentityUpdate { did: DID },
-    Blocked { did: DID },
-    BlockedBy { did: DID },
-    Unblocked { did: DID },
-    UnblockedBy { did: DID },
+    FriendRequestReceived {
+        from: DID,
+        date: DateTime<Utc>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        message: Option<String>,
+    },
+    FriendRequestSent {
+        to: DID,
+        date: DateTime<Utc>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        message: Option<String>,
+    },
+    IncomingFriendRequestRejected {
+        did: DID,
+    },
+    OutgoingFriendRequestRejected {
+        did: DID,
+    },
+    IncomingFriendRequestClosed {
+        did: DID,
+    },
+    OutgoingFriendRequestClosed {
+        did: DID,
+    },
+    /// Outgoing friend request was not answered before it expired
+    FriendRequestExpired {
+        did: DID,
+    },
+    FriendAdded {
+        did: DID,
+    },
+    FriendRemoved {
+        did: DID,
+    },
+    IdentityOnline {
+        did: DID,
+    },
+    IdentityOffline {
+        did: DID,
+    },
+    IdentityUpdate {
+        did: DID,
+    },
+    Blocked {
+        did: DID,
+    },
+    BlockedBy {
+        did: DID,
+    },
+    Unblocked {
+        did: DID,
+    },
+    UnblockedBy {
+        did: DID,
+    },
+    AppDataUpdated {
+        namespace: String,
+    },
+    NotificationPreferencesUpdated,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -68,6 +123,46 @@ pub enum IdentityImportOption<'a> {
 
 pub type MultiPassEventStream = BoxStream<'static, MultiPassEventKind>;
 
+/// Change made by an identity to their profile
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IdentityChange {
+    Username {
+        username: String,
+    },
+    StatusMessage {
+        status_message: Option<String>,
+    },
+    /// Cid of the new profile picture, if any
+    Picture {
+        cid: Option<String>,
+    },
+    /// Cid of the new profile banner, if any
+    Banner {
+        cid: Option<String>,
+    },
+    Status {
+        status: IdentityStatus,
+    },
+    Activity {
+        activity: Option<IdentityActivity>,
+    },
+    /// Profile fields visible to us after the change
+    ProfileFields {
+        fields: ProfileFields,
+    },
+    Platform {
+        platform: Platform,
+    },
+    /// Metadata key that was added, updated, or removed if `value` is `None`
+    MetadataKey {
+        key: String,
+        value: Option<String>,
+    },
+}
+
+pub type IdentityChangeStream = BoxStream<'static, IdentityChange>;
+
 #[async_trait::async_trait]
 pub trait MultiPass:
     Extension
@@ -89,6 +184,24 @@ pub trait MultiPass:
 
     /// Obtain an [`Identity`] using [`Identifier`]
     fn get_identity(&self, id: impl Into<Identifier>) -> GetIdentity;
+
+    /// Verify a share code created by [`Identity::to_share_code`] and send a friend request to the identity
+    async fn add_from_share_code(&mut self, code: &str) -> Result<DID, Error> {
+        let code = ShareCode::decode(code)?;
+        let did = code.did().clone();
+        self.send_request(&did).await?;
+        Ok(did)
+    }
+
+    /// Local history of relationship changes with an identity, ordered from oldest to newest
+    async fn relationship_history(&self, _: &DID) -> Result<Vec<RelationshipHistoryEntry>, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Stream of changes to the profile of a remote identity as they are announced
+    async fn watch_identity(&self, _: &DID) -> Result<IdentityChangeStream, Error> {
+        Err(Error::Unimplemented)
+    }
 }
 
 #[async_trait::async_trait]
@@ -105,6 +218,46 @@ pub trait LocalIdentity: Sync + Send {
     /// Update your own [`Identity`] using [`IdentityUpdate`]
     async fn update_identity(&mut self, option: IdentityUpdate) -> Result<(), Error>;
 
+    /// Store application defined data under a namespace. Supplying empty data will remove the namespace
+    async fn set_app_data(&mut self, _: &str, _: Bytes) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Obtain application defined data stored under a namespace
+    async fn get_app_data(&self, _: &str) -> Result<Bytes, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Create a signed share code for the local [`Identity`]
+    async fn share_code(&self) -> Result<String, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Create a detached minisign signature of exported content (eg a serialized message or file),
+    /// which can be verified outside of warp with [`minisign::public_key`](crate::crypto::minisign::public_key)
+    async fn sign_detached(&self, _: &[u8], _: Option<&str>) -> Result<String, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Obtain the [`NotificationPreferences`] for the local [`Identity`]
+    async fn notification_preferences(&self) -> Result<NotificationPreferences, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Replace the [`NotificationPreferences`] for the local [`Identity`]
+    async fn set_notification_preferences(
+        &mut self,
+        _: NotificationPreferences,
+    ) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Evaluate the [`NotificationPreferences`] to determine if the event should produce a notification
+    async fn should_notify(&self, event: &NotificationEvent) -> Result<bool, Error> {
+        let preferences = self.notification_preferences().await?;
+        Ok(preferences.should_notify(event))
+    }
+
     fn tesseract(&self) -> Tesseract;
 }
 
@@ -131,6 +284,11 @@ pub trait Friends: Sync + Send {
         Err(Error::Unimplemented)
     }
 
+    /// Send friend request to corresponding public key with a short greeting message
+    async fn send_request_with_message(&mut self, _: &DID, _: &str) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
     /// Accept friend request from public key
     async fn accept_request(&mut self, _: &DID) -> Result<(), Error> {
         Err(Error::Unimplemented)
@@ -146,6 +304,11 @@ pub trait Friends: Sync + Send {
         Err(Error::Unimplemented)
     }
 
+    /// Send an outgoing friend request again, renewing its date
+    async fn resend_request(&mut self, _: &DID) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
     /// Check to determine if a request been received from the DID
     async fn received_friend_request_from(&self, _: &DID) -> Result<bool, Error> {
         Err(Error::Unimplemented)
@@ -200,6 +363,37 @@ pub trait Friends: Sync + Send {
     async fn has_friend(&self, _: &DID) -> Result<bool, Error> {
         Err(Error::Unimplemented)
     }
+
+    /// Settings controlling what is shared about the friends list of the account
+    async fn friends_privacy(&self) -> Result<FriendsPrivacy, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Update the settings controlling what is shared about the friends list of the account
+    async fn set_friends_privacy(&mut self, _: FriendsPrivacy) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Number of friends the identity has, if they shared it with us
+    async fn friend_count(&self, _: &DID) -> Result<Option<usize>, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Friends of the account that are also friends of the identity, if they shared it with us
+    async fn mutual_friends(&self, _: &DID) -> Result<Vec<DID>, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Export the list of friends in the specified format
+    async fn export_contacts(&self, _: ContactFormat) -> Result<Vec<u8>, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Import a list of contacts, sending a friend request to or accepting a pending request from
+    /// each contact that is not already a friend. Returns the contacts that were acted on.
+    async fn import_contacts(&mut self, _: ContactFormat, _: &[u8]) -> Result<Vec<DID>, Error> {
+        Err(Error::Unimplemented)
+    }
 }
 
 #[async_trait::async_trait]
@@ -208,6 +402,14 @@ pub trait MultiPassEvent: Sync + Send {
     async fn multipass_subscribe(&mut self) -> Result<MultiPassEventStream, Error> {
         Err(Error::Unimplemented)
     }
+
+    /// Subscribe to an stream of events, buffering at most [`SubscriptionOptions::capacity`] events
+    async fn multipass_subscribe_with(
+        &mut self,
+        _: SubscriptionOptions,
+    ) -> Result<MultiPassEventStream, Error> {
+        Err(Error::Unimplemented)
+    }
 }
 
 #[async_trait::async_trait]
@@ -222,6 +424,16 @@ pub trait IdentityInformation: Send + Sync {
         Err(Error::Unimplemented)
     }
 
+    /// Profile picture belonging to the `Identity`, resized to fit within the given dimension in pixels
+    async fn identity_picture_sized(&self, _: &DID, _: u32) -> Result<IdentityImage, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Profile banner belonging to the `Identity`, resized to fit within the given dimension in pixels
+    async fn identity_banner_sized(&self, _: &DID, _: u32) -> Result<IdentityImage, Error> {
+        Err(Error::Unimplemented)
+    }
+
     /// Identity status to determine if they are online or offline
     async fn identity_status(&self, _: &DID) -> Result<IdentityStatus, Error> {
         Err(Error::Unimplemented)
@@ -232,6 +444,27 @@ pub trait IdentityInformation: Send + Sync {
         Err(Error::Unimplemented)
     }
 
+    /// Activity published by the identity alongside their status, if any
+    async fn identity_activity(&self, _: &DID) -> Result<Option<IdentityActivity>, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Publish or clear the activity shown alongside the status of the identity
+    async fn set_identity_activity(&mut self, _: Option<IdentityActivity>) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Profile fields of the identity. For the own identity, all fields are returned, otherwise
+    /// only the fields the identity has shared with us are returned
+    async fn identity_profile_fields(&self, _: &DID) -> Result<ProfileFields, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Set the profile fields of the identity, replacing any existing fields
+    async fn set_identity_profile_fields(&mut self, _: ProfileFields) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
     /// Find the relationship with an existing identity.
     async fn identity_relationship(&self, _: &DID) -> Result<Relationship, Error> {
         Err(Error::Unimplemented)
@@ -0,0 +1,290 @@
+//! Notification preferences shared between clients.
+//!
+//! Preferences are stored alongside the identity so that every client applies the same rules when
+//! deciding if an event should produce a notification.
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Duration, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{crypto::DID, raygun::Message};
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationMode {
+    /// Notify on every message
+    #[default]
+    All,
+    /// Only notify when mentioned
+    MentionsOnly,
+    /// Never notify
+    Muted,
+}
+
+/// Daily window where do-not-disturb is active.
+/// The window can wrap around midnight (eg 22:00 to 07:00). A window with the same start and end is never active.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DoNotDisturbSchedule {
+    start: NaiveTime,
+    end: NaiveTime,
+    /// Offset from UTC, in seconds, that `start` and `end` are relative to
+    #[serde(default)]
+    utc_offset: i32,
+}
+
+impl DoNotDisturbSchedule {
+    pub fn new(start: NaiveTime, end: NaiveTime, utc_offset: i32) -> Self {
+        Self {
+            start,
+            end,
+            utc_offset,
+        }
+    }
+}
+
+impl DoNotDisturbSchedule {
+    pub fn start(&self) -> NaiveTime {
+        self.start
+    }
+
+    pub fn end(&self) -> NaiveTime {
+        self.end
+    }
+
+    pub fn utc_offset(&self) -> i32 {
+        self.utc_offset
+    }
+
+    /// Check to determine if the schedule is active at the given date
+    pub fn is_active(&self, date: DateTime<Utc>) -> bool {
+        let time = (date + Duration::seconds(self.utc_offset as i64)).time();
+        match self.start.cmp(&self.end) {
+            std::cmp::Ordering::Less => time >= self.start && time < self.end,
+            std::cmp::Ordering::Greater => time >= self.start || time < self.end,
+            std::cmp::Ordering::Equal => false,
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotificationPreferences {
+    /// Mode used for conversations without an override
+    #[serde(default)]
+    mode: NotificationMode,
+    /// Per conversation overrides
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    conversations: BTreeMap<Uuid, NotificationMode>,
+    /// Do-not-disturb until the specified date
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dnd_until: Option<DateTime<Utc>>,
+    /// Recurring do-not-disturb schedule
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dnd_schedule: Option<DoNotDisturbSchedule>,
+    /// Allow mentions to notify while do-not-disturb is active
+    #[serde(default)]
+    dnd_allow_mentions: bool,
+}
+
+impl NotificationPreferences {
+    pub fn set_mode(&mut self, mode: NotificationMode) {
+        self.mode = mode;
+    }
+
+    /// Set an override for a conversation. Supplying `None` will remove the override
+    pub fn set_conversation_mode(&mut self, conversation_id: Uuid, mode: Option<NotificationMode>) {
+        match mode {
+            Some(mode) => {
+                self.conversations.insert(conversation_id, mode);
+            }
+            None => {
+                self.conversations.remove(&conversation_id);
+            }
+        }
+    }
+
+    pub fn set_dnd_until(&mut self, date: Option<DateTime<Utc>>) {
+        self.dnd_until = date;
+    }
+
+    pub fn set_dnd_schedule(&mut self, schedule: Option<DoNotDisturbSchedule>) {
+        self.dnd_schedule = schedule;
+    }
+
+    pub fn set_dnd_allow_mentions(&mut self, allow: bool) {
+        self.dnd_allow_mentions = allow;
+    }
+}
+
+impl NotificationPreferences {
+    pub fn mode(&self) -> NotificationMode {
+        self.mode
+    }
+
+    /// Mode that applies to a conversation, taking any override into account
+    pub fn conversation_mode(&self, conversation_id: Uuid) -> NotificationMode {
+        self.conversations
+            .get(&conversation_id)
+            .copied()
+            .unwrap_or(self.mode)
+    }
+
+    pub fn conversation_overrides(&self) -> &BTreeMap<Uuid, NotificationMode> {
+        &self.conversations
+    }
+
+    pub fn dnd_until(&self) -> Option<DateTime<Utc>> {
+        self.dnd_until
+    }
+
+    pub fn dnd_schedule(&self) -> Option<&DoNotDisturbSchedule> {
+        self.dnd_schedule.as_ref()
+    }
+
+    pub fn dnd_allow_mentions(&self) -> bool {
+        self.dnd_allow_mentions
+    }
+
+    /// Check to determine if do-not-disturb is active at the given date
+    pub fn is_dnd_active(&self, date: DateTime<Utc>) -> bool {
+        self.dnd_until.map(|until| date < until).unwrap_or_default()
+            || self
+                .dnd_schedule
+                .map(|schedule| schedule.is_active(date))
+                .unwrap_or_default()
+    }
+
+    /// Evaluate the preferences to determine if the event should produce a notification
+    pub fn should_notify(&self, event: &NotificationEvent) -> bool {
+        let mode = match event.conversation_id {
+            Some(id) => self.conversation_mode(id),
+            None => self.mode,
+        };
+
+        match mode {
+            NotificationMode::Muted => return false,
+            NotificationMode::MentionsOnly if !event.mentioned => return false,
+            _ => {}
+        }
+
+        if self.is_dnd_active(event.date) {
+            return event.mentioned && self.dnd_allow_mentions;
+        }
+
+        true
+    }
+}
+
+/// Event to be evaluated against [`NotificationPreferences`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NotificationEvent {
+    conversation_id: Option<Uuid>,
+    mentioned: bool,
+    date: DateTime<Utc>,
+}
+
+impl NotificationEvent {
+    pub fn new(conversation_id: Option<Uuid>, mentioned: bool, date: DateTime<Utc>) -> Self {
+        Self {
+            conversation_id,
+            mentioned,
+            date,
+        }
+    }
+
+    /// Create an event from a received message, where `own` is the local identity
+    pub fn from_message(message: &Message, own: &DID) -> Self {
+        Self {
+            conversation_id: Some(message.conversation_id()),
+            mentioned: message.mentions().contains(own),
+            date: message.date(),
+        }
+    }
+}
+
+impl NotificationEvent {
+    pub fn conversation_id(&self) -> Option<Uuid> {
+        self.conversation_id
+    }
+
+    pub fn mentioned(&self) -> bool {
+        self.mentioned
+    }
+
+    pub fn date(&self) -> DateTime<Utc> {
+        self.date
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, NaiveTime, TimeZone, Utc};
+    use uuid::Uuid;
+
+    use super::{
+        DoNotDisturbSchedule, NotificationEvent, NotificationMode, NotificationPreferences,
+    };
+
+    #[test]
+    fn conversation_overrides() {
+        let conversation_id = Uuid::new_v4();
+        let mut preferences = NotificationPreferences::default();
+
+        let event = NotificationEvent::new(Some(conversation_id), false, Utc::now());
+        let mentioned = NotificationEvent::new(Some(conversation_id), true, Utc::now());
+
+        assert!(preferences.should_notify(&event));
+
+        preferences.set_conversation_mode(conversation_id, Some(NotificationMode::MentionsOnly));
+        assert!(!preferences.should_notify(&event));
+        assert!(preferences.should_notify(&mentioned));
+
+        preferences.set_conversation_mode(conversation_id, Some(NotificationMode::Muted));
+        assert!(!preferences.should_notify(&mentioned));
+
+        preferences.set_mode(NotificationMode::Muted);
+        preferences.set_conversation_mode(conversation_id, Some(NotificationMode::All));
+        assert!(preferences.should_notify(&event));
+        assert!(!preferences.should_notify(&NotificationEvent::new(None, false, Utc::now())));
+    }
+
+    #[test]
+    fn dnd_until() {
+        let now = Utc::now();
+        let mut preferences = NotificationPreferences::default();
+        preferences.set_dnd_until(Some(now + Duration::hours(1)));
+
+        assert!(!preferences.should_notify(&NotificationEvent::new(None, true, now)));
+
+        preferences.set_dnd_allow_mentions(true);
+        assert!(preferences.should_notify(&NotificationEvent::new(None, true, now)));
+        assert!(!preferences.should_notify(&NotificationEvent::new(None, false, now)));
+
+        let later = now + Duration::hours(2);
+        assert!(preferences.should_notify(&NotificationEvent::new(None, false, later)));
+    }
+
+    #[test]
+    fn dnd_schedule() {
+        let start = NaiveTime::from_hms_opt(22, 0, 0).unwrap();
+        let end = NaiveTime::from_hms_opt(7, 0, 0).unwrap();
+
+        let schedule = DoNotDisturbSchedule::new(start, end, 0);
+        let night = Utc.with_ymd_and_hms(2024, 1, 1, 23, 30, 0).unwrap();
+        let morning = Utc.with_ymd_and_hms(2024, 1, 1, 6, 0, 0).unwrap();
+        let noon = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+
+        assert!(schedule.is_active(night));
+        assert!(schedule.is_active(morning));
+        assert!(!schedule.is_active(noon));
+
+        // 12:00 UTC is 22:00 at UTC+10
+        let schedule = DoNotDisturbSchedule::new(start, end, 10 * 60 * 60);
+        assert!(schedule.is_active(noon));
+
+        let mut preferences = NotificationPreferences::default();
+        preferences.set_dnd_schedule(Some(DoNotDisturbSchedule::new(start, end, 0)));
+        assert!(!preferences.should_notify(&NotificationEvent::new(None, false, night)));
+        assert!(preferences.should_notify(&NotificationEvent::new(None, false, noon)));
+    }
+}
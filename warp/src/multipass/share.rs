@@ -0,0 +1,192 @@
+//! Compact, signed payload used to share an identity (eg through a QR code).
+//!
+//! A share code contains the DID of the identity, an optional username and optional shuttle hints
+//! that can be used to locate the identity. The payload is signed by the identity so that a share
+//! code cannot be altered without being detected.
+use std::str::FromStr;
+
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signature, Signer, Verifier};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::{
+    crypto::{KeyMaterial, DID},
+    error::Error,
+};
+
+/// Prefix used to identify a share code
+pub const SHARE_CODE_PREFIX: &str = "warp:";
+
+/// Maximum amount of shuttle hints that can be included in a share code
+pub const MAX_SHARE_CODE_HINTS: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShareCode {
+    #[serde(rename = "d")]
+    did: DID,
+    #[serde(rename = "u", default, skip_serializing_if = "Option::is_none")]
+    username: Option<String>,
+    #[serde(rename = "h", default, skip_serializing_if = "Vec::is_empty")]
+    hints: Vec<String>,
+    #[serde(rename = "s", default, skip_serializing_if = "Vec::is_empty")]
+    signature: Vec<u8>,
+}
+
+impl ShareCode {
+    /// Create a share code signed by the supplied keypair
+    pub fn new(keypair: &DID, username: Option<String>, hints: Vec<String>) -> Result<Self, Error> {
+        if hints.len() > MAX_SHARE_CODE_HINTS {
+            return Err(Error::InvalidLength {
+                context: "hints".into(),
+                current: hints.len(),
+                minimum: None,
+                maximum: Some(MAX_SHARE_CODE_HINTS),
+            });
+        }
+
+        let username = username.filter(|username| !username.trim().is_empty());
+
+        let mut code = ShareCode {
+            did: keypair.clone(),
+            username,
+            hints,
+            signature: vec![],
+        };
+
+        let bytes = Zeroizing::new(keypair.private_key_bytes());
+        let secret = SecretKey::from_bytes(&bytes).map_err(|_| Error::PrivateKeyInvalid)?;
+        let public: PublicKey = (&secret).into();
+        let keypair = Keypair { secret, public };
+
+        let signature = keypair.sign(&code.signing_bytes()?);
+        code.signature = signature.to_bytes().to_vec();
+
+        Ok(code)
+    }
+
+    /// Decode a share code, verifying its signature
+    pub fn decode(code: &str) -> Result<Self, Error> {
+        let code = code.trim();
+        let encoded = code
+            .strip_prefix(SHARE_CODE_PREFIX)
+            .ok_or(Error::InvalidDataType)?;
+
+        let bytes = bs58::decode(encoded)
+            .into_vec()
+            .map_err(|_| Error::InvalidDataType)?;
+
+        let code: ShareCode = serde_json::from_slice(&bytes)?;
+
+        if code.hints.len() > MAX_SHARE_CODE_HINTS {
+            return Err(Error::InvalidLength {
+                context: "hints".into(),
+                current: code.hints.len(),
+                minimum: None,
+                maximum: Some(MAX_SHARE_CODE_HINTS),
+            });
+        }
+
+        code.verify()?;
+
+        Ok(code)
+    }
+
+    /// Encode the share code into a string suitable for a QR code or link
+    pub fn encode(&self) -> Result<String, Error> {
+        let bytes = serde_json::to_vec(self)?;
+        Ok(format!(
+            "{SHARE_CODE_PREFIX}{}",
+            bs58::encode(bytes).into_string()
+        ))
+    }
+
+    fn verify(&self) -> Result<(), Error> {
+        let public = PublicKey::from_bytes(&self.did.public_key_bytes())
+            .map_err(|_| Error::PublicKeyInvalid)?;
+        let signature =
+            Signature::from_bytes(&self.signature).map_err(|_| Error::InvalidSignature)?;
+        public
+            .verify(&self.signing_bytes()?, &signature)
+            .map_err(|_| Error::InvalidSignature)
+    }
+
+    fn signing_bytes(&self) -> Result<Vec<u8>, Error> {
+        let unsigned = ShareCode {
+            signature: vec![],
+            ..self.clone()
+        };
+        serde_json::to_vec(&unsigned).map_err(Error::from)
+    }
+}
+
+impl ShareCode {
+    pub fn did(&self) -> &DID {
+        &self.did
+    }
+
+    pub fn username(&self) -> Option<&str> {
+        self.username.as_deref()
+    }
+
+    /// Shuttle addresses that can be used to locate the identity
+    pub fn hints(&self) -> &[String] {
+        &self.hints
+    }
+}
+
+impl FromStr for ShareCode {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ShareCode::decode(s)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ShareCode, MAX_SHARE_CODE_HINTS};
+    use crate::crypto::DID;
+
+    #[test]
+    fn share_code_round_trip() -> anyhow::Result<()> {
+        let keypair = DID::default();
+        let hints = vec!["/ip4/127.0.0.1/tcp/4444".to_string()];
+        let code = ShareCode::new(&keypair, Some("JohnDoe".into()), hints.clone())?;
+
+        let encoded = code.encode()?;
+        let decoded: ShareCode = encoded.parse()?;
+
+        assert_eq!(decoded.did(), &keypair);
+        assert_eq!(decoded.username(), Some("JohnDoe"));
+        assert_eq!(decoded.hints(), hints.as_slice());
+        assert_eq!(code, decoded);
+        Ok(())
+    }
+
+    #[test]
+    fn share_code_rejects_tampering() -> anyhow::Result<()> {
+        let keypair = DID::default();
+        let mut code = ShareCode::new(&keypair, Some("JohnDoe".into()), vec![])?;
+        code.username = Some("JaneDoe".into());
+
+        assert!(ShareCode::decode(&code.encode()?).is_err());
+
+        let mut code = ShareCode::new(&keypair, None, vec![])?;
+        code.did = DID::default();
+
+        assert!(ShareCode::decode(&code.encode()?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn share_code_invalid() {
+        let keypair = DID::default();
+        assert!(ShareCode::decode("warp:invalid").is_err());
+        assert!(ShareCode::decode(&keypair.to_string()).is_err());
+        assert!(ShareCode::new(
+            &keypair,
+            None,
+            vec![String::new(); MAX_SHARE_CODE_HINTS + 1]
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,367 @@
+//! Lightweight framework for building automation (bot) accounts on top of [`RayGun`].
+//!
+//! A [`Bot`] listens for received messages across all conversations, parses messages that are
+//! addressed to it (eg `/weather london` or `/weather@forecast london`) and dispatches them to the
+//! registered [`CommandHandler`]. Any lines returned by a handler are sent back as a reply to the
+//! original message, subject to the [`RateLimit`] of the bot.
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    sync::Arc,
+    task::Poll,
+};
+
+use chrono::{DateTime, Duration, Utc};
+use futures::{stream::SelectAll, StreamExt};
+use uuid::Uuid;
+
+use crate::{crypto::DID, error::Error};
+
+use super::{Message, MessageEventKind, MessageEventStream, MessageType, RayGun, RayGunEventKind};
+
+/// Prefix used to address a command to a bot
+pub const BOT_COMMAND_PREFIX: char = '/';
+
+/// Command parsed from a message addressed to a bot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BotCommand {
+    conversation_id: Uuid,
+    message_id: Uuid,
+    sender: DID,
+    name: String,
+    args: Vec<String>,
+}
+
+impl BotCommand {
+    /// Parse a command from a message. If the command is addressed to a specific bot (eg `/weather@forecast`),
+    /// `None` is returned unless it matches `bot_name`
+    pub fn parse(message: &Message, bot_name: Option<&str>) -> Option<Self> {
+        if message.message_type() != MessageType::Message {
+            return None;
+        }
+
+        let text = message.lines().join("\n");
+        let mut tokens = text.split_whitespace();
+
+        let command = tokens.next()?.strip_prefix(BOT_COMMAND_PREFIX)?;
+
+        let name = match command.split_once('@') {
+            Some((name, target)) => {
+                if !bot_name.is_some_and(|bot| bot.eq_ignore_ascii_case(target)) {
+                    return None;
+                }
+                name
+            }
+            None => command,
+        };
+
+        if name.is_empty() {
+            return None;
+        }
+
+        Some(BotCommand {
+            conversation_id: message.conversation_id(),
+            message_id: message.id(),
+            sender: message.sender().clone(),
+            name: name.to_lowercase(),
+            args: tokens.map(String::from).collect(),
+        })
+    }
+}
+
+impl BotCommand {
+    pub fn conversation_id(&self) -> Uuid {
+        self.conversation_id
+    }
+
+    pub fn message_id(&self) -> Uuid {
+        self.message_id
+    }
+
+    pub fn sender(&self) -> &DID {
+        &self.sender
+    }
+
+    /// Name of the command, without the prefix
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+}
+
+#[async_trait::async_trait]
+pub trait CommandHandler: Send + Sync + 'static {
+    /// Handle a command, returning the lines to reply with, if any
+    async fn handle(&self, command: BotCommand) -> Result<Option<Vec<String>>, Error>;
+}
+
+#[async_trait::async_trait]
+impl<F, Fut> CommandHandler for F
+where
+    F: Fn(BotCommand) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Option<Vec<String>>, Error>> + Send + 'static,
+{
+    async fn handle(&self, command: BotCommand) -> Result<Option<Vec<String>>, Error> {
+        (self)(command).await
+    }
+}
+
+/// Maximum amount of commands a bot will handle within an interval
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    max_commands: usize,
+    interval: Duration,
+}
+
+impl RateLimit {
+    pub fn new(max_commands: usize, interval: Duration) -> Self {
+        Self {
+            max_commands,
+            interval,
+        }
+    }
+
+    pub fn max_commands(&self) -> usize {
+        self.max_commands
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self::new(10, Duration::seconds(10))
+    }
+}
+
+#[derive(Debug, Default)]
+struct RateLimiter {
+    limit: RateLimit,
+    handled: VecDeque<DateTime<Utc>>,
+}
+
+impl RateLimiter {
+    fn check(&mut self, now: DateTime<Utc>) -> bool {
+        while self
+            .handled
+            .front()
+            .is_some_and(|date| *date + self.limit.interval <= now)
+        {
+            self.handled.pop_front();
+        }
+
+        if self.handled.len() >= self.limit.max_commands {
+            return false;
+        }
+
+        self.handled.push_back(now);
+        true
+    }
+}
+
+pub struct Bot<R: RayGun> {
+    raygun: R,
+    name: Option<String>,
+    handlers: HashMap<String, Arc<dyn CommandHandler>>,
+    limiter: RateLimiter,
+}
+
+impl<R: RayGun> Bot<R> {
+    pub fn new(raygun: R) -> Self {
+        Self {
+            raygun,
+            name: None,
+            handlers: HashMap::new(),
+            limiter: RateLimiter::default(),
+        }
+    }
+
+    /// Name used to address the bot directly (eg `/weather@forecast`)
+    pub fn set_name(&mut self, name: impl Into<String>) -> &mut Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn set_rate_limit(&mut self, limit: RateLimit) -> &mut Self {
+        self.limiter.limit = limit;
+        self
+    }
+
+    /// Register a handler for a command (eg `/weather`), replacing any existing handler for the command
+    pub fn on_command<H: CommandHandler>(&mut self, command: &str, handler: H) -> &mut Self {
+        let command = command
+            .trim()
+            .trim_start_matches(BOT_COMMAND_PREFIX)
+            .to_lowercase();
+        self.handlers.insert(command, Arc::new(handler));
+        self
+    }
+
+    pub fn raygun(&self) -> &R {
+        &self.raygun
+    }
+
+    pub fn raygun_mut(&mut self) -> &mut R {
+        &mut self.raygun
+    }
+}
+
+impl<R: RayGun> Bot<R> {
+    /// Dispatch a message to the handler of the command it contains, replying with the result of the handler.
+    /// Returns the id of the reply, if one was sent
+    pub async fn handle_message(&mut self, message: &Message) -> Result<Option<Uuid>, Error> {
+        let Some(command) = BotCommand::parse(message, self.name.as_deref()) else {
+            return Ok(None);
+        };
+
+        let Some(handler) = self.handlers.get(command.name()).cloned() else {
+            return Ok(None);
+        };
+
+        if !self.limiter.check(Utc::now()) {
+            tracing::debug!(command = command.name(), sender = %command.sender(), "rate limit reached. Ignoring command");
+            return Ok(None);
+        }
+
+        let conversation_id = command.conversation_id();
+        let message_id = command.message_id();
+
+        let Some(lines) = handler.handle(command).await? else {
+            return Ok(None);
+        };
+
+        if lines.is_empty() {
+            return Ok(None);
+        }
+
+        self.raygun
+            .reply(conversation_id, message_id, lines)
+            .await
+            .map(Some)
+    }
+
+    /// Process messages from existing and newly created conversations until the event stream ends
+    pub async fn run(mut self) -> Result<(), Error> {
+        let mut events = self.raygun.raygun_subscribe().await?;
+        let mut streams: SelectAll<MessageEventStream> = SelectAll::new();
+
+        for conversation in self.raygun.list_conversations().await? {
+            streams.push(
+                self.raygun
+                    .get_conversation_stream(conversation.id())
+                    .await?,
+            );
+        }
+
+        enum BotEvent {
+            RayGun(RayGunEventKind),
+            Message(MessageEventKind),
+        }
+
+        loop {
+            let event = futures::future::poll_fn(|cx| {
+                if let Poll::Ready(event) = events.poll_next_unpin(cx) {
+                    return Poll::Ready(event.map(BotEvent::RayGun));
+                }
+
+                match streams.poll_next_unpin(cx) {
+                    Poll::Ready(Some(event)) => Poll::Ready(Some(BotEvent::Message(event))),
+                    // an empty set of streams will return `None`, which is treated as pending until a conversation is created
+                    _ => Poll::Pending,
+                }
+            })
+            .await;
+
+            let Some(event) = event else {
+                return Ok(());
+            };
+
+            match event {
+                BotEvent::RayGun(RayGunEventKind::ConversationCreated { conversation_id }) => {
+                    match self.raygun.get_conversation_stream(conversation_id).await {
+                        Ok(stream) => streams.push(stream),
+                        Err(e) => {
+                            tracing::warn!(%conversation_id, error = %e, "unable to subscribe to conversation")
+                        }
+                    }
+                }
+                BotEvent::Message(MessageEventKind::MessageReceived {
+                    conversation_id,
+                    message_id,
+                }) => {
+                    let message = match self.raygun.get_message(conversation_id, message_id).await {
+                        Ok(message) => message,
+                        Err(e) => {
+                            tracing::warn!(%conversation_id, %message_id, error = %e, "unable to get message");
+                            continue;
+                        }
+                    };
+
+                    if let Err(e) = self.handle_message(&message).await {
+                        tracing::warn!(%conversation_id, %message_id, error = %e, "unable to handle command");
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, Utc};
+
+    use super::{BotCommand, RateLimit, RateLimiter};
+    use crate::raygun::{Message, MessageType};
+
+    fn message(text: &str) -> Message {
+        let mut message = Message::default();
+        message.set_lines(vec![text.into()]);
+        message
+    }
+
+    #[test]
+    fn parse_command() {
+        let command = BotCommand::parse(&message("/Weather london  uk"), None).expect("command");
+        assert_eq!(command.name(), "weather");
+        assert_eq!(command.args(), ["london", "uk"]);
+
+        assert!(BotCommand::parse(&message("weather london"), None).is_none());
+        assert!(BotCommand::parse(&message("/"), None).is_none());
+        assert!(BotCommand::parse(&message(""), None).is_none());
+
+        let mut event = message("/weather");
+        event.set_message_type(MessageType::Event);
+        assert!(BotCommand::parse(&event, None).is_none());
+    }
+
+    #[test]
+    fn parse_addressed_command() {
+        let addressed = message("/weather@Forecast london");
+        assert!(BotCommand::parse(&addressed, None).is_none());
+        assert!(BotCommand::parse(&addressed, Some("other")).is_none());
+
+        let command = BotCommand::parse(&addressed, Some("forecast")).expect("command");
+        assert_eq!(command.name(), "weather");
+        assert_eq!(command.args(), ["london"]);
+    }
+
+    #[test]
+    fn rate_limit() {
+        let mut limiter = RateLimiter {
+            limit: RateLimit::new(2, Duration::seconds(10)),
+            ..Default::default()
+        };
+
+        let now = Utc::now();
+        assert!(limiter.check(now));
+        assert!(limiter.check(now));
+        assert!(!limiter.check(now + Duration::seconds(5)));
+        assert!(limiter.check(now + Duration::seconds(10)));
+    }
+}
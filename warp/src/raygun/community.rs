@@ -180,6 +180,8 @@ pub struct CommunityChannel {
     modified: DateTime<Utc>,
     channel_type: CommunityChannelType,
     permissions: CommunityChannelPermissions,
+    #[serde(default)]
+    slow_mode: u64,
 }
 
 impl CommunityChannel {
@@ -204,6 +206,10 @@ impl CommunityChannel {
     pub fn permissions(&self) -> &CommunityChannelPermissions {
         &self.permissions
     }
+    /// Minimum amount of seconds between messages of a member. Disabled if zero
+    pub fn slow_mode(&self) -> u64 {
+        self.slow_mode
+    }
 }
 impl CommunityChannel {
     pub fn set_id(&mut self, id: Uuid) {
@@ -227,6 +233,9 @@ impl CommunityChannel {
     pub fn set_permissions(&mut self, permissions: CommunityChannelPermissions) {
         self.permissions = permissions;
     }
+    pub fn set_slow_mode(&mut self, slow_mode: u64) {
+        self.slow_mode = slow_mode;
+    }
 }
 
 #[derive(Default, Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -494,6 +503,16 @@ pub trait RayGunCommunity: Sync + Send {
     ) -> Result<(), Error> {
         Err(Error::Unimplemented)
     }
+    /// Set the minimum amount of seconds between messages of each member in a channel.
+    /// Zero disables slow mode. Members with [`CommunityPermission::EditChannels`] are exempt
+    async fn edit_community_channel_slow_mode(
+        &mut self,
+        _community_id: Uuid,
+        _channel_id: Uuid,
+        _slow_mode: u64,
+    ) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
     async fn grant_community_channel_permission(
         &mut self,
         _community_id: Uuid,
@@ -683,7 +702,7 @@ pub trait RayGunCommunity: Sync + Send {
     ) -> Result<(Uuid, AttachmentEventStream), Error> {
         Err(Error::Unimplemented)
     }
-    /// Downloads a file that been attached to a message
+    /// Downloads a file that has been attached to a message
     /// Note: Must use the filename associated when downloading
     async fn download_from_community_channel_message(
         &self,
@@ -695,7 +714,7 @@ pub trait RayGunCommunity: Sync + Send {
     ) -> Result<ConstellationProgressStream, Error> {
         Err(Error::Unimplemented)
     }
-    /// Stream a file that been attached to a message
+    /// Stream a file that has been attached to a message
     /// Note: Must use the filename associated when downloading
     async fn download_stream_from_community_channel_message(
         &self,
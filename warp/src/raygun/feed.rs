@@ -0,0 +1,703 @@
+//! Ingestion of RSS and Atom feeds into conversations or community channels on top of [`RayGun`].
+//!
+//! A [`FeedIngestor`] keeps a schedule per feed, fetching the feed through a [`FeedFetcher`] supplied by the embedder
+//! (eg an HTTP client) once it is due. Items that were not seen before are posted to the target of the feed as a
+//! [`FeedItem`] message, with the link on its own line so it can be picked up as a link preview. The first poll of a
+//! feed only posts the most recent items, as configured by [`FeedConfig::backfill`], to avoid flooding the target.
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+use crate::error::Error;
+
+use super::{EmbedState, RayGun};
+
+/// Maximum amount of characters of the summary posted for an item
+pub const MAX_SUMMARY_CHARS: usize = 280;
+
+/// Amount of item keys remembered per feed to deduplicate items
+pub const MAX_SEEN_ITEMS: usize = 1000;
+
+#[async_trait::async_trait]
+pub trait FeedFetcher: Send + Sync + 'static {
+    /// Fetch the document of a feed
+    async fn fetch(&self, url: &str) -> Result<String, Error>;
+}
+
+/// Where the items of a feed are posted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FeedTarget {
+    Conversation(Uuid),
+    CommunityChannel {
+        community_id: Uuid,
+        channel_id: Uuid,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedConfig {
+    pub url: String,
+    pub target: FeedTarget,
+    /// Interval between polls of the feed
+    pub interval: Duration,
+    /// Amount of items posted when the feed is polled for the first time
+    pub backfill: usize,
+    /// Maximum amount of items posted per poll. Remaining items are posted on the following polls
+    pub max_items: usize,
+}
+
+impl FeedConfig {
+    pub fn new(url: impl Into<String>, target: FeedTarget) -> Self {
+        Self {
+            url: url.into(),
+            target,
+            interval: Duration::minutes(15),
+            backfill: 1,
+            max_items: 10,
+        }
+    }
+}
+
+/// Item of a feed
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct FeedItem {
+    /// Guid (RSS) or id (Atom) of the item
+    pub id: Option<String>,
+    pub title: Option<String>,
+    pub link: Option<String>,
+    /// Plain text summary of the item
+    pub summary: Option<String>,
+    pub published: Option<DateTime<Utc>>,
+}
+
+impl FeedItem {
+    /// Key used to deduplicate the item, falling back to the link and then to the title if the item has no id
+    pub fn key(&self) -> Option<&str> {
+        self.id
+            .as_deref()
+            .or(self.link.as_deref())
+            .or(self.title.as_deref())
+    }
+
+    /// Lines of the message posted for the item
+    pub fn lines(&self, feed_title: Option<&str>) -> Vec<String> {
+        let mut lines = vec![];
+
+        let title = self.title.as_deref().unwrap_or("Untitled");
+        match feed_title {
+            Some(feed) => lines.push(format!("**{title}** ({feed})")),
+            None => lines.push(format!("**{title}**")),
+        }
+
+        if let Some(summary) = self.summary.as_deref().filter(|s| !s.is_empty()) {
+            lines.push(summary.to_string());
+        }
+
+        if let Some(link) = &self.link {
+            lines.push(link.clone());
+        }
+
+        lines
+    }
+}
+
+/// Parsed RSS or Atom document
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Feed {
+    pub title: Option<String>,
+    /// Items in the order of the document, which is usually the most recent first
+    pub items: Vec<FeedItem>,
+}
+
+impl Feed {
+    /// Parse an RSS 2.0 or Atom document
+    pub fn parse(document: &str) -> Result<Self, Error> {
+        let (item_tag, is_atom) = if find_element(document, "feed").is_some() {
+            ("entry", true)
+        } else if find_element(document, "rss").is_some()
+            || find_element(document, "rdf:RDF").is_some()
+        {
+            ("item", false)
+        } else {
+            return Err(Error::OtherWithContext(
+                "Document is not an RSS or Atom feed".into(),
+            ));
+        };
+
+        // The title of the feed precedes its items
+        let head = match document.find(&format!("<{item_tag}")) {
+            Some(index) => &document[..index],
+            None => document,
+        };
+
+        let title = element_text(head, "title");
+
+        let items = elements(document, item_tag)
+            .into_iter()
+            .map(|block| match is_atom {
+                true => FeedItem {
+                    id: element_text(block, "id"),
+                    title: element_text(block, "title"),
+                    link: atom_link(block),
+                    summary: element_text(block, "summary")
+                        .or_else(|| element_text(block, "content"))
+                        .map(|text| summarize(&text)),
+                    published: element_text(block, "published")
+                        .or_else(|| element_text(block, "updated"))
+                        .and_then(|date| DateTime::parse_from_rfc3339(&date).ok())
+                        .map(|date| date.with_timezone(&Utc)),
+                },
+                false => FeedItem {
+                    id: element_text(block, "guid"),
+                    title: element_text(block, "title"),
+                    link: element_text(block, "link"),
+                    summary: element_text(block, "description").map(|text| summarize(&text)),
+                    published: element_text(block, "pubDate")
+                        .and_then(|date| DateTime::parse_from_rfc2822(&date).ok())
+                        .map(|date| date.with_timezone(&Utc)),
+                },
+            })
+            .collect();
+
+        Ok(Feed { title, items })
+    }
+}
+
+#[derive(Debug)]
+struct FeedState {
+    config: FeedConfig,
+    next_poll: DateTime<Utc>,
+    polled: bool,
+    seen: HashSet<String>,
+    order: VecDeque<String>,
+}
+
+impl FeedState {
+    fn mark_seen(&mut self, key: &str) -> bool {
+        if !self.seen.insert(key.to_string()) {
+            return false;
+        }
+
+        self.order.push_back(key.to_string());
+        while self.order.len() > MAX_SEEN_ITEMS {
+            if let Some(key) = self.order.pop_front() {
+                self.seen.remove(&key);
+            }
+        }
+        true
+    }
+
+    /// Items that were not seen before, oldest first. Selected items are only marked as seen once they are posted,
+    /// while the items skipped by the backfill of the first poll are marked as seen right away
+    fn select(&mut self, feed: &Feed) -> Vec<FeedItem> {
+        let mut keys = HashSet::new();
+        let mut items = feed
+            .items
+            .iter()
+            .filter(|item| {
+                item.key()
+                    .is_some_and(|key| !self.seen.contains(key) && keys.insert(key))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        match self.polled {
+            true => {
+                // The oldest items are posted first so the remaining items follow on the next polls
+                items.reverse();
+                items.truncate(self.config.max_items);
+            }
+            false => {
+                let limit = self.config.backfill.min(self.config.max_items);
+                for item in items.split_off(limit.min(items.len())) {
+                    if let Some(key) = item.key() {
+                        self.mark_seen(key);
+                    }
+                }
+                items.reverse();
+            }
+        }
+        self.polled = true;
+
+        items
+    }
+}
+
+pub struct FeedIngestor<R: RayGun, F: FeedFetcher> {
+    raygun: R,
+    fetcher: F,
+    feeds: HashMap<String, FeedState>,
+}
+
+impl<R: RayGun, F: FeedFetcher> FeedIngestor<R, F> {
+    pub fn new(raygun: R, fetcher: F) -> Self {
+        Self {
+            raygun,
+            fetcher,
+            feeds: HashMap::new(),
+        }
+    }
+
+    /// Add a feed, replacing the configuration of the feed if it already exists. Newly added feeds are due
+    /// immediately
+    pub fn add_feed(&mut self, config: FeedConfig) {
+        match self.feeds.get_mut(&config.url) {
+            Some(state) => state.config = config,
+            None => {
+                self.feeds.insert(
+                    config.url.clone(),
+                    FeedState {
+                        config,
+                        next_poll: DateTime::<Utc>::MIN_UTC,
+                        polled: false,
+                        seen: HashSet::new(),
+                        order: VecDeque::new(),
+                    },
+                );
+            }
+        }
+    }
+
+    pub fn remove_feed(&mut self, url: &str) -> Option<FeedConfig> {
+        self.feeds.remove(url).map(|state| state.config)
+    }
+
+    pub fn feeds(&self) -> impl Iterator<Item = &FeedConfig> {
+        self.feeds.values().map(|state| &state.config)
+    }
+
+    /// Mark an item of a feed as seen so it is never posted (eg items that were posted before a restart)
+    pub fn mark_seen(&mut self, url: &str, key: &str) {
+        if let Some(state) = self.feeds.get_mut(url) {
+            state.mark_seen(key);
+            state.polled = true;
+        }
+    }
+
+    /// Time at which the next feed is due, if any feed is configured
+    pub fn next_poll(&self) -> Option<DateTime<Utc>> {
+        self.feeds.values().map(|state| state.next_poll).min()
+    }
+
+    pub fn raygun(&self) -> &R {
+        &self.raygun
+    }
+
+    pub fn raygun_mut(&mut self) -> &mut R {
+        &mut self.raygun
+    }
+
+    /// Poll the feeds that are due at `now`, posting their new items. Returns the amount of items posted
+    pub async fn poll(&mut self, now: DateTime<Utc>) -> Result<usize, Error> {
+        let due = self
+            .feeds
+            .values()
+            .filter(|state| state.next_poll <= now)
+            .map(|state| state.config.url.clone())
+            .collect::<Vec<_>>();
+
+        let mut posted = 0;
+
+        for url in due {
+            match self.poll_feed(&url, now).await {
+                Ok(count) => posted += count,
+                Err(e) => tracing::warn!(%url, error = %e, "unable to poll feed"),
+            }
+        }
+
+        Ok(posted)
+    }
+
+    async fn poll_feed(&mut self, url: &str, now: DateTime<Utc>) -> Result<usize, Error> {
+        let Some(state) = self.feeds.get_mut(url) else {
+            return Ok(0);
+        };
+
+        // Feeds are rescheduled even if fetching fails so a broken feed does not get polled continuously
+        state.next_poll = now + state.config.interval;
+
+        let document = self.fetcher.fetch(url).await?;
+        let feed = Feed::parse(&document)?;
+
+        let Some(state) = self.feeds.get_mut(url) else {
+            return Ok(0);
+        };
+
+        let target = state.config.target;
+        let items = state.select(&feed);
+
+        let mut posted = 0;
+        for item in items {
+            let lines = item.lines(feed.title.as_deref());
+            match self.post(target, lines).await {
+                Ok(()) => posted += 1,
+                Err(e) => {
+                    // The item is left unseen so it is posted again on the next poll
+                    tracing::warn!(%url, item = ?item.key(), error = %e, "unable to post feed item");
+                    continue;
+                }
+            }
+
+            if let (Some(state), Some(key)) = (self.feeds.get_mut(url), item.key()) {
+                state.mark_seen(key);
+            }
+        }
+
+        Ok(posted)
+    }
+
+    async fn post(&mut self, target: FeedTarget, lines: Vec<String>) -> Result<(), Error> {
+        match target {
+            FeedTarget::Conversation(conversation_id) => {
+                let message_id = self.raygun.send(conversation_id, lines).await?;
+                if let Err(e) = self
+                    .raygun
+                    .embeds(conversation_id, message_id, EmbedState::Enabled)
+                    .await
+                {
+                    tracing::debug!(%conversation_id, %message_id, error = %e, "unable to enable link preview");
+                }
+            }
+            FeedTarget::CommunityChannel {
+                community_id,
+                channel_id,
+            } => {
+                self.raygun
+                    .send_community_channel_message(community_id, channel_id, lines)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Find the start of the opening tag of an element
+fn find_element(input: &str, tag: &str) -> Option<usize> {
+    let pattern = format!("<{tag}");
+    let mut offset = 0;
+    while let Some(index) = input[offset..].find(&pattern) {
+        let start = offset + index;
+        let next = input[start + pattern.len()..].chars().next();
+        if matches!(next, Some('>' | '/') | Some(' ' | '\t' | '\r' | '\n')) {
+            return Some(start);
+        }
+        offset = start + pattern.len();
+    }
+    None
+}
+
+/// Opening tag and content of the elements named `tag`
+fn element_blocks<'a>(input: &'a str, tag: &str) -> Vec<(&'a str, Option<&'a str>)> {
+    let close = format!("</{tag}>");
+    let mut blocks = vec![];
+    let mut rest = input;
+
+    while let Some(start) = find_element(rest, tag) {
+        let Some(end) = rest[start..].find('>').map(|end| start + end) else {
+            break;
+        };
+        let open = &rest[start..=end];
+
+        if open.ends_with("/>") {
+            blocks.push((open, None));
+            rest = &rest[end + 1..];
+            continue;
+        }
+
+        let body = &rest[end + 1..];
+        match body.find(&close) {
+            Some(length) => {
+                blocks.push((open, Some(&body[..length])));
+                rest = &body[length + close.len()..];
+            }
+            None => break,
+        }
+    }
+
+    blocks
+}
+
+fn elements<'a>(input: &'a str, tag: &str) -> Vec<&'a str> {
+    element_blocks(input, tag)
+        .into_iter()
+        .filter_map(|(_, content)| content)
+        .collect()
+}
+
+/// Decoded text of the first element named `tag`
+fn element_text(input: &str, tag: &str) -> Option<String> {
+    let (_, content) = element_blocks(input, tag).into_iter().next()?;
+    let text = decode_text(content?);
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    for quote in ['"', '\''] {
+        let pattern = format!(" {name}={quote}");
+        if let Some(start) = tag.find(&pattern).map(|index| index + pattern.len()) {
+            let end = tag[start..].find(quote)?;
+            return Some(decode_entities(&tag[start..start + end]));
+        }
+    }
+    None
+}
+
+/// Alternate link of an Atom entry
+fn atom_link(input: &str) -> Option<String> {
+    element_blocks(input, "link")
+        .into_iter()
+        .map(|(open, _)| open)
+        .find(|open| attribute(open, "rel").map_or(true, |rel| rel == "alternate"))
+        .and_then(|open| attribute(open, "href"))
+}
+
+/// Unwrap CDATA sections and decode entities of the remaining text
+fn decode_text(input: &str) -> String {
+    let mut output = String::new();
+    let mut rest = input;
+
+    while let Some(start) = rest.find("<![CDATA[") {
+        output.push_str(&decode_entities(&rest[..start]));
+        let body = &rest[start + 9..];
+        match body.find("]]>") {
+            Some(end) => {
+                output.push_str(&body[..end]);
+                rest = &body[end + 3..];
+            }
+            None => {
+                output.push_str(body);
+                rest = "";
+            }
+        }
+    }
+
+    output.push_str(&decode_entities(rest));
+    output
+}
+
+fn decode_entities(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(start) = rest.find('&') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let Some(end) = rest.find(';').filter(|end| *end <= 10) else {
+            output.push('&');
+            rest = &rest[1..];
+            continue;
+        };
+
+        let entity = &rest[1..end];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                .and_then(char::from_u32),
+        };
+
+        match decoded {
+            Some(ch) => {
+                output.push(ch);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                output.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// Plain text summary of HTML content, with collapsed whitespace and limited to [`MAX_SUMMARY_CHARS`]
+fn summarize(input: &str) -> String {
+    let mut text = String::with_capacity(input.len());
+    let mut in_tag = false;
+
+    for ch in input.chars() {
+        match ch {
+            '<' => {
+                in_tag = true;
+                text.push(' ');
+            }
+            '>' if in_tag => in_tag = false,
+            _ if in_tag => {}
+            _ => text.push(ch),
+        }
+    }
+
+    // Markup escaped within the content is only decoded once the tags are stripped
+    let text = decode_entities(&text);
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    match text.char_indices().nth(MAX_SUMMARY_CHARS) {
+        Some((index, _)) => format!("{}…", text[..index].trim_end()),
+        None => text,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, TimeZone, Utc};
+    use uuid::Uuid;
+
+    use super::{Feed, FeedConfig, FeedItem, FeedState, FeedTarget};
+
+    const RSS: &str = r#"<?xml version="1.0"?>
+<rss version="2.0">
+  <channel>
+    <title>Warp &amp; Friends</title>
+    <item>
+      <title>Second</title>
+      <link>https://example.com/2</link>
+      <guid isPermaLink="false">item-2</guid>
+      <description><![CDATA[<p>Hello <b>world</b></p>]]></description>
+      <pubDate>Tue, 10 Jun 2025 04:00:00 GMT</pubDate>
+    </item>
+    <item>
+      <title>First</title>
+      <link>https://example.com/1</link>
+    </item>
+  </channel>
+</rss>"#;
+
+    const ATOM: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title type="text">Example</title>
+  <entry>
+    <title>Entry</title>
+    <link rel="self" href="https://example.com/self"/>
+    <link href="https://example.com/entry"/>
+    <id>urn:uuid:1225c695</id>
+    <updated>2025-06-10T04:00:00Z</updated>
+    <summary type="html">&lt;p&gt;Some &amp;amp; text&lt;/p&gt;</summary>
+  </entry>
+</feed>"#;
+
+    #[test]
+    fn parse_rss() {
+        let feed = Feed::parse(RSS).expect("valid feed");
+        assert_eq!(feed.title.as_deref(), Some("Warp & Friends"));
+        assert_eq!(feed.items.len(), 2);
+
+        let item = &feed.items[0];
+        assert_eq!(item.key(), Some("item-2"));
+        assert_eq!(item.link.as_deref(), Some("https://example.com/2"));
+        assert_eq!(item.summary.as_deref(), Some("Hello world"));
+        assert_eq!(
+            item.published,
+            Some(Utc.with_ymd_and_hms(2025, 6, 10, 4, 0, 0).unwrap())
+        );
+
+        assert_eq!(feed.items[1].key(), Some("https://example.com/1"));
+    }
+
+    #[test]
+    fn parse_atom() {
+        let feed = Feed::parse(ATOM).expect("valid feed");
+        assert_eq!(feed.title.as_deref(), Some("Example"));
+
+        let item = &feed.items[0];
+        assert_eq!(item.key(), Some("urn:uuid:1225c695"));
+        assert_eq!(item.link.as_deref(), Some("https://example.com/entry"));
+        assert_eq!(item.summary.as_deref(), Some("Some & text"));
+        assert!(item.published.is_some());
+
+        assert_eq!(
+            item.lines(feed.title.as_deref()),
+            [
+                "**Entry** (Example)",
+                "Some & text",
+                "https://example.com/entry"
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_invalid_document() {
+        assert!(Feed::parse("<html><body></body></html>").is_err());
+    }
+
+    #[test]
+    fn select_new_items() {
+        let mut config = FeedConfig::new("", FeedTarget::Conversation(Uuid::nil()));
+        config.interval = Duration::minutes(1);
+
+        let mut state = FeedState {
+            config,
+            next_poll: Utc::now(),
+            polled: false,
+            seen: Default::default(),
+            order: Default::default(),
+        };
+
+        let feed = Feed::parse(RSS).expect("valid feed");
+
+        // Only the most recent item is posted on the first poll
+        let items = state.select(&feed);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].key(), Some("item-2"));
+
+        // The item is selected again until it is posted
+        assert_eq!(state.select(&feed), items);
+        state.mark_seen("item-2");
+        assert!(state.select(&feed).is_empty());
+
+        let mut updated = feed.clone();
+        updated.items[0].id = Some("item-3".into());
+        let items = state.select(&updated);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].key(), Some("item-3"));
+    }
+
+    #[test]
+    fn remaining_items_are_selected_on_next_poll() {
+        let mut config = FeedConfig::new("", FeedTarget::Conversation(Uuid::nil()));
+        config.max_items = 2;
+
+        let mut state = FeedState {
+            config,
+            next_poll: Utc::now(),
+            polled: true,
+            seen: Default::default(),
+            order: Default::default(),
+        };
+
+        let feed = Feed {
+            title: None,
+            items: (0..3)
+                .rev()
+                .map(|index| FeedItem {
+                    id: Some(format!("item-{index}")),
+                    ..Default::default()
+                })
+                .collect(),
+        };
+
+        let items = state.select(&feed);
+        let keys = items.iter().filter_map(FeedItem::key).collect::<Vec<_>>();
+        assert_eq!(keys, ["item-0", "item-1"]);
+
+        for key in keys {
+            state.mark_seen(key);
+        }
+
+        let items = state.select(&feed);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].key(), Some("item-2"));
+    }
+}
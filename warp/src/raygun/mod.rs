@@ -1,11 +1,17 @@
+pub mod bot;
 pub mod community;
+pub mod feed;
 pub mod group;
+pub mod payment;
+pub mod processor;
 
 use crate::constellation::file::{File, FileType};
 use crate::constellation::{ConstellationProgressStream, Progression};
 use crate::crypto::DID;
 use crate::error::Error;
 use crate::raygun::community::RayGunCommunity;
+use crate::raygun::processor::MessageProcessor;
+use crate::subscription::SubscriptionOptions;
 use crate::{Extension, SingleHandle};
 
 use community::{
@@ -16,13 +22,14 @@ use derive_more::Display;
 use futures::stream::BoxStream;
 
 use bytes::Bytes;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use core::ops::Range;
 use indexmap::{IndexMap, IndexSet};
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeSet, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::fmt::Debug;
 use std::path::PathBuf;
+use std::str::FromStr;
 use uuid::Uuid;
 
 #[allow(unused_imports)]
@@ -35,6 +42,11 @@ pub enum RayGunEventKind {
     ConversationArchived { conversation_id: Uuid },
     ConversationUnarchived { conversation_id: Uuid },
     ConversationDeleted { conversation_id: Uuid },
+    ConversationMerged { primary: Uuid, duplicate: Uuid },
+    // Emitted when a conversation stops processing events, which also ends its conversation streams,
+    // and once it is restarted, after which conversation streams need to be obtained again
+    ConversationDegraded { conversation_id: Uuid },
+    ConversationRecovered { conversation_id: Uuid },
     CommunityCreated { community_id: Uuid },
     CommunityInvited { community_id: Uuid, invite_id: Uuid },
     CommunityUninvited { community_id: Uuid, invite_id: Uuid },
@@ -72,6 +84,17 @@ pub enum MessageEventKind {
         conversation_id: Uuid,
         message_id: Uuid,
     },
+    MessageStatusUpdated {
+        conversation_id: Uuid,
+        message_id: Uuid,
+        status: MessageStatus,
+    },
+    /// Emitted when a member of the conversation has read a message
+    MessageRead {
+        conversation_id: Uuid,
+        message_id: Uuid,
+        did_key: DID,
+    },
     MessageReactionAdded {
         conversation_id: Uuid,
         message_id: Uuid,
@@ -98,6 +121,18 @@ pub enum MessageEventKind {
         conversation_id: Uuid,
         description: Option<String>,
     },
+    ConversationHistoryVisibilityChanged {
+        conversation_id: Uuid,
+        visibility: HistoryVisibility,
+    },
+    ConversationSlowModeChanged {
+        conversation_id: Uuid,
+        slow_mode: u64,
+    },
+    ConversationRetentionPolicyChanged {
+        conversation_id: Uuid,
+        policy: Option<RetentionPolicy>,
+    },
     RecipientAdded {
         conversation_id: Uuid,
         recipient: DID,
@@ -228,6 +263,11 @@ pub enum MessageEventKind {
         channel_id: Uuid,
         description: Option<String>,
     },
+    EditedCommunityChannelSlowMode {
+        community_id: Uuid,
+        channel_id: Uuid,
+        slow_mode: u64,
+    },
     GrantedCommunityChannelPermission {
         community_id: Uuid,
         channel_id: Uuid,
@@ -312,6 +352,26 @@ pub enum AttachmentKind {
 
 pub type AttachmentEventStream = BoxStream<'static, AttachmentKind>;
 
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AttachmentOptions {
+    strip_metadata: Option<bool>,
+}
+
+impl AttachmentOptions {
+    /// Strip metadata (eg EXIF and GPS data) from images before they are uploaded.
+    /// If not set, the default of the implementation is used.
+    pub fn set_strip_metadata(mut self, strip: bool) -> Self {
+        self.strip_metadata = Some(strip);
+        self
+    }
+}
+
+impl AttachmentOptions {
+    pub fn strip_metadata(&self) -> Option<bool> {
+        self.strip_metadata
+    }
+}
+
 pub type MessageEventStream = BoxStream<'static, MessageEventKind>;
 
 pub type MessageStream = BoxStream<'static, Message>;
@@ -426,6 +486,88 @@ impl MessageOptions {
     }
 }
 
+/// Query used to search the messages of conversations
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SearchQuery {
+    text: String,
+    sender: Option<DID>,
+    limit: Option<usize>,
+}
+
+impl SearchQuery {
+    /// Messages match if they contain every word of `text`. The last word also matches as a prefix
+    pub fn new(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn set_sender(mut self, sender: DID) -> Self {
+        self.sender = Some(sender);
+        self
+    }
+
+    pub fn set_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+}
+
+impl SearchQuery {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn sender(&self) -> Option<&DID> {
+        self.sender.as_ref()
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+}
+
+/// Location of a match within the lines of a message
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SearchHighlight {
+    /// Index of the line of the message
+    pub line: usize,
+    /// Byte offset of the start of the match within the line
+    pub start: usize,
+    /// Byte offset of the end of the match within the line
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SearchResult {
+    message: Message,
+    highlights: Vec<SearchHighlight>,
+}
+
+impl SearchResult {
+    pub fn new(message: Message, highlights: Vec<SearchHighlight>) -> Self {
+        Self {
+            message,
+            highlights,
+        }
+    }
+
+    pub fn conversation_id(&self) -> Uuid {
+        self.message.conversation_id()
+    }
+
+    pub fn message(&self) -> &Message {
+        &self.message
+    }
+
+    pub fn highlights(&self) -> &[SearchHighlight] {
+        &self.highlights
+    }
+}
+
+pub type SearchResultStream = BoxStream<'static, SearchResult>;
+
 #[derive(Default, Debug, Hash, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Display)]
 #[serde(rename_all = "lowercase")]
 pub enum MessagesType {
@@ -572,6 +714,127 @@ pub enum ConversationType {
     Group,
 }
 
+/// Controls which part of the history of a group newly added members are able to read
+#[derive(Debug, Default, Hash, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Display)]
+#[serde(rename_all = "snake_case")]
+#[repr(C)]
+pub enum HistoryVisibility {
+    /// New members can read the whole history of the conversation
+    #[display(fmt = "all")]
+    All,
+    /// New members can read messages sent from the point they joined, including after losing and requesting their keys again
+    #[default]
+    #[display(fmt = "from_join")]
+    FromJoin,
+    /// New members can only read messages sent with the latest keys that were shared with them
+    #[display(fmt = "none")]
+    None,
+}
+
+/// Retention requirements of a group, set by its creator or an administrator of the organization of its members.
+/// Durations are in seconds and are measured from the date the message was sent
+#[derive(Debug, Default, Hash, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// Messages can not be deleted before they reach this age
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minimum: Option<u64>,
+    /// Messages are deleted once they reach this age
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub maximum: Option<u64>,
+    /// Messages can not be deleted, regardless of their age, while the conversation is under legal hold
+    #[serde(default)]
+    pub legal_hold: bool,
+}
+
+impl RetentionPolicy {
+    /// Returns true if the minimum retention does not exceed the maximum retention
+    pub fn is_valid(&self) -> bool {
+        match (self.minimum, self.maximum) {
+            (Some(minimum), Some(maximum)) => minimum <= maximum,
+            _ => true,
+        }
+    }
+
+    /// Returns true if a message sent at `sent` can be deleted at `now`
+    pub fn can_delete(&self, sent: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        if self.legal_hold {
+            return false;
+        }
+
+        let age = (now - sent).num_seconds().max(0) as u64;
+        self.minimum.is_none_or(|minimum| age >= minimum)
+    }
+
+    /// Returns true if a message sent at `sent` has outlived the maximum retention at `now`
+    pub fn is_expired(&self, sent: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        if self.legal_hold {
+            return false;
+        }
+
+        let age = (now - sent).num_seconds().max(0) as u64;
+        self.maximum.is_some_and(|maximum| age >= maximum)
+    }
+}
+
+/// Statistics of a conversation, based on the messages held by the local node
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ConversationStats {
+    pub message_count: usize,
+    /// Amount of messages sent by each member
+    pub members: IndexMap<DID, usize>,
+    /// Date of the first message. Not moved forward when the message is deleted
+    pub first_activity: Option<DateTime<Utc>>,
+    /// Date of the latest message. Not moved back when the message is deleted
+    pub last_activity: Option<DateTime<Utc>>,
+    /// Total size of the files attached to the messages, in bytes
+    pub attachment_bytes: u64,
+    /// Amount of messages sent on each day (UTC)
+    pub daily_activity: BTreeMap<NaiveDate, usize>,
+}
+
+impl ConversationStats {
+    /// Account for a message sent by `sender` at `date`
+    pub fn record(&mut self, sender: &DID, date: DateTime<Utc>, attachment_bytes: u64) {
+        self.message_count += 1;
+        *self.members.entry(sender.clone()).or_default() += 1;
+        *self.daily_activity.entry(date.date_naive()).or_default() += 1;
+        self.attachment_bytes += attachment_bytes;
+
+        if self.first_activity.is_none_or(|first| date < first) {
+            self.first_activity = Some(date);
+        }
+
+        if self.last_activity.is_none_or(|last| date > last) {
+            self.last_activity = Some(date);
+        }
+    }
+
+    /// Remove a message that was previously accounted for with [`ConversationStats::record`]
+    pub fn forget(&mut self, sender: &DID, date: DateTime<Utc>, attachment_bytes: u64) {
+        self.message_count = self.message_count.saturating_sub(1);
+        self.attachment_bytes = self.attachment_bytes.saturating_sub(attachment_bytes);
+
+        if let Some(count) = self.members.get_mut(sender) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                self.members.shift_remove(sender);
+            }
+        }
+
+        let day = date.date_naive();
+        if let Some(count) = self.daily_activity.get_mut(&day) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                self.daily_activity.remove(&day);
+            }
+        }
+    }
+
+    pub fn messages_from(&self, did: &DID) -> usize {
+        self.members.get(did).copied().unwrap_or_default()
+    }
+}
+
 pub type GroupPermissions = IndexMap<DID, IndexSet<GroupPermission>>;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -800,6 +1063,12 @@ pub struct Conversation {
     archived: bool,
     recipients: Vec<DID>,
     description: Option<String>,
+    #[serde(default)]
+    history_visibility: HistoryVisibility,
+    #[serde(default)]
+    slow_mode: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    retention_policy: Option<RetentionPolicy>,
 }
 
 impl core::hash::Hash for Conversation {
@@ -833,6 +1102,9 @@ impl Default for Conversation {
             archived: false,
             recipients,
             description: None,
+            history_visibility: HistoryVisibility::default(),
+            slow_mode: 0,
+            retention_policy: None,
         }
     }
 }
@@ -881,6 +1153,19 @@ impl Conversation {
     pub fn archived(&self) -> bool {
         self.archived
     }
+
+    pub fn history_visibility(&self) -> HistoryVisibility {
+        self.history_visibility
+    }
+
+    /// Minimum amount of seconds between messages of a member. Disabled if zero
+    pub fn slow_mode(&self) -> u64 {
+        self.slow_mode
+    }
+
+    pub fn retention_policy(&self) -> Option<&RetentionPolicy> {
+        self.retention_policy.as_ref()
+    }
 }
 
 impl Conversation {
@@ -927,6 +1212,18 @@ impl Conversation {
     pub fn set_archived(&mut self, archived: bool) {
         self.archived = archived;
     }
+
+    pub fn set_history_visibility(&mut self, visibility: HistoryVisibility) {
+        self.history_visibility = visibility;
+    }
+
+    pub fn set_slow_mode(&mut self, slow_mode: u64) {
+        self.slow_mode = slow_mode;
+    }
+
+    pub fn set_retention_policy(&mut self, policy: Option<RetentionPolicy>) {
+        self.retention_policy = policy;
+    }
 }
 
 #[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Hash)]
@@ -960,12 +1257,76 @@ pub enum MessageType {
     /// constellation or sent directly
     #[display(fmt = "attachment")]
     Attachment,
-    /// Event sent as a message.
-    /// TBD
+    /// Change to the conversation recorded as a message. See [`SystemMessage`]
     #[display(fmt = "event")]
     Event,
 }
 
+/// Change to a conversation, such as a member being added or the name being changed, recorded in the conversation
+/// as a [`MessageType::Event`] message. Clients render it in their own language from [`SystemMessage::key`] and
+/// [`SystemMessage::params`] rather than from the lines of the message, which are empty.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "key", content = "params")]
+pub enum SystemMessage {
+    #[serde(rename = "conversation.member_added")]
+    MemberAdded { actor: DID, member: DID },
+    #[serde(rename = "conversation.member_removed")]
+    MemberRemoved { actor: DID, member: DID },
+    #[serde(rename = "conversation.name_changed")]
+    NameChanged { actor: DID, name: String },
+    #[serde(rename = "conversation.name_removed")]
+    NameRemoved { actor: DID },
+    #[serde(rename = "conversation.description_changed")]
+    DescriptionChanged { actor: DID, description: String },
+    #[serde(rename = "conversation.description_removed")]
+    DescriptionRemoved { actor: DID },
+}
+
+impl SystemMessage {
+    /// Stable key identifying the kind of change, used to look up the localized text
+    pub fn key(&self) -> &'static str {
+        match self {
+            SystemMessage::MemberAdded { .. } => "conversation.member_added",
+            SystemMessage::MemberRemoved { .. } => "conversation.member_removed",
+            SystemMessage::NameChanged { .. } => "conversation.name_changed",
+            SystemMessage::NameRemoved { .. } => "conversation.name_removed",
+            SystemMessage::DescriptionChanged { .. } => "conversation.description_changed",
+            SystemMessage::DescriptionRemoved { .. } => "conversation.description_removed",
+        }
+    }
+
+    /// Identity that made the change
+    pub fn actor(&self) -> &DID {
+        match self {
+            SystemMessage::MemberAdded { actor, .. }
+            | SystemMessage::MemberRemoved { actor, .. }
+            | SystemMessage::NameChanged { actor, .. }
+            | SystemMessage::NameRemoved { actor }
+            | SystemMessage::DescriptionChanged { actor, .. }
+            | SystemMessage::DescriptionRemoved { actor } => actor,
+        }
+    }
+
+    /// Parameters to substitute into the localized text, keyed by name
+    pub fn params(&self) -> IndexMap<&'static str, String> {
+        let mut params = IndexMap::from_iter([("actor", self.actor().to_string())]);
+        match self {
+            SystemMessage::MemberAdded { member, .. }
+            | SystemMessage::MemberRemoved { member, .. } => {
+                params.insert("member", member.to_string());
+            }
+            SystemMessage::NameChanged { name, .. } => {
+                params.insert("name", name.clone());
+            }
+            SystemMessage::DescriptionChanged { description, .. } => {
+                params.insert("description", description.clone());
+            }
+            SystemMessage::NameRemoved { .. } | SystemMessage::DescriptionRemoved { .. } => {}
+        }
+        params
+    }
+}
+
 #[derive(Default, Clone, Debug, PartialEq, Eq)]
 pub struct MessageReference {
     /// ID of the Message
@@ -1116,6 +1477,10 @@ pub struct Message {
     /// List of Attachment
     attachment: Vec<File>,
 
+    /// Change to the conversation if the message is a [`MessageType::Event`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    system: Option<SystemMessage>,
+
     /// Metadata related to the message. Can be used externally, but more internally focused
     #[serde(flatten)]
     metadata: IndexMap<String, String>,
@@ -1136,6 +1501,7 @@ impl Default for Message {
             replied: None,
             lines: Vec::new(),
             attachment: Vec::new(),
+            system: None,
             metadata: IndexMap::new(),
         }
     }
@@ -1205,6 +1571,21 @@ impl Message {
         &self.attachment
     }
 
+    pub fn system_message(&self) -> Option<&SystemMessage> {
+        self.system.as_ref()
+    }
+
+    /// Amount of reactors of each reaction, skipping any reaction that is malformed
+    pub fn reaction_counts(&self) -> IndexMap<Reaction, usize> {
+        self.reactions
+            .iter()
+            .filter_map(|(reaction, reactors)| {
+                let reaction = reaction.parse::<Reaction>().ok()?;
+                Some((reaction, reactors.len()))
+            })
+            .collect()
+    }
+
     pub fn metadata(&self) -> &IndexMap<String, String> {
         &self.metadata
     }
@@ -1266,6 +1647,10 @@ impl Message {
     pub fn set_replied(&mut self, replied: Option<Uuid>) {
         self.replied = replied
     }
+
+    pub fn set_system_message(&mut self, system: Option<SystemMessage>) {
+        self.system = system
+    }
 }
 
 // Mutable functions
@@ -1299,9 +1684,21 @@ pub enum MessageStatus {
     #[display(fmt = "sent")]
     Sent,
 
-    /// Confirmation of message being delivered. May be used in the future
+    /// Confirmation of the message being delivered to every recipient
     #[display(fmt = "delivered")]
     Delivered,
+
+    /// Confirmation of the message being read by every recipient
+    #[display(fmt = "read")]
+    Read,
+
+    /// If a message is waiting in the outbound queue for one or more recipients to become reachable
+    #[display(fmt = "queued")]
+    Queued,
+
+    /// If a message could not be sent to one or more recipients after exhausting its retries
+    #[display(fmt = "failed")]
+    Failed,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
@@ -1311,6 +1708,73 @@ pub enum ReactionState {
     Remove,
 }
 
+/// Emoji from an emoji pack, referenced by the id of the pack and of the item within it
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CustomEmoji {
+    pub pack_id: Uuid,
+    pub item_id: Uuid,
+}
+
+impl CustomEmoji {
+    pub fn new(pack_id: Uuid, item_id: Uuid) -> Self {
+        Self { pack_id, item_id }
+    }
+
+    /// Key of the emoji when used as a reaction, in the form of `:<pack_id>:<item_id>:`
+    pub fn to_reaction(&self) -> String {
+        Reaction::Custom(*self).to_string()
+    }
+}
+
+/// Reaction to a message, as stored in [`Message::reactions`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Reaction {
+    Emoji(String),
+    Custom(CustomEmoji),
+}
+
+impl std::fmt::Display for Reaction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Reaction::Emoji(emoji) => write!(f, "{emoji}"),
+            Reaction::Custom(emoji) => write!(f, ":{}:{}:", emoji.pack_id, emoji.item_id),
+        }
+    }
+}
+
+impl Reaction {
+    pub fn custom_emoji(&self) -> Option<CustomEmoji> {
+        match self {
+            Reaction::Custom(emoji) => Some(*emoji),
+            Reaction::Emoji(_) => None,
+        }
+    }
+}
+
+impl FromStr for Reaction {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let reaction = s.trim();
+        if reaction.is_empty() {
+            return Err(Error::InvalidReaction);
+        }
+
+        // Anything wrapped in colons is reserved for custom emoji
+        let Some(reference) = reaction
+            .strip_prefix(':')
+            .and_then(|reference| reference.strip_suffix(':'))
+        else {
+            return Ok(Reaction::Emoji(reaction.to_string()));
+        };
+
+        let (pack_id, item_id) = reference.split_once(':').ok_or(Error::InvalidReaction)?;
+        let pack_id = pack_id.parse().map_err(|_| Error::InvalidReaction)?;
+        let item_id = item_id.parse().map_err(|_| Error::InvalidReaction)?;
+
+        Ok(Reaction::Custom(CustomEmoji::new(pack_id, item_id)))
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
 pub enum PinState {
@@ -1453,11 +1917,21 @@ pub trait RayGun:
         Err(Error::Unimplemented)
     }
 
+    /// Get the statistics of a conversation
+    async fn conversation_stats(&self, _: Uuid) -> Result<ConversationStats, Error> {
+        Err(Error::Unimplemented)
+    }
+
     /// Get a status of a message in a conversation
     async fn message_status(&self, _: Uuid, _: Uuid) -> Result<MessageStatus, Error> {
         Err(Error::Unimplemented)
     }
 
+    /// Mark a message in a conversation as read, notifying the sender and the other members of the conversation
+    async fn mark_read(&mut self, _: Uuid, _: Uuid) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
     /// Retrieve all message references from a conversation
     async fn get_message_references(
         &self,
@@ -1472,6 +1946,16 @@ pub trait RayGun:
         Err(Error::Unimplemented)
     }
 
+    /// Search the messages of a conversation, or of all conversations if none is given.
+    /// Results are returned from the newest to the oldest message
+    async fn search_messages(
+        &self,
+        _: Option<Uuid>,
+        _: SearchQuery,
+    ) -> Result<SearchResultStream, Error> {
+        Err(Error::Unimplemented)
+    }
+
     /// Retrieve all messages from a conversation
     async fn get_messages(
         &self,
@@ -1567,6 +2051,36 @@ pub trait RayGun:
 
     /// Unarchived a conversation
     async fn unarchived_conversation(&mut self, conversation_id: Uuid) -> Result<(), Error>;
+
+    /// Merge a duplicate direct conversation into `primary`. Messages of the duplicate are folded into
+    /// `primary` and the duplicate is removed for both parties
+    async fn merge_conversations(&mut self, _: Uuid, _: Uuid) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Replay events reconstructed from the current state of a conversation (eg members, name and pinned messages)
+    /// so a client rebuilding its local state can resynchronize. Events relating to messages are limited to messages
+    /// sent after `since`, if provided
+    async fn replay_events(
+        &self,
+        _: Uuid,
+        _: Option<DateTime<Utc>>,
+    ) -> Result<MessageEventStream, Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Register a [`MessageProcessor`] that will process received messages before they are surfaced
+    async fn register_message_processor(
+        &mut self,
+        _: std::sync::Arc<dyn MessageProcessor>,
+    ) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Remove a registered [`MessageProcessor`] by its name
+    async fn unregister_message_processor(&mut self, _: &str) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
 }
 
 #[async_trait::async_trait]
@@ -1602,7 +2116,20 @@ pub trait RayGunAttachment: Sync + Send {
         Err(Error::Unimplemented)
     }
 
-    /// Downloads a file that been attached to a message
+    /// Send files to a conversation with [`AttachmentOptions`] that apply only to this message.
+    /// See [`RayGunAttachment::attach`]
+    async fn attach_with_options(
+        &mut self,
+        _: Uuid,
+        _: Option<Uuid>,
+        _: Vec<Location>,
+        _: Vec<String>,
+        _: AttachmentOptions,
+    ) -> Result<(Uuid, AttachmentEventStream), Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Downloads a file that has been attached to a message
     /// Note: Must use the filename associated when downloading
     async fn download(
         &self,
@@ -1614,7 +2141,7 @@ pub trait RayGunAttachment: Sync + Send {
         Err(Error::Unimplemented)
     }
 
-    /// Stream a file that been attached to a message
+    /// Stream a file that has been attached to a message
     /// Note: Must use the filename associated when downloading
     async fn download_stream(
         &self,
@@ -1624,6 +2151,20 @@ pub trait RayGunAttachment: Sync + Send {
     ) -> Result<BoxStream<'static, Result<Bytes, std::io::Error>>, Error> {
         Err(Error::Unimplemented)
     }
+
+    /// Save a file that has been attached to a message to the host filesystem.
+    /// The file is written to a temporary location and only moved to the destination
+    /// once the download completes and is verified, so a partial file is never exposed.
+    /// Note: Must use the filename associated when downloading
+    async fn save_attachment(
+        &self,
+        _: Uuid,
+        _: Uuid,
+        _: &str,
+        _: PathBuf,
+    ) -> Result<ConstellationProgressStream, Error> {
+        Err(Error::Unimplemented)
+    }
 }
 
 #[async_trait::async_trait]
@@ -1633,10 +2174,27 @@ pub trait RayGunStream: Sync + Send {
         Err(Error::Unimplemented)
     }
 
+    /// Subscribe to an stream of events from the conversation, buffering at most [`SubscriptionOptions::capacity`] events
+    async fn get_conversation_stream_with(
+        &mut self,
+        _: Uuid,
+        _: SubscriptionOptions,
+    ) -> Result<MessageEventStream, Error> {
+        Err(Error::Unimplemented)
+    }
+
     /// Subscribe to an stream of events
     async fn raygun_subscribe(&mut self) -> Result<RayGunEventStream, Error> {
         Err(Error::Unimplemented)
     }
+
+    /// Subscribe to an stream of events, buffering at most [`SubscriptionOptions::capacity`] events
+    async fn raygun_subscribe_with(
+        &mut self,
+        _: SubscriptionOptions,
+    ) -> Result<RayGunEventStream, Error> {
+        Err(Error::Unimplemented)
+    }
 }
 
 #[async_trait::async_trait]
@@ -1660,4 +2218,25 @@ pub trait RayGunConversationInformation: Sync + Send {
         conversation_id: Uuid,
         description: Option<&str>,
     ) -> Result<(), Error>;
+
+    /// Set which part of the history newly added members of a group can read
+    async fn set_history_visibility(&mut self, _: Uuid, _: HistoryVisibility) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Set the minimum amount of seconds between messages of each member in a group.
+    /// Zero disables slow mode. Only the creator is able to change it and is exempt from it
+    async fn set_slow_mode(&mut self, _: Uuid, _: u64) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
+
+    /// Set the retention policy of a group, or remove it with `None`. Only the creator or an administrator of the
+    /// organization can set the policy, which is signed and distributed to every member once they all support it
+    async fn set_retention_policy(
+        &mut self,
+        _: Uuid,
+        _: Option<RetentionPolicy>,
+    ) -> Result<(), Error> {
+        Err(Error::Unimplemented)
+    }
 }
@@ -0,0 +1,349 @@
+//! Structured payment requests exchanged within conversations.
+//!
+//! A [`PaymentRequest`] is sent as a regular message containing a human readable summary followed by an encoded line
+//! starting with [`PAYMENT_PREFIX`], so clients without payment support still show something meaningful. Settlements
+//! are posted as a reply to the request once the [`WalletAdapter`] of the payer submitted the transaction, allowing
+//! the requester to verify the transaction with its own adapter.
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::error::Error;
+
+use super::{Message, MessageType, RayGun};
+
+/// Prefix used to identify an encoded payment message
+pub const PAYMENT_PREFIX: &str = "warp-pay:";
+
+/// Maximum length of the memo of a payment request
+pub const MAX_PAYMENT_MEMO_LENGTH: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaymentRequest {
+    pub id: Uuid,
+    /// Network the payment is expected on (eg `solana`)
+    pub network: String,
+    /// Asset requested, such as the native currency of the network or a token address
+    pub asset: String,
+    /// Amount in the smallest unit of the asset
+    pub amount: u64,
+    /// Decimals of the asset, used to display the amount
+    pub decimals: u8,
+    /// Address the payment is sent to
+    pub recipient: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiry: Option<DateTime<Utc>>,
+}
+
+impl PaymentRequest {
+    pub fn new(
+        network: impl Into<String>,
+        asset: impl Into<String>,
+        amount: u64,
+        decimals: u8,
+        recipient: impl Into<String>,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            network: network.into(),
+            asset: asset.into(),
+            amount,
+            decimals,
+            recipient: recipient.into(),
+            memo: None,
+            expiry: None,
+        }
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expiry.is_some_and(|expiry| expiry <= now)
+    }
+
+    /// Amount formatted with the decimals of the asset (eg `1.5`)
+    pub fn display_amount(&self) -> String {
+        format_amount(self.amount, self.decimals)
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        if self.amount == 0 {
+            return Err(Error::OtherWithContext(
+                "Payment amount cannot be zero".into(),
+            ));
+        }
+
+        if self.recipient.trim().is_empty() {
+            return Err(Error::OtherWithContext(
+                "Payment recipient cannot be empty".into(),
+            ));
+        }
+
+        if let Some(memo) = &self.memo {
+            if memo.len() > MAX_PAYMENT_MEMO_LENGTH {
+                return Err(Error::InvalidLength {
+                    context: "memo".into(),
+                    current: memo.len(),
+                    minimum: None,
+                    maximum: Some(MAX_PAYMENT_MEMO_LENGTH),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Confirmation that a payment request was paid
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaymentSettlement {
+    pub request_id: Uuid,
+    pub network: String,
+    /// Identifier of the transaction on the network (eg the signature of a solana transaction)
+    pub transaction: String,
+    /// Address the payment was sent from
+    pub payer: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum PaymentMessage {
+    Request(PaymentRequest),
+    Settlement(PaymentSettlement),
+}
+
+impl PaymentMessage {
+    /// Lines of the message, consisting of a summary followed by the encoded payment message
+    pub fn to_lines(&self) -> Result<Vec<String>, Error> {
+        let summary = match self {
+            PaymentMessage::Request(request) => match &request.memo {
+                Some(memo) => format!(
+                    "Payment request: {} {} ({memo})",
+                    request.display_amount(),
+                    request.asset
+                ),
+                None => format!(
+                    "Payment request: {} {}",
+                    request.display_amount(),
+                    request.asset
+                ),
+            },
+            PaymentMessage::Settlement(settlement) => {
+                format!("Payment sent: {}", settlement.transaction)
+            }
+        };
+
+        let bytes = serde_json::to_vec(self)?;
+        Ok(vec![
+            summary,
+            format!("{PAYMENT_PREFIX}{}", bs58::encode(bytes).into_string()),
+        ])
+    }
+
+    /// Decode the payment message contained in the lines of a message, if any
+    pub fn from_lines(lines: &[String]) -> Option<Self> {
+        let encoded = lines
+            .iter()
+            .find_map(|line| line.trim().strip_prefix(PAYMENT_PREFIX))?;
+        let bytes = bs58::decode(encoded).into_vec().ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    pub fn from_message(message: &Message) -> Option<Self> {
+        if message.message_type() != MessageType::Message {
+            return None;
+        }
+        Self::from_lines(message.lines())
+    }
+}
+
+#[async_trait::async_trait]
+pub trait WalletAdapter: Send + Sync + 'static {
+    /// Network supported by the wallet (eg `solana`)
+    fn network(&self) -> &str;
+
+    /// Address payments to this wallet are sent to
+    async fn address(&self) -> Result<String, Error>;
+
+    /// Submit a transaction paying the request, returning the identifier of the transaction
+    async fn pay(&self, request: &PaymentRequest) -> Result<String, Error>;
+
+    /// Check that the transaction of the settlement paid the request
+    async fn verify(
+        &self,
+        request: &PaymentRequest,
+        settlement: &PaymentSettlement,
+    ) -> Result<bool, Error>;
+}
+
+pub struct Payments<R: RayGun, W: WalletAdapter> {
+    raygun: R,
+    wallet: W,
+}
+
+impl<R: RayGun, W: WalletAdapter> Payments<R, W> {
+    pub fn new(raygun: R, wallet: W) -> Self {
+        Self { raygun, wallet }
+    }
+
+    pub fn wallet(&self) -> &W {
+        &self.wallet
+    }
+
+    pub fn raygun(&self) -> &R {
+        &self.raygun
+    }
+
+    pub fn raygun_mut(&mut self) -> &mut R {
+        &mut self.raygun
+    }
+
+    /// Send a request for `amount` of `asset` to the address of the wallet. Returns the id of the message
+    pub async fn request(
+        &mut self,
+        conversation_id: Uuid,
+        asset: impl Into<String>,
+        amount: u64,
+        decimals: u8,
+        memo: Option<String>,
+        expiry: Option<DateTime<Utc>>,
+    ) -> Result<(Uuid, PaymentRequest), Error> {
+        let recipient = self.wallet.address().await?;
+
+        let mut request =
+            PaymentRequest::new(self.wallet.network(), asset, amount, decimals, recipient);
+        request.memo = memo.filter(|memo| !memo.trim().is_empty());
+        request.expiry = expiry;
+        request.validate()?;
+
+        let lines = PaymentMessage::Request(request.clone()).to_lines()?;
+        let message_id = self.raygun.send(conversation_id, lines).await?;
+        Ok((message_id, request))
+    }
+
+    /// Pay the request contained in a message and post the settlement as a reply to it. Returns the id of the reply
+    pub async fn pay(
+        &mut self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<(Uuid, PaymentSettlement), Error> {
+        let message = self.raygun.get_message(conversation_id, message_id).await?;
+
+        let Some(PaymentMessage::Request(request)) = PaymentMessage::from_message(&message) else {
+            return Err(Error::InvalidMessage);
+        };
+
+        request.validate()?;
+
+        if request.network != self.wallet.network() {
+            return Err(Error::OtherWithContext(format!(
+                "Wallet does not support the \"{}\" network",
+                request.network
+            )));
+        }
+
+        if request.is_expired(Utc::now()) {
+            return Err(Error::OtherWithContext("Payment request is expired".into()));
+        }
+
+        let transaction = self.wallet.pay(&request).await?;
+        let settlement = PaymentSettlement {
+            request_id: request.id,
+            network: request.network.clone(),
+            transaction,
+            payer: self.wallet.address().await?,
+        };
+
+        let lines = PaymentMessage::Settlement(settlement.clone()).to_lines()?;
+        let reply_id = self
+            .raygun
+            .reply(conversation_id, message_id, lines)
+            .await?;
+
+        Ok((reply_id, settlement))
+    }
+
+    /// Verify the settlement contained in a message against the request it replied to
+    pub async fn verify(
+        &self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<PaymentSettlement, Error> {
+        let message = self.raygun.get_message(conversation_id, message_id).await?;
+
+        let Some(PaymentMessage::Settlement(settlement)) = PaymentMessage::from_message(&message)
+        else {
+            return Err(Error::InvalidMessage);
+        };
+
+        let request_message_id = message.replied().ok_or(Error::InvalidMessage)?;
+        let request_message = self
+            .raygun
+            .get_message(conversation_id, request_message_id)
+            .await?;
+
+        let Some(PaymentMessage::Request(request)) = PaymentMessage::from_message(&request_message)
+        else {
+            return Err(Error::InvalidMessage);
+        };
+
+        if request.id != settlement.request_id || request.network != settlement.network {
+            return Err(Error::InvalidMessage);
+        }
+
+        if !self.wallet.verify(&request, &settlement).await? {
+            return Err(Error::OtherWithContext(
+                "Transaction does not settle the payment request".into(),
+            ));
+        }
+
+        Ok(settlement)
+    }
+}
+
+/// Format an amount in the smallest unit of an asset with its decimals, trimming trailing zeros
+pub fn format_amount(amount: u64, decimals: u8) -> String {
+    if decimals == 0 {
+        return amount.to_string();
+    }
+
+    let digits = format!("{amount:0>width$}", width = decimals as usize + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - decimals as usize);
+    let fraction = fraction.trim_end_matches('0');
+
+    match fraction.is_empty() {
+        true => whole.to_string(),
+        false => format!("{whole}.{fraction}"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{format_amount, PaymentMessage, PaymentRequest, PAYMENT_PREFIX};
+
+    #[test]
+    fn amount_formatting() {
+        assert_eq!(format_amount(1_500_000_000, 9), "1.5");
+        assert_eq!(format_amount(1, 9), "0.000000001");
+        assert_eq!(format_amount(2_000_000, 6), "2");
+        assert_eq!(format_amount(42, 0), "42");
+    }
+
+    #[test]
+    fn payment_message_roundtrip() {
+        let mut request = PaymentRequest::new("solana", "SOL", 1_500_000_000, 9, "address");
+        request.memo = Some("dinner".into());
+
+        let message = PaymentMessage::Request(request);
+        let lines = message.to_lines().expect("valid message");
+        assert_eq!(lines[0], "Payment request: 1.5 SOL (dinner)");
+        assert!(lines[1].starts_with(PAYMENT_PREFIX));
+
+        assert_eq!(PaymentMessage::from_lines(&lines), Some(message));
+        assert_eq!(PaymentMessage::from_lines(&["hello".into()]), None);
+        assert_eq!(
+            PaymentMessage::from_lines(&[format!("{PAYMENT_PREFIX}invalid")]),
+            None
+        );
+    }
+}
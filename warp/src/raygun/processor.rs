@@ -0,0 +1,267 @@
+//! Post-receive processing of messages.
+//!
+//! Processors registered with [`RayGun::register_message_processor`](super::RayGun::register_message_processor)
+//! are invoked, in order of registration, on every received message before the message is surfaced
+//! in an event. Each processor returns derived metadata (eg a translation or a masked version of
+//! the message) that is attached to [`Message::metadata`] under `<processor name>.<key>`.
+use std::sync::Arc;
+
+use indexmap::IndexMap;
+use parking_lot::RwLock;
+use uuid::Uuid;
+
+use crate::error::Error;
+
+use super::{Message, MessagePage, Messages};
+
+/// Maximum amount of processed messages that are cached
+pub const MAX_PROCESSED_MESSAGE_CACHE: usize = 1024;
+
+#[async_trait::async_trait]
+pub trait MessageProcessor: Send + Sync + 'static {
+    /// Unique name of the processor, used to namespace the metadata it produces
+    fn name(&self) -> &str;
+
+    /// Process a received message, returning metadata to attach to the message
+    async fn process(&self, message: &Message) -> Result<IndexMap<String, String>, Error>;
+}
+
+#[derive(Clone, Default)]
+pub struct MessageProcessorPipeline {
+    processors: Arc<RwLock<Vec<Arc<dyn MessageProcessor>>>>,
+    cache: Arc<RwLock<IndexMap<Uuid, IndexMap<String, String>>>>,
+}
+
+impl core::fmt::Debug for MessageProcessorPipeline {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("MessageProcessorPipeline")
+            .field("processors", &self.processors.read().len())
+            .field("cached", &self.cache.read().len())
+            .finish()
+    }
+}
+
+impl MessageProcessorPipeline {
+    /// Register a processor at the end of the pipeline
+    pub fn register(&self, processor: Arc<dyn MessageProcessor>) -> Result<(), Error> {
+        let name = processor.name();
+        if name.trim().is_empty() {
+            return Err(Error::InvalidLength {
+                context: "name".into(),
+                current: 0,
+                minimum: Some(1),
+                maximum: None,
+            });
+        }
+
+        let mut processors = self.processors.write();
+
+        if processors.iter().any(|item| item.name() == name) {
+            return Err(Error::OtherWithContext(format!(
+                "processor \"{name}\" is already registered"
+            )));
+        }
+
+        processors.push(processor);
+        Ok(())
+    }
+
+    /// Remove a processor from the pipeline
+    pub fn unregister(&self, name: &str) -> Result<(), Error> {
+        let mut processors = self.processors.write();
+        let index = processors
+            .iter()
+            .position(|item| item.name() == name)
+            .ok_or(Error::ObjectNotFound)?;
+        processors.remove(index);
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.processors.read().is_empty()
+    }
+
+    /// Run the message through the pipeline, returning the cached result if the message was already processed.
+    /// Processors that fail are skipped.
+    pub async fn process(&self, message: &Message) -> IndexMap<String, String> {
+        if let Some(metadata) = self.cached(message.id()) {
+            return metadata;
+        }
+
+        let processors = self.processors.read().clone();
+
+        let mut metadata = IndexMap::new();
+
+        for processor in processors {
+            let name = processor.name();
+            match processor.process(message).await {
+                Ok(map) => {
+                    metadata.extend(
+                        map.into_iter()
+                            .map(|(key, value)| (format!("{name}.{key}"), value)),
+                    );
+                }
+                Err(e) => {
+                    tracing::warn!(processor = name, message_id = %message.id(), error = %e, "unable to process message");
+                }
+            }
+        }
+
+        let mut cache = self.cache.write();
+        if cache.len() >= MAX_PROCESSED_MESSAGE_CACHE {
+            cache.shift_remove_index(0);
+        }
+        cache.insert(message.id(), metadata.clone());
+
+        metadata
+    }
+
+    /// Metadata produced for a message, if it was processed
+    pub fn cached(&self, message_id: Uuid) -> Option<IndexMap<String, String>> {
+        self.cache.read().get(&message_id).cloned()
+    }
+
+    /// Remove the cached result for a message (eg if it was edited or deleted)
+    pub fn invalidate(&self, message_id: Uuid) {
+        self.cache.write().shift_remove(&message_id);
+    }
+
+    /// Attach any cached metadata to the message
+    pub fn apply(&self, message: &mut Message) {
+        if let Some(metadata) = self.cached(message.id()) {
+            message.metadata_mut().extend(metadata);
+        }
+    }
+
+    /// Attach any cached metadata to the messages
+    pub fn apply_messages(&self, messages: Messages) -> Messages {
+        if self.cache.read().is_empty() {
+            return messages;
+        }
+
+        match messages {
+            Messages::List(list) => Messages::List(
+                list.into_iter()
+                    .map(|mut message| {
+                        self.apply(&mut message);
+                        message
+                    })
+                    .collect(),
+            ),
+            Messages::Stream(stream) => {
+                use futures::StreamExt;
+                let pipeline = self.clone();
+                Messages::Stream(
+                    stream
+                        .map(move |mut message| {
+                            pipeline.apply(&mut message);
+                            message
+                        })
+                        .boxed(),
+                )
+            }
+            Messages::Page { pages, total } => Messages::Page {
+                pages: pages
+                    .into_iter()
+                    .map(|page| {
+                        let messages = page
+                            .messages()
+                            .iter()
+                            .cloned()
+                            .map(|mut message| {
+                                self.apply(&mut message);
+                                message
+                            })
+                            .collect();
+                        MessagePage::new(page.id(), messages, page.total())
+                    })
+                    .collect(),
+                total,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use indexmap::IndexMap;
+
+    use super::{MessageProcessor, MessageProcessorPipeline};
+    use crate::{error::Error, raygun::Message};
+
+    #[derive(Default)]
+    struct Shout {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl MessageProcessor for Shout {
+        fn name(&self) -> &str {
+            "shout"
+        }
+
+        async fn process(&self, message: &Message) -> Result<IndexMap<String, String>, Error> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let mut map = IndexMap::new();
+            map.insert("text".into(), message.lines().join("\n").to_uppercase());
+            Ok(map)
+        }
+    }
+
+    struct Failing;
+
+    #[async_trait::async_trait]
+    impl MessageProcessor for Failing {
+        fn name(&self) -> &str {
+            "failing"
+        }
+
+        async fn process(&self, _: &Message) -> Result<IndexMap<String, String>, Error> {
+            Err(Error::Other)
+        }
+    }
+
+    #[tokio::test]
+    async fn pipeline_process_and_cache() -> anyhow::Result<()> {
+        let pipeline = MessageProcessorPipeline::default();
+        let shout = Arc::new(Shout::default());
+
+        pipeline.register(shout.clone())?;
+        pipeline.register(Arc::new(Failing))?;
+        assert!(pipeline.register(Arc::new(Failing)).is_err());
+
+        let mut message = Message::default();
+        message.set_lines(vec!["hello".into()]);
+
+        let metadata = pipeline.process(&message).await;
+        assert_eq!(
+            metadata.get("shout.text").map(String::as_str),
+            Some("HELLO")
+        );
+        assert_eq!(metadata.len(), 1);
+
+        // result should be cached
+        pipeline.process(&message).await;
+        assert_eq!(shout.calls.load(Ordering::SeqCst), 1);
+
+        pipeline.apply(&mut message);
+        assert_eq!(
+            message.metadata().get("shout.text").map(String::as_str),
+            Some("HELLO")
+        );
+
+        pipeline.invalidate(message.id());
+        assert!(pipeline.cached(message.id()).is_none());
+
+        pipeline.unregister("shout")?;
+        pipeline.unregister("failing")?;
+        assert!(pipeline.is_empty());
+        assert!(pipeline.unregister("shout").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,432 @@
+//! Handles that expose a limited set of capabilities of an implementation.
+//!
+//! A host application that embeds untrusted plugin code can pass the plugin one of these handles instead of the full
+//! implementation. The scope is enforced by the handle itself: the wrapped implementation is not reachable from the
+//! handle and only the operations within its scope are provided.
+use std::ops::Range;
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::stream::BoxStream;
+use uuid::Uuid;
+
+use crate::constellation::directory::Directory;
+use crate::constellation::{Constellation, ConstellationEventStream};
+use crate::crypto::DID;
+use crate::error::Error;
+use crate::multipass::identity::{
+    FriendRequest, Identifier, Identity, IdentityImage, IdentityStatus, ProfileFields,
+};
+use crate::multipass::{GetIdentity, MultiPass, MultiPassEventStream};
+use crate::raygun::{
+    AttachmentEventStream, AttachmentOptions, Conversation, ConversationImage, Location, Message,
+    MessageEvent, MessageEventStream, MessageOptions, MessageReference, MessageStatus, Messages,
+    PinState, RayGun, RayGunAttachment, RayGunEventStream, RayGunEvents, RayGunStream,
+    ReactionState,
+};
+
+/// Read access to the files of a [`Constellation`] implementation.
+/// Nothing can be uploaded, removed, moved or renamed, and files can not be written to the local filesystem
+#[derive(Clone)]
+pub struct ReadOnlyConstellation<C> {
+    inner: C,
+}
+
+impl<C: Constellation> ReadOnlyConstellation<C> {
+    pub fn new(inner: C) -> Self {
+        Self { inner }
+    }
+
+    /// Timestamp of when the file system was modified
+    pub fn modified(&self) -> DateTime<Utc> {
+        self.inner.modified()
+    }
+
+    /// Copy of the root directory. Changes made to the copy are not applied to the file system
+    pub fn root_directory(&self) -> Directory {
+        self.inner.root_directory().detached()
+    }
+
+    /// Copy of the directory at `path`. Changes made to the copy are not applied to the file system
+    pub fn open_directory(&self, path: &str) -> Result<Directory, Error> {
+        self.inner
+            .open_directory(path)
+            .map(|directory| directory.detached())
+    }
+
+    pub fn current_size(&self) -> usize {
+        self.inner.current_size()
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.inner.max_size()
+    }
+
+    pub async fn get_buffer(&self, name: &str) -> Result<Bytes, Error> {
+        self.inner.get_buffer(name).await
+    }
+
+    pub async fn get_stream(
+        &self,
+        name: &str,
+    ) -> Result<BoxStream<'static, Result<Bytes, std::io::Error>>, Error> {
+        self.inner.get_stream(name).await
+    }
+
+    pub async fn get_stream_range(
+        &self,
+        name: &str,
+        range: Range<usize>,
+    ) -> Result<BoxStream<'static, Result<Bytes, std::io::Error>>, Error> {
+        self.inner.get_stream_range(name, range).await
+    }
+
+    pub async fn subscribe(&mut self) -> Result<ConstellationEventStream, Error> {
+        self.inner.constellation_subscribe().await
+    }
+}
+
+/// Access to a single conversation of a [`RayGun`] implementation.
+/// Messages can be read and sent within the conversation, but the conversation itself can not be deleted or modified
+/// and other conversations can not be accessed
+#[derive(Clone)]
+pub struct SingleConversationRayGun<R> {
+    inner: R,
+    conversation_id: Uuid,
+}
+
+impl<R: RayGun> SingleConversationRayGun<R> {
+    pub fn new(inner: R, conversation_id: Uuid) -> Self {
+        Self {
+            inner,
+            conversation_id,
+        }
+    }
+
+    pub fn conversation_id(&self) -> Uuid {
+        self.conversation_id
+    }
+
+    pub async fn conversation(&self) -> Result<Conversation, Error> {
+        self.inner.get_conversation(self.conversation_id).await
+    }
+
+    pub async fn get_message(&self, message_id: Uuid) -> Result<Message, Error> {
+        self.inner
+            .get_message(self.conversation_id, message_id)
+            .await
+    }
+
+    pub async fn get_message_count(&self) -> Result<usize, Error> {
+        self.inner.get_message_count(self.conversation_id).await
+    }
+
+    pub async fn message_status(&self, message_id: Uuid) -> Result<MessageStatus, Error> {
+        self.inner
+            .message_status(self.conversation_id, message_id)
+            .await
+    }
+
+    pub async fn get_message_reference(&self, message_id: Uuid) -> Result<MessageReference, Error> {
+        self.inner
+            .get_message_reference(self.conversation_id, message_id)
+            .await
+    }
+
+    pub async fn get_message_references(
+        &self,
+        opt: MessageOptions,
+    ) -> Result<BoxStream<'static, MessageReference>, Error> {
+        self.inner
+            .get_message_references(self.conversation_id, opt)
+            .await
+    }
+
+    pub async fn get_messages(&self, opt: MessageOptions) -> Result<Messages, Error> {
+        self.inner.get_messages(self.conversation_id, opt).await
+    }
+
+    pub async fn send(&mut self, message: Vec<String>) -> Result<Uuid, Error> {
+        self.inner.send(self.conversation_id, message).await
+    }
+
+    pub async fn edit(&mut self, message_id: Uuid, message: Vec<String>) -> Result<(), Error> {
+        self.inner
+            .edit(self.conversation_id, message_id, message)
+            .await
+    }
+
+    /// Delete a message. Unlike [`RayGun::delete`], the conversation can not be deleted
+    pub async fn delete(&mut self, message_id: Uuid) -> Result<(), Error> {
+        self.inner
+            .delete(self.conversation_id, Some(message_id))
+            .await
+    }
+
+    pub async fn react(
+        &mut self,
+        message_id: Uuid,
+        state: ReactionState,
+        emoji: String,
+    ) -> Result<(), Error> {
+        self.inner
+            .react(self.conversation_id, message_id, state, emoji)
+            .await
+    }
+
+    pub async fn pin(&mut self, message_id: Uuid, state: PinState) -> Result<(), Error> {
+        self.inner
+            .pin(self.conversation_id, message_id, state)
+            .await
+    }
+
+    pub async fn mark_read(&mut self, message_id: Uuid) -> Result<(), Error> {
+        self.inner.mark_read(self.conversation_id, message_id).await
+    }
+
+    pub async fn reply(&mut self, message_id: Uuid, message: Vec<String>) -> Result<Uuid, Error> {
+        self.inner
+            .reply(self.conversation_id, message_id, message)
+            .await
+    }
+}
+
+impl<R: RayGun + RayGunStream> SingleConversationRayGun<R> {
+    /// Subscribe to the events of the conversation
+    pub async fn subscribe(&mut self) -> Result<MessageEventStream, Error> {
+        self.inner
+            .get_conversation_stream(self.conversation_id)
+            .await
+    }
+}
+
+impl<R: RayGun + RayGunEvents> SingleConversationRayGun<R> {
+    pub async fn send_event(&mut self, event: MessageEvent) -> Result<(), Error> {
+        self.inner.send_event(self.conversation_id, event).await
+    }
+
+    pub async fn cancel_event(&mut self, event: MessageEvent) -> Result<(), Error> {
+        self.inner.cancel_event(self.conversation_id, event).await
+    }
+}
+
+impl<R: RayGun + RayGunAttachment> SingleConversationRayGun<R> {
+    /// Send a message with attachments from disk or from a stream. Files stored in [`Constellation`] are outside of
+    /// the scope of the handle and can not be attached
+    pub async fn attach(
+        &mut self,
+        message_id: Option<Uuid>,
+        locations: Vec<Location>,
+        message: Vec<String>,
+        options: AttachmentOptions,
+    ) -> Result<(Uuid, AttachmentEventStream), Error> {
+        if locations
+            .iter()
+            .any(|location| matches!(location, Location::Constellation { .. }))
+        {
+            return Err(Error::Unauthorized);
+        }
+
+        self.inner
+            .attach_with_options(
+                self.conversation_id,
+                message_id,
+                locations,
+                message,
+                options,
+            )
+            .await
+    }
+
+    pub async fn download_stream(
+        &self,
+        message_id: Uuid,
+        file: &str,
+    ) -> Result<BoxStream<'static, Result<Bytes, std::io::Error>>, Error> {
+        self.inner
+            .download_stream(self.conversation_id, message_id, file)
+            .await
+    }
+}
+
+/// Read access to the identity, friends and requests of a [`MultiPass`] implementation.
+/// The identity can not be updated and requests can not be sent or answered
+#[derive(Clone)]
+pub struct ReadOnlyMultiPass<M> {
+    inner: M,
+}
+
+impl<M: MultiPass> ReadOnlyMultiPass<M> {
+    pub fn new(inner: M) -> Self {
+        Self { inner }
+    }
+
+    pub async fn identity(&self) -> Result<Identity, Error> {
+        self.inner.identity().await
+    }
+
+    pub fn get_identity(&self, id: impl Into<Identifier>) -> GetIdentity {
+        self.inner.get_identity(id)
+    }
+
+    pub async fn profile_picture(&self) -> Result<IdentityImage, Error> {
+        self.inner.profile_picture().await
+    }
+
+    pub async fn profile_banner(&self) -> Result<IdentityImage, Error> {
+        self.inner.profile_banner().await
+    }
+
+    pub async fn identity_picture(&self, did: &DID) -> Result<IdentityImage, Error> {
+        self.inner.identity_picture(did).await
+    }
+
+    pub async fn identity_banner(&self, did: &DID) -> Result<IdentityImage, Error> {
+        self.inner.identity_banner(did).await
+    }
+
+    pub async fn identity_status(&self, did: &DID) -> Result<IdentityStatus, Error> {
+        self.inner.identity_status(did).await
+    }
+
+    pub async fn identity_profile_fields(&self, did: &DID) -> Result<ProfileFields, Error> {
+        self.inner.identity_profile_fields(did).await
+    }
+
+    pub async fn list_friends(&self) -> Result<Vec<DID>, Error> {
+        self.inner.list_friends().await
+    }
+
+    pub async fn has_friend(&self, did: &DID) -> Result<bool, Error> {
+        self.inner.has_friend(did).await
+    }
+
+    pub async fn block_list(&self) -> Result<Vec<DID>, Error> {
+        self.inner.block_list().await
+    }
+
+    pub async fn is_blocked(&self, did: &DID) -> Result<bool, Error> {
+        self.inner.is_blocked(did).await
+    }
+
+    pub async fn list_incoming_request(&self) -> Result<Vec<FriendRequest>, Error> {
+        self.inner.list_incoming_request().await
+    }
+
+    pub async fn list_outgoing_request(&self) -> Result<Vec<FriendRequest>, Error> {
+        self.inner.list_outgoing_request().await
+    }
+
+    pub async fn subscribe(&mut self) -> Result<MultiPassEventStream, Error> {
+        self.inner.multipass_subscribe().await
+    }
+}
+
+/// Read access to every conversation of a [`RayGun`] implementation.
+/// Nothing can be sent, and conversations or messages can not be created, modified or deleted
+#[derive(Clone)]
+pub struct ReadOnlyRayGun<R> {
+    inner: R,
+}
+
+impl<R: RayGun> ReadOnlyRayGun<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner }
+    }
+
+    pub async fn get_conversation(&self, conversation_id: Uuid) -> Result<Conversation, Error> {
+        self.inner.get_conversation(conversation_id).await
+    }
+
+    pub async fn list_conversations(&self) -> Result<Vec<Conversation>, Error> {
+        self.inner.list_conversations().await
+    }
+
+    pub async fn conversation_icon(
+        &self,
+        conversation_id: Uuid,
+    ) -> Result<ConversationImage, Error> {
+        self.inner.conversation_icon(conversation_id).await
+    }
+
+    pub async fn conversation_banner(
+        &self,
+        conversation_id: Uuid,
+    ) -> Result<ConversationImage, Error> {
+        self.inner.conversation_banner(conversation_id).await
+    }
+
+    pub async fn get_message(
+        &self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<Message, Error> {
+        self.inner.get_message(conversation_id, message_id).await
+    }
+
+    pub async fn get_message_count(&self, conversation_id: Uuid) -> Result<usize, Error> {
+        self.inner.get_message_count(conversation_id).await
+    }
+
+    pub async fn message_status(
+        &self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<MessageStatus, Error> {
+        self.inner.message_status(conversation_id, message_id).await
+    }
+
+    pub async fn get_message_reference(
+        &self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+    ) -> Result<MessageReference, Error> {
+        self.inner
+            .get_message_reference(conversation_id, message_id)
+            .await
+    }
+
+    pub async fn get_message_references(
+        &self,
+        conversation_id: Uuid,
+        opt: MessageOptions,
+    ) -> Result<BoxStream<'static, MessageReference>, Error> {
+        self.inner
+            .get_message_references(conversation_id, opt)
+            .await
+    }
+
+    pub async fn get_messages(
+        &self,
+        conversation_id: Uuid,
+        opt: MessageOptions,
+    ) -> Result<Messages, Error> {
+        self.inner.get_messages(conversation_id, opt).await
+    }
+}
+
+impl<R: RayGun + RayGunStream> ReadOnlyRayGun<R> {
+    pub async fn subscribe(&mut self) -> Result<RayGunEventStream, Error> {
+        self.inner.raygun_subscribe().await
+    }
+
+    pub async fn get_conversation_stream(
+        &mut self,
+        conversation_id: Uuid,
+    ) -> Result<MessageEventStream, Error> {
+        self.inner.get_conversation_stream(conversation_id).await
+    }
+}
+
+impl<R: RayGun + RayGunAttachment> ReadOnlyRayGun<R> {
+    pub async fn download_stream(
+        &self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        file: &str,
+    ) -> Result<BoxStream<'static, Result<Bytes, std::io::Error>>, Error> {
+        self.inner
+            .download_stream(conversation_id, message_id, file)
+            .await
+    }
+}
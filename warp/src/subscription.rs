@@ -0,0 +1,254 @@
+//! Options for bounded event subscriptions.
+//!
+//! Subscribers that do not keep up with the events emitted would otherwise cause events to accumulate without limit.
+//! A bounded subscription buffers at most [`SubscriptionOptions::capacity`] events for its subscriber and applies the
+//! [`OverflowPolicy`] once the buffer is full. The [`SubscriptionMetrics`] of the subscription can be used to track how
+//! far behind the subscriber is.
+//!
+//! Bounded subscriptions are opt-in through the `*_subscribe_with` functions. The default subscriptions are lossless and
+//! apply backpressure instead, holding back the events until the subscriber is ready to receive them.
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::constellation::ConstellationEventKind;
+use crate::multipass::MultiPassEventKind;
+use crate::raygun::{MessageEventKind, RayGunEventKind};
+
+/// Default amount of events buffered for a subscriber
+pub const DEFAULT_SUBSCRIPTION_CAPACITY: usize = 128;
+
+/// Action taken when an event is emitted while the buffer of a subscriber is full
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Drop the oldest buffered event to make room for the new event
+    #[default]
+    DropOldest,
+    /// Replace the buffered event that is superseded by the new event (see [`Coalesce`]).
+    /// If no buffered event is superseded, the oldest buffered event is dropped
+    Coalesce,
+    /// End the subscription. The stream will return `None` once the buffered events are consumed
+    /// and [`SubscriptionMetrics::overflowed`] will return true
+    Error,
+}
+
+/// Events that can replace an older event of the same kind when the buffer of a subscriber is full
+pub trait Coalesce {
+    /// Returns true if this event makes `previous` obsolete, so `previous` does not need to be delivered
+    fn supersedes(&self, _previous: &Self) -> bool {
+        false
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SubscriptionOptions {
+    capacity: usize,
+    overflow: OverflowPolicy,
+    metrics: SubscriptionMetrics,
+}
+
+impl Default for SubscriptionOptions {
+    fn default() -> Self {
+        Self::new(DEFAULT_SUBSCRIPTION_CAPACITY, OverflowPolicy::default())
+    }
+}
+
+impl SubscriptionOptions {
+    /// Note: A capacity of zero is treated as one
+    pub fn new(capacity: usize, overflow: OverflowPolicy) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            overflow,
+            metrics: SubscriptionMetrics::default(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn overflow(&self) -> OverflowPolicy {
+        self.overflow
+    }
+
+    /// Handle to the metrics of the subscription created with these options.
+    /// The handle should be cloned before the options are used to subscribe.
+    pub fn metrics(&self) -> SubscriptionMetrics {
+        self.metrics.clone()
+    }
+}
+
+/// Counters of a subscription. Cloning the metrics returns a handle to the same counters
+#[derive(Default, Debug, Clone)]
+pub struct SubscriptionMetrics {
+    inner: Arc<MetricsInner>,
+}
+
+#[derive(Default, Debug)]
+struct MetricsInner {
+    delivered: AtomicU64,
+    dropped: AtomicU64,
+    coalesced: AtomicU64,
+    buffered: AtomicUsize,
+    overflowed: AtomicBool,
+}
+
+impl SubscriptionMetrics {
+    /// Amount of events that were consumed by the subscriber
+    pub fn delivered(&self) -> u64 {
+        self.inner.delivered.load(Ordering::Relaxed)
+    }
+
+    /// Amount of events that were dropped because the buffer was full
+    pub fn dropped(&self) -> u64 {
+        self.inner.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Amount of events that were replaced by a newer event because the buffer was full
+    pub fn coalesced(&self) -> u64 {
+        self.inner.coalesced.load(Ordering::Relaxed)
+    }
+
+    /// Amount of events waiting to be consumed by the subscriber
+    pub fn lag(&self) -> usize {
+        self.inner.buffered.load(Ordering::Relaxed)
+    }
+
+    /// Returns true if the subscription was ended by [`OverflowPolicy::Error`]
+    pub fn overflowed(&self) -> bool {
+        self.inner.overflowed.load(Ordering::Relaxed)
+    }
+
+    // The functions below are used by implementations to update the counters
+
+    pub fn record_delivered(&self) {
+        self.inner.delivered.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_dropped(&self, amount: u64) {
+        self.inner.dropped.fetch_add(amount, Ordering::Relaxed);
+    }
+
+    pub fn record_coalesced(&self) {
+        self.inner.coalesced.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_lag(&self, buffered: usize) {
+        self.inner.buffered.store(buffered, Ordering::Relaxed);
+    }
+
+    pub fn record_overflow(&self) {
+        self.inner.overflowed.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Coalesce for MultiPassEventKind {
+    fn supersedes(&self, previous: &Self) -> bool {
+        use MultiPassEventKind::*;
+        match (self, previous) {
+            (
+                IdentityOnline { did } | IdentityOffline { did },
+                IdentityOnline { did: previous } | IdentityOffline { did: previous },
+            ) => did == previous,
+            (IdentityUpdate { did }, IdentityUpdate { did: previous }) => did == previous,
+            (
+                AppDataUpdated { namespace },
+                AppDataUpdated {
+                    namespace: previous,
+                },
+            ) => namespace == previous,
+            (NotificationPreferencesUpdated, NotificationPreferencesUpdated) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Coalesce for MessageEventKind {
+    fn supersedes(&self, previous: &Self) -> bool {
+        use MessageEventKind::*;
+        match (self, previous) {
+            (
+                MessageEdited {
+                    conversation_id,
+                    message_id,
+                },
+                MessageEdited {
+                    conversation_id: previous_conversation_id,
+                    message_id: previous_message_id,
+                },
+            ) => conversation_id == previous_conversation_id && message_id == previous_message_id,
+            (
+                MessageStatusUpdated {
+                    conversation_id,
+                    message_id,
+                    ..
+                },
+                MessageStatusUpdated {
+                    conversation_id: previous_conversation_id,
+                    message_id: previous_message_id,
+                    ..
+                },
+            ) => conversation_id == previous_conversation_id && message_id == previous_message_id,
+            (
+                EventReceived {
+                    conversation_id,
+                    did_key,
+                    event,
+                }
+                | EventCancelled {
+                    conversation_id,
+                    did_key,
+                    event,
+                },
+                EventReceived {
+                    conversation_id: previous_conversation_id,
+                    did_key: previous_did_key,
+                    event: previous_event,
+                }
+                | EventCancelled {
+                    conversation_id: previous_conversation_id,
+                    did_key: previous_did_key,
+                    event: previous_event,
+                },
+            ) => {
+                conversation_id == previous_conversation_id
+                    && did_key == previous_did_key
+                    && event == previous_event
+            }
+            _ => false,
+        }
+    }
+}
+
+// Every event of these kinds is significant on its own
+impl Coalesce for RayGunEventKind {}
+
+impl Coalesce for ConstellationEventKind {}
+
+#[cfg(test)]
+mod test {
+    use crate::crypto::DID;
+    use crate::multipass::MultiPassEventKind;
+    use crate::subscription::{Coalesce, OverflowPolicy, SubscriptionOptions};
+
+    #[test]
+    fn presence_supersedes_presence_of_same_identity() {
+        let did = DID::default();
+
+        let online = MultiPassEventKind::IdentityOnline { did: did.clone() };
+        let offline = MultiPassEventKind::IdentityOffline { did: did.clone() };
+        let update = MultiPassEventKind::IdentityUpdate { did };
+
+        assert!(offline.supersedes(&online));
+        assert!(online.supersedes(&offline));
+        assert!(!update.supersedes(&online));
+    }
+
+    #[test]
+    fn zero_capacity_is_raised() {
+        let options = SubscriptionOptions::new(0, OverflowPolicy::Error);
+        assert_eq!(options.capacity(), 1);
+    }
+}
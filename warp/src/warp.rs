@@ -3,30 +3,39 @@ pub mod dummy;
 use crate::constellation::directory::Directory;
 use crate::constellation::{
     Constellation, ConstellationEvent, ConstellationEventStream, ConstellationProgressStream,
+    IntegrityReport, UploadSession,
 };
 use crate::crypto::DID;
 use crate::error::Error;
 use crate::module::Module;
+use crate::multipass::contact::ContactFormat;
 use crate::multipass::identity::{
-    FriendRequest, Identifier, Identity, IdentityImage, IdentityProfile, IdentityStatus,
-    IdentityUpdate, Platform, Relationship,
+    FriendRequest, FriendsPrivacy, Identifier, Identity, IdentityActivity, IdentityImage,
+    IdentityProfile, IdentityStatus, IdentityUpdate, Platform, ProfileFields, Relationship,
+    RelationshipHistoryEntry,
 };
+use crate::multipass::notification::{NotificationEvent, NotificationPreferences};
 use crate::multipass::{
-    Friends, GetIdentity, IdentityImportOption, IdentityInformation, ImportLocation, LocalIdentity,
-    MultiPass, MultiPassEvent, MultiPassEventStream, MultiPassImportExport,
+    Friends, GetIdentity, IdentityChangeStream, IdentityImportOption, IdentityInformation,
+    ImportLocation, LocalIdentity, MultiPass, MultiPassEvent, MultiPassEventStream,
+    MultiPassImportExport,
 };
 use crate::raygun::community::{
     CommunityChannelPermission, CommunityPermission, CommunityRole, RoleId,
 };
+use crate::raygun::processor::MessageProcessor;
 use crate::raygun::{
     community::{
         Community, CommunityChannel, CommunityChannelType, CommunityInvite, RayGunCommunity,
     },
-    AttachmentEventStream, Conversation, ConversationImage, EmbedState, GroupPermissionOpt,
-    Location, Message, MessageEvent, MessageEventStream, MessageOptions, MessageReference,
-    MessageStatus, Messages, PinState, RayGun, RayGunAttachment, RayGunConversationInformation,
-    RayGunEventStream, RayGunEvents, RayGunGroupConversation, RayGunStream, ReactionState,
+    AttachmentEventStream, AttachmentOptions, Conversation, ConversationImage, ConversationStats,
+    EmbedState, GroupPermissionOpt, HistoryVisibility, Location, Message, MessageEvent,
+    MessageEventStream, MessageOptions, MessageReference, MessageStatus, Messages, PinState,
+    RayGun, RayGunAttachment, RayGunConversationInformation, RayGunEventStream, RayGunEvents,
+    RayGunGroupConversation, RayGunStream, ReactionState, RetentionPolicy, SearchQuery,
+    SearchResultStream,
 };
+use crate::subscription::SubscriptionOptions;
 use crate::tesseract::Tesseract;
 use crate::warp::dummy::Dummy;
 use crate::{Extension, SingleHandle};
@@ -35,7 +44,9 @@ use chrono::{DateTime, Utc};
 use futures::stream::BoxStream;
 use indexmap::IndexSet;
 use std::any::Any;
+use std::ops::Range;
 use std::path::PathBuf;
+use std::sync::Arc;
 use uuid::Uuid;
 
 pub struct Warp<M, R, C>
@@ -244,6 +255,28 @@ where
         self.multipass.identity_banner(identity).await
     }
 
+    /// Profile picture belonging to the `Identity`, resized to fit within the given dimension in pixels
+    async fn identity_picture_sized(
+        &self,
+        identity: &DID,
+        dimension: u32,
+    ) -> Result<IdentityImage, Error> {
+        self.multipass
+            .identity_picture_sized(identity, dimension)
+            .await
+    }
+
+    /// Profile banner belonging to the `Identity`, resized to fit within the given dimension in pixels
+    async fn identity_banner_sized(
+        &self,
+        identity: &DID,
+        dimension: u32,
+    ) -> Result<IdentityImage, Error> {
+        self.multipass
+            .identity_banner_sized(identity, dimension)
+            .await
+    }
+
     /// Identity status to determine if they are online or offline
     async fn identity_status(&self, identity: &DID) -> Result<IdentityStatus, Error> {
         self.multipass.identity_status(identity).await
@@ -254,6 +287,30 @@ where
         self.multipass.set_identity_status(status).await
     }
 
+    /// Activity published by the identity alongside their status, if any
+    async fn identity_activity(&self, identity: &DID) -> Result<Option<IdentityActivity>, Error> {
+        self.multipass.identity_activity(identity).await
+    }
+
+    /// Publish or clear the activity shown alongside the status of the identity
+    async fn set_identity_activity(
+        &mut self,
+        activity: Option<IdentityActivity>,
+    ) -> Result<(), Error> {
+        self.multipass.set_identity_activity(activity).await
+    }
+
+    /// Profile fields of the identity. For the own identity, all fields are returned, otherwise
+    /// only the fields the identity has shared with us are returned
+    async fn identity_profile_fields(&self, identity: &DID) -> Result<ProfileFields, Error> {
+        self.multipass.identity_profile_fields(identity).await
+    }
+
+    /// Set the profile fields of the identity, replacing any existing fields
+    async fn set_identity_profile_fields(&mut self, fields: ProfileFields) -> Result<(), Error> {
+        self.multipass.set_identity_profile_fields(fields).await
+    }
+
     /// Find the relationship with an existing identity.
     async fn identity_relationship(&self, identity: &DID) -> Result<Relationship, Error> {
         self.multipass.identity_relationship(identity).await
@@ -297,6 +354,17 @@ where
         self.multipass.send_request(identity).await
     }
 
+    /// Send friend request to corresponding public key with a short greeting message
+    async fn send_request_with_message(
+        &mut self,
+        identity: &DID,
+        message: &str,
+    ) -> Result<(), Error> {
+        self.multipass
+            .send_request_with_message(identity, message)
+            .await
+    }
+
     /// Accept friend request from public key
     async fn accept_request(&mut self, identity: &DID) -> Result<(), Error> {
         self.multipass.accept_request(identity).await
@@ -312,6 +380,11 @@ where
         self.multipass.close_request(identity).await
     }
 
+    /// Send an outgoing friend request again, renewing its date
+    async fn resend_request(&mut self, identity: &DID) -> Result<(), Error> {
+        self.multipass.resend_request(identity).await
+    }
+
     /// Check to determine if a request been received from the DID
     async fn received_friend_request_from(&self, identity: &DID) -> Result<bool, Error> {
         self.multipass.received_friend_request_from(identity).await
@@ -366,6 +439,41 @@ where
     async fn has_friend(&self, identity: &DID) -> Result<bool, Error> {
         self.multipass.has_friend(identity).await
     }
+
+    /// Settings controlling what is shared about the friends list of the account
+    async fn friends_privacy(&self) -> Result<FriendsPrivacy, Error> {
+        self.multipass.friends_privacy().await
+    }
+
+    /// Update the settings controlling what is shared about the friends list of the account
+    async fn set_friends_privacy(&mut self, privacy: FriendsPrivacy) -> Result<(), Error> {
+        self.multipass.set_friends_privacy(privacy).await
+    }
+
+    /// Number of friends the identity has, if they shared it with us
+    async fn friend_count(&self, identity: &DID) -> Result<Option<usize>, Error> {
+        self.multipass.friend_count(identity).await
+    }
+
+    /// Friends of the account that are also friends of the identity, if they shared it with us
+    async fn mutual_friends(&self, identity: &DID) -> Result<Vec<DID>, Error> {
+        self.multipass.mutual_friends(identity).await
+    }
+
+    /// Export the list of friends in the specified format
+    async fn export_contacts(&self, format: ContactFormat) -> Result<Vec<u8>, Error> {
+        self.multipass.export_contacts(format).await
+    }
+
+    /// Import a list of contacts, sending a friend request to or accepting a pending request from
+    /// each contact that is not already a friend. Returns the contacts that were acted on.
+    async fn import_contacts(
+        &mut self,
+        format: ContactFormat,
+        data: &[u8],
+    ) -> Result<Vec<DID>, Error> {
+        self.multipass.import_contacts(format, data).await
+    }
 }
 
 #[async_trait::async_trait]
@@ -391,6 +499,43 @@ where
         self.multipass.update_identity(option).await
     }
 
+    async fn set_app_data(&mut self, namespace: &str, data: Bytes) -> Result<(), Error> {
+        self.multipass.set_app_data(namespace, data).await
+    }
+
+    async fn get_app_data(&self, namespace: &str) -> Result<Bytes, Error> {
+        self.multipass.get_app_data(namespace).await
+    }
+
+    async fn share_code(&self) -> Result<String, Error> {
+        self.multipass.share_code().await
+    }
+
+    async fn sign_detached(
+        &self,
+        data: &[u8],
+        trusted_comment: Option<&str>,
+    ) -> Result<String, Error> {
+        self.multipass.sign_detached(data, trusted_comment).await
+    }
+
+    async fn notification_preferences(&self) -> Result<NotificationPreferences, Error> {
+        self.multipass.notification_preferences().await
+    }
+
+    async fn set_notification_preferences(
+        &mut self,
+        preferences: NotificationPreferences,
+    ) -> Result<(), Error> {
+        self.multipass
+            .set_notification_preferences(preferences)
+            .await
+    }
+
+    async fn should_notify(&self, event: &NotificationEvent) -> Result<bool, Error> {
+        self.multipass.should_notify(event).await
+    }
+
     fn tesseract(&self) -> Tesseract {
         self.multipass.tesseract()
     }
@@ -406,6 +551,13 @@ where
     async fn multipass_subscribe(&mut self) -> Result<MultiPassEventStream, Error> {
         self.multipass.multipass_subscribe().await
     }
+
+    async fn multipass_subscribe_with(
+        &mut self,
+        options: SubscriptionOptions,
+    ) -> Result<MultiPassEventStream, Error> {
+        self.multipass.multipass_subscribe_with(options).await
+    }
 }
 
 #[async_trait::async_trait]
@@ -426,6 +578,23 @@ where
     fn get_identity(&self, id: impl Into<Identifier>) -> GetIdentity {
         self.multipass.get_identity(id)
     }
+
+    /// Verify a share code created by [`Identity::to_share_code`] and send a friend request to the identity
+    async fn add_from_share_code(&mut self, code: &str) -> Result<DID, Error> {
+        self.multipass.add_from_share_code(code).await
+    }
+
+    /// Local history of relationship changes with an identity, ordered from oldest to newest
+    async fn relationship_history(
+        &self,
+        identity: &DID,
+    ) -> Result<Vec<RelationshipHistoryEntry>, Error> {
+        self.multipass.relationship_history(identity).await
+    }
+
+    async fn watch_identity(&self, identity: &DID) -> Result<IdentityChangeStream, Error> {
+        self.multipass.watch_identity(identity).await
+    }
 }
 
 #[async_trait::async_trait]
@@ -504,6 +673,26 @@ where
             .await
     }
 
+    async fn put_resumable(
+        &mut self,
+        name: &str,
+        total_size: usize,
+    ) -> Result<Box<dyn UploadSession>, Error> {
+        self.constellation.put_resumable(name, total_size).await
+    }
+
+    async fn upload_session(&self, id: Uuid) -> Result<Box<dyn UploadSession>, Error> {
+        self.constellation.upload_session(id).await
+    }
+
+    async fn upload_sessions(&self) -> Result<Vec<Uuid>, Error> {
+        self.constellation.upload_sessions().await
+    }
+
+    async fn cancel_upload(&mut self, id: Uuid) -> Result<(), Error> {
+        self.constellation.cancel_upload(id).await
+    }
+
     async fn get_stream(
         &self,
         name: &str,
@@ -511,6 +700,14 @@ where
         self.constellation.get_stream(name).await
     }
 
+    async fn get_stream_range(
+        &self,
+        name: &str,
+        range: Range<usize>,
+    ) -> Result<BoxStream<'static, Result<Bytes, std::io::Error>>, Error> {
+        self.constellation.get_stream_range(name, range).await
+    }
+
     async fn rename(&mut self, current: &str, new: &str) -> Result<(), Error> {
         self.constellation.rename(current, new).await
     }
@@ -530,6 +727,10 @@ where
     async fn sync_ref(&mut self, name: &str) -> Result<(), Error> {
         self.constellation.sync_ref(name).await
     }
+
+    async fn verify(&self, refetch: bool) -> Result<IntegrityReport, Error> {
+        self.constellation.verify(refetch).await
+    }
 }
 
 #[async_trait::async_trait]
@@ -542,6 +743,15 @@ where
     async fn constellation_subscribe(&mut self) -> Result<ConstellationEventStream, Error> {
         self.constellation.constellation_subscribe().await
     }
+
+    async fn constellation_subscribe_with(
+        &mut self,
+        options: SubscriptionOptions,
+    ) -> Result<ConstellationEventStream, Error> {
+        self.constellation
+            .constellation_subscribe_with(options)
+            .await
+    }
 }
 
 #[async_trait::async_trait]
@@ -558,9 +768,26 @@ where
         self.raygun.get_conversation_stream(conversation_id).await
     }
 
+    async fn get_conversation_stream_with(
+        &mut self,
+        conversation_id: Uuid,
+        options: SubscriptionOptions,
+    ) -> Result<MessageEventStream, Error> {
+        self.raygun
+            .get_conversation_stream_with(conversation_id, options)
+            .await
+    }
+
     async fn raygun_subscribe(&mut self) -> Result<RayGunEventStream, Error> {
         self.raygun.raygun_subscribe().await
     }
+
+    async fn raygun_subscribe_with(
+        &mut self,
+        options: SubscriptionOptions,
+    ) -> Result<RayGunEventStream, Error> {
+        self.raygun.raygun_subscribe_with(options).await
+    }
 }
 
 #[async_trait::async_trait]
@@ -827,6 +1054,16 @@ where
             .edit_community_channel_description(community_id, channel_id, description)
             .await
     }
+    async fn edit_community_channel_slow_mode(
+        &mut self,
+        community_id: Uuid,
+        channel_id: Uuid,
+        slow_mode: u64,
+    ) -> Result<(), Error> {
+        self.raygun
+            .edit_community_channel_slow_mode(community_id, channel_id, slow_mode)
+            .await
+    }
     async fn grant_community_channel_permission(
         &mut self,
         community_id: Uuid,
@@ -1119,6 +1356,19 @@ where
             .await
     }
 
+    async fn attach_with_options(
+        &mut self,
+        conversation_id: Uuid,
+        message_id: Option<Uuid>,
+        locations: Vec<Location>,
+        message: Vec<String>,
+        options: AttachmentOptions,
+    ) -> Result<(Uuid, AttachmentEventStream), Error> {
+        self.raygun
+            .attach_with_options(conversation_id, message_id, locations, message, options)
+            .await
+    }
+
     async fn download(
         &self,
         conversation_id: Uuid,
@@ -1141,6 +1391,18 @@ where
             .download_stream(conversation_id, message_id, name)
             .await
     }
+
+    async fn save_attachment(
+        &self,
+        conversation_id: Uuid,
+        message_id: Uuid,
+        name: &str,
+        path: PathBuf,
+    ) -> Result<ConstellationProgressStream, Error> {
+        self.raygun
+            .save_attachment(conversation_id, message_id, name, path)
+            .await
+    }
 }
 
 #[async_trait::async_trait]
@@ -1183,6 +1445,30 @@ where
             .set_conversation_description(conversation_id, description)
             .await
     }
+
+    async fn set_history_visibility(
+        &mut self,
+        conversation_id: Uuid,
+        visibility: HistoryVisibility,
+    ) -> Result<(), Error> {
+        self.raygun
+            .set_history_visibility(conversation_id, visibility)
+            .await
+    }
+
+    async fn set_slow_mode(&mut self, conversation_id: Uuid, slow_mode: u64) -> Result<(), Error> {
+        self.raygun.set_slow_mode(conversation_id, slow_mode).await
+    }
+
+    async fn set_retention_policy(
+        &mut self,
+        conversation_id: Uuid,
+        policy: Option<RetentionPolicy>,
+    ) -> Result<(), Error> {
+        self.raygun
+            .set_retention_policy(conversation_id, policy)
+            .await
+    }
 }
 
 #[async_trait::async_trait]
@@ -1233,6 +1519,10 @@ where
         self.raygun.get_message_count(conversation_id).await
     }
 
+    async fn conversation_stats(&self, conversation_id: Uuid) -> Result<ConversationStats, Error> {
+        self.raygun.conversation_stats(conversation_id).await
+    }
+
     async fn message_status(
         &self,
         conversation_id: Uuid,
@@ -1243,6 +1533,10 @@ where
             .await
     }
 
+    async fn mark_read(&mut self, conversation_id: Uuid, message_id: Uuid) -> Result<(), Error> {
+        self.raygun.mark_read(conversation_id, message_id).await
+    }
+
     async fn get_message_references(
         &self,
         conversation_id: Uuid,
@@ -1263,6 +1557,14 @@ where
             .await
     }
 
+    async fn search_messages(
+        &self,
+        conversation_id: Option<Uuid>,
+        query: SearchQuery,
+    ) -> Result<SearchResultStream, Error> {
+        self.raygun.search_messages(conversation_id, query).await
+    }
+
     async fn get_messages(
         &self,
         conversation_id: Uuid,
@@ -1384,4 +1686,27 @@ where
     async fn unarchived_conversation(&mut self, conversation_id: Uuid) -> Result<(), Error> {
         self.raygun.unarchived_conversation(conversation_id).await
     }
+
+    async fn merge_conversations(&mut self, primary: Uuid, duplicate: Uuid) -> Result<(), Error> {
+        self.raygun.merge_conversations(primary, duplicate).await
+    }
+
+    async fn replay_events(
+        &self,
+        conversation_id: Uuid,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<MessageEventStream, Error> {
+        self.raygun.replay_events(conversation_id, since).await
+    }
+
+    async fn register_message_processor(
+        &mut self,
+        processor: Arc<dyn MessageProcessor>,
+    ) -> Result<(), Error> {
+        self.raygun.register_message_processor(processor).await
+    }
+
+    async fn unregister_message_processor(&mut self, name: &str) -> Result<(), Error> {
+        self.raygun.unregister_message_processor(name).await
+    }
 }